@@ -0,0 +1,75 @@
+//! Internationalized email address handling (EAI, RFC 6530).
+//!
+//! Domains travel over the wire in their ASCII (punycode) form, so IDN
+//! recipients work with any server; only UTF-8 local parts need the SMTPUTF8
+//! extension. For display, punycode domains are decoded back to Unicode.
+
+use anyhow::{Context, Result};
+use lettre::Address;
+use lettre::message::{Mailbox, Mailboxes};
+
+/// Formats an address for display, decoding punycode domain labels.
+pub fn display_address(local: &str, domain: &str) -> String {
+    let (unicode, result) = idna::domain_to_unicode(domain);
+    let domain = if result.is_ok() {
+        unicode.as_str()
+    } else {
+        domain
+    };
+    format!("{}@{}", local, domain)
+}
+
+/// Returns whether an address can only be delivered with SMTPUTF8, i.e. its
+/// local part contains non-ASCII characters.
+pub fn requires_smtputf8(address: &Address) -> bool {
+    !address.user().is_ascii()
+}
+
+/// Converts the address's domain to its ASCII (punycode) form.
+pub fn to_ascii_address(address: &Address) -> Result<Address> {
+    let domain = idna::domain_to_ascii(address.domain())
+        .with_context(|| format!("Invalid domain: {}", address.domain()))?;
+    Address::new(address.user(), domain).with_context(|| format!("Invalid address: {}", address))
+}
+
+/// Converts every mailbox's domain to its ASCII form, keeping display names.
+pub fn to_ascii_mailboxes(mailboxes: Mailboxes) -> Result<Mailboxes> {
+    mailboxes
+        .into_iter()
+        .map(|mailbox| {
+            Ok(Mailbox::new(
+                mailbox.name,
+                to_ascii_address(&mailbox.email)?,
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_address() {
+        assert_eq!(
+            display_address("info", "xn--bcher-kva.example"),
+            "info@bücher.example"
+        );
+        assert_eq!(display_address("用户", "example.com"), "用户@example.com");
+        assert_eq!(display_address("a", "example.com"), "a@example.com");
+    }
+
+    #[test]
+    fn test_to_ascii_address() {
+        let address: Address = "info@bücher.example".parse().unwrap();
+        let ascii = to_ascii_address(&address).unwrap();
+        assert_eq!(ascii.to_string(), "info@xn--bcher-kva.example");
+        assert!(!requires_smtputf8(&ascii));
+
+        let address: Address = "用户@例子.广告".parse().unwrap();
+        let ascii = to_ascii_address(&address).unwrap();
+        assert_eq!(ascii.user(), "用户");
+        assert!(ascii.domain().is_ascii());
+        assert!(requires_smtputf8(&ascii));
+    }
+}
@@ -0,0 +1,196 @@
+//! Saving the attachments of many messages in the background.
+//!
+//! `:save-attachments` downloads every message it covers, which can take a
+//! while for a long list, so a worker thread with its own session fetches
+//! them one at a time and reports the messages done meanwhile. Bodies in the
+//! cache are not downloaded again.
+
+use anyhow::{Error, Result};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::thread;
+
+use crate::attachments::{extract_attachments, save_attachment, scan_attachment, unique_path};
+use crate::imap_client::ImapClient;
+
+/// A message whose attachments are saved.
+#[derive(Debug, Clone)]
+pub struct SaveItem {
+    pub uid: u32,
+    /// Subject of the message, for reporting a failure.
+    pub subject: String,
+}
+
+/// The outcome of saving the attachments of several messages.
+#[derive(Debug, Default)]
+pub struct SavedAttachments {
+    /// Number of attachments written.
+    pub saved: usize,
+    /// Total size of the attachments written.
+    pub bytes: u64,
+    /// Number of messages that had attachments.
+    pub messages: usize,
+    /// Number of attachments rejected by the scan command.
+    pub blocked: usize,
+    /// Subjects of the messages that could not be downloaded, and why.
+    pub failed: Vec<(String, Error)>,
+}
+
+enum SaveEvent {
+    Saved(usize),
+    Done(Result<SavedAttachments>),
+}
+
+/// Attachments being saved in a background thread.
+#[derive(Debug)]
+pub struct AttachmentSave {
+    dir: PathBuf,
+    events: Receiver<SaveEvent>,
+    done: usize,
+    total: usize,
+}
+
+impl AttachmentSave {
+    /// Starts saving the attachments of `items` in `mailbox` into `dir`, over
+    /// a session opened with `connect`, vetting each with `scan_command` if
+    /// set.
+    pub fn new(
+        connect: impl FnOnce() -> Result<ImapClient> + Send + 'static,
+        mailbox: &str,
+        items: Vec<SaveItem>,
+        dir: PathBuf,
+        scan_command: Option<String>,
+    ) -> Self {
+        let (tx, events) = channel();
+        let total = items.len();
+        let mailbox = mailbox.to_string();
+        let worker_dir = dir.clone();
+        thread::spawn(move || {
+            let result = connect().and_then(|mut client| {
+                client.set_mailbox(&mailbox);
+                save_all(
+                    &items,
+                    |uid| client.fetch_raw_message(uid),
+                    &worker_dir,
+                    scan_command.as_deref(),
+                    &tx,
+                )
+            });
+            let _ = tx.send(SaveEvent::Done(result));
+        });
+        AttachmentSave {
+            dir,
+            events,
+            done: 0,
+            total,
+        }
+    }
+
+    /// Returns the directory the attachments are saved into.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the number of messages gone through so far and in all.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.done, self.total)
+    }
+
+    /// Takes in the progress reported since the last call, and returns the
+    /// outcome once every message was gone through, without blocking.
+    pub fn poll(&mut self) -> Option<Result<SavedAttachments>> {
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                SaveEvent::Saved(done) => self.done = done,
+                SaveEvent::Done(result) => return Some(result),
+            }
+        }
+        None
+    }
+}
+
+/// Saves the attachments of each message fetched with `fetch` into `dir`,
+/// numbering names that are already taken. A message that fails to download
+/// does not stop the rest, but one that cannot be written does, as the next
+/// would fail the same way.
+fn save_all(
+    items: &[SaveItem],
+    mut fetch: impl FnMut(u32) -> Result<Vec<u8>>,
+    dir: &Path,
+    scan_command: Option<&str>,
+    tx: &Sender<SaveEvent>,
+) -> Result<SavedAttachments> {
+    let mut result = SavedAttachments::default();
+    for (i, item) in items.iter().enumerate() {
+        match fetch(item.uid).and_then(|raw| extract_attachments(&raw)) {
+            Ok(attachments) => {
+                if !attachments.is_empty() {
+                    result.messages += 1;
+                }
+                for attachment in attachments {
+                    if let Some(command) = scan_command
+                        && scan_attachment(command, &attachment).is_err()
+                    {
+                        result.blocked += 1;
+                        continue;
+                    }
+                    save_attachment(&attachment, &unique_path(dir, &attachment.filename))?;
+                    result.saved += 1;
+                    result.bytes += attachment.data.len() as u64;
+                }
+            }
+            Err(e) => result.failed.push((item.subject.clone(), e)),
+        }
+        let _ = tx.send(SaveEvent::Saved(i + 1));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    const RAW: &[u8] = b"From: a@example.com\r\n\
+Content-Type: multipart/mixed; boundary=\"XYZ\"\r\n\
+\r\n\
+--XYZ\r\n\
+Content-Type: text/plain\r\n\
+\r\n\
+Hello\r\n\
+--XYZ\r\n\
+Content-Type: application/pdf; name=\"invoice.pdf\"\r\n\
+Content-Disposition: attachment; filename=\"invoice.pdf\"\r\n\
+Content-Transfer-Encoding: base64\r\n\
+\r\n\
+SGVsbG8gUERG\r\n\
+--XYZ--\r\n";
+
+    #[test]
+    fn test_save_all() {
+        let dir = tempfile::tempdir().unwrap();
+        let items = [1, 2, 3].map(|uid| SaveItem {
+            uid,
+            subject: format!("Invoice {}", uid),
+        });
+        let (tx, events) = channel();
+        let fetch = |uid| match uid {
+            2 => Err(anyhow!("connection reset")),
+            _ => Ok(RAW.to_vec()),
+        };
+
+        let result = save_all(&items, fetch, dir.path(), None, &tx).unwrap();
+        assert_eq!((result.saved, result.bytes, result.messages), (2, 18, 2));
+        assert!(dir.path().join("invoice.pdf").exists());
+        assert!(dir.path().join("invoice (2).pdf").exists());
+        let [(subject, e)] = result.failed.as_slice() else {
+            panic!("expected one failure: {:?}", result.failed);
+        };
+        assert_eq!(subject, "Invoice 2");
+        assert_eq!(e.to_string(), "connection reset");
+        assert_eq!(events.try_iter().count(), 3);
+
+        let result = save_all(&items[..1], fetch, dir.path(), Some("exit 1"), &tx).unwrap();
+        assert_eq!((result.saved, result.blocked), (0, 1));
+    }
+}
@@ -0,0 +1,400 @@
+//! Attachment extraction and download directory resolution.
+//!
+//! Walks the MIME tree of a raw message to find attachments and picks the
+//! directory to save each one into based on the configured sender/type rules.
+//! An optional scanner command vets each attachment before it is opened or
+//! saved.
+
+use anyhow::{Context, Result, bail};
+use mailparse::{DispositionType, ParsedMail, parse_mail};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use crate::config::{AttachmentRule, AttachmentsConfig};
+use crate::urls::open_path;
+use crate::utils::expand_tilde;
+
+/// A decoded attachment extracted from a message.
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    /// File name suggested by the message, or a generated fallback.
+    pub filename: String,
+    /// MIME type of the attachment (e.g. "application/pdf").
+    pub mime_type: String,
+    /// Decoded attachment content.
+    pub data: Vec<u8>,
+}
+
+/// Extracts all attachments from a raw RFC822 message.
+pub fn extract_attachments(raw: &[u8]) -> Result<Vec<Attachment>> {
+    let mail = parse_mail(raw).context("Failed to parse message")?;
+
+    let mut attachments = Vec::new();
+    collect_attachments(&mail, &mut attachments)?;
+
+    Ok(attachments)
+}
+
+/// Recursively collects attachment parts from a MIME tree.
+fn collect_attachments(part: &ParsedMail, out: &mut Vec<Attachment>) -> Result<()> {
+    if !part.subparts.is_empty() {
+        for subpart in &part.subparts {
+            collect_attachments(subpart, out)?;
+        }
+        return Ok(());
+    }
+
+    let disposition = part.get_content_disposition();
+    let filename = disposition
+        .params
+        .get("filename")
+        .or_else(|| part.ctype.params.get("name"))
+        .cloned();
+
+    let is_attachment = disposition.disposition == DispositionType::Attachment;
+    if !is_attachment && filename.is_none() {
+        return Ok(());
+    }
+
+    let data = part.get_body_raw().context("Failed to decode attachment")?;
+
+    out.push(Attachment {
+        filename: filename.unwrap_or_else(|| format!("attachment-{}", out.len() + 1)),
+        mime_type: part.ctype.mimetype.to_lowercase(),
+        data,
+    });
+
+    Ok(())
+}
+
+impl AttachmentRule {
+    /// Returns true if this rule applies to an attachment of the given type
+    /// sent by the given address.
+    pub fn matches(&self, sender: Option<&str>, mime_type: &str) -> bool {
+        let sender_matches = match &self.sender {
+            Some(pattern) => sender.is_some_and(|s| sender_matches(pattern, s)),
+            None => true,
+        };
+        let mime_matches = match &self.mime_type {
+            Some(pattern) => mime_type_matches(pattern, mime_type),
+            None => true,
+        };
+
+        sender_matches && mime_matches
+    }
+}
+
+/// Matches a sender pattern against an address.
+///
+/// A pattern containing `@` must match the address exactly. Otherwise it is
+/// treated as a domain and matches that domain and any of its subdomains.
+pub(crate) fn sender_matches(pattern: &str, sender: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let sender = sender.to_lowercase();
+
+    if pattern.contains('@') {
+        return pattern == sender;
+    }
+
+    let domain = sender.rsplit_once('@').map_or("", |(_, d)| d);
+    domain == pattern || domain.ends_with(&format!(".{}", pattern))
+}
+
+/// Matches a MIME type pattern such as "image/*" against a MIME type.
+pub(crate) fn mime_type_matches(pattern: &str, mime_type: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let mime_type = mime_type.to_lowercase();
+
+    match pattern.strip_suffix("/*") {
+        Some(top_level) => mime_type
+            .split_once('/')
+            .is_some_and(|(t, _)| t == top_level),
+        None => pattern == mime_type,
+    }
+}
+
+/// Resolves the directory an attachment should be saved into.
+///
+/// The first matching rule wins; if none match, the configured default
+/// directory (or the current directory) is used.
+pub fn resolve_save_dir(
+    config: &AttachmentsConfig,
+    sender: Option<&str>,
+    mime_type: &str,
+) -> PathBuf {
+    config
+        .rules
+        .iter()
+        .find(|rule| rule.matches(sender, mime_type))
+        .map(|rule| expand_tilde(&rule.dir))
+        .or_else(|| config.default_dir.as_deref().map(expand_tilde))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Guesses a MIME type from a file's extension.
+pub fn guess_mime_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+
+    match ext.as_deref() {
+        Some("pdf") => "application/pdf",
+        Some("zip") => "application/zip",
+        Some("json") => "application/json",
+        Some("txt") | Some("log") => "text/plain",
+        Some("csv") => "text/csv",
+        Some("html") | Some("htm") => "text/html",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("ics") => "text/calendar",
+        Some("eml") => "message/rfc822",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Runs a scanner command through `sh -c` with the attachment's content on
+/// stdin, failing with the scanner's output if it exits with a non-zero
+/// status.
+pub fn scan_attachment(command: &str, attachment: &Attachment) -> Result<()> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run scan command: {}", command))?;
+
+    // Feed stdin from another thread so a scanner writing output before it
+    // has read everything cannot deadlock against us.
+    let mut stdin = child.stdin.take().context("Scan command has no stdin")?;
+    let data = attachment.data.clone();
+    let writer = thread::spawn(move || {
+        // A scanner may exit without reading all input; its status decides.
+        let _ = stdin.write_all(&data);
+    });
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("Failed to run scan command: {}", command))?;
+    let _ = writer.join();
+
+    if !output.status.success() {
+        let mut report = String::from_utf8_lossy(&output.stdout).into_owned();
+        report.push_str(&String::from_utf8_lossy(&output.stderr));
+        let report = report
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join("; ");
+        bail!("Scanner rejected it ({}): {}", output.status, report);
+    }
+    Ok(())
+}
+
+/// Writes attachment data to the given path, creating parent directories.
+pub fn save_attachment(attachment: &Attachment, path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {:?}", parent))?;
+    }
+
+    std::fs::write(path, &attachment.data)
+        .with_context(|| format!("Failed to write attachment to {:?}", path))?;
+
+    Ok(())
+}
+
+/// Writes an attachment to the temporary directory and opens it with the
+/// desktop's opener, returning the path written.
+pub fn open_attachment(attachment: &Attachment) -> Result<PathBuf> {
+    let path = unique_path(&std::env::temp_dir().join("rutt"), &attachment.filename);
+    save_attachment(attachment, &path)?;
+    open_path(&path)?;
+    Ok(path)
+}
+
+/// Reduces an attachment's name to its last path component, so a crafted
+/// name such as `../../.bashrc` cannot lead outside the directory it is saved
+/// in. A name without one, such as `..` or an empty one, becomes
+/// `attachment`.
+pub fn safe_filename(filename: &str) -> String {
+    let unified = filename.replace('\\', "/");
+    match Path::new(&unified)
+        .file_name()
+        .and_then(|name| name.to_str())
+    {
+        Some(name) if !name.trim_start_matches('.').is_empty() => name.to_string(),
+        _ => "attachment".to_string(),
+    }
+}
+
+/// Returns a path in `dir` for saving a file without overwriting another,
+/// numbering the name as in `invoice (2).pdf` when it is taken.
+///
+/// The name is reduced by [`safe_filename`] first.
+pub fn unique_path(dir: &Path, filename: &str) -> PathBuf {
+    let name = safe_filename(filename);
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, format!(".{}", ext)),
+        _ => (name.as_str(), String::new()),
+    };
+
+    let mut path = dir.join(&name);
+    let mut n = 2;
+    while path.exists() {
+        path = dir.join(format!("{} ({}){}", stem, n, extension));
+        n += 1;
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(sender: Option<&str>, mime_type: Option<&str>, dir: &str) -> AttachmentRule {
+        AttachmentRule {
+            sender: sender.map(String::from),
+            mime_type: mime_type.map(String::from),
+            dir: dir.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_extract_attachments() {
+        let raw = b"From: a@example.com\r\n\
+Content-Type: multipart/mixed; boundary=\"XYZ\"\r\n\
+\r\n\
+--XYZ\r\n\
+Content-Type: text/plain\r\n\
+\r\n\
+Hello\r\n\
+--XYZ\r\n\
+Content-Type: application/pdf; name=\"invoice.pdf\"\r\n\
+Content-Disposition: attachment; filename=\"invoice.pdf\"\r\n\
+Content-Transfer-Encoding: base64\r\n\
+\r\n\
+SGVsbG8gUERG\r\n\
+--XYZ--\r\n";
+
+        let attachments = extract_attachments(raw).unwrap();
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0].filename, "invoice.pdf");
+        assert_eq!(attachments[0].mime_type, "application/pdf");
+        assert_eq!(attachments[0].data, b"Hello PDF");
+    }
+
+    #[test]
+    fn test_extract_attachments_none() {
+        let raw = b"From: a@example.com\r\nContent-Type: text/plain\r\n\r\nHello\r\n";
+        let attachments = extract_attachments(raw).unwrap();
+        assert!(attachments.is_empty());
+    }
+
+    #[test]
+    fn test_sender_rule_matches_domain_and_subdomain() {
+        let r = rule(Some("example.com"), None, "/tmp");
+        assert!(r.matches(Some("billing@example.com"), "application/pdf"));
+        assert!(r.matches(Some("noreply@mail.example.com"), "application/pdf"));
+        assert!(!r.matches(Some("someone@notexample.com"), "application/pdf"));
+        assert!(!r.matches(None, "application/pdf"));
+    }
+
+    #[test]
+    fn test_mime_rule_wildcard() {
+        let r = rule(None, Some("image/*"), "/tmp");
+        assert!(r.matches(Some("a@b.com"), "image/png"));
+        assert!(r.matches(None, "IMAGE/JPEG"));
+        assert!(!r.matches(None, "application/pdf"));
+    }
+
+    #[test]
+    fn test_unique_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        assert_eq!(unique_path(dir, "invoice.pdf"), dir.join("invoice.pdf"));
+
+        std::fs::write(dir.join("invoice.pdf"), "").unwrap();
+        std::fs::write(dir.join("invoice (2).pdf"), "").unwrap();
+        assert_eq!(unique_path(dir, "invoice.pdf"), dir.join("invoice (3).pdf"));
+
+        std::fs::write(dir.join("README"), "").unwrap();
+        assert_eq!(unique_path(dir, "README"), dir.join("README (2)"));
+
+        assert_eq!(unique_path(dir, "../../.bashrc"), dir.join(".bashrc"));
+        assert_eq!(unique_path(dir, ".."), dir.join("attachment"));
+    }
+
+    #[test]
+    fn test_safe_filename() {
+        assert_eq!(safe_filename("invoice.pdf"), "invoice.pdf");
+        assert_eq!(safe_filename("../../etc/passwd"), "passwd");
+        assert_eq!(safe_filename("..\\..\\boot.ini"), "boot.ini");
+        assert_eq!(safe_filename("/tmp/"), "tmp");
+        assert_eq!(safe_filename("a/.."), "attachment");
+        assert_eq!(safe_filename(""), "attachment");
+    }
+
+    #[test]
+    fn test_guess_mime_type() {
+        assert_eq!(guess_mime_type(Path::new("a.PDF")), "application/pdf");
+        assert_eq!(guess_mime_type(Path::new("photo.jpeg")), "image/jpeg");
+        assert_eq!(
+            guess_mime_type(Path::new("noext")),
+            "application/octet-stream"
+        );
+    }
+
+    #[test]
+    fn test_resolve_save_dir() {
+        let config = AttachmentsConfig {
+            default_dir: Some("/downloads".to_string()),
+            rules: vec![
+                rule(Some("shop.com"), Some("application/pdf"), "/invoices"),
+                rule(None, Some("image/*"), "/pictures"),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            resolve_save_dir(&config, Some("orders@shop.com"), "application/pdf"),
+            PathBuf::from("/invoices")
+        );
+        assert_eq!(
+            resolve_save_dir(&config, Some("orders@shop.com"), "image/png"),
+            PathBuf::from("/pictures")
+        );
+        assert_eq!(
+            resolve_save_dir(&config, Some("friend@mail.com"), "text/csv"),
+            PathBuf::from("/downloads")
+        );
+        assert_eq!(
+            resolve_save_dir(&AttachmentsConfig::default(), None, "text/csv"),
+            PathBuf::from(".")
+        );
+    }
+
+    #[test]
+    fn test_scan_attachment() {
+        let attachment = Attachment {
+            filename: "a.txt".to_string(),
+            mime_type: "text/plain".to_string(),
+            data: b"EICAR-STANDARD-ANTIVIRUS-TEST-FILE".to_vec(),
+        };
+        scan_attachment("cat > /dev/null", &attachment).unwrap();
+
+        let err = scan_attachment(
+            "grep -q EICAR && echo 'stdin: Eicar-Signature FOUND' && exit 1",
+            &attachment,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("stdin: Eicar-Signature FOUND"));
+        assert!(scan_attachment("exit 2", &attachment).is_err());
+    }
+}
@@ -0,0 +1,227 @@
+//! Authentication with the IMAP and SMTP servers.
+//!
+//! By default the configured app password is used, or the one printed by
+//! `app_password_cmd` so that it can stay in a password manager or the system
+//! keyring; the command is run once per process, not on every login. When `auth_command` is set, it is run instead and its output used
+//! as a ready SASL XOAUTH2 token, so any token broker (corporate SSO,
+//! `mutt_oauth2.py`, `oauth2ms --encode-xoauth2`) can handle the OAuth flow
+//! without rutt knowing about it.
+
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use std::collections::HashMap;
+use std::fmt;
+use std::process::Command;
+use std::sync::{LazyLock, Mutex, PoisonError};
+
+use crate::config::GmailConfig;
+
+/// How to authenticate with the mail servers.
+#[derive(Clone, PartialEq)]
+pub enum Auth {
+    /// Log in with the username and password.
+    Password(String),
+    /// Authenticate with a base64-encoded SASL XOAUTH2 token.
+    XOAuth2(String),
+}
+
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print the secret itself.
+        match self {
+            Auth::Password(_) => f.write_str("Password(..)"),
+            Auth::XOAuth2(_) => f.write_str("XOAuth2(..)"),
+        }
+    }
+}
+
+impl Auth {
+    /// Returns the authentication configured for the account, running the
+    /// auth command if there is one.
+    pub fn from_config(gmail: &GmailConfig) -> Result<Self> {
+        match &gmail.auth_command {
+            Some(command) => Ok(Auth::XOAuth2(run_auth_command(command)?)),
+            None => Ok(Auth::Password(gmail.password()?)),
+        }
+    }
+}
+
+/// Passwords printed by `app_password_cmd`, by command, so that a password
+/// manager asking to be unlocked is not asked again on every reconnection.
+static PASSWORDS: LazyLock<Mutex<HashMap<String, String>>> = LazyLock::new(Mutex::default);
+
+impl GmailConfig {
+    /// Returns the app password, running `app_password_cmd` when the password
+    /// is not set directly. The command's password is kept for the rest of
+    /// the process; a failed run is tried again next time.
+    pub fn password(&self) -> Result<String> {
+        match &self.app_password_cmd {
+            Some(command) if self.app_password.is_empty() => {
+                let mut passwords = PASSWORDS.lock().unwrap_or_else(PoisonError::into_inner);
+                if let Some(password) = passwords.get(command) {
+                    return Ok(password.clone());
+                }
+                let password = run_password_command(command)?;
+                passwords.insert(command.clone(), password.clone());
+                Ok(password)
+            }
+            _ => Ok(self.app_password.clone()),
+        }
+    }
+}
+
+/// Runs a password command through the shell and returns the first line it
+/// prints, as `pass` prints the password before any other fields.
+fn run_password_command(command: &str) -> Result<String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .output()
+        .with_context(|| format!("Failed to run app_password_cmd: {}", command))?;
+    if !output.status.success() {
+        bail!(
+            "app_password_cmd failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let stdout =
+        String::from_utf8(output.stdout).context("app_password_cmd printed invalid UTF-8")?;
+    let password = stdout.lines().next().unwrap_or_default().trim_end();
+    if password.is_empty() {
+        bail!("app_password_cmd printed no password: {}", command);
+    }
+    Ok(password.to_string())
+}
+
+/// Runs an auth command through the shell and returns the token it prints.
+fn run_auth_command(command: &str) -> Result<String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .output()
+        .with_context(|| format!("Failed to run auth command: {}", command))?;
+    if !output.status.success() {
+        bail!(
+            "Auth command failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let token = String::from_utf8(output.stdout)
+        .context("Auth command printed invalid UTF-8")?
+        .trim()
+        .to_string();
+    if token.is_empty() {
+        bail!("Auth command printed no token: {}", command);
+    }
+    STANDARD
+        .decode(&token)
+        .context("Auth command must print a base64-encoded SASL token")?;
+    Ok(token)
+}
+
+/// Answers the IMAP `AUTHENTICATE XOAUTH2` exchange with a token.
+pub(crate) struct XOAuth2Authenticator {
+    response: Vec<u8>,
+}
+
+impl XOAuth2Authenticator {
+    /// Creates an authenticator from a base64-encoded token.
+    pub(crate) fn new(token: &str) -> Result<Self> {
+        let response = STANDARD
+            .decode(token)
+            .context("Invalid base64 in SASL token")?;
+        Ok(XOAuth2Authenticator { response })
+    }
+}
+
+impl imap::Authenticator for XOAuth2Authenticator {
+    type Response = Vec<u8>;
+
+    fn process(&self, challenge: &[u8]) -> Self::Response {
+        // A non-empty challenge carries the error details of a rejected
+        // token; an empty response lets the server finish with NO.
+        if challenge.is_empty() {
+            self.response.clone()
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use imap::Authenticator;
+
+    fn gmail(auth_command: Option<&str>) -> GmailConfig {
+        GmailConfig {
+            username: "me@gmail.com".to_string(),
+            app_password: "secret".to_string(),
+            auth_command: auth_command.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_password_command() {
+        let mut gmail = gmail(None);
+        gmail.app_password_cmd = Some("printf 'from-cmd\\nlogin: me\\n'".to_string());
+        assert_eq!(gmail.password().unwrap(), "secret");
+
+        gmail.app_password.clear();
+        assert_eq!(
+            Auth::from_config(&gmail).unwrap(),
+            Auth::Password("from-cmd".to_string())
+        );
+
+        gmail.app_password_cmd = Some("echo locked >&2; exit 1".to_string());
+        let failed = gmail.password().unwrap_err();
+        assert_eq!(
+            failed.to_string(),
+            "app_password_cmd failed (exit status: 1): locked"
+        );
+        gmail.app_password_cmd = Some("true".to_string());
+        assert!(gmail.password().is_err());
+    }
+
+    #[test]
+    fn test_password_command_runs_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let runs = dir.path().join("runs");
+        let mut gmail = gmail(None);
+        gmail.app_password.clear();
+        gmail.app_password_cmd = Some(format!("echo run >> {:?}; echo pw", runs));
+
+        assert_eq!(gmail.password().unwrap(), "pw");
+        assert_eq!(gmail.password().unwrap(), "pw");
+        assert_eq!(std::fs::read_to_string(&runs).unwrap(), "run\n");
+    }
+
+    #[test]
+    fn test_from_config() {
+        assert_eq!(
+            Auth::from_config(&gmail(None)).unwrap(),
+            Auth::Password("secret".to_string())
+        );
+
+        let auth = Auth::from_config(&gmail(Some("printf 'dG9rZW4=\\n'"))).unwrap();
+        assert_eq!(auth, Auth::XOAuth2("dG9rZW4=".to_string()));
+
+        let failed = Auth::from_config(&gmail(Some("echo denied >&2; exit 3"))).unwrap_err();
+        assert!(format!("{failed:#}").contains("denied"));
+        assert!(Auth::from_config(&gmail(Some("true"))).is_err());
+        assert!(Auth::from_config(&gmail(Some("echo 'not a token!'"))).is_err());
+    }
+
+    #[test]
+    fn test_authenticator() {
+        let authenticator = XOAuth2Authenticator::new("dG9rZW4=").unwrap();
+        assert_eq!(authenticator.process(b""), b"token");
+        assert!(authenticator.process(b"{\"status\":\"400\"}").is_empty());
+    }
+}
@@ -0,0 +1,233 @@
+//! Full-account backup to Maildir and restore via IMAP APPEND.
+//!
+//! A backup directory holds one Maildir per folder plus a `manifest.toml`
+//! recording each folder's UIDVALIDITY and every message's UID, flags, and
+//! internal date. Gmail labels are captured by folder membership: a message
+//! carrying several labels is backed up once per label folder.
+
+use anyhow::{Context, Result};
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::imap_client::ImapClient;
+use crate::maildir::Maildir;
+
+/// Name of the manifest file inside a backup directory.
+pub const MANIFEST_FILE: &str = "manifest.toml";
+
+/// Description of a backup's contents.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Folders included in the backup.
+    #[serde(default)]
+    pub folders: Vec<FolderManifest>,
+}
+
+/// A backed-up folder.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FolderManifest {
+    /// Mailbox name on the server.
+    pub name: String,
+    /// Maildir directory, relative to the backup root.
+    pub dir: PathBuf,
+    /// UIDVALIDITY of the mailbox at backup time.
+    pub uid_validity: Option<u32>,
+    /// Messages in the folder.
+    #[serde(default)]
+    pub messages: Vec<MessageManifest>,
+}
+
+/// A backed-up message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageManifest {
+    /// UID of the message in its mailbox at backup time.
+    pub uid: u32,
+    /// Message file, relative to the folder's Maildir.
+    pub file: PathBuf,
+    /// IMAP flags set on the message.
+    #[serde(default)]
+    pub flags: Vec<String>,
+    /// Server internal date in RFC 3339 format.
+    pub internal_date: Option<String>,
+}
+
+impl Manifest {
+    /// Reads the manifest from a backup directory.
+    pub fn load(backup_dir: &Path) -> Result<Self> {
+        let path = backup_dir.join(MANIFEST_FILE);
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read manifest {:?}", path))?;
+        toml::from_str(&contents).with_context(|| format!("Failed to parse manifest {:?}", path))
+    }
+
+    /// Writes the manifest into a backup directory.
+    pub fn save(&self, backup_dir: &Path) -> Result<()> {
+        let path = backup_dir.join(MANIFEST_FILE);
+        let contents = toml::to_string(self).context("Failed to serialize manifest")?;
+        fs::write(&path, contents).with_context(|| format!("Failed to write manifest {:?}", path))
+    }
+}
+
+/// Converts a mailbox name into a safe directory name.
+///
+/// The index prefix keeps names unique even when different mailboxes
+/// sanitize to the same string.
+fn folder_dir_name(index: usize, mailbox: &str) -> String {
+    let sanitized = mailbox
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    format!("{:03}-{}", index, sanitized)
+}
+
+/// Backs up the given folders (or every selectable folder when `folders` is
+/// empty) into `backup_dir`.
+///
+/// `progress` is called after each downloaded batch with the folder name and
+/// the number of messages done and total.
+pub fn backup(
+    client: &mut ImapClient,
+    backup_dir: &Path,
+    folders: &[String],
+    batch_size: usize,
+    mut progress: impl FnMut(&str, usize, usize),
+) -> Result<Manifest> {
+    fs::create_dir_all(backup_dir)
+        .with_context(|| format!("Failed to create backup directory {:?}", backup_dir))?;
+
+    let folders = if folders.is_empty() {
+        client
+            .list_mailboxes()?
+            .into_iter()
+            .filter(|m| m.selectable)
+            .map(|m| m.name)
+            .collect()
+    } else {
+        folders.to_vec()
+    };
+
+    let mut manifest = Manifest::default();
+    for (index, name) in folders.iter().enumerate() {
+        let dir = PathBuf::from(folder_dir_name(index, name));
+        let maildir = Maildir::create(backup_dir.join(&dir))?;
+        let (uid_validity, uids) = client.list_uids(name)?;
+
+        let mut messages = Vec::with_capacity(uids.len());
+        for batch in uids.chunks(batch_size.max(1)) {
+            for message in client.fetch_raw_messages(batch)? {
+                let file = maildir.store(&message.data, &message.flags)?;
+                messages.push(MessageManifest {
+                    uid: message.uid,
+                    file,
+                    flags: message.flags,
+                    internal_date: message.internal_date.map(|d| d.to_rfc3339()),
+                });
+            }
+            progress(name, messages.len(), uids.len());
+        }
+
+        manifest.folders.push(FolderManifest {
+            name: name.clone(),
+            dir,
+            uid_validity,
+            messages,
+        });
+        // Save after every folder so an interrupted backup is still usable.
+        manifest.save(backup_dir)?;
+    }
+
+    Ok(manifest)
+}
+
+/// Restores a backup by appending every message to its original folder,
+/// creating folders as needed and preserving flags and internal dates.
+///
+/// Returns the number of messages restored.
+pub fn restore(
+    client: &mut ImapClient,
+    backup_dir: &Path,
+    mut progress: impl FnMut(&str, usize, usize),
+) -> Result<usize> {
+    let manifest = Manifest::load(backup_dir)?;
+
+    let mut restored = 0;
+    for folder in &manifest.folders {
+        client.ensure_mailbox(&folder.name)?;
+
+        for (done, message) in folder.messages.iter().enumerate() {
+            let path = backup_dir.join(&folder.dir).join(&message.file);
+            let raw = fs::read(&path).with_context(|| format!("Failed to read {:?}", path))?;
+            let date = message
+                .internal_date
+                .as_deref()
+                .and_then(|d| DateTime::parse_from_rfc3339(d).ok());
+
+            let flags = restorable_flags(&message.flags);
+            client.restore_message(&folder.name, &raw, &flags, date)?;
+            restored += 1;
+            progress(&folder.name, done + 1, folder.messages.len());
+        }
+    }
+
+    Ok(restored)
+}
+
+/// Returns the flags a restored message can be appended with. `\Recent` is
+/// set by the server alone, and an APPEND carrying it is rejected.
+fn restorable_flags(flags: &[String]) -> Vec<String> {
+    flags
+        .iter()
+        .filter(|flag| !flag.eq_ignore_ascii_case("\\Recent"))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_folder_dir_name() {
+        assert_eq!(folder_dir_name(0, "INBOX"), "000-INBOX");
+        assert_eq!(
+            folder_dir_name(3, "[Gmail]/Sent Mail"),
+            "003-_Gmail__Sent_Mail"
+        );
+    }
+
+    #[test]
+    fn test_restorable_flags() {
+        let flags = ["\\Seen", "\\Recent", "$Label1"].map(String::from);
+        assert_eq!(restorable_flags(&flags), ["\\Seen", "$Label1"]);
+    }
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let manifest = Manifest {
+            folders: vec![FolderManifest {
+                name: "[Gmail]/Sent Mail".to_string(),
+                dir: PathBuf::from("000-_Gmail__Sent_Mail"),
+                uid_validity: Some(42),
+                messages: vec![MessageManifest {
+                    uid: 7,
+                    file: PathBuf::from("cur/1.rutt:2,S"),
+                    flags: vec!["\\Seen".to_string()],
+                    internal_date: Some("2024-01-01T10:00:00+00:00".to_string()),
+                }],
+            }],
+        };
+
+        manifest.save(dir.path()).unwrap();
+        assert_eq!(Manifest::load(dir.path()).unwrap(), manifest);
+    }
+}
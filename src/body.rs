@@ -0,0 +1,508 @@
+//! Extraction of the text shown for a message.
+//!
+//! Parsing the MIME structure only records where each part lies in the raw
+//! message, without copying. Of all the parts, only the text part shown is
+//! decoded, and at most [`MAX_TEXT_BYTES`] of it, so a newsletter with huge
+//! inline images never has them decoded into memory. HTML is converted to
+//! plain text for the terminal.
+
+use anyhow::{Context, Result};
+use mailparse::body::Body;
+use mailparse::{DispositionType, MailHeaderMap, ParsedMail, parse_mail};
+
+/// Largest amount of a text part, before decoding, that is shown.
+pub const MAX_TEXT_BYTES: usize = 1024 * 1024;
+
+/// The text shown for a message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageText {
+    /// The plain-text version when there is one, else the first other text
+    /// part.
+    pub text: String,
+    /// Both versions, when the message has a plain-text and an HTML part.
+    pub alternatives: Option<Alternatives>,
+    /// The HTML part as sent, before its conversion to text, when the
+    /// message has one.
+    pub html: Option<String>,
+}
+
+/// The plain-text and HTML versions of a message, the latter converted to
+/// text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alternatives {
+    /// The plain-text part.
+    pub plain: String,
+    /// The HTML part as text.
+    pub html: String,
+}
+
+/// Returns the text of a message: its first plain-text part, or failing that
+/// its first HTML part or other text part, decoded.
+///
+/// Text beyond [`MAX_TEXT_BYTES`] is cut off with a note.
+pub fn message_text(raw: &[u8]) -> Result<MessageText> {
+    let mail = parse_mail(raw).context("Failed to parse message")?;
+    let plain = find_text_part(&mail, "text/plain");
+    let html = find_text_part(&mail, "text/html");
+
+    let html = html
+        .map(|html| decode_text(html, MAX_TEXT_BYTES))
+        .transpose()?;
+
+    let (text, alternatives) = match (plain, &html) {
+        (Some(plain), Some(html)) => {
+            let plain = decode_text(plain, MAX_TEXT_BYTES)?;
+            let html = html_to_text(html);
+            (plain.clone(), Some(Alternatives { plain, html }))
+        }
+        (Some(plain), None) => (decode_text(plain, MAX_TEXT_BYTES)?, None),
+        (None, Some(html)) => (html_to_text(html), None),
+        (None, None) => match find_text_part(&mail, "text/") {
+            Some(part) => (decode_text(part, MAX_TEXT_BYTES)?, None),
+            None => ("(No text content)".to_string(), None),
+        },
+    };
+    Ok(MessageText {
+        text,
+        alternatives,
+        html,
+    })
+}
+
+/// Finds the first inline leaf part, depth-first, whose MIME type starts with
+/// `prefix`.
+pub(crate) fn find_text_part<'a>(
+    part: &'a ParsedMail<'a>,
+    prefix: &str,
+) -> Option<&'a ParsedMail<'a>> {
+    if !part.subparts.is_empty() {
+        return part
+            .subparts
+            .iter()
+            .find_map(|subpart| find_text_part(subpart, prefix));
+    }
+    let inline = part.get_content_disposition().disposition != DispositionType::Attachment;
+    (inline && part.ctype.mimetype.to_lowercase().starts_with(prefix)).then_some(part)
+}
+
+/// Decodes at most `limit` bytes of a part's encoded body into text.
+///
+/// The body is cut at a line break, which keeps base64 groups and
+/// quoted-printable escapes whole.
+fn decode_text(part: &ParsedMail, limit: usize) -> Result<String> {
+    let encoded = part.get_body_encoded();
+    let raw = match &encoded {
+        Body::Base64(body) | Body::QuotedPrintable(body) => body.get_raw(),
+        Body::SevenBit(body) | Body::EightBit(body) => body.get_raw(),
+        Body::Binary(body) => body.get_raw(),
+    };
+    if raw.len() <= limit {
+        return part.get_body().context("Failed to decode message text");
+    }
+
+    let end = raw[..limit]
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(limit, |i| i + 1);
+    let transfer_encoding = part
+        .headers
+        .get_first_value("Content-Transfer-Encoding")
+        .map(|s| s.to_lowercase());
+    let text = match Body::new(&raw[..end], &part.ctype, &transfer_encoding) {
+        Body::Base64(body) | Body::QuotedPrintable(body) => body.get_decoded_as_string(),
+        Body::SevenBit(body) | Body::EightBit(body) => body.get_as_string(),
+        Body::Binary(body) => body.get_as_string(),
+    }
+    .context("Failed to decode message text")?;
+
+    Ok(format!(
+        "{}\n[Message truncated; {} more bytes not shown]",
+        text.trim_end(),
+        raw.len() - end
+    ))
+}
+
+/// Placed before a link whose text is a URL of a different site than the one
+/// it leads to, as phishing mail does.
+pub const DECEPTIVE_LINK_MARK: &str = "⚠ ";
+
+/// Elements whose content is not shown.
+const HIDDEN_ELEMENTS: &[&str] = &["head", "script", "style", "title"];
+
+/// Elements that start a new line.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "blockquote",
+    "div",
+    "dl",
+    "dt",
+    "dd",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "table",
+    "tr",
+    "ul",
+];
+
+/// Converts HTML to plain text: tags are dropped, blocks and line breaks
+/// become new lines, list items get a bullet, and link targets follow their
+/// text in angle brackets.
+///
+/// Links whose text looks like a URL of another site than their target are
+/// preceded by [`DECEPTIVE_LINK_MARK`].
+pub fn html_to_text(html: &str) -> String {
+    let mut out = String::new();
+    let mut hidden: Option<String> = None;
+    // The open link's target and where its text starts in `out`.
+    let mut link: Option<(String, usize)> = None;
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        let Some(start) = rest.find('<') else {
+            if hidden.is_none() {
+                push_text(&mut out, rest);
+            }
+            break;
+        };
+        if hidden.is_none() {
+            push_text(&mut out, &rest[..start]);
+        }
+        rest = &rest[start..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        // An unclosed tag runs to the end of the text.
+        let (tag, after) = match rest[1..].find('>') {
+            Some(end) => (&rest[1..end + 1], &rest[end + 2..]),
+            None => (&rest[1..], ""),
+        };
+        rest = after;
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+
+        if let Some(element) = &hidden {
+            if closing && name == *element {
+                hidden = None;
+            }
+            continue;
+        }
+        if !closing && HIDDEN_ELEMENTS.contains(&name.as_str()) && !tag.ends_with('/') {
+            hidden = Some(name);
+            continue;
+        }
+
+        match name.as_str() {
+            "br" => out.push('\n'),
+            "li" if !closing => {
+                new_line(&mut out);
+                out.push_str("* ");
+            }
+            "a" if !closing => link = attribute(tag, "href").map(|href| (href, out.len())),
+            "a" => {
+                if let Some((href, start)) = link.take()
+                    && href.starts_with("http")
+                    && !out.ends_with(&href)
+                {
+                    let text = out[start..].trim();
+                    if is_deceptive_link(text, &href) {
+                        let start = start + out[start..].len() - out[start..].trim_start().len();
+                        out.insert_str(start, DECEPTIVE_LINK_MARK);
+                    }
+                    out.push_str(&format!(" <{}>", href));
+                }
+            }
+            name if BLOCK_ELEMENTS.contains(&name) => new_line(&mut out),
+            _ => {}
+        }
+    }
+
+    // Collapse runs of blank lines left by nested blocks.
+    let mut text = String::new();
+    let mut blank = 0;
+    for line in out.lines().map(str::trim_end) {
+        blank = if line.trim().is_empty() { blank + 1 } else { 0 };
+        if blank < 2 {
+            text.push_str(line.trim_start_matches(' '));
+            text.push('\n');
+        }
+    }
+    text.trim().to_string()
+}
+
+/// Returns true if a link's text looks like a URL whose site differs from
+/// the link's target.
+///
+/// Subdomains of the shown site count as the same site, so `example.com`
+/// linking to `https://mail.example.com/` is not flagged.
+fn is_deceptive_link(text: &str, href: &str) -> bool {
+    let looks_like_url = !text.contains(char::is_whitespace)
+        && !text.contains('@')
+        && (text.starts_with("http://")
+            || text.starts_with("https://")
+            || text.starts_with("www.")
+            || text
+                .split('/')
+                .next()
+                .and_then(|host| host.rsplit_once('.'))
+                .is_some_and(|(name, tld)| {
+                    !name.is_empty()
+                        && tld.len() >= 2
+                        && tld.chars().all(|c| c.is_ascii_alphabetic())
+                }));
+    if !looks_like_url {
+        return false;
+    }
+
+    let shown = url_host(text);
+    let target = url_host(href);
+    !(target == shown || target.ends_with(&format!(".{}", shown)))
+}
+
+/// Returns the lowercased host of a URL, without a leading `www.`.
+fn url_host(url: &str) -> String {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    let host = authority.rsplit('@').next().unwrap_or("");
+    let host = host.split(':').next().unwrap_or("").to_ascii_lowercase();
+    host.strip_prefix("www.").unwrap_or(&host).to_string()
+}
+
+/// Appends a run of HTML text, collapsing whitespace and decoding entities.
+fn push_text(out: &mut String, text: &str) {
+    let is_space = |c: char| c.is_ascii_whitespace();
+    if text.starts_with(is_space) && !out.is_empty() && !out.ends_with([' ', '\n']) {
+        out.push(' ');
+    }
+    let mut words = text.split_ascii_whitespace().peekable();
+    let has_words = words.peek().is_some();
+    for (i, word) in words.enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        out.push_str(&decode_entities(word));
+    }
+    if has_words && text.ends_with(is_space) {
+        out.push(' ');
+    }
+}
+
+/// Ends the current line unless it is already empty.
+fn new_line(out: &mut String) {
+    while out.ends_with(' ') {
+        out.pop();
+    }
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+/// Returns the value of an attribute in a tag's source.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let start = lower.find(&format!("{}=", name))? + name.len() + 1;
+    let value = &tag[start..];
+    let value = match value.chars().next()? {
+        quote @ ('"' | '\'') => value[1..].split(quote).next()?,
+        _ => value.split_whitespace().next()?,
+    };
+    Some(decode_entities(value))
+}
+
+/// Decodes the HTML character references common in mail.
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => ' ',
+                _ => {
+                    let code = match entity.strip_prefix("#x").or(entity.strip_prefix("#X")) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => entity.strip_prefix('#')?.parse().ok()?,
+                    };
+                    char::from_u32(code)?
+                }
+            };
+            Some((c, end + 1))
+        });
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_picks_plain_text_part() {
+        let raw = concat!(
+            "Content-Type: multipart/mixed; boundary=\"b\"\r\n",
+            "\r\n",
+            "--b\r\n",
+            "Content-Type: image/png\r\n",
+            "Content-Transfer-Encoding: base64\r\n",
+            "\r\n",
+            "iVBORw0KGgo=\r\n",
+            "--b\r\n",
+            "Content-Type: text/plain; charset=utf-8\r\n",
+            "Content-Transfer-Encoding: quoted-printable\r\n",
+            "\r\n",
+            "Caf=C3=A9\r\n",
+            "--b--\r\n",
+        );
+        assert_eq!(
+            message_text(raw.as_bytes()).unwrap().text.trim_end(),
+            "Café"
+        );
+
+        let raw = "Content-Type: image/png\r\n\r\nxxxx";
+        assert_eq!(
+            message_text(raw.as_bytes()).unwrap().text,
+            "(No text content)"
+        );
+    }
+
+    #[test]
+    fn test_decodes_charsets() {
+        let raw = concat!(
+            "Content-Type: text/plain; charset=ISO-2022-JP\r\n",
+            "Content-Transfer-Encoding: base64\r\n",
+            "\r\n",
+            "GyRCJDMkcyRLJEEkTxsoQg==\r\n",
+        );
+        assert_eq!(message_text(raw.as_bytes()).unwrap().text, "こんにちは");
+
+        let mut raw = b"Content-Type: text/plain; charset=Shift_JIS\r\n".to_vec();
+        raw.extend_from_slice(b"Content-Transfer-Encoding: 8bit\r\n\r\n");
+        raw.extend_from_slice(&[0x93, 0xfa, 0x96, 0x7b, 0x8c, 0xea]);
+        assert_eq!(message_text(&raw).unwrap().text, "日本語");
+    }
+
+    #[test]
+    fn test_alternatives() {
+        let raw = concat!(
+            "Content-Type: multipart/alternative; boundary=\"b\"\r\n",
+            "\r\n",
+            "--b\r\n",
+            "Content-Type: text/plain\r\n",
+            "\r\n",
+            "Hello\r\n",
+            "--b\r\n",
+            "Content-Type: text/html\r\n",
+            "\r\n",
+            "<p>Hello <b>there</b></p>\r\n",
+            "--b--\r\n",
+        );
+        let text = message_text(raw.as_bytes()).unwrap();
+        assert_eq!(text.text.trim_end(), "Hello");
+        let alternatives = text.alternatives.unwrap();
+        assert_eq!(alternatives.html, "Hello there");
+
+        let raw = "Content-Type: text/html\r\n\r\n<p>Only&nbsp;HTML</p>";
+        let text = message_text(raw.as_bytes()).unwrap();
+        assert_eq!(text.text, "Only HTML");
+        assert_eq!(text.alternatives, None);
+    }
+
+    #[test]
+    fn test_deceptive_links_are_marked() {
+        let html = concat!(
+            "<a href=\"https://evil.example/login\">https://www.paypal.com/signin</a> ",
+            "<a href=\"https://evil.example/\">paypal.com</a> ",
+            "<a href=\"https://mail.example.com/x\">example.com</a> ",
+            "<a href=\"https://www.example.com/\">www.example.com</a> ",
+            "<a href=\"https://evil.example/\">Sign in</a>",
+        );
+        assert_eq!(
+            html_to_text(html),
+            concat!(
+                "⚠ https://www.paypal.com/signin <https://evil.example/login> ",
+                "⚠ paypal.com <https://evil.example/> ",
+                "example.com <https://mail.example.com/x> ",
+                "www.example.com <https://www.example.com/> ",
+                "Sign in <https://evil.example/>",
+            )
+        );
+    }
+
+    #[test]
+    fn test_html_to_text() {
+        let html = concat!(
+            "<html><head><style>p { color: red; }</style></head><body>\n",
+            "<h1>News</h1><!-- tracking -->\n",
+            "<p>Read   the <a href=\"https://example.com/a?x=1&amp;y=2\">post</a>.<br>Thanks</p>\n",
+            "<ul><li>One</li><li>Two &amp; three</li></ul>\n",
+            "<script>alert('x')</script>&#169; &#x263A; &bogus;",
+            "</body></html>",
+        );
+        assert_eq!(
+            html_to_text(html),
+            concat!(
+                "News\n",
+                "Read the post <https://example.com/a?x=1&y=2>.\n",
+                "Thanks\n",
+                "* One\n",
+                "* Two & three\n",
+                "© ☺ &bogus;",
+            )
+        );
+    }
+
+    #[test]
+    fn test_html_to_text_unclosed_tag() {
+        assert_eq!(html_to_text("<é"), "");
+        assert_eq!(html_to_text("a <ü"), "a");
+        assert_eq!(html_to_text("<"), "");
+    }
+
+    #[test]
+    fn test_large_text_is_truncated_at_a_line() {
+        let raw = "Content-Transfer-Encoding: base64\r\n\r\naGVsbG8=\r\nd29ybGQ=\r\n";
+        let mail = parse_mail(raw.as_bytes()).unwrap();
+        assert_eq!(decode_text(&mail, 100).unwrap(), "helloworld");
+        assert_eq!(
+            decode_text(&mail, 14).unwrap(),
+            "hello\n[Message truncated; 10 more bytes not shown]"
+        );
+    }
+}
@@ -0,0 +1,716 @@
+//! Local on-disk cache of message headers and bodies.
+//!
+//! Each mailbox has a directory holding an index of the envelopes last
+//! fetched, as JSON, and the raw source of every message opened, as
+//! `<uidvalidity>-<uid>.eml`. Everything is keyed by UIDVALIDITY: when the
+//! server reports a new one, UIDs no longer identify the same messages and the
+//! mailbox's cache is discarded. On servers with CONDSTORE, the index also
+//! keeps the mailbox's HIGHESTMODSEQ as of the last sync, so the next one only
+//! asks for flags changed since.
+//!
+//! With a size limit, message sources are evicted least recently read first;
+//! envelope indexes are small and always kept. Reading a source updates its
+//! modification time, which serves as the last-used time. The space taken is
+//! counted once and then kept up to date as sources are stored, so storing
+//! one does not go over the whole cache.
+//!
+//! The index only holds the envelopes last fetched, which loading older mail
+//! grows and the next sync shrinks again, so a source is not dropped for
+//! missing from it: only once the server no longer lists the message, or the
+//! mailbox was reset.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::SystemTime;
+
+use crate::body::message_text;
+use crate::fulltext::FullTextIndex;
+use crate::imap_client::{Email, email_from_header};
+use crate::utils::{cache_dir, format_bytes};
+
+/// Name of the envelope index file in a mailbox directory.
+const INDEX_FILE: &str = "index.json";
+
+/// Name of the full-text index file in a mailbox directory.
+const FULL_TEXT_FILE: &str = "fulltext.json";
+
+/// Version of the envelope index, raised when envelopes are parsed
+/// differently so indexes written before are rebuilt.
+const INDEX_VERSION: u32 = 2;
+
+/// The cached envelopes of a mailbox.
+#[derive(Debug, Serialize, Deserialize)]
+struct Index {
+    #[serde(default)]
+    version: u32,
+    uid_validity: u32,
+    emails: Vec<Email>,
+    /// HIGHESTMODSEQ the envelopes are up to date with, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    highest_modseq: Option<u64>,
+}
+
+/// A cached message source found on disk.
+#[derive(Debug)]
+struct CachedBody {
+    path: PathBuf,
+    size: u64,
+    last_used: SystemTime,
+}
+
+/// How much space the cache takes.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheStats {
+    /// Number of mailbox directories.
+    pub mailboxes: usize,
+    /// Number of cached message sources.
+    pub messages: usize,
+    /// Bytes taken by message sources.
+    pub message_bytes: u64,
+    /// Bytes taken by envelope indexes.
+    pub index_bytes: u64,
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} messages ({}) and envelopes ({}) in {} folders",
+            self.messages,
+            format_bytes(self.message_bytes),
+            format_bytes(self.index_bytes),
+            self.mailboxes
+        )
+    }
+}
+
+/// Files removed from the cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Removed {
+    /// Number of files removed.
+    pub files: usize,
+    /// Bytes freed.
+    pub bytes: u64,
+}
+
+impl Removed {
+    fn add(&mut self, size: u64) {
+        self.files += 1;
+        self.bytes += size;
+    }
+}
+
+/// A cache directory for one account.
+#[derive(Debug, Clone)]
+pub struct MessageCache {
+    dir: PathBuf,
+    /// Most bytes message sources may take, if limited.
+    max_bytes: Option<u64>,
+    /// Bytes message sources take, once counted.
+    total: Arc<Mutex<Option<u64>>>,
+}
+
+impl MessageCache {
+    /// Opens a cache stored in the given directory.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        MessageCache {
+            dir: dir.as_ref().to_path_buf(),
+            max_bytes: None,
+            total: Arc::default(),
+        }
+    }
+
+    /// Limits the space message sources may take, evicting the least
+    /// recently read ones when a new one would exceed it.
+    pub fn with_max_size(mut self, max_bytes: Option<u64>) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Opens the cache of an account in the default cache directory.
+    pub fn open_default(account: &str) -> Self {
+        Self::new(cache_dir().join(encode_name(account)))
+    }
+
+    /// Returns the cached envelopes of a mailbox, without bodies.
+    ///
+    /// Returns nothing, discarding the mailbox's cache, if it was stored under
+    /// a different UIDVALIDITY.
+    pub fn emails(&self, mailbox: &str, uid_validity: u32) -> Result<Vec<Email>> {
+        Ok(self.synced_emails(mailbox, uid_validity)?.0)
+    }
+
+    /// Returns the cached envelopes of a mailbox like
+    /// [`emails`](Self::emails), with the HIGHESTMODSEQ they were stored
+    /// under, if any.
+    pub fn synced_emails(
+        &self,
+        mailbox: &str,
+        uid_validity: u32,
+    ) -> Result<(Vec<Email>, Option<u64>)> {
+        let dir = self.mailbox_dir(mailbox);
+        let path = dir.join(INDEX_FILE);
+        if !path.exists() {
+            return Ok((Vec::new(), None));
+        }
+
+        let contents =
+            fs::read(&path).with_context(|| format!("Failed to read cache {:?}", path))?;
+        // A corrupt index is rebuilt from the server like a missing one.
+        let index = match serde_json::from_slice::<Index>(&contents) {
+            Ok(index) if index.version == INDEX_VERSION && index.uid_validity == uid_validity => {
+                index
+            }
+            _ => {
+                fs::remove_dir_all(&dir)
+                    .with_context(|| format!("Failed to clear cache {:?}", dir))?;
+                return Ok((Vec::new(), None));
+            }
+        };
+        Ok((index.emails, index.highest_modseq))
+    }
+
+    /// Replaces the cached envelopes of a mailbox.
+    pub fn store_emails(&self, mailbox: &str, uid_validity: u32, emails: &[Email]) -> Result<()> {
+        self.store_synced_emails(mailbox, uid_validity, emails, None)
+    }
+
+    /// Replaces the cached envelopes of a mailbox with ones up to date as of
+    /// a HIGHESTMODSEQ.
+    pub fn store_synced_emails(
+        &self,
+        mailbox: &str,
+        uid_validity: u32,
+        emails: &[Email],
+        highest_modseq: Option<u64>,
+    ) -> Result<()> {
+        let dir = self.mailbox_dir(mailbox);
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create cache {:?}", dir))?;
+
+        let index = Index {
+            version: INDEX_VERSION,
+            uid_validity,
+            emails: emails.to_vec(),
+            highest_modseq,
+        };
+        let contents = serde_json::to_vec(&index).context("Failed to serialize cache index")?;
+        // Write to a temporary file first so an interrupted write never
+        // leaves a truncated index behind.
+        let path = dir.join(INDEX_FILE);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, contents).with_context(|| format!("Failed to write cache {:?}", tmp))?;
+        fs::rename(&tmp, &path).with_context(|| format!("Failed to write cache {:?}", path))
+    }
+
+    /// Returns the cached envelopes of a mailbox with the UIDVALIDITY they
+    /// were stored under, for reading them without asking the server.
+    pub fn stored_emails(&self, mailbox: &str) -> Result<Option<(u32, Vec<Email>)>> {
+        let path = self.mailbox_dir(mailbox).join(INDEX_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents =
+            fs::read(&path).with_context(|| format!("Failed to read cache {:?}", path))?;
+        Ok(serde_json::from_slice::<Index>(&contents)
+            .ok()
+            .filter(|index| index.version == INDEX_VERSION)
+            .map(|index| (index.uid_validity, index.emails)))
+    }
+
+    /// Returns the cached raw source of a message, if it was opened before.
+    pub fn body(&self, mailbox: &str, uid_validity: u32, uid: u32) -> Option<Vec<u8>> {
+        let path = self.body_path(mailbox, uid_validity, uid);
+        let raw = fs::read(&path).ok()?;
+        // Mark it recently used so eviction keeps it longest. Failing to is
+        // harmless.
+        let _ = File::options()
+            .append(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        Some(raw)
+    }
+
+    /// Stores the raw source of a message, evicting others to stay within the
+    /// size limit.
+    pub fn store_body(&self, mailbox: &str, uid_validity: u32, uid: u32, raw: &[u8]) -> Result<()> {
+        let dir = self.mailbox_dir(mailbox);
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create cache {:?}", dir))?;
+        let path = self.body_path(mailbox, uid_validity, uid);
+        let replaced = fs::metadata(&path).map_or(0, |metadata| metadata.len());
+        fs::write(&path, raw).with_context(|| format!("Failed to write cache {:?}", path))?;
+
+        let Some(max_bytes) = self.max_bytes else {
+            return Ok(());
+        };
+        let mut total = self.total.lock().unwrap_or_else(PoisonError::into_inner);
+        let bytes = match *total {
+            Some(bytes) => bytes.saturating_sub(replaced) + raw.len() as u64,
+            None => self.bodies()?.iter().map(|body| body.size).sum(),
+        };
+        *total = Some(bytes);
+        drop(total);
+        if bytes > max_bytes {
+            self.evict()?;
+        }
+        Ok(())
+    }
+
+    /// Removes the sources of messages the server no longer lists.
+    pub fn forget_bodies(&self, mailbox: &str, uid_validity: u32, uids: &[u32]) -> Result<()> {
+        let mut removed = Removed::default();
+        for &uid in uids {
+            let path = self.body_path(mailbox, uid_validity, uid);
+            if let Ok(metadata) = fs::metadata(&path) {
+                fs::remove_file(&path).with_context(|| format!("Failed to remove {:?}", path))?;
+                removed.add(metadata.len());
+            }
+        }
+        let mut total = self.total.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(bytes) = total.as_mut() {
+            *bytes = bytes.saturating_sub(removed.bytes);
+        }
+        Ok(())
+    }
+
+    /// Searches the cached messages of a mailbox for the words of a query,
+    /// returning the envelopes of those containing them all, newest first.
+    ///
+    /// Both the envelopes last fetched and every message source kept are
+    /// searched; a message with only its source cached gets an envelope from
+    /// its header. The mailbox's full-text index is updated first: messages
+    /// cached since the last search are added, and those no longer cached
+    /// dropped.
+    pub fn search_text(&self, mailbox: &str, uid_validity: u32, query: &str) -> Result<Vec<Email>> {
+        let mut emails = self
+            .emails(mailbox, uid_validity)?
+            .into_iter()
+            .map(|email| (email._uid, email))
+            .collect::<HashMap<_, _>>();
+        let with_body = self.body_uids(mailbox, uid_validity);
+        let path = self.mailbox_dir(mailbox).join(FULL_TEXT_FILE);
+        let mut index = fs::read(&path)
+            .ok()
+            .and_then(|contents| serde_json::from_slice::<FullTextIndex>(&contents).ok())
+            .filter(|index| index.is_current(uid_validity))
+            .unwrap_or_else(|| FullTextIndex::new(uid_validity));
+
+        let uids = emails
+            .keys()
+            .chain(&with_body)
+            .copied()
+            .collect::<HashSet<_>>();
+        let mut changed = index.retain(&uids);
+        for &uid in &uids {
+            if !index.needs(uid, with_body.contains(&uid)) {
+                continue;
+            }
+            // Read directly rather than with `body`, so indexing does not
+            // count as reading for eviction.
+            let raw = with_body
+                .contains(&uid)
+                .then(|| fs::read(self.body_path(mailbox, uid_validity, uid)).ok())
+                .flatten();
+            let email = match (emails.get(&uid), &raw) {
+                (Some(email), _) => email.clone(),
+                (None, Some(raw)) => Email {
+                    _uid: uid,
+                    ..email_from_header(raw)
+                },
+                // Removed since listed.
+                (None, None) => continue,
+            };
+            let text = raw.and_then(|raw| message_text(&raw).ok());
+            index.add(&email, text.as_ref().map(|text| text.text.as_str()));
+            changed = true;
+        }
+
+        if changed && !uids.is_empty() {
+            let contents =
+                serde_json::to_vec(&index).context("Failed to serialize full-text index")?;
+            let tmp = path.with_extension("tmp");
+            fs::write(&tmp, contents)
+                .with_context(|| format!("Failed to write cache {:?}", tmp))?;
+            fs::rename(&tmp, &path).with_context(|| format!("Failed to write cache {:?}", path))?;
+        }
+
+        let mut found = index
+            .search(query)
+            .into_iter()
+            .filter_map(|uid| match emails.remove(&uid) {
+                Some(email) => Some(email),
+                None => {
+                    let raw = fs::read(self.body_path(mailbox, uid_validity, uid)).ok()?;
+                    Some(Email {
+                        _uid: uid,
+                        ..email_from_header(&raw)
+                    })
+                }
+            })
+            .collect::<Vec<_>>();
+        found.sort_by_key(|email| std::cmp::Reverse(email.date));
+        Ok(found)
+    }
+
+    /// Returns the UIDs of the messages of a mailbox whose sources are kept
+    /// under the given UIDVALIDITY, going by the file names alone.
+    fn body_uids(&self, mailbox: &str, uid_validity: u32) -> HashSet<u32> {
+        let prefix = format!("{}-", uid_validity);
+        let Ok(entries) = fs::read_dir(self.mailbox_dir(mailbox)) else {
+            return HashSet::new();
+        };
+        entries
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name().into_string().ok()?;
+                name.strip_prefix(&prefix)?
+                    .strip_suffix(".eml")?
+                    .parse()
+                    .ok()
+            })
+            .collect()
+    }
+
+    /// Returns how much space the cache takes.
+    pub fn stats(&self) -> Result<CacheStats> {
+        let mut stats = CacheStats::default();
+        for dir in self.mailbox_dirs()? {
+            stats.mailboxes += 1;
+            for name in [INDEX_FILE, FULL_TEXT_FILE] {
+                if let Ok(index) = fs::metadata(dir.join(name)) {
+                    stats.index_bytes += index.len();
+                }
+            }
+        }
+        for body in self.bodies()? {
+            stats.messages += 1;
+            stats.message_bytes += body.size;
+        }
+        Ok(stats)
+    }
+
+    /// Removes least recently read message sources until they fit in the
+    /// size limit.
+    pub fn evict(&self) -> Result<Removed> {
+        let mut removed = Removed::default();
+        let Some(max_bytes) = self.max_bytes else {
+            return Ok(removed);
+        };
+        let mut bodies = self.bodies()?;
+        let mut total = bodies.iter().map(|body| body.size).sum::<u64>();
+        bodies.sort_by_key(|body| body.last_used);
+        for body in bodies {
+            if total <= max_bytes {
+                break;
+            }
+            fs::remove_file(&body.path)
+                .with_context(|| format!("Failed to remove {:?}", body.path))?;
+            total -= body.size;
+            removed.add(body.size);
+        }
+        *self.total.lock().unwrap_or_else(PoisonError::into_inner) = Some(total);
+        Ok(removed)
+    }
+
+    /// Removes files no longer needed: message sources kept under another
+    /// UIDVALIDITY than their mailbox's index, and leftovers of interrupted
+    /// writes. Then evicts down to the size limit.
+    ///
+    /// Sources of messages missing from the index are kept, as it only holds
+    /// the envelopes last fetched; those of messages the server removed go
+    /// when a sync finds them gone.
+    pub fn compact(&self) -> Result<Removed> {
+        let mut removed = Removed::default();
+        for dir in self.mailbox_dirs()? {
+            let uid_validity = fs::read(dir.join(INDEX_FILE))
+                .ok()
+                .and_then(|contents| serde_json::from_slice::<Index>(&contents).ok())
+                .map(|index| index.uid_validity.to_string());
+
+            for entry in fs::read_dir(&dir).with_context(|| format!("Failed to read {:?}", dir))? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let kept = match name.strip_suffix(".eml") {
+                    // Without an index, which UIDVALIDITY is current is not
+                    // known.
+                    Some(stem) => uid_validity.as_ref().is_none_or(|validity| {
+                        stem.split_once('-')
+                            .is_some_and(|(stored, _)| stored == validity)
+                    }),
+                    None => name == INDEX_FILE || name == FULL_TEXT_FILE,
+                };
+                if kept {
+                    continue;
+                }
+                let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+                fs::remove_file(entry.path())
+                    .with_context(|| format!("Failed to remove {:?}", entry.path()))?;
+                removed.add(size);
+            }
+            // Only removed when nothing is left in it.
+            let _ = fs::remove_dir(&dir);
+        }
+
+        let evicted = self.evict()?;
+        removed.files += evicted.files;
+        removed.bytes += evicted.bytes;
+        Ok(removed)
+    }
+
+    /// Returns the mailbox directories in the cache.
+    fn mailbox_dirs(&self) -> Result<Vec<PathBuf>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut dirs = Vec::new();
+        for entry in
+            fs::read_dir(&self.dir).with_context(|| format!("Failed to read {:?}", self.dir))?
+        {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                dirs.push(entry.path());
+            }
+        }
+        Ok(dirs)
+    }
+
+    /// Returns every cached message source.
+    fn bodies(&self) -> Result<Vec<CachedBody>> {
+        let mut bodies = Vec::new();
+        for dir in self.mailbox_dirs()? {
+            for entry in fs::read_dir(&dir).with_context(|| format!("Failed to read {:?}", dir))? {
+                let entry = entry?;
+                let path = entry.path();
+                if path.extension().is_none_or(|ext| ext != "eml") {
+                    continue;
+                }
+                let metadata = entry.metadata()?;
+                bodies.push(CachedBody {
+                    path,
+                    size: metadata.len(),
+                    last_used: metadata.modified()?,
+                });
+            }
+        }
+        Ok(bodies)
+    }
+
+    /// Returns the directory of a mailbox.
+    fn mailbox_dir(&self, mailbox: &str) -> PathBuf {
+        self.dir.join(encode_name(mailbox))
+    }
+
+    /// Returns the path a message's source is stored at.
+    fn body_path(&self, mailbox: &str, uid_validity: u32, uid: u32) -> PathBuf {
+        self.mailbox_dir(mailbox)
+            .join(format!("{}-{}.eml", uid_validity, uid))
+    }
+}
+
+/// Encodes a mailbox or account name as a single path component, escaping
+/// bytes other than ASCII letters, digits, `.`, `-`, `_`, and `@` as `%XX`.
+pub(crate) fn encode_name(name: &str) -> String {
+    let mut encoded = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || b".-_@".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    // `.` and `..` would not name a new directory.
+    if encoded.bytes().all(|b| b == b'.') {
+        encoded = encoded.replace('.', "%2E");
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imap_client::test_email;
+    use tempfile::TempDir;
+
+    fn email(uid: u32) -> Email {
+        Email {
+            subject: format!("Message {}", uid),
+            body: Some("not cached".to_string()),
+            message_id: Some(format!("<{}@example.com>", uid)),
+            ..test_email(uid, "alice@example.com")
+        }
+    }
+
+    #[test]
+    fn test_encode_name() {
+        assert_eq!(encode_name("INBOX"), "INBOX");
+        assert_eq!(encode_name("[Gmail]/All Mail"), "%5BGmail%5D%2FAll%20Mail");
+        assert_eq!(encode_name(".."), "%2E%2E");
+    }
+
+    #[test]
+    fn test_emails_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let cache = MessageCache::new(dir.path());
+        assert!(cache.emails("INBOX", 1).unwrap().is_empty());
+
+        cache
+            .store_emails("INBOX", 1, &[email(10), email(11)])
+            .unwrap();
+        let emails = cache.emails("INBOX", 1).unwrap();
+        assert_eq!(emails.len(), 2);
+        assert_eq!(emails[0]._uid, 10);
+        assert_eq!(emails[1].subject, "Message 11");
+        assert_eq!(emails[0].from.email.as_deref(), Some("alice@example.com"));
+        // Bodies are cached as raw sources, not in the index.
+        assert_eq!(emails[0].body, None);
+    }
+
+    #[test]
+    fn test_synced_emails_keep_highest_modseq() {
+        let dir = TempDir::new().unwrap();
+        let cache = MessageCache::new(dir.path());
+        cache
+            .store_synced_emails("INBOX", 1, &[email(10)], Some(4242))
+            .unwrap();
+        let (emails, modseq) = cache.synced_emails("INBOX", 1).unwrap();
+        assert_eq!((emails.len(), modseq), (1, Some(4242)));
+
+        // Envelopes stored without one are synced in full next time.
+        cache.store_emails("INBOX", 1, &[email(10)]).unwrap();
+        assert_eq!(cache.synced_emails("INBOX", 1).unwrap().1, None);
+        let (emails, modseq) = cache.synced_emails("INBOX", 2).unwrap();
+        assert!(emails.is_empty() && modseq.is_none());
+    }
+
+    #[test]
+    fn test_new_uid_validity_discards_mailbox() {
+        let dir = TempDir::new().unwrap();
+        let cache = MessageCache::new(dir.path());
+        cache.store_emails("INBOX", 1, &[email(10)]).unwrap();
+        cache
+            .store_body("INBOX", 1, 10, b"Subject: x\r\n\r\nHi")
+            .unwrap();
+        assert_eq!(
+            cache.body("INBOX", 1, 10).as_deref(),
+            Some(&b"Subject: x\r\n\r\nHi"[..])
+        );
+        assert_eq!(cache.body("INBOX", 2, 10), None);
+
+        assert!(cache.emails("INBOX", 2).unwrap().is_empty());
+        assert_eq!(cache.body("INBOX", 1, 10), None);
+    }
+
+    #[test]
+    fn test_old_index_version_is_rebuilt() {
+        let dir = TempDir::new().unwrap();
+        let cache = MessageCache::new(dir.path());
+        cache.store_emails("INBOX", 1, &[email(10)]).unwrap();
+        assert_eq!(cache.emails("INBOX", 1).unwrap().len(), 1);
+
+        let path = dir.path().join("INBOX").join(INDEX_FILE);
+        let contents = fs::read_to_string(&path).unwrap();
+        let old = contents.replace(&format!("\"version\":{},", INDEX_VERSION), "");
+        assert_ne!(old, contents);
+        fs::write(&path, old).unwrap();
+        assert!(cache.emails("INBOX", 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_evicts_least_recently_read() {
+        let dir = TempDir::new().unwrap();
+        let cache = MessageCache::new(dir.path()).with_max_size(Some(25));
+        cache.store_body("INBOX", 1, 1, &[b'a'; 10]).unwrap();
+        cache.store_body("INBOX", 1, 2, &[b'b'; 10]).unwrap();
+        // Make the first message the older one, then read it so the second
+        // becomes least recently used.
+        let old = SystemTime::now() - std::time::Duration::from_secs(60);
+        for uid in [1, 2] {
+            File::options()
+                .append(true)
+                .open(cache.body_path("INBOX", 1, uid))
+                .unwrap()
+                .set_modified(old - std::time::Duration::from_secs(uid as u64))
+                .unwrap();
+        }
+        assert!(cache.body("INBOX", 1, 1).is_some());
+
+        cache.store_body("Work", 1, 3, &[b'c'; 10]).unwrap();
+        assert!(cache.body("INBOX", 1, 1).is_some());
+        assert!(cache.body("INBOX", 1, 2).is_none());
+        assert!(cache.body("Work", 1, 3).is_some());
+    }
+
+    #[test]
+    fn test_search_text() {
+        let dir = TempDir::new().unwrap();
+        let cache = MessageCache::new(dir.path());
+        cache
+            .store_emails("INBOX", 1, &[email(1), email(2)])
+            .unwrap();
+        let uids = |query| {
+            cache
+                .search_text("INBOX", 1, query)
+                .unwrap()
+                .iter()
+                .map(|email| email._uid)
+                .collect::<Vec<_>>()
+        };
+        let mut alice = uids("alice");
+        alice.sort_unstable();
+        assert_eq!(alice, [1, 2]);
+        assert!(uids("invoice").is_empty());
+
+        // Bodies opened since the last search are indexed.
+        cache
+            .store_body("INBOX", 1, 2, b"Subject: x\r\n\r\nThe invoice is attached.")
+            .unwrap();
+        assert_eq!(uids("invoice"), [2]);
+
+        // Sources are searched once the envelopes are no longer listed,
+        // until the message is gone from the server.
+        cache.store_emails("INBOX", 1, &[email(1)]).unwrap();
+        assert_eq!(uids("invoice"), [2]);
+        cache.forget_bodies("INBOX", 1, &[2]).unwrap();
+        assert!(uids("invoice").is_empty());
+        assert_eq!(uids("message"), [1]);
+    }
+
+    #[test]
+    fn test_stats_and_compact() {
+        let dir = TempDir::new().unwrap();
+        let cache = MessageCache::new(dir.path());
+        assert_eq!(cache.stats().unwrap(), CacheStats::default());
+
+        cache.store_emails("INBOX", 7, &[email(1)]).unwrap();
+        cache.store_body("INBOX", 7, 1, b"kept").unwrap();
+        // Older than the envelopes in the index, and from an old
+        // UIDVALIDITY.
+        cache.store_body("INBOX", 7, 2, b"older").unwrap();
+        cache.store_body("INBOX", 6, 1, b"reset").unwrap();
+        // A mailbox whose index is missing, and a write cut short.
+        cache.store_body("Old", 1, 1, b"orphan").unwrap();
+        fs::write(cache.mailbox_dir("Old").join("index.tmp"), b"{").unwrap();
+
+        let stats = cache.stats().unwrap();
+        assert_eq!(stats.mailboxes, 2);
+        assert_eq!(stats.messages, 4);
+        assert_eq!(stats.message_bytes, 20);
+        assert!(stats.index_bytes > 0);
+
+        let removed = cache.compact().unwrap();
+        assert_eq!(removed, Removed { files: 2, bytes: 6 });
+        assert_eq!(cache.body("INBOX", 7, 1).unwrap(), b"kept");
+        assert_eq!(cache.body("INBOX", 7, 2).unwrap(), b"older");
+        assert_eq!(cache.body("Old", 1, 1).unwrap(), b"orphan");
+        assert_eq!(cache.emails("INBOX", 7).unwrap().len(), 1);
+        let stats = cache.stats().unwrap();
+        assert_eq!((stats.mailboxes, stats.messages), (2, 3));
+
+        cache.forget_bodies("INBOX", 7, &[2, 3]).unwrap();
+        assert!(cache.body("INBOX", 7, 2).is_none());
+        assert_eq!(cache.stats().unwrap().messages, 2);
+    }
+}
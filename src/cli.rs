@@ -0,0 +1,776 @@
+//! Command-line interface definitions and non-interactive subcommands.
+//!
+//! Without a subcommand rutt launches the terminal UI. Subcommands provide
+//! scriptable access to the same functionality.
+
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand};
+use std::fs::File;
+use std::io::{BufWriter, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use crate::backup::{backup, restore};
+use crate::compose::Draft;
+use crate::config::{Config, ImapOverrides, default_config_path};
+use crate::config_check::{Severity, check_config_file};
+use crate::contacts::{AddressBook, Contact, ContactFormat, read_contacts, write_contacts};
+use crate::folders::InboxTab;
+use crate::imap_client::ImapClient;
+use crate::index_export::{IndexEntry, IndexFormat, write_index};
+use crate::mbox::MboxWriter;
+use crate::outbox::Outbox;
+use crate::smtp::{SendOutcome, check_smtp, save_to_sent, send_or_queue};
+use crate::special_use::SpecialUse;
+use crate::transfer::exceeds_budget;
+use crate::utils::format_bytes;
+
+/// Exit code used when a message was queued instead of sent (EX_TEMPFAIL).
+pub const EXIT_QUEUED: u8 = 75;
+
+/// A minimal Gmail IMAP client with a mutt-like terminal interface.
+#[derive(Debug, Parser)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    /// Open compose mode for a `mailto:` URI.
+    #[arg(value_name = "MAILTO")]
+    pub mailto: Option<String>,
+
+    /// Validate config.toml and exit without connecting.
+    #[arg(long)]
+    pub check_config: bool,
+
+    /// Read the configuration from this file instead of looking it up in
+    /// `$RUTT_CONFIG`, `./config.toml`, and `~/.config/rutt/config.toml`.
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Apply the settings of a `[profiles]` entry from config.toml.
+    #[arg(long, global = true, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Number of recent messages fetched when opening a folder, instead of
+    /// `imap.fetch_limit`.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub fetch_limit: Option<u32>,
+
+    /// Folder opened at launch, instead of `imap.default_mailbox`.
+    #[arg(long, value_name = "FOLDER")]
+    pub mailbox: Option<String>,
+
+    /// Seconds the IMAP server may stay silent, instead of
+    /// `imap.timeout_secs`.
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub timeout: Option<u64>,
+
+    /// Do not print notices around the interface, such as config warnings or
+    /// going offline.
+    #[arg(short, long)]
+    pub quiet: bool,
+
+    /// Report a failed subcommand on stderr as a JSON object with the
+    /// cause, exit code, and message.
+    #[arg(long, global = true)]
+    pub json_errors: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    /// Returns the config file to read.
+    pub fn config_path(&self) -> PathBuf {
+        self.config.clone().unwrap_or_else(default_config_path)
+    }
+
+    /// Returns the `[imap]` settings given on the command line, to apply
+    /// over the config whenever it is loaded.
+    pub fn imap_overrides(&self) -> ImapOverrides {
+        ImapOverrides {
+            fetch_limit: self.fetch_limit,
+            default_mailbox: self.mailbox.clone(),
+            timeout_secs: self.timeout,
+        }
+    }
+}
+
+/// Non-interactive subcommands.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Print the headers of the most recent messages in a folder.
+    Fetch(FetchArgs),
+    /// Compose and send a message without opening the UI.
+    Send(SendArgs),
+    /// Export messages matching a search to an mbox file.
+    Export(ExportArgs),
+    /// Back up folders to Maildirs with a manifest of flags and dates.
+    Backup(BackupArgs),
+    /// Restore a backup by appending its messages back to the server.
+    Restore(RestoreArgs),
+    /// Show how much space the local cache takes.
+    Cache(CacheArgs),
+    /// Check the connections to the IMAP and SMTP servers step by step.
+    Doctor(DoctorArgs),
+    /// Export the address book to a vCard or CSV file, or add contacts to it.
+    Contacts(ContactsArgs),
+}
+
+/// Arguments for `rutt fetch`.
+#[derive(Debug, Args)]
+pub struct FetchArgs {
+    /// Number of recent messages to list, instead of `imap.fetch_limit`.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub limit: Option<u32>,
+    /// Folder to list, instead of `imap.default_mailbox`.
+    #[arg(long, value_name = "FOLDER")]
+    pub mailbox: Option<String>,
+    /// Print a JSON array of objects instead of tab-separated lines.
+    #[arg(long)]
+    pub json: bool,
+}
+
+/// Arguments for `rutt send`.
+#[derive(Debug, Args)]
+pub struct SendArgs {
+    /// Recipient address. May be repeated.
+    #[arg(long, required = true)]
+    pub to: Vec<String>,
+    /// Carbon copy recipient. May be repeated.
+    #[arg(long)]
+    pub cc: Vec<String>,
+    /// Blind carbon copy recipient. May be repeated.
+    #[arg(long)]
+    pub bcc: Vec<String>,
+    /// Subject line.
+    #[arg(long, default_value = "")]
+    pub subject: String,
+    /// Read the body from a file (`-` for stdin). Defaults to stdin when it
+    /// is not a terminal.
+    #[arg(long, value_name = "PATH", conflicts_with = "body")]
+    pub body_file: Option<PathBuf>,
+    /// Message body text.
+    #[arg(long)]
+    pub body: Option<String>,
+    /// File to attach. May be repeated.
+    #[arg(long, value_name = "PATH")]
+    pub attach: Vec<PathBuf>,
+    /// Also save a copy to the Sent mailbox over IMAP.
+    #[arg(long)]
+    pub save_sent: bool,
+}
+
+/// Arguments for `rutt export`.
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// Gmail search query (e.g. "from:foo before:2024/01/01").
+    #[arg(long)]
+    pub query: String,
+    /// Path of the mbox file to write.
+    #[arg(long, value_name = "PATH")]
+    pub out: PathBuf,
+    /// Mailbox to search, instead of the one holding all mail.
+    #[arg(long)]
+    pub mailbox: Option<String>,
+    /// Number of messages downloaded per request.
+    #[arg(long, default_value_t = 50, value_parser = clap::value_parser!(u32).range(1..))]
+    pub batch_size: u32,
+}
+
+/// Arguments for `rutt backup`.
+#[derive(Debug, Args)]
+#[command(group(clap::ArgGroup::new("folders").required(true).args(["all_folders", "folder"])))]
+pub struct BackupArgs {
+    /// Back up every selectable folder.
+    #[arg(long)]
+    pub all_folders: bool,
+    /// Folder to back up. May be repeated.
+    #[arg(long)]
+    pub folder: Vec<String>,
+    /// Number of messages downloaded per request.
+    #[arg(long, default_value_t = 50, value_parser = clap::value_parser!(u32).range(1..))]
+    pub batch_size: u32,
+    /// Directory to write the backup into.
+    pub dir: PathBuf,
+}
+
+/// Arguments for `rutt restore`.
+#[derive(Debug, Args)]
+pub struct RestoreArgs {
+    /// Backup directory created by `rutt backup`.
+    pub dir: PathBuf,
+}
+
+/// Arguments for `rutt cache`.
+#[derive(Debug, Args)]
+pub struct CacheArgs {
+    /// Remove message sources from before a UIDVALIDITY reset, leftover
+    /// temporary files, and anything over the size limit.
+    #[arg(long)]
+    pub compact: bool,
+}
+
+/// Arguments for `rutt doctor`.
+#[derive(Debug, Args)]
+pub struct DoctorArgs {
+    /// Also send a test message to your own address.
+    #[arg(long)]
+    pub send_test: bool,
+}
+
+/// Arguments for `rutt contacts`.
+#[derive(Debug, Args)]
+pub struct ContactsArgs {
+    #[command(subcommand)]
+    pub action: ContactsAction,
+}
+
+/// What `rutt contacts` does with the address book.
+#[derive(Debug, Subcommand)]
+pub enum ContactsAction {
+    /// Write every contact to a vCard (`.vcf`) or CSV file.
+    Export {
+        /// File to write; vCard for `.vcf`, CSV otherwise.
+        file: PathBuf,
+    },
+    /// Add the contacts of a vCard (`.vcf`) or CSV file.
+    Import {
+        /// File to read; vCard for `.vcf`, CSV otherwise.
+        file: PathBuf,
+    },
+    /// Add a contact by hand.
+    Add {
+        /// Email address.
+        address: String,
+        /// Display name.
+        #[arg(long)]
+        name: Option<String>,
+    },
+}
+
+impl SendArgs {
+    /// Builds a draft from the arguments, reading the body as requested.
+    fn to_draft(&self) -> Result<Draft> {
+        let body = match (&self.body, &self.body_file) {
+            (Some(body), _) => body.clone(),
+            (None, Some(path)) if path.as_os_str() == "-" => read_stdin()?,
+            (None, Some(path)) => std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read body from {:?}", path))?,
+            (None, None) if !std::io::stdin().is_terminal() => read_stdin()?,
+            (None, None) => String::new(),
+        };
+
+        Ok(Draft {
+            to: self.to.join(", "),
+            cc: self.cc.join(", "),
+            bcc: self.bcc.join(", "),
+            subject: self.subject.clone(),
+            body,
+            attachments: self.attach.clone(),
+            save_sent: self.save_sent,
+            ..Default::default()
+        })
+    }
+}
+
+fn read_stdin() -> Result<String> {
+    let mut body = String::new();
+    std::io::stdin()
+        .read_to_string(&mut body)
+        .context("Failed to read body from stdin")?;
+    Ok(body)
+}
+
+/// Runs `rutt send`.
+///
+/// Exits with 0 when the message was sent and [`EXIT_QUEUED`] when the server
+/// was unreachable and the message was queued in the outbox. Other failures
+/// are returned as errors.
+fn run_send(config: &Config, args: &SendArgs) -> Result<ExitCode> {
+    let draft = args.to_draft()?;
+
+    let outcome = send_or_queue(config, &Outbox::open_default(&config.account()), &draft)?;
+    if let Err(e) = AddressBook::open_default().and_then(|mut book| book.harvest(&draft)) {
+        eprintln!("Warning: recipients not added to contacts: {e:#}");
+    }
+    match outcome {
+        SendOutcome::Sent(raw) => {
+            // The message went out, so failing to keep a copy is no failure
+            // to send.
+            if args.save_sent
+                && let Err(e) = save_to_sent(config, &raw)
+            {
+                eprintln!("Warning: sent, but not saved to the Sent mailbox: {e:#}");
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        SendOutcome::Queued => {
+            eprintln!("SMTP server unreachable; message queued in outbox");
+            Ok(ExitCode::from(EXIT_QUEUED))
+        }
+    }
+}
+
+/// Runs `rutt fetch`.
+///
+/// Prints one record per message, newest first, with the same fields as
+/// `:export-index`. Envelopes that could not be fetched are reported on
+/// stderr, and make the command fail after the others were printed.
+fn run_fetch(config: &Config, args: &FetchArgs) -> Result<ExitCode> {
+    let mut client = ImapClient::connect_with_config(&config.gmail, &config.imap)
+        .context("Failed to connect to the IMAP server")?;
+    if let Some(mailbox) = &args.mailbox {
+        client.set_mailbox(mailbox);
+    }
+
+    let fetched = client.fetch_emails(args.limit.unwrap_or(config.imap.fetch_limit))?;
+    let important = if client.is_gmail() {
+        let mailbox = client.mailbox().to_string();
+        client.search(&mailbox, InboxTab::Important.query())?
+    } else {
+        Vec::new()
+    };
+    let entries = fetched
+        .emails
+        .iter()
+        .map(|email| IndexEntry::new(email, important.contains(&email._uid)))
+        .collect::<Vec<_>>();
+
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    if args.json {
+        write_index(&mut out, IndexFormat::Json, &entries)?;
+    } else {
+        for entry in &entries {
+            writeln!(
+                out,
+                "{}\t{}\t{}\t{}",
+                entry.uid, entry.date, entry.from, entry.subject
+            )?;
+        }
+    }
+    out.flush()?;
+
+    if fetched.failed.is_empty() {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        eprintln!(
+            "Failed to fetch {} messages: UIDs {}",
+            fetched.failed.len(),
+            fetched
+                .failed
+                .iter()
+                .map(u32::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+/// Runs `rutt export`.
+///
+/// The search runs on the server; matching messages are downloaded in batches
+/// and appended to the mbox file, with progress reported on stderr.
+fn run_export(config: &Config, args: &ExportArgs) -> Result<ExitCode> {
+    let mut client = ImapClient::connect_with_config(&config.gmail, &config.imap)
+        .context("Failed to connect to the IMAP server")?;
+
+    let mailbox = match &args.mailbox {
+        Some(mailbox) => mailbox.clone(),
+        None => client.special_folder(SpecialUse::All),
+    };
+    let uids = client.search(&mailbox, &args.query)?;
+    eprintln!("Found {} messages", uids.len());
+
+    if config.network.transfer_budget_kb.is_some() {
+        let total = uids
+            .chunks(args.batch_size as usize)
+            .map(|batch| client.total_size(batch))
+            .sum::<Result<u64>>()?;
+        if exceeds_budget(total, config.network.transfer_budget_kb) {
+            eprintln!(
+                "Warning: export will download {}, over the transfer budget",
+                format_bytes(total)
+            );
+        }
+    }
+
+    let file =
+        File::create(&args.out).with_context(|| format!("Failed to create {:?}", args.out))?;
+    let mut mbox = MboxWriter::new(BufWriter::new(file));
+
+    let mut written = 0;
+    for batch in uids.chunks(args.batch_size as usize) {
+        for message in client.fetch_raw_messages(batch)? {
+            mbox.write_message(&message.data, message.internal_date)?;
+            written += 1;
+        }
+        eprint!("\rDownloaded {}/{}", written, uids.len());
+        std::io::stderr().flush()?;
+    }
+    mbox.finish()?;
+    eprintln!();
+
+    eprintln!("Wrote {} messages to {}", written, args.out.display());
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Runs a non-interactive subcommand.
+pub fn run_command(config: &Config, command: &Command) -> Result<ExitCode> {
+    match command {
+        Command::Fetch(args) => run_fetch(config, args),
+        Command::Send(args) => run_send(config, args),
+        Command::Export(args) => run_export(config, args),
+        Command::Backup(args) => run_backup(config, args),
+        Command::Restore(args) => run_restore(config, args),
+        Command::Cache(args) => run_cache(config, args),
+        Command::Doctor(args) => run_doctor(config, args),
+        Command::Contacts(args) => run_contacts(&args.action),
+    }
+}
+
+/// Runs `rutt contacts`. Works offline, on the address book in the data
+/// directory.
+fn run_contacts(action: &ContactsAction) -> Result<ExitCode> {
+    let mut book = AddressBook::open_default()?;
+    match action {
+        ContactsAction::Export { file } => {
+            let out = File::create(file).with_context(|| format!("Failed to create {:?}", file))?;
+            write_contacts(
+                BufWriter::new(out),
+                ContactFormat::from_path(file),
+                book.contacts(),
+            )
+            .with_context(|| format!("Failed to write {:?}", file))?;
+            println!(
+                "Wrote {} contacts to {}",
+                book.contacts().len(),
+                file.display()
+            );
+        }
+        ContactsAction::Import { file } => {
+            let contents = std::fs::read_to_string(file)
+                .with_context(|| format!("Failed to read {:?}", file))?;
+            let contacts = read_contacts(&contents, ContactFormat::from_path(file))
+                .with_context(|| format!("Failed to parse {:?}", file))?;
+            let total = contacts.len();
+            let added = contacts
+                .into_iter()
+                .filter(|contact| book.add(contact.clone()))
+                .count();
+            book.save()?;
+            println!(
+                "Imported {} contacts, {} new, from {}",
+                total,
+                added,
+                file.display()
+            );
+        }
+        ContactsAction::Add { address, name } => {
+            book.add(Contact {
+                address: address.clone(),
+                name: name.clone(),
+                manual: true,
+            });
+            book.save()?;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Prints the outcome of one `rutt doctor` check.
+fn print_check(name: &str, result: &Result<String>) {
+    match result {
+        Ok(detail) => println!("{:<14} ok      {}", name, detail),
+        Err(e) => println!("{:<14} FAILED  {:#}", name, e),
+    }
+}
+
+/// Runs `rutt doctor`: connects to the IMAP server, then goes through the
+/// SMTP connection stage by stage, printing each result as it completes.
+/// Fails if any check did.
+fn run_doctor(config: &Config, args: &DoctorArgs) -> Result<ExitCode> {
+    let imap = ImapClient::connect_with_config(&config.gmail, &config.imap).map(|client| {
+        let kind = if client.is_gmail() { "Gmail" } else { "IMAP" };
+        format!("{}:{} ({})", config.imap.host, config.imap.port, kind)
+    });
+    print_check("imap", &imap);
+
+    let smtp = check_smtp(config, args.send_test, |stage, result| {
+        print_check(&format!("smtp {}", stage), result);
+    });
+    Ok(if imap.is_ok() && smtp {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// Runs `rutt cache`. Works offline, on the cache of the configured account.
+fn run_cache(config: &Config, args: &CacheArgs) -> Result<ExitCode> {
+    let cache = config.cache.open(&config.gmail.username);
+    if args.compact {
+        let removed = cache.compact()?;
+        println!(
+            "Removed {} files, freed {}",
+            removed.files,
+            format_bytes(removed.bytes)
+        );
+    }
+    println!("{}", cache.stats()?);
+    if let Some(mb) = config.cache.max_size_mb {
+        println!("Limit: {} MB", mb);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Runs `rutt --check-config`: prints every problem in the config file and
+/// fails if any of them prevents loading it.
+pub fn run_check_config(path: &Path) -> Result<ExitCode> {
+    let diagnostics = check_config_file(path)?;
+    for d in &diagnostics {
+        println!("{}:{}", path.display(), d);
+    }
+
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        return Ok(ExitCode::FAILURE);
+    }
+    if diagnostics.is_empty() {
+        println!("{}: OK", path.display());
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Prints warnings about the config file on stderr, such as unknown keys,
+/// which are otherwise ignored when loading.
+pub fn print_config_warnings(path: &Path) {
+    let Ok(diagnostics) = check_config_file(path) else {
+        return;
+    };
+    for d in diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Warning)
+    {
+        eprintln!("{}:{}", path.display(), d);
+    }
+}
+
+/// Prints a progress line for a folder on stderr.
+fn print_progress(folder: &str, done: usize, total: usize) {
+    eprint!("\r{}: {}/{}", folder, done, total);
+    if done == total {
+        eprintln!();
+    }
+    let _ = std::io::stderr().flush();
+}
+
+/// Runs `rutt backup`.
+fn run_backup(config: &Config, args: &BackupArgs) -> Result<ExitCode> {
+    let mut client = ImapClient::connect_with_config(&config.gmail, &config.imap)
+        .context("Failed to connect to the IMAP server")?;
+
+    let manifest = backup(
+        &mut client,
+        &args.dir,
+        &args.folder,
+        args.batch_size as usize,
+        print_progress,
+    )?;
+
+    let total: usize = manifest.folders.iter().map(|f| f.messages.len()).sum();
+    eprintln!(
+        "Backed up {} messages in {} folders to {}",
+        total,
+        manifest.folders.len(),
+        args.dir.display()
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Runs `rutt restore`.
+fn run_restore(config: &Config, args: &RestoreArgs) -> Result<ExitCode> {
+    let mut client = ImapClient::connect_with_config(&config.gmail, &config.imap)
+        .context("Failed to connect to the IMAP server")?;
+
+    let restored = restore(&mut client, &args.dir, print_progress)?;
+
+    eprintln!("Restored {} messages", restored);
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mailto() {
+        let cli = Cli::try_parse_from(["rutt", "mailto:a@example.com"]).unwrap();
+        assert_eq!(cli.mailto.as_deref(), Some("mailto:a@example.com"));
+        assert!(cli.command.is_none());
+    }
+
+    #[test]
+    fn test_parse_check_config() {
+        let cli = Cli::try_parse_from(["rutt", "--check-config"]).unwrap();
+        assert!(cli.check_config);
+        assert!(cli.mailto.is_none());
+    }
+
+    #[test]
+    fn test_parse_quiet() {
+        let cli = Cli::try_parse_from(["rutt", "-q"]).unwrap();
+        assert!(cli.quiet);
+        let cli = Cli::try_parse_from(["rutt", "--quiet", "mailto:a@example.com"]).unwrap();
+        assert!(cli.quiet);
+        assert!(!Cli::try_parse_from(["rutt"]).unwrap().quiet);
+    }
+
+    #[test]
+    fn test_override_config() {
+        let cli =
+            Cli::try_parse_from(["rutt", "--fetch-limit", "50", "--mailbox", "Work"]).unwrap();
+        let mut config = Config::default();
+        cli.imap_overrides().apply(&mut config.imap);
+        assert_eq!(config.imap.fetch_limit, 50);
+        assert_eq!(config.imap.default_mailbox, "Work");
+        assert_eq!(config.imap.timeout_secs, 60);
+        assert!(Cli::try_parse_from(["rutt", "--fetch-limit", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_profile() {
+        let cli = Cli::try_parse_from(["rutt", "--profile", "work"]).unwrap();
+        assert_eq!(cli.profile.as_deref(), Some("work"));
+        let cli = Cli::try_parse_from(["rutt", "cache", "--profile", "work"]).unwrap();
+        assert_eq!(cli.profile.as_deref(), Some("work"));
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let cli = Cli::try_parse_from(["rutt"]).unwrap();
+        assert!(cli.config.is_none());
+        let cli = Cli::try_parse_from(["rutt", "--config", "work.toml"]).unwrap();
+        assert_eq!(cli.config_path(), PathBuf::from("work.toml"));
+        let cli = Cli::try_parse_from(["rutt", "cache", "--config", "work.toml"]).unwrap();
+        assert_eq!(cli.config_path(), PathBuf::from("work.toml"));
+    }
+
+    #[test]
+    fn test_parse_fetch() {
+        let cli = Cli::try_parse_from(["rutt", "fetch", "--limit", "50", "--json"]).unwrap();
+        let Some(Command::Fetch(args)) = cli.command else {
+            panic!("expected fetch command");
+        };
+        assert_eq!(args.limit, Some(50));
+        assert!(args.mailbox.is_none());
+        assert!(args.json);
+        assert!(Cli::try_parse_from(["rutt", "fetch", "--limit", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_send() {
+        let cli = Cli::try_parse_from([
+            "rutt",
+            "send",
+            "--to",
+            "a@example.com",
+            "--to",
+            "b@example.com",
+            "--subject",
+            "hi",
+            "--body",
+            "hello",
+            "--attach",
+            "f.pdf",
+            "--save-sent",
+        ])
+        .unwrap();
+
+        let Some(Command::Send(args)) = cli.command else {
+            panic!("expected send command");
+        };
+        let draft = args.to_draft().unwrap();
+        assert_eq!(draft.to, "a@example.com, b@example.com");
+        assert_eq!(draft.subject, "hi");
+        assert_eq!(draft.body, "hello");
+        assert_eq!(draft.attachments, vec![PathBuf::from("f.pdf")]);
+        assert!(args.save_sent);
+    }
+
+    #[test]
+    fn test_parse_export() {
+        let cli = Cli::try_parse_from([
+            "rutt",
+            "export",
+            "--query",
+            "from:foo before:2024/01/01",
+            "--out",
+            "foo.mbox",
+        ])
+        .unwrap();
+
+        let Some(Command::Export(args)) = cli.command else {
+            panic!("expected export command");
+        };
+        assert_eq!(args.query, "from:foo before:2024/01/01");
+        assert_eq!(args.out, PathBuf::from("foo.mbox"));
+        assert_eq!(args.mailbox, None);
+        assert_eq!(args.batch_size, 50);
+    }
+
+    #[test]
+    fn test_parse_backup() {
+        let cli = Cli::try_parse_from(["rutt", "backup", "--all-folders", "/tmp/backup"]).unwrap();
+        let Some(Command::Backup(args)) = cli.command else {
+            panic!("expected backup command");
+        };
+        assert!(args.all_folders);
+        assert_eq!(args.dir, PathBuf::from("/tmp/backup"));
+
+        // A folder selection is required.
+        assert!(Cli::try_parse_from(["rutt", "backup", "/tmp/backup"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_doctor() {
+        let cli = Cli::try_parse_from(["rutt", "doctor", "--send-test"]).unwrap();
+        let Some(Command::Doctor(args)) = cli.command else {
+            panic!("expected doctor command");
+        };
+        assert!(args.send_test);
+    }
+
+    #[test]
+    fn test_parse_contacts() {
+        let cli = Cli::try_parse_from(["rutt", "contacts", "export", "book.vcf"]).unwrap();
+        let Some(Command::Contacts(args)) = cli.command else {
+            panic!("expected contacts command");
+        };
+        assert!(matches!(
+            args.action,
+            ContactsAction::Export { file } if file == Path::new("book.vcf")
+        ));
+        assert!(Cli::try_parse_from(["rutt", "contacts"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_send_requires_recipient() {
+        assert!(Cli::try_parse_from(["rutt", "send", "--subject", "hi"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_send_body_conflicts() {
+        assert!(
+            Cli::try_parse_from([
+                "rutt",
+                "send",
+                "--to",
+                "a@example.com",
+                "--body",
+                "x",
+                "--body-file",
+                "y"
+            ])
+            .is_err()
+        );
+    }
+}
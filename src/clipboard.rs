@@ -0,0 +1,82 @@
+//! Copying text to the system clipboard.
+//!
+//! By default the text is handed to the terminal with an OSC 52 escape
+//! sequence, which works over SSH and inside tmux (with `set-clipboard on`)
+//! without any helper installed. Terminals without OSC 52 support can use a
+//! `clipboard_command` under `[ui]` instead, such as `wl-copy`, `xclip
+//! -selection clipboard`, or `pbcopy`, which reads the text on stdin.
+
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// Copies text to the clipboard with a command if one is given, or through
+/// the terminal.
+pub fn copy(text: &str, command: Option<&str>) -> Result<()> {
+    match command {
+        Some(command) => copy_with_command(text, command),
+        None => {
+            let mut stdout = io::stdout();
+            stdout.write_all(osc52(text).as_bytes())?;
+            stdout.flush()?;
+            Ok(())
+        }
+    }
+}
+
+/// Returns the escape sequence asking the terminal to put text on the
+/// clipboard.
+fn osc52(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", STANDARD.encode(text))
+}
+
+/// Pipes text to a clipboard command run through `sh -c`.
+///
+/// Its output is discarded rather than read: helpers such as `xclip` leave a
+/// process behind holding the clipboard, which would keep a pipe open.
+fn copy_with_command(text: &str, command: &str) -> Result<()> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run {}", command))?;
+    if let Some(mut stdin) = child.stdin.take()
+        && let Err(e) = stdin.write_all(text.as_bytes())
+        // A command exiting without reading is reported by its status.
+        && e.kind() != io::ErrorKind::BrokenPipe
+    {
+        return Err(e).with_context(|| format!("Failed to write to {}", command));
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("Clipboard command failed ({})", status);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_osc52() {
+        assert_eq!(osc52("482913"), "\x1b]52;c;NDgyOTEz\x07");
+    }
+
+    #[test]
+    fn test_copy_with_command() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("clipboard");
+        copy("QX7T2M", Some(&format!("cat > {}", path.display()))).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "QX7T2M");
+
+        let err = copy("x", Some("exit 1")).unwrap_err();
+        assert_eq!(err.to_string(), "Clipboard command failed (exit status: 1)");
+    }
+}
@@ -0,0 +1,649 @@
+//! Draft messages for composing and sending email.
+//!
+//! Provides the draft model shared by the compose view and the mailto handler,
+//! including the plain-text format used when editing a draft in `$EDITOR` and
+//! conversion to an RFC822 message.
+
+use anyhow::{Context, Result, bail};
+use lettre::Message;
+use lettre::message::{
+    Attachment as MimeAttachment, Mailbox, Mailboxes, MultiPart, SinglePart,
+    header::{ContentType, HeaderName, HeaderValue},
+};
+use mailparse::{MailHeaderMap, parse_mail};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::address::{to_ascii_address, to_ascii_mailboxes};
+use crate::attachments::{guess_mime_type, sender_matches};
+use crate::body::find_text_part;
+use crate::config::ReplyTemplate;
+use crate::imap_client::{Email, NameAddr};
+use crate::language::detect_language;
+
+/// An email being composed.
+///
+/// Address fields hold comma-separated lists exactly as typed by the user and
+/// are only parsed when the message is built.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Draft {
+    /// Primary recipients.
+    pub to: String,
+    /// Carbon copy recipients.
+    pub cc: String,
+    /// Blind carbon copy recipients.
+    pub bcc: String,
+    /// Subject line.
+    pub subject: String,
+    /// Plain-text body.
+    pub body: String,
+    /// Message-ID of the message being replied to.
+    #[serde(default)]
+    pub in_reply_to: Option<String>,
+    /// Message-IDs of the thread this message belongs to.
+    #[serde(default)]
+    pub references: Option<String>,
+    /// Files to attach.
+    #[serde(default)]
+    pub attachments: Vec<PathBuf>,
+    /// Ask the SMTP server for delivery status notifications.
+    #[serde(default)]
+    pub request_dsn: bool,
+    /// Ask recipients for a read receipt (`Disposition-Notification-To`).
+    #[serde(default)]
+    pub request_read_receipt: bool,
+    /// ISO 639-3 code of the language of the message replied to, passed to
+    /// the editor for its spell checker.
+    #[serde(default)]
+    pub language: Option<String>,
+    /// Store a copy in the Sent mailbox once sent, as `rutt send
+    /// --save-sent` asks, including when sent later from the outbox.
+    #[serde(default)]
+    pub save_sent: bool,
+}
+
+impl Draft {
+    /// Creates a draft from a `mailto:` URI (RFC 6068).
+    ///
+    /// Supports the `to`, `cc`, `bcc`, `subject`, and `body` header fields.
+    /// Unknown fields are ignored.
+    pub fn from_mailto(uri: &str) -> Result<Self> {
+        let Some(rest) = uri.strip_prefix("mailto:") else {
+            bail!("Not a mailto URI: {}", uri);
+        };
+
+        let (addresses, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+        let mut draft = Draft::default();
+        let mut to = vec![percent_decode(addresses)?];
+
+        for field in query.split('&').filter(|f| !f.is_empty()) {
+            let (key, value) = field.split_once('=').unwrap_or((field, ""));
+            let value = percent_decode(value)?;
+            match key.to_lowercase().as_str() {
+                "to" => to.push(value),
+                "cc" => draft.cc = value,
+                "bcc" => draft.bcc = value,
+                "subject" => draft.subject = value,
+                "body" => draft.body = value,
+                _ => {}
+            }
+        }
+
+        draft.to = to
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        Ok(draft)
+    }
+
+    /// Creates a reply to an email, with its body quoted and the threading
+    /// headers set so the reply joins the conversation.
+    ///
+    /// With `all`, everyone else the email was sent to is copied, except
+    /// `self_address`. The first of `templates` matching the sender and the
+    /// language of the email adds its greeting and signature around the
+    /// space for the reply, and may replace the line introducing the quote.
+    pub fn reply(
+        email: &Email,
+        self_address: &str,
+        all: bool,
+        templates: &[ReplyTemplate],
+    ) -> Self {
+        let sender = email.from.email.as_deref().unwrap_or("");
+        let is_self = |addr: &NameAddr| {
+            addr.email.as_deref().is_some_and(|e| {
+                e.eq_ignore_ascii_case(self_address) || e.eq_ignore_ascii_case(sender)
+            })
+        };
+        let cc = if all {
+            email
+                .to
+                .iter()
+                .chain(&email.cc)
+                .filter(|addr| !is_self(addr))
+                .filter_map(format_recipient)
+                .collect::<Vec<_>>()
+                .join(", ")
+        } else {
+            String::new()
+        };
+
+        let subject = if email.subject.to_lowercase().starts_with("re:") {
+            email.subject.clone()
+        } else {
+            format!("Re: {}", email.subject)
+        };
+
+        let name = email.from.name_or_addr().unwrap_or("(unknown)");
+        let date = email.date.format("%Y/%m/%d %H:%M").to_string();
+        let language = email.body.as_deref().and_then(detect_language);
+        let template = templates.iter().find(|template| {
+            let recipient = template.recipient.as_deref().is_none_or(|recipient| {
+                let pattern = recipient.trim_start_matches("*.");
+                sender.contains('@') && sender_matches(pattern, sender)
+            });
+            let language = template
+                .language
+                .as_deref()
+                .is_none_or(|name| language.is_some_and(|language| language.is(name)));
+            recipient && language
+        });
+        let fill = |text: &str| text.replace("{name}", name).replace("{date}", &date);
+
+        let mut body = String::new();
+        if let Some(greeting) = template.and_then(|t| t.greeting.as_deref()) {
+            body.push_str(&fill(greeting));
+            body.push('\n');
+        }
+        body.push_str("\n\n");
+        if let Some(signature) = template.and_then(|t| t.signature.as_deref()) {
+            body.push_str(&fill(signature));
+            body.push_str("\n\n");
+        }
+        match template.and_then(|t| t.attribution.as_deref()) {
+            Some(attribution) => body.push_str(&fill(attribution)),
+            None => body.push_str(&format!("On {}, {} wrote:", date, name)),
+        }
+        body.push('\n');
+        for line in email.body.as_deref().unwrap_or("").lines() {
+            body.push_str(if line.is_empty() { ">" } else { "> " });
+            body.push_str(line);
+            body.push('\n');
+        }
+
+        let references = email
+            .references
+            .iter()
+            .chain(&email.message_id)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        Draft {
+            to: format_recipient(&email.from).unwrap_or_default(),
+            cc,
+            subject,
+            body,
+            in_reply_to: email.message_id.clone(),
+            references: (!references.is_empty()).then(|| references.join(" ")),
+            language: language.map(|language| language.code().to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Creates a draft from an existing message, e.g. one returned in a
+    /// bounce, so it can be edited and sent again.
+    ///
+    /// Keeps the recipients, subject, and plain-text body. Attachments are
+    /// dropped.
+    pub fn from_message(raw: &[u8]) -> Result<Self> {
+        let mail = parse_mail(raw).context("Failed to parse message")?;
+        let header = |name: &str| mail.headers.get_first_value(name).unwrap_or_default();
+
+        // A message of a single part is taken whatever its type.
+        let text_part = if mail.subparts.is_empty() {
+            Some(&mail)
+        } else {
+            find_text_part(&mail, "text/plain")
+        };
+        let body = match text_part {
+            Some(part) => part.get_body().context("Failed to decode message body")?,
+            None => String::new(),
+        };
+
+        Ok(Draft {
+            to: header("To"),
+            cc: header("Cc"),
+            subject: header("Subject"),
+            body: body.replace("\r\n", "\n"),
+            ..Default::default()
+        })
+    }
+
+    /// Renders the draft as text for editing in an external editor.
+    ///
+    /// Headers come first, followed by a blank line and the body.
+    pub fn to_editor_text(&self) -> String {
+        format!(
+            "To: {}\nCc: {}\nBcc: {}\nSubject: {}\n\n{}",
+            self.to, self.cc, self.bcc, self.subject, self.body
+        )
+    }
+
+    /// Updates the draft from text produced by [`Draft::to_editor_text`] and
+    /// edited by the user.
+    pub fn update_from_editor_text(&mut self, text: &str) {
+        let (headers, body) = text
+            .split_once("\n\n")
+            .unwrap_or((text.trim_end_matches('\n'), ""));
+
+        for line in headers.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim().to_string();
+            match key.trim().to_lowercase().as_str() {
+                "to" => self.to = value,
+                "cc" => self.cc = value,
+                "bcc" => self.bcc = value,
+                "subject" => self.subject = value,
+                _ => {}
+            }
+        }
+
+        self.body = body.to_string();
+    }
+
+    /// Builds an RFC822 message from the draft.
+    pub fn to_message(&self, from: &str) -> Result<Message> {
+        let from: Mailbox = from
+            .parse()
+            .with_context(|| format!("Invalid sender address: {}", from))?;
+        let from = Mailbox::new(from.name, to_ascii_address(&from.email)?);
+
+        let mut builder = Message::builder()
+            .from(from.clone())
+            .subject(self.subject.as_str());
+
+        let to = parse_mailboxes(&self.to).context("Invalid To address")?;
+        if to.iter().next().is_none() {
+            bail!("No recipients");
+        }
+        for mbox in to {
+            builder = builder.to(mbox);
+        }
+        for mbox in parse_mailboxes(&self.cc).context("Invalid Cc address")? {
+            builder = builder.cc(mbox);
+        }
+        for mbox in parse_mailboxes(&self.bcc).context("Invalid Bcc address")? {
+            builder = builder.bcc(mbox);
+        }
+
+        if let Some(id) = &self.in_reply_to {
+            builder = builder.in_reply_to(id.clone());
+        }
+        if let Some(refs) = &self.references {
+            builder = builder.references(refs.clone());
+        }
+        if self.request_read_receipt {
+            builder = builder.raw_header(HeaderValue::new(
+                HeaderName::new_from_ascii_str("Disposition-Notification-To"),
+                from.to_string(),
+            ));
+        }
+
+        if self.attachments.is_empty() {
+            return builder
+                .header(ContentType::TEXT_PLAIN)
+                .body(self.body.clone())
+                .context("Failed to build message");
+        }
+
+        let mut multipart = MultiPart::mixed().singlepart(SinglePart::plain(self.body.clone()));
+        for path in &self.attachments {
+            let data = std::fs::read(path)
+                .with_context(|| format!("Failed to read attachment {:?}", path))?;
+            let filename = path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "attachment".to_string());
+            let content_type = ContentType::parse(guess_mime_type(path))
+                .context("Invalid attachment content type")?;
+            multipart =
+                multipart.singlepart(MimeAttachment::new(filename).body(data, content_type));
+        }
+
+        builder
+            .multipart(multipart)
+            .context("Failed to build message")
+    }
+}
+
+/// Formats an address for a draft's recipient list, quoting display names
+/// that contain separators. Returns `None` without an address.
+fn format_recipient(addr: &NameAddr) -> Option<String> {
+    let email = addr.email.as_deref()?;
+    Some(match addr.name.as_deref() {
+        Some(name) if name.contains([',', ';', ':', '<', '>', '@', '"', '(', ')', '\\']) => {
+            format!(
+                "\"{}\" <{}>",
+                name.replace('\\', "\\\\").replace('"', "\\\""),
+                email
+            )
+        }
+        Some(name) => format!("{} <{}>", name, email),
+        None => email.to_string(),
+    })
+}
+
+/// Parses a comma-separated address list. An empty list is allowed.
+///
+/// Internationalized domains are converted to punycode so that only UTF-8
+/// local parts require SMTPUTF8.
+fn parse_mailboxes(list: &str) -> Result<Mailboxes> {
+    if list.trim().is_empty() {
+        return Ok(Mailboxes::new());
+    }
+    let mailboxes = list
+        .parse::<Mailboxes>()
+        .with_context(|| format!("Failed to parse address list: {}", list))?;
+    to_ascii_mailboxes(mailboxes)
+}
+
+/// Decodes `%XX` escapes in a URI component.
+fn percent_decode(s: &str) -> Result<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = s
+                .get(i + 1..i + 3)
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+                .with_context(|| format!("Invalid percent-encoding in: {}", s))?;
+            out.push(hex);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(out).context("Percent-decoded value is not valid UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_mailto_simple() {
+        let draft = Draft::from_mailto("mailto:alice@example.com").unwrap();
+        assert_eq!(draft.to, "alice@example.com");
+        assert_eq!(draft.subject, "");
+    }
+
+    #[test]
+    fn test_from_mailto_with_fields() {
+        let draft = Draft::from_mailto(
+            "mailto:alice@example.com?subject=Hello%20there&cc=bob@example.com&body=Line1%0ALine2",
+        )
+        .unwrap();
+        assert_eq!(draft.to, "alice@example.com");
+        assert_eq!(draft.cc, "bob@example.com");
+        assert_eq!(draft.subject, "Hello there");
+        assert_eq!(draft.body, "Line1\nLine2");
+    }
+
+    #[test]
+    fn test_from_mailto_multiple_recipients() {
+        let draft =
+            Draft::from_mailto("mailto:a@example.com,b@example.com?to=c@example.com").unwrap();
+        assert_eq!(draft.to, "a@example.com,b@example.com, c@example.com");
+    }
+
+    #[test]
+    fn test_from_mailto_query_only() {
+        let draft = Draft::from_mailto("mailto:?to=a@example.com&subject=Hi").unwrap();
+        assert_eq!(draft.to, "a@example.com");
+        assert_eq!(draft.subject, "Hi");
+    }
+
+    #[test]
+    fn test_from_mailto_invalid() {
+        assert!(Draft::from_mailto("http://example.com").is_err());
+        assert!(Draft::from_mailto("mailto:a@example.com?subject=%ZZ").is_err());
+    }
+
+    #[test]
+    fn test_from_message() {
+        let raw = b"From: me@gmail.com\r\nTo: Bob <bob@example.com>\r\nCc: c@example.com\r\n\
+Subject: Hello\r\n\r\nHi there\r\n";
+        let draft = Draft::from_message(raw).unwrap();
+        assert_eq!(draft.to, "Bob <bob@example.com>");
+        assert_eq!(draft.cc, "c@example.com");
+        assert_eq!(draft.subject, "Hello");
+        assert_eq!(draft.body, "Hi there\n");
+
+        // The text is found inside nested parts, not among attachments.
+        let raw = b"To: bob@example.com\r\nSubject: Hello\r\n\
+Content-Type: multipart/mixed; boundary=\"outer\"\r\n\r\n\
+--outer\r\nContent-Type: text/plain\r\nContent-Disposition: attachment\r\n\r\n\
+notes\r\n\
+--outer\r\nContent-Type: multipart/alternative; boundary=\"inner\"\r\n\r\n\
+--inner\r\nContent-Type: text/plain\r\n\r\nHi there\r\n\
+--inner\r\nContent-Type: text/html\r\n\r\n<p>Hi there</p>\r\n\
+--inner--\r\n--outer--\r\n";
+        let draft = Draft::from_message(raw).unwrap();
+        assert_eq!(draft.body, "Hi there\n");
+    }
+
+    #[test]
+    fn test_editor_text_round_trip() {
+        let draft = Draft {
+            to: "a@example.com".to_string(),
+            cc: "b@example.com".to_string(),
+            subject: "Hi".to_string(),
+            body: "Hello\n\nBye\n".to_string(),
+            ..Default::default()
+        };
+
+        let mut edited = Draft::default();
+        edited.update_from_editor_text(&draft.to_editor_text());
+        assert_eq!(edited, draft);
+    }
+
+    #[test]
+    fn test_to_message() {
+        let draft = Draft {
+            to: "Alice <a@example.com>, b@example.com".to_string(),
+            subject: "Test".to_string(),
+            body: "Body".to_string(),
+            in_reply_to: Some("<123@example.com>".to_string()),
+            ..Default::default()
+        };
+
+        let message = draft.to_message("me@gmail.com").unwrap();
+        let formatted = String::from_utf8(message.formatted()).unwrap();
+        assert!(formatted.contains("From: me@gmail.com"));
+        assert!(formatted.contains("Subject: Test"));
+        assert!(formatted.contains("In-Reply-To: <123@example.com>"));
+        assert!(!formatted.contains("Disposition-Notification-To"));
+        assert_eq!(message.envelope().to().len(), 2);
+
+        let draft = Draft {
+            request_read_receipt: true,
+            ..draft
+        };
+        let formatted =
+            String::from_utf8(draft.to_message("me@gmail.com").unwrap().formatted()).unwrap();
+        assert!(formatted.contains("Disposition-Notification-To: me@gmail.com"));
+    }
+
+    #[test]
+    fn test_to_message_idn() {
+        let draft = Draft {
+            to: "Ünal <info@bücher.example>, 用户@example.com".to_string(),
+            subject: "Test".to_string(),
+            ..Default::default()
+        };
+
+        let message = draft.to_message("me@gmail.com").unwrap();
+        let recipients = message
+            .envelope()
+            .to()
+            .iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            recipients,
+            ["info@xn--bcher-kva.example", "用户@example.com"]
+        );
+    }
+
+    #[test]
+    fn test_to_message_with_attachment() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("report.pdf");
+        std::fs::write(&path, b"%PDF-1.4").unwrap();
+
+        let draft = Draft {
+            to: "a@example.com".to_string(),
+            subject: "Report".to_string(),
+            body: "See attached".to_string(),
+            attachments: vec![path],
+            ..Default::default()
+        };
+
+        let message = draft.to_message("me@gmail.com").unwrap();
+        let formatted = String::from_utf8(message.formatted()).unwrap();
+        assert!(formatted.contains("multipart/mixed"));
+        assert!(formatted.contains("application/pdf"));
+        assert!(formatted.contains("filename=\"report.pdf\""));
+    }
+
+    #[test]
+    fn test_to_message_requires_recipient() {
+        let draft = Draft::default();
+        assert!(draft.to_message("me@gmail.com").is_err());
+    }
+
+    #[test]
+    fn test_reply() {
+        let addr = |name: Option<&str>, email: &str| NameAddr {
+            name: name.map(str::to_string),
+            email: Some(email.to_string()),
+        };
+        let email = Email {
+            _uid: 1,
+            subject: "Lunch".to_string(),
+            from: addr(Some("Doe, Jane"), "jane@example.com"),
+            to: vec![
+                addr(None, "me@gmail.com"),
+                addr(Some("Bob"), "bob@example.com"),
+            ],
+            cc: vec![addr(None, "carol@example.com")],
+            date: chrono::Local::now(),
+            is_read: true,
+            body: Some("Noon?\n\nJane".to_string()),
+            message_id: Some("<2@example.com>".to_string()),
+            in_reply_to: Some("<1@example.com>".to_string()),
+            references: vec!["<1@example.com>".to_string()],
+            ..Default::default()
+        };
+
+        let reply = Draft::reply(&email, "me@gmail.com", false, &[]);
+        assert_eq!(reply.to, "\"Doe, Jane\" <jane@example.com>");
+        assert_eq!(reply.cc, "");
+        assert_eq!(reply.subject, "Re: Lunch");
+        assert_eq!(reply.in_reply_to.as_deref(), Some("<2@example.com>"));
+        assert_eq!(
+            reply.references.as_deref(),
+            Some("<1@example.com> <2@example.com>")
+        );
+        assert!(
+            reply
+                .body
+                .ends_with("Doe, Jane wrote:\n> Noon?\n>\n> Jane\n")
+        );
+
+        let reply_all = Draft::reply(&email, "ME@gmail.com", true, &[]);
+        assert_eq!(reply_all.cc, "Bob <bob@example.com>, carol@example.com");
+        let message = reply_all.to_message("me@gmail.com").unwrap();
+        assert_eq!(message.envelope().to().len(), 3);
+
+        let email = Email {
+            subject: "RE: Lunch".to_string(),
+            ..email
+        };
+        assert_eq!(
+            Draft::reply(&email, "me@gmail.com", false, &[]).subject,
+            "RE: Lunch"
+        );
+    }
+
+    #[test]
+    fn test_reply_template() {
+        let email = Email {
+            _uid: 1,
+            subject: "見積もり".to_string(),
+            from: NameAddr {
+                name: Some("田中".to_string()),
+                email: Some("tanaka@sales.example.co.jp".to_string()),
+            },
+            date: chrono::Local::now(),
+            is_read: true,
+            body: Some("ご確認ください。".to_string()),
+            ..Default::default()
+        };
+        let templates = [
+            ReplyTemplate {
+                recipient: Some("example.com".to_string()),
+                language: None,
+                greeting: Some("Hi {name},".to_string()),
+                signature: None,
+                attribution: None,
+            },
+            ReplyTemplate {
+                recipient: Some("*.co.jp".to_string()),
+                language: None,
+                greeting: Some("{name}様\n\nお世話になっております。".to_string()),
+                signature: Some("よろしくお願いいたします。".to_string()),
+                attribution: Some("{name}様のメール:".to_string()),
+            },
+        ];
+
+        let reply = Draft::reply(&email, "me@gmail.com", false, &templates);
+        assert_eq!(
+            reply.body,
+            "田中様\n\nお世話になっております。\n\n\n\
+             よろしくお願いいたします。\n\n\
+             田中様のメール:\n> ご確認ください。\n"
+        );
+
+        // Without a matching template the reply starts with the quote.
+        let reply = Draft::reply(&email, "me@gmail.com", false, &templates[..1]);
+        assert!(reply.body.starts_with("\n\nOn "));
+
+        // Templates can match the language the message is written in.
+        let hebrew = [ReplyTemplate {
+            recipient: None,
+            language: Some("Hebrew".to_string()),
+            greeting: Some("שלום {name},".to_string()),
+            signature: None,
+            attribution: None,
+        }];
+        let reply = Draft::reply(&email, "me@gmail.com", false, &hebrew);
+        assert!(reply.body.starts_with("\n\nOn "));
+        let email = Email {
+            body: Some("רציתי לשאול אם הפגישה של מחר עדיין בתוקף ומה השעה.".to_string()),
+            ..email
+        };
+        let reply = Draft::reply(&email, "me@gmail.com", false, &hebrew);
+        assert!(reply.body.starts_with("שלום 田中,"));
+        assert_eq!(reply.language.as_deref(), Some("heb"));
+    }
+}
@@ -1,46 +1,878 @@
 //! Configuration loading and management for Gmail IMAP settings.
 //!
 //! Handles loading TOML configuration files containing Gmail credentials and
-//! connection parameters.
+//! connection parameters. The file is looked up in the environment, the
+//! working directory, and the user's config directory, and credentials can
+//! be given in the environment instead of the file.
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
 use serde::Deserialize;
+use std::ffi::OsString;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use toml_edit::{DocumentMut, Item, Table, Value, value};
+
+use crate::cache::MessageCache;
+use crate::imap_client::DEFAULT_FETCH_LIMIT;
+use crate::special_use::SpecialFolders;
+use crate::watchdog::IMAP_TIMEOUT;
+
+/// Path of the configuration file, relative to the working directory.
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// Environment variable naming the configuration file to read.
+pub const CONFIG_ENV: &str = "RUTT_CONFIG";
+
+/// Environment variable overriding `gmail.username`.
+pub const USERNAME_ENV: &str = "RUTT_USERNAME";
+
+/// Environment variable overriding `gmail.app_password`.
+pub const APP_PASSWORD_ENV: &str = "RUTT_APP_PASSWORD";
 
 /// Top-level configuration structure containing all settings.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct Config {
-    /// Gmail-specific configuration settings.
+    /// Account credentials, for Gmail or any other IMAP server.
+    #[serde(default)]
     pub gmail: GmailConfig,
+    /// Incoming mail server settings.
+    #[serde(default)]
+    pub imap: ImapConfig,
+    /// Attachment saving settings.
+    #[serde(default)]
+    pub attachments: AttachmentsConfig,
+    /// Settings for writing messages.
+    #[serde(default)]
+    pub compose: ComposeConfig,
+    /// Where archived messages are moved.
+    #[serde(default)]
+    pub archive: ArchiveConfig,
+    /// Outgoing mail server settings.
+    #[serde(default)]
+    pub smtp: SmtpConfig,
+    /// Network usage settings.
+    #[serde(default)]
+    pub network: NetworkConfig,
+    /// Local message cache settings.
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// Display settings.
+    #[serde(default)]
+    pub ui: UiConfig,
+    /// Headers shown in the detail view.
+    #[serde(default)]
+    pub headers: HeadersConfig,
+    /// Translating messages with `:translate`.
+    #[serde(default)]
+    pub translate: TranslateConfig,
+    /// Commands such as `:folder INBOX` run in order after launch.
+    #[serde(default)]
+    pub startup_commands: Vec<String>,
+    /// Commands the text of matching messages is piped through before it is
+    /// shown, the first matching one applying.
+    #[serde(default)]
+    pub display_filters: Vec<DisplayFilter>,
+    /// The `[profiles]` entry applied over the rest of the file, if any.
+    #[serde(skip)]
+    pub profile: Option<String>,
 }
 
 /// Gmail IMAP connection configuration.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
 pub struct GmailConfig {
     /// Gmail username (email address).
+    #[serde(default)]
     pub username: String,
-    /// Gmail app password for IMAP access.
+    /// Gmail app password for IMAP access. Not needed with `auth_command`.
+    #[serde(default)]
     pub app_password: String,
+    /// Shell command printing the app password, run when logging in instead
+    /// of keeping the password in this file (e.g. "pass show gmail").
+    pub app_password_cmd: Option<String>,
+    /// Shell command printing a base64-encoded SASL XOAUTH2 token, used
+    /// instead of the app password.
+    pub auth_command: Option<String>,
+    /// Short tag identifying the account (e.g. "W"), shown in brackets in
+    /// the header and new-mail notifications.
+    pub tag: Option<String>,
+}
+
+/// Settings controlling where attachments are saved.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AttachmentsConfig {
+    /// Directory used when no rule matches. Defaults to the current directory.
+    pub default_dir: Option<String>,
+    /// Rules mapping senders or MIME types to download directories, checked in
+    /// order.
+    #[serde(default)]
+    pub rules: Vec<AttachmentRule>,
+    /// Command (e.g. "clamscan -") given each attachment on stdin before it
+    /// is offered for opening or saving. A non-zero exit blocks the
+    /// attachment.
+    pub scan_command: Option<String>,
+}
+
+/// Settings for writing messages.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ComposeConfig {
+    /// Templates for replies, checked in order; the first matching the
+    /// recipient is used.
+    #[serde(default)]
+    pub reply_templates: Vec<ReplyTemplate>,
+}
+
+/// Text pre-filled in replies to certain recipients, such as a greeting and
+/// signature in the recipient's language.
+///
+/// A template matches when every condition it specifies matches. `{name}` is
+/// replaced by the recipient's name, or their address when the message gives
+/// none.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReplyTemplate {
+    /// Recipient address or domain, matching subdomains too (e.g. "co.jp" or
+    /// "*.co.jp").
+    pub recipient: Option<String>,
+    /// Language the message replied to is written in, as an ISO 639-3 code
+    /// or English name (e.g. "jpn" or "Japanese").
+    pub language: Option<String>,
+    /// Opening line(s) placed above the reply.
+    pub greeting: Option<String>,
+    /// Closing placed below the reply, before the quoted message.
+    pub signature: Option<String>,
+    /// Line introducing the quoted message instead of "On {date}, {name}
+    /// wrote:". `{date}` is replaced by the message's date.
+    pub attribution: Option<String>,
+}
+
+/// Settings controlling where archived messages go.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ArchiveConfig {
+    /// Folder messages are archived into instead of All Mail, with `{year}`
+    /// replaced by the year each message was sent (e.g. "Archive/{year}").
+    /// Missing folders are created.
+    pub folder: Option<String>,
+}
+
+/// A rule selecting a download directory for matching attachments.
+///
+/// A rule matches when every condition it specifies matches; a rule without
+/// conditions matches everything.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AttachmentRule {
+    /// Sender address or domain (e.g. "billing.example.com").
+    pub sender: Option<String>,
+    /// MIME type, optionally with a wildcard subtype (e.g. "image/*").
+    pub mime_type: Option<String>,
+    /// Directory to save matching attachments into. `~` is expanded.
+    pub dir: String,
+}
+
+/// A command the text of matching messages is piped through before it is
+/// shown.
+///
+/// A filter matches when every condition it specifies matches; a filter
+/// without conditions applies to every message.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DisplayFilter {
+    /// Sender address or domain (e.g. "news.example.com").
+    pub sender: Option<String>,
+    /// Content type of the text, optionally with a wildcard subtype (e.g.
+    /// "text/html"). HTML is piped as sent, other text decoded.
+    pub mime_type: Option<String>,
+    /// Shell command reading the text on stdin and printing what to show.
+    pub command: String,
+}
+
+/// IMAP server settings for reading mail.
+///
+/// Credentials are shared with the `[gmail]` section.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ImapConfig {
+    /// IMAP server hostname.
+    pub host: String,
+    /// IMAP server port.
+    pub port: u16,
+    /// Use STARTTLS instead of implicit TLS.
+    pub starttls: bool,
+    /// Record the server's certificate on first connect and refuse a
+    /// different one until it is accepted. Off by default, as providers
+    /// replace their certificates every few weeks.
+    pub pin_certificate: bool,
+    /// Special folders to use instead of those the server marks, e.g.
+    /// `trash = "Deleted Items"`.
+    pub folders: SpecialFolders,
+    /// Number of recent messages fetched when opening a folder.
+    pub fetch_limit: u32,
+    /// Folder opened at launch.
+    pub default_mailbox: String,
+    /// Seconds the server may stay silent before the connection is
+    /// considered stalled.
+    pub timeout_secs: u64,
+}
+
+impl ImapConfig {
+    /// Returns how long the server may stay silent.
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+}
+
+/// `[imap]` settings given on the command line, which take precedence over
+/// the config file each time it is loaded.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImapOverrides {
+    pub fetch_limit: Option<u32>,
+    pub default_mailbox: Option<String>,
+    pub timeout_secs: Option<u64>,
+}
+
+impl ImapOverrides {
+    /// Applies the settings given over `imap`.
+    pub fn apply(&self, imap: &mut ImapConfig) {
+        if let Some(limit) = self.fetch_limit {
+            imap.fetch_limit = limit;
+        }
+        if let Some(mailbox) = &self.default_mailbox {
+            imap.default_mailbox = mailbox.clone();
+        }
+        if let Some(secs) = self.timeout_secs {
+            imap.timeout_secs = secs;
+        }
+    }
+}
+
+impl Default for ImapConfig {
+    fn default() -> Self {
+        ImapConfig {
+            host: "imap.gmail.com".to_string(),
+            port: 993,
+            starttls: false,
+            pin_certificate: false,
+            folders: SpecialFolders::default(),
+            fetch_limit: DEFAULT_FETCH_LIMIT,
+            default_mailbox: "INBOX".to_string(),
+            timeout_secs: IMAP_TIMEOUT.as_secs(),
+        }
+    }
+}
+
+/// SMTP server settings for sending mail.
+///
+/// Credentials are shared with the `[gmail]` section.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SmtpConfig {
+    /// SMTP server hostname.
+    pub host: String,
+    /// SMTP server port.
+    pub port: u16,
+    /// Use STARTTLS instead of implicit TLS.
+    pub starttls: bool,
+    /// Mailbox sent messages are saved to when requested. Defaults to the
+    /// server's Sent folder.
+    pub sent_mailbox: Option<String>,
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        SmtpConfig {
+            host: "smtp.gmail.com".to_string(),
+            port: 465,
+            starttls: false,
+            sent_mailbox: None,
+        }
+    }
+}
+
+/// Settings for limiting network usage on metered connections.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// Largest download, in KiB, a single action may make without asking for
+    /// confirmation. Unlimited when unset.
+    pub transfer_budget_kb: Option<u64>,
+    /// Keep a second connection in IMAP IDLE to show new mail as it arrives.
+    pub idle: bool,
+    /// Ask GitHub for the latest release when `:version` is opened.
+    pub check_updates: bool,
+    /// Sync the headers of Sent, Starred and recently opened folders into
+    /// the cache while no key is pressed, so opening them is quicker.
+    pub warm_up: bool,
+    /// Seconds without a key press before warming up the cache.
+    pub warm_up_after_secs: u64,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            transfer_budget_kb: None,
+            idle: true,
+            check_updates: false,
+            warm_up: true,
+            warm_up_after_secs: 60,
+        }
+    }
+}
+
+/// Settings for the local cache of headers and bodies.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Keep fetched headers and opened messages in the cache directory.
+    pub enabled: bool,
+    /// Most megabytes of message sources to keep. The least recently read
+    /// are evicted first; headers are always kept.
+    pub max_size_mb: Option<u64>,
+    /// Answer `/` searches from a local full-text index of the cached
+    /// messages instead of the server.
+    pub full_text: bool,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            enabled: true,
+            max_size_mb: None,
+            full_text: false,
+        }
+    }
+}
+
+impl CacheConfig {
+    /// Opens the cache of an account with the configured size limit.
+    pub fn open(&self, username: &str) -> MessageCache {
+        MessageCache::open_default(username)
+            .with_max_size(self.max_size_mb.map(|mb| mb * 1024 * 1024))
+    }
+}
+
+/// Settings for translating messages with `:translate`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TranslateConfig {
+    /// Shell command reading the text on stdin and printing its translation,
+    /// with `{from}` and `{to}` replaced by the languages (e.g.
+    /// "trans -b {from}:{to}").
+    pub command: Option<String>,
+    /// Language messages are translated from, as a code such as "ja".
+    /// Detected from each message when unset, or `auto` if it cannot be told.
+    pub source: Option<String>,
+    /// Language messages are translated into, as a code such as "en".
+    pub target: String,
+    /// Where the translation is shown.
+    pub layout: TranslateLayout,
+    /// Seconds the command may take before it is stopped.
+    pub timeout_secs: u64,
+}
+
+impl Default for TranslateConfig {
+    fn default() -> Self {
+        TranslateConfig {
+            command: None,
+            source: None,
+            target: "en".to_string(),
+            layout: TranslateLayout::Replace,
+            timeout_secs: 60,
+        }
+    }
+}
+
+/// Where a translation is shown in the detail view.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TranslateLayout {
+    /// In place of the original body.
+    #[default]
+    Replace,
+    /// In a column beside the original body.
+    SideBySide,
+}
+
+/// Terminal interface display settings.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct UiConfig {
+    /// Show sender initials blocks in the email list. They are always shown
+    /// in the detail view.
+    pub index_avatars: bool,
+    /// Milliseconds to wait for the next key of a multi-key sequence (e.g.
+    /// `gg`) before discarding the pending keys.
+    pub chord_timeout_ms: u64,
+    /// Where the cursor starts when a folder is opened.
+    pub initial_cursor: InitialCursor,
+    /// Where the cursor goes when leaving a message for the list.
+    pub after_read: AfterRead,
+    /// Group the email list into conversation threads at startup.
+    pub threaded: bool,
+    /// Order of the messages within a thread.
+    pub thread_order: ThreadOrder,
+    /// Order of the email list.
+    pub sort: SortMode,
+    /// Version shown first for messages with plain-text and HTML versions.
+    pub prefer: TextPart,
+    /// Folders skipped when jumping to the next folder with unread mail.
+    pub unread_sweep_skip: Vec<String>,
+    /// How senders too long for the list's From column are shortened.
+    pub truncation: Truncation,
+    /// Timezone message dates are shown in.
+    pub timezone: DateZone,
+    /// Terminal width below which each list row spans two lines. Unset keeps
+    /// one line per row at any width.
+    pub two_line_below: Option<u16>,
+    /// Show each message's size in a column of the email list.
+    pub size_column: bool,
+    /// Number the rows of the email list, for jumps such as `42G`.
+    pub index_numbers: bool,
+    /// Show the folder sidebar at startup (toggle with `b`).
+    pub sidebar: bool,
+    /// Set the terminal window title to the folder and its unread count.
+    pub window_title: bool,
+    /// How new mail arriving while rutt runs is signalled.
+    pub new_mail_alert: NewMailAlert,
+    /// Sender addresses whose messages are hidden from the list, added from
+    /// the cleanup view.
+    pub muted_senders: Vec<String>,
+    /// Render for terminal screen readers: no box drawing or symbols, list
+    /// rows spelled out as one line each, and view changes announced on the
+    /// status line.
+    pub screen_reader: bool,
+    /// Show right-to-left lines, such as Arabic and Hebrew, in display order
+    /// and aligned right. Turn off for terminals that reorder them
+    /// themselves.
+    pub bidi: bool,
+    /// Command reading text on stdin to put on the clipboard, such as
+    /// `wl-copy`. Unset hands the text to the terminal (OSC 52).
+    pub clipboard_command: Option<String>,
+}
+
+/// Headers the detail view shows besides Date, From, To, Cc, and Subject,
+/// chosen like mutt's `ignore` and `unignore`: a header is shown unless it
+/// matches `ignore`, or when it matches `unignore`. Names match by prefix,
+/// ignoring case, and `*` matches every header. `H` shows all headers.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HeadersConfig {
+    /// Headers hidden, e.g. `["*"]` or `["received", "dkim-"]`.
+    pub ignore: Vec<String>,
+    /// Headers shown even though they match `ignore`.
+    pub unignore: Vec<String>,
+}
+
+impl Default for HeadersConfig {
+    fn default() -> Self {
+        HeadersConfig {
+            ignore: vec!["*".to_string()],
+            unignore: Vec::new(),
+        }
+    }
+}
+
+impl HeadersConfig {
+    /// Returns whether a header is shown in the weeded header list.
+    pub fn shows(&self, name: &str) -> bool {
+        let matches = |patterns: &[String]| {
+            patterns.iter().any(|pattern| {
+                pattern == "*"
+                    || name
+                        .get(..pattern.len())
+                        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(pattern))
+            })
+        };
+        !matches(&self.ignore) || matches(&self.unignore)
+    }
+}
+
+/// Signal for new mail, for rutt running in a background window or pane.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NewMailAlert {
+    /// No signal beyond the status line.
+    #[default]
+    Off,
+    /// Ring the terminal bell, which tmux and most terminals can turn into
+    /// a window highlight or desktop notification.
+    Bell,
+    /// Flash the screen by briefly inverting it.
+    Flash,
+}
+
+/// Starting cursor position in the email list.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InitialCursor {
+    /// The first row of the list.
+    #[default]
+    Top,
+    /// The oldest unread message.
+    FirstUnread,
+    /// The most recent unread message.
+    LatestUnread,
+}
+
+/// Cursor position in the email list after reading a message.
+///
+/// The values are also accepted with hyphens, e.g. `"next-unread"`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AfterRead {
+    /// The message just read.
+    #[default]
+    #[serde(alias = "return-to-list")]
+    ReturnToList,
+    /// The message below it.
+    Next,
+    /// The next unread message, wrapping around at the end.
+    #[serde(alias = "next-unread")]
+    NextUnread,
+}
+
+/// Order of the email list.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortMode {
+    /// Most recent first.
+    #[default]
+    DateDesc,
+    /// Oldest first.
+    DateAsc,
+    /// By sender name or address, most recent first for each.
+    Sender,
+    /// By subject ignoring `Re:` and `Fwd:`, most recent first for each.
+    Subject,
+    /// Unread before read, most recent first within each.
+    UnreadFirst,
+    /// Flagged before unflagged, most recent first within each.
+    FlaggedFirst,
+}
+
+impl SortMode {
+    /// Returns the mode after this one, cycling back to the first.
+    pub fn next(self) -> Self {
+        match self {
+            SortMode::DateDesc => SortMode::DateAsc,
+            SortMode::DateAsc => SortMode::Sender,
+            SortMode::Sender => SortMode::Subject,
+            SortMode::Subject => SortMode::UnreadFirst,
+            SortMode::UnreadFirst => SortMode::FlaggedFirst,
+            SortMode::FlaggedFirst => SortMode::DateDesc,
+        }
+    }
+
+    /// Returns a short description for the header and status line.
+    pub fn name(self) -> &'static str {
+        match self {
+            SortMode::DateDesc => "newest first",
+            SortMode::DateAsc => "oldest first",
+            SortMode::Sender => "sender",
+            SortMode::Subject => "subject",
+            SortMode::UnreadFirst => "unread first",
+            SortMode::FlaggedFirst => "flagged first",
+        }
+    }
+}
+
+/// Order of the messages within a conversation thread.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThreadOrder {
+    /// Oldest first, following the reply tree, for reading.
+    #[default]
+    Chronological,
+    /// Most recent first, for triage.
+    NewestFirst,
+}
+
+impl ThreadOrder {
+    /// Returns the other order.
+    pub fn toggled(self) -> Self {
+        match self {
+            ThreadOrder::Chronological => ThreadOrder::NewestFirst,
+            ThreadOrder::NewestFirst => ThreadOrder::Chronological,
+        }
+    }
+
+    /// Returns the config value naming the order.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ThreadOrder::Chronological => "chronological",
+            ThreadOrder::NewestFirst => "newest_first",
+        }
+    }
+}
+
+/// A version of a message offered as plain text and HTML.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextPart {
+    /// The text/plain part.
+    #[default]
+    Plain,
+    /// The text/html part, converted to text.
+    Html,
+}
+
+/// Where text that does not fit its column is cut.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Truncation {
+    /// Keep the start, e.g. `jsmith.longname@long...`.
+    End,
+    /// Keep the start and end, e.g. `jsmith…@longcompany.com`, so the domain
+    /// of an address stays visible.
+    #[default]
+    Middle,
+}
+
+/// Timezone message dates are shown in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateZone {
+    /// The local timezone of this computer.
+    #[default]
+    Local,
+    /// Coordinated Universal Time.
+    Utc,
+    /// The timezone the sender wrote the message in, from its Date header.
+    Sender,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        UiConfig {
+            index_avatars: false,
+            chord_timeout_ms: 1000,
+            initial_cursor: InitialCursor::Top,
+            after_read: AfterRead::ReturnToList,
+            threaded: false,
+            thread_order: ThreadOrder::Chronological,
+            sort: SortMode::DateDesc,
+            prefer: TextPart::Plain,
+            unread_sweep_skip: [
+                "[Gmail]/All Mail",
+                "[Gmail]/Important",
+                "[Gmail]/Spam",
+                "[Gmail]/Starred",
+                "[Gmail]/Trash",
+            ]
+            .map(String::from)
+            .to_vec(),
+            truncation: Truncation::Middle,
+            timezone: DateZone::Local,
+            size_column: false,
+            index_numbers: true,
+            sidebar: false,
+            window_title: false,
+            new_mail_alert: NewMailAlert::Off,
+            muted_senders: Vec::new(),
+            screen_reader: false,
+            bidi: true,
+            clipboard_command: None,
+            two_line_below: None,
+        }
+    }
 }
 
 impl Config {
     /// Loads configuration from a TOML file at the specified path.
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::load_profile(path, None)
+    }
+
+    /// Loads configuration from a TOML file, with the settings of the named
+    /// `[profiles]` entry applied over the rest of the file.
+    pub fn load_profile<P: AsRef<Path>>(path: P, profile: Option<&str>) -> Result<Self> {
         let contents = fs::read_to_string(&path)
             .with_context(|| format!("Failed to read config from {:?}", path.as_ref()))?;
 
-        let config: Config = toml::from_str(&contents).context("Failed to parse config file")?;
+        let mut config = Self::parse(&contents, profile)?;
+        config.override_from_env(env_var);
+        config.validate()?;
 
         Ok(config)
     }
 
-    /// Loads configuration from the default "config.toml" file.
+    /// Parses the contents of a config file, applying the named profile.
+    ///
+    /// A profile holds the same keys as the top level: its tables are merged
+    /// key by key into the top-level ones, and other values replace them.
+    /// Its `folder` is opened before the `startup_commands` run.
+    pub fn parse(contents: &str, profile: Option<&str>) -> Result<Self> {
+        let Some(name) = profile else {
+            return toml::from_str(contents).context("Failed to parse config file");
+        };
+        let mut table: toml::Table =
+            toml::from_str(contents).context("Failed to parse config file")?;
+        let profiles = table.remove("profiles");
+        let mut overrides = match profiles.as_ref().and_then(|p| p.get(name)) {
+            Some(toml::Value::Table(overrides)) => overrides.clone(),
+            Some(_) => bail!("[profiles.{}] is not a table", name),
+            None => {
+                let known = profiles
+                    .as_ref()
+                    .and_then(toml::Value::as_table)
+                    .map(|p| p.keys().map(String::as_str).collect::<Vec<_>>())
+                    .unwrap_or_default();
+                if known.is_empty() {
+                    bail!("No profile `{}`: the config defines no [profiles]", name);
+                }
+                bail!(
+                    "No profile `{}`; expected one of {}",
+                    name,
+                    known.join(", ")
+                );
+            }
+        };
+        let folder = match overrides.remove("folder") {
+            Some(toml::Value::String(folder)) => Some(folder),
+            Some(_) => bail!("profiles.{}.folder must be a string", name),
+            None => None,
+        };
+        merge_tables(&mut table, overrides);
+
+        let mut config: Config = table
+            .try_into()
+            .with_context(|| format!("Failed to parse profile `{}`", name))?;
+        if let Some(folder) = folder {
+            config
+                .startup_commands
+                .insert(0, format!(":folder {}", folder));
+        }
+        config.profile = Some(name.to_string());
+        Ok(config)
+    }
+
+    /// Replaces the credentials with those set in [`USERNAME_ENV`] and
+    /// [`APP_PASSWORD_ENV`], as looked up by `var`.
+    pub fn override_from_env(&mut self, var: impl Fn(&str) -> Option<String>) {
+        if let Some(username) = var(USERNAME_ENV) {
+            self.gmail.username = username;
+        }
+        if let Some(password) = var(APP_PASSWORD_ENV) {
+            self.gmail.app_password = password;
+        }
+    }
+
+    /// Returns the account as `user@host`, which the data kept per account
+    /// is filed under.
+    pub fn account(&self) -> String {
+        format!("{}@{}", self.gmail.username, self.imap.host)
+    }
+
+    /// Checks constraints between settings that parsing alone cannot.
+    pub fn validate(&self) -> Result<()> {
+        if self.gmail.username.is_empty() {
+            bail!("gmail.username must be set");
+        }
+        if self.gmail.app_password.is_empty()
+            && self.gmail.app_password_cmd.is_none()
+            && self.gmail.auth_command.is_none()
+        {
+            bail!(
+                "One of gmail.app_password, gmail.app_password_cmd or gmail.auth_command must be set"
+            );
+        }
+        if self.imap.fetch_limit == 0 {
+            bail!("imap.fetch_limit must be at least 1");
+        }
+        if self.imap.timeout_secs == 0 {
+            bail!("imap.timeout_secs must be at least 1");
+        }
+        Ok(())
+    }
+
+    /// Loads configuration from the file found by [`default_config_path`].
     pub fn load_default() -> Result<Self> {
-        Self::load("config.toml")
+        Self::load(default_config_path())
     }
 }
 
+/// Returns a non-empty environment variable.
+pub fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// Returns the config file to read when none is given on the command line.
+///
+/// `$RUTT_CONFIG` is used as it is. Otherwise the first existing file of
+/// `config.toml` in the working directory, `$XDG_CONFIG_HOME/rutt/config.toml`
+/// and `~/.config/rutt/config.toml` is picked; when there is none, the one in
+/// the user's config directory is named in the error.
+pub fn default_config_path() -> PathBuf {
+    resolve_config_path(
+        |name| std::env::var_os(name).filter(|value| !value.is_empty()),
+        |path| path.exists(),
+    )
+}
+
+fn resolve_config_path(
+    var: impl Fn(&str) -> Option<OsString>,
+    exists: impl Fn(&Path) -> bool,
+) -> PathBuf {
+    if let Some(path) = var(CONFIG_ENV) {
+        return PathBuf::from(path);
+    }
+    let user_dirs = [
+        var("XDG_CONFIG_HOME").map(PathBuf::from),
+        var("HOME").map(|home| PathBuf::from(home).join(".config")),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|dir| dir.join("rutt").join("config.toml"))
+    .collect::<Vec<_>>();
+
+    let local = PathBuf::from(DEFAULT_CONFIG_PATH);
+    if exists(&local) {
+        return local;
+    }
+    user_dirs
+        .iter()
+        .find(|path| exists(path))
+        .or(user_dirs.first())
+        .cloned()
+        .unwrap_or(local)
+}
+
+/// Merges `overrides` into `base`, table by table.
+fn merge_tables(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(inner)), toml::Value::Table(value)) => {
+                merge_tables(inner, value)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Sets a setting in a `[table]` of the config file, leaving the rest of the
+/// file, comments included, as it is.
+pub fn save_setting<P: AsRef<Path>>(
+    path: P,
+    table: &str,
+    key: &str,
+    setting: impl Into<Value>,
+) -> Result<()> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config from {:?}", path))?;
+    let mut doc = contents
+        .parse::<DocumentMut>()
+        .context("Failed to parse config file")?;
+
+    let table = doc
+        .entry(table)
+        .or_insert(Item::Table(Table::new()))
+        .as_table_mut()
+        .with_context(|| format!("[{}] is not a table", table))?;
+    table[key] = value(setting);
+
+    fs::write(path, doc.to_string()).with_context(|| format!("Failed to write {:?}", path))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -63,6 +895,143 @@ app_password = "test-password-123"
         let config = Config::load(temp_file.path()).unwrap();
         assert_eq!(config.gmail.username, "test@gmail.com");
         assert_eq!(config.gmail.app_password, "test-password-123");
+        assert_eq!(config.smtp.host, "smtp.gmail.com");
+        assert_eq!(config.smtp.port, 465);
+        assert_eq!(config.imap, ImapConfig::default());
+        assert_eq!(config.network.transfer_budget_kb, None);
+        assert_eq!(config.ui.chord_timeout_ms, 1000);
+        assert_eq!(config.ui.initial_cursor, InitialCursor::Top);
+        assert_eq!(config.ui.truncation, Truncation::Middle);
+    }
+
+    #[test]
+    fn test_load_imap_server() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(
+            temp_file,
+            r#"
+[gmail]
+username = "me@example.org"
+app_password = "test-password-123"
+
+[imap]
+host = "mail.example.org"
+port = 143
+starttls = true
+
+[imap.folders]
+trash = "Deleted Items"
+"#
+        )
+        .unwrap();
+
+        let config = Config::load(temp_file.path()).unwrap();
+        assert_eq!(
+            config.imap,
+            ImapConfig {
+                host: "mail.example.org".to_string(),
+                port: 143,
+                starttls: true,
+                folders: SpecialFolders {
+                    trash: Some("Deleted Items".to_string()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_load_auth_command() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(
+            temp_file,
+            r#"
+[gmail]
+username = "test@gmail.com"
+auth_command = "oauth2ms --encode-xoauth2"
+"#
+        )
+        .unwrap();
+
+        let config = Config::load(temp_file.path()).unwrap();
+        assert_eq!(config.gmail.app_password, "");
+        assert_eq!(
+            config.gmail.auth_command.as_deref(),
+            Some("oauth2ms --encode-xoauth2")
+        );
+    }
+
+    #[test]
+    fn test_load_attachment_rules() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(
+            temp_file,
+            r#"
+[gmail]
+username = "test@gmail.com"
+app_password = "test-password-123"
+
+[attachments]
+default_dir = "~/Downloads"
+
+[[attachments.rules]]
+sender = "billing.example.com"
+dir = "~/Documents/invoices"
+
+[[attachments.rules]]
+mime_type = "image/*"
+dir = "~/Pictures/mail"
+"#
+        )
+        .unwrap();
+
+        let config = Config::load(temp_file.path()).unwrap();
+        assert_eq!(
+            config.attachments.default_dir.as_deref(),
+            Some("~/Downloads")
+        );
+        assert_eq!(config.attachments.rules.len(), 2);
+        assert_eq!(
+            config.attachments.rules[0].sender.as_deref(),
+            Some("billing.example.com")
+        );
+        assert_eq!(
+            config.attachments.rules[1].mime_type.as_deref(),
+            Some("image/*")
+        );
+    }
+
+    #[test]
+    fn test_load_ui_settings() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(
+            temp_file,
+            r#"
+[gmail]
+username = "test@gmail.com"
+app_password = "test-password-123"
+
+[ui]
+initial_cursor = "latest_unread"
+after_read = "next_unread"
+truncation = "end"
+prefer = "html"
+two_line_below = 80
+"#
+        )
+        .unwrap();
+
+        let config = Config::load(temp_file.path()).unwrap();
+        assert_eq!(config.ui.initial_cursor, InitialCursor::LatestUnread);
+        assert_eq!(config.ui.after_read, AfterRead::NextUnread);
+        let ui: UiConfig = toml::from_str("after_read = \"return-to-list\"").unwrap();
+        assert_eq!(ui.after_read, AfterRead::ReturnToList);
+        assert_eq!(config.ui.truncation, Truncation::End);
+        assert_eq!(config.ui.prefer, TextPart::Html);
+        assert_eq!(config.ui.two_line_below, Some(80));
+        assert!(!config.ui.index_avatars);
+        assert_eq!(config.ui.chord_timeout_ms, 1000);
     }
 
     #[test]
@@ -107,4 +1076,163 @@ username = "test@gmail.com"
         let result = Config::load(temp_file.path());
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_resolve_config_path() {
+        let env = |name: &str| match name {
+            "XDG_CONFIG_HOME" => Some(OsString::from("/xdg")),
+            "HOME" => Some(OsString::from("/home/me")),
+            _ => None,
+        };
+        let xdg = Path::new("/xdg/rutt/config.toml");
+        let home = Path::new("/home/me/.config/rutt/config.toml");
+
+        let found = resolve_config_path(env, |path| path == Path::new("config.toml"));
+        assert_eq!(found, Path::new("config.toml"));
+        assert_eq!(resolve_config_path(env, |path| path == home), home);
+        assert_eq!(
+            resolve_config_path(env, |path| path == xdg || path == home),
+            xdg
+        );
+        // Without any file the user config directory is reported.
+        assert_eq!(resolve_config_path(env, |_| false), xdg);
+
+        let with_var = |name: &str| match name {
+            CONFIG_ENV => Some(OsString::from("/etc/rutt.toml")),
+            _ => env(name),
+        };
+        let found = resolve_config_path(with_var, |_| true);
+        assert_eq!(found, Path::new("/etc/rutt.toml"));
+    }
+
+    #[test]
+    fn test_override_from_env() {
+        let mut config = Config::parse(
+            "[imap]
+fetch_limit = 10
+",
+            None,
+        )
+        .unwrap();
+        assert!(config.validate().is_err());
+
+        config.override_from_env(|name| match name {
+            USERNAME_ENV => Some("me@gmail.com".to_string()),
+            APP_PASSWORD_ENV => Some("secret".to_string()),
+            _ => None,
+        });
+        assert_eq!(config.gmail.username, "me@gmail.com");
+        assert_eq!(config.gmail.app_password, "secret");
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_imap_fetch_settings() {
+        let credentials = "[gmail]\nusername = \"a\"\napp_password = \"b\"\n";
+        let config = Config::parse(credentials, None).unwrap();
+        assert_eq!(config.imap.fetch_limit, 200);
+        assert_eq!(config.imap.default_mailbox, "INBOX");
+        assert_eq!(config.imap.timeout(), Duration::from_secs(60));
+
+        let contents = format!("{credentials}[imap]\nfetch_limit = 0\ntimeout_secs = 5\n");
+        let config = Config::parse(&contents, None).unwrap();
+        assert_eq!(config.imap.timeout(), Duration::from_secs(5));
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "imap.fetch_limit must be at least 1"
+        );
+    }
+
+    #[test]
+    fn test_parse_profile() {
+        let contents = r#"
+startup_commands = [":limit is:unread"]
+
+[gmail]
+username = "me@gmail.com"
+app_password = "personal"
+
+[ui]
+sidebar = true
+
+[profiles.work]
+folder = "Projects"
+
+[profiles.work.gmail]
+username = "me@work.example"
+
+[profiles.work.ui]
+threaded = true
+"#;
+        let base = Config::parse(contents, None).unwrap();
+        assert_eq!(base.gmail.username, "me@gmail.com");
+        assert!(!base.ui.threaded);
+        assert_eq!(base.profile, None);
+
+        let work = Config::parse(contents, Some("work")).unwrap();
+        assert_eq!(work.gmail.username, "me@work.example");
+        assert_eq!(work.gmail.app_password, "personal");
+        assert!(work.ui.threaded);
+        assert!(work.ui.sidebar);
+        assert_eq!(
+            work.startup_commands,
+            [":folder Projects", ":limit is:unread"]
+        );
+        assert_eq!(work.profile.as_deref(), Some("work"));
+
+        let missing = Config::parse(contents, Some("home")).unwrap_err();
+        assert_eq!(
+            missing.to_string(),
+            "No profile `home`; expected one of work"
+        );
+    }
+
+    #[test]
+    fn test_save_setting() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(
+            temp_file,
+            r#"[gmail]
+username = "test@gmail.com"
+app_password = "test-password-123"
+"#
+        )
+        .unwrap();
+
+        save_setting(temp_file.path(), "ui", "thread_order", "newest_first").unwrap();
+        let config = Config::load(temp_file.path()).unwrap();
+        assert_eq!(config.ui.thread_order, ThreadOrder::NewestFirst);
+
+        save_setting(temp_file.path(), "ui", "thread_order", "chronological").unwrap();
+        let contents = fs::read_to_string(temp_file.path()).unwrap();
+        assert!(contents.ends_with("\n[ui]\nthread_order = \"chronological\"\n"));
+
+        let muted = ["news@shop.example"]
+            .into_iter()
+            .collect::<toml_edit::Array>();
+        save_setting(temp_file.path(), "ui", "muted_senders", muted).unwrap();
+        let config = Config::load(temp_file.path()).unwrap();
+        assert_eq!(config.ui.muted_senders, ["news@shop.example"]);
+    }
+
+    #[test]
+    fn test_headers_shows() {
+        let headers = HeadersConfig::default();
+        assert!(!headers.shows("X-Mailer"));
+
+        let headers = HeadersConfig {
+            ignore: vec!["*".to_string()],
+            unignore: vec!["list-".to_string()],
+        };
+        assert!(headers.shows("List-Id"));
+        assert!(headers.shows("LIST-UNSUBSCRIBE"));
+        assert!(!headers.shows("Received"));
+
+        let headers = HeadersConfig {
+            ignore: vec!["received".to_string(), "dkim-".to_string()],
+            unignore: Vec::new(),
+        };
+        assert!(!headers.shows("DKIM-Signature"));
+        assert!(headers.shows("X-Mailer"));
+    }
 }
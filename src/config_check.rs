@@ -0,0 +1,394 @@
+//! Validation of the configuration file.
+//!
+//! Reports syntax and type errors with their line and column, and warns about
+//! keys rutt does not know, suggesting the closest known key. Unknown keys are
+//! otherwise silently ignored when the config is loaded, so a misspelled
+//! setting would just have no effect.
+
+use anyhow::{Context, Result};
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use toml_edit::{ImDocument, Item, Table};
+
+use crate::config::{Config, env_var};
+
+/// Known keys of each table, by dotted table path ("" for the top level).
+///
+/// Must be kept in sync with the structs in `config.rs`; the sample config is
+/// checked against it in the tests.
+const SCHEMA: &[(&str, &[&str])] = &[
+    (
+        "",
+        &[
+            "gmail",
+            "imap",
+            "attachments",
+            "compose",
+            "archive",
+            "smtp",
+            "network",
+            "cache",
+            "ui",
+            "headers",
+            "startup_commands",
+            "display_filters",
+            "translate",
+            "profiles",
+        ],
+    ),
+    (
+        "gmail",
+        &[
+            "username",
+            "app_password",
+            "app_password_cmd",
+            "auth_command",
+            "tag",
+        ],
+    ),
+    (
+        "imap",
+        &[
+            "host",
+            "port",
+            "starttls",
+            "pin_certificate",
+            "folders",
+            "fetch_limit",
+            "default_mailbox",
+            "timeout_secs",
+        ],
+    ),
+    (
+        "imap.folders",
+        &[
+            "all", "archive", "drafts", "flagged", "junk", "sent", "trash",
+        ],
+    ),
+    ("attachments", &["default_dir", "rules", "scan_command"]),
+    ("attachments.rules", &["sender", "mime_type", "dir"]),
+    ("display_filters", &["sender", "mime_type", "command"]),
+    ("compose", &["reply_templates"]),
+    (
+        "compose.reply_templates",
+        &[
+            "recipient",
+            "language",
+            "greeting",
+            "signature",
+            "attribution",
+        ],
+    ),
+    ("archive", &["folder"]),
+    ("smtp", &["host", "port", "starttls", "sent_mailbox"]),
+    (
+        "network",
+        &[
+            "transfer_budget_kb",
+            "idle",
+            "check_updates",
+            "warm_up",
+            "warm_up_after_secs",
+        ],
+    ),
+    ("cache", &["enabled", "max_size_mb", "full_text"]),
+    ("headers", &["ignore", "unignore"]),
+    (
+        "translate",
+        &["command", "source", "target", "layout", "timeout_secs"],
+    ),
+    (
+        "ui",
+        &[
+            "index_avatars",
+            "chord_timeout_ms",
+            "initial_cursor",
+            "after_read",
+            "threaded",
+            "thread_order",
+            "sort",
+            "prefer",
+            "unread_sweep_skip",
+            "truncation",
+            "timezone",
+            "two_line_below",
+            "size_column",
+            "index_numbers",
+            "sidebar",
+            "window_title",
+            "new_mail_alert",
+            "muted_senders",
+            "screen_reader",
+            "bidi",
+            "clipboard_command",
+        ],
+    ),
+];
+
+/// How serious a problem is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The setting is ignored but the config still loads.
+    Warning,
+    /// The config cannot be loaded.
+    Error,
+}
+
+/// A problem found in the config file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// How serious the problem is.
+    pub severity: Severity,
+    /// 1-based line of the problem.
+    pub line: usize,
+    /// 1-based column of the problem.
+    pub column: usize,
+    /// Description of the problem.
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.line, self.column, severity, self.message
+        )
+    }
+}
+
+/// Checks the contents of a config file.
+pub fn check_config(contents: &str) -> Vec<Diagnostic> {
+    let document = match ImDocument::parse(contents) {
+        Ok(document) => document,
+        Err(e) => {
+            return vec![diagnostic(
+                contents,
+                Severity::Error,
+                e.span(),
+                e.message().to_string(),
+            )];
+        }
+    };
+
+    let mut diagnostics = Vec::new();
+    check_table(contents, document.as_table(), "", &mut diagnostics);
+
+    match toml::from_str::<Config>(contents) {
+        Ok(mut config) => {
+            config.override_from_env(env_var);
+            if let Err(e) = config.validate() {
+                let span = document.as_table().key("gmail").and_then(|key| key.span());
+                diagnostics.push(diagnostic(contents, Severity::Error, span, e.to_string()));
+            }
+            check_profiles(contents, &document, &mut diagnostics);
+        }
+        Err(e) => diagnostics.push(diagnostic(
+            contents,
+            Severity::Error,
+            e.span(),
+            e.message().to_string(),
+        )),
+    }
+
+    diagnostics.sort_by_key(|d| (d.line, d.column));
+    diagnostics
+}
+
+/// Reports the profiles that cannot be applied, at their names.
+fn check_profiles(contents: &str, document: &ImDocument<&str>, diagnostics: &mut Vec<Diagnostic>) {
+    let Some(profiles) = document.get("profiles").and_then(Item::as_table_like) else {
+        return;
+    };
+    for (name, _) in profiles.iter() {
+        let checked = Config::parse(contents, Some(name)).and_then(|config| config.validate());
+        if let Err(e) = checked {
+            let span = profiles.key(name).and_then(|key| key.span());
+            let message = format!("profile `{}`: {:#}", name, e);
+            diagnostics.push(diagnostic(contents, Severity::Error, span, message));
+        }
+    }
+}
+
+/// Reads and checks a config file.
+pub fn check_config_file<P: AsRef<Path>>(path: P) -> Result<Vec<Diagnostic>> {
+    let contents = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read config from {:?}", path.as_ref()))?;
+    Ok(check_config(&contents))
+}
+
+/// Returns the known keys of a table. The tables of a profile know the same
+/// keys as the top-level ones, and a profile itself also knows `folder`.
+fn known_keys(path: &str) -> Option<Vec<&'static str>> {
+    let (in_profile, path) = match path.strip_prefix("profiles.") {
+        Some(rest) => (true, rest.split_once('.').map_or("", |(_, inner)| inner)),
+        None => (false, path),
+    };
+    let mut keys = SCHEMA.iter().find(|(p, _)| *p == path)?.1.to_vec();
+    if in_profile && path.is_empty() {
+        keys.retain(|key| *key != "profiles");
+        keys.push("folder");
+    }
+    Some(keys)
+}
+
+/// Warns about unknown keys in a table and the known tables nested in it.
+fn check_table(contents: &str, table: &Table, path: &str, diagnostics: &mut Vec<Diagnostic>) {
+    // Profiles are named freely, so only what they hold is checked.
+    if path == "profiles" {
+        for (name, item) in table.iter() {
+            check_item(contents, item, &format!("profiles.{}", name), diagnostics);
+        }
+        return;
+    }
+    let Some(known) = known_keys(path) else {
+        return;
+    };
+
+    for (name, item) in table.iter() {
+        let child = if path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", path, name)
+        };
+
+        if !known.contains(&name) {
+            let section = if path.is_empty() {
+                "at the top level".to_string()
+            } else {
+                format!("in [{}]", path)
+            };
+            let mut message = format!("unknown key `{}` {}", name, section);
+            if let Some(suggestion) = suggest(name, &known) {
+                message.push_str(&format!("; did you mean `{}`?", suggestion));
+            }
+            let span = table.key(name).and_then(|key| key.span());
+            diagnostics.push(diagnostic(contents, Severity::Warning, span, message));
+            continue;
+        }
+
+        check_item(contents, item, &child, diagnostics);
+    }
+}
+
+/// Checks the tables held by an item.
+fn check_item(contents: &str, item: &Item, path: &str, diagnostics: &mut Vec<Diagnostic>) {
+    match item {
+        Item::Table(inner) => check_table(contents, inner, path, diagnostics),
+        Item::ArrayOfTables(array) => {
+            for inner in array.iter() {
+                check_table(contents, inner, path, diagnostics);
+            }
+        }
+        Item::Value(value) => {
+            if let Some(inline) = value.as_inline_table() {
+                check_table(contents, &inline.clone().into_table(), path, diagnostics);
+            }
+        }
+        Item::None => {}
+    }
+}
+
+/// Returns the known key closest to `name`, if any is close enough to be a
+/// likely typo.
+fn suggest<'a>(name: &str, known: &[&'a str]) -> Option<&'a str> {
+    known
+        .iter()
+        .map(|candidate| (strsim::levenshtein(name, candidate), *candidate))
+        .filter(|(distance, candidate)| *distance <= (candidate.len() / 3).max(2))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Builds a diagnostic located at the start of a byte span.
+fn diagnostic(
+    contents: &str,
+    severity: Severity,
+    span: Option<Range<usize>>,
+    message: String,
+) -> Diagnostic {
+    let offset = span.map_or(0, |span| span.start).min(contents.len());
+    let before = &contents[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rsplit('\n')
+        .next()
+        .map_or(0, |text| text.chars().count())
+        + 1;
+    Diagnostic {
+        severity,
+        line,
+        column,
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_keys_with_suggestions() {
+        let contents = "[gmail]\nusername = \"a\"\napp_password = \"b\"\n\n[ui]\nindex_avatar = true\n\n[[attachments.rules]]\ndir = \"x\"\n  sendr = \"y\"\n\n[colors]\n";
+        let diagnostics = check_config(contents);
+        let rendered = diagnostics
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rendered,
+            [
+                "6:1: warning: unknown key `index_avatar` in [ui]; did you mean `index_avatars`?",
+                "10:3: warning: unknown key `sendr` in [attachments.rules]; did you mean `sender`?",
+                "12:2: warning: unknown key `colors` at the top level",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_errors_have_positions() {
+        let syntax = check_config("[gmail]\nusername = \n");
+        assert_eq!(syntax.len(), 1);
+        assert_eq!(syntax[0].severity, Severity::Error);
+        assert_eq!((syntax[0].line, syntax[0].column), (2, 12));
+
+        let invalid = check_config(
+            "[gmail]\nusername = \"a\"\napp_password = \"b\"\n[ui]\ninitial_cursor = \"middle\"\n",
+        );
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(invalid[0].severity, Severity::Error);
+        assert_eq!((invalid[0].line, invalid[0].column), (5, 18));
+        assert!(invalid[0].message.contains("unknown variant `middle`"));
+
+        let no_credentials = check_config("[gmail]\nusername = \"a\"\n");
+        assert_eq!(no_credentials.len(), 1);
+        assert_eq!((no_credentials[0].line, no_credentials[0].column), (1, 2));
+    }
+
+    #[test]
+    fn test_profiles_are_checked() {
+        let contents = "[gmail]\nusername = \"a\"\napp_password = \"b\"\n\n[profiles.work]\nfolder = \"Work\"\nsidebr = true\n\n[profiles.work.ui]\nsort = \"size\"\n";
+        let rendered = check_config(contents)
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(rendered.len(), 2);
+        assert!(rendered[0].starts_with("5:11: error: profile `work`: Failed to parse profile"));
+        assert_eq!(
+            rendered[1],
+            "7:1: warning: unknown key `sidebr` in [profiles.work]"
+        );
+    }
+
+    #[test]
+    fn test_sample_config_is_clean() {
+        let sample = include_str!("../config.toml.sample");
+        assert_eq!(check_config(sample), []);
+    }
+}
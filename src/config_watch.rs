@@ -0,0 +1,131 @@
+//! Live reloading of the configuration file.
+//!
+//! The directory containing the config file is watched rather than the file
+//! itself, since editors often save by writing a new file and renaming it
+//! over the old one, which would end a watch on the file.
+
+use anyhow::{Context, Result};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, channel};
+
+use crate::config::Config;
+
+/// Watches the config file for changes.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    file_name: OsString,
+    events: Receiver<notify::Result<Event>>,
+    _watcher: RecommendedWatcher,
+}
+
+impl fmt::Debug for ConfigWatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConfigWatcher")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ConfigWatcher {
+    /// Starts watching the config file at `path`.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file_name = path
+            .file_name()
+            .with_context(|| format!("Not a file path: {:?}", path))?
+            .to_os_string();
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+
+        let (tx, events) = channel();
+        let mut watcher =
+            notify::recommended_watcher(tx).context("Failed to create file watcher")?;
+        watcher
+            .watch(&dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("Failed to watch {:?}", dir))?;
+
+        Ok(ConfigWatcher {
+            path,
+            file_name,
+            events,
+            _watcher: watcher,
+        })
+    }
+
+    /// Returns the path of the watched config file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns whether the config file changed since the last call, without
+    /// blocking.
+    pub fn changed(&self) -> bool {
+        let mut changed = false;
+        while let Ok(event) = self.events.try_recv() {
+            if let Ok(event) = event
+                && !event.kind.is_access()
+                && event
+                    .paths
+                    .iter()
+                    .any(|p| p.file_name() == Some(self.file_name.as_os_str()))
+            {
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+/// Returns the config sections that changed but only take effect after a
+/// restart.
+///
+/// Everything else is read when it is used and applies immediately.
+pub fn restart_required(old: &Config, new: &Config) -> Vec<&'static str> {
+    let mut sections = Vec::new();
+    // The IMAP session is logged in once at startup.
+    if old.gmail != new.gmail {
+        sections.push("gmail");
+    }
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_restart_required() {
+        let old = Config::default();
+        let mut new = old.clone();
+        new.ui.index_avatars = true;
+        new.network.transfer_budget_kb = Some(100);
+        assert!(restart_required(&old, &new).is_empty());
+
+        new.gmail.username = "other@gmail.com".to_string();
+        assert_eq!(restart_required(&old, &new), ["gmail"]);
+    }
+
+    #[test]
+    fn test_watcher_detects_change() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "[gmail]\n").unwrap();
+        std::fs::write(dir.path().join("other.toml"), "").unwrap();
+
+        let watcher = ConfigWatcher::new(&path).unwrap();
+        assert!(!watcher.changed());
+
+        std::fs::write(&path, "[gmail]\nusername = \"a\"\n").unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !watcher.changed() {
+            assert!(Instant::now() < deadline, "change not detected");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
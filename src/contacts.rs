@@ -0,0 +1,431 @@
+//! The address book: contacts harvested from sent mail plus those added by
+//! hand.
+//!
+//! Every recipient of a message sent from rutt is recorded in
+//! `contacts.toml` in the data directory. `rutt contacts` exports the book to
+//! a vCard or CSV file and imports contacts from one, so they can move
+//! between rutt and other mail clients.
+
+use anyhow::{Context, Result, bail};
+use lettre::message::Mailboxes;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::compose::Draft;
+use crate::index_export::csv_field;
+use crate::utils::data_dir;
+
+/// A person in the address book.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Contact {
+    /// Email address.
+    pub address: String,
+    /// Display name, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Whether the contact was added by hand or imported rather than
+    /// harvested from sent mail.
+    #[serde(default)]
+    pub manual: bool,
+}
+
+/// Layout of `contacts.toml`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Stored {
+    #[serde(default)]
+    contact: Vec<Contact>,
+}
+
+/// The contacts recorded so far, in the order they were added.
+#[derive(Debug)]
+pub struct AddressBook {
+    path: PathBuf,
+    contacts: Vec<Contact>,
+}
+
+impl AddressBook {
+    /// Opens the address book file, which need not exist yet.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let stored = match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str::<Stored>(&contents)
+                .with_context(|| format!("Failed to parse {:?}", path))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Stored::default(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+        };
+        Ok(AddressBook {
+            path,
+            contacts: stored.contact,
+        })
+    }
+
+    /// Opens the address book in the default application data directory.
+    pub fn open_default() -> Result<Self> {
+        Self::open(data_dir().join("contacts.toml"))
+    }
+
+    /// Returns the contacts.
+    pub fn contacts(&self) -> &[Contact] {
+        &self.contacts
+    }
+
+    /// Adds a contact, merging it into an existing one with the same address.
+    /// A name given by hand replaces a harvested one. Returns whether the
+    /// address was new.
+    pub fn add(&mut self, contact: Contact) -> bool {
+        let existing = self
+            .contacts
+            .iter_mut()
+            .find(|c| c.address.eq_ignore_ascii_case(&contact.address));
+        let Some(existing) = existing else {
+            self.contacts.push(contact);
+            return true;
+        };
+        if contact.name.is_some() && (existing.name.is_none() || contact.manual) {
+            existing.name = contact.name;
+        }
+        existing.manual |= contact.manual;
+        false
+    }
+
+    /// Records the recipients of a sent message and saves the book.
+    pub fn harvest(&mut self, draft: &Draft) -> Result<()> {
+        for list in [&draft.to, &draft.cc, &draft.bcc] {
+            if list.trim().is_empty() {
+                continue;
+            }
+            let mailboxes = list
+                .parse::<Mailboxes>()
+                .with_context(|| format!("Failed to parse address list: {}", list))?;
+            for mailbox in mailboxes {
+                self.add(Contact {
+                    address: mailbox.email.to_string(),
+                    name: mailbox.name,
+                    manual: false,
+                });
+            }
+        }
+        self.save()
+    }
+
+    /// Writes the address book to its file.
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+        }
+        let stored = Stored {
+            contact: self.contacts.clone(),
+        };
+        let contents = toml::to_string(&stored).context("Failed to serialize contacts")?;
+        fs::write(&self.path, contents).with_context(|| format!("Failed to write {:?}", self.path))
+    }
+}
+
+/// File format of exported contacts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContactFormat {
+    /// vCard 3.0, one card per contact.
+    VCard,
+    /// Comma-separated values with a `name,email` header row.
+    Csv,
+}
+
+impl ContactFormat {
+    /// Picks the format from a file's extension: vCard for `.vcf` and
+    /// `.vcard`, CSV otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("vcf") || ext.eq_ignore_ascii_case("vcard") => {
+                ContactFormat::VCard
+            }
+            _ => ContactFormat::Csv,
+        }
+    }
+}
+
+/// Writes contacts in the given format.
+pub fn write_contacts<W: Write>(
+    mut writer: W,
+    format: ContactFormat,
+    contacts: &[Contact],
+) -> Result<()> {
+    match format {
+        ContactFormat::VCard => {
+            for contact in contacts {
+                // FN is required; fall back to the address like other clients.
+                let name = contact.name.as_deref().unwrap_or(&contact.address);
+                write!(
+                    writer,
+                    "BEGIN:VCARD\r\nVERSION:3.0\r\nFN:{}\r\nEMAIL;TYPE=INTERNET:{}\r\nEND:VCARD\r\n",
+                    vcard_escape(name),
+                    contact.address
+                )?;
+            }
+        }
+        ContactFormat::Csv => {
+            writeln!(writer, "name,email")?;
+            for contact in contacts {
+                writeln!(
+                    writer,
+                    "{},{}",
+                    csv_field(contact.name.as_deref().unwrap_or("")),
+                    csv_field(&contact.address)
+                )?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads contacts from a vCard or CSV file's contents. They count as added
+/// by hand.
+pub fn read_contacts(contents: &str, format: ContactFormat) -> Result<Vec<Contact>> {
+    match format {
+        ContactFormat::VCard => Ok(read_vcards(contents)),
+        ContactFormat::Csv => read_csv(contents),
+    }
+}
+
+/// Reads every email address of every card, each with the card's name.
+fn read_vcards(contents: &str) -> Vec<Contact> {
+    // Long lines are folded onto continuation lines starting with a space
+    // or tab.
+    let mut lines = Vec::<String>::new();
+    for line in contents.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut contacts = Vec::new();
+    let mut name = None;
+    let mut addresses = Vec::new();
+    for line in &lines {
+        let Some((property, value)) = line.split_once(':') else {
+            continue;
+        };
+        // Drop parameters (`EMAIL;TYPE=work`) and groups (`item1.EMAIL`).
+        let property = property.split(';').next().unwrap_or_default();
+        let property = property.rsplit('.').next().unwrap_or_default();
+        match property.to_ascii_uppercase().as_str() {
+            "BEGIN" => {
+                name = None;
+                addresses.clear();
+            }
+            "FN" => name = Some(vcard_unescape(value)).filter(|n| !n.is_empty()),
+            "EMAIL" if !value.trim().is_empty() => addresses.push(value.trim().to_string()),
+            "END" => {
+                for address in addresses.drain(..) {
+                    contacts.push(Contact {
+                        name: name.clone().filter(|n| *n != address),
+                        address,
+                        manual: true,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    contacts
+}
+
+/// Reads a CSV file with a header row. The name and email columns are found
+/// by their headers, so exports of other clients such as `Display Name` and
+/// `E-mail Address` work too.
+fn read_csv(contents: &str) -> Result<Vec<Contact>> {
+    let mut records = csv_records(contents).into_iter();
+    let Some(header) = records.next() else {
+        return Ok(Vec::new());
+    };
+    let header = header
+        .iter()
+        .map(|h| h.trim().to_ascii_lowercase())
+        .collect::<Vec<_>>();
+    let Some(email) = header
+        .iter()
+        .position(|h| h.contains("email") || h.contains("e-mail"))
+    else {
+        bail!("No email column in the CSV header");
+    };
+    let name = header
+        .iter()
+        .position(|h| ["name", "display name", "full name"].contains(&h.as_str()));
+
+    Ok(records
+        .filter_map(|record| {
+            let address = record.get(email)?.trim();
+            if address.is_empty() {
+                return None;
+            }
+            Some(Contact {
+                address: address.to_string(),
+                name: name
+                    .and_then(|i| record.get(i))
+                    .map(|n| n.trim().to_string())
+                    .filter(|n| !n.is_empty()),
+                manual: true,
+            })
+        })
+        .collect())
+}
+
+/// Splits CSV contents into records of fields, handling quoted fields with
+/// separators, doubled quotes, and line breaks.
+fn csv_records(contents: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = contents.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records.retain(|r| r.iter().any(|f| !f.is_empty()));
+    records
+}
+
+/// Escapes a vCard text value.
+fn vcard_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(';', "\\;")
+        .replace('\n', "\\n")
+}
+
+/// Undoes [`vcard_escape`].
+fn vcard_unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => out.push('\n'),
+            Some(escaped) => out.push(escaped),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contact(address: &str, name: Option<&str>) -> Contact {
+        Contact {
+            address: address.to_string(),
+            name: name.map(str::to_string),
+            manual: false,
+        }
+    }
+
+    #[test]
+    fn test_harvest_and_merge() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rutt").join("contacts.toml");
+
+        let mut book = AddressBook::open(&path).unwrap();
+        let draft = Draft {
+            to: "Alice <alice@example.com>, bob@example.com".to_string(),
+            cc: "ALICE@example.com".to_string(),
+            ..Default::default()
+        };
+        book.harvest(&draft).unwrap();
+
+        let mut book = AddressBook::open(&path).unwrap();
+        assert_eq!(
+            book.contacts(),
+            [
+                contact("alice@example.com", Some("Alice")),
+                contact("bob@example.com", None)
+            ]
+        );
+
+        // A harvested name does not replace a known one; a manual one does.
+        assert!(!book.add(contact("alice@example.com", Some("A."))));
+        assert_eq!(book.contacts()[0].name.as_deref(), Some("Alice"));
+        assert!(!book.add(Contact {
+            manual: true,
+            ..contact("alice@example.com", Some("Alice Smith"))
+        }));
+        assert_eq!(book.contacts()[0].name.as_deref(), Some("Alice Smith"));
+        assert!(book.contacts()[0].manual);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let contacts = vec![
+            Contact {
+                manual: true,
+                ..contact("smith@example.com", Some("Smith, John; Jr."))
+            },
+            Contact {
+                manual: true,
+                ..contact("bob@example.com", None)
+            },
+        ];
+        for format in [ContactFormat::VCard, ContactFormat::Csv] {
+            let mut out = Vec::new();
+            write_contacts(&mut out, format, &contacts).unwrap();
+            let text = String::from_utf8(out).unwrap();
+            assert_eq!(read_contacts(&text, format).unwrap(), contacts, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_read_other_clients() {
+        let vcard = "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Carol\r\n  Jones\r\n\
+                     item1.EMAIL;TYPE=work:carol@work.example\r\n\
+                     EMAIL:carol@home.example\r\nEND:VCARD\r\n";
+        assert_eq!(
+            read_contacts(vcard, ContactFormat::VCard).unwrap(),
+            [
+                Contact {
+                    manual: true,
+                    ..contact("carol@work.example", Some("Carol Jones"))
+                },
+                Contact {
+                    manual: true,
+                    ..contact("carol@home.example", Some("Carol Jones"))
+                },
+            ]
+        );
+
+        let csv = "First Name,Display Name,E-mail Address\r\n\
+                   Dave,\"Dave \"\"D\"\" Lee\",dave@example.com\r\n\
+                   Nobody,No Address,\r\n";
+        assert_eq!(
+            read_contacts(csv, ContactFormat::Csv).unwrap(),
+            [Contact {
+                manual: true,
+                ..contact("dave@example.com", Some("Dave \"D\" Lee"))
+            }]
+        );
+        assert!(read_contacts("name,phone\n", ContactFormat::Csv).is_err());
+    }
+}
@@ -0,0 +1,171 @@
+//! External commands the text of a message is piped through before it is
+//! shown.
+//!
+//! Like mutt's `display_filter`, a `[[display_filters]]` entry names a shell
+//! command reading the decoded body on stdin and printing the text to show,
+//! for messages of a sender or content type. HTML reaches its filter as sent,
+//! so a browser such as `w3m -dump` can render it instead of rutt.
+//!
+//! Filters run on the body fetcher's worker, before the body reaches the
+//! interface, so a slow command does not hold up drawing.
+
+use anyhow::{Context, Error, Result};
+use std::time::Duration;
+
+use crate::attachments::{mime_type_matches, sender_matches};
+use crate::config::DisplayFilter;
+use crate::imap_client::MessageBody;
+use crate::utils::pipe_through;
+
+/// How long a filter may run before it is stopped and the text shown as it
+/// was, so a stuck command cannot freeze the interface.
+const FILTER_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl DisplayFilter {
+    /// Returns true if this filter applies to text of the given type sent by
+    /// the given address.
+    pub fn matches(&self, sender: Option<&str>, mime_type: &str) -> bool {
+        let sender_matches = match &self.sender {
+            Some(pattern) => sender.is_some_and(|s| sender_matches(pattern, s)),
+            None => true,
+        };
+        let mime_matches = match &self.mime_type {
+            Some(pattern) => mime_type_matches(pattern, mime_type),
+            None => true,
+        };
+
+        sender_matches && mime_matches
+    }
+}
+
+/// Returns the first filter applying to text of the given type sent by the
+/// given address.
+pub fn find_filter<'a>(
+    filters: &'a [DisplayFilter],
+    sender: Option<&str>,
+    mime_type: &str,
+) -> Option<&'a DisplayFilter> {
+    filters
+        .iter()
+        .find(|filter| filter.matches(sender, mime_type))
+}
+
+/// Runs a filter command through `sh -c` with `input` on stdin and returns
+/// what it printed, failing if it exits with a non-zero status or runs past
+/// [`FILTER_TIMEOUT`].
+pub fn run_filter(command: &str, input: &str) -> Result<String> {
+    pipe_through(command, input, FILTER_TIMEOUT).context("Display filter failed")
+}
+
+/// Runs the text of a body through the filters matching its sender and
+/// content type. HTML goes to its filter as sent, in place of the conversion
+/// to text; a failing filter leaves the text as it was, and its error is
+/// returned.
+pub fn filter_body(
+    filters: &[DisplayFilter],
+    sender: Option<&str>,
+    body: &mut MessageBody,
+) -> Vec<Error> {
+    let mut failures = Vec::new();
+    if filters.is_empty() || body.report.is_some() {
+        return failures;
+    }
+    let mut filter = |mime_type: &str, input: &str, text: &mut String| {
+        let Some(filter) = find_filter(filters, sender, mime_type) else {
+            return;
+        };
+        match run_filter(&filter.command, input) {
+            Ok(filtered) => *text = filtered,
+            Err(e) => failures.push(e),
+        }
+    };
+
+    match (&mut body.alternatives, &body.html) {
+        (Some(alternatives), html) => {
+            let plain = alternatives.plain.clone();
+            filter("text/plain", &plain, &mut alternatives.plain);
+            let html = html.clone().unwrap_or_else(|| alternatives.html.clone());
+            filter("text/html", &html, &mut alternatives.html);
+            body.text = alternatives.plain.clone();
+        }
+        (None, Some(html)) => filter("text/html", html, &mut body.text),
+        (None, None) => {
+            let text = body.text.clone();
+            filter("text/plain", &text, &mut body.text);
+        }
+    }
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(sender: Option<&str>, mime_type: Option<&str>, command: &str) -> DisplayFilter {
+        DisplayFilter {
+            sender: sender.map(str::to_string),
+            mime_type: mime_type.map(str::to_string),
+            command: command.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_find_filter() {
+        let filters = [
+            filter(Some("news.example.com"), None, "strip"),
+            filter(None, Some("text/html"), "w3m -dump -T text/html"),
+        ];
+        let found = |sender, mime_type| {
+            find_filter(&filters, sender, mime_type).map(|f| f.command.as_str())
+        };
+        assert_eq!(
+            found(Some("a@news.example.com"), "text/html"),
+            Some("strip")
+        );
+        assert_eq!(
+            found(Some("a@example.com"), "text/html"),
+            Some("w3m -dump -T text/html")
+        );
+        assert_eq!(found(None, "text/plain"), None);
+    }
+
+    #[test]
+    fn test_filter_body() {
+        let mut filters = vec![
+            filter(None, Some("text/html"), "sed 's/<[^>]*>//g'"),
+            filter(None, Some("text/plain"), "tr a-z A-Z"),
+        ];
+        let filtered = |filters: &[DisplayFilter], text: &str, html: Option<&str>| {
+            let mut body = MessageBody {
+                text: text.to_string(),
+                report: None,
+                alternatives: None,
+                html: html.map(str::to_string),
+                headers: Vec::new(),
+            };
+            let failures = filter_body(filters, None, &mut body);
+            (body.text, failures.len())
+        };
+
+        assert_eq!(filtered(&filters, "hello", None), ("HELLO".to_string(), 0));
+        // HTML is filtered as sent, not as converted by rutt.
+        assert_eq!(
+            filtered(&filters, "converted", Some("<p>as sent</p>")),
+            ("as sent".to_string(), 0)
+        );
+
+        filters[1].command = "exit 1".to_string();
+        assert_eq!(filtered(&filters, "kept", None), ("kept".to_string(), 1));
+    }
+
+    #[test]
+    fn test_run_filter() {
+        assert_eq!(run_filter("tr a-z A-Z", "hello\n").unwrap(), "HELLO\n");
+
+        let err = run_filter("echo broken >&2; exit 3", "hello").unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "Display filter failed: Command failed (exit status: 3): broken"
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! Delivery status notifications (RFC 3461, RFC 3464).
+//!
+//! Outgoing messages can ask the server to report delivery through the `NOTIFY`
+//! parameter of `RCPT TO`. Incoming reports arrive as `multipart/report`
+//! messages whose `message/delivery-status` part lists the outcome for each
+//! recipient; they are summarized here instead of being shown as raw MIME.
+
+use anyhow::{Context, Result};
+use mailparse::{MailHeaderMap, ParsedMail, parse_headers, parse_mail};
+
+/// `NOTIFY` value requesting a report on success, failure, or delay.
+pub const NOTIFY_ALL: &str = "SUCCESS,FAILURE,DELAY";
+
+/// Delivery outcome for one recipient of a report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecipientStatus {
+    /// Recipient address, from `Final-Recipient`.
+    pub recipient: String,
+    /// `failed`, `delayed`, `delivered`, `relayed`, or `expanded`.
+    pub action: String,
+    /// Enhanced status code (e.g. "5.1.1").
+    pub status: String,
+    /// Server response explaining the outcome, if given.
+    pub diagnostic: Option<String>,
+}
+
+impl RecipientStatus {
+    /// Returns whether delivery to this recipient failed.
+    pub fn failed(&self) -> bool {
+        self.action == "failed"
+    }
+
+    /// Describes a failure in one line, e.g. "Delivery failed to a@b: 550
+    /// mailbox unavailable".
+    pub fn failure_line(&self) -> String {
+        let reason = match &self.diagnostic {
+            Some(diagnostic) => short_diagnostic(diagnostic),
+            None => self.status.clone(),
+        };
+        format!("Delivery failed to {}: {}", self.recipient, reason)
+    }
+}
+
+/// A parsed delivery status report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeliveryReport {
+    /// Server that generated the report.
+    pub reporting_mta: Option<String>,
+    /// Outcome for each recipient.
+    pub recipients: Vec<RecipientStatus>,
+    /// Text of the human-readable part.
+    pub explanation: String,
+    /// The returned message, or only its headers, when included.
+    pub original: Option<Vec<u8>>,
+}
+
+impl DeliveryReport {
+    /// Formats the report as readable text: one line per recipient followed
+    /// by the server's explanation.
+    pub fn summary(&self) -> String {
+        let mut lines = vec![match &self.reporting_mta {
+            Some(mta) => format!("Delivery report from {}", mta),
+            None => "Delivery report".to_string(),
+        }];
+        lines.push(String::new());
+        for r in &self.recipients {
+            lines.push(format!("  {}: {} ({})", r.recipient, r.action, r.status));
+            if let Some(diagnostic) = &r.diagnostic {
+                lines.push(format!("    {}", diagnostic));
+            }
+        }
+        if !self.explanation.trim().is_empty() {
+            lines.push(String::new());
+            lines.push(self.explanation.trim_end().to_string());
+        }
+        lines.join("\n")
+    }
+
+    /// Returns the recipients delivery failed for.
+    pub fn failures(&self) -> impl Iterator<Item = &RecipientStatus> {
+        self.recipients.iter().filter(|r| r.failed())
+    }
+}
+
+/// Parses a message as a delivery status report.
+///
+/// Returns `None` when the message is not a `multipart/report` with a
+/// `message/delivery-status` part.
+pub fn parse_delivery_report(raw: &[u8]) -> Result<Option<DeliveryReport>> {
+    let mail = parse_mail(raw).context("Failed to parse message")?;
+    if !mail.ctype.mimetype.eq_ignore_ascii_case("multipart/report") {
+        return Ok(None);
+    }
+    let Some(status_part) = find_part(&mail, "message/delivery-status") else {
+        return Ok(None);
+    };
+
+    let explanation = match find_part(&mail, "text/plain") {
+        Some(part) => part.get_body().context("Failed to decode report text")?,
+        None => String::new(),
+    };
+    let status = status_part
+        .get_body_raw()
+        .context("Failed to decode delivery status")?;
+    let original = find_part(&mail, "message/rfc822")
+        .or_else(|| find_part(&mail, "text/rfc822-headers"))
+        .map(|part| part.get_body_raw())
+        .transpose()
+        .context("Failed to decode returned message")?;
+
+    let mut reporting_mta = None;
+    let mut recipients = Vec::new();
+    for (i, block) in field_blocks(&status).iter().enumerate() {
+        let (headers, _) = parse_headers(block.as_bytes()).context("Invalid delivery status")?;
+        let field = |name: &str| headers.get_first_value(name).map(|v| strip_type(&v));
+        if i == 0 {
+            reporting_mta = field("Reporting-MTA");
+            continue;
+        }
+        let Some(recipient) = field("Final-Recipient").or_else(|| field("Original-Recipient"))
+        else {
+            continue;
+        };
+        recipients.push(RecipientStatus {
+            recipient,
+            action: field("Action").unwrap_or_default().to_lowercase(),
+            status: field("Status").unwrap_or_default(),
+            diagnostic: field("Diagnostic-Code"),
+        });
+    }
+
+    Ok(Some(DeliveryReport {
+        reporting_mta,
+        recipients,
+        explanation,
+        original,
+    }))
+}
+
+/// Finds the first part with the given MIME type, depth-first.
+fn find_part<'a>(mail: &'a ParsedMail<'a>, mimetype: &str) -> Option<&'a ParsedMail<'a>> {
+    if mail.ctype.mimetype.eq_ignore_ascii_case(mimetype) {
+        return Some(mail);
+    }
+    mail.subparts
+        .iter()
+        .find_map(|part| find_part(part, mimetype))
+}
+
+/// Splits a delivery-status body into its blank-line separated field groups:
+/// the per-message fields, then one group per recipient.
+fn field_blocks(body: &[u8]) -> Vec<String> {
+    let text = String::from_utf8_lossy(body).replace("\r\n", "\n");
+    text.split("\n\n")
+        .map(|block| block.trim_matches('\n'))
+        .filter(|block| !block.is_empty())
+        .map(|block| format!("{}\n\n", block))
+        .collect()
+}
+
+/// Shortens a diagnostic by dropping its enhanced status codes, e.g. "550
+/// 5.1.1 mailbox unavailable" -> "550 mailbox unavailable".
+fn short_diagnostic(diagnostic: &str) -> String {
+    let is_enhanced_code = |word: &str| {
+        let parts = word.split('.').collect::<Vec<_>>();
+        parts.len() == 3
+            && parts
+                .iter()
+                .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+    };
+    diagnostic
+        .split_whitespace()
+        .filter(|word| !is_enhanced_code(word))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Removes the type prefix of a typed field (e.g. "rfc822; a@b" -> "a@b").
+fn strip_type(value: &str) -> String {
+    match value.split_once(';') {
+        Some((_, rest)) => rest.trim().to_string(),
+        None => value.trim().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A bounce as generated by Gmail's mailer daemon.
+    const BOUNCE: &str = "From: Mail Delivery Subsystem <mailer-daemon@googlemail.com>\r\n\
+To: me@gmail.com\r\n\
+Subject: Delivery Status Notification (Failure)\r\n\
+MIME-Version: 1.0\r\n\
+Content-Type: multipart/report; report-type=delivery-status; boundary=\"b1\"\r\n\
+\r\n\
+--b1\r\n\
+Content-Type: text/plain; charset=UTF-8\r\n\
+\r\n\
+Your message wasn't delivered to nobody@example.com.\r\n\
+--b1\r\n\
+Content-Type: message/delivery-status\r\n\
+\r\n\
+Reporting-MTA: dns; googlemail.com\r\n\
+\r\n\
+Final-Recipient: rfc822; nobody@example.com\r\n\
+Action: failed\r\n\
+Status: 5.1.1\r\n\
+Diagnostic-Code: smtp; 550 5.1.1 mailbox unavailable\r\n\
+\r\n\
+--b1\r\n\
+Content-Type: message/rfc822\r\n\
+\r\n\
+From: me@gmail.com\r\n\
+To: nobody@example.com\r\n\
+Subject: Hello\r\n\
+\r\n\
+Hi there\r\n\
+--b1--\r\n";
+
+    #[test]
+    fn test_parse_delivery_report() {
+        let report = parse_delivery_report(BOUNCE.as_bytes()).unwrap().unwrap();
+        assert_eq!(report.reporting_mta.as_deref(), Some("googlemail.com"));
+        assert_eq!(
+            report.recipients,
+            [RecipientStatus {
+                recipient: "nobody@example.com".to_string(),
+                action: "failed".to_string(),
+                status: "5.1.1".to_string(),
+                diagnostic: Some("550 5.1.1 mailbox unavailable".to_string()),
+            }]
+        );
+
+        assert_eq!(
+            report.failures().next().unwrap().failure_line(),
+            "Delivery failed to nobody@example.com: 550 mailbox unavailable"
+        );
+        let original = String::from_utf8(report.original.clone().unwrap()).unwrap();
+        assert!(original.starts_with("From: me@gmail.com"));
+
+        let summary = report.summary();
+        assert!(summary.starts_with("Delivery report from googlemail.com"));
+        assert!(summary.contains("  nobody@example.com: failed (5.1.1)"));
+        assert!(summary.ends_with("Your message wasn't delivered to nobody@example.com."));
+    }
+
+    #[test]
+    fn test_parse_delivery_report_plain_message() {
+        let raw = b"Subject: Hi\r\nContent-Type: text/plain\r\n\r\nHello\r\n";
+        assert_eq!(parse_delivery_report(raw).unwrap(), None);
+    }
+}
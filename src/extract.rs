@@ -0,0 +1,406 @@
+//! Key values recognized in structured messages.
+//!
+//! Parcel notices, flight confirmations, one-time codes, and meeting
+//! invitations each carry the one value their reader is after: a tracking
+//! number, a booking reference, a code, a link to join. Rather than a parser
+//! per sender, lightweight extractors look for a keyword and a value of the
+//! right shape near it. The detail view shows what they find above the body,
+//! with `y` to copy it.
+
+use std::fmt;
+
+use crate::urls::find_urls;
+
+/// Words introducing a one-time code. ASCII ones match whole words.
+const CODE_KEYWORDS: &[&str] = &[
+    "code",
+    "otp",
+    "passcode",
+    "pin",
+    "verification",
+    "認証コード",
+    "確認コード",
+    "ワンタイム",
+];
+
+/// Number of lines after a mention of a code where the code may stand on a
+/// line of its own, past a greeting or an instruction.
+const CODE_LINES_BELOW: usize = 3;
+
+/// Words marking a message as about a flight.
+const FLIGHT_KEYWORDS: &[&str] = &["flight", "flights", "boarding", "フライト", "搭乗"];
+
+/// Phrases introducing a booking reference.
+const REFERENCE_PHRASES: &[&str] = &[
+    "booking reference",
+    "booking code",
+    "confirmation code",
+    "confirmation number",
+    "record locator",
+    "reservation code",
+    "pnr",
+    "予約番号",
+    "確認番号",
+];
+
+/// Words introducing a parcel tracking number.
+const TRACKING_KEYWORDS: &[&str] = &["tracking", "追跡番号", "お問い合わせ番号"];
+
+/// Hosts of video meeting services, with the service's name.
+const MEETING_HOSTS: &[(&str, &str)] = &[
+    ("zoom.us", "Zoom"),
+    ("meet.google.com", "Google Meet"),
+    ("teams.microsoft.com", "Microsoft Teams"),
+    ("teams.live.com", "Microsoft Teams"),
+    ("webex.com", "Webex"),
+    ("whereby.com", "Whereby"),
+    ("meet.jit.si", "Jitsi Meet"),
+];
+
+/// What kind of value a summary holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SummaryKind {
+    /// A one-time code for signing in or confirming an action.
+    Code,
+    /// A flight's booking reference, or its flight number.
+    Flight,
+    /// A parcel's tracking number.
+    Parcel,
+    /// A link to join a video meeting.
+    Meeting,
+}
+
+impl SummaryKind {
+    /// Returns the name shown before the value.
+    pub fn label(&self) -> &'static str {
+        match self {
+            SummaryKind::Code => "One-time code",
+            SummaryKind::Flight => "Flight",
+            SummaryKind::Parcel => "Tracking number",
+            SummaryKind::Meeting => "Meeting",
+        }
+    }
+}
+
+/// A key value found in a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Summary {
+    pub kind: SummaryKind,
+    /// The value, as copied to the clipboard.
+    pub value: String,
+    /// What else is known about it, such as the flight number beside a
+    /// booking reference.
+    pub detail: Option<String>,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.kind.label(), self.value)?;
+        if let Some(detail) = &self.detail {
+            write!(f, " ({})", detail)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the key values found in a message, one-time codes first as they
+/// are the most urgent, then flights, parcels, and meetings.
+pub fn summarize(subject: &str, body: &str) -> Vec<Summary> {
+    [
+        one_time_code(subject, body).map(|code| Summary {
+            kind: SummaryKind::Code,
+            value: code,
+            detail: None,
+        }),
+        flight(subject, body),
+        parcel(body),
+        meeting(body),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// Returns the one-time code in a message: 4 to 8 digits on a line
+/// mentioning a code, or alone on one of the few lines after it.
+pub fn one_time_code(subject: &str, body: &str) -> Option<String> {
+    let lines = text_lines(subject, body);
+    lines.iter().enumerate().find_map(|(i, line)| {
+        if !mentions(line, CODE_KEYWORDS) {
+            return None;
+        }
+        let on_line = words(line).find(|word| is_code(word));
+        let below = lines
+            .iter()
+            .skip(i + 1)
+            .take(CODE_LINES_BELOW)
+            .find(|next| is_code(next))
+            .copied();
+        on_line.or(below).map(str::to_string)
+    })
+}
+
+/// Returns the booking reference or flight number of a message about a
+/// flight.
+fn flight(subject: &str, body: &str) -> Option<Summary> {
+    let lines = text_lines(subject, body);
+    if !lines.iter().any(|line| mentions(line, FLIGHT_KEYWORDS)) {
+        return None;
+    }
+    let reference = lines.iter().enumerate().find_map(|(i, line)| {
+        let lower = line.to_lowercase();
+        let phrase = REFERENCE_PHRASES.iter().find(|p| lower.contains(*p))?;
+        // The reference follows the phrase, on its line or the next.
+        let after = line.get(lower.find(phrase)? + phrase.len()..)?;
+        words(after)
+            .chain(lines.get(i + 1).into_iter().flat_map(|next| words(next)))
+            .find(|word| is_reference(word))
+            .map(str::to_string)
+    });
+    let number = lines
+        .iter()
+        .filter(|line| mentions(line, FLIGHT_KEYWORDS))
+        .find_map(|line| flight_number(line));
+
+    match (reference, number) {
+        (Some(reference), number) => Some(Summary {
+            kind: SummaryKind::Flight,
+            value: reference,
+            detail: number.map(|number| format!("flight {}", number)),
+        }),
+        (None, Some(number)) => Some(Summary {
+            kind: SummaryKind::Flight,
+            value: number,
+            detail: None,
+        }),
+        (None, None) => None,
+    }
+}
+
+/// Returns the tracking number of a parcel: a long code following a
+/// mention of tracking, or a UPS number anywhere.
+fn parcel(body: &str) -> Option<Summary> {
+    let lines = text_lines("", body);
+    let tracked = lines.iter().enumerate().find_map(|(i, line)| {
+        let lower = line.to_lowercase();
+        let keyword = TRACKING_KEYWORDS.iter().find(|k| lower.contains(*k))?;
+        let after = line.get(lower.find(keyword)? + keyword.len()..)?;
+        words(after)
+            .chain(lines.get(i + 1).into_iter().flat_map(|next| words(next)))
+            .find(|word| is_tracking_number(word))
+    });
+    let number = tracked.or_else(|| {
+        lines
+            .iter()
+            .flat_map(|line| words(line))
+            .find(|word| is_ups_number(word))
+    })?;
+
+    Some(Summary {
+        kind: SummaryKind::Parcel,
+        value: number.to_string(),
+        detail: is_ups_number(number).then(|| "UPS".to_string()),
+    })
+}
+
+/// Returns the first link to a video meeting in a message.
+fn meeting(body: &str) -> Option<Summary> {
+    find_urls(body).into_iter().find_map(|url| {
+        let host = url
+            .split_once("://")
+            .map_or(url.as_str(), |(_, rest)| rest)
+            .split(['/', '?', '#'])
+            .next()?
+            .to_lowercase();
+        let (_, service) = MEETING_HOSTS
+            .iter()
+            .find(|(domain, _)| host == *domain || host.ends_with(&format!(".{}", domain)))?;
+        Some(Summary {
+            kind: SummaryKind::Meeting,
+            value: url.clone(),
+            detail: Some(service.to_string()),
+        })
+    })
+}
+
+/// Returns the subject and body lines worth searching: quoted lines are
+/// left out, as they belong to an earlier message.
+fn text_lines<'a>(subject: &'a str, body: &'a str) -> Vec<&'a str> {
+    std::iter::once(subject)
+        .chain(body.lines())
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('>'))
+        .collect()
+}
+
+/// Splits a line into runs of ASCII letters and digits.
+fn words(line: &str) -> impl Iterator<Item = &str> {
+    line.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+}
+
+/// Returns whether a line mentions one of the keywords, ASCII ones as whole
+/// words so that "pin" is not found in "shipping".
+fn mentions(line: &str, keywords: &[&str]) -> bool {
+    let lower = line.to_lowercase();
+    keywords.iter().any(|keyword| {
+        if keyword.is_ascii() {
+            words(&lower).any(|word| word == *keyword)
+        } else {
+            lower.contains(keyword)
+        }
+    })
+}
+
+/// Returns whether a word looks like a one-time code: 4 to 8 digits that
+/// are not a year.
+fn is_code(word: &str) -> bool {
+    let is_year = word.len() == 4 && (word.starts_with("19") || word.starts_with("20"));
+    (4..=8).contains(&word.len()) && word.bytes().all(|b| b.is_ascii_digit()) && !is_year
+}
+
+/// Returns whether a word looks like a booking reference: 5 to 8 capital
+/// letters and digits, with at least one letter.
+fn is_reference(word: &str) -> bool {
+    (5..=8).contains(&word.len())
+        && word
+            .bytes()
+            .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+        && word.bytes().any(|b| b.is_ascii_uppercase())
+}
+
+/// Returns the first flight number on a line, such as "NH106" or "UA 837":
+/// a two-character airline code with a letter, then 1 to 4 digits.
+fn flight_number(line: &str) -> Option<String> {
+    let is_airline = |code: &str| {
+        code.len() == 2
+            && code
+                .bytes()
+                .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+            && code.bytes().any(|b| b.is_ascii_uppercase())
+    };
+    let is_number = |digits: &str| {
+        (1..=4).contains(&digits.len()) && digits.bytes().all(|b| b.is_ascii_digit())
+    };
+    let words = words(line).collect::<Vec<_>>();
+    words.iter().enumerate().find_map(|(i, word)| {
+        if word.len() > 2 && is_airline(&word[..2]) && is_number(&word[2..]) {
+            Some(word.to_string())
+        } else if is_airline(word) && words.get(i + 1).is_some_and(|next| is_number(next)) {
+            Some(format!("{} {}", word, words[i + 1]))
+        } else {
+            None
+        }
+    })
+}
+
+/// Returns whether a word looks like a tracking number: 8 to 34 capital
+/// letters and digits, at least 8 of them digits.
+fn is_tracking_number(word: &str) -> bool {
+    (8..=34).contains(&word.len())
+        && word
+            .bytes()
+            .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+        && word.bytes().filter(u8::is_ascii_digit).count() >= 8
+}
+
+/// Returns whether a word is a UPS tracking number: "1Z" and 16 capital
+/// letters and digits.
+fn is_ups_number(word: &str) -> bool {
+    word.len() == 18 && word.starts_with("1Z") && is_tracking_number(word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_one_time_code() {
+        assert_eq!(
+            one_time_code(
+                "Your sign-in code",
+                "Hello,\n\n  482913\n\nIt expires in 10 minutes."
+            )
+            .as_deref(),
+            Some("482913")
+        );
+        assert_eq!(
+            one_time_code("G-731502 is your Google verification code", "").as_deref(),
+            Some("731502")
+        );
+        assert_eq!(
+            one_time_code("ログイン", "認証コード：905311").as_deref(),
+            Some("905311")
+        );
+        // Years, numbers away from a mention of a code, and quoted codes are
+        // not codes.
+        assert_eq!(one_time_code("Code of conduct 2026", "Order 12345"), None);
+        assert_eq!(one_time_code("Re: hi", "> Your code is 123456"), None);
+        assert_eq!(one_time_code("Shipping update", "Shipping 4 items"), None);
+    }
+
+    #[test]
+    fn test_flight() {
+        let summaries = summarize(
+            "Your flight to Tokyo",
+            "Booking reference: QX7T2M\n\nFlight NH106 departs LAX at 10:50.",
+        );
+        assert_eq!(
+            summaries,
+            vec![Summary {
+                kind: SummaryKind::Flight,
+                value: "QX7T2M".to_string(),
+                detail: Some("flight NH106".to_string()),
+            }]
+        );
+        assert_eq!(summaries[0].to_string(), "Flight: QX7T2M (flight NH106)");
+
+        let number = flight("Check in now", "Your flight UA 837 boards at gate 12.").unwrap();
+        assert_eq!((number.value.as_str(), number.detail), ("UA 837", None));
+        assert_eq!(
+            flight("Confirmation number ABC123", "Thanks for your order"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parcel() {
+        let tracked =
+            parcel("Your package has shipped.\nTracking number:\n9400111899223344556677").unwrap();
+        assert_eq!(tracked.value, "9400111899223344556677");
+        assert_eq!(tracked.detail, None);
+
+        let ups =
+            parcel("Track it at https://www.ups.com/track?tracknum=1Z999AA10123456784").unwrap();
+        assert_eq!(ups.value, "1Z999AA10123456784");
+        assert_eq!(ups.detail.as_deref(), Some("UPS"));
+
+        assert_eq!(parcel("Tracking pixels are blocked by default."), None);
+    }
+
+    #[test]
+    fn test_meeting() {
+        let invite =
+            meeting("Join: https://us02web.zoom.us/j/8812345678?pwd=abc.\nSee you").unwrap();
+        assert_eq!(invite.value, "https://us02web.zoom.us/j/8812345678?pwd=abc");
+        assert_eq!(invite.detail.as_deref(), Some("Zoom"));
+        assert_eq!(
+            meeting("https://meet.google.com/abc-defg-hij").map(|m| m.detail),
+            Some(Some("Google Meet".to_string()))
+        );
+        assert_eq!(meeting("https://notzoom.us/j/1"), None);
+    }
+
+    #[test]
+    fn test_summarize_orders_codes_first() {
+        let kinds = summarize(
+            "Your verification code",
+            "Code: 551234\nJoin at https://meet.google.com/abc-defg-hij",
+        )
+        .into_iter()
+        .map(|summary| summary.kind)
+        .collect::<Vec<_>>();
+        assert_eq!(kinds, [SummaryKind::Code, SummaryKind::Meeting]);
+        assert!(summarize("Hello", "Just saying hi.").is_empty());
+    }
+}
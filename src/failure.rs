@@ -0,0 +1,157 @@
+//! Causes of failure told apart for scripts.
+//!
+//! The non-interactive subcommands exit with a code documented for each
+//! cause, and with `--json-errors` print the error as a JSON object, so a
+//! status bar can tell a wrong password from a dropped connection. The cause
+//! is found by walking the error's chain: IMAP response codes (RFC 5530),
+//! SMTP reply codes, and I/O error kinds.
+
+use serde_json::json;
+use std::io;
+use std::process::ExitCode;
+
+/// Why a command failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// The server rejected the credentials.
+    Auth,
+    /// The server could not be reached, or the connection dropped.
+    Network,
+    /// A folder or file does not exist.
+    NotFound,
+    /// Anything else.
+    Other,
+}
+
+impl Failure {
+    /// Classifies an error by the first cause in its chain that tells.
+    pub fn of(error: &anyhow::Error) -> Self {
+        error
+            .chain()
+            .find_map(|cause| {
+                if let Some(e) = cause.downcast_ref::<imap::Error>() {
+                    imap_failure(e)
+                } else if let Some(e) = cause.downcast_ref::<lettre::transport::smtp::Error>() {
+                    smtp_failure(e)
+                } else {
+                    cause.downcast_ref::<io::Error>().and_then(io_failure)
+                }
+            })
+            .unwrap_or(Failure::Other)
+    }
+
+    /// Returns the exit code for this cause.
+    pub fn code(self) -> u8 {
+        match self {
+            Failure::Other => 1,
+            Failure::Auth => 2,
+            Failure::Network => 3,
+            Failure::NotFound => 4,
+        }
+    }
+
+    /// Returns the name used in JSON errors.
+    pub fn name(self) -> &'static str {
+        match self {
+            Failure::Auth => "auth",
+            Failure::Network => "network",
+            Failure::NotFound => "not_found",
+            Failure::Other => "other",
+        }
+    }
+}
+
+fn imap_failure(error: &imap::Error) -> Option<Failure> {
+    match error {
+        imap::Error::Io(e) => io_failure(e),
+        imap::Error::ConnectionLost => Some(Failure::Network),
+        imap::Error::No(text) | imap::Error::Bad(text) => {
+            let text = text.to_ascii_uppercase();
+            if [
+                "[AUTHENTICATIONFAILED]",
+                "[AUTHORIZATIONFAILED]",
+                "[EXPIRED]",
+            ]
+            .iter()
+            .any(|code| text.contains(code))
+            {
+                Some(Failure::Auth)
+            } else if text.contains("[NONEXISTENT]") {
+                Some(Failure::NotFound)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+fn smtp_failure(error: &lettre::transport::smtp::Error) -> Option<Failure> {
+    match error.status().map(|code| code.to_string()) {
+        // Authentication required, too weak, or rejected.
+        Some(code) if ["530", "534", "535"].contains(&code.as_str()) => Some(Failure::Auth),
+        Some(_) => None,
+        // No reply at all: the connection failed.
+        None if !(error.is_client() || error.is_tls() || error.is_response()) => {
+            Some(Failure::Network)
+        }
+        None => None,
+    }
+}
+
+fn io_failure(error: &io::Error) -> Option<Failure> {
+    use io::ErrorKind::*;
+    match error.kind() {
+        NotFound => Some(Failure::NotFound),
+        ConnectionRefused | ConnectionReset | ConnectionAborted | NotConnected | TimedOut
+        | HostUnreachable | NetworkUnreachable | NetworkDown | AddrNotAvailable | BrokenPipe
+        | UnexpectedEof => Some(Failure::Network),
+        _ => None,
+    }
+}
+
+/// Reports a failed subcommand on stderr, as a JSON object with
+/// `json_errors`, and returns the exit code for its cause.
+pub fn report_failure(error: &anyhow::Error, json_errors: bool) -> ExitCode {
+    let failure = Failure::of(error);
+    if json_errors {
+        let object = json!({
+            "error": failure.name(),
+            "code": failure.code(),
+            "message": format!("{error:#}"),
+        });
+        eprintln!("{object}");
+    } else {
+        eprintln!("Error: {error:#}");
+    }
+    ExitCode::from(failure.code())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_failure_of() {
+        let rejected = Err::<(), _>(imap::Error::No(
+            "[AUTHENTICATIONFAILED] Invalid credentials (Failure)".to_string(),
+        ))
+        .context("Failed to login to imap.gmail.com")
+        .unwrap_err();
+        assert_eq!(Failure::of(&rejected), Failure::Auth);
+
+        let missing = anyhow::Error::new(imap::Error::No(
+            "[NONEXISTENT] Unknown Mailbox: Receipts (Failure)".to_string(),
+        ));
+        assert_eq!(Failure::of(&missing), Failure::NotFound);
+
+        let refused = Err::<(), _>(io::Error::from(io::ErrorKind::ConnectionRefused))
+            .context("Failed to connect to imap.gmail.com:993")
+            .unwrap_err();
+        assert_eq!(Failure::of(&refused), Failure::Network);
+        assert_eq!(Failure::of(&refused).code(), 3);
+
+        assert_eq!(Failure::of(&anyhow::anyhow!("Bad date")), Failure::Other);
+    }
+}
@@ -0,0 +1,185 @@
+//! Background fetching of message bodies.
+//!
+//! Downloading a body over a slow connection would freeze the interface, so a
+//! worker thread with its own session fetches the bodies the UI asks for and
+//! sends them back over a channel. The UI keeps drawing, with a spinner in
+//! place of the body, until it arrives. Display filters run on the worker
+//! too, before the body is sent back.
+
+use anyhow::{Context, Error, Result};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+
+use crate::config::DisplayFilter;
+use crate::display_filter::filter_body;
+use crate::imap_client::{ImapClient, MessageBody};
+
+/// A body the UI asked for.
+#[derive(Debug)]
+struct Request {
+    mailbox: String,
+    uid: u32,
+    peek: bool,
+    /// Address of the sender, for choosing display filters.
+    sender: Option<String>,
+}
+
+/// The outcome of fetching a requested body.
+#[derive(Debug)]
+pub struct FetchedBody {
+    /// Mailbox the message is in.
+    pub mailbox: String,
+    /// UID of the message.
+    pub uid: u32,
+    /// Whether the body was only looked into, leaving the message unread.
+    pub peek: bool,
+    /// The body, or why it could not be fetched.
+    pub body: Result<MessageBody>,
+    /// Why display filters failed, leaving the text they were given.
+    pub filter_errors: Vec<Error>,
+}
+
+/// Fetches message bodies on a background thread.
+///
+/// The worker connects on the first request and reconnects after a failure.
+/// It exits once the fetcher is dropped.
+#[derive(Debug)]
+pub struct BodyFetcher {
+    requests: Sender<Request>,
+    bodies: Receiver<FetchedBody>,
+    filters: Arc<Mutex<Vec<DisplayFilter>>>,
+}
+
+impl BodyFetcher {
+    /// Starts a worker opening its session with `connect`, running fetched
+    /// bodies through `filters`.
+    pub fn new(
+        connect: impl Fn() -> Result<ImapClient> + Send + 'static,
+        filters: Vec<DisplayFilter>,
+    ) -> Self {
+        let (requests, pending) = channel::<Request>();
+        let (tx, bodies) = channel();
+        let filters = Arc::new(Mutex::new(filters));
+
+        let worker_filters = Arc::clone(&filters);
+        thread::spawn(move || {
+            let mut client = None;
+            for request in pending {
+                let mut body = fetch(&mut client, &connect, &request);
+                let filter_errors = match &mut body {
+                    Ok(body) => {
+                        let filters = worker_filters
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .clone();
+                        filter_body(&filters, request.sender.as_deref(), body)
+                    }
+                    Err(_) => Vec::new(),
+                };
+                let fetched = FetchedBody {
+                    mailbox: request.mailbox,
+                    uid: request.uid,
+                    peek: request.peek,
+                    body,
+                    filter_errors,
+                };
+                if tx.send(fetched).is_err() {
+                    break;
+                }
+            }
+        });
+
+        BodyFetcher {
+            requests,
+            bodies,
+            filters,
+        }
+    }
+
+    /// Replaces the display filters bodies are run through from now on.
+    pub fn set_filters(&self, filters: Vec<DisplayFilter>) {
+        *self.filters.lock().unwrap_or_else(PoisonError::into_inner) = filters;
+    }
+
+    /// Asks for the body of a message from `sender`; it is returned by a
+    /// later call to [`BodyFetcher::received`].
+    pub fn request(&self, mailbox: &str, uid: u32, sender: Option<&str>) {
+        self.send(mailbox, uid, false, sender);
+    }
+
+    /// Asks for the body of a message like [`BodyFetcher::request`], without
+    /// marking it read.
+    pub fn peek(&self, mailbox: &str, uid: u32, sender: Option<&str>) {
+        self.send(mailbox, uid, true, sender);
+    }
+
+    fn send(&self, mailbox: &str, uid: u32, peek: bool, sender: Option<&str>) {
+        // The worker only stops when the fetcher is dropped.
+        let _ = self.requests.send(Request {
+            mailbox: mailbox.to_string(),
+            uid,
+            peek,
+            sender: sender.map(str::to_string),
+        });
+    }
+
+    /// Returns the bodies fetched since the last call, without blocking.
+    pub fn received(&self) -> Vec<FetchedBody> {
+        self.bodies.try_iter().collect()
+    }
+}
+
+/// Fetches a requested body, connecting first if needed.
+///
+/// The connection is dropped after a failure so the next request starts on a
+/// fresh one.
+fn fetch(
+    client: &mut Option<ImapClient>,
+    connect: &impl Fn() -> Result<ImapClient>,
+    request: &Request,
+) -> Result<MessageBody> {
+    let session = match client {
+        Some(session) => session,
+        None => client.insert(connect().context("Failed to connect for fetching bodies")?),
+    };
+    session.set_mailbox(&request.mailbox);
+    let body = if request.peek {
+        session.peek_email_body(request.uid)
+    } else {
+        session.fetch_email_body(request.uid)
+    };
+    if body.is_err() {
+        *client = None;
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::bail;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_failed_fetch_is_returned() {
+        let fetcher = BodyFetcher::new(|| bail!("offline"), Vec::new());
+        fetcher.request("INBOX", 7, None);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let fetched = loop {
+            if let Some(fetched) = fetcher.received().pop() {
+                break fetched;
+            }
+            assert!(Instant::now() < deadline, "no response from the worker");
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(fetched.mailbox, "INBOX");
+        assert_eq!(fetched.uid, 7);
+        let err = fetched.body.unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "Failed to connect for fetching bodies: offline"
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! Searching several folders in the background.
+//!
+//! An all-folder search issues a SEARCH per folder over its own connection,
+//! which can take a while on a server with many folders, so it runs on a
+//! worker thread and reports the folders done meanwhile. On Gmail a search
+//! of All Mail covers every folder at once.
+
+use anyhow::{Context, Result};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::thread;
+
+use crate::imap_client::{Email, ImapClient};
+
+/// A message matching an all-folder search, with the folder it is in.
+#[derive(Debug, Clone)]
+pub struct FolderHit {
+    pub folder: String,
+    pub email: Email,
+}
+
+/// The outcome of an all-folder search.
+#[derive(Debug, Default)]
+pub struct FolderResults {
+    /// Matches from every folder, most recent first.
+    pub hits: Vec<FolderHit>,
+    /// Folders that could not be searched, e.g. ones that cannot be opened.
+    pub failed: Vec<String>,
+}
+
+enum SearchEvent {
+    Searched(usize),
+    Done(Result<FolderResults>),
+}
+
+/// An all-folder search running in a background thread.
+#[derive(Debug)]
+pub struct FolderSearch {
+    query: String,
+    events: Receiver<SearchEvent>,
+    done: usize,
+    total: usize,
+}
+
+impl FolderSearch {
+    /// Starts searching `folders` for `query`, over a session opened with
+    /// `connect`, keeping the `limit` most recent matches of each folder.
+    pub fn new(
+        connect: impl FnOnce() -> Result<ImapClient> + Send + 'static,
+        folders: Vec<String>,
+        query: &str,
+        limit: usize,
+    ) -> Self {
+        let (tx, events) = channel();
+        let total = folders.len();
+        let worker_query = query.to_string();
+        thread::spawn(move || {
+            let result = connect()
+                .map(|mut client| search(&mut client, &folders, &worker_query, limit, &tx));
+            let _ = tx.send(SearchEvent::Done(result));
+        });
+        FolderSearch {
+            query: query.to_string(),
+            events,
+            done: 0,
+            total,
+        }
+    }
+
+    /// Returns the query searched for.
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// Returns the number of folders searched so far and in all.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.done, self.total)
+    }
+
+    /// Takes in the progress reported since the last call, and returns the
+    /// results once every folder was searched, without blocking.
+    pub fn poll(&mut self) -> Option<Result<FolderResults>> {
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                SearchEvent::Searched(done) => self.done = done,
+                SearchEvent::Done(result) => return Some(result),
+            }
+        }
+        None
+    }
+}
+
+fn search(
+    client: &mut ImapClient,
+    folders: &[String],
+    query: &str,
+    limit: usize,
+    tx: &Sender<SearchEvent>,
+) -> FolderResults {
+    let mut results = FolderResults::default();
+    for (i, folder) in folders.iter().enumerate() {
+        let found = client.search(folder, query).and_then(|uids| {
+            let recent = &uids[uids.len().saturating_sub(limit)..];
+            client
+                .fetch_envelopes(recent)
+                .with_context(|| format!("Failed to fetch matches in {}", folder))
+        });
+        match found {
+            Ok(emails) => results
+                .hits
+                .extend(emails.into_iter().map(|email| FolderHit {
+                    folder: folder.clone(),
+                    email,
+                })),
+            Err(_) => results.failed.push(folder.clone()),
+        }
+        let _ = tx.send(SearchEvent::Searched(i + 1));
+    }
+    results
+        .hits
+        .sort_by_key(|hit| std::cmp::Reverse(hit.email.date));
+    results
+}
@@ -0,0 +1,522 @@
+//! Folder hierarchy for the folder browser.
+//!
+//! Builds a tree from the flat IMAP `LIST` response. Containers that cannot
+//! be opened — `\Noselect` mailboxes such as Gmail's "[Gmail]", and parents
+//! that only exist implicitly through their children's names — become
+//! non-selectable nodes that expand to show their children instead.
+//!
+//! Gmail's inbox tabs have no mailbox of their own; they are offered as
+//! virtual folders that show INBOX limited to a Gmail search.
+
+use std::collections::HashSet;
+
+use crate::imap_client::MailboxInfo;
+
+/// A node in the folder tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FolderNode {
+    /// Last component of the mailbox name, shown in the browser.
+    pub label: String,
+    /// Full mailbox name on the server.
+    pub path: String,
+    /// Whether the mailbox can be opened.
+    pub selectable: bool,
+    /// Child folders, sorted by label.
+    pub children: Vec<FolderNode>,
+}
+
+/// A visible row of the folder browser.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FolderRow<'a> {
+    /// The folder shown on this row.
+    pub node: &'a FolderNode,
+    /// Nesting depth, 0 for top-level folders.
+    pub depth: usize,
+    /// Whether the folder's children are shown.
+    pub expanded: bool,
+}
+
+/// Result of activating a row in the folder browser.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FolderAction {
+    /// Open the mailbox with this name.
+    Open(String),
+    /// The row was a container and was expanded or collapsed.
+    Toggled,
+    /// Nothing to do (empty tree or a container without children).
+    None,
+}
+
+/// Builds the folder tree from a `LIST` response.
+///
+/// INBOX is placed first; other folders are sorted by name.
+pub fn build_folder_tree(mailboxes: &[MailboxInfo]) -> Vec<FolderNode> {
+    let mut roots = Vec::new();
+
+    for mailbox in mailboxes {
+        let parts = match mailbox.delimiter.as_deref() {
+            Some(delim) if !delim.is_empty() => mailbox.name.split(delim).collect::<Vec<_>>(),
+            _ => vec![mailbox.name.as_str()],
+        };
+
+        let mut level = &mut roots;
+        for (i, part) in parts.iter().enumerate() {
+            let path = match mailbox.delimiter.as_deref() {
+                Some(delim) => parts[..=i].join(delim),
+                None => mailbox.name.clone(),
+            };
+            let pos = match level.iter().position(|n: &FolderNode| n.path == path) {
+                Some(pos) => pos,
+                None => {
+                    // Parents missing from the listing are hierarchy-only.
+                    level.push(FolderNode {
+                        label: part.to_string(),
+                        path,
+                        selectable: false,
+                        children: Vec::new(),
+                    });
+                    level.len() - 1
+                }
+            };
+            if i == parts.len() - 1 {
+                level[pos].selectable = mailbox.selectable;
+            }
+            level = &mut level[pos].children;
+        }
+    }
+
+    sort_nodes(&mut roots);
+    roots
+}
+
+fn sort_nodes(nodes: &mut [FolderNode]) {
+    nodes.sort_by(|a, b| {
+        (!a.path.eq_ignore_ascii_case("INBOX"), &a.label)
+            .cmp(&(!b.path.eq_ignore_ascii_case("INBOX"), &b.label))
+    });
+    for node in nodes {
+        sort_nodes(&mut node.children);
+    }
+}
+
+/// Returns the names of all selectable folders in tree order.
+pub fn selectable_folders(roots: &[FolderNode]) -> Vec<String> {
+    let mut names = Vec::new();
+    for node in roots {
+        if node.selectable {
+            names.push(node.path.clone());
+        }
+        names.extend(selectable_folders(&node.children));
+    }
+    names
+}
+
+/// Returns the folders to visit after `current`, in order, wrapping around
+/// and ending before `current`.
+///
+/// When `current` is not in the list, all folders are returned.
+pub fn folders_after<'a>(folders: &'a [String], current: &str) -> Vec<&'a str> {
+    let start = folders
+        .iter()
+        .position(|f| f == current)
+        .map_or(0, |pos| pos + 1);
+    folders[start..]
+        .iter()
+        .chain(&folders[..start])
+        .map(String::as_str)
+        .filter(|f| *f != current)
+        .collect()
+}
+
+/// A folder offered by the change-folder prompt.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FolderChoice {
+    /// Full mailbox name on the server.
+    pub path: String,
+    /// Number of unread messages.
+    pub unread: u32,
+}
+
+/// Orders folders for the change-folder prompt: recently used folders first,
+/// most recent first, then folders with unread mail, most unread first, then
+/// the rest in their original order.
+pub fn rank_folders(mut folders: Vec<FolderChoice>, recent: &[String]) -> Vec<FolderChoice> {
+    // Sorting is stable, so ties keep the original order.
+    folders.sort_by_key(|folder| {
+        let recency = recent
+            .iter()
+            .position(|r| *r == folder.path)
+            .unwrap_or(usize::MAX);
+        (recency, std::cmp::Reverse(folder.unread))
+    });
+    folders
+}
+
+/// Returns the folder a message sent in `year` is archived into under a
+/// folder template such as "Archive/{year}".
+pub fn archive_folder(template: &str, year: i32) -> String {
+    template.replace("{year}", &year.to_string())
+}
+
+/// A tab of Gmail's tabbed inbox, in the order Gmail shows them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InboxTab {
+    Primary,
+    Promotions,
+    Social,
+    Updates,
+    /// Messages Gmail marked as important, from any category.
+    Important,
+}
+
+impl InboxTab {
+    /// All tabs, in the order of their `g1`–`g5` shortcuts.
+    pub const ALL: [InboxTab; 5] = [
+        InboxTab::Primary,
+        InboxTab::Promotions,
+        InboxTab::Social,
+        InboxTab::Updates,
+        InboxTab::Important,
+    ];
+
+    /// Returns the tab with the given name, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|tab| tab.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Name shown to the user and accepted by `:tab`.
+    pub fn name(self) -> &'static str {
+        match self {
+            InboxTab::Primary => "Primary",
+            InboxTab::Promotions => "Promotions",
+            InboxTab::Social => "Social",
+            InboxTab::Updates => "Updates",
+            InboxTab::Important => "Important",
+        }
+    }
+
+    /// Gmail search selecting the tab's messages within INBOX.
+    pub fn query(self) -> &'static str {
+        match self {
+            InboxTab::Primary => "category:primary",
+            InboxTab::Promotions => "category:promotions",
+            InboxTab::Social => "category:social",
+            InboxTab::Updates => "category:updates",
+            InboxTab::Important => "is:important",
+        }
+    }
+}
+
+/// Returns the paths of the folders containing `path`, outermost first, or
+/// `None` if it is not in the tree.
+fn ancestors(nodes: &[FolderNode], path: &str) -> Option<Vec<String>> {
+    nodes.iter().find_map(|node| {
+        if node.path == path {
+            return Some(Vec::new());
+        }
+        let mut inner = ancestors(&node.children, path)?;
+        inner.insert(0, node.path.clone());
+        Some(inner)
+    })
+}
+
+/// Navigation state of the folder browser.
+#[derive(Debug, Clone, Default)]
+pub struct FolderTree {
+    roots: Vec<FolderNode>,
+    expanded: HashSet<String>,
+    selected: usize,
+}
+
+impl FolderTree {
+    /// Creates a browser over the given tree with the cursor on `current`.
+    ///
+    /// Everything is collapsed except the parents of `current`.
+    pub fn new(roots: Vec<FolderNode>, current: &str) -> Self {
+        let mut tree = FolderTree {
+            roots,
+            ..Default::default()
+        };
+        tree.select_path(current);
+        tree
+    }
+
+    /// Returns the names of all folders in tree order, shown or not.
+    pub fn paths(&self) -> Vec<&str> {
+        fn walk<'a>(nodes: &'a [FolderNode], paths: &mut Vec<&'a str>) {
+            for node in nodes {
+                paths.push(&node.path);
+                walk(&node.children, paths);
+            }
+        }
+
+        let mut paths = Vec::new();
+        walk(&self.roots, &mut paths);
+        paths
+    }
+
+    /// Moves the cursor to a folder, expanding its parents to show it.
+    pub fn select_path(&mut self, path: &str) {
+        let Some(parents) = ancestors(&self.roots, path) else {
+            return;
+        };
+        self.expanded.extend(parents);
+        if let Some(pos) = self.rows().iter().position(|row| row.node.path == path) {
+            self.selected = pos;
+        }
+    }
+
+    /// Returns the visible rows, depth-first.
+    pub fn rows(&self) -> Vec<FolderRow<'_>> {
+        fn walk<'a>(
+            nodes: &'a [FolderNode],
+            depth: usize,
+            expanded: &HashSet<String>,
+            rows: &mut Vec<FolderRow<'a>>,
+        ) {
+            for node in nodes {
+                let is_expanded = expanded.contains(&node.path);
+                rows.push(FolderRow {
+                    node,
+                    depth,
+                    expanded: is_expanded,
+                });
+                if is_expanded {
+                    walk(&node.children, depth + 1, expanded, rows);
+                }
+            }
+        }
+
+        let mut rows = Vec::new();
+        walk(&self.roots, 0, &self.expanded, &mut rows);
+        rows
+    }
+
+    /// Index of the selected row.
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Moves the cursor down one row.
+    pub fn next(&mut self) {
+        if self.selected + 1 < self.rows().len() {
+            self.selected += 1;
+        }
+    }
+
+    /// Moves the cursor up one row.
+    pub fn previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// Activates the selected row.
+    ///
+    /// Selectable folders are opened. Containers are never fetched; instead
+    /// they expand with the cursor moved to their first child, or collapse if
+    /// already expanded.
+    pub fn activate(&mut self) -> FolderAction {
+        let Some(row) = self.rows().get(self.selected).copied() else {
+            return FolderAction::None;
+        };
+
+        if row.node.selectable {
+            return FolderAction::Open(row.node.path.clone());
+        }
+        if row.node.children.is_empty() {
+            return FolderAction::None;
+        }
+
+        let path = row.node.path.clone();
+        if row.expanded {
+            self.expanded.remove(&path);
+        } else {
+            self.expanded.insert(path);
+            self.selected += 1;
+        }
+        FolderAction::Toggled
+    }
+
+    /// Expands the selected folder, if it has children.
+    pub fn expand(&mut self) {
+        if let Some(row) = self.rows().get(self.selected)
+            && !row.node.children.is_empty()
+        {
+            let path = row.node.path.clone();
+            self.expanded.insert(path);
+        }
+    }
+
+    /// Collapses the selected folder, or moves to its parent when it is
+    /// already collapsed.
+    pub fn collapse(&mut self) {
+        let rows = self.rows();
+        let Some(row) = rows.get(self.selected).copied() else {
+            return;
+        };
+
+        if row.expanded {
+            let path = row.node.path.clone();
+            self.expanded.remove(&path);
+        } else if let Some(parent) = rows[..self.selected]
+            .iter()
+            .rposition(|r| r.depth < row.depth)
+        {
+            self.selected = parent;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Mailboxes as returned by Gmail for `LIST "" "*"`.
+    fn gmail_list() -> Vec<MailboxInfo> {
+        [
+            ("INBOX", true),
+            ("Receipts", true),
+            ("Work/Projects", true),
+            ("[Gmail]", false),
+            ("[Gmail]/All Mail", true),
+            ("[Gmail]/Drafts", true),
+            ("[Gmail]/Sent Mail", true),
+            ("[Gmail]/Spam", true),
+            ("[Gmail]/Trash", true),
+        ]
+        .into_iter()
+        .map(|(name, selectable)| MailboxInfo {
+            name: name.to_string(),
+            delimiter: Some("/".to_string()),
+            selectable,
+            attributes: Vec::new(),
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_build_folder_tree() {
+        let tree = build_folder_tree(&gmail_list());
+        let labels = tree.iter().map(|n| n.label.as_str()).collect::<Vec<_>>();
+        assert_eq!(labels, ["INBOX", "Receipts", "Work", "[Gmail]"]);
+
+        let gmail = &tree[3];
+        assert!(!gmail.selectable);
+        assert_eq!(gmail.children.len(), 5);
+        assert_eq!(gmail.children[2].path, "[Gmail]/Sent Mail");
+        assert!(gmail.children[2].selectable);
+
+        // "Work" is only implied by "Work/Projects".
+        let work = &tree[2];
+        assert!(!work.selectable);
+        assert_eq!(work.children[0].path, "Work/Projects");
+    }
+
+    #[test]
+    fn test_noselect_expands_instead_of_opening() {
+        let mut tree = FolderTree::new(build_folder_tree(&gmail_list()), "INBOX");
+        assert_eq!(tree.selected(), 0);
+        assert_eq!(tree.activate(), FolderAction::Open("INBOX".to_string()));
+
+        // Move to "[Gmail]" and activate it: it expands rather than opening.
+        for _ in 0..3 {
+            tree.next();
+        }
+        assert_eq!(tree.rows()[tree.selected()].node.path, "[Gmail]");
+        assert_eq!(tree.activate(), FolderAction::Toggled);
+        assert_eq!(tree.rows().len(), 9);
+        assert_eq!(tree.rows()[tree.selected()].node.path, "[Gmail]/All Mail");
+        assert_eq!(tree.rows()[tree.selected()].depth, 1);
+        assert_eq!(
+            tree.activate(),
+            FolderAction::Open("[Gmail]/All Mail".to_string())
+        );
+
+        // Collapsing from a child goes to the parent, then folds it.
+        tree.collapse();
+        assert_eq!(tree.rows()[tree.selected()].node.path, "[Gmail]");
+        tree.collapse();
+        assert_eq!(tree.rows().len(), 4);
+    }
+
+    #[test]
+    fn test_folders_after() {
+        let folders = selectable_folders(&build_folder_tree(&gmail_list()));
+        assert_eq!(
+            folders,
+            [
+                "INBOX",
+                "Receipts",
+                "Work/Projects",
+                "[Gmail]/All Mail",
+                "[Gmail]/Drafts",
+                "[Gmail]/Sent Mail",
+                "[Gmail]/Spam",
+                "[Gmail]/Trash",
+            ]
+        );
+
+        let after = folders_after(&folders, "[Gmail]/Spam");
+        assert_eq!(after[..3], ["[Gmail]/Trash", "INBOX", "Receipts"]);
+        assert_eq!(after.len(), 7);
+        assert_eq!(folders_after(&folders, "Unknown").len(), 8);
+    }
+
+    #[test]
+    fn test_rank_folders() {
+        let choice = |path: &str, unread| FolderChoice {
+            path: path.to_string(),
+            unread,
+        };
+        let folders = vec![
+            choice("INBOX", 0),
+            choice("Receipts", 2),
+            choice("Work/Projects", 5),
+            choice("[Gmail]/Sent Mail", 0),
+            choice("[Gmail]/Spam", 0),
+        ];
+        let ranked = rank_folders(folders, &["[Gmail]/Spam".to_string(), "INBOX".to_string()]);
+        let paths = ranked.iter().map(|c| c.path.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                "[Gmail]/Spam",
+                "INBOX",
+                "Work/Projects",
+                "Receipts",
+                "[Gmail]/Sent Mail"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_new_selects_current_folder() {
+        let tree = FolderTree::new(build_folder_tree(&gmail_list()), "Receipts");
+        assert_eq!(tree.selected(), 1);
+
+        // Nested labels are revealed.
+        let tree = FolderTree::new(build_folder_tree(&gmail_list()), "[Gmail]/Sent Mail");
+        assert_eq!(tree.rows().len(), 9);
+        assert_eq!(tree.rows()[tree.selected()].node.path, "[Gmail]/Sent Mail");
+
+        let mut tree = FolderTree::new(build_folder_tree(&gmail_list()), "INBOX");
+        assert!(tree.paths().contains(&"[Gmail]/Spam"));
+        tree.select_path("[Gmail]/Spam");
+        assert_eq!(tree.rows()[tree.selected()].node.path, "[Gmail]/Spam");
+    }
+
+    #[test]
+    fn test_archive_folder() {
+        assert_eq!(archive_folder("Archive/{year}", 2024), "Archive/2024");
+        assert_eq!(archive_folder("Old", 2024), "Old");
+    }
+
+    #[test]
+    fn test_inbox_tab_from_name() {
+        assert_eq!(InboxTab::from_name("social"), Some(InboxTab::Social));
+        assert_eq!(InboxTab::from_name("IMPORTANT"), Some(InboxTab::Important));
+        assert_eq!(InboxTab::from_name("forums"), None);
+        assert_eq!(InboxTab::ALL[1].query(), "category:promotions");
+    }
+}
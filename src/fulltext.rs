@@ -0,0 +1,165 @@
+//! Local full-text index of cached messages, for instant offline searches.
+//!
+//! Each mailbox's cache directory holds an inverted index mapping every word
+//! of its cached messages' subjects, senders, recipients, and, once opened,
+//! bodies to the UIDs of the messages containing it. The index is brought up
+//! to date before each search, so only messages new since the last one are
+//! read.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use crate::imap_client::{Email, NameAddr};
+
+/// Version of the index format, raised when words are split differently so
+/// indexes written before are rebuilt.
+pub const FULL_TEXT_VERSION: u32 = 1;
+
+/// Shortest word indexed; shorter ones match too much to be useful.
+const MIN_WORD_LEN: usize = 2;
+
+/// An inverted index of one mailbox's cached messages.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FullTextIndex {
+    version: u32,
+    uid_validity: u32,
+    /// UIDs of the messages indexed, with whether their body was included.
+    indexed: BTreeMap<u32, bool>,
+    /// Words and the UIDs of the messages containing them.
+    words: BTreeMap<String, BTreeSet<u32>>,
+}
+
+impl FullTextIndex {
+    /// Creates an empty index for a mailbox with the given UIDVALIDITY.
+    pub fn new(uid_validity: u32) -> Self {
+        FullTextIndex {
+            version: FULL_TEXT_VERSION,
+            uid_validity,
+            ..Default::default()
+        }
+    }
+
+    /// Returns whether the index was written in the current format for the
+    /// given UIDVALIDITY.
+    pub fn is_current(&self, uid_validity: u32) -> bool {
+        self.version == FULL_TEXT_VERSION && self.uid_validity == uid_validity
+    }
+
+    /// Returns whether a message has to be indexed: it is new, or its body
+    /// has been cached since it was indexed.
+    pub fn needs(&self, uid: u32, has_body: bool) -> bool {
+        self.indexed
+            .get(&uid)
+            .is_none_or(|&indexed_body| has_body && !indexed_body)
+    }
+
+    /// Adds the words of a message's headers, and of its decoded body if
+    /// given.
+    pub fn add(&mut self, email: &Email, body: Option<&str>) {
+        let mut text = email.subject.clone();
+        let addresses = [&email.from]
+            .into_iter()
+            .chain(&email.to)
+            .chain(&email.cc)
+            .chain(&email.bcc);
+        for NameAddr { name, email } in addresses {
+            for part in name.iter().chain(email) {
+                text.push(' ');
+                text.push_str(part);
+            }
+        }
+        if let Some(body) = body {
+            text.push(' ');
+            text.push_str(body);
+        }
+
+        for word in words(&text) {
+            self.words.entry(word).or_default().insert(email._uid);
+        }
+        self.indexed.insert(email._uid, body.is_some());
+    }
+
+    /// Drops the messages not in `uids`. Returns whether any was dropped.
+    pub fn retain(&mut self, uids: &HashSet<u32>) -> bool {
+        let before = self.indexed.len();
+        self.indexed.retain(|uid, _| uids.contains(uid));
+        if self.indexed.len() == before {
+            return false;
+        }
+        self.words.retain(|_, found| {
+            found.retain(|uid| uids.contains(uid));
+            !found.is_empty()
+        });
+        true
+    }
+
+    /// Returns the UIDs of the messages containing every word of a query,
+    /// each as a word or the start of one, in ascending order.
+    pub fn search(&self, query: &str) -> Vec<u32> {
+        let mut matches: Option<BTreeSet<u32>> = None;
+        for term in words(query) {
+            let found = self
+                .words
+                .range(term.clone()..)
+                .take_while(|(word, _)| word.starts_with(&term))
+                .flat_map(|(_, uids)| uids.iter().copied())
+                .collect::<BTreeSet<_>>();
+            matches = Some(match matches {
+                Some(matches) => matches.intersection(&found).copied().collect(),
+                None => found,
+            });
+        }
+        matches.unwrap_or_default().into_iter().collect()
+    }
+}
+
+/// Splits text into lowercase words, dropping very short ones.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= MIN_WORD_LEN)
+        .map(str::to_lowercase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imap_client::test_email;
+
+    fn email(uid: u32, subject: &str, from: &str) -> Email {
+        Email {
+            subject: subject.to_string(),
+            ..test_email(uid, from)
+        }
+    }
+
+    #[test]
+    fn test_search() {
+        let mut index = FullTextIndex::new(1);
+        index.add(&email(1, "Quarterly report", "alice@example.com"), None);
+        index.add(
+            &email(2, "Lunch", "bob@example.com"),
+            Some("The REPORT is attached."),
+        );
+        index.add(&email(3, "Re: Lunch", "alice@example.com"), None);
+
+        assert_eq!(index.search("report"), [1, 2]);
+        assert_eq!(index.search("Repo alice"), [1]);
+        assert_eq!(index.search("lunch bob"), [2]);
+        assert!(index.search("dinner").is_empty());
+
+        assert!(index.retain(&HashSet::from([2, 3])));
+        assert_eq!(index.search("report"), [2]);
+        assert!(!index.retain(&HashSet::from([2, 3])));
+    }
+
+    #[test]
+    fn test_needs_body() {
+        let mut index = FullTextIndex::new(1);
+        assert!(index.needs(1, false));
+        index.add(&email(1, "Hello", "a@example.com"), None);
+        assert!(!index.needs(1, false));
+        assert!(index.needs(1, true));
+        index.add(&email(1, "Hello", "a@example.com"), Some("body"));
+        assert!(!index.needs(1, true));
+    }
+}
@@ -0,0 +1,112 @@
+//! Real-time new-mail notification with IMAP IDLE.
+//!
+//! IDLE occupies the connection it runs on, so a background thread keeps a
+//! second session open on the watched mailbox and signals whenever the server
+//! reports a change. The UI then fetches the new messages on its own session.
+
+use anyhow::{Context, Result};
+use imap::extensions::idle::WaitOutcome;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::{GmailConfig, ImapConfig};
+use crate::imap_client::open_session;
+
+/// How long one IDLE command runs before it is re-issued. Servers may drop
+/// clients idling for 30 minutes, and a stopped watcher exits at the latest
+/// after this long.
+const IDLE_REFRESH: Duration = Duration::from_secs(60);
+
+/// How long to wait before reconnecting after the IDLE session failed.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Watches a mailbox for changes in a background thread.
+pub struct MailWatcher {
+    mailbox: String,
+    events: Receiver<()>,
+    stop: Arc<AtomicBool>,
+}
+
+impl fmt::Debug for MailWatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MailWatcher")
+            .field("mailbox", &self.mailbox)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MailWatcher {
+    /// Starts watching `mailbox` on a new connection.
+    pub fn new(gmail: &GmailConfig, imap: &ImapConfig, mailbox: &str) -> Self {
+        let (tx, events) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let gmail = gmail.clone();
+        let imap = imap.clone();
+        let name = mailbox.to_string();
+        let thread_stop = stop.clone();
+        thread::spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                // Errors such as a dropped connection are retried; the UI
+                // keeps working without notifications meanwhile.
+                if idle(&gmail, &imap, &name, &tx, &thread_stop).is_err() {
+                    thread::sleep(RETRY_DELAY);
+                }
+            }
+        });
+
+        MailWatcher {
+            mailbox: mailbox.to_string(),
+            events,
+            stop,
+        }
+    }
+
+    /// Returns the watched mailbox.
+    pub fn mailbox(&self) -> &str {
+        &self.mailbox
+    }
+
+    /// Returns whether the mailbox changed since the last call, without
+    /// blocking.
+    pub fn changed(&self) -> bool {
+        self.events.try_iter().count() > 0
+    }
+}
+
+impl Drop for MailWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Idles on a mailbox, sending an event for every change, until stopped.
+fn idle(
+    gmail: &GmailConfig,
+    imap: &ImapConfig,
+    mailbox: &str,
+    tx: &Sender<()>,
+    stop: &AtomicBool,
+) -> Result<()> {
+    let mut session = open_session(gmail, imap, &Default::default(), &Default::default())?;
+    session
+        .examine(mailbox)
+        .with_context(|| format!("Failed to examine {}", mailbox))?;
+
+    while !stop.load(Ordering::Relaxed) {
+        let outcome = session
+            .idle()
+            .context("Failed to start IDLE")?
+            .wait_with_timeout(IDLE_REFRESH)
+            .context("IDLE failed")?;
+        if outcome == WaitOutcome::MailboxChanged && tx.send(()).is_err() {
+            break;
+        }
+    }
+    let _ = session.logout();
+    Ok(())
+}
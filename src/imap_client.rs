@@ -0,0 +1,1869 @@
+//! IMAP client implementation with SSL/TLS support.
+//!
+//! Provides secure connection to an IMAP server, Gmail's by default, email
+//! fetching, and message parsing functionality. Gmail's extensions (search
+//! syntax, All Mail archiving) are used only when the server advertises them.
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, FixedOffset, Local};
+use imap::Session;
+use mailparse::{
+    MailAddr, MailHeaderMap, addrparse_header, parse_header, parse_headers, parse_mail,
+};
+use native_tls::{TlsConnector, TlsStream};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::address::display_address;
+use crate::auth::{Auth, XOAuth2Authenticator};
+use crate::body::{Alternatives, message_text};
+use crate::cache::MessageCache;
+use crate::config::{GmailConfig, ImapConfig};
+use crate::dsn::{DeliveryReport, parse_delivery_report};
+use crate::failure::Failure;
+use crate::known_hosts::KnownHosts;
+use crate::labels::parse_label_fetches;
+use crate::special_use::{SpecialFolderStore, SpecialFolders, SpecialUse};
+use crate::transfer::{CountingStream, TransferSnapshot, TransferStats};
+use crate::watchdog::WatchdogStream;
+
+/// Capability advertised by servers with Gmail's IMAP extensions.
+const GMAIL_CAPABILITY: &str = "X-GM-EXT-1";
+
+/// Capability of servers keeping a modification sequence per message
+/// (RFC 7162), so a refresh can ask only for flags changed since the last.
+const CONDSTORE_CAPABILITY: &str = "CONDSTORE";
+
+/// Number of recent emails fetched when opening a mailbox, unless
+/// `imap.fetch_limit` says otherwise.
+pub const DEFAULT_FETCH_LIMIT: u32 = 200;
+
+/// Fetch items needed to build an [`Email`].
+const ENVELOPE_ITEMS: &str = "(UID FLAGS RFC822.SIZE ENVELOPE RFC822.HEADER)";
+
+/// Number of envelopes fetched per command, so progress can be reported
+/// between them.
+const ENVELOPE_BATCH: usize = 50;
+
+/// Stream type of the IMAP connection.
+pub(crate) type ImapStream = CountingStream<WatchdogStream<TlsStream<TcpStream>>>;
+
+/// Read and flagged states of messages, by UID.
+type MessageFlags = HashMap<u32, (bool, bool)>;
+
+/// How the read states of cached emails are brought up to date.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FlagSync {
+    /// Fetch the flags of every message.
+    All,
+    /// Fetch the flags of messages changed since a modification sequence.
+    ChangedSince(u64),
+    /// Nothing changed since the cache was stored.
+    Unchanged,
+}
+
+/// Emails fetched from a mailbox, and the UIDs of those whose envelopes
+/// could not be fetched or parsed.
+#[derive(Debug, Default)]
+pub struct FetchedEmails {
+    pub emails: Vec<Email>,
+    pub failed: Vec<u32>,
+}
+
+/// Represents an email message with metadata.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Email {
+    /// Unique identifier for the email in the mailbox.
+    pub _uid: u32,
+    /// Subject line of the email.
+    pub subject: String,
+    /// Sender's name or email address.
+    pub from: NameAddr,
+    /// Primary recipients.
+    pub to: Vec<NameAddr>,
+    /// Carbon copy recipients.
+    pub cc: Vec<NameAddr>,
+    /// Blind carbon copy recipients.
+    pub bcc: Vec<NameAddr>,
+    /// Date and time the email was sent.
+    pub date: DateTime<Local>,
+    /// Whether the email has been read.
+    pub is_read: bool,
+    /// Whether the email is flagged (starred in Gmail).
+    #[serde(default)]
+    pub is_flagged: bool,
+    /// Size of the whole message in bytes, as reported by the server.
+    #[serde(default)]
+    pub size: u32,
+    /// Email body content (lazily loaded).
+    #[serde(skip)]
+    pub body: Option<String>,
+    /// Message-ID header, including angle brackets.
+    pub message_id: Option<String>,
+    /// Message-ID of the message this one replies to.
+    pub in_reply_to: Option<String>,
+    /// Message-IDs from the References header, oldest first.
+    pub references: Vec<String>,
+    /// Offset from UTC of the Date header in seconds, i.e. the sender's
+    /// timezone, when the header parsed.
+    #[serde(default)]
+    pub utc_offset: Option<i32>,
+    /// Whether the server sent no envelope, so the fields above were read
+    /// from the raw header, or left empty without one.
+    #[serde(default)]
+    pub malformed: bool,
+    /// Gmail labels of the message besides system ones such as `\\Inbox`,
+    /// shown as chips in the list and detail views.
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+/// Represents an email address with an optional display name.
+///
+/// This structure can represent email addresses in various formats:
+/// - Name and email: "John Doe <john@example.com>"
+/// - Email only: "john@example.com"
+/// - Name only: "John Doe" (less common)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NameAddr {
+    pub name: Option<String>,
+    pub email: Option<String>,
+}
+
+impl fmt::Display for NameAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self {
+                name: Some(name),
+                email: Some(email),
+            } => write!(f, "{} <{}>", name, email),
+            Self {
+                name: None,
+                email: Some(email),
+            } => write!(f, "{}", email),
+            Self {
+                name: Some(name),
+                email: None,
+            } => write!(f, "{} <(unknown)>", name),
+            Self {
+                name: None,
+                email: None,
+            } => write!(f, "(unknown)"),
+        }
+    }
+}
+
+impl Email {
+    /// Returns the Message-ID of the message this one replies to, from
+    /// In-Reply-To or else the last of the References.
+    pub fn parent_id(&self) -> Option<&str> {
+        self.in_reply_to
+            .as_deref()
+            .or_else(|| self.references.last().map(String::as_str))
+    }
+}
+
+impl NameAddr {
+    /// Returns the display name if available, otherwise the email address.
+    ///
+    /// This method prioritizes the display name over the email address for
+    /// user-friendly display purposes. Returns `None` if both fields are empty.
+    pub fn name_or_addr(&self) -> Option<&str> {
+        match self {
+            Self {
+                name: Some(name),
+                email: _,
+            } => Some(name),
+            Self {
+                name: None,
+                email: Some(email),
+            } => Some(email),
+            Self {
+                name: None,
+                email: None,
+            } => None,
+        }
+    }
+}
+
+/// The raw source of a message as stored on the server.
+#[derive(Debug, Clone)]
+pub struct RawMessage {
+    /// UID of the message in its mailbox.
+    pub uid: u32,
+    /// Full RFC822 message source.
+    pub data: Vec<u8>,
+    /// Date the server received the message.
+    pub internal_date: Option<DateTime<FixedOffset>>,
+    /// IMAP flags set on the message (e.g. "\\Seen").
+    pub flags: Vec<String>,
+}
+
+/// The displayable body of a message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageBody {
+    /// Text shown in the detail view.
+    pub text: String,
+    /// The parsed report when the message is a delivery status notification.
+    pub report: Option<DeliveryReport>,
+    /// The plain-text and HTML versions, when the message has both.
+    pub alternatives: Option<Alternatives>,
+    /// The HTML part as sent, for a display filter rendering it.
+    pub html: Option<String>,
+    /// Every header of the message in order, as name and decoded value.
+    pub headers: Vec<(String, String)>,
+}
+
+/// A mailbox (folder or Gmail label) on the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MailboxInfo {
+    /// Full mailbox name (e.g. "[Gmail]/Sent Mail").
+    pub name: String,
+    /// Hierarchy delimiter, if the server uses one.
+    pub delimiter: Option<String>,
+    /// Whether the mailbox can be selected (false for `\\Noselect`).
+    pub selectable: bool,
+    /// Attributes besides the standard ones, such as the special use
+    /// `\\Sent`.
+    pub attributes: Vec<String>,
+}
+
+/// IMAP client for secure email access.
+#[derive(Debug)]
+pub struct ImapClient {
+    session: Session<ImapStream>,
+    stats: Arc<TransferStats>,
+    /// Set when the server stopped responding, so the session is replaced.
+    stalled: Arc<AtomicBool>,
+    /// Account used to reconnect.
+    account: GmailConfig,
+    /// Server used to reconnect.
+    server: ImapConfig,
+    /// Whether the server has Gmail's extensions.
+    gmail: bool,
+    /// Whether the server keeps modification sequences.
+    condstore: bool,
+    /// Local cache of headers and bodies, if enabled.
+    cache: Option<MessageCache>,
+    /// UIDVALIDITY of the mailbox last selected or examined.
+    uid_validity: Option<u32>,
+    /// Mailbox that emails are listed and fetched from.
+    mailbox: String,
+    /// Special folders found on the server, once needed.
+    special: Option<SpecialFolders>,
+}
+
+impl ImapClient {
+    /// Connects to a server with the account's configured authentication.
+    pub fn connect_with_config(gmail: &GmailConfig, imap: &ImapConfig) -> Result<Self> {
+        Self::open(gmail, imap, Arc::new(TransferStats::default()), None)
+    }
+
+    /// Connects with traffic counted in `stats`.
+    fn open(
+        gmail: &GmailConfig,
+        imap: &ImapConfig,
+        stats: Arc<TransferStats>,
+        cache: Option<MessageCache>,
+    ) -> Result<Self> {
+        let stalled = Arc::new(AtomicBool::new(false));
+        let mut session = open_session(gmail, imap, &stats, &stalled)?;
+        stats.record_command();
+        let capabilities = session
+            .capabilities()
+            .context("Failed to read server capabilities")?;
+        let is_gmail = capabilities.has_str(GMAIL_CAPABILITY);
+        let condstore = capabilities.has_str(CONDSTORE_CAPABILITY);
+        drop(capabilities);
+
+        Ok(ImapClient {
+            session,
+            stats,
+            stalled,
+            account: gmail.clone(),
+            server: imap.clone(),
+            gmail: is_gmail,
+            condstore,
+            cache,
+            uid_validity: None,
+            mailbox: imap.default_mailbox.clone(),
+            special: None,
+        })
+    }
+
+    /// Returns a function opening another connection to the same account,
+    /// sharing this client's transfer statistics and cache, so a background
+    /// thread can connect without blocking the caller.
+    pub(crate) fn connector(&self) -> impl Fn() -> Result<ImapClient> + Send + 'static {
+        let account = self.account.clone();
+        let server = self.server.clone();
+        let stats = self.stats.clone();
+        let cache = self.cache.clone();
+        move || Self::open(&account, &server, stats.clone(), cache.clone())
+    }
+
+    /// Returns whether the server has Gmail's extensions, such as its search
+    /// syntax, labels, and inbox categories.
+    pub fn is_gmail(&self) -> bool {
+        self.gmail
+    }
+
+    /// Keeps fetched headers and opened messages in a local cache, so they
+    /// are not downloaded again.
+    pub fn with_cache(mut self, cache: MessageCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Returns the local cache, if enabled.
+    pub fn message_cache(&self) -> Option<&MessageCache> {
+        self.cache.as_ref()
+    }
+
+    /// Returns the cache along with the UIDVALIDITY its entries for the
+    /// current mailbox are keyed by.
+    fn cache(&self) -> Option<(MessageCache, u32)> {
+        self.cache.clone().zip(self.uid_validity)
+    }
+
+    /// Establishes a secure connection to Gmail's IMAP server.
+    pub fn connect(username: &str, password: &str) -> Result<Self> {
+        Self::connect_with_config(
+            &GmailConfig {
+                username: username.to_string(),
+                app_password: password.to_string(),
+                ..Default::default()
+            },
+            &ImapConfig::default(),
+        )
+    }
+
+    /// Returns the session for issuing a command, counting the command in the
+    /// transfer statistics.
+    ///
+    /// If the server stopped responding during an earlier command, the
+    /// connection is replaced with a new one first; the mailbox is selected
+    /// again by the commands that need it.
+    fn session(&mut self) -> Result<&mut Session<ImapStream>> {
+        if self.stalled.load(Ordering::Relaxed) {
+            let stalled = Arc::new(AtomicBool::new(false));
+            self.session = open_session(&self.account, &self.server, &self.stats, &stalled)
+                .context("Failed to reconnect after the IMAP server stopped responding")?;
+            self.stalled = stalled;
+        }
+        self.stats.record_command();
+        Ok(&mut self.session)
+    }
+
+    /// Returns the mailbox emails are fetched from.
+    pub fn mailbox(&self) -> &str {
+        &self.mailbox
+    }
+
+    /// Returns the UIDVALIDITY of the mailbox selected last, if known.
+    pub fn uid_validity(&self) -> Option<u32> {
+        self.uid_validity
+    }
+
+    /// Asks the server for the UIDVALIDITY of a mailbox, without selecting
+    /// it.
+    pub fn mailbox_uid_validity(&mut self, mailbox: &str) -> Result<Option<u32>> {
+        let command = format!("STATUS {} (UIDVALIDITY)", quote_imap_string(mailbox));
+        let response = self
+            .session()?
+            .run_command_and_read_response(command)
+            .with_context(|| format!("Failed to get status of {}", mailbox))?;
+        Ok(parse_status_item(&response, "UIDVALIDITY").and_then(|v| u32::try_from(v).ok()))
+    }
+
+    /// Switches the mailbox emails are fetched from.
+    pub fn set_mailbox(&mut self, mailbox: &str) {
+        self.mailbox = mailbox.to_string();
+    }
+
+    /// Selects the current mailbox read-write.
+    fn select_current(&mut self) -> Result<()> {
+        let mailbox = self.mailbox.clone();
+        let selected = self
+            .session()?
+            .select(&mailbox)
+            .with_context(|| format!("Failed to select {}", mailbox))?;
+        self.uid_validity = selected.uid_validity;
+        Ok(())
+    }
+
+    /// Returns the bytes transferred and commands issued so far this session.
+    pub fn transfer_stats(&self) -> TransferSnapshot {
+        self.stats.snapshot()
+    }
+
+    /// Returns the size in bytes of a message in the current mailbox.
+    pub fn message_size(&mut self, uid: u32) -> Result<u32> {
+        self.select_current()?;
+
+        let messages = self
+            .session()?
+            .uid_fetch(uid.to_string(), "RFC822.SIZE")
+            .context("Failed to fetch message size")?;
+
+        messages
+            .iter()
+            .next()
+            .and_then(|msg| msg.size)
+            .context("Message size not found")
+    }
+
+    /// Returns the total size in bytes of messages in the currently selected
+    /// mailbox.
+    pub fn total_size(&mut self, uids: &[u32]) -> Result<u64> {
+        if uids.is_empty() {
+            return Ok(0);
+        }
+
+        let uid_set = uid_set(uids);
+        let messages = self
+            .session()?
+            .uid_fetch(&uid_set, "RFC822.SIZE")
+            .context("Failed to fetch message sizes")?;
+
+        Ok(messages
+            .iter()
+            .filter_map(|msg| msg.size)
+            .map(u64::from)
+            .sum())
+    }
+
+    /// Fetches the most recent emails from the current mailbox.
+    ///
+    /// With a cache, only the flags of cached messages are fetched and only
+    /// new messages are downloaded in full. On servers with CONDSTORE, only
+    /// flags changed since the last fetch are, and none when nothing changed.
+    pub fn fetch_emails(&mut self, limit: u32) -> Result<FetchedEmails> {
+        self.fetch_emails_with_progress(limit, |_, _| {})
+    }
+
+    /// Fetches the most recent emails like
+    /// [`fetch_emails`](Self::fetch_emails), calling `progress` with the
+    /// number of envelopes fetched so far and in all after each batch.
+    pub fn fetch_emails_with_progress(
+        &mut self,
+        limit: u32,
+        progress: impl FnMut(usize, usize),
+    ) -> Result<FetchedEmails> {
+        // Read before examining, so that changes made in between are fetched
+        // again next time rather than missed.
+        let name = self.mailbox.clone();
+        let highest_modseq = self.highest_modseq(&name);
+
+        // Get the number of messages in the mailbox
+        let mailbox = self
+            .session()?
+            .examine(&name)
+            .with_context(|| format!("Failed to examine {}", name))?;
+        self.uid_validity = mailbox.uid_validity;
+        let cache = self.cache();
+
+        // The cache is only an optimization; if it cannot be read, every
+        // message is fetched.
+        let (cached, synced) = cache
+            .as_ref()
+            .and_then(|(cache, validity)| cache.synced_emails(&name, *validity).ok())
+            .unwrap_or_default();
+        let cached_uids = cached.iter().map(|email| email._uid).collect::<Vec<_>>();
+
+        let total = mailbox.exists;
+        let mut fetched = if total == 0 {
+            FetchedEmails::default()
+        } else {
+            // Calculate the sequence range for the most recent messages
+            let start = if total > limit { total - limit + 1 } else { 1 };
+            let flags = match (synced, highest_modseq) {
+                _ if cached.is_empty() => FlagSync::All,
+                (Some(synced), Some(current)) if synced == current => FlagSync::Unchanged,
+                (Some(synced), Some(current)) if synced < current => FlagSync::ChangedSince(synced),
+                _ => FlagSync::All,
+            };
+            let sequence_set = format!("{}:{}", start, total);
+            self.sync_emails(&sequence_set, cached, flags, progress)?
+        };
+
+        self.fetch_labels(&mut fetched.emails);
+        fetched.emails.sort_by_key(|e| std::cmp::Reverse(e.date));
+
+        if let Some((cache, validity)) = cache {
+            let _ = cache.store_synced_emails(&name, validity, &fetched.emails, highest_modseq);
+            let expunged = expunged_uids(&cached_uids, &fetched);
+            let _ = cache.forget_bodies(&name, validity, &expunged);
+        }
+        Ok(fetched)
+    }
+
+    /// Fetches again the envelopes of messages of the current mailbox that
+    /// failed to load, as [`fetch_emails`](Self::fetch_emails) does.
+    pub fn retry_envelopes(&mut self, uids: &[u32]) -> Result<FetchedEmails> {
+        let name = self.mailbox.clone();
+        self.session()?
+            .examine(&name)
+            .with_context(|| format!("Failed to examine {}", name))?;
+
+        let mut fetched = FetchedEmails::default();
+        for batch in uids.chunks(ENVELOPE_BATCH) {
+            let (emails, failed) = self.fetch_batch(batch, true);
+            fetched.emails.extend(emails);
+            fetched.failed.extend(failed);
+        }
+        self.fetch_labels(&mut fetched.emails);
+        Ok(fetched)
+    }
+
+    /// Brings cached emails up to date with the messages in a sequence range:
+    /// read states are refreshed as `flags` says, new messages fetched, and
+    /// messages no longer in the range dropped. Without cached emails, every
+    /// message is new.
+    fn sync_emails(
+        &mut self,
+        sequence_set: &str,
+        cached: Vec<Email>,
+        flags: FlagSync,
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<FetchedEmails> {
+        let (uids, changed) = match flags {
+            FlagSync::All => self.fetch_flags(sequence_set, "(UID FLAGS)", true)?,
+            FlagSync::ChangedSince(modseq) => {
+                let uids = self.range_uids(sequence_set)?;
+                let query = format!("(UID FLAGS) (CHANGEDSINCE {})", modseq);
+                (uids, self.fetch_flags("1:*", &query, false)?.1)
+            }
+            FlagSync::Unchanged => (self.range_uids(sequence_set)?, HashMap::new()),
+        };
+
+        let mut cached = cached
+            .into_iter()
+            .map(|email| (email._uid, email))
+            .collect::<HashMap<_, _>>();
+        let mut emails = Vec::new();
+        let mut missing = Vec::new();
+        for uid in uids {
+            match (cached.remove(&uid), changed.get(&uid)) {
+                (Some(email), Some(&(is_read, is_flagged))) => emails.push(Email {
+                    is_read,
+                    is_flagged,
+                    ..email
+                }),
+                (Some(email), None) => emails.push(email),
+                (None, _) => missing.push(uid),
+            }
+        }
+
+        let total = emails.len() + missing.len();
+        let mut failed = Vec::new();
+        progress(emails.len(), total);
+        for batch in missing.chunks(ENVELOPE_BATCH) {
+            let (fetched, left_out) = self.fetch_batch(batch, true);
+            emails.extend(fetched);
+            failed.extend(left_out);
+            progress(emails.len() + failed.len(), total);
+        }
+        Ok(FetchedEmails { emails, failed })
+    }
+
+    /// Fetches the read and flagged states of messages of the examined
+    /// mailbox, by sequence number with `by_sequence` and by UID otherwise.
+    /// Returns the UIDs in the order fetched, with the states by UID.
+    fn fetch_flags(
+        &mut self,
+        set: &str,
+        query: &str,
+        by_sequence: bool,
+    ) -> Result<(Vec<u32>, MessageFlags)> {
+        let session = self.session()?;
+        let fetches = if by_sequence {
+            session.fetch(set, query)
+        } else {
+            session.uid_fetch(set, query)
+        }
+        .context("Failed to fetch message flags")?;
+
+        let mut uids = Vec::new();
+        let mut flags = HashMap::new();
+        for msg in fetches.iter() {
+            if let Some(uid) = msg.uid {
+                uids.push(uid);
+                flags.insert(uid, (is_seen(msg), is_flagged(msg)));
+            }
+        }
+        Ok((uids, flags))
+    }
+
+    /// Returns the UIDs of the messages in a sequence range of the examined
+    /// mailbox, in ascending order.
+    fn range_uids(&mut self, sequence_set: &str) -> Result<Vec<u32>> {
+        let mut uids = self
+            .session()?
+            .uid_search(sequence_set)
+            .context("Failed to list messages")?
+            .into_iter()
+            .collect::<Vec<_>>();
+        uids.sort_unstable();
+        Ok(uids)
+    }
+
+    /// Returns the HIGHESTMODSEQ of a mailbox, or nothing when the server
+    /// does not keep modification sequences or does not answer.
+    fn highest_modseq(&mut self, mailbox: &str) -> Option<u64> {
+        if !self.condstore {
+            return None;
+        }
+        let command = format!("STATUS {} (HIGHESTMODSEQ)", quote_imap_string(mailbox));
+        let response = self
+            .session()
+            .ok()?
+            .run_command_and_read_response(command)
+            .ok()?;
+        parse_status_item(&response, "HIGHESTMODSEQ")
+    }
+
+    /// Fetches the envelopes of a batch of messages of the examined mailbox.
+    ///
+    /// A batch that fails, e.g. on a dropped connection or a response that
+    /// does not parse, is tried once more, then split in halves to fetch
+    /// around the messages at fault, whose UIDs are returned as failed.
+    fn fetch_batch(&mut self, uids: &[u32], retry: bool) -> (Vec<Email>, Vec<u32>) {
+        let error = match self.uid_fetch_envelopes(uids) {
+            Ok(emails) => return (emails, Vec::new()),
+            Err(e) => e,
+        };
+        if !self.recover(&error) {
+            return (Vec::new(), uids.to_vec());
+        }
+        if retry {
+            return self.fetch_batch(uids, false);
+        }
+        if uids.len() == 1 {
+            return (Vec::new(), uids.to_vec());
+        }
+        let (first, second) = uids.split_at(uids.len() / 2);
+        let (mut emails, mut failed) = self.fetch_batch(first, false);
+        let (more, more_failed) = self.fetch_batch(second, false);
+        emails.extend(more);
+        failed.extend(more_failed);
+        (emails, failed)
+    }
+
+    /// Gets the connection ready to fetch again after a failed command,
+    /// reconnecting if it dropped and examining the mailbox again. Returns
+    /// false when that fails too.
+    fn recover(&mut self, error: &anyhow::Error) -> bool {
+        if Failure::of(error) == Failure::Network {
+            self.stalled.store(true, Ordering::Relaxed);
+        }
+        let name = self.mailbox.clone();
+        self.session()
+            .and_then(|session| Ok(session.examine(&name)?))
+            .is_ok()
+    }
+
+    /// Fetches the envelopes of messages of the examined mailbox by UID, in
+    /// one command.
+    fn uid_fetch_envelopes(&mut self, uids: &[u32]) -> Result<Vec<Email>> {
+        Ok(self
+            .session()?
+            .uid_fetch(uid_set(uids), ENVELOPE_ITEMS)
+            .context("Failed to fetch messages")?
+            .iter()
+            .map(parse_email)
+            .collect())
+    }
+
+    /// Fetches the body of a specific email by its UID.
+    ///
+    /// Delivery status reports are replaced by a readable summary and
+    /// returned parsed alongside it.
+    pub fn fetch_email_body(&mut self, uid: u32) -> Result<MessageBody> {
+        self.fetch_body(uid, true)
+    }
+
+    /// Fetches the body of an email like
+    /// [`fetch_email_body`](Self::fetch_email_body), but leaves it unread,
+    /// for looking into new mail the user has not opened.
+    pub fn peek_email_body(&mut self, uid: u32) -> Result<MessageBody> {
+        self.fetch_body(uid, false)
+    }
+
+    /// Fetches the body of an email, marking it read with `mark_read`.
+    fn fetch_body(&mut self, uid: u32, mark_read: bool) -> Result<MessageBody> {
+        self.select_current()?;
+        let cache = self.cache();
+
+        if let Some((cache, validity)) = &cache
+            && let Some(raw) = cache.body(&self.mailbox, *validity, uid)
+        {
+            // Fetching the body would have marked the message read.
+            if mark_read {
+                self.session()?
+                    .uid_store(uid.to_string(), "+FLAGS (\\Seen)")
+                    .context("Failed to update flags")?;
+            }
+            return Ok(parse_message_body(&raw));
+        }
+
+        let uid_set = format!("{}", uid);
+        let items = if mark_read { "BODY[]" } else { "BODY.PEEK[]" };
+        let messages = self
+            .session()?
+            .uid_fetch(&uid_set, items)
+            .context("Failed to fetch message body")?;
+
+        if let Some(msg) = messages.iter().next()
+            && let Some(raw) = msg.body()
+        {
+            if let Some((cache, validity)) = &cache {
+                let _ = cache.store_body(&self.mailbox, *validity, uid, raw);
+            }
+            return Ok(parse_message_body(raw));
+        }
+
+        Ok(MessageBody {
+            text: "(No body content)".to_string(),
+            report: None,
+            alternatives: None,
+            html: None,
+            headers: Vec::new(),
+        })
+    }
+
+    /// Fetches the full raw RFC822 source of a specific email by its UID.
+    pub fn fetch_raw_message(&mut self, uid: u32) -> Result<Vec<u8>> {
+        self.select_current()?;
+        if let Some((cache, validity)) = self.cache()
+            && let Some(raw) = cache.body(&self.mailbox, validity, uid)
+        {
+            return Ok(raw);
+        }
+
+        let uid_set = format!("{}", uid);
+        let messages = self
+            .session()?
+            .uid_fetch(&uid_set, "BODY.PEEK[]")
+            .context("Failed to fetch message source")?;
+
+        messages
+            .iter()
+            .next()
+            .and_then(|msg| msg.body())
+            .map(|body| body.to_vec())
+            .context("Message source not found")
+    }
+
+    /// Appends a raw message to a mailbox, marked as read.
+    pub fn append_message(&mut self, mailbox: &str, raw: &[u8]) -> Result<()> {
+        self.session()?
+            .append_with_flags(mailbox, raw, &[imap::types::Flag::Seen])
+            .with_context(|| format!("Failed to append message to {}", mailbox))
+    }
+
+    /// Sets or clears the `\Seen` flag on messages in the current mailbox.
+    pub fn set_seen(&mut self, uids: &[u32], seen: bool) -> Result<()> {
+        self.select_current()?;
+        let change = if seen { "+FLAGS" } else { "-FLAGS" };
+        self.session()?
+            .uid_store(uid_set(uids), format!("{} (\\Seen)", change))
+            .context("Failed to update flags")?;
+        Ok(())
+    }
+
+    /// Sets or clears the `\Flagged` flag on messages in the current mailbox,
+    /// which Gmail shows as a star.
+    pub fn set_flagged(&mut self, uids: &[u32], flagged: bool) -> Result<()> {
+        self.select_current()?;
+        let change = if flagged { "+FLAGS" } else { "-FLAGS" };
+        self.session()?
+            .uid_store(uid_set(uids), format!("{} (\\Flagged)", change))
+            .context("Failed to update flags")?;
+        Ok(())
+    }
+
+    /// Archives messages by moving them out of the current mailbox into All
+    /// Mail, which removes Gmail's Inbox label. Other servers have no such
+    /// folder, so messages are moved to their Archive folder instead.
+    pub fn archive(&mut self, uids: &[u32]) -> Result<()> {
+        let archive = self.special_folder(SpecialUse::Archive);
+        if !self.gmail || self.server.folders.archive.is_some() {
+            return self.move_to(uids, &archive);
+        }
+        // All Mail always exists on Gmail.
+        self.select_current()?;
+        self.session()?
+            .uid_mv(uid_set(uids), &archive)
+            .context("Failed to archive messages")
+    }
+
+    /// Deletes messages by moving them to the trash, from which the server
+    /// removes them for good later.
+    pub fn trash(&mut self, uids: &[u32]) -> Result<()> {
+        let trash = self.special_folder(SpecialUse::Trash);
+        self.move_to(uids, &trash)
+    }
+
+    /// Returns the folder with a role: as set under `[imap.folders]`, else
+    /// the one the server marks with it, else Gmail's or the usual name. On
+    /// Gmail, messages are archived to All Mail.
+    pub fn special_folder(&mut self, special: SpecialUse) -> String {
+        if let Some(name) = self.server.folders.get(special) {
+            return name.to_string();
+        }
+        if special == SpecialUse::Archive && self.gmail {
+            return self.special_folder(SpecialUse::All);
+        }
+        if self.special.is_none() {
+            self.special = Some(self.detect_special_folders());
+        }
+        self.special
+            .as_ref()
+            .and_then(|folders| folders.get(special))
+            .unwrap_or(special.default_name(self.gmail))
+            .to_string()
+    }
+
+    /// Finds the special folders from the attributes the server lists
+    /// mailboxes with, remembering them for the account. When listing
+    /// fails, those remembered last time are used.
+    fn detect_special_folders(&mut self) -> SpecialFolders {
+        let mut store = SpecialFolderStore::open_default();
+        let account = format!("{}@{}", self.account.username, self.server.host);
+        match self.list_mailboxes() {
+            Ok(mailboxes) => {
+                let found = SpecialFolders::detect(
+                    mailboxes
+                        .iter()
+                        .map(|m| (m.name.as_str(), m.attributes.as_slice())),
+                );
+                // Only needed when listing fails later.
+                let _ = store.save(&account, &found);
+                found
+            }
+            Err(_) => store.get(&account).cloned().unwrap_or_default(),
+        }
+    }
+
+    /// Moves messages from the current mailbox into another, creating it if
+    /// needed.
+    pub fn move_to(&mut self, uids: &[u32], mailbox: &str) -> Result<()> {
+        self.ensure_mailbox(mailbox)?;
+        self.select_current()?;
+        self.session()?
+            .uid_mv(uid_set(uids), mailbox)
+            .with_context(|| format!("Failed to move messages to {}", mailbox))
+    }
+
+    /// Adds a Gmail label to messages in the current mailbox, all in one
+    /// `UID STORE +X-GM-LABELS`. Other servers have no labels, so messages
+    /// are copied to the label's mailbox instead.
+    pub fn add_label(&mut self, uids: &[u32], label: &str) -> Result<()> {
+        self.select_current()?;
+        if !self.gmail {
+            return self
+                .session()?
+                .uid_copy(uid_set(uids), label)
+                .with_context(|| format!("Failed to label messages with {}", label));
+        }
+        self.store_labels(uids, '+', label)
+            .with_context(|| format!("Failed to label messages with {}", label))
+    }
+
+    /// Removes a Gmail label from messages in the current mailbox, all in one
+    /// `UID STORE -X-GM-LABELS`.
+    pub fn remove_label(&mut self, uids: &[u32], label: &str) -> Result<()> {
+        if !self.gmail {
+            bail!("Labels can only be removed on Gmail");
+        }
+        self.select_current()?;
+        self.store_labels(uids, '-', label)
+            .with_context(|| format!("Failed to remove label {}", label))
+    }
+
+    /// Adds (`+`) or removes (`-`) a label with `UID STORE`.
+    ///
+    /// The `.SILENT` form is used as the labels the server would send back
+    /// cannot be parsed.
+    fn store_labels(&mut self, uids: &[u32], sign: char, label: &str) -> Result<()> {
+        self.session()?
+            .run_command_and_check_ok(format!(
+                "UID STORE {} {}X-GM-LABELS.SILENT ({})",
+                uid_set(uids),
+                sign,
+                quote_imap_string(label)
+            ))
+            .context("Failed to store labels")
+    }
+
+    /// Searches a mailbox using Gmail search syntax (`X-GM-RAW`), returning
+    /// the matching UIDs in ascending order. On other servers the query is
+    /// matched against the message text (`TEXT`).
+    ///
+    /// The mailbox stays selected so the results can be fetched afterwards.
+    pub fn search(&mut self, mailbox: &str, query: &str) -> Result<Vec<u32>> {
+        self.session()?
+            .examine(mailbox)
+            .with_context(|| format!("Failed to examine {}", mailbox))?;
+
+        let key = if self.gmail { "X-GM-RAW" } else { "TEXT" };
+        let mut uids = self
+            .session()?
+            .uid_search(format!("{} {}", key, quote_imap_string(query)))
+            .context("Failed to search messages")?
+            .into_iter()
+            .collect::<Vec<_>>();
+        uids.sort_unstable();
+
+        Ok(uids)
+    }
+
+    /// Searches the cached messages of the current mailbox with the local
+    /// full-text index, without contacting the server.
+    ///
+    /// Returns `None` when there is no cache to search.
+    pub fn search_cached(&self, query: &str) -> Result<Option<Vec<Email>>> {
+        match self.cache() {
+            Some((cache, validity)) => cache.search_text(&self.mailbox, validity, query).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Looks up a message of a mailbox by its Message-ID, returning its UID.
+    ///
+    /// The mailbox stays selected so the message can be fetched afterwards.
+    pub fn find_message_id(&mut self, mailbox: &str, message_id: &str) -> Result<Option<u32>> {
+        self.session()?
+            .examine(mailbox)
+            .with_context(|| format!("Failed to examine {}", mailbox))?;
+
+        let uids = self
+            .session()?
+            .uid_search(format!(
+                "HEADER Message-ID {}",
+                quote_imap_string(message_id)
+            ))
+            .context("Failed to search messages")?;
+        Ok(uids.into_iter().max())
+    }
+
+    /// Fetches up to `limit` messages older than `before_uid` from the
+    /// current mailbox, most recent first, and whether even older ones remain.
+    pub fn fetch_older(&mut self, before_uid: u32, limit: u32) -> Result<(Vec<Email>, bool)> {
+        if before_uid <= 1 {
+            return Ok((Vec::new(), false));
+        }
+        let name = self.mailbox.clone();
+        self.session()?
+            .examine(&name)
+            .with_context(|| format!("Failed to examine {}", name))?;
+
+        let mut uids = self
+            .session()?
+            .uid_search(format!("UID 1:{}", before_uid - 1))
+            .context("Failed to search messages")?
+            .into_iter()
+            .collect::<Vec<_>>();
+        uids.sort_unstable();
+
+        let page = &uids[uids.len().saturating_sub(limit as usize)..];
+        let more = page.len() < uids.len();
+        Ok((self.fetch_envelopes(page)?, more))
+    }
+
+    /// Fetches the envelopes of messages in the currently selected mailbox,
+    /// most recent first.
+    pub fn fetch_envelopes(&mut self, uids: &[u32]) -> Result<Vec<Email>> {
+        if uids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut emails = self.uid_fetch_envelopes(uids)?;
+        self.fetch_labels(&mut emails);
+        emails.sort_by_key(|e| std::cmp::Reverse(e.date));
+        Ok(emails)
+    }
+
+    /// Fills in the Gmail labels of emails in the current mailbox, with one
+    /// `UID FETCH` of `X-GM-LABELS`.
+    ///
+    /// The IMAP library cannot parse the attribute, so the raw response is
+    /// read. Labels are only shown, so on failure the emails keep those
+    /// they had, such as cached ones.
+    fn fetch_labels(&mut self, emails: &mut [Email]) {
+        if !self.gmail || emails.is_empty() {
+            return;
+        }
+        let uids = emails.iter().map(|email| email._uid).collect::<Vec<_>>();
+        let Ok(response) = self.session().and_then(|session| {
+            session
+                .run_command_and_read_response(format!(
+                    "UID FETCH {} (UID X-GM-LABELS)",
+                    uid_set(&uids)
+                ))
+                .context("Failed to fetch labels")
+        }) else {
+            return;
+        };
+        let mut labels = parse_label_fetches(&response);
+        for email in emails {
+            if let Some(found) = labels.remove(&email._uid) {
+                email.labels = found;
+            }
+        }
+    }
+
+    /// Fetches the raw source of messages in the currently selected mailbox.
+    pub fn fetch_raw_messages(&mut self, uids: &[u32]) -> Result<Vec<RawMessage>> {
+        if uids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let uid_set = uid_set(uids);
+        let messages = self
+            .session()?
+            .uid_fetch(&uid_set, "(UID FLAGS INTERNALDATE BODY.PEEK[])")
+            .context("Failed to fetch messages")?;
+
+        Ok(messages
+            .iter()
+            .filter_map(|msg| {
+                Some(RawMessage {
+                    uid: msg.uid?,
+                    data: msg.body()?.to_vec(),
+                    internal_date: msg.internal_date(),
+                    flags: msg.flags().iter().map(|f| f.to_string()).collect(),
+                })
+            })
+            .collect())
+    }
+
+    /// Lists all mailboxes on the server.
+    pub fn list_mailboxes(&mut self) -> Result<Vec<MailboxInfo>> {
+        let names = self
+            .session()?
+            .list(Some(""), Some("*"))
+            .context("Failed to list mailboxes")?;
+
+        Ok(names
+            .iter()
+            .map(|name| MailboxInfo {
+                name: name.name().to_string(),
+                delimiter: name.delimiter().map(|d| d.to_string()),
+                selectable: !name
+                    .attributes()
+                    .contains(&imap::types::NameAttribute::NoSelect),
+                attributes: name
+                    .attributes()
+                    .iter()
+                    .filter_map(|attribute| match attribute {
+                        imap::types::NameAttribute::Custom(custom) => Some(custom.to_string()),
+                        _ => None,
+                    })
+                    .collect(),
+            })
+            .collect())
+    }
+
+    /// Lists the subscribed mailboxes that can be opened, falling back to
+    /// all of them when none is subscribed.
+    pub fn subscribed_mailboxes(&mut self) -> Result<Vec<String>> {
+        let names = self
+            .session()?
+            .lsub(Some(""), Some("*"))
+            .context("Failed to list subscribed mailboxes")?;
+        let subscribed = names
+            .iter()
+            .filter(|name| {
+                !name
+                    .attributes()
+                    .contains(&imap::types::NameAttribute::NoSelect)
+            })
+            .map(|name| name.name().to_string())
+            .collect::<Vec<_>>();
+        if !subscribed.is_empty() {
+            return Ok(subscribed);
+        }
+        Ok(self
+            .list_mailboxes()?
+            .into_iter()
+            .filter(|mailbox| mailbox.selectable)
+            .map(|mailbox| mailbox.name)
+            .collect())
+    }
+
+    /// Returns the number of unread messages in a mailbox.
+    ///
+    /// The response is read directly: imap 2.4 hands STATUS data over as an
+    /// unsolicited response, under the mailbox name as the server spells it,
+    /// and picking it out of that queue would drop the other responses
+    /// waiting there.
+    pub fn unseen_count(&mut self, mailbox: &str) -> Result<u32> {
+        let command = format!("STATUS {} (UNSEEN)", quote_imap_string(mailbox));
+        let response = self
+            .session()?
+            .run_command_and_read_response(command)
+            .with_context(|| format!("Failed to get status of {}", mailbox))?;
+        parse_status_item(&response, "UNSEEN")
+            .and_then(|count| u32::try_from(count).ok())
+            .with_context(|| format!("Server did not report unread count for {}", mailbox))
+    }
+
+    /// Opens a mailbox read-only and returns the UIDs of all its messages in
+    /// ascending order, along with its UIDVALIDITY.
+    pub fn list_uids(&mut self, mailbox: &str) -> Result<(Option<u32>, Vec<u32>)> {
+        let status = self
+            .session()?
+            .examine(mailbox)
+            .with_context(|| format!("Failed to examine {}", mailbox))?;
+
+        let mut uids = self
+            .session()?
+            .uid_search("ALL")
+            .context("Failed to list messages")?
+            .into_iter()
+            .collect::<Vec<_>>();
+        uids.sort_unstable();
+
+        Ok((status.uid_validity, uids))
+    }
+
+    /// Creates a mailbox, succeeding if it already exists.
+    pub fn ensure_mailbox(&mut self, mailbox: &str) -> Result<()> {
+        let exists = self.list_mailboxes()?.iter().any(|m| m.name == mailbox);
+        if !exists {
+            self.session()?
+                .create(mailbox)
+                .with_context(|| format!("Failed to create mailbox {}", mailbox))?;
+        }
+        Ok(())
+    }
+
+    /// Appends a raw message to a mailbox with the given flags and internal
+    /// date.
+    pub fn restore_message(
+        &mut self,
+        mailbox: &str,
+        raw: &[u8],
+        flags: &[String],
+        date: Option<DateTime<FixedOffset>>,
+    ) -> Result<()> {
+        let flags = flags
+            .iter()
+            .map(|f| imap::types::Flag::from(f.as_str()))
+            .collect::<Vec<_>>();
+        self.session()?
+            .append_with_flags_and_date(mailbox, raw, &flags, date)
+            .with_context(|| format!("Failed to append message to {}", mailbox))
+    }
+
+    fn _logout(mut self) -> Result<()> {
+        self.session()?.logout().context("Failed to logout")?;
+        Ok(())
+    }
+}
+
+/// Opens an authenticated session with the IMAP server.
+///
+/// The socket gets `imap.timeout_secs` for connecting, reading, and writing,
+/// so a stalled server fails the command and sets `stalled` instead of blocking
+/// forever.
+pub(crate) fn open_session(
+    gmail: &GmailConfig,
+    imap: &ImapConfig,
+    stats: &Arc<TransferStats>,
+    stalled: &Arc<AtomicBool>,
+) -> Result<Session<ImapStream>> {
+    let auth = Auth::from_config(gmail)?;
+    let tls = TlsConnector::builder()
+        .build()
+        .context("Failed to create TLS connector")?;
+
+    let timeout = imap.timeout();
+    let tcp = connect_tcp((imap.host.as_str(), imap.port), timeout)
+        .with_context(|| format!("Failed to connect to {}:{}", imap.host, imap.port))?;
+    tcp.set_read_timeout(Some(timeout))
+        .and_then(|()| tcp.set_write_timeout(Some(timeout)))
+        .context("Failed to set socket timeouts")?;
+    if imap.starttls {
+        starttls(&tcp)?;
+    }
+    let tls_stream = tls
+        .connect(&imap.host, tcp)
+        .context("Failed to establish TLS connection")?;
+    if imap.pin_certificate {
+        let certificate = tls_stream
+            .peer_certificate()
+            .and_then(|cert| cert.map(|cert| cert.to_der()).transpose())
+            .context("Failed to read the server certificate")?
+            .context("The server sent no certificate")?;
+        KnownHosts::open_default()?.check(&format!("{}:{}", imap.host, imap.port), &certificate)?;
+    }
+
+    let stream = WatchdogStream::new(tls_stream, stalled.clone(), timeout);
+    let mut client = imap::Client::new(CountingStream::new(stream, stats.clone()));
+    // After STARTTLS the server sends no second greeting.
+    if !imap.starttls {
+        client
+            .read_greeting()
+            .context("Failed to read server greeting")?;
+    }
+
+    stats.record_command();
+    let session = match &auth {
+        Auth::Password(password) => client.login(&gmail.username, password),
+        Auth::XOAuth2(token) => client.authenticate("XOAUTH2", &XOAuth2Authenticator::new(token)?),
+    }
+    .map_err(|(e, _)| e)
+    .with_context(|| format!("Failed to login to {}", imap.host))?;
+    Ok(session)
+}
+
+/// Reads the greeting and upgrades a plain connection with STARTTLS, leaving
+/// the socket ready for the TLS handshake.
+///
+/// Done by hand because the IMAP library only upgrades into its own stream
+/// type, without the watchdog and transfer counting.
+fn starttls(tcp: &TcpStream) -> Result<()> {
+    let mut reader = BufReader::new(tcp);
+    let mut line = String::new();
+    reader
+        .read_line(&mut line)
+        .context("Failed to read server greeting")?;
+    if !line.starts_with("* OK") {
+        bail!("Unexpected server greeting: {}", line.trim_end());
+    }
+
+    let mut writer = tcp;
+    writer
+        .write_all(b"a0 STARTTLS\r\n")
+        .context("Failed to send STARTTLS")?;
+    loop {
+        line.clear();
+        if reader
+            .read_line(&mut line)
+            .context("Failed to read STARTTLS response")?
+            == 0
+        {
+            bail!("Server closed the connection during STARTTLS");
+        }
+        if let Some(status) = line.strip_prefix("a0 ") {
+            if status.starts_with("OK") {
+                return Ok(());
+            }
+            bail!("Server refused STARTTLS: {}", status.trim_end());
+        }
+    }
+}
+
+/// Connects to the first reachable address of a host, giving up on each
+/// after `timeout`.
+pub(crate) fn connect_tcp(addr: impl ToSocketAddrs, timeout: Duration) -> io::Result<TcpStream> {
+    // A host that cannot be resolved is unreachable, not a missing file.
+    let addrs = addr
+        .to_socket_addrs()
+        .map_err(|e| io::Error::new(io::ErrorKind::HostUnreachable, e))?;
+    let mut last_error = io::Error::new(io::ErrorKind::HostUnreachable, "host has no addresses");
+    for addr in addrs {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+/// Formats UIDs as an IMAP sequence set, runs of consecutive UIDs as
+/// ranges so that many tagged messages still fit in a short command.
+fn uid_set(uids: &[u32]) -> String {
+    let mut sorted = uids.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for uid in sorted {
+        match ranges.last_mut() {
+            Some((_, last)) if *last + 1 == uid => *last = uid,
+            _ => ranges.push((uid, uid)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|&(first, last)| {
+            if first == last {
+                first.to_string()
+            } else {
+                format!("{}:{}", first, last)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Picks the cached UIDs the server no longer lists: those within the
+/// synced range, from its lowest UID up, that are missing from it. Older
+/// messages are left alone, as they were only outside the range fetched.
+/// With nothing synced, the mailbox is empty and every cached UID is gone.
+fn expunged_uids(cached: &[u32], fetched: &FetchedEmails) -> Vec<u32> {
+    let listed = fetched
+        .emails
+        .iter()
+        .map(|email| email._uid)
+        .chain(fetched.failed.iter().copied())
+        .collect::<HashSet<_>>();
+    let lowest = listed.iter().min().copied().unwrap_or(0);
+    cached
+        .iter()
+        .copied()
+        .filter(|uid| *uid >= lowest && !listed.contains(uid))
+        .collect()
+}
+
+/// Takes a data item, such as UNSEEN or HIGHESTMODSEQ, out of the raw
+/// response to a `STATUS` command. Only the parenthesized list is looked
+/// at, so a mailbox named after an item cannot be mistaken for it.
+fn parse_status_item(response: &[u8], item: &str) -> Option<u64> {
+    let response = String::from_utf8_lossy(response);
+    let line = response
+        .lines()
+        .find(|line| line.to_ascii_uppercase().starts_with("* STATUS "))?;
+    let (_, items) = line.trim_end().trim_end_matches(')').rsplit_once('(')?;
+    let mut words = items.split_whitespace();
+    while let Some(name) = words.next() {
+        let value = words.next()?;
+        if name.eq_ignore_ascii_case(item) {
+            return value.parse().ok();
+        }
+    }
+    None
+}
+
+/// Quotes a string for use as an IMAP quoted string argument.
+fn quote_imap_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Returns whether a fetched message has the `\Seen` flag.
+fn is_seen(msg: &imap::types::Fetch) -> bool {
+    msg.flags().iter().any(|f| f == &imap::types::Flag::Seen)
+}
+
+/// Returns whether a fetched message has the `\Flagged` flag.
+fn is_flagged(msg: &imap::types::Fetch) -> bool {
+    msg.flags().iter().any(|f| f == &imap::types::Flag::Flagged)
+}
+
+/// Builds the displayable body from a message's raw source.
+///
+/// Delivery status reports are replaced by a readable summary and returned
+/// parsed alongside it.
+pub(crate) fn parse_message_body(raw: &[u8]) -> MessageBody {
+    let headers = parse_headers(raw)
+        .map(|(headers, _)| {
+            headers
+                .iter()
+                .map(|header| (header.get_key(), header.get_value()))
+                .collect()
+        })
+        .unwrap_or_default();
+    if let Ok(Some(report)) = parse_delivery_report(raw) {
+        return MessageBody {
+            text: report.summary(),
+            report: Some(report),
+            alternatives: None,
+            html: None,
+            headers,
+        };
+    }
+    match message_text(raw) {
+        Ok(text) => MessageBody {
+            text: text.text,
+            report: None,
+            alternatives: text.alternatives,
+            html: text.html,
+            headers,
+        },
+        Err(_) => MessageBody {
+            text: "(Unable to decode message body)".to_string(),
+            report: None,
+            alternatives: None,
+            html: None,
+            headers,
+        },
+    }
+}
+
+/// Decodes header text from an envelope, such as a subject or display name:
+/// RFC 2047 encoded words (`=?ISO-2022-JP?B?...?=`) are decoded from their
+/// charset, and raw 8-bit text is read as UTF-8 or, failing that, Latin-1.
+fn decode_header_text(raw: &[u8]) -> String {
+    let mut header = b"X: ".to_vec();
+    header.extend_from_slice(raw);
+    match parse_header(&header) {
+        Ok((header, _)) => header.get_value(),
+        Err(_) => String::from_utf8_lossy(raw).into_owned(),
+    }
+}
+
+/// Builds an email from a fetch of [`ENVELOPE_ITEMS`].
+///
+/// A message the server sent no envelope for is built from its raw header
+/// instead and marked malformed, so the list still counts it and its source
+/// can be viewed.
+fn parse_email(msg: &imap::types::Fetch) -> Email {
+    let _uid = msg.uid.unwrap_or(0);
+
+    let is_read = is_seen(msg);
+    let is_flagged = is_flagged(msg);
+
+    let Some(envelope) = msg.envelope() else {
+        return Email {
+            _uid,
+            is_read,
+            is_flagged,
+            size: msg.size.unwrap_or(0),
+            ..email_from_header(msg.header().unwrap_or_default())
+        };
+    };
+    let sent = msg.header().and_then(parse_date_from_header);
+    let date = sent.map_or_else(Local::now, |date| date.with_timezone(&Local));
+
+    let subject = envelope
+        .subject
+        .map(decode_header_text)
+        .unwrap_or_else(|| "(no subject)".to_string());
+
+    let from = envelope
+        .from
+        .as_ref()
+        .and_then(|addrs| addrs.first())
+        .map(|addr| {
+            let name = addr.name.map(decode_header_text).unwrap_or_default();
+            let mailbox = addr
+                .mailbox
+                .as_ref()
+                .and_then(|m| std::str::from_utf8(m).ok())
+                .unwrap_or("");
+            let host = addr
+                .host
+                .as_ref()
+                .and_then(|h| std::str::from_utf8(h).ok())
+                .unwrap_or("");
+            let name = if !name.is_empty() { Some(name) } else { None };
+            let email = Some(display_address(mailbox, host));
+            NameAddr { name, email }
+        })
+        .unwrap_or_else(|| NameAddr {
+            name: None,
+            email: None,
+        });
+
+    let to = envelope
+        .to
+        .as_ref()
+        .map(|addrs| {
+            addrs
+                .iter()
+                .map(|addr| {
+                    let name = addr.name.map(decode_header_text).filter(|s| !s.is_empty());
+                    let mailbox = addr
+                        .mailbox
+                        .as_ref()
+                        .and_then(|m| std::str::from_utf8(m).ok())
+                        .unwrap_or("");
+                    let host = addr
+                        .host
+                        .as_ref()
+                        .and_then(|h| std::str::from_utf8(h).ok())
+                        .unwrap_or("");
+                    let email = if !mailbox.is_empty() && !host.is_empty() {
+                        Some(display_address(mailbox, host))
+                    } else {
+                        None
+                    };
+                    NameAddr { name, email }
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_else(Vec::new);
+
+    let cc = envelope
+        .cc
+        .as_ref()
+        .map(|addrs| {
+            addrs
+                .iter()
+                .map(|addr| {
+                    let name = addr.name.map(decode_header_text).filter(|s| !s.is_empty());
+                    let mailbox = addr
+                        .mailbox
+                        .as_ref()
+                        .and_then(|m| std::str::from_utf8(m).ok())
+                        .unwrap_or("");
+                    let host = addr
+                        .host
+                        .as_ref()
+                        .and_then(|h| std::str::from_utf8(h).ok())
+                        .unwrap_or("");
+                    let email = if !mailbox.is_empty() && !host.is_empty() {
+                        Some(display_address(mailbox, host))
+                    } else {
+                        None
+                    };
+                    NameAddr { name, email }
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_else(Vec::new);
+
+    let bcc = envelope
+        .bcc
+        .as_ref()
+        .map(|addrs| {
+            addrs
+                .iter()
+                .map(|addr| {
+                    let name = addr.name.map(decode_header_text).filter(|s| !s.is_empty());
+                    let mailbox = addr
+                        .mailbox
+                        .as_ref()
+                        .and_then(|m| std::str::from_utf8(m).ok())
+                        .unwrap_or("");
+                    let host = addr
+                        .host
+                        .as_ref()
+                        .and_then(|h| std::str::from_utf8(h).ok())
+                        .unwrap_or("");
+                    let email = if !mailbox.is_empty() && !host.is_empty() {
+                        Some(display_address(mailbox, host))
+                    } else {
+                        None
+                    };
+                    NameAddr { name, email }
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_else(Vec::new);
+
+    let message_id = envelope
+        .message_id
+        .and_then(|id| std::str::from_utf8(id).ok())
+        .and_then(|id| parse_message_ids(id).into_iter().next());
+    let in_reply_to = envelope
+        .in_reply_to
+        .and_then(|id| std::str::from_utf8(id).ok())
+        .and_then(|id| parse_message_ids(id).into_iter().next());
+    let references = msg
+        .header()
+        .and_then(|header| parse_headers(header).ok())
+        .and_then(|(headers, _)| headers.get_first_value("References"))
+        .map(|refs| parse_message_ids(&refs))
+        .unwrap_or_default();
+
+    Email {
+        _uid,
+        subject,
+        from,
+        to,
+        cc,
+        bcc,
+        date,
+        is_read,
+        is_flagged,
+        size: msg.size.unwrap_or(0),
+        body: None,
+        message_id,
+        in_reply_to,
+        references,
+        utc_offset: sent.map(|date| date.offset().local_minus_utc()),
+        malformed: false,
+        labels: vec![],
+    }
+}
+
+/// Builds a malformed email from a raw header, for a message without an
+/// envelope. Fields the header lacks or that do not parse are left empty.
+pub(crate) fn email_from_header(header: &[u8]) -> Email {
+    let headers = parse_headers(header)
+        .map(|(headers, _)| headers)
+        .unwrap_or_default();
+    let addresses = |name: &str| {
+        headers
+            .get_first_header(name)
+            .and_then(|header| addrparse_header(header).ok())
+            .map(|list| {
+                list.iter()
+                    .flat_map(|addr| match addr {
+                        MailAddr::Single(info) => vec![info.clone()],
+                        MailAddr::Group(group) => group.addrs.clone(),
+                    })
+                    .map(|info| NameAddr {
+                        name: info.display_name.filter(|name| !name.is_empty()),
+                        email: Some(info.addr).filter(|addr| !addr.is_empty()),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default()
+    };
+    let sent = parse_date_from_header(header);
+    let message_ids = |name: &str| {
+        headers
+            .get_first_value(name)
+            .map(|value| parse_message_ids(&value))
+            .unwrap_or_default()
+    };
+
+    Email {
+        _uid: 0,
+        subject: headers
+            .get_first_value("Subject")
+            .filter(|subject| !subject.trim().is_empty())
+            .unwrap_or_else(|| "(no subject)".to_string()),
+        from: addresses("From").into_iter().next().unwrap_or(NameAddr {
+            name: None,
+            email: None,
+        }),
+        to: addresses("To"),
+        cc: addresses("Cc"),
+        bcc: addresses("Bcc"),
+        date: sent.map_or_else(Local::now, |date| date.with_timezone(&Local)),
+        is_read: false,
+        is_flagged: false,
+        size: 0,
+        body: None,
+        message_id: message_ids("Message-ID").into_iter().next(),
+        in_reply_to: message_ids("In-Reply-To").into_iter().next(),
+        references: message_ids("References"),
+        utc_offset: sent.map(|date| date.offset().local_minus_utc()),
+        malformed: true,
+        labels: vec![],
+    }
+}
+
+/// Parses date from email header bytes using multiple date formats.
+///
+/// Attempts to parse RFC2822 format first, then falls back to a common
+/// alternative format if that fails. The date keeps the sender's offset.
+fn parse_date_from_header(header: &[u8]) -> Option<DateTime<FixedOffset>> {
+    let mail = parse_mail(header).ok()?;
+
+    for header in mail.headers {
+        if header.get_key().eq_ignore_ascii_case("date") {
+            let date_str = header.get_value();
+            if let Ok(date) = DateTime::parse_from_rfc2822(&date_str) {
+                return Some(date);
+            }
+
+            if let Ok(date) =
+                chrono::DateTime::parse_from_str(&date_str, "%a, %d %b %Y %H:%M:%S %z")
+            {
+                return Some(date);
+            }
+        }
+    }
+
+    None
+}
+
+/// Extracts the `<...>` message IDs from a header value such as References.
+fn parse_message_ids(value: &str) -> Vec<String> {
+    value
+        .split('<')
+        .skip(1)
+        .filter_map(|part| part.split_once('>'))
+        .map(|(id, _)| format!("<{}>", id.trim()))
+        .collect()
+}
+
+/// Returns a read message with the UID from `from`, dated now, for tests to
+/// adjust with struct update syntax.
+#[cfg(test)]
+pub(crate) fn test_email(uid: u32, from: &str) -> Email {
+    Email {
+        _uid: uid,
+        subject: format!("Email {}", uid),
+        from: NameAddr {
+            name: None,
+            email: Some(from.to_string()),
+        },
+        date: Local::now(),
+        is_read: true,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Runs a server answering the greeting and STARTTLS with `reply`, and
+    /// returns what the client sent.
+    fn starttls_with(reply: &'static str) -> (Result<()>, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"* OK Dovecot ready.\r\n").unwrap();
+            let mut received = [0; 64];
+            let n = stream.read(&mut received).unwrap();
+            stream.write_all(reply.as_bytes()).unwrap();
+            String::from_utf8_lossy(&received[..n]).into_owned()
+        });
+
+        let tcp = TcpStream::connect(addr).unwrap();
+        let result = starttls(&tcp);
+        (result, server.join().unwrap())
+    }
+
+    #[test]
+    fn test_starttls() {
+        let (result, sent) = starttls_with("a0 OK Begin TLS negotiation now.\r\n");
+        result.unwrap();
+        assert_eq!(sent, "a0 STARTTLS\r\n");
+
+        let (result, _) = starttls_with("* BYE going away\r\na0 NO TLS unavailable\r\n");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Server refused STARTTLS: NO TLS unavailable"
+        );
+    }
+
+    #[test]
+    fn test_email_from_header() {
+        let header = b"From: =?UTF-8?Q?J=C3=B6rg?= <jorg@example.com>\r\n\
+To: a@example.com, Team: b@example.com, c@example.com;\r\n\
+Subject: Broken envelope\r\n\
+Date: Mon, 1 Jan 2024 10:00:00 +0000\r\n\
+In-Reply-To: <parent@example.com>\r\n\r\n";
+        let email = email_from_header(header);
+        assert!(email.malformed);
+        assert_eq!(email.subject, "Broken envelope");
+        assert_eq!(email.from.to_string(), "Jörg <jorg@example.com>");
+        let to = email
+            .to
+            .iter()
+            .map(|addr| addr.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(to, ["a@example.com", "b@example.com", "c@example.com"]);
+        assert_eq!(email.date.timestamp(), 1704103200);
+        assert_eq!(email.in_reply_to.as_deref(), Some("<parent@example.com>"));
+
+        // Without a header, the message is still listed.
+        let email = email_from_header(b"");
+        assert_eq!(email.subject, "(no subject)");
+        assert_eq!(email.from.email, None);
+    }
+
+    #[test]
+    fn test_parse_message_ids() {
+        assert_eq!(
+            parse_message_ids("<a@x.com>\r\n <b@y.com> (comment) <c@z>"),
+            ["<a@x.com>", "<b@y.com>", "<c@z>"]
+        );
+        assert!(parse_message_ids("").is_empty());
+        assert!(parse_message_ids("no ids here").is_empty());
+    }
+
+    #[test]
+    fn test_decode_header_text() {
+        assert_eq!(
+            decode_header_text(b"=?ISO-2022-JP?B?GyRCRnxLXDhsGyhC?= news"),
+            "日本語 news"
+        );
+        assert_eq!(
+            decode_header_text(b"=?utf-8?Q?Caf=C3=A9?= =?utf-8?Q?_au_lait?="),
+            "Café au lait"
+        );
+        assert_eq!(decode_header_text("Grüße".as_bytes()), "Grüße");
+        assert_eq!(decode_header_text(b"Plain subject"), "Plain subject");
+    }
+
+    #[test]
+    fn test_parse_date_from_header_rfc2822() {
+        let header = b"Date: Wed, 15 Jan 2025 10:30:45 +0900\r\n\r\n";
+        let result = parse_date_from_header(header);
+        assert!(result.is_some());
+        let date = result.unwrap();
+        assert_eq!(
+            date.format("%Y/%m/%d %H:%M").to_string(),
+            "2025/01/15 10:30"
+        );
+        assert_eq!(date.offset().local_minus_utc(), 9 * 3600);
+    }
+
+    #[test]
+    fn test_uid_set() {
+        assert_eq!(uid_set(&[7]), "7");
+        assert_eq!(uid_set(&[5, 3, 4, 9, 1, 4]), "1,3:5,9");
+    }
+
+    #[test]
+    fn test_expunged_uids() {
+        let fetched = FetchedEmails {
+            emails: [5, 8]
+                .map(|uid| Email {
+                    _uid: uid,
+                    ..email_from_header(b"")
+                })
+                .into(),
+            failed: vec![9],
+        };
+        // 2 and 3 are only older than the range synced.
+        assert_eq!(expunged_uids(&[2, 3, 5, 6, 7, 8, 9], &fetched), [6, 7]);
+        assert_eq!(expunged_uids(&[2, 3], &FetchedEmails::default()), [2, 3]);
+    }
+
+    #[test]
+    fn test_parse_status_item() {
+        let response = b"* STATUS \"INBOX\" (HIGHESTMODSEQ 7011231777)\r\n";
+        assert_eq!(
+            parse_status_item(response, "HIGHESTMODSEQ"),
+            Some(7011231777)
+        );
+        assert_eq!(
+            parse_status_item(b"* STATUS Sent (highestmodseq 12)\r\n", "HIGHESTMODSEQ"),
+            Some(12)
+        );
+        assert_eq!(
+            parse_status_item(b"* STATUS INBOX (MESSAGES 3)\r\n", "HIGHESTMODSEQ"),
+            None
+        );
+
+        let response = b"* STATUS \"UNSEEN 9 (old)\" (MESSAGES 12 UNSEEN 4)\r\n";
+        assert_eq!(parse_status_item(response, "UNSEEN"), Some(4));
+        let response = b"* 3 EXISTS\r\n* STATUS \"[Gmail]/Sent Mail\" (UNSEEN 0)\r\n";
+        assert_eq!(parse_status_item(response, "UNSEEN"), Some(0));
+    }
+
+    #[test]
+    fn test_quote_imap_string() {
+        assert_eq!(quote_imap_string("from:foo"), r#""from:foo""#);
+        assert_eq!(
+            quote_imap_string(r#"subject:"a b" \x"#),
+            r#""subject:\"a b\" \\x""#
+        );
+    }
+
+    #[test]
+    fn test_parse_date_from_header_invalid() {
+        let header = b"Date: Invalid Date Format\r\n\r\n";
+        let result = parse_date_from_header(header);
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_parse_date_from_header_missing() {
+        let header = b"Subject: Test Subject\r\n\r\n";
+        let result = parse_date_from_header(header);
+        assert!(result.is_none());
+    }
+}
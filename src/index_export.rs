@@ -0,0 +1,191 @@
+//! Exporting the email list as CSV or JSON.
+//!
+//! `:export-index` writes the messages currently listed, after any search,
+//! limit, or filter, one record each, for reporting and ad-hoc analysis of
+//! mail volume. `rutt fetch --json` prints the same records for scripts.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+
+use crate::imap_client::Email;
+
+/// Columns of the CSV output, in order.
+const CSV_HEADER: &str = "date,from,subject,flags,uid,size";
+
+/// Output format of an exported index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexFormat {
+    /// Comma-separated values with a header row.
+    Csv,
+    /// A JSON array of objects.
+    Json,
+}
+
+impl IndexFormat {
+    /// Picks the format from a file's extension: JSON for `.json`, CSV
+    /// otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => IndexFormat::Json,
+            _ => IndexFormat::Csv,
+        }
+    }
+}
+
+/// One message of an exported index.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IndexEntry {
+    /// Date in RFC 3339 format.
+    pub date: String,
+    /// Sender as shown in the detail view.
+    pub from: String,
+    /// Subject line.
+    pub subject: String,
+    /// Flags set on the message: `seen`, `flagged`, `important`.
+    pub flags: Vec<&'static str>,
+    /// UID in its folder.
+    pub uid: u32,
+    /// Size in bytes.
+    pub size: u32,
+}
+
+impl IndexEntry {
+    /// Builds the entry of a message. Importance comes from Gmail's markers,
+    /// which are not part of the email itself.
+    pub fn new(email: &Email, important: bool) -> Self {
+        let flags = [
+            (email.is_read, "seen"),
+            (email.is_flagged, "flagged"),
+            (important, "important"),
+        ]
+        .into_iter()
+        .filter_map(|(set, name)| set.then_some(name))
+        .collect();
+        IndexEntry {
+            date: email.date.to_rfc3339(),
+            from: email.from.to_string(),
+            subject: email.subject.clone(),
+            flags,
+            uid: email._uid,
+            size: email.size,
+        }
+    }
+}
+
+/// Writes index entries in the given format.
+pub fn write_index<W: Write>(
+    mut writer: W,
+    format: IndexFormat,
+    entries: &[IndexEntry],
+) -> Result<()> {
+    match format {
+        IndexFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, entries)?;
+            writeln!(writer)?;
+        }
+        IndexFormat::Csv => {
+            writeln!(writer, "{}", CSV_HEADER)?;
+            for entry in entries {
+                writeln!(
+                    writer,
+                    "{},{},{},{},{},{}",
+                    csv_field(&entry.date),
+                    csv_field(&entry.from),
+                    csv_field(&entry.subject),
+                    entry.flags.join(" "),
+                    entry.uid,
+                    entry.size
+                )?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Quotes a CSV field when it contains a separator, quote, or line break.
+///
+/// A field starting like a formula, such as a subject `=HYPERLINK(...)`, gets
+/// a `'` in front, so a spreadsheet opening the file shows it as text rather
+/// than evaluating it.
+pub(crate) fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(subject: &str, flags: Vec<&'static str>) -> IndexEntry {
+        IndexEntry {
+            date: "2024-03-01T09:30:00+00:00".to_string(),
+            from: "Alice <alice@example.com>".to_string(),
+            subject: subject.to_string(),
+            flags,
+            uid: 42,
+            size: 2048,
+        }
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            IndexFormat::from_path(Path::new("mail.JSON")),
+            IndexFormat::Json
+        );
+        assert_eq!(
+            IndexFormat::from_path(Path::new("mail.csv")),
+            IndexFormat::Csv
+        );
+        assert_eq!(IndexFormat::from_path(Path::new("mail")), IndexFormat::Csv);
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let entries = [
+            entry("Lunch, \"maybe\"", vec!["seen", "flagged"]),
+            entry("Hi", vec![]),
+        ];
+        let mut out = Vec::new();
+        write_index(&mut out, IndexFormat::Csv, &entries).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "date,from,subject,flags,uid,size\n\
+             2024-03-01T09:30:00+00:00,Alice <alice@example.com>,\"Lunch, \"\"maybe\"\"\",seen flagged,42,2048\n\
+             2024-03-01T09:30:00+00:00,Alice <alice@example.com>,Hi,,42,2048\n"
+        );
+    }
+
+    #[test]
+    fn test_csv_field_formula() {
+        assert_eq!(csv_field("=1+1"), "'=1+1");
+        assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(
+            csv_field("=HYPERLINK(\"http://x\")"),
+            "\"'=HYPERLINK(\"\"http://x\"\")\""
+        );
+        assert_eq!(csv_field("-- lunch"), "'-- lunch");
+        assert_eq!(csv_field("Re: =1"), "Re: =1");
+    }
+
+    #[test]
+    fn test_write_json() {
+        let mut out = Vec::new();
+        write_index(&mut out, IndexFormat::Json, &[entry("Hi", vec!["seen"])]).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(value[0]["subject"], "Hi");
+        assert_eq!(value[0]["flags"], serde_json::json!(["seen"]));
+        assert_eq!(value[0]["uid"], 42);
+    }
+}
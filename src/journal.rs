@@ -0,0 +1,360 @@
+//! Write-ahead journal of operations on messages.
+//!
+//! Every flag change, move and label change is appended to the journal, and
+//! synced to disk, before it is sent to the server, and marked done once the
+//! server answered. Operations still pending at startup were cut short by a
+//! crash or power loss, or made offline and never replayed; they are listed
+//! and replayed if the user agrees. Entries carry the account and the
+//! mailbox's UIDVALIDITY, so none is replayed on other messages after the
+//! server reset the mailbox. Sending mail is covered by the outbox, where
+//! drafts are written before they are sent.
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::cache::encode_name;
+use crate::imap_client::ImapClient;
+use crate::utils::data_dir;
+
+/// An operation on messages of a mailbox.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum JournalOp {
+    MarkRead,
+    MarkUnread,
+    Flag,
+    Unflag,
+    /// Move into All Mail, or the `Archive` folder on other servers.
+    Archive,
+    /// Move into a folder, such as a dated archive folder.
+    MoveTo {
+        folder: String,
+    },
+    Trash,
+    AddLabel {
+        label: String,
+    },
+    RemoveLabel {
+        label: String,
+    },
+}
+
+impl JournalOp {
+    /// Performs the operation on messages of the client's current mailbox.
+    pub fn apply(&self, client: &mut ImapClient, uids: &[u32]) -> Result<()> {
+        match self {
+            JournalOp::MarkRead => client.set_seen(uids, true),
+            JournalOp::MarkUnread => client.set_seen(uids, false),
+            JournalOp::Flag => client.set_flagged(uids, true),
+            JournalOp::Unflag => client.set_flagged(uids, false),
+            JournalOp::Archive => client.archive(uids),
+            JournalOp::MoveTo { folder } => client.move_to(uids, folder),
+            JournalOp::Trash => client.trash(uids),
+            JournalOp::AddLabel { label } => client.add_label(uids, label),
+            JournalOp::RemoveLabel { label } => client.remove_label(uids, label),
+        }
+    }
+}
+
+/// An operation recorded in the journal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Identifies the entry when it is marked done.
+    pub id: u64,
+    /// Account the operation was made on, as `user@host`.
+    #[serde(default)]
+    pub account: String,
+    /// Mailbox the messages are in.
+    pub mailbox: String,
+    /// UIDVALIDITY of the mailbox when the operation was made, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid_validity: Option<u32>,
+    /// UIDs of the messages.
+    pub uids: Vec<u32>,
+    #[serde(flatten)]
+    pub op: JournalOp,
+}
+
+impl JournalEntry {
+    /// Performs the operation on the server, in the entry's mailbox.
+    ///
+    /// Fails without touching any message when the mailbox was reset since
+    /// the operation was recorded, as its UIDs now name other messages.
+    pub fn apply(&self, client: &mut ImapClient) -> Result<()> {
+        if let Some(recorded) = self.uid_validity
+            && client.mailbox_uid_validity(&self.mailbox)? != Some(recorded)
+        {
+            bail!("{} was reset since", self.mailbox);
+        }
+        let previous = client.mailbox().to_string();
+        client.set_mailbox(&self.mailbox);
+        let result = self.op.apply(client, &self.uids);
+        client.set_mailbox(&previous);
+        result
+    }
+}
+
+impl fmt::Display for JournalEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match &self.op {
+            JournalOp::MarkRead => "mark read".to_string(),
+            JournalOp::MarkUnread => "mark unread".to_string(),
+            JournalOp::Flag => "flag".to_string(),
+            JournalOp::Unflag => "unflag".to_string(),
+            JournalOp::Archive => "archive".to_string(),
+            JournalOp::MoveTo { folder } => format!("move to {}", folder),
+            JournalOp::Trash => "move to the trash".to_string(),
+            JournalOp::AddLabel { label } => format!("label {}", label),
+            JournalOp::RemoveLabel { label } => format!("remove label {}", label),
+        };
+        let count = match self.uids.len() {
+            1 => "1 message".to_string(),
+            n => format!("{} messages", n),
+        };
+        write!(f, "{}: {} in {}", action, count, self.mailbox)
+    }
+}
+
+/// A line of the journal file: an operation, or the id of one done.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Record {
+    Done { done: u64 },
+    Entry(JournalEntry),
+}
+
+/// An append-only journal file, one JSON record per line.
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    /// Account operations are recorded for, as `user@host`.
+    account: String,
+    next_id: u64,
+}
+
+impl Journal {
+    /// Opens a journal of an account's operations stored at the given path.
+    pub fn new<P: AsRef<Path>>(path: P, account: &str) -> Self {
+        let mut journal = Journal {
+            path: path.as_ref().to_path_buf(),
+            account: account.to_string(),
+            next_id: 1,
+        };
+        // Ids continue after every one in the file, as a new entry taking
+        // the id of one done would be read as done too.
+        if let Ok(records) = journal.records() {
+            journal.next_id = records
+                .iter()
+                .map(|record| match record {
+                    Record::Done { done: id } | Record::Entry(JournalEntry { id, .. }) => id + 1,
+                })
+                .max()
+                .unwrap_or(1);
+        }
+        journal
+    }
+
+    /// Opens the journal of an account in the default application data
+    /// directory, one file per account.
+    pub fn open_default(account: &str) -> Self {
+        let file = format!("{}.jsonl", encode_name(account));
+        Self::new(data_dir().join("journal").join(file), account)
+    }
+
+    /// Records an operation before it is performed, returning its entry.
+    /// `uid_validity` is that of the mailbox, so that a replay can tell when
+    /// the UIDs no longer name the same messages.
+    ///
+    /// The record is synced to disk before returning, so it survives a
+    /// crash or power loss.
+    pub fn record(
+        &mut self,
+        mailbox: &str,
+        uid_validity: Option<u32>,
+        uids: &[u32],
+        op: JournalOp,
+    ) -> Result<JournalEntry> {
+        let entry = JournalEntry {
+            id: self.next_id,
+            account: self.account.clone(),
+            mailbox: mailbox.to_string(),
+            uid_validity,
+            uids: uids.to_vec(),
+            op,
+        };
+        self.append(&Record::Entry(entry.clone()))?;
+        self.next_id += 1;
+        Ok(entry)
+    }
+
+    /// Marks an operation done. Once none is pending, the file is emptied.
+    pub fn complete(&self, id: u64) -> Result<()> {
+        self.append(&Record::Done { done: id })?;
+        if self.unfinished()?.is_empty() {
+            self.clear()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the operations of the account not marked done, oldest first.
+    /// Those recorded before entries named their account are included.
+    pub fn pending(&self) -> Result<Vec<JournalEntry>> {
+        let mut entries = self.unfinished()?;
+        entries.retain(|entry| entry.account.is_empty() || entry.account == self.account);
+        Ok(entries)
+    }
+
+    /// Returns the operations of any account not marked done.
+    fn unfinished(&self) -> Result<Vec<JournalEntry>> {
+        let mut entries = Vec::new();
+        let mut done = HashSet::new();
+        for record in self.records()? {
+            match record {
+                Record::Entry(entry) => entries.push(entry),
+                Record::Done { done: id } => {
+                    done.insert(id);
+                }
+            }
+        }
+        entries.retain(|entry| !done.contains(&entry.id));
+        Ok(entries)
+    }
+
+    /// Reads every record in the file.
+    ///
+    /// A line cut short by a crash while it was written is skipped.
+    fn records(&self) -> Result<Vec<Record>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read journal {:?}", self.path));
+            }
+        };
+        Ok(contents
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+
+    /// Forgets every operation, pending or done.
+    pub fn clear(&self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to clear journal {:?}", self.path))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Appends a record and syncs it to disk.
+    fn append(&self, record: &Record) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create journal directory {:?}", dir))?;
+        }
+        let mut file = File::options()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open journal {:?}", self.path))?;
+        // Start a new line after one cut short by a crash.
+        let mut last = [b'\n'];
+        if file.metadata()?.len() > 0 {
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+        }
+        let mut line = if last[0] == b'\n' {
+            String::new()
+        } else {
+            "\n".to_string()
+        };
+        line += &serde_json::to_string(record).context("Failed to serialize journal")?;
+        line.push('\n');
+        file.write_all(line.as_bytes())
+            .and_then(|()| file.sync_data())
+            .with_context(|| format!("Failed to write journal {:?}", self.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const ACCOUNT: &str = "me@imap.gmail.com";
+
+    #[test]
+    fn test_pending_until_complete() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("journal.jsonl");
+        let mut journal = Journal::new(&path, ACCOUNT);
+        let read = journal
+            .record("INBOX", Some(5), &[1, 2], JournalOp::MarkRead)
+            .unwrap();
+        let moved = journal
+            .record(
+                "INBOX",
+                Some(5),
+                &[3],
+                JournalOp::MoveTo {
+                    folder: "Archive/2024".to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            moved.to_string(),
+            "move to Archive/2024: 1 message in INBOX"
+        );
+
+        journal.complete(read.id).unwrap();
+        // A record cut short by a crash is skipped.
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"id\":9,\"mail")
+            .unwrap();
+
+        let mut reopened = Journal::new(&path, ACCOUNT);
+        assert_eq!(reopened.pending().unwrap(), std::slice::from_ref(&moved));
+        let next = reopened
+            .record("Work", None, &[4], JournalOp::Flag)
+            .unwrap();
+        assert_eq!(next.id, moved.id + 1);
+
+        reopened.complete(moved.id).unwrap();
+        reopened.complete(next.id).unwrap();
+        assert!(reopened.pending().unwrap().is_empty());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_ids_continue_after_done_entries() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("journal.jsonl");
+        let mut journal = Journal::new(&path, ACCOUNT);
+        let first = journal
+            .record("INBOX", Some(5), &[1], JournalOp::Trash)
+            .unwrap();
+        let other = Journal::new(&path, "you@imap.example.com")
+            .record("INBOX", Some(7), &[1], JournalOp::Trash)
+            .unwrap();
+        journal.complete(first.id).unwrap();
+
+        // Another account's entry is kept, but not replayed here.
+        assert!(journal.pending().unwrap().is_empty());
+        let mut reopened = Journal::new(&path, ACCOUNT);
+        let next = reopened
+            .record("INBOX", Some(5), &[2], JournalOp::Trash)
+            .unwrap();
+        assert!(next.id > other.id);
+        assert_eq!(reopened.pending().unwrap(), [next]);
+    }
+}
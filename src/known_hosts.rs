@@ -0,0 +1,182 @@
+//! Trust-on-first-use pinning of IMAP server certificates, when turned on.
+//!
+//! The first time rutt connects to a server, the SHA-256 fingerprint of the
+//! certificate it presents is recorded in `known_hosts.toml` in the data
+//! directory. Later connections presenting a different certificate fail with
+//! [`CertificateChanged`] until the new one is accepted, which protects
+//! against interception by anyone holding a certificate from a trusted CA.
+//!
+//! Only the server's own certificate can be pinned, as the TLS library does
+//! not expose the rest of the chain. Providers such as Gmail replace theirs
+//! every few weeks, each time asking for the new one to be accepted, so
+//! pinning is off unless `pin_certificate` is set.
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::utils::data_dir;
+
+/// Certificate fingerprints recorded per server.
+#[derive(Debug)]
+pub struct KnownHosts {
+    path: PathBuf,
+    /// Fingerprints keyed by `host:port`.
+    hosts: BTreeMap<String, String>,
+}
+
+/// A server presented a certificate other than the one recorded for it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CertificateChanged {
+    /// The server, as `host:port`.
+    pub host: String,
+    /// Fingerprint recorded on an earlier connection.
+    pub known: String,
+    /// Fingerprint of the certificate presented now.
+    pub presented: String,
+}
+
+impl fmt::Display for CertificateChanged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The certificate of {} has changed (known {}, presented {}). Someone may be \
+             intercepting the connection; if the server renewed its certificate, start rutt \
+             in a terminal to review and accept it.",
+            self.host, self.known, self.presented
+        )
+    }
+}
+
+impl std::error::Error for CertificateChanged {}
+
+impl KnownHosts {
+    /// Opens the known hosts file, which need not exist yet.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let hosts = match fs::read_to_string(&path) {
+            Ok(contents) => {
+                toml::from_str(&contents).with_context(|| format!("Failed to parse {:?}", path))?
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+        };
+        Ok(KnownHosts { path, hosts })
+    }
+
+    /// Opens the known hosts file in the default application data directory.
+    pub fn open_default() -> Result<Self> {
+        Self::open(data_dir().join("known_hosts.toml"))
+    }
+
+    /// Checks the certificate a server presented against the one recorded
+    /// for it, recording it if the server is new.
+    pub fn check(&mut self, host: &str, certificate: &[u8]) -> Result<()> {
+        let presented = fingerprint(certificate);
+        match self.hosts.get(host) {
+            Some(known) if *known == presented => Ok(()),
+            Some(known) => Err(CertificateChanged {
+                host: host.to_string(),
+                known: known.clone(),
+                presented,
+            }
+            .into()),
+            None => self.trust(host, presented),
+        }
+    }
+
+    /// Records a fingerprint as the trusted one for a server.
+    pub fn trust(&mut self, host: &str, fingerprint: String) -> Result<()> {
+        self.hosts.insert(host.to_string(), fingerprint);
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+        }
+        let contents = toml::to_string(&self.hosts).context("Failed to serialize known hosts")?;
+        // Write to a temporary file first so an interrupted write never
+        // leaves the recorded fingerprints truncated.
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, contents).with_context(|| format!("Failed to write {:?}", tmp))?;
+        fs::rename(&tmp, &self.path).with_context(|| format!("Failed to write {:?}", self.path))
+    }
+}
+
+/// Warns about a changed certificate on the terminal and asks whether to
+/// trust the new one, recording it if so.
+pub fn confirm_certificate_change(changed: &CertificateChanged) -> Result<bool> {
+    let mut stderr = io::stderr();
+    writeln!(
+        stderr,
+        "\nWARNING: THE CERTIFICATE OF {} HAS CHANGED!\n\
+         Someone may be intercepting the connection, or the server renewed its certificate.\n\
+         Known:     {}\n\
+         Presented: {}",
+        changed.host, changed.known, changed.presented
+    )?;
+    write!(stderr, "Accept the new certificate? (y/N): ")?;
+    stderr.flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    if !answer.trim().eq_ignore_ascii_case("y") {
+        return Ok(false);
+    }
+    KnownHosts::open_default()?.trust(&changed.host, changed.presented.clone())?;
+    Ok(true)
+}
+
+/// Formats the SHA-256 fingerprint of a DER certificate the way
+/// `openssl x509 -fingerprint -sha256` does.
+pub fn fingerprint(certificate: &[u8]) -> String {
+    let hex = Sha256::digest(certificate)
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(":");
+    format!("SHA256:{}", hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint() {
+        assert_eq!(
+            fingerprint(b"abc"),
+            "SHA256:BA:78:16:BF:8F:01:CF:EA:41:41:40:DE:5D:AE:22:23:\
+             B0:03:61:A3:96:17:7A:9C:B4:10:FF:61:F2:00:15:AD"
+        );
+    }
+
+    #[test]
+    fn test_trust_on_first_use() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rutt").join("known_hosts.toml");
+
+        let mut hosts = KnownHosts::open(&path).unwrap();
+        hosts.check("imap.example.com:993", b"first").unwrap();
+
+        // The fingerprint is kept across runs.
+        let mut hosts = KnownHosts::open(&path).unwrap();
+        hosts.check("imap.example.com:993", b"first").unwrap();
+        let err = hosts.check("imap.example.com:993", b"second").unwrap_err();
+        let changed = err.downcast_ref::<CertificateChanged>().unwrap();
+        assert_eq!(changed.known, fingerprint(b"first"));
+        assert_eq!(changed.presented, fingerprint(b"second"));
+
+        // Other servers are pinned separately.
+        hosts.check("imap.example.com:143", b"second").unwrap();
+
+        hosts
+            .trust("imap.example.com:993", fingerprint(b"second"))
+            .unwrap();
+        let mut hosts = KnownHosts::open(&path).unwrap();
+        hosts.check("imap.example.com:993", b"second").unwrap();
+        // Written in place of the old file, not over it.
+        assert!(!path.with_extension("tmp").exists());
+    }
+}
@@ -0,0 +1,218 @@
+//! Reads Gmail's `X-GM-LABELS` from raw `FETCH` responses, which the IMAP
+//! library cannot parse.
+
+use std::collections::HashMap;
+
+/// Returns the labels of each message in the untagged `FETCH` responses to
+/// `UID FETCH <set> (UID X-GM-LABELS)`, by UID.
+///
+/// System labels such as `\Inbox` and `\Important` are left out, as the
+/// folder and flags already show them.
+pub(crate) fn parse_label_fetches(response: &[u8]) -> HashMap<u32, Vec<String>> {
+    let mut labels = HashMap::new();
+    let mut rest = response;
+    while let Some(start) = find(rest, b"FETCH (") {
+        let mut parser = Parser {
+            input: &rest[start + b"FETCH (".len()..],
+            pos: 0,
+        };
+        if let (Some(uid), Some(list)) = parser.attributes() {
+            let list = list
+                .into_iter()
+                .filter(|label| !label.starts_with('\\'))
+                .collect();
+            labels.insert(uid, list);
+        }
+        rest = &parser.input[parser.pos..];
+    }
+    labels
+}
+
+/// Returns the position of a byte string in another.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// A cursor over the attributes of one `FETCH` response.
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    /// Reads attribute names and values up to the closing parenthesis,
+    /// returning the UID and labels among them.
+    fn attributes(&mut self) -> (Option<u32>, Option<Vec<String>>) {
+        let (mut uid, mut labels) = (None, None);
+        loop {
+            self.skip_spaces();
+            match self.peek() {
+                None => break,
+                Some(b')') => {
+                    self.advance(1);
+                    break;
+                }
+                Some(_) => {}
+            }
+            let name = self.atom().to_ascii_uppercase();
+            if name.is_empty() {
+                // Not an attribute list after all.
+                break;
+            }
+            self.skip_spaces();
+            match name.as_str() {
+                "UID" => uid = self.atom().parse().ok(),
+                "X-GM-LABELS" => labels = Some(self.list()),
+                _ => {
+                    self.list();
+                }
+            }
+        }
+        (uid, labels)
+    }
+
+    /// Reads a parenthesized list of strings, or a single value as a list
+    /// of one.
+    fn list(&mut self) -> Vec<String> {
+        if self.peek() != Some(b'(') {
+            return self.string().into_iter().collect();
+        }
+        self.advance(1);
+        let mut items = Vec::new();
+        loop {
+            self.skip_spaces();
+            match self.peek() {
+                None => break,
+                Some(b')') => {
+                    self.advance(1);
+                    break;
+                }
+                Some(b'(') => {
+                    // Nested lists hold no labels.
+                    self.list();
+                }
+                Some(_) => {
+                    let start = self.pos;
+                    items.extend(self.string());
+                    // Skip a stray line break rather than looping on it.
+                    if self.pos == start {
+                        self.advance(1);
+                    }
+                }
+            }
+        }
+        items
+    }
+
+    /// Reads a quoted string, a literal, or an atom.
+    fn string(&mut self) -> Option<String> {
+        match self.peek()? {
+            b'"' => Some(self.quoted()),
+            b'{' => Some(self.literal()),
+            _ => {
+                let atom = self.atom();
+                (!atom.is_empty() && !atom.eq_ignore_ascii_case("NIL")).then_some(atom)
+            }
+        }
+    }
+
+    fn quoted(&mut self) -> String {
+        self.advance(1);
+        let mut bytes = Vec::new();
+        while let Some(b) = self.peek() {
+            self.advance(1);
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    bytes.extend(self.peek());
+                    self.advance(1);
+                }
+                _ => bytes.push(b),
+            }
+        }
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    /// Reads `{n}` and the line break after it, then the next `n` bytes.
+    fn literal(&mut self) -> String {
+        self.advance(1);
+        let len = self.atom_until(b'}').parse::<usize>().unwrap_or(0);
+        self.advance(1);
+        if self.input[self.pos..].starts_with(b"\r\n") {
+            self.advance(2);
+        }
+        let end = (self.pos + len).min(self.input.len());
+        let text = String::from_utf8_lossy(&self.input[self.pos..end]).into_owned();
+        self.pos = end;
+        text
+    }
+
+    fn atom(&mut self) -> String {
+        let start = self.pos;
+        while let Some(b) = self.peek() {
+            if matches!(b, b' ' | b'(' | b')' | b'\r' | b'\n') {
+                break;
+            }
+            self.advance(1);
+        }
+        String::from_utf8_lossy(&self.input[start..self.pos]).into_owned()
+    }
+
+    fn atom_until(&mut self, end: u8) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(|b| b != end) {
+            self.advance(1);
+        }
+        String::from_utf8_lossy(&self.input[start..self.pos]).into_owned()
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek() == Some(b' ') {
+            self.advance(1);
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    /// Moves past `n` bytes, stopping at the end of a response cut short.
+    fn advance(&mut self, n: usize) {
+        self.pos = (self.pos + n).min(self.input.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_label_fetches() {
+        let response =
+            b"* 1 FETCH (X-GM-LABELS (\\Inbox \\Important Work \"Travel plans\") UID 12)\r\n\
+            * 2 FETCH (UID 13 X-GM-LABELS ())\r\n\
+            * 3 FETCH (UID 14 X-GM-LABELS ({5}\r\nR(x)y \"Say \\\"hi\\\"\"))\r\n";
+        let labels = parse_label_fetches(response);
+        assert_eq!(labels[&12], ["Work", "Travel plans"]);
+        assert!(labels[&13].is_empty());
+        assert_eq!(labels[&14], ["R(x)y", "Say \"hi\""]);
+        assert_eq!(labels.len(), 3);
+    }
+
+    #[test]
+    fn test_parse_truncated_label_fetches() {
+        for response in [
+            &b"* 1 FETCH (UID 12 X-GM-LABELS ({5"[..],
+            b"* 1 FETCH (UID 12 X-GM-LABELS ({5}\r\nWo",
+            b"* 1 FETCH (UID 12 X-GM-LABELS (\"Wo\\",
+            b"* 1 FETCH (UID 12 X-GM-LABELS (Work",
+        ] {
+            let labels = parse_label_fetches(response);
+            assert!(labels.len() <= 1);
+        }
+        let labels = parse_label_fetches(b"* 1 FETCH (UID 12 X-GM-LABELS ({5}\r\nWo");
+        assert_eq!(labels[&12], ["Wo"]);
+    }
+}
@@ -0,0 +1,202 @@
+//! Language and writing direction of message text.
+//!
+//! Terminals lay text out left to right, so Arabic or Hebrew lines printed
+//! as stored come out scrambled. Lines whose first strong character is
+//! right-to-left are put in display order with the Unicode Bidirectional
+//! Algorithm here, wrapped first so each screen line reads correctly. The
+//! language of a message is detected from its text, for reply templates
+//! and the editor's spell checker.
+
+use std::ops::Range;
+use unicode_bidi::{Direction, ParagraphBidiInfo, get_base_direction};
+use unicode_width::UnicodeWidthStr;
+use whatlang::Lang;
+
+/// A language detected in message text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Language(Lang);
+
+impl Language {
+    /// Returns the ISO 639-3 code, e.g. "heb".
+    pub fn code(&self) -> &'static str {
+        self.0.code()
+    }
+
+    /// Returns the ISO 639-1 code, e.g. "he", which translators take.
+    pub fn short_code(&self) -> &'static str {
+        match self.0 {
+            Lang::Afr => "af",
+            Lang::Aka => "ak",
+            Lang::Amh => "am",
+            Lang::Ara => "ar",
+            Lang::Aze => "az",
+            Lang::Bel => "be",
+            Lang::Ben => "bn",
+            Lang::Bul => "bg",
+            Lang::Cat => "ca",
+            Lang::Ces => "cs",
+            Lang::Cmn => "zh",
+            Lang::Dan => "da",
+            Lang::Deu => "de",
+            Lang::Ell => "el",
+            Lang::Eng => "en",
+            Lang::Epo => "eo",
+            Lang::Est => "et",
+            Lang::Fin => "fi",
+            Lang::Fra => "fr",
+            Lang::Guj => "gu",
+            Lang::Heb => "he",
+            Lang::Hin => "hi",
+            Lang::Hrv => "hr",
+            Lang::Hun => "hu",
+            Lang::Hye => "hy",
+            Lang::Ind => "id",
+            Lang::Ita => "it",
+            Lang::Jav => "jv",
+            Lang::Jpn => "ja",
+            Lang::Kan => "kn",
+            Lang::Kat => "ka",
+            Lang::Khm => "km",
+            Lang::Kor => "ko",
+            Lang::Lat => "la",
+            Lang::Lav => "lv",
+            Lang::Lit => "lt",
+            Lang::Mal => "ml",
+            Lang::Mar => "mr",
+            Lang::Mkd => "mk",
+            Lang::Mya => "my",
+            Lang::Nep => "ne",
+            Lang::Nld => "nl",
+            Lang::Nob => "nb",
+            Lang::Ori => "or",
+            Lang::Pan => "pa",
+            Lang::Pes => "fa",
+            Lang::Pol => "pl",
+            Lang::Por => "pt",
+            Lang::Ron => "ro",
+            Lang::Rus => "ru",
+            Lang::Sin => "si",
+            Lang::Slk => "sk",
+            Lang::Slv => "sl",
+            Lang::Sna => "sn",
+            Lang::Spa => "es",
+            Lang::Srp => "sr",
+            Lang::Swe => "sv",
+            Lang::Tam => "ta",
+            Lang::Tel => "te",
+            Lang::Tgl => "tl",
+            Lang::Tha => "th",
+            Lang::Tuk => "tk",
+            Lang::Tur => "tr",
+            Lang::Ukr => "uk",
+            Lang::Urd => "ur",
+            Lang::Uzb => "uz",
+            Lang::Vie => "vi",
+            Lang::Yid => "yi",
+            Lang::Zul => "zu",
+        }
+    }
+
+    /// Returns the English name, e.g. "Hebrew".
+    pub fn name(&self) -> &'static str {
+        self.0.eng_name()
+    }
+
+    /// Returns whether a code or English name from the config names this
+    /// language, ignoring case.
+    pub fn is(&self, name: &str) -> bool {
+        let name = name.trim();
+        name.eq_ignore_ascii_case(self.code()) || name.eq_ignore_ascii_case(self.name())
+    }
+}
+
+/// Detects the language of a message body, when it can be told reliably.
+///
+/// Quoted lines are left out, so a reply is detected by what its sender
+/// wrote.
+pub fn detect_language(body: &str) -> Option<Language> {
+    let text = body
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('>'))
+        .collect::<Vec<_>>()
+        .join("\n");
+    whatlang::detect(&text)
+        .filter(|info| info.is_reliable())
+        .map(|info| Language(info.lang()))
+}
+
+/// Returns whether a line is written right to left, judged by its first
+/// strong character as the bidirectional algorithm does.
+pub fn is_rtl(line: &str) -> bool {
+    get_base_direction(line) == Direction::Rtl
+}
+
+/// Wraps a right-to-left line to a width in columns and returns its screen
+/// lines in display order, left-to-right runs such as numbers and Latin
+/// words kept readable within them.
+pub fn display_lines(line: &str, width: usize) -> Vec<String> {
+    let bidi = ParagraphBidiInfo::new(line, None);
+    wrap_ranges(line, width.max(1))
+        .into_iter()
+        .map(|range| bidi.reorder_line(range).trim().to_string())
+        .collect()
+}
+
+/// Splits a line into ranges of at most a width, at spaces where possible.
+fn wrap_ranges(line: &str, width: usize) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    while line[start..].width() > width {
+        // The longest prefix that fits, broken after its last space.
+        let mut end = start;
+        let mut space = None;
+        for (i, c) in line[start..].char_indices() {
+            let next = start + i + c.len_utf8();
+            if line[start..next].width() > width {
+                break;
+            }
+            end = next;
+            if c == ' ' {
+                space = Some(next);
+            }
+        }
+        // A character wider than the line still takes one.
+        if end == start {
+            end += line[start..].chars().next().map_or(1, char::len_utf8);
+        }
+        let end = space.unwrap_or(end);
+        ranges.push(start..end);
+        start = end;
+    }
+    ranges.push(start..line.len());
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_lines() {
+        // Stored in logical order, shown with the first word on the right.
+        let line = "שלום עולם";
+        assert!(is_rtl(line));
+        assert!(!is_rtl("Hello שלום"));
+        assert_eq!(display_lines(line, 40), ["םלוע םולש"]);
+
+        // Wrapped before reordering, so the first words come first; numbers
+        // keep their digit order.
+        assert_eq!(display_lines("שלום עולם 123", 5), ["םולש", "םלוע", "123"]);
+    }
+
+    #[test]
+    fn test_detect_language() {
+        let body = "שלום, רציתי לשאול אם הפגישה של מחר עדיין בתוקף ומה השעה המדויקת.\n\
+                    > Is the meeting tomorrow still on?\n";
+        let language = detect_language(body).unwrap();
+        assert_eq!(language.code(), "heb");
+        assert_eq!(language.short_code(), "he");
+        assert!(language.is("Hebrew") && language.is("HEB"));
+        assert_eq!(detect_language(""), None);
+    }
+}
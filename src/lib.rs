@@ -3,11 +3,67 @@
 //! This crate provides a simple TUI application for reading Gmail messages via
 //! IMAP connection with SSL/TLS support.
 
+mod address;
+mod attachment_save;
+mod attachments;
+mod auth;
+mod backup;
+mod body;
+mod cache;
+mod cli;
+mod clipboard;
+mod compose;
 mod config;
-mod gmail_client;
+mod config_check;
+mod config_watch;
+mod contacts;
+mod display_filter;
+mod dsn;
+mod extract;
+mod failure;
+mod fetcher;
+mod folder_search;
+mod folders;
+mod fulltext;
+mod idle;
+mod imap_client;
+mod index_export;
+mod journal;
+mod known_hosts;
+mod labels;
+mod language;
+mod loader;
+mod maildir;
+mod mbox;
+mod offline;
+mod outbox;
+mod participants;
+mod pattern;
+mod senders;
+mod smtp;
+mod special_use;
+mod threads;
+mod transfer;
+mod translate;
 mod ui;
+mod updates;
+mod urls;
 mod utils;
+mod warmup;
+mod watchdog;
 
-pub use config::Config;
-pub use gmail_client::{Email, GmailClient, NameAddr};
+pub use cache::MessageCache;
+pub use cli::{Cli, Command, print_config_warnings, run_check_config, run_command};
+pub use compose::Draft;
+pub use config::{Config, DEFAULT_CONFIG_PATH};
+pub use config_watch::ConfigWatcher;
+pub use failure::{Failure, report_failure};
+pub use idle::MailWatcher;
+pub use imap_client::{DEFAULT_FETCH_LIMIT, Email, ImapClient, NameAddr};
+pub use journal::Journal;
+pub use known_hosts::{CertificateChanged, confirm_certificate_change};
+pub use loader::{LoadFailure, LoadStage, Loader};
+pub use offline::{OfflineMailbox, Reconnector};
+pub use outbox::Outbox;
+pub use smtp::{Flushed, SendOutcome, flush_outbox, send_or_queue};
 pub use ui::{App, run_app};
@@ -0,0 +1,149 @@
+//! Startup work done in the background: sending the messages queued while
+//! offline, connecting, and fetching the inbox, so the interface can show
+//! the progress meanwhile and be left with `q`.
+
+use anyhow::{Context, Result};
+use std::fmt;
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::thread;
+
+use crate::config::Config;
+use crate::imap_client::{FetchedEmails, ImapClient};
+use crate::outbox::Outbox;
+use crate::smtp::{Flushed, flush_outbox};
+
+/// What the startup load is doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadStage {
+    /// Sending messages queued while offline.
+    SendingQueued,
+    /// Connecting and logging in to the IMAP server.
+    Connecting,
+    /// Fetching envelopes, with the number fetched and in all.
+    Fetching { done: usize, total: usize },
+}
+
+impl fmt::Display for LoadStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadStage::SendingQueued => write!(f, "Sending queued messages"),
+            LoadStage::Connecting => write!(f, "Connecting to the IMAP server"),
+            LoadStage::Fetching { total: 0, .. } => write!(f, "Fetching emails"),
+            LoadStage::Fetching { done, total } => {
+                write!(f, "Fetched {}/{} envelopes", done, total)
+            }
+        }
+    }
+}
+
+/// The inbox fetched at startup.
+#[derive(Debug)]
+pub struct Loaded {
+    pub client: ImapClient,
+    pub fetched: FetchedEmails,
+    /// What sending the queued messages first came to, or why the outbox
+    /// could not be read.
+    pub sent: Result<Flushed>,
+}
+
+/// A startup load that failed, and the stage it failed at.
+#[derive(Debug)]
+pub struct LoadFailure {
+    pub stage: LoadStage,
+    pub error: anyhow::Error,
+}
+
+enum LoadEvent {
+    Stage(LoadStage),
+    Done(Box<Result<Loaded, LoadFailure>>),
+}
+
+/// The startup load running in a background thread.
+#[derive(Debug)]
+pub struct Loader {
+    events: Receiver<LoadEvent>,
+    stage: LoadStage,
+}
+
+impl Loader {
+    /// Starts loading the `limit` most recent messages of the default mailbox.
+    pub fn new(config: &Config, limit: u32) -> Self {
+        let config = config.clone();
+        let (tx, events) = channel();
+        thread::spawn(move || {
+            let result = load(&config, limit, &tx);
+            let _ = tx.send(LoadEvent::Done(Box::new(result)));
+        });
+        Loader {
+            events,
+            stage: LoadStage::SendingQueued,
+        }
+    }
+
+    /// Returns the stage reached.
+    pub fn stage(&self) -> LoadStage {
+        self.stage
+    }
+
+    /// Takes in the progress reported since the last call, and returns the
+    /// result once the load finished, without blocking.
+    pub fn poll(&mut self) -> Option<Result<Loaded, LoadFailure>> {
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                LoadEvent::Stage(stage) => self.stage = stage,
+                LoadEvent::Done(result) => return Some(*result),
+            }
+        }
+        None
+    }
+}
+
+fn load(config: &Config, limit: u32, tx: &Sender<LoadEvent>) -> Result<Loaded, LoadFailure> {
+    let stage = |stage| {
+        let _ = tx.send(LoadEvent::Stage(stage));
+    };
+    let fail = |stage| move |error| LoadFailure { stage, error };
+
+    stage(LoadStage::SendingQueued);
+    let sent = flush_outbox(config, &Outbox::open_default(&config.account()));
+
+    stage(LoadStage::Connecting);
+    let mut client = ImapClient::connect_with_config(&config.gmail, &config.imap)
+        .map_err(fail(LoadStage::Connecting))?;
+    if config.cache.enabled {
+        client = client.with_cache(config.cache.open(&config.gmail.username));
+    }
+
+    stage(LoadStage::Fetching { done: 0, total: 0 });
+    let fetched = client
+        .fetch_emails_with_progress(limit, |done, total| {
+            stage(LoadStage::Fetching { done, total });
+        })
+        .context("Failed to fetch emails")
+        .map_err(fail(LoadStage::Fetching { done: 0, total: 0 }))?;
+    Ok(Loaded {
+        client,
+        fetched,
+        sent,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_stage_display() {
+        assert_eq!(
+            LoadStage::Connecting.to_string(),
+            "Connecting to the IMAP server"
+        );
+        let starting = LoadStage::Fetching { done: 0, total: 0 };
+        assert_eq!(starting.to_string(), "Fetching emails");
+        let fetching = LoadStage::Fetching {
+            done: 50,
+            total: 200,
+        };
+        assert_eq!(fetching.to_string(), "Fetched 50/200 envelopes");
+    }
+}
@@ -0,0 +1,115 @@
+//! Writing messages into Maildir directories.
+//!
+//! Messages are delivered straight into `cur/` with their IMAP flags encoded
+//! in the filename info suffix (`:2,FS`), as described in the Maildir spec.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Counter making delivery filenames unique within this process.
+static DELIVERY_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A Maildir directory with `cur`, `new`, and `tmp` subdirectories.
+#[derive(Debug, Clone)]
+pub struct Maildir {
+    root: PathBuf,
+}
+
+impl Maildir {
+    /// Opens a Maildir, creating its directory structure if needed.
+    pub fn create<P: AsRef<Path>>(root: P) -> Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        for sub in ["cur", "new", "tmp"] {
+            let dir = root.join(sub);
+            fs::create_dir_all(&dir)
+                .with_context(|| format!("Failed to create Maildir directory {:?}", dir))?;
+        }
+        Ok(Maildir { root })
+    }
+
+    /// Stores a message with the given IMAP flags.
+    ///
+    /// The message is written to `tmp/` first and then moved into `cur/`, so
+    /// readers never see a partial file. Returns the path relative to the
+    /// Maildir root.
+    pub fn store(&self, raw: &[u8], flags: &[String]) -> Result<PathBuf> {
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_micros())
+            .unwrap_or(0);
+        let unique = format!(
+            "{}.{}_{}.rutt",
+            stamp,
+            std::process::id(),
+            DELIVERY_COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+
+        let tmp = self.root.join("tmp").join(&unique);
+        fs::write(&tmp, raw).with_context(|| format!("Failed to write {:?}", tmp))?;
+
+        let relative = PathBuf::from("cur").join(format!("{}:2,{}", unique, maildir_flags(flags)));
+        let dest = self.root.join(&relative);
+        fs::rename(&tmp, &dest).with_context(|| format!("Failed to move message to {:?}", dest))?;
+
+        Ok(relative)
+    }
+}
+
+/// Converts IMAP flags to Maildir info flags, in the required ASCII order.
+pub fn maildir_flags(flags: &[String]) -> String {
+    let mut letters = flags
+        .iter()
+        .filter_map(|flag| match flag.as_str() {
+            "\\Draft" => Some('D'),
+            "\\Flagged" => Some('F'),
+            "\\Answered" => Some('R'),
+            "\\Seen" => Some('S'),
+            "\\Deleted" => Some('T'),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    letters.sort_unstable();
+    letters.dedup();
+    letters.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_maildir_flags() {
+        let flags = vec![
+            "\\Seen".to_string(),
+            "\\Flagged".to_string(),
+            "\\Recent".to_string(),
+            "$Custom".to_string(),
+        ];
+        assert_eq!(maildir_flags(&flags), "FS");
+        assert_eq!(maildir_flags(&[]), "");
+    }
+
+    #[test]
+    fn test_store() {
+        let dir = TempDir::new().unwrap();
+        let maildir = Maildir::create(dir.path().join("INBOX")).unwrap();
+
+        let first = maildir
+            .store(b"Subject: a\r\n\r\nA", &["\\Seen".to_string()])
+            .unwrap();
+        let second = maildir.store(b"Subject: b\r\n\r\nB", &[]).unwrap();
+
+        assert_ne!(first, second);
+        assert!(first.to_string_lossy().ends_with(":2,S"));
+        assert!(second.to_string_lossy().ends_with(":2,"));
+        assert_eq!(
+            fs::read(maildir.root.join(&first)).unwrap(),
+            b"Subject: a\r\n\r\nA"
+        );
+        assert_eq!(fs::read_dir(maildir.root.join("tmp")).unwrap().count(), 0);
+    }
+}
@@ -1,31 +1,142 @@
 //! Main application entry point for the rutt Gmail terminal client.
 //!
 //! Sets up terminal UI, connects to Gmail IMAP, fetches emails, and runs the
-//! interactive terminal interface.
+//! interactive terminal interface. When invoked with a `mailto:` URI, opens
+//! straight into compose mode instead.
 
 use anyhow::{Context, Result};
+use clap::Parser;
 use crossterm::{
     event::{DisableMouseCapture, EnableMouseCapture},
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
 use ratatui::{Terminal, backend::CrosstermBackend};
-use std::io;
+use std::io::{self, IsTerminal};
+use std::process::ExitCode;
 
-use rutt::{App, Config, GmailClient, run_app};
+use rutt::{
+    App, CertificateChanged, Cli, Config, ConfigWatcher, Draft, Failure, ImapClient, Journal,
+    LoadStage, Loader, OfflineMailbox, Reconnector, confirm_certificate_change,
+    print_config_warnings, report_failure, run_app, run_check_config, run_command,
+};
 
-fn main() -> Result<()> {
-    let config = Config::load_default().context("Failed to load config.toml")?;
+fn main() -> Result<ExitCode> {
+    let cli = Cli::parse();
+    let config_path = &cli.config_path();
+    let config_error = || format!("Failed to load {}", config_path.display());
+    if cli.check_config {
+        return run_check_config(config_path);
+    }
 
-    println!("Connecting to Gmail IMAP...");
-    let mut client = GmailClient::connect(&config.gmail.username, &config.gmail.app_password)
-        .context("Failed to connect to Gmail")?;
+    // Subcommands exit with a code telling scripts why they failed.
+    if let Some(command) = &cli.command {
+        let result = Config::load_profile(config_path, cli.profile.as_deref())
+            .with_context(config_error)
+            .and_then(|config| {
+                if !cli.quiet {
+                    print_config_warnings(config_path);
+                }
+                run_command(&config, command)
+            });
+        return Ok(result.unwrap_or_else(|e| report_failure(&e, cli.json_errors)));
+    }
 
-    println!("Fetching emails...");
-    let emails = client.fetch_emails(200).context("Failed to fetch emails")?;
+    let mut config =
+        Config::load_profile(config_path, cli.profile.as_deref()).with_context(config_error)?;
+    let overrides = cli.imap_overrides();
+    overrides.apply(&mut config.imap);
+    if !cli.quiet {
+        print_config_warnings(config_path);
+    }
+
+    // Notices printed while the interface does not hold the screen.
+    let notice = |message: &str| {
+        if !cli.quiet {
+            eprintln!("{message}");
+        }
+    };
+
+    // Act as a mailto: handler, e.g. `rutt "mailto:a@b?subject=hi"`.
+    if let Some(uri) = &cli.mailto {
+        let draft = Draft::from_mailto(uri).context("Failed to parse mailto URI")?;
+        run_tui(App::compose_only(draft).with_config(config))?;
+        return Ok(ExitCode::SUCCESS);
+    }
 
-    println!("Found {} emails", emails.len());
+    // Connecting and fetching happen in the interface, which shows their
+    // progress. When connecting fails, the terminal is restored to ask about
+    // a changed certificate, or to list the cached inbox instead.
+    loop {
+        let mut app = App::starting(Loader::new(&config, config.imap.fetch_limit))
+            .with_config(config.clone())
+            .with_imap_overrides(overrides.clone())
+            .with_journal(Journal::open_default(&config.account()));
+        match ConfigWatcher::new(config_path) {
+            Ok(watcher) => app = app.with_config_watcher(watcher),
+            Err(e) => notice(&format!("Config changes will need a restart: {e:#}")),
+        }
+        let Some(failure) = run_tui(app)?.take_load_failure() else {
+            return Ok(ExitCode::SUCCESS);
+        };
+        if failure.stage != LoadStage::Connecting {
+            return Err(failure.error);
+        }
+        let accepted = match failure.error.downcast_ref::<CertificateChanged>() {
+            Some(changed) if io::stdin().is_terminal() => confirm_certificate_change(changed)?,
+            _ => false,
+        };
+        if !accepted {
+            let network = Failure::of(&failure.error) == Failure::Network;
+            let error = failure
+                .error
+                .context("Failed to connect to the IMAP server");
+            // Other failures, such as a rejected password, would only be
+            // hidden behind the cached inbox.
+            if !network {
+                return Err(error);
+            }
+            return run_offline(config, error, notice);
+        }
+    }
+}
+
+/// Lists the cached inbox when the server cannot be reached, trying to
+/// connect again in the background, after telling why with `notice`.
+/// Without a cached inbox, fails with the connection error.
+fn run_offline(config: Config, error: anyhow::Error, notice: impl Fn(&str)) -> Result<ExitCode> {
+    if !config.cache.enabled {
+        return Err(error);
+    }
+    let cache = config.cache.open(&config.gmail.username);
+    let Some((mailbox, emails)) =
+        OfflineMailbox::open(cache.clone(), &config.imap.default_mailbox)?
+    else {
+        return Err(error);
+    };
+    notice(&format!("{error:#}"));
+    notice(&format!(
+        "Offline: showing the {} cached messages",
+        emails.len()
+    ));
+
+    let (gmail, imap) = (config.gmail.clone(), config.imap.clone());
+    let reconnector = Reconnector::new(move || {
+        ImapClient::connect_with_config(&gmail, &imap).map(|c| c.with_cache(cache.clone()))
+    });
+    let journal = Journal::open_default(&config.account());
+    let mut app = App::offline(mailbox, emails)
+        .with_config(config)
+        .with_journal(journal)
+        .with_reconnector(reconnector);
+    app.run_startup_commands();
+    run_tui(app)?;
+    Ok(ExitCode::SUCCESS)
+}
 
+/// Sets up the terminal, runs the UI until the user quits, and restores the
+/// terminal afterwards, returning the app as it was left.
+fn run_tui(mut app: App) -> Result<App> {
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -33,9 +144,7 @@ fn main() -> Result<()> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    // Create app and run
-    let app = App::new(client, emails);
-    let res = run_app(&mut terminal, app);
+    let res = run_app(&mut terminal, &mut app);
 
     // Restore terminal
     disable_raw_mode()?;
@@ -50,5 +159,5 @@ fn main() -> Result<()> {
         eprintln!("Error: {err:?}");
     }
 
-    Ok(())
+    Ok(app)
 }
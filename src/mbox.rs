@@ -0,0 +1,110 @@
+//! Writing messages in mbox format.
+//!
+//! Produces mboxrd files: each message is preceded by a `From ` separator
+//! line, and body lines that look like separators are quoted with `>`.
+
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset};
+use mailparse::{MailHeaderMap, addrparse_header, parse_headers};
+use std::io::Write;
+
+/// Writes messages to an mbox stream.
+#[derive(Debug)]
+pub struct MboxWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> MboxWriter<W> {
+    /// Creates a writer appending to the given stream.
+    pub fn new(writer: W) -> Self {
+        MboxWriter { writer }
+    }
+
+    /// Appends a raw RFC822 message.
+    ///
+    /// The separator line uses the message's From address and the given
+    /// delivery date.
+    pub fn write_message(&mut self, raw: &[u8], date: Option<DateTime<FixedOffset>>) -> Result<()> {
+        let sender = envelope_sender(raw);
+        let date = date
+            .map(|d| d.format("%a %b %e %H:%M:%S %Y").to_string())
+            .unwrap_or_else(|| "Thu Jan  1 00:00:00 1970".to_string());
+        writeln!(self.writer, "From {} {}", sender, date)?;
+
+        // Normalize line endings and quote separator-like lines (mboxrd).
+        let text = raw
+            .strip_suffix(b"\r\n")
+            .or(raw.strip_suffix(b"\n"))
+            .unwrap_or(raw);
+        for line in text.split(|&b| b == b'\n') {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let unquoted = line.iter().position(|&b| b != b'>').map(|i| &line[i..]);
+            if unquoted.is_some_and(|rest| rest.starts_with(b"From ")) {
+                self.writer.write_all(b">")?;
+            }
+            self.writer.write_all(line)?;
+            self.writer.write_all(b"\n")?;
+        }
+        self.writer.write_all(b"\n")?;
+
+        Ok(())
+    }
+
+    /// Flushes the underlying stream and returns it.
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Returns the address to use on the `From ` separator line.
+fn envelope_sender(raw: &[u8]) -> String {
+    let Ok((headers, _)) = parse_headers(raw) else {
+        return "MAILER-DAEMON".to_string();
+    };
+
+    ["Return-Path", "From"]
+        .iter()
+        .filter_map(|name| headers.get_first_header(name))
+        .filter_map(|header| addrparse_header(header).ok())
+        .filter_map(|addrs| addrs.extract_single_info())
+        .map(|info| info.addr)
+        .find(|addr| !addr.is_empty())
+        .unwrap_or_else(|| "MAILER-DAEMON".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_message() {
+        let raw = b"From: Alice <alice@example.com>\r\nSubject: Hi\r\n\r\nHello\r\nFrom here on\r\n>From quoted\r\n";
+        let date = DateTime::parse_from_rfc2822("Mon, 1 Jan 2024 10:00:00 +0000").unwrap();
+
+        let mut writer = MboxWriter::new(Vec::new());
+        writer.write_message(raw, Some(date)).unwrap();
+        let output = String::from_utf8(writer.finish().unwrap()).unwrap();
+
+        assert_eq!(
+            output,
+            "From alice@example.com Mon Jan  1 10:00:00 2024\n\
+From: Alice <alice@example.com>\n\
+Subject: Hi\n\
+\n\
+Hello\n\
+>From here on\n\
+>>From quoted\n\
+\n"
+        );
+    }
+
+    #[test]
+    fn test_envelope_sender_fallback() {
+        assert_eq!(envelope_sender(b"Subject: x\r\n\r\nbody"), "MAILER-DAEMON");
+        assert_eq!(
+            envelope_sender(b"Return-Path: <bounce@example.com>\r\nFrom: a@example.com\r\n\r\n"),
+            "bounce@example.com"
+        );
+    }
+}
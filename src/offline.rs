@@ -0,0 +1,110 @@
+//! Offline mode, for reading the cache when the server cannot be reached.
+//!
+//! When connecting fails at startup, the envelopes last fetched into the
+//! cache are listed instead, and messages opened before can be read again.
+//! A background thread keeps trying to connect, so the interface can go back
+//! online and replay the flag changes made meanwhile.
+
+use anyhow::Result;
+use std::sync::mpsc::{Receiver, channel};
+use std::thread;
+use std::time::Duration;
+
+use crate::cache::MessageCache;
+use crate::imap_client::{Email, ImapClient, MessageBody, parse_message_body};
+use crate::known_hosts::CertificateChanged;
+
+/// How long to wait between attempts to connect again.
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The cached copy of a mailbox read while offline.
+#[derive(Debug)]
+pub struct OfflineMailbox {
+    cache: MessageCache,
+    mailbox: String,
+    uid_validity: u32,
+}
+
+impl OfflineMailbox {
+    /// Opens the cached copy of a mailbox, returning it with its envelopes,
+    /// or `None` when nothing of it is cached.
+    pub fn open(cache: MessageCache, mailbox: &str) -> Result<Option<(Self, Vec<Email>)>> {
+        let Some((uid_validity, emails)) = cache.stored_emails(mailbox)? else {
+            return Ok(None);
+        };
+        let offline = OfflineMailbox {
+            cache,
+            mailbox: mailbox.to_string(),
+            uid_validity,
+        };
+        Ok(Some((offline, emails)))
+    }
+
+    /// Returns the name of the mailbox.
+    pub fn mailbox(&self) -> &str {
+        &self.mailbox
+    }
+
+    /// Returns the UIDVALIDITY the mailbox was cached under.
+    pub fn uid_validity(&self) -> u32 {
+        self.uid_validity
+    }
+
+    /// Returns the envelopes of the cached messages.
+    pub fn emails(&self) -> Result<Vec<Email>> {
+        let stored = self.cache.stored_emails(&self.mailbox)?;
+        Ok(stored.map(|(_, emails)| emails).unwrap_or_default())
+    }
+
+    /// Searches the cached messages with the full-text index.
+    pub fn search_text(&self, query: &str) -> Result<Vec<Email>> {
+        self.cache
+            .search_text(&self.mailbox, self.uid_validity, query)
+    }
+
+    /// Returns the body of a message, if it was opened before.
+    pub fn body(&self, uid: u32) -> Option<MessageBody> {
+        self.cache
+            .body(&self.mailbox, self.uid_validity, uid)
+            .map(|raw| parse_message_body(&raw))
+    }
+}
+
+/// Tries to connect in the background until it succeeds.
+#[derive(Debug)]
+pub struct Reconnector {
+    connected: Receiver<Result<ImapClient, CertificateChanged>>,
+}
+
+impl Reconnector {
+    /// Starts a thread calling `connect` every [`RETRY_INTERVAL`] until it
+    /// succeeds. A changed server certificate is reported on each attempt,
+    /// so it can be accepted; the thread stops once the reconnector is
+    /// dropped.
+    pub fn new(connect: impl Fn() -> Result<ImapClient> + Send + 'static) -> Self {
+        let (tx, connected) = channel();
+        thread::spawn(move || {
+            loop {
+                thread::sleep(RETRY_INTERVAL);
+                let result = match connect() {
+                    Ok(client) => Ok(client),
+                    Err(e) => match e.downcast::<CertificateChanged>() {
+                        Ok(changed) => Err(changed),
+                        Err(_) => continue,
+                    },
+                };
+                let connected = result.is_ok();
+                if tx.send(result).is_err() || connected {
+                    return;
+                }
+            }
+        });
+        Reconnector { connected }
+    }
+
+    /// Returns the new connection once there is one, or the certificate
+    /// that kept the last attempt from connecting, without blocking.
+    pub fn connected(&self) -> Option<Result<ImapClient, CertificateChanged>> {
+        self.connected.try_recv().ok()
+    }
+}
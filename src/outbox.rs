@@ -0,0 +1,155 @@
+//! On-disk queue of messages waiting to be sent.
+//!
+//! Drafts that could not be sent because the SMTP server was unreachable are
+//! stored here as TOML files and retried later.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::cache::encode_name;
+use crate::compose::Draft;
+use crate::utils::data_dir;
+
+/// A directory of queued drafts.
+#[derive(Debug, Clone)]
+pub struct Outbox {
+    dir: PathBuf,
+}
+
+impl Outbox {
+    /// Opens an outbox stored in the given directory.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Outbox {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Opens the outbox of an account in the default application data
+    /// directory, so that mail queued for one account is not sent through
+    /// another.
+    pub fn open_default(account: &str) -> Self {
+        Self::new(data_dir().join("outbox").join(encode_name(account)))
+    }
+
+    /// Adds a draft to the queue, returning the path it was stored at.
+    ///
+    /// Attachments are recorded by their canonical paths, as the draft may be
+    /// sent later from another working directory.
+    pub fn queue(&self, draft: &Draft) -> Result<PathBuf> {
+        let mut draft = draft.clone();
+        for path in &mut draft.attachments {
+            *path = fs::canonicalize(&*path)
+                .with_context(|| format!("Failed to find attachment {:?}", path))?;
+        }
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create outbox at {:?}", self.dir))?;
+
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let path = self.dir.join(format!("{}.toml", stamp));
+
+        let contents = toml::to_string(&draft).context("Failed to serialize draft")?;
+        // Synced, as drafts are queued before sending to survive a crash.
+        fs::File::create(&path)
+            .and_then(|mut file| {
+                file.write_all(contents.as_bytes())?;
+                file.sync_data()
+            })
+            .with_context(|| format!("Failed to write queued draft to {:?}", path))?;
+
+        Ok(path)
+    }
+
+    /// Returns all queued drafts, oldest first.
+    pub fn drafts(&self) -> Result<Vec<(PathBuf, Draft)>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut paths = fs::read_dir(&self.dir)
+            .with_context(|| format!("Failed to read outbox at {:?}", self.dir))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        paths
+            .into_iter()
+            .map(|path| {
+                let contents = fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read queued draft {:?}", path))?;
+                let draft = toml::from_str(&contents)
+                    .with_context(|| format!("Failed to parse queued draft {:?}", path))?;
+                Ok((path, draft))
+            })
+            .collect()
+    }
+
+    /// Removes a queued draft after it has been sent.
+    pub fn remove(&self, path: &Path) -> Result<()> {
+        fs::remove_file(path).with_context(|| format!("Failed to remove queued draft {:?}", path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_queue_and_list() {
+        let dir = TempDir::new().unwrap();
+        let outbox = Outbox::new(dir.path().join("outbox"));
+        assert!(outbox.drafts().unwrap().is_empty());
+
+        let first = Draft {
+            to: "a@example.com".to_string(),
+            subject: "First".to_string(),
+            ..Default::default()
+        };
+        let second = Draft {
+            to: "b@example.com".to_string(),
+            subject: "Second".to_string(),
+            ..Default::default()
+        };
+        outbox.queue(&first).unwrap();
+        outbox.queue(&second).unwrap();
+
+        let drafts = outbox.drafts().unwrap();
+        assert_eq!(drafts.len(), 2);
+        assert_eq!(drafts[0].1, first);
+        assert_eq!(drafts[1].1, second);
+
+        outbox.remove(&drafts[0].0).unwrap();
+        assert_eq!(outbox.drafts().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_queue_records_canonical_attachments() {
+        let dir = TempDir::new().unwrap();
+        let outbox = Outbox::new(dir.path().join("outbox"));
+        fs::write(dir.path().join("report.pdf"), "%PDF").unwrap();
+        fs::create_dir(dir.path().join("docs")).unwrap();
+        let draft = Draft {
+            attachments: vec![dir.path().join("docs/../report.pdf")],
+            ..Default::default()
+        };
+        outbox.queue(&draft).unwrap();
+        let queued = &outbox.drafts().unwrap()[0].1;
+        assert_eq!(
+            queued.attachments,
+            [fs::canonicalize(dir.path().join("report.pdf")).unwrap()]
+        );
+
+        let missing = Draft {
+            attachments: vec![dir.path().join("missing.pdf")],
+            ..Default::default()
+        };
+        assert!(outbox.queue(&missing).is_err());
+    }
+}
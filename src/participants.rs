@@ -0,0 +1,119 @@
+//! Everyone taking part in a conversation, for the participants panel of the
+//! detail view.
+//!
+//! In long threads with many people on Cc, the panel shows who wrote how
+//! much and lets the thread be narrowed to one person's messages.
+
+use std::cmp::Reverse;
+
+use crate::imap_client::{Email, NameAddr};
+
+/// A sender or recipient of a thread's messages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Participant {
+    /// Address, lowercased.
+    pub address: String,
+    /// First display name seen for the address, if any.
+    pub name: Option<String>,
+    /// Number of the thread's messages they sent.
+    pub sent: usize,
+}
+
+/// Lists the senders and recipients of a thread's messages, those who wrote
+/// the most first, otherwise in order of appearance.
+pub fn participants<'a>(emails: impl IntoIterator<Item = &'a Email>) -> Vec<Participant> {
+    let mut participants = Vec::<Participant>::new();
+    let mut add = |addr: &NameAddr, sent: usize| {
+        let Some(address) = addr.email.as_ref().map(|a| a.to_lowercase()) else {
+            return;
+        };
+        match participants.iter_mut().find(|p| p.address == address) {
+            Some(participant) => {
+                participant.sent += sent;
+                if participant.name.is_none() {
+                    participant.name = addr.name.clone();
+                }
+            }
+            None => participants.push(Participant {
+                address,
+                name: addr.name.clone(),
+                sent,
+            }),
+        }
+    };
+    for email in emails {
+        add(&email.from, 1);
+        for recipient in email.to.iter().chain(&email.cc) {
+            add(recipient, 0);
+        }
+    }
+
+    // The sort is stable, so ties keep their order of appearance.
+    participants.sort_by_key(|p| Reverse(p.sent));
+    participants
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imap_client::test_email;
+
+    fn addr(name: Option<&str>, email: &str) -> NameAddr {
+        NameAddr {
+            name: name.map(str::to_string),
+            email: Some(email.to_string()),
+        }
+    }
+
+    fn email(from: NameAddr, to: Vec<NameAddr>, cc: Vec<NameAddr>) -> Email {
+        Email {
+            from,
+            to,
+            cc,
+            ..test_email(1, "")
+        }
+    }
+
+    #[test]
+    fn test_participants() {
+        let thread = vec![
+            email(
+                addr(None, "alice@example.com"),
+                vec![addr(Some("Bob"), "bob@example.com")],
+                vec![addr(Some("Carol"), "carol@example.com")],
+            ),
+            email(
+                addr(Some("Bob"), "Bob@Example.com"),
+                vec![addr(Some("Alice"), "alice@example.com")],
+                vec![],
+            ),
+            email(
+                addr(Some("Bob"), "bob@example.com"),
+                vec![addr(None, "alice@example.com")],
+                vec![addr(None, "dave@example.com")],
+            ),
+        ];
+
+        let summary = participants(&thread)
+            .into_iter()
+            .map(|p| (p.address, p.name, p.sent))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                ("bob@example.com".to_string(), Some("Bob".to_string()), 2),
+                (
+                    "alice@example.com".to_string(),
+                    Some("Alice".to_string()),
+                    1
+                ),
+                (
+                    "carol@example.com".to_string(),
+                    Some("Carol".to_string()),
+                    0
+                ),
+                ("dave@example.com".to_string(), None, 0),
+            ]
+        );
+    }
+}
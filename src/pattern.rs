@@ -0,0 +1,226 @@
+//! Mutt-style patterns narrowing the email list locally.
+//!
+//! Patterns are matched against the headers already fetched, without asking
+//! the server. Terms separated by spaces must all match:
+//!
+//! - `~N` unread, `~F` flagged
+//! - `~f TEXT` sender name or address contains TEXT
+//! - `~s TEXT` subject contains TEXT
+//! - `~d RANGE` date in RANGE: `2024-01-31`, `2024-01-01..2024-01-31` (either
+//!   end may be left out), `<7d` (newer than) or `>2w` (older than), with
+//!   units `d`, `w`, `m` (30 days), and `y` (365 days)
+//! - any other word: subject or sender contains it
+//!
+//! A term prefixed with `!` must not match. Text is compared ignoring case.
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone};
+
+use crate::imap_client::Email;
+
+/// A single condition of a pattern.
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    Unread,
+    Flagged,
+    From(String),
+    Subject(String),
+    /// Date at or after the first bound and before the second.
+    Date(Option<DateTime<Local>>, Option<DateTime<Local>>),
+    /// Subject or sender.
+    Text(String),
+}
+
+impl Term {
+    fn matches(&self, email: &Email) -> bool {
+        let from = || email.from.to_string().to_lowercase();
+        match self {
+            Term::Unread => !email.is_read,
+            Term::Flagged => email.is_flagged,
+            Term::From(text) => from().contains(text),
+            Term::Subject(text) => email.subject.to_lowercase().contains(text),
+            Term::Date(after, before) => {
+                after.is_none_or(|after| email.date >= after)
+                    && before.is_none_or(|before| email.date < before)
+            }
+            Term::Text(text) => {
+                email.subject.to_lowercase().contains(text) || from().contains(text)
+            }
+        }
+    }
+}
+
+/// A parsed pattern.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    source: String,
+    /// Terms, each with whether it is negated.
+    terms: Vec<(bool, Term)>,
+}
+
+impl Pattern {
+    /// Parses a pattern, resolving relative dates against `now`.
+    pub fn parse(source: &str, now: DateTime<Local>) -> Result<Self> {
+        let mut terms = Vec::new();
+        let mut words = source.split_whitespace();
+        while let Some(word) = words.next() {
+            let (negated, word) = match word.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, word),
+            };
+            let mut argument = |name: &str| {
+                words
+                    .next()
+                    .map(str::to_lowercase)
+                    .with_context(|| format!("`{}` needs an argument", name))
+            };
+            let term = match word {
+                "~N" => Term::Unread,
+                "~F" => Term::Flagged,
+                "~f" => Term::From(argument(word)?),
+                "~s" => Term::Subject(argument(word)?),
+                "~d" => {
+                    let range = argument(word)?;
+                    parse_date_range(&range, now)
+                        .with_context(|| format!("Invalid date range `{}`", range))?
+                }
+                _ if word.starts_with('~') => bail!("Unknown pattern `{}`", word),
+                "" => bail!("`!` needs a term after it"),
+                _ => Term::Text(word.to_lowercase()),
+            };
+            terms.push((negated, term));
+        }
+        Ok(Pattern {
+            source: source.trim().to_string(),
+            terms,
+        })
+    }
+
+    /// Returns the pattern as typed.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns whether an email matches every term.
+    pub fn matches(&self, email: &Email) -> bool {
+        self.terms
+            .iter()
+            .all(|(negated, term)| term.matches(email) != *negated)
+    }
+}
+
+/// Parses the argument of `~d`.
+fn parse_date_range(range: &str, now: DateTime<Local>) -> Result<Term> {
+    let before = |age: &str| -> Result<DateTime<Local>> {
+        now.checked_sub_signed(parse_age(age)?)
+            .context("age out of range")
+    };
+    if let Some(age) = range.strip_prefix('<') {
+        return Ok(Term::Date(Some(before(age)?), None));
+    }
+    if let Some(age) = range.strip_prefix('>') {
+        return Ok(Term::Date(None, Some(before(age)?)));
+    }
+    let (start, end) = match range.split_once("..") {
+        Some((start, end)) => (start, end),
+        None => (range, range),
+    };
+    let bound = |date: &str, days_after: i64| -> Result<Option<DateTime<Local>>> {
+        if date.is_empty() {
+            return Ok(None);
+        }
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")?;
+        let midnight = date
+            .checked_add_signed(Duration::days(days_after))
+            .context("date out of range")?
+            .and_hms_opt(0, 0, 0)
+            .expect("midnight is valid");
+        Ok(Local.from_local_datetime(&midnight).earliest())
+    };
+    // The end date is inclusive, so the bound is the midnight after it.
+    Ok(Term::Date(bound(start, 0)?, bound(end, 1)?))
+}
+
+/// Parses an age such as `7d` or `2w`.
+fn parse_age(age: &str) -> Result<Duration> {
+    let split = age.char_indices().last().map_or(0, |(i, _)| i);
+    let (count, unit) = age.split_at(split);
+    let count = count.parse::<i64>().context("expected a number")?;
+    let days = match unit {
+        "d" => 1,
+        "w" => 7,
+        "m" => 30,
+        "y" => 365,
+        _ => bail!("expected a unit of d, w, m, or y"),
+    };
+    count
+        .checked_mul(days)
+        .and_then(Duration::try_days)
+        .context("age out of range")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imap_client::{NameAddr, test_email};
+
+    fn email(subject: &str, from: &str, is_read: bool, date: DateTime<Local>) -> Email {
+        Email {
+            subject: subject.to_string(),
+            from: NameAddr {
+                name: Some("Alice Smith".to_string()),
+                email: Some(from.to_string()),
+            },
+            date,
+            is_read,
+            ..test_email(1, from)
+        }
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(y, m, d, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_terms() {
+        let now = date(2024, 3, 1);
+        let newsletter = email("Weekly News", "news@shop.example", false, date(2024, 1, 31));
+        let reply = email("Re: Lunch", "alice@example.com", true, date(2024, 2, 28));
+
+        let matching = |source: &str| {
+            let pattern = Pattern::parse(source, now).unwrap();
+            [&newsletter, &reply]
+                .into_iter()
+                .filter(|email| pattern.matches(email))
+                .map(|email| email.subject.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(matching("~N"), ["Weekly News"]);
+        assert_eq!(matching("!~N"), ["Re: Lunch"]);
+        assert_eq!(matching("~F"), Vec::<&str>::new());
+        assert_eq!(matching("~f SHOP.example"), ["Weekly News"]);
+        assert_eq!(matching("~f smith ~s lunch"), ["Re: Lunch"]);
+        assert_eq!(matching("news"), ["Weekly News"]);
+        assert_eq!(matching("~d 2024-01-31"), ["Weekly News"]);
+        assert_eq!(matching("~d 2024-02-01.."), ["Re: Lunch"]);
+        assert_eq!(matching("~d ..2024-01-31"), ["Weekly News"]);
+        assert_eq!(matching("~d <1w"), ["Re: Lunch"]);
+        assert_eq!(matching("~d >3w"), ["Weekly News"]);
+        assert_eq!(matching(""), ["Weekly News", "Re: Lunch"]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let now = date(2024, 3, 1);
+        let error = |source: &str| format!("{:#}", Pattern::parse(source, now).unwrap_err());
+        assert_eq!(error("~f"), "`~f` needs an argument");
+        assert_eq!(error("~x"), "Unknown pattern `~x`");
+        assert_eq!(error("! ~N"), "`!` needs a term after it");
+        assert!(error("~d 2024-13-01").starts_with("Invalid date range `2024-13-01`"));
+        assert!(error("~d <7x").contains("expected a unit of d, w, m, or y"));
+        assert!(error("~d <7é").contains("expected a unit of d, w, m, or y"));
+        assert!(error("~d >99999999999y").ends_with("age out of range"));
+        assert!(error("~d <9999999999999d").ends_with("age out of range"));
+        assert!(error("~d <99999999d").ends_with("age out of range"));
+    }
+}
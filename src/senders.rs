@@ -0,0 +1,109 @@
+//! Per-sender statistics for the inbox cleanup view.
+//!
+//! Groups the messages already loaded by sender address, so the senders
+//! taking the most messages or space, typically newsletters, can be archived,
+//! deleted, or muted in one go.
+
+use std::collections::HashMap;
+
+use crate::imap_client::Email;
+
+/// Messages of one sender among those loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SenderStats {
+    /// Sender address, lowercased.
+    pub address: String,
+    /// Display name of the sender's most recent message, if any.
+    pub name: Option<String>,
+    /// Number of messages.
+    pub count: usize,
+    /// Number of unread messages.
+    pub unread: usize,
+    /// Total size of the messages in bytes.
+    pub bytes: u64,
+}
+
+/// Returns the sender address of an email as used for grouping, lowercased.
+pub fn sender_address(email: &Email) -> Option<String> {
+    email
+        .from
+        .email
+        .as_ref()
+        .map(|address| address.to_lowercase())
+}
+
+/// Groups emails by sender, most messages first, or largest total size first
+/// with `by_size`. Emails without a sender address are left out.
+pub fn sender_stats(emails: &[Email], by_size: bool) -> Vec<SenderStats> {
+    let mut senders = HashMap::<String, SenderStats>::new();
+    // Emails are listed newest first, so the first name seen is the latest.
+    for email in emails {
+        let Some(address) = sender_address(email) else {
+            continue;
+        };
+        let stats = senders
+            .entry(address.clone())
+            .or_insert_with(|| SenderStats {
+                address,
+                name: email.from.name.clone(),
+                count: 0,
+                unread: 0,
+                bytes: 0,
+            });
+        stats.count += 1;
+        stats.unread += usize::from(!email.is_read);
+        stats.bytes += u64::from(email.size);
+    }
+
+    let mut senders = senders.into_values().collect::<Vec<_>>();
+    senders.sort_by(|a, b| {
+        let (a_key, b_key) = if by_size {
+            ((a.bytes, a.count as u64), (b.bytes, b.count as u64))
+        } else {
+            ((a.count as u64, a.bytes), (b.count as u64, b.bytes))
+        };
+        b_key.cmp(&a_key).then_with(|| a.address.cmp(&b.address))
+    });
+    senders
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imap_client::test_email;
+
+    fn email(from: &str, size: u32, is_read: bool) -> Email {
+        Email {
+            is_read,
+            size,
+            ..test_email(1, from)
+        }
+    }
+
+    #[test]
+    fn test_sender_stats() {
+        let emails = vec![
+            email("news@shop.example", 10_000, false),
+            email("alice@example.com", 500_000, true),
+            email("News@Shop.example", 20_000, true),
+            email("bob@example.com", 1_000, true),
+        ];
+
+        let by_count = sender_stats(&emails, false);
+        let summary = by_count
+            .iter()
+            .map(|s| (s.address.as_str(), s.count, s.unread, s.bytes))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                ("news@shop.example", 2, 1, 30_000),
+                ("alice@example.com", 1, 0, 500_000),
+                ("bob@example.com", 1, 0, 1_000),
+            ]
+        );
+
+        let by_size = sender_stats(&emails, true);
+        assert_eq!(by_size[0].address, "alice@example.com");
+    }
+}
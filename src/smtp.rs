@@ -0,0 +1,476 @@
+//! Sending mail over SMTP.
+//!
+//! Messages are sent with the configured Gmail credentials or auth command.
+//! When the server cannot be reached the draft is queued in the outbox
+//! instead of being lost.
+//!
+//! [`check_smtp`] goes through the same connection one stage at a time for
+//! `rutt doctor`, so a failing setup can be pinned down before composing.
+
+use anyhow::{Context, Result};
+use lettre::transport::smtp::authentication::{Credentials, Mechanism};
+use lettre::transport::smtp::client::{SmtpConnection, Tls, TlsParameters};
+use lettre::transport::smtp::commands::{Data, Ehlo, Mail, Rcpt};
+use lettre::transport::smtp::extension::{
+    ClientId, MailBodyParameter, MailParameter, RcptParameter,
+};
+use lettre::transport::smtp::response::Response;
+use lettre::{Message, SmtpTransport, Transport};
+use std::fmt;
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::address::requires_smtputf8;
+use crate::auth::Auth;
+use crate::compose::Draft;
+use crate::config::Config;
+use crate::dsn::NOTIFY_ALL;
+use crate::imap_client::ImapClient;
+use crate::outbox::Outbox;
+use crate::special_use::SpecialUse;
+
+/// How long sending waits for the SMTP server to answer before giving up,
+/// so a stalled connection cannot hang the interface.
+const SEND_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long `rutt doctor` waits for the SMTP server at each stage.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Result of attempting to send a draft.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SendOutcome {
+    /// The message was accepted by the SMTP server. Holds the raw message as
+    /// sent.
+    Sent(Vec<u8>),
+    /// The server was unreachable and the draft was queued for later.
+    Queued,
+}
+
+/// Builds an SMTP transport from the configuration, logging in with an
+/// already resolved password.
+fn transport(config: &Config, password: &str) -> Result<SmtpTransport> {
+    let smtp = &config.smtp;
+    let tls_parameters =
+        TlsParameters::new(smtp.host.clone()).context("Failed to create TLS parameters")?;
+    let tls = if smtp.starttls {
+        Tls::Required(tls_parameters)
+    } else {
+        Tls::Wrapper(tls_parameters)
+    };
+
+    Ok(SmtpTransport::builder_dangerous(smtp.host.as_str())
+        .port(smtp.port)
+        .tls(tls)
+        .timeout(Some(SEND_TIMEOUT))
+        .credentials(Credentials::new(
+            config.gmail.username.clone(),
+            password.to_string(),
+        ))
+        .build())
+}
+
+/// Sends a message through the configured SMTP server.
+fn send_message(config: &Config, message: &Message, password: &str) -> Result<()> {
+    transport(config, password)?
+        .send(message)
+        .context("Failed to send message")?;
+    Ok(())
+}
+
+/// Sends a message over a connection driven directly, for what the transport
+/// cannot do: authenticating with a ready SASL token, and requesting delivery
+/// status notifications for every recipient when `dsn` is set, since the
+/// transport cannot add parameters to `RCPT TO`. A server that does not
+/// advertise DSN gets the message without the request, as it would refuse
+/// the parameters.
+///
+/// The connection is set up like the transport's.
+fn send_message_direct(config: &Config, message: &Message, auth: &Auth, dsn: bool) -> Result<()> {
+    let smtp = &config.smtp;
+    let tls_parameters =
+        TlsParameters::new(smtp.host.clone()).context("Failed to create TLS parameters")?;
+    let hello = ClientId::default();
+    let envelope = message.envelope();
+    let raw = message.formatted();
+
+    let mut mail_parameters = Vec::new();
+    if envelope
+        .from()
+        .into_iter()
+        .chain(envelope.to())
+        .any(requires_smtputf8)
+    {
+        mail_parameters.push(MailParameter::SmtpUtfEight);
+    }
+    if !raw.is_ascii() {
+        mail_parameters.push(MailParameter::Body(MailBodyParameter::EightBitMime));
+    }
+
+    let send = || -> Result<(), lettre::transport::smtp::Error> {
+        let mut conn = SmtpConnection::connect(
+            (smtp.host.as_str(), smtp.port),
+            Some(SEND_TIMEOUT),
+            &hello,
+            (!smtp.starttls).then_some(&tls_parameters),
+            None,
+        )?;
+        if smtp.starttls {
+            conn.starttls(&tls_parameters, &hello)?;
+        }
+        // The connection keeps the extensions it knows of, which leave DSN
+        // out, so the server is asked again.
+        let dsn = dsn && advertises_dsn(&conn.command(Ehlo::new(hello.clone()))?);
+        authenticate(&mut conn, config, auth)?;
+
+        let mut parameters = mail_parameters.clone();
+        if dsn {
+            parameters.push(MailParameter::Other {
+                keyword: "RET".to_string(),
+                value: Some("HDRS".to_string()),
+            });
+        }
+        conn.command(Mail::new(envelope.from().cloned(), parameters))?;
+        for to in envelope.to() {
+            let parameters = if dsn {
+                vec![RcptParameter::Other {
+                    keyword: "NOTIFY".to_string(),
+                    value: Some(NOTIFY_ALL.to_string()),
+                }]
+            } else {
+                Vec::new()
+            };
+            conn.command(Rcpt::new(to.clone(), parameters))?;
+        }
+        conn.command(Data)?;
+        conn.message(&raw)?;
+        // The message is accepted; a failed QUIT does not matter.
+        let _ = conn.quit();
+        Ok(())
+    };
+    send().context("Failed to send message")
+}
+
+/// Returns whether an EHLO response lists the DSN extension (RFC 3461).
+fn advertises_dsn(ehlo: &Response) -> bool {
+    ehlo.message()
+        .skip(1)
+        .any(|line| line.split_whitespace().next() == Some("DSN"))
+}
+
+/// Authenticates an open connection with a password or a ready SASL token.
+fn authenticate(
+    conn: &mut SmtpConnection,
+    config: &Config,
+    auth: &Auth,
+) -> Result<(), lettre::transport::smtp::Error> {
+    match auth {
+        Auth::Password(password) => {
+            let credentials = Credentials::new(config.gmail.username.clone(), password.clone());
+            conn.auth(&[Mechanism::Plain, Mechanism::Login], &credentials)?;
+        }
+        Auth::XOAuth2(token) => {
+            let response = conn.command(format!("AUTH XOAUTH2 {}\r\n", token))?;
+            // A rejected token gets a challenge with the error details;
+            // answering it with an empty line yields the final error.
+            if response.has_code(334) {
+                conn.command("\r\n")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A stage of the SMTP connection checked by [`check_smtp`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpStage {
+    /// Resolving the host and opening a TCP connection.
+    Connect,
+    /// Negotiating TLS, implicitly or with STARTTLS.
+    Tls,
+    /// Logging in with the configured credentials.
+    Auth,
+    /// Sending a test message to the configured address.
+    SendTest,
+}
+
+impl fmt::Display for SmtpStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SmtpStage::Connect => "connect",
+            SmtpStage::Tls => "tls",
+            SmtpStage::Auth => "auth",
+            SmtpStage::SendTest => "send test",
+        })
+    }
+}
+
+/// Checks the SMTP setup stage by stage, passing each stage's outcome to
+/// `report` as it completes, and stops at the first failure. A test message
+/// to the configured address is sent last when `send_test` is set.
+///
+/// Returns whether every stage succeeded.
+pub fn check_smtp(
+    config: &Config,
+    send_test: bool,
+    mut report: impl FnMut(SmtpStage, &Result<String>),
+) -> bool {
+    let mut stage = |stage: SmtpStage, result: Result<String>| {
+        report(stage, &result);
+        result.is_ok()
+    };
+    let smtp = &config.smtp;
+    let hello = ClientId::default();
+    let tls_parameters = match TlsParameters::new(smtp.host.clone()) {
+        Ok(parameters) => parameters,
+        Err(e) => return stage(SmtpStage::Tls, Err(e.into())),
+    };
+
+    // With implicit TLS the handshake is part of connecting, so the TCP
+    // connection is first tried on its own to tell the two apart.
+    let address = format!("{}:{}", smtp.host, smtp.port);
+    if !smtp.starttls {
+        let reachable = tcp_connect(&smtp.host, smtp.port)
+            .map(|_| address.clone())
+            .with_context(|| format!("Failed to connect to {}", address));
+        if !stage(SmtpStage::Connect, reachable) {
+            return false;
+        }
+    }
+    let connected = SmtpConnection::connect(
+        (smtp.host.as_str(), smtp.port),
+        Some(CHECK_TIMEOUT),
+        &hello,
+        (!smtp.starttls).then_some(&tls_parameters),
+        None,
+    );
+    let mut conn = match connected {
+        Ok(conn) if smtp.starttls => {
+            stage(SmtpStage::Connect, Ok(address));
+            conn
+        }
+        Ok(conn) => {
+            stage(SmtpStage::Tls, Ok("implicit TLS".to_string()));
+            conn
+        }
+        Err(e) if smtp.starttls => {
+            let e = anyhow::Error::from(e).context(format!("Failed to connect to {}", address));
+            return stage(SmtpStage::Connect, Err(e));
+        }
+        Err(e) => {
+            let e = anyhow::Error::from(e).context("TLS handshake failed");
+            return stage(SmtpStage::Tls, Err(e));
+        }
+    };
+
+    if smtp.starttls {
+        let upgraded = conn
+            .starttls(&tls_parameters, &hello)
+            .map(|()| "STARTTLS".to_string())
+            .context("STARTTLS failed");
+        if !stage(SmtpStage::Tls, upgraded) {
+            return false;
+        }
+    }
+
+    let authenticated = Auth::from_config(&config.gmail).and_then(|auth| {
+        authenticate(&mut conn, config, &auth).context("Authentication failed")?;
+        Ok(format!("logged in as {}", config.gmail.username))
+    });
+    let _ = conn.quit();
+    if !stage(SmtpStage::Auth, authenticated) {
+        return false;
+    }
+
+    if send_test {
+        let draft = Draft {
+            to: config.gmail.username.clone(),
+            subject: "rutt test message".to_string(),
+            body: "This message was sent by `rutt doctor --send-test`.\n".to_string(),
+            ..Default::default()
+        };
+        let sent = send_draft(config, &draft).map(|_| format!("sent to {}", draft.to));
+        return stage(SmtpStage::SendTest, sent);
+    }
+    true
+}
+
+/// Connects to the first reachable address of a host, giving up on each
+/// after [`CHECK_TIMEOUT`].
+fn tcp_connect(host: &str, port: u16) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "host has no addresses");
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, CHECK_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+/// Returns true if a send error means the server could not be reached, as
+/// opposed to the server rejecting the message or credentials.
+fn is_connection_error(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<lettre::transport::smtp::Error>()
+        .is_some_and(|e| !(e.is_permanent() || e.is_client() || e.is_tls()))
+}
+
+/// Sends a draft, returning the raw message as sent.
+///
+/// Lettre negotiates SMTPUTF8 when a recipient has a UTF-8 local part; if the
+/// server lacks it, the send fails with an explanatory error. Delivery status
+/// notifications are requested when the draft asks for them and the server
+/// offers them. With an `auth_command`, the command is run for a fresh token
+/// on every send.
+pub fn send_draft(config: &Config, draft: &Draft) -> Result<Vec<u8>> {
+    let message = draft.to_message(&config.gmail.username)?;
+    let needs_smtputf8 = message.envelope().to().iter().any(requires_smtputf8);
+
+    let auth = Auth::from_config(&config.gmail)?;
+    let result = match &auth {
+        Auth::Password(password) if !draft.request_dsn => send_message(config, &message, password),
+        _ => send_message_direct(config, &message, &auth, draft.request_dsn),
+    };
+    result.map_err(|e| {
+        let smtp_error = e.downcast_ref::<lettre::transport::smtp::Error>();
+        let rejected = smtp_error.is_some_and(|e| e.is_client());
+        if needs_smtputf8 && rejected {
+            e.context("Recipient has a non-ASCII local part, which requires SMTPUTF8")
+        } else {
+            e
+        }
+    })?;
+    Ok(message.formatted())
+}
+
+/// Sends a draft, queueing it in the outbox if the server is unreachable.
+///
+/// The draft is queued before sending and removed once sent, so a crash
+/// while sending leaves it in the outbox to be sent at the next start.
+pub fn send_or_queue(config: &Config, outbox: &Outbox, draft: &Draft) -> Result<SendOutcome> {
+    let path = outbox.queue(draft)?;
+    match send_draft(config, draft) {
+        Ok(raw) => {
+            outbox
+                .remove(&path)
+                .context("Sent, but failed to remove the draft from the outbox")?;
+            Ok(SendOutcome::Sent(raw))
+        }
+        Err(e) if is_connection_error(&e) => Ok(SendOutcome::Queued),
+        Err(e) => {
+            outbox.remove(&path)?;
+            Err(e)
+        }
+    }
+}
+
+/// Stores a copy of a sent message in the Sent mailbox over IMAP
+/// (`sent_mailbox` under `[smtp]`, or the folder the server marks as Sent).
+pub fn save_to_sent(config: &Config, raw: &[u8]) -> Result<()> {
+    let mut client = ImapClient::connect_with_config(&config.gmail, &config.imap)
+        .context("Failed to connect to the IMAP server")?;
+    let sent = match &config.smtp.sent_mailbox {
+        Some(sent) => sent.clone(),
+        None => client.special_folder(SpecialUse::Sent),
+    };
+    client.append_message(&sent, raw)
+}
+
+/// What sending the drafts queued in the outbox came to.
+#[derive(Debug, Default)]
+pub struct Flushed {
+    /// How many drafts were sent.
+    pub sent: usize,
+    /// Why drafts could not be sent, or copies of sent ones not saved.
+    pub errors: Vec<anyhow::Error>,
+}
+
+/// Sends all drafts queued in the outbox, saving a copy of those that ask
+/// for it in the Sent mailbox.
+///
+/// A draft the server rejects stays queued and the others are still sent;
+/// once the server cannot be reached, the rest stay queued without trying.
+pub fn flush_outbox(config: &Config, outbox: &Outbox) -> Result<Flushed> {
+    let mut flushed = Flushed::default();
+
+    for (path, draft) in outbox.drafts()? {
+        let raw = match send_draft(config, &draft) {
+            Ok(raw) => raw,
+            Err(e) if is_connection_error(&e) => {
+                flushed.errors.push(e);
+                break;
+            }
+            Err(e) => {
+                flushed
+                    .errors
+                    .push(e.context(format!("Failed to send queued draft {:?}", path)));
+                continue;
+            }
+        };
+        outbox.remove(&path)?;
+        flushed.sent += 1;
+        if draft.save_sent
+            && let Err(e) = save_to_sent(config, &raw)
+        {
+            flushed
+                .errors
+                .push(e.context("Sent, but failed to save a copy to the Sent mailbox"));
+        }
+    }
+
+    Ok(flushed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_advertises_dsn() {
+        let ehlo = |extensions: &str| {
+            format!(
+                "250-smtp.example.com\r\n{}250 SIZE 35882577\r\n",
+                extensions
+            )
+            .parse::<Response>()
+            .unwrap()
+        };
+        assert!(advertises_dsn(&ehlo("250-8BITMIME\r\n250-DSN\r\n")));
+        assert!(!advertises_dsn(&ehlo("250-8BITMIME\r\n")));
+        // The greeting line is the server's name, not an extension.
+        let greeting = "250-DSN\r\n250 SIZE\r\n".parse::<Response>().unwrap();
+        assert!(!advertises_dsn(&greeting));
+    }
+
+    #[test]
+    fn test_check_smtp_stops_at_failed_stage() {
+        // A server that greets and answers EHLO without offering STARTTLS.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"220 localhost ESMTP\r\n").unwrap();
+            let mut ehlo = String::new();
+            BufReader::new(&stream).read_line(&mut ehlo).unwrap();
+            stream.write_all(b"250 localhost\r\n").unwrap();
+        });
+
+        let mut config = Config::default();
+        config.smtp.host = "127.0.0.1".to_string();
+        config.smtp.port = port;
+        config.smtp.starttls = true;
+        let mut stages = Vec::new();
+        let ok = check_smtp(&config, false, |stage, result| {
+            stages.push((stage, result.is_ok()));
+        });
+        server.join().unwrap();
+
+        assert!(!ok);
+        assert_eq!(
+            stages,
+            [(SmtpStage::Connect, true), (SmtpStage::Tls, false)]
+        );
+    }
+}
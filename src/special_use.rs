@@ -0,0 +1,215 @@
+//! Locates the special folders (Sent, Drafts, Trash, Junk, Archive, All
+//! Mail and Starred) from the SPECIAL-USE attributes servers mark them with
+//! (RFC 6154), instead of relying on Gmail's names.
+//!
+//! The attributes are read from a plain `LIST`, which RFC 6154 has servers
+//! answer with them too; the IMAP library cannot send `LIST ... RETURN
+//! (SPECIAL-USE)`. The folders found are remembered per account in
+//! `special_folders.toml` in the data directory, so they are still known
+//! when listing fails, and `[imap.folders]` in the config overrides any of
+//! them.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::utils::data_dir;
+
+/// A role a folder can have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecialUse {
+    /// Every message, like Gmail's All Mail.
+    All,
+    Archive,
+    Drafts,
+    /// Flagged messages, like Gmail's Starred.
+    Flagged,
+    Junk,
+    Sent,
+    Trash,
+}
+
+impl SpecialUse {
+    /// Every role, in the order they are listed.
+    pub const ALL: [SpecialUse; 7] = [
+        SpecialUse::All,
+        SpecialUse::Archive,
+        SpecialUse::Drafts,
+        SpecialUse::Flagged,
+        SpecialUse::Junk,
+        SpecialUse::Sent,
+        SpecialUse::Trash,
+    ];
+
+    /// Returns the `LIST` attribute marking a folder with this role.
+    pub fn attribute(self) -> &'static str {
+        match self {
+            SpecialUse::All => "\\All",
+            SpecialUse::Archive => "\\Archive",
+            SpecialUse::Drafts => "\\Drafts",
+            SpecialUse::Flagged => "\\Flagged",
+            SpecialUse::Junk => "\\Junk",
+            SpecialUse::Sent => "\\Sent",
+            SpecialUse::Trash => "\\Trash",
+        }
+    }
+
+    /// Returns the folder used when the server marks none, by Gmail's name
+    /// or the usual one elsewhere.
+    pub fn default_name(self, gmail: bool) -> &'static str {
+        match (self, gmail) {
+            (SpecialUse::All | SpecialUse::Archive, true) => "[Gmail]/All Mail",
+            (SpecialUse::Drafts, true) => "[Gmail]/Drafts",
+            (SpecialUse::Flagged, true) => "[Gmail]/Starred",
+            (SpecialUse::Junk, true) => "[Gmail]/Spam",
+            (SpecialUse::Sent, true) => "[Gmail]/Sent Mail",
+            (SpecialUse::Trash, true) => "[Gmail]/Trash",
+            (SpecialUse::All | SpecialUse::Archive, false) => "Archive",
+            (SpecialUse::Drafts, false) => "Drafts",
+            (SpecialUse::Flagged, false) => "Flagged",
+            (SpecialUse::Junk, false) => "Junk",
+            (SpecialUse::Sent, false) => "Sent",
+            (SpecialUse::Trash, false) => "Trash",
+        }
+    }
+}
+
+/// The folder found or configured for each role.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpecialFolders {
+    pub all: Option<String>,
+    pub archive: Option<String>,
+    pub drafts: Option<String>,
+    pub flagged: Option<String>,
+    pub junk: Option<String>,
+    pub sent: Option<String>,
+    pub trash: Option<String>,
+}
+
+impl SpecialFolders {
+    /// Finds the folders marked with each role among listed mailboxes and
+    /// their attributes. The first marked with a role wins.
+    pub fn detect<'a>(mailboxes: impl IntoIterator<Item = (&'a str, &'a [String])>) -> Self {
+        let mut folders = SpecialFolders::default();
+        for (name, attributes) in mailboxes {
+            for special in SpecialUse::ALL {
+                let marked = attributes
+                    .iter()
+                    .any(|a| a.eq_ignore_ascii_case(special.attribute()));
+                let slot = folders.slot(special);
+                if marked && slot.is_none() {
+                    *slot = Some(name.to_string());
+                }
+            }
+        }
+        folders
+    }
+
+    /// Returns the folder for a role, if known.
+    pub fn get(&self, special: SpecialUse) -> Option<&str> {
+        match special {
+            SpecialUse::All => self.all.as_deref(),
+            SpecialUse::Archive => self.archive.as_deref(),
+            SpecialUse::Drafts => self.drafts.as_deref(),
+            SpecialUse::Flagged => self.flagged.as_deref(),
+            SpecialUse::Junk => self.junk.as_deref(),
+            SpecialUse::Sent => self.sent.as_deref(),
+            SpecialUse::Trash => self.trash.as_deref(),
+        }
+    }
+
+    fn slot(&mut self, special: SpecialUse) -> &mut Option<String> {
+        match special {
+            SpecialUse::All => &mut self.all,
+            SpecialUse::Archive => &mut self.archive,
+            SpecialUse::Drafts => &mut self.drafts,
+            SpecialUse::Flagged => &mut self.flagged,
+            SpecialUse::Junk => &mut self.junk,
+            SpecialUse::Sent => &mut self.sent,
+            SpecialUse::Trash => &mut self.trash,
+        }
+    }
+}
+
+/// Special folders remembered per account.
+#[derive(Debug)]
+pub struct SpecialFolderStore {
+    path: PathBuf,
+    /// Folders keyed by `user@host`.
+    accounts: BTreeMap<String, SpecialFolders>,
+}
+
+impl SpecialFolderStore {
+    /// Opens the store at a path; a missing or unreadable file starts empty.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let accounts = fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default();
+        SpecialFolderStore { path, accounts }
+    }
+
+    /// Opens the store in the default application data directory.
+    pub fn open_default() -> Self {
+        Self::new(data_dir().join("special_folders.toml"))
+    }
+
+    /// Returns the folders remembered for an account.
+    pub fn get(&self, account: &str) -> Option<&SpecialFolders> {
+        self.accounts.get(account)
+    }
+
+    /// Remembers the folders of an account, writing the file when they
+    /// changed.
+    pub fn save(&mut self, account: &str, folders: &SpecialFolders) -> Result<()> {
+        if self.accounts.get(account) == Some(folders) {
+            return Ok(());
+        }
+        self.accounts.insert(account.to_string(), folders.clone());
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create directory {:?}", dir))?;
+        }
+        let contents =
+            toml::to_string(&self.accounts).context("Failed to serialize special folders")?;
+        fs::write(&self.path, contents).with_context(|| format!("Failed to write {:?}", self.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_detect_and_remember() {
+        let attrs = |list: &[&str]| list.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        let listed = [
+            ("INBOX", attrs(&[])),
+            ("Sent Items", attrs(&["\\HasNoChildren", "\\Sent"])),
+            ("Deleted Items", attrs(&["\\trash"])),
+            ("Old Trash", attrs(&["\\Trash"])),
+            ("Junk E-mail", attrs(&["\\Junk"])),
+            ("[Gmail]/Starred", attrs(&["\\HasNoChildren", "\\Flagged"])),
+        ];
+        let folders = SpecialFolders::detect(listed.iter().map(|(name, a)| (*name, a.as_slice())));
+        assert_eq!(folders.get(SpecialUse::Sent), Some("Sent Items"));
+        assert_eq!(folders.get(SpecialUse::Trash), Some("Deleted Items"));
+        assert_eq!(folders.get(SpecialUse::Junk), Some("Junk E-mail"));
+        assert_eq!(folders.get(SpecialUse::Flagged), Some("[Gmail]/Starred"));
+        assert_eq!(folders.get(SpecialUse::Drafts), None);
+        assert_eq!(SpecialUse::Drafts.default_name(false), "Drafts");
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("special_folders.toml");
+        let mut store = SpecialFolderStore::new(&path);
+        store.save("me@imap.example.com", &folders).unwrap();
+        let reopened = SpecialFolderStore::new(&path);
+        assert_eq!(reopened.get("me@imap.example.com"), Some(&folders));
+        assert_eq!(reopened.get("me@imap.gmail.com"), None);
+    }
+}
@@ -0,0 +1,175 @@
+//! Grouping emails into conversation threads.
+//!
+//! Messages are linked to their parent through the `References` and
+//! `In-Reply-To` headers. A message whose parent is not loaded starts its own
+//! thread. Threads keep the order of the email list, placed where their most
+//! recent message appears, and list their messages depth-first in
+//! chronological order, or newest first.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::config::ThreadOrder;
+use crate::imap_client::Email;
+
+/// A conversation thread.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Thread {
+    /// Key identifying the thread across rebuilds: the root's Message-ID, or
+    /// its UID when it has none.
+    pub key: String,
+    /// Indexes into the email list with their reply depth, in the thread's
+    /// order.
+    pub messages: Vec<(usize, usize)>,
+}
+
+impl Thread {
+    /// Returns the index of the thread's first message: its root, or its most
+    /// recent message when ordered newest first.
+    pub fn first(&self) -> usize {
+        self.messages[0].0
+    }
+
+    /// Returns whether every message of the thread has been read.
+    pub fn all_read(&self, emails: &[Email]) -> bool {
+        self.messages.iter().all(|&(idx, _)| emails[idx].is_read)
+    }
+}
+
+/// Returns the index of the parent of each email, if it is loaded.
+fn parents(emails: &[Email]) -> Vec<Option<usize>> {
+    let by_id = emails
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, email)| email.message_id.as_deref().map(|id| (id, idx)))
+        .collect::<HashMap<_, _>>();
+
+    let mut parents = emails
+        .iter()
+        .enumerate()
+        .map(|(idx, email)| {
+            email
+                .references
+                .iter()
+                .rev()
+                .chain(&email.in_reply_to)
+                .filter_map(|id| by_id.get(id.as_str()).copied())
+                .find(|&parent| parent != idx)
+        })
+        .collect::<Vec<_>>();
+
+    // Break reference cycles by detaching the message that closes them.
+    for start in 0..emails.len() {
+        let mut seen = HashSet::from([start]);
+        let mut current = start;
+        while let Some(parent) = parents[current] {
+            if !seen.insert(parent) {
+                parents[current] = None;
+                break;
+            }
+            current = parent;
+        }
+    }
+
+    parents
+}
+
+/// Groups emails into threads.
+///
+/// In [`ThreadOrder::NewestFirst`] the reply tree is flattened: the most
+/// recent message comes first and the others follow one level below it.
+pub fn build_threads(emails: &[Email], order: ThreadOrder) -> Vec<Thread> {
+    let parents = parents(emails);
+
+    let mut children = vec![Vec::new(); emails.len()];
+    for (idx, parent) in parents.iter().enumerate() {
+        if let Some(parent) = parent {
+            children[*parent].push(idx);
+        }
+    }
+    for list in &mut children {
+        list.sort_by_key(|&idx| emails[idx].date);
+    }
+
+    let mut threads = parents
+        .iter()
+        .enumerate()
+        .filter(|(_, parent)| parent.is_none())
+        .map(|(root, _)| {
+            let mut messages = Vec::new();
+            let mut stack = vec![(root, 0)];
+            while let Some((idx, depth)) = stack.pop() {
+                messages.push((idx, depth));
+                stack.extend(children[idx].iter().rev().map(|&child| (child, depth + 1)));
+            }
+            if order == ThreadOrder::NewestFirst {
+                messages.sort_by_key(|&(idx, _)| std::cmp::Reverse(emails[idx].date));
+                for (position, (_, depth)) in messages.iter_mut().enumerate() {
+                    *depth = position.min(1);
+                }
+            }
+            let key = emails[root]
+                .message_id
+                .clone()
+                .unwrap_or_else(|| format!("uid:{}", emails[root]._uid));
+            Thread { key, messages }
+        })
+        .collect::<Vec<_>>();
+
+    threads.sort_by_key(|thread| thread.messages.iter().map(|&(idx, _)| idx).min());
+    threads
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imap_client::test_email;
+    use chrono::{Duration, Local};
+
+    fn email(uid: u32, id: &str, parent: Option<&str>, hours_ago: i64) -> Email {
+        Email {
+            date: Local::now() - Duration::hours(hours_ago),
+            message_id: Some(id.to_string()),
+            in_reply_to: parent.map(str::to_string),
+            references: parent.map(|p| vec![p.to_string()]).unwrap_or_default(),
+            ..test_email(uid, "a@example.com")
+        }
+    }
+
+    #[test]
+    fn test_build_threads() {
+        // Newest first, as in the email list.
+        let emails = vec![
+            email(5, "<e>", Some("<b>"), 1),
+            email(4, "<d>", None, 2),
+            email(3, "<c>", Some("<a>"), 3),
+            email(2, "<b>", Some("<a>"), 4),
+            email(1, "<a>", None, 5),
+        ];
+
+        let threads = build_threads(&emails, ThreadOrder::Chronological);
+        assert_eq!(threads.len(), 2);
+
+        // The thread containing the newest message comes first.
+        assert_eq!(threads[0].key, "<a>");
+        assert_eq!(threads[0].messages, [(4, 0), (3, 1), (0, 2), (2, 1)]);
+        assert_eq!(threads[0].first(), 4);
+        assert_eq!(threads[1].messages, [(1, 0)]);
+
+        let threads = build_threads(&emails, ThreadOrder::NewestFirst);
+        assert_eq!(threads[0].key, "<a>");
+        assert_eq!(threads[0].messages, [(0, 0), (2, 1), (3, 1), (4, 1)]);
+        assert_eq!(threads[0].first(), 0);
+    }
+
+    #[test]
+    fn test_build_threads_reference_cycle() {
+        let emails = vec![
+            email(2, "<b>", Some("<a>"), 1),
+            email(1, "<a>", Some("<b>"), 2),
+        ];
+
+        let threads = build_threads(&emails, ThreadOrder::Chronological);
+        let total = threads.iter().map(|t| t.messages.len()).sum::<usize>();
+        assert_eq!(total, 2);
+    }
+}
@@ -0,0 +1,129 @@
+//! Network transfer accounting for the IMAP session.
+//!
+//! Wraps the connection stream to count bytes in both directions and keeps a
+//! running total of IMAP commands issued, so the UI can report per-session
+//! usage and warn before large downloads on metered connections.
+
+use imap::extensions::idle::SetReadTimeout;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Shared transfer counters for a session.
+#[derive(Debug, Default)]
+pub struct TransferStats {
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    commands: AtomicU64,
+}
+
+/// A point-in-time copy of [`TransferStats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransferSnapshot {
+    /// Bytes written to the server.
+    pub bytes_sent: u64,
+    /// Bytes read from the server.
+    pub bytes_received: u64,
+    /// IMAP commands issued.
+    pub commands: u64,
+}
+
+impl TransferStats {
+    /// Records that an IMAP command was issued.
+    pub fn record_command(&self) {
+        self.commands.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the current counter values.
+    pub fn snapshot(&self) -> TransferSnapshot {
+        TransferSnapshot {
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            commands: self.commands.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Returns whether transferring `bytes` would exceed a budget given in KiB.
+///
+/// No budget means no limit.
+pub fn exceeds_budget(bytes: u64, budget_kb: Option<u64>) -> bool {
+    budget_kb.is_some_and(|kb| bytes > kb.saturating_mul(1024))
+}
+
+/// A stream adapter counting the bytes read and written through it.
+#[derive(Debug)]
+pub struct CountingStream<S> {
+    inner: S,
+    stats: Arc<TransferStats>,
+}
+
+impl<S> CountingStream<S> {
+    /// Wraps a stream, recording traffic into `stats`.
+    pub fn new(inner: S, stats: Arc<TransferStats>) -> Self {
+        CountingStream { inner, stats }
+    }
+}
+
+impl<S: Read> Read for CountingStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.stats
+            .bytes_received
+            .fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+impl<S: Write> Write for CountingStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.stats.bytes_sent.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<S: SetReadTimeout> SetReadTimeout for CountingStream<S> {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> imap::Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_exceeds_budget() {
+        assert!(!exceeds_budget(10_000_000, None));
+        assert!(!exceeds_budget(1024, Some(1)));
+        assert!(exceeds_budget(1025, Some(1)));
+    }
+
+    #[test]
+    fn test_counting_stream() {
+        let stats = Arc::new(TransferStats::default());
+        let mut stream =
+            CountingStream::new(Cursor::new(b"* OK ready\r\n".to_vec()), stats.clone());
+
+        let mut buf = [0u8; 5];
+        stream.read_exact(&mut buf).unwrap();
+        stream.write_all(b"a1 NOOP\r\n").unwrap();
+        stats.record_command();
+
+        assert_eq!(
+            stats.snapshot(),
+            TransferSnapshot {
+                bytes_sent: 9,
+                bytes_received: 5,
+                commands: 1,
+            }
+        );
+    }
+}
@@ -0,0 +1,150 @@
+//! Translating the message being read with an external command.
+//!
+//! `:translate` pipes the body to the `command` under `[translate]`, any
+//! translator reading text on stdin and printing its translation, such as
+//! translate-shell or a local model. It runs in the background, as a
+//! translator usually goes over the network.
+
+use anyhow::{Context, Result, bail};
+use std::sync::mpsc::{Receiver, channel};
+use std::thread;
+use std::time::Duration;
+
+use crate::utils::pipe_through;
+
+/// Used as the source language when it is neither set nor detected.
+pub const AUTO_LANGUAGE: &str = "auto";
+
+/// A translated message body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Translation {
+    /// Language translated from.
+    pub from: String,
+    /// Language translated into.
+    pub to: String,
+    /// The translated text.
+    pub text: String,
+}
+
+/// A translation running in a background thread.
+#[derive(Debug)]
+pub struct TranslationJob {
+    uid: u32,
+    from: String,
+    to: String,
+    result: Receiver<Result<String>>,
+}
+
+impl TranslationJob {
+    /// Starts translating the body of the message with `uid` from one
+    /// language into another, with a translator command in which `{from}`
+    /// and `{to}` stand for the languages.
+    ///
+    /// The languages go into a shell command, so anything but a language
+    /// code such as "ja" or "pt-BR" is refused.
+    pub fn start(
+        uid: u32,
+        command: &str,
+        body: String,
+        from: &str,
+        to: &str,
+        timeout: Duration,
+    ) -> Result<Self> {
+        for language in [from, to] {
+            if !is_language_code(language) {
+                bail!("Not a language code: {:?}", language);
+            }
+        }
+        let command = translator_command(command, from, to);
+        let (tx, result) = channel();
+        thread::spawn(move || {
+            let translated = pipe_through(&command, &body, timeout).context("Translation failed");
+            let _ = tx.send(translated);
+        });
+        Ok(TranslationJob {
+            uid,
+            from: from.to_string(),
+            to: to.to_string(),
+            result,
+        })
+    }
+
+    /// Returns the UID of the message being translated.
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    /// Returns the translation once it is done, without blocking.
+    pub fn poll(&self) -> Option<Result<Translation>> {
+        let text = self.result.try_recv().ok()?;
+        Some(text.map(|text| Translation {
+            from: self.from.clone(),
+            to: self.to.clone(),
+            text,
+        }))
+    }
+}
+
+/// Returns whether text is a language code, letters and hyphens only.
+fn is_language_code(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(|c| c.is_ascii_alphabetic() || c == '-')
+}
+
+/// Fills the languages into a translator command.
+fn translator_command(command: &str, from: &str, to: &str) -> String {
+    command.replace("{from}", from).replace("{to}", to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn test_translator_command() {
+        assert_eq!(
+            translator_command("trans -b {from}:{to}", "ja", "en"),
+            "trans -b ja:en"
+        );
+        assert_eq!(translator_command("my-model", "auto", "en"), "my-model");
+    }
+
+    #[test]
+    fn test_language_code() {
+        assert!(is_language_code("ja") && is_language_code("pt-BR"));
+        assert!(is_language_code(AUTO_LANGUAGE));
+        for text in ["", "en; rm -rf ~", "$(id)", "en fr"] {
+            assert!(!is_language_code(text), "{text}");
+        }
+        let err = TranslationJob::start(1, "cat", String::new(), "ja", "`id`", Duration::ZERO)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Not a language code: \"`id`\"");
+    }
+
+    #[test]
+    fn test_translation_job() {
+        let job = TranslationJob::start(
+            7,
+            "echo \"{from}>{to}\"; tr a-z A-Z",
+            "hola\n".to_string(),
+            "es",
+            "en",
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let translation = loop {
+            if let Some(result) = job.poll() {
+                break result.unwrap();
+            }
+            assert!(Instant::now() < deadline, "translation not finished");
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(job.uid(), 7);
+        assert_eq!(translation.text, "es>en\nHOLA\n");
+        assert_eq!(
+            (translation.from.as_str(), translation.to.as_str()),
+            ("es", "en")
+        );
+    }
+}
@@ -3,16 +3,296 @@
 //! Handles email list state, view modes, and user navigation between list and
 //! detail views.
 
-use crate::gmail_client::{Email, GmailClient};
+use crate::attachment_save::{AttachmentSave, SaveItem};
+use crate::attachments::{
+    Attachment, extract_attachments, open_attachment, resolve_save_dir, safe_filename,
+    save_attachment, scan_attachment, unique_path,
+};
+use crate::body::{Alternatives, message_text};
+use crate::cache::MessageCache;
+use crate::compose::Draft;
+use crate::config::{
+    AfterRead, Config, ImapOverrides, InitialCursor, NewMailAlert, SortMode, TextPart, ThreadOrder,
+    save_setting,
+};
+use crate::config_watch::{ConfigWatcher, restart_required};
+use crate::contacts::AddressBook;
+use crate::display_filter;
+use crate::dsn::DeliveryReport;
+use crate::extract::{Summary, one_time_code, summarize};
+use crate::fetcher::{BodyFetcher, FetchedBody};
+use crate::folder_search::{FolderHit, FolderSearch};
+use crate::folders::{
+    FolderAction, FolderChoice, FolderTree, InboxTab, archive_folder, build_folder_tree,
+    folders_after, rank_folders, selectable_folders,
+};
+use crate::idle::MailWatcher;
+use crate::imap_client::{DEFAULT_FETCH_LIMIT, Email, ImapClient, MessageBody, NameAddr};
+use crate::index_export::{IndexEntry, IndexFormat, write_index};
+use crate::journal::{Journal, JournalEntry, JournalOp};
+use crate::known_hosts::{CertificateChanged, KnownHosts};
+use crate::language::detect_language;
+use crate::loader::{LoadFailure, LoadStage, Loaded, Loader};
+use crate::maildir::Maildir;
+use crate::offline::{OfflineMailbox, Reconnector};
+use crate::outbox::Outbox;
+use crate::participants::{Participant, participants};
+use crate::pattern::Pattern;
+use crate::senders::{SenderStats, sender_address, sender_stats};
+use crate::smtp::{SendOutcome, send_or_queue};
+use crate::special_use::SpecialUse;
+use crate::threads::{Thread, build_threads};
+use crate::transfer::exceeds_budget;
+use crate::translate::{AUTO_LANGUAGE, Translation, TranslationJob};
+use crate::ui::command::{self, Command};
+use crate::ui::keymap::{Binding, HELP, bindings, help_lines};
+use crate::ui::picker::{PickerFilter, fuzzy_matches, matching_folders};
+use crate::ui::status::StatusLine;
+use crate::updates::{UpdateCheck, UpdateStatus};
+use crate::urls::{Link, find_links, open_url};
+use crate::utils::{expand_tilde, format_bytes};
+use crate::warmup::{WarmUp, folders_to_warm};
+use anyhow::{Context, Result, bail};
+use chrono::{Datelike, Local};
 use ratatui::widgets::ListState;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Number of recently used folders ranked first by the change-folder prompt.
+const MAX_RECENT_FOLDERS: usize = 5;
+
+/// Frames of the spinner shown while a body is being fetched.
+const SPINNER: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+/// How long each spinner frame is shown.
+const SPINNER_FRAME: Duration = Duration::from_millis(100);
+
+/// Largest new message whose body is looked into for a one-time code, in
+/// bytes. Messages carrying codes are short; newsletters are not read.
+const CODE_PEEK_MAX_SIZE: u32 = 64 * 1024;
+
+/// How long a one-time code found in new mail can be copied from the list,
+/// about as long as such codes stay valid.
+const CODE_OFFER_TTL: Duration = Duration::from_secs(10 * 60);
 
 /// Application view modes for different UI states.
 #[derive(Debug, Clone)]
 pub(crate) enum ViewMode {
+    /// Progress of connecting and fetching the inbox at startup.
+    Loading,
     /// Email list view showing all emails.
     List,
     /// Email detail view showing specific email at index.
     Detail(usize),
+    /// Compose view for the draft being written.
+    Compose,
+    /// Session statistics view.
+    Stats,
+    /// Folder browser.
+    Folders,
+    /// Senders of the loaded messages, for bulk cleanup.
+    Cleanup,
+    /// Participants panel over the detail view of the email at index.
+    Participants(usize),
+    /// Picker of the links in the email at index, over its detail view.
+    Urls(usize),
+    /// Matches of a search across all folders.
+    Results,
+    /// Build information and the update check.
+    Version,
+}
+
+/// A quick filter narrowing the email list using the data already fetched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Filter {
+    /// Only unread messages.
+    Unread,
+    /// Only flagged (starred) messages.
+    Flagged,
+    /// Only messages sent to the user directly or in Cc.
+    ToMe,
+}
+
+impl Filter {
+    /// Name shown in the header.
+    pub fn name(self) -> &'static str {
+        match self {
+            Filter::Unread => "unread",
+            Filter::Flagged => "flagged",
+            Filter::ToMe => "@me",
+        }
+    }
+
+    /// Returns whether an email passes the filter for the given user address.
+    fn matches(self, email: &Email, me: &str) -> bool {
+        match self {
+            Filter::Unread => !email.is_read,
+            Filter::Flagged => email.is_flagged,
+            Filter::ToMe => email
+                .to
+                .iter()
+                .chain(&email.cc)
+                .filter_map(|addr| addr.email.as_deref())
+                .any(|addr| addr.eq_ignore_ascii_case(me)),
+        }
+    }
+}
+
+/// Single-line text input shown in place of the footer.
+#[derive(Debug, Clone)]
+pub(crate) struct Prompt {
+    /// Label displayed before the input (e.g. "Save to: ").
+    pub label: String,
+    /// Current input text.
+    pub input: String,
+    /// What to do with the input once submitted.
+    pub kind: PromptKind,
+}
+
+/// Action performed when a prompt is submitted.
+#[derive(Debug, Clone)]
+pub(crate) enum PromptKind {
+    /// Save the attachment to the entered path.
+    SaveAttachment(Attachment),
+    /// Open the attachment with the desktop's opener if the user answers
+    /// "y".
+    OpenAttachment(Attachment),
+    /// Report an attachment rejected by the scan command; either key moves
+    /// on to the next attachment.
+    ScanBlocked,
+    /// Perform a download exceeding the transfer budget if the user answers
+    /// "y".
+    ConfirmDownload(Download),
+    /// Add the picked label to the tagged messages, or else the selected
+    /// message or thread.
+    Label,
+    /// Remove the picked label from the tagged messages, or else the
+    /// selected message or thread.
+    Unlabel,
+    /// Move the tagged messages, or else the selected message or thread,
+    /// into the picked folder.
+    Move,
+    /// Tag the loaded messages matching the entered pattern, or untag all
+    /// when it is empty.
+    TagPattern,
+    /// Search the current folder with the entered Gmail query.
+    Search,
+    /// Run the entered `:` command.
+    Command,
+    /// Limit the list to the messages matching the entered pattern.
+    Pattern,
+    /// Open the best folder matching the input, or the folder browser when
+    /// the input is empty.
+    ChangeFolder,
+    /// Apply the operation to the whole thread if the user answers "y", or
+    /// only to the selected message otherwise.
+    ConfirmThread(MessageOp),
+    /// Move every loaded message from the sender to the trash if the user
+    /// answers "y".
+    ConfirmDeleteSender(String),
+    /// Move the tagged messages to the trash if the user answers "y".
+    ConfirmDeleteTagged,
+    /// Save the attachments of the listed messages into the directory if
+    /// the user answers "y".
+    ConfirmSaveAttachments(PathBuf),
+    /// Save the email at index to the entered path.
+    SaveMessage(usize),
+    /// Save the listed messages into the Maildir if the user answers "y".
+    ConfirmSaveMaildir(PathBuf),
+    /// Replay the operations left in the journal if the user answers "y",
+    /// or forget them otherwise.
+    ConfirmReplay(Vec<JournalEntry>),
+    /// Trust the certificate a server now presents if the user answers
+    /// "y", or stay offline otherwise.
+    ConfirmCertificate(CertificateChanged),
+}
+
+/// An operation on messages that can apply to a whole thread.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum MessageOp {
+    /// Set the `\Seen` flag.
+    MarkRead,
+    /// Clear the `\Seen` flag.
+    MarkUnread,
+    /// Move out of the inbox into All Mail, or the configured archive
+    /// folder.
+    Archive,
+    /// Add a Gmail label.
+    Label(String),
+    /// Remove a Gmail label.
+    Unlabel(String),
+    /// Move to the trash.
+    Delete,
+    /// Move into another folder.
+    Move(String),
+    /// Set the `\Flagged` flag.
+    Flag,
+    /// Clear the `\Flagged` flag.
+    Unflag,
+}
+
+impl MessageOp {
+    /// Returns the journaled form of the operation.
+    fn journal_op(&self) -> JournalOp {
+        match self {
+            MessageOp::MarkRead => JournalOp::MarkRead,
+            MessageOp::MarkUnread => JournalOp::MarkUnread,
+            MessageOp::Archive => JournalOp::Archive,
+            MessageOp::Label(label) => JournalOp::AddLabel {
+                label: label.clone(),
+            },
+            MessageOp::Unlabel(label) => JournalOp::RemoveLabel {
+                label: label.clone(),
+            },
+            MessageOp::Delete => JournalOp::Trash,
+            MessageOp::Move(folder) => JournalOp::MoveTo {
+                folder: folder.clone(),
+            },
+            MessageOp::Flag => JournalOp::Flag,
+            MessageOp::Unflag => JournalOp::Unflag,
+        }
+    }
+}
+
+/// A download that may need confirmation against the transfer budget.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Download {
+    /// Fetch the body of the email at index and open it.
+    Body(usize),
+    /// Fetch the email at index and save its attachments.
+    Attachments(usize),
+    /// Fetch the email at index and open its attachments.
+    OpenAttachments(usize),
+    /// Fetch the email at index and show its raw source.
+    Source(usize),
+}
+
+/// A row of the email list.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ListRow {
+    /// Index of the email shown on this row.
+    pub email: usize,
+    /// Index of the row's thread in threaded view.
+    pub thread: Option<usize>,
+    /// Reply depth within the thread, 0 outside threaded view.
+    pub depth: usize,
+    /// Number of messages folded into this row when its thread is collapsed.
+    pub hidden: usize,
+    /// Whether the row's message, or any message folded into it, is unread.
+    pub unread: bool,
+}
+
+/// A Gmail search the email list is limited to.
+#[derive(Debug)]
+struct Limit {
+    /// The search query, shown in the header.
+    query: String,
+    /// UIDs of the folder's messages matching the query.
+    uids: HashSet<u32>,
 }
 
 /// Main application state containing emails and UI state.
@@ -20,12 +300,199 @@ pub(crate) enum ViewMode {
 pub struct App {
     /// Vector of emails to display.
     pub emails: Vec<Email>,
-    /// Current selection state for the email list.
+    /// Rows of the email list, one per email or per collapsed thread.
+    pub(crate) rows: Vec<ListRow>,
+    /// Conversation threads, built in threaded view.
+    threads: Vec<Thread>,
+    /// Whether the list is grouped into threads.
+    pub threaded: bool,
+    /// Order of the list.
+    sort: SortMode,
+    /// Threads the user collapsed (true) or expanded (false), by thread key.
+    thread_folds: HashMap<String, bool>,
+    /// Current selection state for the email list (a row index).
     pub list_state: ListState,
-    /// Gmail client for potential future operations.
-    pub client: GmailClient,
+    /// Gmail client, absent when only composing a message.
+    pub client: Option<ImapClient>,
     /// Current view mode (list or detail).
     pub(crate) mode: ViewMode,
+    /// User configuration.
+    pub config: Config,
+    /// Watches the config file for live reloading, if enabled.
+    config_watcher: Option<ConfigWatcher>,
+    /// `[imap]` settings from the command line, kept over a reloaded config.
+    imap_overrides: ImapOverrides,
+    /// Watches the open mailbox for new mail with IMAP IDLE, if enabled.
+    mail_watcher: Option<MailWatcher>,
+    /// Fetches bodies in the background, when connected.
+    body_fetcher: Option<BodyFetcher>,
+    /// Syncs other folders into the cache while no key is pressed.
+    warm_up: Option<WarmUp>,
+    /// When a key was last pressed.
+    last_activity: Instant,
+    /// Whether the cache was warmed up since the last key press.
+    warmed: bool,
+    /// The cached mailbox listed when started without a connection.
+    offline: Option<OfflineMailbox>,
+    /// Tries to connect again while offline.
+    reconnector: Option<Reconnector>,
+    /// Flag changes made while offline, oldest first.
+    queued: Vec<JournalEntry>,
+    /// Operations are recorded here before they are sent to the server.
+    journal: Option<Journal>,
+    /// Connection and fetch of the inbox under way at startup.
+    loader: Option<Loader>,
+    /// Why the startup load failed, for the caller to fall back on.
+    load_failure: Option<LoadFailure>,
+    /// UIDs of messages whose bodies are being fetched.
+    loading: HashSet<u32>,
+    /// UIDs of new messages whose bodies are being looked into for a
+    /// one-time code.
+    peeking: HashSet<u32>,
+    /// One-time code found in new mail, with when it was found, for `y` to
+    /// copy from the list.
+    code_offer: Option<(String, Instant)>,
+    /// UIDs of messages next to the one read whose bodies are being fetched
+    /// ahead.
+    prefetching: HashSet<u32>,
+    /// Bodies fetched ahead without marking the messages read, by UID.
+    prefetched: HashMap<u32, MessageBody>,
+    /// UIDs of the folder's messages whose envelopes failed to load.
+    failed_uids: Vec<u32>,
+    /// Whether the mailbox changed and the list is due to be refreshed.
+    refresh_pending: bool,
+    /// UIDs of messages that arrived while running and are not opened yet.
+    new_mail: HashSet<u32>,
+    /// Whether new mail arrived since the last alert was given.
+    alert_pending: bool,
+    /// Text waiting to be put on the clipboard.
+    clipboard: Option<String>,
+    /// Search the list is limited to, set with `:limit`.
+    limit: Option<Limit>,
+    /// Quick filters in the order they were turned on.
+    filters: Vec<Filter>,
+    /// Local pattern the list is limited to, set with `l`.
+    pattern: Option<Pattern>,
+    /// Whether focus mode hides read messages and moves on to the next
+    /// unread one after each action.
+    focus: bool,
+    /// Senders listed by the cleanup view.
+    senders: Vec<SenderStats>,
+    /// Row of the cleanup view under the cursor.
+    sender_selected: usize,
+    /// Whether the cleanup view ranks senders by size rather than count.
+    senders_by_size: bool,
+    /// Search across all folders under way.
+    folder_search: Option<FolderSearch>,
+    /// Saving of many messages' attachments under way.
+    attachment_save: Option<AttachmentSave>,
+    /// Query and matches of the last search across all folders.
+    folder_results: Option<(String, Vec<FolderHit>)>,
+    /// Row of the all-folder results view under the cursor.
+    result_selected: usize,
+    /// Lookup of the latest release, started when the version view is
+    /// first opened with `check_updates` set.
+    update_check: Option<UpdateCheck>,
+    /// Translations shown in place of or beside the bodies of messages, by
+    /// UID.
+    translations: HashMap<u32, Translation>,
+    /// Translation under way.
+    translation_job: Option<TranslationJob>,
+    /// Everyone on the thread shown in the participants panel.
+    participants: Vec<Participant>,
+    /// Row of the participants panel under the cursor.
+    participant_selected: usize,
+    /// Address the detail view's thread is narrowed to, if any.
+    participant_filter: Option<String>,
+    /// Links in the message shown in the URL picker.
+    urls: Vec<Link>,
+    /// Row of the URL picker under the cursor, among the links matching
+    /// its filter.
+    url_selected: usize,
+    /// Filter typed into the open URL picker or folder browser.
+    picker: PickerFilter,
+    /// UIDs of the folder's messages Gmail marked as important.
+    important: HashSet<u32>,
+    /// Query whose results replace the folder's messages in the list.
+    search: Option<String>,
+    /// Active input prompt, if any. Takes all key input while open.
+    pub(crate) prompt: Option<Prompt>,
+    /// Notices shown in the status line, such as the outcome of an action.
+    pub(crate) status: StatusLine,
+    /// The view and folder last announced in screen reader mode.
+    announced: Option<String>,
+    /// Keys typed so far of an incomplete multi-key sequence.
+    pub pending_keys: String,
+    /// Number typed before the last key, as the `42` of `42G`.
+    pub count: Option<usize>,
+    /// When the first pending key was typed.
+    pending_since: Option<Instant>,
+    /// Delivery status reports among the opened emails, by UID.
+    pub(crate) reports: HashMap<u32, DeliveryReport>,
+    /// Plain-text and HTML versions of the opened emails having both, by
+    /// UID, with the version shown.
+    alternatives: HashMap<u32, (Alternatives, TextPart)>,
+    /// Every header of the opened emails, by UID.
+    headers: HashMap<u32, Vec<(String, String)>>,
+    /// Whether the detail view shows every header instead of the weeded
+    /// list.
+    full_headers: bool,
+    /// Folder browser state, loaded when the browser is opened.
+    pub folder_tree: Option<FolderTree>,
+    /// Selectable folders with unread counts in browser order, listed when
+    /// the change-folder prompt is first opened.
+    known_folders: Option<Vec<FolderChoice>>,
+    /// Folders offered by the open change-folder or label prompt, ranked.
+    folder_choices: Vec<FolderChoice>,
+    /// UIDs of the messages tagged in the open folder, which label
+    /// operations apply to.
+    tagged: BTreeSet<u32>,
+    /// Folders left this session, most recent first.
+    recent_folders: Vec<String>,
+    /// Commands entered at the `:` prompt this session, oldest first.
+    command_history: Vec<String>,
+    /// Entry of the command history shown in the `:` prompt, while going
+    /// through it with the arrow keys.
+    history_position: Option<usize>,
+    /// Folders listed by the sidebar with their unread counts, when it is
+    /// shown.
+    sidebar: Option<Vec<FolderChoice>>,
+    /// Row of the sidebar under its cursor.
+    sidebar_selected: usize,
+    /// UID and raw source of the message whose source is shown in detail
+    /// view instead of its body.
+    source: Option<(u32, String)>,
+    /// Attachments waiting to be prompted for a save location.
+    pending_attachments: VecDeque<Attachment>,
+    /// Whether the pending attachments are offered for opening instead.
+    opening_attachments: bool,
+    /// Whether the event loop should exit.
+    pub should_quit: bool,
+    /// When the session started.
+    pub started_at: Instant,
+
+    /// Number of the folder's most recent messages listed, grown as older
+    /// ones are loaded so refreshes keep them.
+    fetch_limit: u32,
+    /// Set when the cursor hit the bottom of the list, so older messages are
+    /// loaded once the "Loading" status has been drawn.
+    pub older_requested: bool,
+    /// Whether the list reaches the folder's oldest message.
+    all_loaded: bool,
+    /// UIDs of messages fetched out of order as the parent of a reply, which
+    /// loading older messages skips over.
+    fetched_parents: HashSet<u32>,
+    /// UIDs of the replies the detail view went to the parents of, most
+    /// recent last.
+    reply_trail: Vec<u32>,
+
+    // For compose mode:
+    /// Draft being composed.
+    pub draft: Option<Draft>,
+    /// Set when the draft should be opened in the external editor.
+    pub editor_requested: bool,
+    /// Exit the application once the draft is sent or aborted.
+    quit_after_compose: bool,
 
     // For list mode:
     /// Index of the first email shown at the top of the visible window.
@@ -35,734 +502,5249 @@ pub struct App {
     ///
     /// This is updated dynamically based on terminal size.
     pub visible_items: usize,
+    /// Whether each list row spans two lines, sender and date above the
+    /// subject, because the terminal is narrower than `ui.two_line_below`.
+    pub two_line_rows: bool,
 
     // For detail mode:
     /// Scroll offset for detail view content.
     pub detail_scroll_offset: u16,
+    /// Lines the detail view can scroll down at most, as of the last draw.
+    detail_max_scroll: u16,
+    /// Height of the detail view's text area, as of the last draw.
+    detail_page: u16,
+
+    // For the help overlay:
+    /// Scroll offset of the help overlay, while it is open over the view.
+    help_scroll: Option<usize>,
 }
 
 impl App {
     /// Creates a new application instance with provided emails.
-    pub fn new(client: GmailClient, emails: Vec<Email>) -> Self {
+    pub fn new(client: ImapClient, emails: Vec<Email>) -> Self {
+        let connector = client.connector();
+        let mut app = Self::with_client(Some(client), emails);
+        let filters = app.config.display_filters.clone();
+        app.body_fetcher = Some(BodyFetcher::new(connector, filters));
+        app.load_important();
+        app
+    }
+
+    /// Creates an application showing the progress of a startup load, which
+    /// lists the inbox once it is fetched.
+    pub fn starting(loader: Loader) -> Self {
+        let mut app = Self::with_client(None, Vec::new());
+        app.loader = Some(loader);
+        app.mode = ViewMode::Loading;
+        app
+    }
+
+    /// Creates an application listing the cached copy of a mailbox, for
+    /// when the server cannot be reached.
+    ///
+    /// Messages opened before can be read, and flag changes are queued until
+    /// connected again.
+    pub fn offline(mailbox: OfflineMailbox, emails: Vec<Email>) -> Self {
+        let mut app = Self::with_client(None, emails);
+        app.offline = Some(mailbox);
+        app
+    }
+
+    /// Connects again in the background while offline, replaying the
+    /// queued changes once connected.
+    pub fn with_reconnector(mut self, reconnector: Reconnector) -> Self {
+        self.reconnector = Some(reconnector);
+        self
+    }
+
+    /// Records operations in a journal before sending them to the server, so
+    /// those cut short can be replayed at the next start.
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Creates an application that only composes and sends a single draft,
+    /// without an IMAP connection.
+    ///
+    /// The draft is opened in the editor immediately and the application exits
+    /// once it is sent or aborted.
+    pub fn compose_only(draft: Draft) -> Self {
+        let mut app = Self::with_client(None, Vec::new());
+        app.mode = ViewMode::Compose;
+        app.draft = Some(draft);
+        app.editor_requested = true;
+        app.quit_after_compose = true;
+        app
+    }
+
+    fn with_client(client: Option<ImapClient>, emails: Vec<Email>) -> Self {
         let mut list_state = ListState::default();
         if !emails.is_empty() {
             list_state.select(Some(0));
         }
 
-        App {
+        let mut app = App {
             emails,
+            rows: Vec::new(),
+            threads: Vec::new(),
+            threaded: false,
+            sort: SortMode::DateDesc,
+            thread_folds: HashMap::new(),
             list_state,
             client,
             mode: ViewMode::List,
+            config: Config::default(),
+            config_watcher: None,
+            imap_overrides: ImapOverrides::default(),
+            mail_watcher: None,
+            warm_up: None,
+            last_activity: Instant::now(),
+            warmed: false,
+            body_fetcher: None,
+            offline: None,
+            reconnector: None,
+            queued: Vec::new(),
+            journal: None,
+            loader: None,
+            load_failure: None,
+            loading: HashSet::new(),
+            peeking: HashSet::new(),
+            code_offer: None,
+            prefetching: HashSet::new(),
+            prefetched: HashMap::new(),
+            failed_uids: Vec::new(),
+            refresh_pending: false,
+            new_mail: HashSet::new(),
+            alert_pending: false,
+            clipboard: None,
+            limit: None,
+            filters: Vec::new(),
+            pattern: None,
+            focus: false,
+            senders: Vec::new(),
+            sender_selected: 0,
+            senders_by_size: false,
+            folder_search: None,
+            attachment_save: None,
+            folder_results: None,
+            result_selected: 0,
+            update_check: None,
+            translations: HashMap::new(),
+            translation_job: None,
+            participants: Vec::new(),
+            participant_selected: 0,
+            participant_filter: None,
+            urls: Vec::new(),
+            url_selected: 0,
+            picker: PickerFilter::default(),
+            important: HashSet::new(),
+            search: None,
+            prompt: None,
+            status: StatusLine::default(),
+            announced: None,
+            pending_keys: String::new(),
+            count: None,
+            pending_since: None,
+            reports: HashMap::new(),
+            alternatives: HashMap::new(),
+            headers: HashMap::new(),
+            full_headers: false,
+            folder_tree: None,
+            known_folders: None,
+            folder_choices: Vec::new(),
+            tagged: BTreeSet::new(),
+            recent_folders: Vec::new(),
+            command_history: Vec::new(),
+            history_position: None,
+            sidebar: None,
+            sidebar_selected: 0,
+            source: None,
+            pending_attachments: VecDeque::new(),
+            opening_attachments: false,
+            should_quit: false,
+            started_at: Instant::now(),
+            draft: None,
+            fetch_limit: DEFAULT_FETCH_LIMIT,
+            older_requested: false,
+            all_loaded: false,
+            fetched_parents: HashSet::new(),
+            reply_trail: Vec::new(),
+            editor_requested: false,
+            quit_after_compose: false,
             scroll_offset: 0,
             visible_items: 0, // Will be updated when rendering.
+            two_line_rows: false,
             detail_scroll_offset: 0,
+            detail_max_scroll: 0,
+            detail_page: 0,
+            help_scroll: None,
+        };
+        app.rebuild_rows();
+        app
+    }
+
+    /// Returns the IMAP client, or an error when running without one.
+    fn client(&mut self) -> Result<&mut ImapClient> {
+        if self.offline.is_some() {
+            bail!("Offline; showing cached messages until connected again");
         }
+        self.client.as_mut().context("Not connected to IMAP server")
     }
 
-    /// Updates the number of visible items based on the current terminal window
-    /// height.
-    ///
-    /// This should be called whenever the terminal is resized or during
-    /// rendering.
-    pub fn set_visible_items(&mut self, height: usize) {
-        self.visible_items = height;
+    /// Sets the user configuration.
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.threaded = config.ui.threaded;
+        self.sort = config.ui.sort;
+        self.fetch_limit = config.imap.fetch_limit;
+        self.config = config;
+        self.rebuild_rows();
+        self.place_initial_cursor();
+        self
     }
 
-    /// Moves cursor to the next email in the list.
-    pub fn next(&mut self) {
-        if self.emails.is_empty() {
-            return;
-        }
+    /// Reloads the configuration whenever the watched file changes.
+    pub fn with_config_watcher(mut self, watcher: ConfigWatcher) -> Self {
+        self.config_watcher = Some(watcher);
+        self
+    }
 
-        let current_selected = self.list_state.selected().unwrap_or(0);
+    /// Keeps `[imap]` settings given on the command line over the config
+    /// when it is reloaded.
+    pub fn with_imap_overrides(mut self, overrides: ImapOverrides) -> Self {
+        self.imap_overrides = overrides;
+        self
+    }
 
-        if current_selected >= self.emails.len() - 1 {
-            // Already at the bottom, don't move.
-            return;
-        }
+    /// Watches the open mailbox for new mail, which is then added to the list
+    /// as it arrives.
+    pub fn with_mail_watcher(mut self, watcher: MailWatcher) -> Self {
+        self.mail_watcher = Some(watcher);
+        self
+    }
 
-        let new_selected = current_selected + 1;
-        self.list_state.select(Some(new_selected));
+    /// Returns whether the open mailbox is watched for new mail.
+    pub fn watching_mail(&self) -> bool {
+        self.mail_watcher.is_some()
+    }
 
-        // Only scroll window when cursor reaches the bottom edge.
-        if new_selected >= self.scroll_offset + self.visible_items {
-            self.scroll_offset = new_selected - self.visible_items + 1;
+    /// Returns whether the cached mailbox is listed without a connection.
+    pub fn is_offline(&self) -> bool {
+        self.offline.is_some()
+    }
+
+    /// Returns how many flag changes wait to be replayed.
+    pub fn queued_count(&self) -> usize {
+        self.queued.len()
+    }
+
+    /// Records a key press, putting off warming up the cache.
+    pub fn note_activity(&mut self) {
+        self.last_activity = Instant::now();
+        self.warmed = false;
+    }
+
+    /// Returns how long until the cache is warmed up, while it is due.
+    pub fn warm_up_timeout(&self) -> Option<Duration> {
+        let network = &self.config.network;
+        if !network.warm_up || !self.config.cache.enabled || self.warmed || self.client.is_none() {
+            return None;
         }
+        let idle = Duration::from_secs(network.warm_up_after_secs);
+        Some(idle.saturating_sub(self.last_activity.elapsed()))
     }
 
-    /// Moves cursor to the previous email in the list.
-    pub fn previous(&mut self) {
-        if self.emails.is_empty() {
+    /// Once no key was pressed for a while, syncs Sent, Starred and the
+    /// folders opened recently into the cache in the background, once until
+    /// the next key press.
+    pub fn warm_up_if_idle(&mut self) {
+        if !self.warm_up_timeout().is_some_and(|t| t.is_zero()) {
             return;
         }
+        self.warmed = true;
+        if self.warm_up.as_ref().is_some_and(WarmUp::is_running) {
+            return;
+        }
+        let Some(client) = self.client.as_mut() else {
+            return;
+        };
+        let special = [SpecialUse::Sent, SpecialUse::Flagged].map(|s| client.special_folder(s));
+        let recent = self.recent_folders.iter().cloned();
+        let folders = folders_to_warm(client.mailbox(), special.into_iter().chain(recent));
+        if folders.is_empty() {
+            return;
+        }
+        self.warm_up = Some(WarmUp::start(
+            client.connector(),
+            folders,
+            self.config.imap.fetch_limit,
+            self.config.network.transfer_budget_kb,
+        ));
+    }
 
-        let current_selected = self.list_state.selected().unwrap_or(0);
-
-        if current_selected == 0 {
-            // Already at the top, don't move.
+    /// While offline, goes back online once the background reconnection
+    /// succeeds: the queued flag changes are replayed and the list is
+    /// refreshed from the server. If the server presents another
+    /// certificate than the pinned one, asks whether to trust it.
+    pub fn reconnect_if_online(&mut self) {
+        let mut client = match self.reconnector.as_ref().and_then(Reconnector::connected) {
+            Some(Ok(client)) => client,
+            // Asked again on the next attempt if something else is being
+            // asked now.
+            Some(Err(changed)) if self.prompt.is_none() => {
+                self.prompt = Some(Prompt {
+                    label: format!(
+                        "The certificate of {} has changed (known {}, presented {}). \
+                         Trust it? (y/N): ",
+                        changed.host, changed.known, changed.presented
+                    ),
+                    input: String::new(),
+                    kind: PromptKind::ConfirmCertificate(changed),
+                });
+                return;
+            }
+            _ => return,
+        };
+        let Some(offline) = self.offline.take() else {
             return;
+        };
+        self.reconnector = None;
+        client.set_mailbox(offline.mailbox());
+
+        // A change that fails stays in the journal with those after it, to
+        // be offered again at the next start.
+        let queued = std::mem::take(&mut self.queued);
+        let mut replayed = 0;
+        let mut failure = None;
+        for entry in &queued {
+            if let Err(e) = entry.apply(&mut client) {
+                failure = Some(e);
+                break;
+            }
+            self.complete(entry);
+            replayed += 1;
+        }
+        self.notify(match failure {
+            None if queued.is_empty() => "Back online".to_string(),
+            None => format!("Back online; replayed {} changes", replayed),
+            Some(e) => format!(
+                "Back online, but replaying changes failed, leaving {} for the next start: {e:#}",
+                queued.len() - replayed
+            ),
+        });
+
+        self.body_fetcher = Some(BodyFetcher::new(
+            client.connector(),
+            self.config.display_filters.clone(),
+        ));
+        if self.config.network.idle {
+            self.mail_watcher = Some(MailWatcher::new(
+                &self.config.gmail,
+                &self.config.imap,
+                offline.mailbox(),
+            ));
         }
+        self.client = Some(client);
+        self.refresh_pending = true;
+    }
 
-        let new_selected = current_selected - 1;
-        self.list_state.select(Some(new_selected));
+    /// Returns what the startup load is doing, while it runs.
+    pub(crate) fn load_stage(&self) -> Option<LoadStage> {
+        self.loader.as_ref().map(Loader::stage)
+    }
 
-        // Only scroll window when cursor reaches the top edge.
-        if new_selected < self.scroll_offset {
-            self.scroll_offset = new_selected;
+    /// Takes in the progress of the startup load, listing the inbox once it
+    /// is fetched. If it failed, quits with the failure kept for
+    /// [`take_load_failure`](Self::take_load_failure).
+    pub fn poll_loader(&mut self) {
+        let Some(result) = self.loader.as_mut().and_then(Loader::poll) else {
+            return;
+        };
+        self.loader = None;
+        match result {
+            Ok(loaded) => self.finish_loading(loaded),
+            Err(failure) => {
+                self.load_failure = Some(failure);
+                self.should_quit = true;
+            }
         }
     }
 
-    /// Switches to detail view for the currently selected email.
-    pub fn view_email(&mut self) {
-        if let Some(selected) = self.list_state.selected() {
-            if selected < self.emails.len() {
-                // Fetch email body if not already loaded
-                if self.emails[selected].body.is_none() {
-                    if let Ok(body) = self.client.fetch_email_body(self.emails[selected]._uid) {
-                        self.emails[selected].body = Some(body);
-                    }
+    /// Returns why the startup load failed, if it did.
+    pub fn take_load_failure(&mut self) -> Option<LoadFailure> {
+        self.load_failure.take()
+    }
+
+    /// Lists the fetched inbox and starts what watches it, as when created
+    /// connected.
+    fn finish_loading(&mut self, loaded: Loaded) {
+        let Loaded {
+            client,
+            fetched,
+            sent,
+        } = loaded;
+        match sent {
+            Ok(flushed) => {
+                if flushed.sent > 0 {
+                    self.notify(format!("Sent {} queued messages", flushed.sent));
+                }
+                for e in flushed.errors {
+                    self.notify(format!("Error: {e:#}"));
                 }
-                self.mode = ViewMode::Detail(selected);
             }
+            Err(e) => self.notify(format!("Error: failed to send queued messages: {e:#}")),
         }
-    }
 
-    /// Returns to the email list view from detail view.
-    pub fn back_to_list(&mut self) {
+        self.body_fetcher = Some(BodyFetcher::new(
+            client.connector(),
+            self.config.display_filters.clone(),
+        ));
+        if self.config.network.idle {
+            self.mail_watcher = Some(MailWatcher::new(
+                &self.config.gmail,
+                &self.config.imap,
+                client.mailbox(),
+            ));
+        }
+        self.client = Some(client);
+        self.emails = fetched.emails;
+        self.set_failed(fetched.failed);
+        self.load_important();
+        self.rebuild_rows();
+        self.place_initial_cursor();
         self.mode = ViewMode::List;
 
-        // Reset detail scroll when going back to list.
-        self.detail_scroll_offset = 0;
+        if self.config.ui.sidebar {
+            self.show_sidebar();
+        }
+        self.run_startup_commands();
+        self.offer_replay();
     }
 
-    /// Moves cursor to the top of the visible window.
-    pub fn goto_page_top(&mut self) {
-        if self.emails.is_empty() {
+    /// Asks whether to replay the operations recorded but not completed
+    /// when the last run was cut short, unless a startup command is asking
+    /// something already; they are then offered again next time.
+    fn offer_replay(&mut self) {
+        if self.prompt.is_some() {
             return;
         }
+        let pending = match self.journal.as_ref().map(Journal::pending) {
+            Some(Ok(pending)) if !pending.is_empty() => pending,
+            Some(Err(e)) => {
+                self.notify(format!("Error: {e:#}"));
+                return;
+            }
+            _ => return,
+        };
+        let label = match pending.as_slice() {
+            [entry] => format!("Not completed last time: {}. Replay it? (y/N): ", entry),
+            _ => format!(
+                "{} operations were not completed last time. Replay them? (y/N): ",
+                pending.len()
+            ),
+        };
+        self.prompt = Some(Prompt {
+            label,
+            input: String::new(),
+            kind: PromptKind::ConfirmReplay(pending),
+        });
+    }
 
-        // Move to the first visible item in the current window.
-        self.list_state.select(Some(self.scroll_offset));
+    /// Stops trying to connect to a server presenting a certificate the user
+    /// did not trust, staying on the cached inbox.
+    fn distrust_certificate(&mut self) {
+        self.reconnector = None;
+        self.notify("Kept the known certificate; staying offline until restarted".to_string());
     }
 
-    /// Moves cursor to the middle of the visible window.
-    pub fn goto_page_middle(&mut self) {
-        if self.emails.is_empty() {
-            return;
+    /// Replays operations left in the journal, forgetting each whether it
+    /// succeeds or not, so a message deleted meanwhile is not retried
+    /// forever. The list is refreshed to show their effect.
+    fn replay(&mut self, entries: Vec<JournalEntry>) {
+        let mut failed = 0;
+        for entry in &entries {
+            let applied = self.client().and_then(|client| entry.apply(client));
+            if let Err(e) = applied {
+                self.notify(format!("Error: failed to {}: {e:#}", entry));
+                failed += 1;
+            }
+            self.complete(entry);
+        }
+        self.notify(format!("Replayed {} operations", entries.len() - failed));
+        self.refresh_pending = true;
+    }
+
+    /// Forgets the operations left in the journal.
+    fn discard_replay(&mut self) {
+        if let Some(Err(e)) = self.journal.as_ref().map(Journal::clear) {
+            self.notify(format!("Error: {e:#}"));
         }
+    }
 
-        // Calculate the middle of the visible window
-        let window_end = (self.scroll_offset + self.visible_items).min(self.emails.len());
-        let window_size = window_end - self.scroll_offset;
-        let middle_offset = window_size / 2;
-        let middle_index = self.scroll_offset + middle_offset;
+    /// Shows a notice in the status line, after any already waiting.
+    pub(crate) fn notify(&mut self, text: String) {
+        self.status.push(text);
+    }
 
-        // Ensure we don't go past the last email
-        let target_index = middle_index.min(self.emails.len() - 1);
-        self.list_state.select(Some(target_index));
+    /// Returns the account's `[tag] ` for prefixing notifications, or nothing
+    /// when no tag is configured.
+    fn account_prefix(&self) -> String {
+        self.config
+            .gmail
+            .tag
+            .as_ref()
+            .map(|tag| format!("[{}] ", tag))
+            .unwrap_or_default()
     }
 
-    /// Moves cursor to the bottom of the visible window.
-    pub fn goto_page_bottom(&mut self) {
-        if self.emails.is_empty() {
-            return;
+    /// Returns the alert to give for new mail that arrived since the last
+    /// call, if one is configured.
+    pub(crate) fn take_new_mail_alert(&mut self) -> Option<NewMailAlert> {
+        let alert = self.config.ui.new_mail_alert;
+        (std::mem::take(&mut self.alert_pending) && alert != NewMailAlert::Off).then_some(alert)
+    }
+
+    /// Returns the text to put on the clipboard since the last call.
+    pub(crate) fn take_clipboard(&mut self) -> Option<String> {
+        self.clipboard.take()
+    }
+
+    /// Returns the terminal window title: the folder and its loaded unread
+    /// messages, e.g. "rutt: INBOX (3 unread)".
+    pub(crate) fn window_title(&self) -> String {
+        let mailbox = self.client.as_ref().map_or("INBOX", |c| c.mailbox());
+        let unread = self.emails.iter().filter(|email| !email.is_read).count();
+        let mut title = format!("{}rutt: {}", self.account_prefix(), mailbox);
+        if unread > 0 {
+            title.push_str(&format!(" ({} unread)", unread));
         }
+        title
+    }
 
-        // Move to the last visible item in the current window
-        let last_visible = (self.scroll_offset + self.visible_items - 1).min(self.emails.len() - 1);
-        self.list_state.select(Some(last_visible));
+    /// Returns the number of messages that arrived while running and have not
+    /// been opened.
+    pub(crate) fn new_mail_count(&self) -> usize {
+        self.new_mail.len()
     }
 
-    /// Moves forward one page.
-    pub fn page_forward(&mut self) {
-        if self.emails.is_empty() {
-            return;
+    /// Refreshes the list if the watched mailbox changed.
+    ///
+    /// The refresh waits until the list view is shown without a prompt, since
+    /// the detail view and prompts refer to emails by their position.
+    pub fn refresh_if_mail_changed(&mut self) {
+        if let Some(watcher) = &self.mail_watcher
+            && watcher.changed()
+        {
+            self.refresh_pending = true;
+        }
+        // Search results are not a fetch of the mailbox; the refresh waits
+        // until the folder is shown again.
+        if self.refresh_pending
+            && matches!(self.mode, ViewMode::List)
+            && self.prompt.is_none()
+            && self.search.is_none()
+        {
+            self.refresh_pending = false;
+            self.refresh_emails();
         }
+    }
 
-        // Calculate new scroll offset (one page forward)
-        let new_offset = (self.scroll_offset + self.visible_items)
-            .min(self.emails.len().saturating_sub(self.visible_items));
+    /// Fetches the open mailbox again, keeping the cursor on the same email
+    /// and noting messages that are new.
+    fn refresh_emails(&mut self) {
+        let limit = self.fetch_limit;
+        let fetched = match self.client().and_then(|c| c.fetch_emails(limit)) {
+            Ok(fetched) => fetched,
+            Err(e) => {
+                self.notify(format!("Error: {e:#}"));
+                return;
+            }
+        };
+        // Parents fetched out of order are not among the messages fetched
+        // again.
+        self.fetched_parents.clear();
+        self.set_emails(fetched.emails);
+        self.set_failed(fetched.failed);
+        self.load_important();
+    }
 
-        // If we can scroll forward
-        if new_offset != self.scroll_offset {
-            self.scroll_offset = new_offset;
-            // Move cursor to the top of the new page
-            self.list_state.select(Some(self.scroll_offset));
-        } else {
-            // Already at the bottom, move cursor to last email
-            self.list_state.select(Some(self.emails.len() - 1));
+    /// Records the messages of the folder whose envelopes failed to load,
+    /// telling how many did when that changed.
+    fn set_failed(&mut self, failed: Vec<u32>) {
+        if !failed.is_empty() && failed != self.failed_uids {
+            self.notify(format!(
+                "{}/{} loaded, {} failed (press r to retry)",
+                self.emails.len(),
+                self.emails.len() + failed.len(),
+                failed.len()
+            ));
         }
+        self.failed_uids = failed;
     }
 
-    /// Moves backward one page.
-    pub fn page_backward(&mut self) {
-        if self.emails.is_empty() {
+    /// Fetches again the envelopes that failed to load, adding those that
+    /// arrive to the list.
+    pub fn retry_failed(&mut self) {
+        if self.failed_uids.is_empty() {
+            self.notify("No messages failed to load".to_string());
             return;
         }
+        let uids = self.failed_uids.clone();
+        let fetched = match self.client().and_then(|c| c.retry_envelopes(&uids)) {
+            Ok(fetched) => fetched,
+            Err(e) => {
+                self.notify(format!("Error: {e:#}"));
+                return;
+            }
+        };
+        let selected = self.selected_email().map(|idx| self.emails[idx]._uid);
+        let loaded = fetched.emails.len();
+        self.emails.extend(fetched.emails);
+        self.emails.sort_by_key(|e| std::cmp::Reverse(e.date));
+        self.rebuild_rows();
+        if let Some(idx) = selected.and_then(|uid| self.emails.iter().position(|e| e._uid == uid)) {
+            self.select_email(idx);
+        }
+        self.failed_uids = fetched.failed;
+        self.notify(match self.failed_uids.len() {
+            0 => format!("Loaded the {} remaining messages", loaded),
+            failed => format!(
+                "{} more loaded, {} still failed (press r to retry)",
+                loaded, failed
+            ),
+        });
+    }
 
-        // Calculate new scroll offset (one page backward)
-        let new_offset = self.scroll_offset.saturating_sub(self.visible_items);
+    /// Looks up which messages of the open folder Gmail marked as important.
+    ///
+    /// Servers without Gmail's extensions just show no markers.
+    fn load_important(&mut self) {
+        self.important = self
+            .client()
+            .and_then(|c| {
+                if !c.is_gmail() {
+                    return Ok(Vec::new());
+                }
+                let mailbox = c.mailbox().to_string();
+                c.search(&mailbox, InboxTab::Important.query())
+            })
+            .map(|uids| uids.into_iter().collect())
+            .unwrap_or_default();
+    }
 
-        // Update scroll offset and move cursor to top of new page
-        self.scroll_offset = new_offset;
-        self.list_state.select(Some(self.scroll_offset));
+    /// Returns whether Gmail marked the email at index as important.
+    pub fn is_important(&self, idx: usize) -> bool {
+        self.emails
+            .get(idx)
+            .is_some_and(|email| self.important.contains(&email._uid))
     }
 
-    /// Scrolls the window down by half a page.
-    pub fn half_page_forward(&mut self) {
-        if self.emails.is_empty() {
-            return;
+    /// Replaces the listed emails with a newer fetch of the same mailbox.
+    ///
+    /// Bodies already loaded are kept, and emails not listed before are
+    /// counted as new mail.
+    fn set_emails(&mut self, mut emails: Vec<Email>) {
+        let selected = self.selected_email().map(|idx| self.emails[idx]._uid);
+        let mut bodies = self
+            .emails
+            .iter_mut()
+            .filter_map(|email| Some((email._uid, email.body.take()?)))
+            .collect::<HashMap<_, _>>();
+
+        let mut arrived = Vec::new();
+        for email in &mut emails {
+            match bodies.remove(&email._uid) {
+                Some(body) => email.body = Some(body),
+                None if !self.emails.iter().any(|e| e._uid == email._uid) => {
+                    self.new_mail.insert(email._uid);
+                    arrived.push(email._uid);
+                }
+                None => {}
+            }
+        }
+        self.new_mail
+            .retain(|uid| emails.iter().any(|email| email._uid == *uid));
+
+        self.emails = emails;
+        self.rebuild_rows();
+        match selected.and_then(|uid| self.emails.iter().position(|e| e._uid == uid)) {
+            Some(idx) => {
+                self.select_email(idx);
+            }
+            None if self.rows.is_empty() => self.list_state.select(None),
+            None => self.select_row(0),
+        }
+        if !arrived.is_empty() {
+            self.alert_pending = true;
+            self.notify(match arrived.len() {
+                1 => format!("{}1 new message", self.account_prefix()),
+                n => format!("{}{} new messages", self.account_prefix(), n),
+            });
+            self.look_for_codes(&arrived);
         }
+    }
 
-        let half_page = (self.visible_items / 2).max(1);
-        let current_selected = self.list_state.selected().unwrap_or(0);
-        let current_position_in_window = current_selected.saturating_sub(self.scroll_offset);
+    /// Looks for one-time codes in new mail: in the subject at once, else in
+    /// the body of a small message, fetched in the background without
+    /// marking it read.
+    fn look_for_codes(&mut self, uids: &[u32]) {
+        let budget = self.config.network.transfer_budget_kb;
+        for &uid in uids {
+            let Some(email) = self.emails.iter().find(|e| e._uid == uid) else {
+                continue;
+            };
+            if let Some(code) = one_time_code(&email.subject, "") {
+                self.offer_code(uid, code);
+                continue;
+            }
+            if email.size > CODE_PEEK_MAX_SIZE || exceeds_budget(email.size.into(), budget) {
+                continue;
+            }
+            if let Some(fetcher) = &self.body_fetcher
+                && let Some(client) = &self.client
+            {
+                fetcher.peek(client.mailbox(), uid, email.from.email.as_deref());
+                self.peeking.insert(uid);
+            }
+        }
+    }
 
-        // Scroll the window down by half a page
-        let new_scroll_offset = (self.scroll_offset + half_page)
-            .min(self.emails.len().saturating_sub(self.visible_items));
+    /// Offers a one-time code found in new mail for copying from the list.
+    fn offer_code(&mut self, uid: u32, code: String) {
+        let from = self
+            .emails
+            .iter()
+            .find(|e| e._uid == uid)
+            .and_then(|e| e.from.name.clone().or_else(|| e.from.email.clone()))
+            .unwrap_or_default();
+        self.notify(format!(
+            "{}Code {} from {} (y: copy)",
+            self.account_prefix(),
+            code,
+            from
+        ));
+        self.code_offer = Some((code, Instant::now()));
+    }
 
-        // Try to keep cursor at the same relative position in the window
-        let new_selected =
-            (new_scroll_offset + current_position_in_window).min(self.emails.len() - 1);
+    /// Copies the one-time code last found in new mail, while it is fresh.
+    pub fn copy_code(&mut self) {
+        let fresh = self
+            .code_offer
+            .as_ref()
+            .filter(|(_, found)| found.elapsed() < CODE_OFFER_TTL)
+            .map(|(code, _)| code.clone());
+        match fresh {
+            Some(code) => {
+                self.notify(format!("Copied one-time code: {}", code));
+                self.clipboard = Some(code);
+            }
+            None => self.notify("No one-time code in new mail".to_string()),
+        }
+    }
 
-        self.scroll_offset = new_scroll_offset;
-        self.list_state.select(Some(new_selected));
+    /// Returns whether the config file is watched for changes.
+    pub fn watching_config(&self) -> bool {
+        self.config_watcher.is_some()
     }
 
-    /// Scrolls the window up by half a page.
-    pub fn half_page_backward(&mut self) {
-        if self.emails.is_empty() {
+    /// Reloads the config file if it changed, reporting the outcome in the
+    /// status line.
+    ///
+    /// An invalid file leaves the current configuration in place.
+    pub fn reload_config_if_changed(&mut self) {
+        let Some(watcher) = &self.config_watcher else {
             return;
+        };
+        if !watcher.changed() {
+            return;
+        }
+        match Config::load_profile(watcher.path(), self.config.profile.as_deref()) {
+            Ok(config) => self.apply_config(config),
+            Err(e) => self.notify(format!("Config not reloaded: {e:#}")),
         }
+    }
 
-        let half_page = (self.visible_items / 2).max(1);
-        let current_selected = self.list_state.selected().unwrap_or(0);
-        let current_position_in_window = current_selected.saturating_sub(self.scroll_offset);
+    /// Applies a reloaded configuration.
+    ///
+    /// Sections that need a restart keep their current values until then, and
+    /// settings given on the command line still take precedence.
+    fn apply_config(&mut self, mut config: Config) {
+        self.imap_overrides.apply(&mut config.imap);
+        let pending = restart_required(&self.config, &config);
+        config.gmail = self.config.gmail.clone();
+        if config.network.idle != self.config.network.idle {
+            self.mail_watcher = match (&self.client, config.network.idle) {
+                (Some(client), true) => Some(MailWatcher::new(
+                    &config.gmail,
+                    &config.imap,
+                    client.mailbox(),
+                )),
+                _ => None,
+            };
+        }
+        if let Some(fetcher) = &self.body_fetcher {
+            fetcher.set_filters(config.display_filters.clone());
+        }
+        let reorder = config.ui.thread_order != self.config.ui.thread_order
+            || config.ui.sort != self.config.ui.sort;
+        if config.ui.sort != self.config.ui.sort {
+            self.sort = config.ui.sort;
+        }
+        self.config = config;
+        if reorder {
+            self.rebuild_rows();
+        }
+        self.notify(if pending.is_empty() {
+            "Config reloaded".to_string()
+        } else {
+            format!(
+                "Config reloaded; restart to apply [{}]",
+                pending.join("], [")
+            )
+        });
+    }
 
-        // Scroll the window up by half a page
-        let new_scroll_offset = self.scroll_offset.saturating_sub(half_page);
+    /// Updates the number of visible items based on the current terminal window
+    /// height.
+    ///
+    /// This should be called whenever the terminal is resized or during
+    /// rendering. The window is scrolled if needed to keep the cursor visible.
+    pub fn set_visible_items(&mut self, height: usize) {
+        self.visible_items = height;
 
-        // Try to keep cursor at the same relative position in the window
-        let new_selected = new_scroll_offset + current_position_in_window;
+        if let Some(selected) = self.list_state.selected()
+            && height > 0
+            && selected >= self.scroll_offset + height
+        {
+            self.scroll_offset = selected + 1 - height;
+        }
+    }
 
-        self.scroll_offset = new_scroll_offset;
-        self.list_state.select(Some(new_selected));
+    /// Updates the row layout and number of visible items for a list area of
+    /// the given size.
+    ///
+    /// Below the `ui.two_line_below` width each row takes two lines, so half
+    /// as many rows fit.
+    pub fn set_list_area(&mut self, width: u16, height: u16) {
+        // A row split over two lines would be read as two.
+        self.two_line_rows = !self.config.ui.screen_reader
+            && self
+                .config
+                .ui
+                .two_line_below
+                .is_some_and(|threshold| width < threshold);
+        let row_height = if self.two_line_rows { 2 } else { 1 };
+        self.set_visible_items(height as usize / row_height);
     }
 
-    /// Scrolls the window down by one line.
-    pub fn line_forward(&mut self) {
-        if self.emails.is_empty() {
-            return;
+    /// Moves the cursor to a row, scrolling the window up if needed.
+    ///
+    /// Scrolling down happens on the next render, see
+    /// [`set_visible_items`](Self::set_visible_items).
+    fn select_row(&mut self, row: usize) {
+        self.list_state.select(Some(row));
+        if row < self.scroll_offset {
+            self.scroll_offset = row;
         }
+    }
 
-        let current_selected = self.list_state.selected().unwrap_or(0);
-
-        // Check if cursor is at the top of the visible window
-        let cursor_at_top = current_selected == self.scroll_offset;
+    /// Moves the cursor to the row showing an email, or to the collapsed
+    /// thread containing it.
+    fn select_email(&mut self, idx: usize) -> bool {
+        let row = self
+            .rows
+            .iter()
+            .position(|row| row.email == idx)
+            .or_else(|| {
+                let thread = self
+                    .threads
+                    .iter()
+                    .position(|t| t.messages.iter().any(|&(email, _)| email == idx))?;
+                self.rows.iter().position(|row| row.thread == Some(thread))
+            });
+        if let Some(row) = row {
+            self.select_row(row);
+        }
+        row.is_some()
+    }
 
-        // Scroll the window down by one line
-        let new_scroll_offset =
-            (self.scroll_offset + 1).min(self.emails.len().saturating_sub(self.visible_items));
+    /// Returns the index of the email on the selected row.
+    pub(crate) fn selected_email(&self) -> Option<usize> {
+        let row = self.list_state.selected()?;
+        self.rows.get(row).map(|row| row.email)
+    }
 
-        self.scroll_offset = new_scroll_offset;
-
-        // If cursor was at top and window actually scrolled, move cursor down to stay visible
-        if cursor_at_top
-            && new_scroll_offset > current_selected
-            && current_selected < self.emails.len() - 1
+    /// Rebuilds the list rows from the emails, keeping the cursor on the same
+    /// email, or on the next one shown when it was filtered out.
+    ///
+    /// In threaded view, threads whose messages are all read are collapsed
+    /// and threads with unread mail are expanded, unless the user toggled
+    /// them.
+    fn rebuild_rows(&mut self) {
+        let selected = self.selected_email();
+
+        self.threads = if self.threaded {
+            build_threads(&self.emails, self.config.ui.thread_order)
+        } else {
+            Vec::new()
+        };
+        self.rows = if self.threaded {
+            let mut order = (0..self.threads.len()).collect::<Vec<_>>();
+            order.sort_by(|&a, &b| {
+                compare_threads(self.sort, &self.emails, &self.threads[a], &self.threads[b])
+            });
+            let mut rows = Vec::new();
+            for thread_idx in order {
+                let thread = &self.threads[thread_idx];
+                let collapsed = self
+                    .thread_folds
+                    .get(&thread.key)
+                    .copied()
+                    .unwrap_or_else(|| thread.all_read(&self.emails));
+                if collapsed {
+                    rows.push(ListRow {
+                        email: thread.first(),
+                        thread: Some(thread_idx),
+                        depth: 0,
+                        hidden: thread.messages.len() - 1,
+                        unread: !thread.all_read(&self.emails),
+                    });
+                } else {
+                    rows.extend(thread.messages.iter().map(|&(email, depth)| ListRow {
+                        email,
+                        thread: Some(thread_idx),
+                        depth,
+                        hidden: 0,
+                        unread: !self.emails[email].is_read,
+                    }));
+                }
+            }
+            rows
+        } else {
+            let mut order = (0..self.emails.len()).collect::<Vec<_>>();
+            order.sort_by(|&a, &b| compare_emails(self.sort, &self.emails[a], &self.emails[b]));
+            order
+                .into_iter()
+                .map(|email| ListRow {
+                    email,
+                    thread: None,
+                    depth: 0,
+                    hidden: 0,
+                    unread: !self.emails[email].is_read,
+                })
+                .collect()
+        };
+        if self.limit.is_some()
+            || !self.filters.is_empty()
+            || self.pattern.is_some()
+            || self.focus
+            || !self.config.ui.muted_senders.is_empty()
         {
-            self.list_state.select(Some(current_selected + 1));
+            let matches = |email: usize| {
+                let email = &self.emails[email];
+                self.limit
+                    .as_ref()
+                    .is_none_or(|limit| limit.uids.contains(&email._uid))
+                    && self
+                        .filters
+                        .iter()
+                        .all(|filter| filter.matches(email, &self.config.gmail.username))
+                    && self
+                        .pattern
+                        .as_ref()
+                        .is_none_or(|pattern| pattern.matches(email))
+                    && !(self.focus && email.is_read)
+                    && !is_muted(&self.config.ui.muted_senders, email)
+            };
+            let threads = &self.threads;
+            // A collapsed thread stays when any of its messages matches.
+            self.rows.retain(|row| match row.thread {
+                Some(thread) if row.hidden > 0 => threads[thread]
+                    .messages
+                    .iter()
+                    .any(|&(email, _)| matches(email)),
+                _ => matches(row.email),
+            });
         }
-    }
 
-    /// Scrolls the window up by one line.
-    pub fn line_backward(&mut self) {
-        if self.emails.is_empty() {
+        if self.rows.is_empty() {
+            self.list_state.select(None);
             return;
         }
+        self.list_state.select(Some(0));
+        if let Some(idx) = selected
+            && !self.select_email(idx)
+        {
+            let next = self.rows.iter().position(|row| row.email > idx);
+            self.select_row(next.unwrap_or(self.rows.len() - 1));
+        }
+        self.scroll_offset = self
+            .scroll_offset
+            .min(self.list_state.selected().unwrap_or(0));
+    }
 
-        let current_selected = self.list_state.selected().unwrap_or(0);
+    /// Switches to the next sort order, re-sorting the loaded messages.
+    pub fn cycle_sort(&mut self) {
+        self.set_sort(self.sort.next());
+    }
 
-        // Check if cursor is at the bottom of the visible window
-        let cursor_at_bottom = current_selected
-            == (self.scroll_offset + self.visible_items - 1).min(self.emails.len() - 1);
+    /// Orders the list.
+    fn set_sort(&mut self, sort: SortMode) {
+        self.sort = sort;
+        self.rebuild_rows();
+        self.notify(format!("Sort: {}", self.sort.name()));
+    }
 
-        // Scroll the window up by one line
-        let new_scroll_offset = self.scroll_offset.saturating_sub(1);
+    /// Returns the order of the list.
+    pub(crate) fn sort(&self) -> SortMode {
+        self.sort
+    }
 
-        self.scroll_offset = new_scroll_offset;
+    /// Switches between the flat and the threaded list.
+    pub fn toggle_threaded(&mut self) {
+        self.threaded = !self.threaded;
+        self.rebuild_rows();
+    }
 
-        // If cursor was at bottom and window actually scrolled, move cursor up to stay visible
-        if cursor_at_bottom && new_scroll_offset < current_selected && current_selected > 0 {
-            self.list_state.select(Some(current_selected - 1));
-        }
+    /// Switches between chronological and newest-first order within threads,
+    /// saving the choice to the watched config file.
+    pub fn toggle_thread_order(&mut self) {
+        let order = self.config.ui.thread_order.toggled();
+        self.config.ui.thread_order = order;
+        self.rebuild_rows();
+
+        let name = match order {
+            ThreadOrder::Chronological => "chronological",
+            ThreadOrder::NewestFirst => "newest first",
+        };
+        let saved = self
+            .config_watcher
+            .as_ref()
+            .context("Config file not watched")
+            .and_then(|watcher| save_setting(watcher.path(), "ui", "thread_order", order.as_str()));
+        self.notify(match saved {
+            Ok(()) => format!("Thread order: {}", name),
+            Err(e) => format!("Thread order: {} (not saved: {e:#})", name),
+        });
     }
 
-    /// Scrolls detail view down by one line (j key).
-    pub fn detail_scroll_down(&mut self) {
-        self.detail_scroll_offset = self.detail_scroll_offset.saturating_add(1);
+    /// Collapses or expands the thread under the cursor.
+    pub fn toggle_thread(&mut self) {
+        let Some(thread_idx) = self
+            .list_state
+            .selected()
+            .and_then(|row| self.rows.get(row))
+            .and_then(|row| row.thread)
+        else {
+            return;
+        };
+        let collapsed = self
+            .rows
+            .iter()
+            .any(|row| row.thread == Some(thread_idx) && row.hidden > 0);
+        let key = self.threads[thread_idx].key.clone();
+        self.thread_folds.insert(key, !collapsed);
+        self.rebuild_rows();
     }
 
-    /// Scrolls detail view up by one line (k key).
-    pub fn detail_scroll_up(&mut self) {
-        self.detail_scroll_offset = self.detail_scroll_offset.saturating_sub(1);
+    /// Collapses every thread, or expands them all when none is expanded.
+    pub fn toggle_all_threads(&mut self) {
+        if !self.threaded {
+            return;
+        }
+        let any_expanded = self.rows.iter().any(|row| row.depth > 0);
+        for thread in &self.threads {
+            self.thread_folds.insert(thread.key.clone(), any_expanded);
+        }
+        self.rebuild_rows();
     }
 
-    /// Scrolls detail view down by one line (ctrl-e).
-    pub fn detail_line_forward(&mut self) {
-        self.detail_scroll_offset = self.detail_scroll_offset.saturating_add(1);
+    /// Positions the cursor according to the `initial_cursor` setting.
+    fn place_initial_cursor(&mut self) {
+        let unread = self
+            .emails
+            .iter()
+            .enumerate()
+            .filter(|(_, email)| !email.is_read);
+        let target = match self.config.ui.initial_cursor {
+            InitialCursor::Top => None,
+            InitialCursor::FirstUnread => unread.min_by_key(|(_, email)| email.date),
+            InitialCursor::LatestUnread => unread.max_by_key(|(_, email)| email.date),
+        };
+
+        if let Some((idx, _)) = target {
+            self.select_email(idx);
+        }
     }
 
-    /// Scrolls detail view up by one line (ctrl-y).
-    pub fn detail_line_backward(&mut self) {
-        self.detail_scroll_offset = self.detail_scroll_offset.saturating_sub(1);
+    /// Moves the cursor to the next unread row after the cursor, wrapping
+    /// around to the top of the list.
+    pub fn next_unread(&mut self) {
+        let len = self.rows.len();
+        let current = self.list_state.selected().unwrap_or(0);
+
+        let next = (1..=len)
+            .map(|offset| (current + offset) % len)
+            .find(|&row| self.rows[row].unread);
+        match next {
+            Some(row) => self.select_row(row),
+            None => self.notify("No unread messages".to_string()),
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::gmail_client::NameAddr;
-    use chrono::Local;
+    /// Moves cursor to the next email in the list.
+    pub fn next(&mut self) {
+        if self.rows.is_empty() {
+            return;
+        }
 
-    #[test]
-    fn test_app_navigation() {
-        let emails = vec![
-            Email {
-                _uid: 1,
-                subject: "Email 1".to_string(),
-                from: NameAddr {
-                    name: None,
-                    email: Some("test1@test.com".to_string()),
-                },
-                to: vec![],
-                cc: vec![],
-                bcc: vec![],
-                date: Local::now(),
-                is_read: false,
-                body: None,
-            },
-            Email {
-                _uid: 2,
-                subject: "Email 2".to_string(),
-                from: NameAddr {
-                    name: None,
-                    email: Some("test2@test.com".to_string()),
-                },
-                to: vec![],
-                cc: vec![],
-                bcc: vec![],
-                date: Local::now(),
-                is_read: true,
-                body: None,
-            },
-        ];
+        let current_selected = self.list_state.selected().unwrap_or(0);
 
-        let client = GmailClient::connect("dummy", "dummy");
-        if client.is_err() {
+        if current_selected >= self.rows.len() - 1 {
+            // Already at the bottom; don't move, but look for older mail.
+            if !self.older_at_top() {
+                self.request_older();
+            }
             return;
         }
 
-        let mut app = App::new(client.unwrap(), emails);
+        let new_selected = current_selected + 1;
+        self.list_state.select(Some(new_selected));
 
-        assert_eq!(app.list_state.selected(), Some(0));
-        app.next();
-        assert_eq!(app.list_state.selected(), Some(1));
-        app.next();
-        assert_eq!(app.list_state.selected(), Some(1)); // Stays at bottom
-        app.previous();
-        assert_eq!(app.list_state.selected(), Some(0));
-        app.previous();
-        assert_eq!(app.list_state.selected(), Some(0)); // Stays at top
+        // Only scroll window when cursor reaches the bottom edge.
+        if new_selected >= self.scroll_offset + self.visible_items {
+            self.scroll_offset = new_selected - self.visible_items + 1;
+        }
     }
 
-    #[test]
-    fn test_app_initialization() {
-        let emails = vec![];
-        let client = GmailClient::connect("dummy", "dummy");
-        if client.is_err() {
+    /// Moves cursor to the previous email in the list.
+    pub fn previous(&mut self) {
+        if self.rows.is_empty() {
             return;
         }
 
-        let app = App::new(client.unwrap(), emails.clone());
-        assert_eq!(app.emails.len(), 0);
-        assert_eq!(app.list_state.selected(), None);
+        let current_selected = self.list_state.selected().unwrap_or(0);
+
+        if current_selected == 0 {
+            // Already at the top; don't move, but look for older mail when
+            // it would be listed there.
+            if self.older_at_top() {
+                self.request_older();
+            }
+            return;
+        }
+
+        let new_selected = current_selected - 1;
+        self.list_state.select(Some(new_selected));
+
+        // Only scroll window when cursor reaches the top edge.
+        if new_selected < self.scroll_offset {
+            self.scroll_offset = new_selected;
+        }
     }
 
-    #[test]
-    fn test_vim_like_scrolling() {
-        let emails: Vec<Email> = (0..20)
-            .map(|i| Email {
-                _uid: i + 1,
-                subject: format!("Email {}", i + 1),
-                from: NameAddr {
-                    name: None,
-                    email: Some(format!("test{}@test.com", i + 1)),
-                },
-                to: vec![],
-                cc: vec![],
-                bcc: vec![],
-                date: Local::now(),
-                is_read: false,
-                body: None,
-            })
-            .collect();
+    /// Switches to detail view for the currently selected email.
+    pub fn view_email(&mut self) {
+        if let Some(selected) = self.selected_email() {
+            if self.emails[selected].body.is_none()
+                && self.confirm_over_budget(Download::Body(selected))
+            {
+                return;
+            }
+            self.open_email(selected);
+        }
+    }
 
-        let client = GmailClient::connect("dummy", "dummy");
-        if client.is_err() {
+    /// Opens the email at index in detail view, fetching its body if needed,
+    /// and the body of the message below it ahead of time.
+    ///
+    /// With a background fetcher the view opens at once and the body is
+    /// filled in when it arrives.
+    fn open_email(&mut self, idx: usize) {
+        self.new_mail.remove(&self.emails[idx]._uid);
+        self.mode = ViewMode::Detail(idx);
+        self.load_body(idx);
+        // Requested after the body, so the fetcher gets to that first.
+        if let Some(row) = self.list_state.selected() {
+            self.prefetch_row(row + 1);
+        }
+    }
+
+    /// Fetches the body of the email at index unless it is loaded or on its
+    /// way, marking it read.
+    fn load_body(&mut self, idx: usize) {
+        let uid = self.emails[idx]._uid;
+        if self.emails[idx].body.is_some() || self.loading.contains(&uid) {
+            return;
+        }
+        if let Some(body) = self.prefetched.remove(&uid) {
+            // It was fetched ahead without marking it read, as fetching it
+            // now would have.
+            if !self.emails[idx].is_read {
+                let entry = self.record(vec![uid], JournalOp::MarkRead);
+                if self.client.is_none() {
+                    self.queued.push(entry);
+                } else if let Err(e) = self.send_entries(&[entry]) {
+                    self.notify(format!("Error: {e:#}"));
+                }
+            }
+            self.set_body(idx, body);
             return;
         }
 
-        let mut app = App::new(client.unwrap(), emails);
-        app.set_visible_items(5); // Simulate a small window with 5 visible items
+        if let Some(fetcher) = &self.body_fetcher
+            && let Some(client) = &self.client
+        {
+            let sender = self.emails[idx].from.email.as_deref();
+            fetcher.request(client.mailbox(), uid, sender);
+            self.loading.insert(uid);
+            return;
+        }
+        if let Some(offline) = &self.offline {
+            match offline.body(uid) {
+                Some(mut body) => {
+                    self.filter_body(idx, &mut body);
+                    self.set_body(idx, body);
+                    // As fetching it from the server would have.
+                    if !self.emails[idx].is_read {
+                        let entry = self.record(vec![uid], JournalOp::MarkRead);
+                        self.queued.push(entry);
+                    }
+                }
+                None => self.notify("Offline; the message was not cached".to_string()),
+            }
+            return;
+        }
+        match self.client().and_then(|c| c.fetch_email_body(uid)) {
+            Ok(mut body) => {
+                self.filter_body(idx, &mut body);
+                self.set_body(idx, body);
+            }
+            Err(e) => self.notify(format!("Error: {e:#}")),
+        }
+    }
 
-        // Test moving down: cursor should move without scrolling initially
-        assert_eq!(app.list_state.selected(), Some(0));
-        assert_eq!(app.scroll_offset, 0);
+    /// Runs a body fetched without the background fetcher through the
+    /// display filters, as the fetcher would have.
+    fn filter_body(&mut self, idx: usize, body: &mut MessageBody) {
+        let sender = self.emails[idx].from.email.as_deref();
+        let failures = display_filter::filter_body(&self.config.display_filters, sender, body);
+        for e in failures {
+            self.notify(format!("Error: {e:#}"));
+        }
+    }
 
-        // Move down to position 4 (last visible item in window)
-        for _ in 0..4 {
-            app.next();
+    /// Stores a fetched body on the email at index, once it went through the
+    /// display filters.
+    fn set_body(&mut self, idx: usize, body: MessageBody) {
+        let uid = self.emails[idx]._uid;
+        self.translations.remove(&uid);
+        self.emails[idx].body = Some(body.text);
+        self.headers.insert(uid, body.headers);
+        if let Some(report) = body.report {
+            self.reports.insert(uid, report);
         }
-        assert_eq!(app.list_state.selected(), Some(4));
-        assert_eq!(app.scroll_offset, 0); // Should not scroll yet
+        if let Some(alternatives) = body.alternatives {
+            self.alternatives
+                .insert(uid, (alternatives, TextPart::Plain));
+            if self.config.ui.prefer == TextPart::Html {
+                self.show_part(idx, TextPart::Html);
+            }
+        }
+    }
 
-        // Move down one more - this should trigger scrolling
-        app.next();
-        assert_eq!(app.list_state.selected(), Some(5));
-        assert_eq!(app.scroll_offset, 1); // Window should scroll down by 1
+    /// Returns every header of the email at index, once its body is loaded.
+    pub(crate) fn message_headers(&self, idx: usize) -> Option<&[(String, String)]> {
+        let uid = self.emails.get(idx)?._uid;
+        self.headers.get(&uid).map(Vec::as_slice)
+    }
 
-        // Test moving up: scroll should happen when cursor reaches top
-        app.previous();
-        assert_eq!(app.list_state.selected(), Some(4));
-        assert_eq!(app.scroll_offset, 0); // Should scroll back up
+    /// Returns whether the detail view shows every header.
+    pub(crate) fn full_headers(&self) -> bool {
+        self.full_headers
     }
 
-    #[test]
-    fn test_view_mode_transitions() {
-        let emails = vec![Email {
-            _uid: 1,
-            subject: "Test".to_string(),
-            from: NameAddr {
-                name: None,
-                email: Some(format!("test@test.com")),
+    /// Switches the detail view between the weeded headers and all of them.
+    pub fn toggle_headers(&mut self) {
+        self.full_headers = !self.full_headers;
+        self.detail_scroll_offset = 0;
+    }
+
+    /// Describes the current view in words.
+    fn view_description(&self) -> String {
+        match self.mode {
+            ViewMode::Loading => "Loading the inbox".to_string(),
+            ViewMode::List => {
+                let mailbox = self.client.as_ref().map_or("INBOX", |c| c.mailbox());
+                let unread = self.rows.iter().filter(|row| row.unread).count();
+                format!(
+                    "{}: {} messages, {} unread",
+                    mailbox,
+                    self.rows.len(),
+                    unread
+                )
+            }
+            ViewMode::Detail(idx) => match self.emails.get(idx) {
+                Some(email) => format!(
+                    "Message from {}: {}",
+                    email.from.name_or_addr().unwrap_or("unknown sender"),
+                    email.subject
+                ),
+                None => "Message".to_string(),
             },
-            to: vec![],
-            cc: vec![],
-            bcc: vec![],
-            date: Local::now(),
-            is_read: false,
-            body: None,
-        }];
+            ViewMode::Compose => "Compose".to_string(),
+            ViewMode::Stats => "Session statistics".to_string(),
+            ViewMode::Version => "Version".to_string(),
+            ViewMode::Folders => "Folders".to_string(),
+            ViewMode::Cleanup => format!("Inbox cleanup: {} senders", self.senders.len()),
+            ViewMode::Participants(_) => {
+                format!("Thread participants: {}", self.participants.len())
+            }
+            ViewMode::Urls(_) => format!("Links: {}", self.urls.len()),
+            ViewMode::Results => format!(
+                "Matches in all folders: {}",
+                self.folder_results
+                    .as_ref()
+                    .map_or(0, |(_, hits)| hits.len())
+            ),
+        }
+    }
 
-        let client = GmailClient::connect("dummy", "dummy");
-        if client.is_err() {
+    /// In screen reader mode, announces the view on the status line when it
+    /// changes, e.g. after opening a message or another folder.
+    pub fn announce_view(&mut self) {
+        if !self.config.ui.screen_reader {
             return;
         }
+        // Keyed by view rather than description, so marking a message read
+        // is not announced as a new view.
+        let mailbox = self.client.as_ref().map_or("INBOX", |c| c.mailbox());
+        let view = format!("{:?} {}", self.mode, mailbox);
+        if self.announced.as_ref() != Some(&view) {
+            self.announced = Some(view);
+            self.notify(self.view_description());
+        }
+    }
 
-        let mut app = App::new(client.unwrap(), emails);
+    /// Stores the bodies the background fetcher has finished, without
+    /// blocking.
+    pub fn receive_bodies(&mut self) {
+        let Some(fetcher) = &self.body_fetcher else {
+            return;
+        };
+        for fetched in fetcher.received() {
+            self.receive_body(fetched);
+        }
+    }
 
-        // Start in list mode
-        assert!(matches!(app.mode, ViewMode::List));
+    /// Stores a body from the background fetcher, unless the list moved on
+    /// to another mailbox meanwhile.
+    fn receive_body(&mut self, fetched: FetchedBody) {
+        let mailbox = self.client.as_ref().map_or("INBOX", |c| c.mailbox());
+        if fetched.peek {
+            // Looking for a code and fetching ahead are conveniences, so
+            // failures go unreported.
+            let uid = fetched.uid;
+            let for_code = self.peeking.remove(&uid);
+            let ahead = self.prefetching.remove(&uid);
+            let Ok(body) = fetched.body else {
+                return;
+            };
+            if mailbox != fetched.mailbox {
+                return;
+            }
+            let Some(idx) = self.emails.iter().position(|e| e._uid == uid) else {
+                return;
+            };
+            if for_code && let Some(code) = one_time_code(&self.emails[idx].subject, &body.text) {
+                self.offer_code(uid, code);
+            }
+            if ahead && self.emails[idx].body.is_none() && !self.loading.contains(&uid) {
+                self.prefetched.insert(uid, body);
+            }
+            return;
+        }
+        if !self.loading.remove(&fetched.uid) || mailbox != fetched.mailbox {
+            return;
+        }
+        let Some(idx) = self.emails.iter().position(|e| e._uid == fetched.uid) else {
+            return;
+        };
+        match fetched.body {
+            Ok(body) => {
+                self.set_body(idx, body);
+                for e in fetched.filter_errors {
+                    self.notify(format!("Error: {e:#}"));
+                }
+            }
+            Err(e) => self.notify(format!("Error: {e:#}")),
+        }
+    }
 
-        // View email
-        app.view_email();
-        assert!(matches!(app.mode, ViewMode::Detail(0)));
+    /// Returns whether any body is being fetched in the background.
+    pub fn loading(&self) -> bool {
+        !self.loading.is_empty()
+    }
 
-        // Go back to list
-        app.back_to_list();
-        assert!(matches!(app.mode, ViewMode::List));
+    /// Returns whether new mail is being looked into for one-time codes, or
+    /// bodies are being fetched ahead.
+    pub fn peeking(&self) -> bool {
+        !self.peeking.is_empty() || !self.prefetching.is_empty()
     }
 
-    #[test]
-    fn test_vim_navigation() {
-        let emails: Vec<Email> = (0..20)
-            .map(|i| Email {
-                _uid: i + 1,
-                subject: format!("Email {}", i + 1),
-                from: NameAddr {
-                    name: None,
-                    email: Some(format!("test{}@test.com", i + 1)),
-                },
-                to: vec![],
-                cc: vec![],
-                bcc: vec![],
-                date: Local::now(),
-                is_read: false,
-                body: None,
-            })
-            .collect();
+    /// Returns the text shown in place of the body of the email at index
+    /// while it is not loaded.
+    pub(crate) fn body_placeholder(&self, idx: usize) -> String {
+        let loading = self
+            .emails
+            .get(idx)
+            .is_some_and(|email| self.loading.contains(&email._uid));
+        if loading && self.config.ui.screen_reader {
+            // An animated spinner would be read out on every frame.
+            "Loading...".to_string()
+        } else if loading {
+            format!("{} Loading...", self.spinner())
+        } else {
+            "(Body not loaded)".to_string()
+        }
+    }
 
-        let client = GmailClient::connect("dummy", "dummy");
-        if client.is_err() {
+    /// Returns the current frame of the spinner shown while loading.
+    pub(crate) fn spinner(&self) -> &'static str {
+        let frame = self.started_at.elapsed().as_millis() / SPINNER_FRAME.as_millis();
+        SPINNER[frame as usize % SPINNER.len()]
+    }
+
+    /// Switches the email shown in detail view between its plain-text and
+    /// HTML versions.
+    pub fn toggle_text_part(&mut self) {
+        let ViewMode::Detail(idx) = self.mode else {
             return;
+        };
+        match self.shown_part() {
+            Some(TextPart::Plain) => self.show_part(idx, TextPart::Html),
+            Some(TextPart::Html) => self.show_part(idx, TextPart::Plain),
+            None => self.notify("No other version of this message".to_string()),
         }
+    }
 
-        let mut app = App::new(client.unwrap(), emails);
-        app.set_visible_items(5); // Window shows 5 items
+    /// Shows a version of an email having plain-text and HTML versions.
+    fn show_part(&mut self, idx: usize, part: TextPart) {
+        let uid = self.emails[idx]._uid;
+        if let Some((alternatives, shown)) = self.alternatives.get_mut(&uid) {
+            let text = match part {
+                TextPart::Plain => &alternatives.plain,
+                TextPart::Html => &alternatives.html,
+            };
+            self.emails[idx].body = Some(text.clone());
+            *shown = part;
+            self.detail_scroll_offset = 0;
+        }
+    }
 
-        // Start at position 0 with scroll_offset 0
-        assert_eq!(app.list_state.selected(), Some(0));
-        assert_eq!(app.scroll_offset, 0);
+    /// Returns the version shown of the email in detail view, if it has both
+    /// a plain-text and an HTML version.
+    pub(crate) fn shown_part(&self) -> Option<TextPart> {
+        let (ViewMode::Detail(idx) | ViewMode::Participants(idx) | ViewMode::Urls(idx)) = self.mode
+        else {
+            return None;
+        };
+        let uid = self.emails.get(idx)?._uid;
+        self.alternatives.get(&uid).map(|&(_, part)| part)
+    }
 
-        // Move down to trigger scrolling
-        for _ in 0..7 {
-            app.next();
-        }
-        assert_eq!(app.list_state.selected(), Some(7));
-        assert_eq!(app.scroll_offset, 3); // Window scrolled to show items 3-7
+    /// Returns the indexes of the emails in the thread containing an email,
+    /// in thread order.
+    ///
+    /// Threads are built on demand outside threaded view, so the detail view
+    /// can step through a conversation either way.
+    fn thread_of(&self, idx: usize) -> Vec<usize> {
+        let built;
+        let threads = if self.threaded {
+            &self.threads
+        } else {
+            built = build_threads(&self.emails, self.config.ui.thread_order);
+            &built
+        };
+        threads
+            .iter()
+            .find(|t| t.messages.iter().any(|&(email, _)| email == idx))
+            .map(|t| t.messages.iter().map(|&(email, _)| email).collect())
+            .unwrap_or_else(|| vec![idx])
+    }
 
-        // Test goto_page_top (H key) - should go to top of visible window (item 3)
-        app.goto_page_top();
-        assert_eq!(app.list_state.selected(), Some(3)); // First visible item
-        assert_eq!(app.scroll_offset, 3); // Scroll doesn't change
+    /// Returns the thread containing an email like [`Self::thread_of`],
+    /// keeping only the messages of the participant it is narrowed to.
+    fn shown_thread(&self, idx: usize) -> Vec<usize> {
+        let mut thread = self.thread_of(idx);
+        if let Some(address) = &self.participant_filter {
+            thread.retain(|&email| sender_address(&self.emails[email]).as_ref() == Some(address));
+        }
+        thread
+    }
 
-        // Test goto_page_bottom (L key) - should go to bottom of visible window (item 7)
-        app.goto_page_bottom();
-        assert_eq!(app.list_state.selected(), Some(7)); // Last visible item (3+5-1)
-        assert_eq!(app.scroll_offset, 3); // Scroll doesn't change
+    /// Returns the 1-based position of the email shown in detail view within
+    /// its thread, and the thread's length, if it has other messages.
+    ///
+    /// With the thread narrowed to a participant, only their messages count.
+    pub(crate) fn thread_position(&self) -> Option<(usize, usize)> {
+        let (ViewMode::Detail(idx) | ViewMode::Participants(idx) | ViewMode::Urls(idx)) = self.mode
+        else {
+            return None;
+        };
+        let thread = self.shown_thread(idx);
+        let position = thread.iter().position(|&email| email == idx)?;
+        (thread.len() > 1).then_some((position + 1, thread.len()))
+    }
 
-        // Test goto_page_middle (M key) - should go to middle of visible window
-        app.goto_page_middle();
-        assert_eq!(app.list_state.selected(), Some(5)); // Middle of window (3 + 2)
-        assert_eq!(app.scroll_offset, 3); // Scroll doesn't change
+    /// Shows the next message of the thread in detail view.
+    pub fn thread_next(&mut self) {
+        self.step_thread(true);
+    }
 
-        // Test at end of list where window is partial
-        for _ in 0..14 {
-            app.next();
-        }
-        assert_eq!(app.list_state.selected(), Some(19)); // Last email
-        assert_eq!(app.scroll_offset, 15); // Window shows items 15-19
+    /// Shows the previous message of the thread in detail view.
+    pub fn thread_previous(&mut self) {
+        self.step_thread(false);
+    }
 
-        // Window shows items 15-19 (only 5 items visible)
-        app.goto_page_top();
-        assert_eq!(app.list_state.selected(), Some(15)); // Top of window
+    /// Moves the detail view to the adjacent message of its thread, keeping
+    /// the list cursor on it.
+    fn step_thread(&mut self, forward: bool) {
+        let ViewMode::Detail(idx) = self.mode else {
+            return;
+        };
+        let thread = self.shown_thread(idx);
+        let Some(position) = thread.iter().position(|&email| email == idx) else {
+            return;
+        };
+        let target = if forward {
+            thread.get(position + 1)
+        } else {
+            position.checked_sub(1).and_then(|p| thread.get(p))
+        };
+        let Some(&target) = target else {
+            self.notify(if forward {
+                "Last message in thread".to_string()
+            } else {
+                "First message in thread".to_string()
+            });
+            return;
+        };
+        self.show_in_thread(target);
+    }
 
-        app.goto_page_bottom();
-        assert_eq!(app.list_state.selected(), Some(19)); // Bottom of window
+    /// Shows the message below in the list in detail view (J).
+    pub fn detail_next(&mut self) {
+        self.step_message(true);
+    }
 
-        app.goto_page_middle();
-        assert_eq!(app.list_state.selected(), Some(17)); // Middle of window (15 + 2)
+    /// Shows the message above in the list in detail view (K).
+    pub fn detail_previous(&mut self) {
+        self.step_message(false);
     }
 
-    #[test]
-    fn test_vim_navigation_empty_list() {
-        let emails = vec![];
-        let client = GmailClient::connect("dummy", "dummy");
-        if client.is_err() {
+    /// Moves the detail view to the adjacent row of the list, and fetches
+    /// the body of the one after it ahead, so that stepping on shows it at
+    /// once.
+    fn step_message(&mut self, forward: bool) {
+        if !matches!(self.mode, ViewMode::Detail(_)) {
             return;
         }
-
-        let mut app = App::new(client.unwrap(), emails);
-
-        // These should not panic on empty list
-        app.goto_page_top();
-        app.goto_page_middle();
-        app.goto_page_bottom();
-        assert_eq!(app.list_state.selected(), None);
+        let Some(row) = self.list_state.selected() else {
+            return;
+        };
+        let target = if forward {
+            row.checked_add(1)
+        } else {
+            row.checked_sub(1)
+        };
+        let Some((target, email)) =
+            target.and_then(|target| Some((target, self.rows.get(target)?.email)))
+        else {
+            self.notify(if forward {
+                "Last message".to_string()
+            } else {
+                "First message".to_string()
+            });
+            return;
+        };
+        self.show_in_thread(email);
+        let ahead = if forward {
+            target.checked_add(1)
+        } else {
+            target.checked_sub(1)
+        };
+        if let Some(ahead) = ahead {
+            self.prefetch_row(ahead);
+        }
     }
 
-    #[test]
-    fn test_page_navigation() {
-        let emails: Vec<Email> = (0..30)
-            .map(|i| Email {
-                _uid: i + 1,
-                subject: format!("Email {}", i + 1),
-                from: NameAddr {
-                    name: None,
-                    email: Some(format!("test{}@test.com", i + 1)),
-                },
-                to: vec![],
-                cc: vec![],
-                bcc: vec![],
-                date: Local::now(),
-                is_read: false,
-                body: None,
-            })
-            .collect();
+    /// Fetches the body of the message at a row of the list in the
+    /// background, without marking it read, unless it is loaded, on its way,
+    /// or over the transfer budget.
+    fn prefetch_row(&mut self, row: usize) {
+        let Some(idx) = self.rows.get(row).map(|row| row.email) else {
+            return;
+        };
+        let email = &self.emails[idx];
+        let uid = email._uid;
+        if email.body.is_some()
+            || self.loading.contains(&uid)
+            || self.prefetching.contains(&uid)
+            || self.prefetched.contains_key(&uid)
+            || exceeds_budget(email.size.into(), self.config.network.transfer_budget_kb)
+        {
+            return;
+        }
+        if let Some(fetcher) = &self.body_fetcher
+            && let Some(client) = &self.client
+        {
+            fetcher.peek(client.mailbox(), uid, email.from.email.as_deref());
+            self.prefetching.insert(uid);
+        }
+    }
 
-        let client = GmailClient::connect("dummy", "dummy");
-        if client.is_err() {
+    /// Shows another message in detail view, keeping the list cursor on it.
+    fn show_in_thread(&mut self, target: usize) {
+        self.select_email(target);
+        if self.emails[target].body.is_none() && self.confirm_over_budget(Download::Body(target)) {
             return;
         }
+        self.pending_attachments.clear();
+        self.source = None;
+        self.detail_scroll_offset = 0;
+        self.open_email(target);
+    }
 
-        let mut app = App::new(client.unwrap(), emails);
-        app.set_visible_items(10); // Window shows 10 items
+    /// Returns what the email at index replies to: the subject of the
+    /// parent when it is loaded, otherwise its Message-ID.
+    pub(crate) fn in_reply_to(&self, idx: usize) -> Option<&str> {
+        let parent_id = self.emails.get(idx)?.parent_id()?;
+        Some(match self.loaded_parent(idx) {
+            Some(parent) => &self.emails[parent].subject,
+            None => parent_id,
+        })
+    }
 
-        // Start at position 0 with scroll_offset 0
-        assert_eq!(app.list_state.selected(), Some(0));
-        assert_eq!(app.scroll_offset, 0);
+    /// Returns the index of the parent of the email at index, if loaded.
+    fn loaded_parent(&self, idx: usize) -> Option<usize> {
+        let parent_id = self.emails[idx].parent_id()?;
+        self.emails
+            .iter()
+            .position(|email| email.message_id.as_deref() == Some(parent_id))
+    }
 
-        // Test page forward (Ctrl+F) - should move forward one page
-        app.page_forward();
-        assert_eq!(app.list_state.selected(), Some(10)); // Cursor at top of new page
-        assert_eq!(app.scroll_offset, 10); // Window shows items 10-19
+    /// Shows the message the one in detail view replies to, fetching it from
+    /// the folder by its Message-ID when it is not loaded.
+    pub fn goto_parent(&mut self) {
+        let ViewMode::Detail(idx) = self.mode else {
+            return;
+        };
+        let Some(parent_id) = self.emails[idx].parent_id().map(str::to_string) else {
+            self.notify("The message is not a reply".to_string());
+            return;
+        };
+        let parent = match self.loaded_parent(idx) {
+            Some(parent) => parent,
+            None => match self.fetch_parent(&parent_id) {
+                Ok(Some(parent)) => parent,
+                Ok(None) => {
+                    let mailbox = self.client.as_ref().map_or("INBOX", |c| c.mailbox());
+                    self.notify(format!("The message replied to is not in {}", mailbox));
+                    return;
+                }
+                Err(e) => {
+                    self.notify(format!("Error: {e:#}"));
+                    return;
+                }
+            },
+        };
+        self.reply_trail.push(self.emails[idx]._uid);
+        self.show_in_thread(parent);
+    }
 
-        // Another page forward
-        app.page_forward();
-        assert_eq!(app.list_state.selected(), Some(20)); // Cursor at top of new page
-        assert_eq!(app.scroll_offset, 20); // Window shows items 20-29
+    /// Fetches a message of the current folder by its Message-ID and adds it
+    /// to the list, returning its index.
+    fn fetch_parent(&mut self, message_id: &str) -> Result<Option<usize>> {
+        let client = self.client()?;
+        let mailbox = client.mailbox().to_string();
+        let Some(uid) = client.find_message_id(&mailbox, message_id)? else {
+            return Ok(None);
+        };
+        let Some(email) = client.fetch_envelopes(&[uid])?.pop() else {
+            return Ok(None);
+        };
+        self.fetched_parents.insert(uid);
+        self.emails.push(email);
+        self.rebuild_rows();
+        Ok(Some(self.emails.len() - 1))
+    }
 
-        // Try to page forward at the end - should just move cursor to last item
-        app.page_forward();
-        assert_eq!(app.list_state.selected(), Some(29)); // Last email
-        assert_eq!(app.scroll_offset, 20); // Window doesn't change
+    /// Returns from a parent to the reply it was reached from.
+    pub fn back_to_reply(&mut self) {
+        if !matches!(self.mode, ViewMode::Detail(_)) {
+            return;
+        }
+        while let Some(uid) = self.reply_trail.pop() {
+            if let Some(reply) = self.emails.iter().position(|email| email._uid == uid) {
+                self.show_in_thread(reply);
+                return;
+            }
+        }
+        self.notify("No reply to go back to".to_string());
+    }
 
-        // Test page backward (Ctrl+B) - should move backward one page
-        app.page_backward();
-        assert_eq!(app.list_state.selected(), Some(10)); // Cursor at top of new page
-        assert_eq!(app.scroll_offset, 10); // Window shows items 10-19
+    /// Opens the participants panel over the detail view, listing everyone
+    /// on the thread with the cursor on the participant it is narrowed to.
+    pub fn show_participants(&mut self) {
+        let ViewMode::Detail(idx) = self.mode else {
+            return;
+        };
+        self.participants = participants(self.thread_of(idx).iter().map(|&i| &self.emails[i]));
+        self.participant_selected = self
+            .participant_filter
+            .as_ref()
+            .and_then(|address| self.participants.iter().position(|p| p.address == *address))
+            .unwrap_or(0);
+        self.mode = ViewMode::Participants(idx);
+    }
 
-        // Another page backward
-        app.page_backward();
-        assert_eq!(app.list_state.selected(), Some(0)); // Back to start
-        assert_eq!(app.scroll_offset, 0); // Window shows items 0-9
+    /// Returns the participants listed by the panel, the selected row, and
+    /// the address the thread is narrowed to.
+    pub(crate) fn participants(&self) -> (&[Participant], usize, Option<&str>) {
+        (
+            &self.participants,
+            self.participant_selected,
+            self.participant_filter.as_deref(),
+        )
+    }
 
-        // Try to page backward at the beginning - should stay at start
-        app.page_backward();
-        assert_eq!(app.list_state.selected(), Some(0)); // Stay at start
-        assert_eq!(app.scroll_offset, 0); // Window doesn't change
+    /// Moves to the next participant in the panel.
+    pub fn participant_next(&mut self) {
+        if self.participant_selected + 1 < self.participants.len() {
+            self.participant_selected += 1;
+        }
     }
 
-    #[test]
-    fn test_page_navigation_small_list() {
-        // Test with a list smaller than one page
-        let emails: Vec<Email> = (0..5)
-            .map(|i| Email {
-                _uid: i + 1,
-                subject: format!("Email {}", i + 1),
-                from: NameAddr {
-                    name: None,
-                    email: Some(format!("test{}@test.com", i + 1)),
-                },
-                to: vec![],
-                cc: vec![],
-                bcc: vec![],
-                date: Local::now(),
-                is_read: false,
-                body: None,
-            })
-            .collect();
+    /// Moves to the previous participant in the panel.
+    pub fn participant_previous(&mut self) {
+        self.participant_selected = self.participant_selected.saturating_sub(1);
+    }
 
-        let client = GmailClient::connect("dummy", "dummy");
-        if client.is_err() {
-            return;
+    /// Closes the participants panel, returning to the message under it.
+    pub fn close_participants(&mut self) {
+        if let ViewMode::Participants(idx) = self.mode {
+            self.mode = ViewMode::Detail(idx);
         }
+    }
 
-        let mut app = App::new(client.unwrap(), emails);
-        app.set_visible_items(10); // Window can show 10 items but we only have 5
+    /// Narrows the thread to the messages of the selected participant and
+    /// shows the first of them, so `n` and `p` step through only theirs.
+    pub fn narrow_to_participant(&mut self) {
+        let ViewMode::Participants(idx) = self.mode else {
+            return;
+        };
+        let Some(participant) = self.participants.get(self.participant_selected) else {
+            return;
+        };
+        let address = participant.address.clone();
+        let first = self
+            .thread_of(idx)
+            .into_iter()
+            .find(|&email| sender_address(&self.emails[email]).as_ref() == Some(&address));
+        let Some(first) = first else {
+            self.notify(format!("{} has not written in this thread", address));
+            return;
+        };
 
-        // Page forward should move to last item since list is smaller than page
-        app.page_forward();
-        assert_eq!(app.list_state.selected(), Some(4)); // Last email
-        assert_eq!(app.scroll_offset, 0); // No scrolling needed
+        self.participant_filter = Some(address);
+        self.mode = ViewMode::Detail(idx);
+        if first != idx {
+            self.show_in_thread(first);
+        }
+    }
 
-        // Page backward should go to first item
-        app.page_backward();
-        assert_eq!(app.list_state.selected(), Some(0)); // First email
-        assert_eq!(app.scroll_offset, 0); // No scrolling needed
+    /// Shows the messages of everyone on the thread again.
+    pub fn show_all_participants(&mut self) {
+        self.participant_filter = None;
+        self.close_participants();
     }
 
-    #[test]
-    fn test_half_page_navigation() {
-        let emails: Vec<Email> = (0..30)
-            .map(|i| Email {
-                _uid: i + 1,
-                subject: format!("Email {}", i + 1),
-                from: NameAddr {
-                    name: None,
-                    email: Some(format!("test{}@test.com", i + 1)),
-                },
-                to: vec![],
-                cc: vec![],
-                bcc: vec![],
-                date: Local::now(),
-                is_read: false,
-                body: None,
-            })
-            .collect();
+    /// Returns the key values found in a message, such as a one-time code or
+    /// a tracking number, shown above its body.
+    pub fn summaries(&self, idx: usize) -> Vec<Summary> {
+        let email = &self.emails[idx];
+        email
+            .body
+            .as_deref()
+            .map(|body| summarize(&email.subject, body))
+            .unwrap_or_default()
+    }
 
-        let client = GmailClient::connect("dummy", "dummy");
-        if client.is_err() {
+    /// Copies the first key value found in the message being read to the
+    /// clipboard.
+    pub fn copy_summary(&mut self) {
+        let ViewMode::Detail(idx) = self.mode else {
             return;
-        }
-
-        let mut app = App::new(client.unwrap(), emails);
-        app.set_visible_items(10); // Window shows 10 items, so half-page is 5
+        };
+        let Some(summary) = self.summaries(idx).into_iter().next() else {
+            self.notify("Nothing to copy in the message".to_string());
+            return;
+        };
+        self.notify(format!("Copied {}", summary));
+        self.clipboard = Some(summary.value);
+    }
 
-        // Start at position 0 (cursor at top of window)
-        assert_eq!(app.list_state.selected(), Some(0));
-        assert_eq!(app.scroll_offset, 0);
+    /// Opens the URL picker over the detail view, listing the links in the
+    /// message.
+    pub fn show_urls(&mut self) {
+        let ViewMode::Detail(idx) = self.mode else {
+            return;
+        };
+        let Some(body) = &self.emails[idx].body else {
+            self.notify("The message is still loading".to_string());
+            return;
+        };
+        let urls = find_links(body);
+        if urls.is_empty() {
+            self.notify("No links in the message".to_string());
+            return;
+        }
+        self.urls = urls;
+        self.url_selected = 0;
+        self.picker.clear();
+        self.mode = ViewMode::Urls(idx);
+    }
 
-        // Move cursor to middle of window first
-        app.next();
-        app.next();
-        app.next(); // Now at position 3
-        assert_eq!(app.list_state.selected(), Some(3));
-        assert_eq!(app.scroll_offset, 0);
+    /// Returns the indices of the links matching the URL picker's filter,
+    /// best match first.
+    fn shown_urls(&self) -> Vec<usize> {
+        fuzzy_matches(&self.urls, self.picker.query(), |link| &link.url)
+    }
 
-        // Test half page forward (Ctrl-D) - should scroll window down by 5
-        app.half_page_forward();
-        assert_eq!(app.scroll_offset, 5); // Window scrolled down by 5
-        assert_eq!(app.list_state.selected(), Some(8)); // Cursor maintains relative position (3rd item in window)
+    /// Returns the links listed by the URL picker with their numbers,
+    /// counting from 1, and the selected row.
+    pub(crate) fn urls(&self) -> (Vec<(usize, &Link)>, usize) {
+        let shown = self
+            .shown_urls()
+            .into_iter()
+            .map(|i| (i + 1, &self.urls[i]))
+            .collect();
+        (shown, self.url_selected)
+    }
 
-        // Test half page backward (Ctrl-U) - should scroll window up by 5
-        app.half_page_backward();
-        assert_eq!(app.scroll_offset, 0); // Window scrolled back up
-        assert_eq!(app.list_state.selected(), Some(3)); // Cursor back to original position
+    /// Moves to the next link in the picker.
+    pub fn url_next(&mut self) {
+        if self.url_selected + 1 < self.shown_urls().len() {
+            self.url_selected += 1;
+        }
+    }
 
-        // Test at end of list - scroll near the end first
-        app.scroll_offset = 15; // Window shows items 15-24
-        app.list_state.select(Some(18)); // Cursor at 3rd position in window
+    /// Moves to the previous link in the picker.
+    pub fn url_previous(&mut self) {
+        self.url_selected = self.url_selected.saturating_sub(1);
+    }
 
-        // Half page forward at end - should scroll as much as possible
-        app.half_page_forward();
-        assert_eq!(app.scroll_offset, 20); // Max scroll for 30 items with 10 visible (30-10=20)
-        assert_eq!(app.list_state.selected(), Some(23)); // Cursor maintains relative position
+    /// Closes the URL picker, returning to the message under it.
+    pub fn close_urls(&mut self) {
+        if let ViewMode::Urls(idx) = self.mode {
+            self.picker.clear();
+            self.mode = ViewMode::Detail(idx);
+        }
     }
 
-    #[test]
-    fn test_half_page_navigation_small_window() {
-        let emails: Vec<Email> = (0..10)
-            .map(|i| Email {
-                _uid: i + 1,
-                subject: format!("Email {}", i + 1),
-                from: NameAddr {
-                    name: None,
-                    email: Some(format!("test{}@test.com", i + 1)),
-                },
-                to: vec![],
-                cc: vec![],
-                bcc: vec![],
-                date: Local::now(),
-                is_read: false,
-                body: None,
-            })
-            .collect();
+    /// Opens the selected link in the browser.
+    pub fn open_selected_url(&mut self) {
+        match self.shown_urls().get(self.url_selected) {
+            Some(&i) => self.open_url_number(i + 1),
+            None => self.notify(format!("No link matches {}", self.picker.query())),
+        }
+    }
 
-        let client = GmailClient::connect("dummy", "dummy");
-        if client.is_err() {
+    /// Opens a link by its number in the picker, counting from 1, and
+    /// closes the picker.
+    pub fn open_url_number(&mut self, number: usize) {
+        let Some(url) = number.checked_sub(1).and_then(|i| self.urls.get(i)) else {
+            self.notify(format!(
+                "No link {}; the message has {}",
+                number,
+                self.urls.len()
+            ));
             return;
+        };
+        let url = url.url.clone();
+        match open_url(&url) {
+            Ok(()) => {
+                self.notify(format!("Opened {}", url));
+                self.close_urls();
+            }
+            Err(e) => self.notify(format!("Error: {e:#}")),
         }
+    }
 
-        let mut app = App::new(client.unwrap(), emails);
-        app.set_visible_items(3); // Very small window, half-page = 1 (minimum)
+    /// Asks for confirmation when a download would exceed the configured
+    /// transfer budget.
+    ///
+    /// Returns true when a confirmation prompt was opened, in which case the
+    /// caller should not proceed; the download runs once the user confirms.
+    fn confirm_over_budget(&mut self, download: Download) -> bool {
+        let budget = self.config.network.transfer_budget_kb;
+        if budget.is_none() {
+            return false;
+        }
 
-        // Start at position 0 (cursor at top of window)
-        assert_eq!(app.list_state.selected(), Some(0));
-        assert_eq!(app.scroll_offset, 0);
+        let (Download::Body(idx)
+        | Download::Attachments(idx)
+        | Download::OpenAttachments(idx)
+        | Download::Source(idx)) = download;
+        let Some(uid) = self.emails.get(idx).map(|email| email._uid) else {
+            return false;
+        };
+        // If the size is unknown, let the download go ahead.
+        let Ok(size) = self.client().and_then(|c| c.message_size(uid)) else {
+            return false;
+        };
+        if !exceeds_budget(size.into(), budget) {
+            return false;
+        }
 
-        // Move cursor to middle of small window
-        app.next(); // Now at position 1
-        assert_eq!(app.list_state.selected(), Some(1));
-        assert_eq!(app.scroll_offset, 0);
+        self.prompt = Some(Prompt {
+            label: format!(
+                "Message is {}, over the {} budget. Download? (y/N): ",
+                format_bytes(size.into()),
+                format_bytes(budget.unwrap_or(0).saturating_mul(1024))
+            ),
+            input: String::new(),
+            kind: PromptKind::ConfirmDownload(download),
+        });
+        true
+    }
 
-        // Half page forward should scroll window by 1
-        app.half_page_forward();
-        assert_eq!(app.scroll_offset, 1); // Window scrolled down by 1
-        assert_eq!(app.list_state.selected(), Some(2)); // Cursor maintains relative position (1st item in window)
+    /// Performs a download confirmed by the user.
+    fn run_download(&mut self, download: Download) {
+        match download {
+            Download::Body(idx) => self.open_email(idx),
+            Download::Attachments(idx) => self.download_attachments(idx, false),
+            Download::OpenAttachments(idx) => self.download_attachments(idx, true),
+            Download::Source(idx) => self.show_source(idx),
+        }
+    }
+
+    /// Opens the folder browser with the current folder selected.
+    pub fn browse_folders(&mut self) {
+        let result = self.client().and_then(|c| {
+            let mailboxes = c.list_mailboxes()?;
+            Ok(FolderTree::new(build_folder_tree(&mailboxes), c.mailbox()))
+        });
+
+        match result {
+            Ok(tree) => {
+                self.folder_tree = Some(tree);
+                self.picker.clear();
+                self.mode = ViewMode::Folders;
+            }
+            Err(e) => self.notify(format!("Error: {e:#}")),
+        }
+    }
+
+    /// Prompts for a folder to open, offering the other folders with their
+    /// unread counts, recently used ones first.
+    pub fn change_folder(&mut self) {
+        match self.rank_folder_choices() {
+            Ok(choices) => {
+                self.folder_choices = choices;
+                self.prompt = Some(Prompt {
+                    label: "Open folder: ".to_string(),
+                    input: String::new(),
+                    kind: PromptKind::ChangeFolder,
+                });
+            }
+            Err(e) => self.notify(format!("Error: {e:#}")),
+        }
+    }
+
+    /// Returns the folders other than the current one, ranked for the
+    /// change-folder prompt.
+    ///
+    /// All folders are listed with their unread counts once; later only the
+    /// counts of recently used folders, where mail was likely read, are
+    /// fetched again.
+    fn rank_folder_choices(&mut self) -> Result<Vec<FolderChoice>> {
+        let recent = self.recent_folders.clone();
+        let mut folders = match self.known_folders.take() {
+            Some(mut folders) => {
+                let client = self.client()?;
+                for folder in folders.iter_mut().filter(|f| recent.contains(&f.path)) {
+                    folder.unread = client.unseen_count(&folder.path)?;
+                }
+                folders
+            }
+            None => self.fetch_folder_counts()?,
+        };
+        let current = self.client()?.mailbox().to_string();
+        self.known_folders = Some(folders.clone());
+
+        folders.retain(|folder| folder.path != current);
+        Ok(rank_folders(folders, &recent))
+    }
+
+    /// Lists the selectable folders in browser order with their unread
+    /// counts, asking the server for each with `STATUS`.
+    fn fetch_folder_counts(&mut self) -> Result<Vec<FolderChoice>> {
+        let client = self.client()?;
+        selectable_folders(&build_folder_tree(&client.list_mailboxes()?))
+            .into_iter()
+            .map(|path| {
+                let unread = client.unseen_count(&path)?;
+                Ok(FolderChoice { path, unread })
+            })
+            .collect()
+    }
+
+    /// Shows or hides the folder sidebar of the list view.
+    pub fn toggle_sidebar(&mut self) {
+        if self.sidebar.take().is_none() {
+            self.show_sidebar();
+        }
+    }
+
+    /// Shows the folder sidebar with fresh unread counts, its cursor on the
+    /// open folder.
+    pub fn show_sidebar(&mut self) {
+        match self.fetch_folder_counts() {
+            Ok(folders) => {
+                let current = self.client.as_ref().map_or("INBOX", |c| c.mailbox());
+                self.sidebar_selected = folders
+                    .iter()
+                    .position(|folder| folder.path == current)
+                    .unwrap_or(0);
+                self.known_folders = Some(folders.clone());
+                self.sidebar = Some(folders);
+            }
+            Err(e) => self.notify(format!("Error: {e:#}")),
+        }
+    }
+
+    /// Returns the folders listed by the sidebar and the row under its
+    /// cursor, when it is shown.
+    pub(crate) fn sidebar(&self) -> Option<(&[FolderChoice], usize)> {
+        self.sidebar
+            .as_deref()
+            .map(|folders| (folders, self.sidebar_selected))
+    }
+
+    /// Moves the sidebar cursor down.
+    pub fn sidebar_next(&mut self) {
+        match &self.sidebar {
+            Some(folders) if self.sidebar_selected + 1 < folders.len() => {
+                self.sidebar_selected += 1;
+            }
+            Some(_) => {}
+            None => self.notify("The sidebar is hidden; b shows it".to_string()),
+        }
+    }
+
+    /// Moves the sidebar cursor up.
+    pub fn sidebar_previous(&mut self) {
+        match &self.sidebar {
+            Some(_) => self.sidebar_selected = self.sidebar_selected.saturating_sub(1),
+            None => self.notify("The sidebar is hidden; b shows it".to_string()),
+        }
+    }
+
+    /// Opens the folder under the sidebar cursor and refreshes the unread
+    /// counts.
+    pub fn sidebar_open(&mut self) {
+        let Some(folders) = &self.sidebar else {
+            self.notify("The sidebar is hidden; b shows it".to_string());
+            return;
+        };
+        let Some(folder) = folders.get(self.sidebar_selected) else {
+            return;
+        };
+        let path = folder.path.clone();
+        match self.open_folder(&path) {
+            Ok(()) => self.show_sidebar(),
+            Err(e) => self.notify(format!("Error: {e:#}")),
+        }
+    }
+
+    /// Returns the folders of the change-folder or label prompt matching its
+    /// input; the first is picked on Enter.
+    pub(crate) fn folder_candidates(&self) -> Vec<&FolderChoice> {
+        match &self.prompt {
+            Some(Prompt {
+                kind:
+                    PromptKind::ChangeFolder
+                    | PromptKind::Label
+                    | PromptKind::Unlabel
+                    | PromptKind::Move,
+                input,
+                ..
+            }) => matching_folders(&self.folder_choices, input),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Moves the folder browser cursor down.
+    pub fn folder_next(&mut self) {
+        if let Some(tree) = self.folder_tree.as_mut() {
+            tree.next();
+        }
+    }
+
+    /// Moves the folder browser cursor up.
+    pub fn folder_previous(&mut self) {
+        if let Some(tree) = self.folder_tree.as_mut() {
+            tree.previous();
+        }
+    }
+
+    /// Starts typing a filter into the URL picker or folder browser.
+    pub fn start_picker_filter(&mut self) {
+        self.picker.start();
+    }
+
+    /// Returns the filter typed into the open picker, while it is typed or
+    /// narrows the list.
+    pub(crate) fn picker_filter(&self) -> Option<&str> {
+        let query = self.picker.query();
+        (self.picker.is_typing() || !query.is_empty()).then_some(query)
+    }
+
+    /// Returns whether keys go to the picker's filter.
+    pub fn picker_typing(&self) -> bool {
+        self.picker.is_typing()
+    }
+
+    /// Adds a character to the picker's filter.
+    pub fn picker_input(&mut self, c: char) {
+        self.picker.push(c);
+        self.apply_picker_filter();
+    }
+
+    /// Removes the last character of the picker's filter.
+    pub fn picker_backspace(&mut self) {
+        self.picker.backspace();
+        self.apply_picker_filter();
+    }
+
+    /// Stops typing the filter and picks the selected entry, as Enter does
+    /// outside the filter.
+    pub fn picker_accept(&mut self) {
+        self.picker.finish();
+        match self.mode {
+            ViewMode::Urls(_) => self.open_selected_url(),
+            ViewMode::Folders => self.folder_activate(),
+            _ => {}
+        }
+    }
+
+    /// Moves the cursor of the open picker down while typing its filter.
+    pub fn picker_next(&mut self) {
+        match self.mode {
+            ViewMode::Urls(_) => self.url_next(),
+            _ => self.folder_next(),
+        }
+    }
+
+    /// Moves the cursor of the open picker up while typing its filter.
+    pub fn picker_previous(&mut self) {
+        match self.mode {
+            ViewMode::Urls(_) => self.url_previous(),
+            _ => self.folder_previous(),
+        }
+    }
+
+    /// Stops typing and clears the picker's filter.
+    pub fn picker_cancel(&mut self) {
+        self.picker.clear();
+        self.apply_picker_filter();
+    }
+
+    /// Narrows the URL picker to the links matching the filter, or moves the
+    /// folder browser's cursor to the best matching folder.
+    fn apply_picker_filter(&mut self) {
+        match self.mode {
+            ViewMode::Urls(_) => self.url_selected = 0,
+            ViewMode::Folders => {
+                let query = self.picker.query().to_string();
+                let Some(tree) = self.folder_tree.as_mut() else {
+                    return;
+                };
+                if query.is_empty() {
+                    return;
+                }
+                let paths = tree.paths();
+                let best = fuzzy_matches(&paths, &query, |path| path)
+                    .first()
+                    .map(|&i| paths[i].to_string());
+                match best {
+                    Some(path) => tree.select_path(&path),
+                    None => self.notify(format!("No folder matches {}", query)),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Expands the selected folder in the browser.
+    pub fn folder_expand(&mut self) {
+        if let Some(tree) = self.folder_tree.as_mut() {
+            tree.expand();
+        }
+    }
+
+    /// Collapses the selected folder, or moves to its parent.
+    pub fn folder_collapse(&mut self) {
+        if let Some(tree) = self.folder_tree.as_mut() {
+            tree.collapse();
+        }
+    }
+
+    /// Opens the selected folder, or expands it if it cannot be selected.
+    pub fn folder_activate(&mut self) {
+        let action = match self.folder_tree.as_mut() {
+            Some(tree) => tree.activate(),
+            None => return,
+        };
+        if let FolderAction::Open(mailbox) = action
+            && let Err(e) = self.open_folder(&mailbox)
+        {
+            self.notify(format!("Error: {e:#}"));
+        }
+    }
+
+    /// Switches to the next folder (in folder browser order) that has unread
+    /// mail and moves the cursor to its oldest unread message.
+    ///
+    /// Folders listed in `unread_sweep_skip` are not considered, nor the
+    /// server's Junk and Trash folders.
+    pub fn next_unread_folder(&mut self) {
+        let mut skip = self.config.ui.unread_sweep_skip.clone();
+        let result = self.client().and_then(|c| {
+            skip.extend([SpecialUse::Junk, SpecialUse::Trash].map(|s| c.special_folder(s)));
+            let folders = selectable_folders(&build_folder_tree(&c.list_mailboxes()?));
+            let current = c.mailbox().to_string();
+            for folder in folders_after(&folders, &current) {
+                if !skip.iter().any(|s| s == folder) && c.unseen_count(folder)? > 0 {
+                    return Ok(Some(folder.to_string()));
+                }
+            }
+            Ok(None)
+        });
+
+        match result {
+            Ok(Some(folder)) => {
+                if let Err(e) = self.open_folder(&folder) {
+                    self.notify(format!("Error: {e:#}"));
+                    return;
+                }
+                let first_unread = self
+                    .emails
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, email)| !email.is_read)
+                    .min_by_key(|(_, email)| email.date);
+                if let Some((idx, _)) = first_unread {
+                    self.select_email(idx);
+                }
+                self.notify(format!("Opened {}", folder));
+            }
+            Ok(None) => self.notify("No other folder has unread mail".to_string()),
+            Err(e) => self.notify(format!("Error: {e:#}")),
+        }
+    }
+
+    /// Switches to a folder and loads its most recent emails.
+    ///
+    /// On failure the previous folder stays current.
+    fn open_folder(&mut self, mailbox: &str) -> Result<()> {
+        let mut previous = String::new();
+        let limit = self.config.imap.fetch_limit;
+        let fetched = self.client().and_then(|c| {
+            previous = c.mailbox().to_string();
+            c.set_mailbox(mailbox);
+            c.fetch_emails(limit).inspect_err(|_| {
+                c.set_mailbox(&previous);
+            })
+        })?;
+
+        if self.mail_watcher.is_some() {
+            self.mail_watcher = Some(MailWatcher::new(
+                &self.config.gmail,
+                &self.config.imap,
+                mailbox,
+            ));
+        }
+        if previous != mailbox {
+            self.recent_folders.retain(|f| *f != previous);
+            self.recent_folders.insert(0, previous);
+            self.recent_folders.truncate(MAX_RECENT_FOLDERS);
+        }
+        self.refresh_pending = false;
+        self.clear_folder_state();
+        // Notices about the previous folder no longer apply.
+        self.status.clear();
+        self.fetch_limit = self.config.imap.fetch_limit;
+        self.all_loaded = false;
+        self.older_requested = false;
+        self.limit = None;
+        self.search = None;
+        self.tagged.clear();
+        self.list_state.select(None);
+        self.emails = fetched.emails;
+        self.failed_uids = Vec::new();
+        self.set_failed(fetched.failed);
+        self.scroll_offset = 0;
+        self.load_important();
+        self.rebuild_rows();
+        self.place_initial_cursor();
+        self.back_to_list();
+        Ok(())
+    }
+
+    /// Forgets what was kept about the previous folder's messages by UID,
+    /// since UIDs are only unique within a folder.
+    fn clear_folder_state(&mut self) {
+        self.new_mail.clear();
+        self.loading.clear();
+        self.peeking.clear();
+        self.prefetching.clear();
+        self.prefetched.clear();
+        self.reports.clear();
+        self.alternatives.clear();
+        self.headers.clear();
+        self.translations.clear();
+        // Its result would land on the new folder's message with the UID.
+        self.translation_job = None;
+        self.source = None;
+        self.reply_trail.clear();
+        self.fetched_parents.clear();
+    }
+
+    /// Switches to one of Gmail's inbox tabs, reporting the outcome in the
+    /// status line.
+    pub fn open_tab(&mut self, tab: InboxTab) {
+        let outcome = match self.show_tab(tab) {
+            Ok(()) => format!("{} tab", tab.name()),
+            Err(e) => format!("Error: {e:#}"),
+        };
+        self.notify(outcome);
+    }
+
+    /// Shows INBOX limited to the messages of an inbox tab.
+    fn show_tab(&mut self, tab: InboxTab) -> Result<()> {
+        let client = self.client()?;
+        if !client.is_gmail() {
+            bail!("Inbox tabs need Gmail");
+        }
+        if client.mailbox() != "INBOX" {
+            self.open_folder("INBOX")?;
+        }
+        self.set_limit(tab.query())
+    }
+
+    /// Runs the config's `startup_commands` in order, stopping at the first
+    /// that fails and reporting it in the status line.
+    pub fn run_startup_commands(&mut self) {
+        for line in self.config.startup_commands.clone() {
+            if let Err(e) = self.run_command(&line) {
+                self.notify(format!("Startup command `{}` failed: {e:#}", line));
+                return;
+            }
+        }
+    }
+
+    /// Parses and runs a command line such as `:folder INBOX`.
+    pub(crate) fn run_command(&mut self, line: &str) -> Result<()> {
+        match Command::parse(line)? {
+            Command::Folder(mailbox) => self.open_folder(&mailbox),
+            Command::Limit(query) => self.set_limit(&query),
+            Command::Tab(tab) => self.show_tab(tab),
+            Command::CacheStats => self.cache_stats(),
+            Command::CacheCompact => self.cache_compact(),
+            Command::Version => {
+                self.show_version();
+                Ok(())
+            }
+            Command::Translate(target) => self.translate(&target),
+            Command::ExportIndex(path) => self.export_index(&path),
+            Command::Goto(number) => {
+                self.goto_number(number);
+                Ok(())
+            }
+            Command::Search(query) => self.search(&query),
+            Command::SearchAll(query) => self.search_all(&query),
+            Command::Sort(sort) => {
+                self.set_sort(sort);
+                Ok(())
+            }
+            Command::SaveAttachments(dir) => {
+                self.save_many_attachments(&dir);
+                Ok(())
+            }
+            Command::SaveMaildir(dir) => {
+                self.save_listed_maildir(&dir);
+                Ok(())
+            }
+        }
+    }
+
+    /// Saves the attachments of the tagged messages, or else the listed ones
+    /// as narrowed by any search, limit, or filter, into one directory,
+    /// asking first when the messages together are over the transfer budget.
+    fn save_many_attachments(&mut self, dir: &str) {
+        let emails = self.attachment_targets();
+        if emails.is_empty() {
+            self.notify("No messages listed".to_string());
+            return;
+        }
+        let dir = expand_tilde(dir);
+        match self.over_budget(&emails) {
+            Some(label) => {
+                self.prompt = Some(Prompt {
+                    label,
+                    input: String::new(),
+                    kind: PromptKind::ConfirmSaveAttachments(dir),
+                });
+            }
+            None => self.run_save_attachments(&dir),
+        }
+    }
+
+    /// Returns the messages `:save-attachments` covers: the tagged ones, or
+    /// every listed one when none is tagged.
+    fn attachment_targets(&self) -> Vec<usize> {
+        let tagged = self.tagged_emails();
+        if !tagged.is_empty() {
+            return tagged;
+        }
+        self.listed_emails()
+    }
+
+    /// Returns the messages listed, including those folded into collapsed
+    /// threads.
+    fn listed_emails(&self) -> Vec<usize> {
+        let mut emails = Vec::new();
+        for row in &self.rows {
+            match row.thread {
+                Some(thread) if row.hidden > 0 => emails.extend(
+                    self.threads[thread]
+                        .messages
+                        .iter()
+                        .map(|&(email, _)| email),
+                ),
+                _ => emails.push(row.email),
+            }
+        }
+        emails
+    }
+
+    /// Returns the question to ask before downloading every listed message
+    /// when together they exceed the transfer budget.
+    fn listed_over_budget(&self) -> Option<String> {
+        self.over_budget(&self.listed_emails())
+    }
+
+    /// Returns the question to ask before downloading the messages at
+    /// `emails` when together they exceed the transfer budget.
+    fn over_budget(&self, emails: &[usize]) -> Option<String> {
+        let budget = self.config.network.transfer_budget_kb;
+        let size = emails
+            .iter()
+            .map(|&idx| u64::from(self.emails[idx].size))
+            .sum::<u64>();
+        exceeds_budget(size, budget).then(|| {
+            format!(
+                "{} messages are {}, over the {} budget. Download? (y/N): ",
+                emails.len(),
+                format_bytes(size),
+                format_bytes(budget.unwrap_or(0).saturating_mul(1024))
+            )
+        })
+    }
+
+    /// Saves every listed message into a Maildir, asking first when they
+    /// exceed the transfer budget.
+    fn save_listed_maildir(&mut self, dir: &str) {
+        if self.rows.is_empty() {
+            self.notify("No messages listed".to_string());
+            return;
+        }
+        let dir = expand_tilde(dir);
+        match self.listed_over_budget() {
+            Some(label) => {
+                self.prompt = Some(Prompt {
+                    label,
+                    input: String::new(),
+                    kind: PromptKind::ConfirmSaveMaildir(dir),
+                });
+            }
+            None => self.run_save_maildir(&dir),
+        }
+    }
+
+    /// Fetches the listed messages and stores them with their flags in the
+    /// Maildir at `dir`, creating it if needed.
+    fn run_save_maildir(&mut self, dir: &Path) {
+        let uids = self
+            .listed_emails()
+            .into_iter()
+            .map(|idx| self.emails[idx]._uid)
+            .collect::<Vec<_>>();
+        let saved = Maildir::create(dir).and_then(|maildir| {
+            let messages = self.client()?.fetch_raw_messages(&uids)?;
+            let mut bytes = 0;
+            for message in &messages {
+                maildir.store(&message.data, &message.flags)?;
+                bytes += message.data.len() as u64;
+            }
+            Ok((messages.len(), bytes))
+        });
+        self.notify(match saved {
+            Ok((count, bytes)) => format!(
+                "Saved {} messages ({}) to {}",
+                count,
+                format_bytes(bytes),
+                dir.display()
+            ),
+            Err(e) => format!("Error: {e:#}"),
+        });
+    }
+
+    /// Prompts for a path to save the message in detail view, or the
+    /// selected one, to.
+    pub fn save_message(&mut self) {
+        let idx = match self.mode {
+            ViewMode::Detail(idx) => Some(idx),
+            _ => self.selected_email(),
+        };
+        let Some(idx) = idx else {
+            return;
+        };
+        // A subject is no path: its slashes stay in the name, not as folders.
+        let subject = self.emails[idx].subject.replace(['/', '\\'], "_");
+        let name = format!("{}.eml", subject);
+        self.prompt = Some(Prompt {
+            label: "Save message to (.txt for text): ".to_string(),
+            input: unique_path(Path::new(""), &name).display().to_string(),
+            kind: PromptKind::SaveMessage(idx),
+        });
+    }
+
+    /// Writes the email at index to a file: its decoded text for a `.txt`
+    /// path, otherwise its raw RFC822 source.
+    fn write_message(&mut self, idx: usize, path: &Path) -> Result<()> {
+        let uid = self.emails[idx]._uid;
+        let raw = self.client()?.fetch_raw_message(uid)?;
+        let data = if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("txt"))
+        {
+            message_as_text(&self.emails[idx], &raw)?.into_bytes()
+        } else {
+            raw
+        };
+
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {:?}", parent))?;
+        }
+        std::fs::write(path, data).with_context(|| format!("Failed to write {:?}", path))
+    }
+
+    /// Starts saving the attachments of the messages `:save-attachments`
+    /// covers into `dir` in the background.
+    fn run_save_attachments(&mut self, dir: &Path) {
+        if self.attachment_save.is_some() {
+            self.notify("Attachments are already being saved".to_string());
+            return;
+        }
+        let items = self
+            .attachment_targets()
+            .into_iter()
+            .map(|idx| SaveItem {
+                uid: self.emails[idx]._uid,
+                subject: self.emails[idx].subject.clone(),
+            })
+            .collect::<Vec<_>>();
+        let scan_command = self.config.attachments.scan_command.clone();
+        let (connect, mailbox) = match self.client() {
+            Ok(client) => (client.connector(), client.mailbox().to_string()),
+            Err(e) => {
+                self.notify(format!("Error: {e:#}"));
+                return;
+            }
+        };
+        self.notify(format!(
+            "Saving the attachments of {} messages to {}",
+            items.len(),
+            dir.display()
+        ));
+        self.attachment_save = Some(AttachmentSave::new(
+            connect,
+            &mailbox,
+            items,
+            dir.to_path_buf(),
+            scan_command,
+        ));
+    }
+
+    /// Returns how many messages the running `:save-attachments` went
+    /// through and in all.
+    pub(crate) fn attachment_save_progress(&self) -> Option<(usize, usize)> {
+        self.attachment_save.as_ref().map(AttachmentSave::progress)
+    }
+
+    /// Takes in the progress of `:save-attachments`, summing up what was
+    /// saved once it finished.
+    pub fn poll_attachment_save(&mut self) {
+        let Some(result) = self.attachment_save.as_mut().and_then(AttachmentSave::poll) else {
+            return;
+        };
+        let Some(save) = self.attachment_save.take() else {
+            return;
+        };
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                self.notify(format!("Error: {e:#}"));
+                return;
+            }
+        };
+        let mut summary = format!(
+            "Saved {} attachments ({}) from {} messages to {}",
+            result.saved,
+            format_bytes(result.bytes),
+            result.messages,
+            save.dir().display()
+        );
+        if result.blocked > 0 {
+            summary.push_str(&format!("; {} blocked by the scanner", result.blocked));
+        }
+        if let Some((subject, e)) = result.failed.first() {
+            summary.push_str(&format!(
+                "; {} messages failed to download, e.g. \"{}\": {:#}",
+                result.failed.len(),
+                subject,
+                e
+            ));
+        }
+        self.notify(summary);
+    }
+
+    /// Writes the listed messages, as narrowed by any search, limit, or
+    /// filter, to a CSV file, or JSON for a `.json` path.
+    fn export_index(&mut self, path: &str) -> Result<()> {
+        let path = expand_tilde(path);
+        let entries = self
+            .listed_emails()
+            .into_iter()
+            .map(|idx| IndexEntry::new(&self.emails[idx], self.is_important(idx)))
+            .collect::<Vec<_>>();
+        let file = File::create(&path).with_context(|| format!("Failed to create {:?}", path))?;
+        write_index(
+            BufWriter::new(file),
+            IndexFormat::from_path(&path),
+            &entries,
+        )
+        .with_context(|| format!("Failed to write {:?}", path))?;
+        self.notify(format!(
+            "Exported {} messages to {}",
+            entries.len(),
+            path.display()
+        ));
+        Ok(())
+    }
+
+    /// Returns the local cache, or an error when it is disabled.
+    fn message_cache(&mut self) -> Result<&MessageCache> {
+        self.client()?
+            .message_cache()
+            .context("The cache is disabled")
+    }
+
+    /// Reports how much space the local cache takes.
+    fn cache_stats(&mut self) -> Result<()> {
+        let limit = self
+            .config
+            .cache
+            .max_size_mb
+            .map(|mb| format!(" (limit {} MB)", mb))
+            .unwrap_or_default();
+        let stats = self.message_cache()?.stats()?;
+        self.notify(format!("Cache: {}{}", stats, limit));
+        Ok(())
+    }
+
+    /// Removes unneeded files from the local cache and reports the space
+    /// freed.
+    fn cache_compact(&mut self) -> Result<()> {
+        let removed = self.message_cache()?.compact()?;
+        self.notify(format!(
+            "Compacted cache: removed {} files, freed {}",
+            removed.files,
+            format_bytes(removed.bytes)
+        ));
+        Ok(())
+    }
+
+    /// Shows only the messages of the current folder matching a Gmail
+    /// search, or all of them again when the query is empty.
+    fn set_limit(&mut self, query: &str) -> Result<()> {
+        self.limit = if query.is_empty() {
+            None
+        } else {
+            let client = self.client()?;
+            let mailbox = client.mailbox().to_string();
+            let uids = client.search(&mailbox, query)?;
+            Some(Limit {
+                query: query.to_string(),
+                uids: uids.into_iter().collect(),
+            })
+        };
+        self.rebuild_rows();
+        Ok(())
+    }
+
+    /// Turns a quick filter on or off, keeping any others.
+    pub(crate) fn toggle_filter(&mut self, filter: Filter) {
+        match self.filters.iter().position(|f| *f == filter) {
+            Some(pos) => {
+                self.filters.remove(pos);
+            }
+            None => self.filters.push(filter),
+        }
+        self.rebuild_rows();
+        if self.rows.is_empty() {
+            self.notify("No messages match the filters".to_string());
+        }
+    }
+
+    /// Turns all quick filters, the pattern and focus mode off, or quits
+    /// when none is on.
+    pub fn clear_filters(&mut self) {
+        if self.filters.is_empty() && self.pattern.is_none() && !self.focus {
+            self.should_quit = true;
+            return;
+        }
+        self.filters.clear();
+        self.pattern = None;
+        self.focus = false;
+        self.rebuild_rows();
+    }
+
+    /// Turns focus mode on or off.
+    ///
+    /// Focus mode is for triage: read and muted messages are hidden, and
+    /// after marking, archiving or flagging a message the cursor moves on to
+    /// the next unread one.
+    pub fn toggle_focus(&mut self) {
+        self.focus = !self.focus;
+        self.rebuild_rows();
+        if !self.focus {
+            self.notify("Focus off".to_string());
+        } else if self.rows.is_empty() {
+            self.notify("Focus on; no unread messages".to_string());
+        } else {
+            self.notify("Focus on; read messages hidden".to_string());
+        }
+    }
+
+    /// Returns whether focus mode is on.
+    pub(crate) fn focus(&self) -> bool {
+        self.focus
+    }
+
+    /// In focus mode, moves the cursor off a message that was just handled
+    /// but is still listed onto the next unread one.
+    fn focus_advance(&mut self) {
+        if !self.focus || self.rows.is_empty() {
+            return;
+        }
+        let current = self.list_state.selected().unwrap_or(0);
+        let len = self.rows.len();
+        if let Some(row) = (1..len)
+            .map(|offset| (current + offset) % len)
+            .find(|&row| self.rows[row].unread)
+        {
+            self.select_row(row);
+        }
+    }
+
+    /// Opens the prompt for a pattern to limit the list to, like mutt's `l`.
+    pub fn start_pattern(&mut self) {
+        self.prompt = Some(Prompt {
+            label: "Limit to pattern: ".to_string(),
+            input: self
+                .pattern
+                .as_ref()
+                .map(|pattern| pattern.as_str().to_string())
+                .unwrap_or_default(),
+            kind: PromptKind::Pattern,
+        });
+    }
+
+    /// Shows only the messages matching a pattern, checked against the
+    /// headers already loaded, or all of them again when it is empty.
+    fn set_pattern(&mut self, source: &str) -> Result<()> {
+        self.pattern = if source.trim().is_empty() {
+            None
+        } else {
+            Some(Pattern::parse(source, Local::now())?)
+        };
+        self.rebuild_rows();
+        if self.rows.is_empty() {
+            self.notify("No messages match the filters".to_string());
+        }
+        Ok(())
+    }
+
+    /// Returns the pattern the list is limited to, if any.
+    pub(crate) fn pattern(&self) -> Option<&Pattern> {
+        self.pattern.as_ref()
+    }
+
+    /// Returns the active quick filters in the order they were turned on.
+    pub(crate) fn filters(&self) -> &[Filter] {
+        &self.filters
+    }
+
+    /// Returns the search the list is limited to, if any.
+    pub fn limit_query(&self) -> Option<&str> {
+        self.limit.as_ref().map(|limit| limit.query.as_str())
+    }
+
+    /// Switches to the inbox cleanup view, listing the senders of the loaded
+    /// messages.
+    pub fn show_cleanup(&mut self) {
+        self.mode = ViewMode::Cleanup;
+        self.sender_selected = 0;
+        self.refresh_senders();
+    }
+
+    /// Recounts the senders after messages were removed, keeping the cursor
+    /// in range.
+    fn refresh_senders(&mut self) {
+        self.senders = sender_stats(&self.emails, self.senders_by_size);
+        self.sender_selected = self
+            .sender_selected
+            .min(self.senders.len().saturating_sub(1));
+    }
+
+    /// Returns the senders listed by the cleanup view and the selected row.
+    pub(crate) fn senders(&self) -> (&[SenderStats], usize) {
+        (&self.senders, self.sender_selected)
+    }
+
+    /// Returns whether the cleanup view ranks senders by size.
+    pub(crate) fn senders_by_size(&self) -> bool {
+        self.senders_by_size
+    }
+
+    /// Moves to the next sender in the cleanup view.
+    pub fn sender_next(&mut self) {
+        if self.sender_selected + 1 < self.senders.len() {
+            self.sender_selected += 1;
+        }
+    }
+
+    /// Moves to the previous sender in the cleanup view.
+    pub fn sender_previous(&mut self) {
+        self.sender_selected = self.sender_selected.saturating_sub(1);
+    }
+
+    /// Ranks the cleanup view by message count or by total size.
+    pub fn toggle_sender_sort(&mut self) {
+        self.senders_by_size = !self.senders_by_size;
+        self.sender_selected = 0;
+        self.refresh_senders();
+    }
+
+    /// Returns the indexes of the loaded emails from a sender.
+    fn sender_emails(&self, address: &str) -> Vec<usize> {
+        (0..self.emails.len())
+            .filter(|&idx| sender_address(&self.emails[idx]).as_deref() == Some(address))
+            .collect()
+    }
+
+    /// Archives every loaded message from the selected sender.
+    pub fn archive_sender(&mut self) {
+        let Some(sender) = self.senders.get(self.sender_selected) else {
+            return;
+        };
+        let targets = self.sender_emails(&sender.address.clone());
+        self.run_op(MessageOp::Archive, &targets);
+        self.refresh_senders();
+    }
+
+    /// Asks whether to move every loaded message from the selected sender
+    /// to the trash.
+    pub fn delete_sender(&mut self) {
+        let Some(sender) = self.senders.get(self.sender_selected) else {
+            return;
+        };
+        self.prompt = Some(Prompt {
+            label: format!(
+                "Move {} messages from {} to the trash? (y/N): ",
+                sender.count, sender.address
+            ),
+            input: String::new(),
+            kind: PromptKind::ConfirmDeleteSender(sender.address.clone()),
+        });
+    }
+
+    /// Hides the selected sender's messages from the list from now on,
+    /// saving the rule to the watched config file.
+    pub fn mute_sender(&mut self) {
+        let Some(sender) = self.senders.get(self.sender_selected) else {
+            return;
+        };
+        let address = sender.address.clone();
+        if !is_muted_address(&self.config.ui.muted_senders, &address) {
+            self.config.ui.muted_senders.push(address.clone());
+        }
+        self.rebuild_rows();
+
+        let muted = self
+            .config
+            .ui
+            .muted_senders
+            .iter()
+            .collect::<toml_edit::Array>();
+        let saved = self
+            .config_watcher
+            .as_ref()
+            .context("Config file not watched")
+            .and_then(|watcher| save_setting(watcher.path(), "ui", "muted_senders", muted));
+        self.notify(match saved {
+            Ok(()) => format!("Muted {}", address),
+            Err(e) => format!("Muted {} (not saved: {e:#})", address),
+        });
+    }
+
+    /// Switches to the session statistics view.
+    pub fn show_stats(&mut self) {
+        self.mode = ViewMode::Stats;
+    }
+
+    /// Translates the message being read into `target`, or the configured
+    /// language when empty. Without a target, a message shown translated is
+    /// shown in the original again.
+    fn translate(&mut self, target: &str) -> Result<()> {
+        let ViewMode::Detail(idx) = self.mode else {
+            bail!("`:translate` works on the message being read");
+        };
+        let uid = self.emails[idx]._uid;
+        if target.is_empty() && self.translations.remove(&uid).is_some() {
+            self.notify("Showing the original".to_string());
+            return Ok(());
+        }
+        let Some(body) = self.emails[idx].body.clone() else {
+            bail!("The message is not loaded yet");
+        };
+        let translate = &self.config.translate;
+        let Some(command) = &translate.command else {
+            bail!("Set command under [translate] to translate messages");
+        };
+        if self.translation_job.is_some() {
+            bail!("A translation is already running");
+        }
+        let from = match &translate.source {
+            Some(source) => source.clone(),
+            None => detect_language(&body)
+                .map_or(AUTO_LANGUAGE, |language| language.short_code())
+                .to_string(),
+        };
+        let to = if target.is_empty() {
+            translate.target.as_str()
+        } else {
+            target
+        };
+        self.translation_job = Some(TranslationJob::start(
+            uid,
+            command,
+            body,
+            &from,
+            to,
+            Duration::from_secs(translate.timeout_secs),
+        )?);
+        self.notify(format!("Translating from {} to {}", from, to));
+        Ok(())
+    }
+
+    /// Returns whether a translation is under way.
+    pub(crate) fn translating(&self) -> bool {
+        self.translation_job.is_some()
+    }
+
+    /// Takes in the translation once it is done, showing it for its message.
+    pub fn poll_translation(&mut self) {
+        let Some(result) = self.translation_job.as_ref().and_then(TranslationJob::poll) else {
+            return;
+        };
+        let Some(job) = self.translation_job.take() else {
+            return;
+        };
+        match result {
+            Ok(translation) => {
+                self.notify(format!(
+                    "Translated from {} to {}; :translate shows the original",
+                    translation.from, translation.to
+                ));
+                self.translations.insert(job.uid(), translation);
+            }
+            Err(e) => self.notify(format!("Error: {e:#}")),
+        }
+    }
+
+    /// Returns the translation of the email at index, if it was translated.
+    pub(crate) fn translation(&self, idx: usize) -> Option<&Translation> {
+        let uid = self.emails.get(idx)?._uid;
+        self.translations.get(&uid)
+    }
+
+    /// Switches to the version view, starting the update check the first
+    /// time if it is enabled.
+    pub fn show_version(&mut self) {
+        if self.config.network.check_updates && self.update_check.is_none() {
+            self.update_check = Some(UpdateCheck::start());
+        }
+        self.mode = ViewMode::Version;
+    }
+
+    /// Returns the outcome of the update check, if one was started.
+    pub(crate) fn update_status(&self) -> Option<&UpdateStatus> {
+        self.update_check.as_ref().map(UpdateCheck::status)
+    }
+
+    /// Takes in the answer of the update check once it arrived.
+    pub fn poll_update_check(&mut self) {
+        if let Some(check) = self.update_check.as_mut() {
+            check.poll();
+        }
+    }
+
+    /// Returns to the email list view from detail view.
+    pub fn back_to_list(&mut self) {
+        let opened = match self.mode {
+            ViewMode::Detail(idx) => Some(idx),
+            _ => None,
+        };
+        // Fetching the body marked the message seen on the server, so in
+        // focus mode it leaves the list.
+        if self.focus
+            && let ViewMode::Detail(idx) = self.mode
+            && self.emails[idx].body.is_some()
+            && !self.emails[idx].is_read
+        {
+            self.emails[idx].is_read = true;
+            self.rebuild_rows();
+        }
+        self.mode = ViewMode::List;
+        self.pending_attachments.clear();
+        self.source = None;
+        self.full_headers = false;
+        self.participant_filter = None;
+        self.reply_trail.clear();
+
+        // Reset detail scroll when going back to list.
+        self.detail_scroll_offset = 0;
+
+        if let Some(idx) = opened {
+            self.advance_after_read(idx);
+        }
+    }
+
+    /// Moves the cursor past a message just read, as `after_read` sets.
+    fn advance_after_read(&mut self, idx: usize) {
+        let after_read = self.config.ui.after_read;
+        if after_read == AfterRead::ReturnToList || self.rows.is_empty() {
+            return;
+        }
+        // A message no longer listed left the cursor on the one after it.
+        let listed = self.select_email(idx);
+        let current = self.list_state.selected().unwrap_or(0);
+        let start = usize::from(listed);
+        match after_read {
+            AfterRead::ReturnToList => {}
+            AfterRead::Next => {
+                if current + start < self.rows.len() {
+                    self.select_row(current + start);
+                }
+            }
+            AfterRead::NextUnread => {
+                let len = self.rows.len();
+                let next = (start..len)
+                    .map(|offset| (current + offset) % len)
+                    .find(|&row| self.rows[row].unread && self.rows[row].email != idx);
+                match next {
+                    Some(row) => self.select_row(row),
+                    None => self.notify("No unread messages".to_string()),
+                }
+            }
+        }
+    }
+
+    /// Adds a key to the pending multi-key sequence.
+    pub fn push_pending_key(&mut self, c: char) {
+        if self.pending_keys.is_empty() {
+            self.pending_since = Some(Instant::now());
+        }
+        self.pending_keys.push(c);
+    }
+
+    /// Discards the pending multi-key sequence.
+    pub fn clear_pending_keys(&mut self) {
+        self.pending_keys.clear();
+        self.pending_since = None;
+    }
+
+    /// Returns how long to wait for the next key of a pending sequence, or
+    /// `None` when no keys are pending.
+    pub fn pending_timeout(&self) -> Option<Duration> {
+        let since = self.pending_since?;
+        let timeout = Duration::from_millis(self.config.ui.chord_timeout_ms);
+        Some(timeout.saturating_sub(since.elapsed()))
+    }
+
+    /// Marks the selected message read, or unread if it is already read.
+    /// With messages tagged, marks them all read unless all are read.
+    pub fn toggle_read(&mut self) {
+        let tagged = self.tagged_emails();
+        let unread = if tagged.is_empty() {
+            let Some(row) = self
+                .list_state
+                .selected()
+                .and_then(|row| self.rows.get(row))
+            else {
+                return;
+            };
+            row.unread
+        } else {
+            tagged.iter().any(|&idx| !self.emails[idx].is_read)
+        };
+        let op = if unread {
+            MessageOp::MarkRead
+        } else {
+            MessageOp::MarkUnread
+        };
+        self.request_op(op);
+    }
+
+    /// Flags or unflags the selected message. With messages tagged, flags
+    /// them all unless all are flagged.
+    pub fn toggle_flag(&mut self) {
+        let mut targets = self.tagged_emails();
+        if targets.is_empty() {
+            targets.extend(self.selected_email());
+        }
+        if targets.is_empty() {
+            return;
+        }
+        let op = if targets.iter().all(|&idx| self.emails[idx].is_flagged) {
+            MessageOp::Unflag
+        } else {
+            MessageOp::Flag
+        };
+        self.request_op(op);
+    }
+
+    /// Archives the selected message.
+    pub fn archive(&mut self) {
+        self.request_op(MessageOp::Archive);
+    }
+
+    /// Moves the selected message to the trash.
+    pub fn delete(&mut self) {
+        self.request_op(MessageOp::Delete);
+    }
+
+    /// Opens the folder picker to move the tagged messages, or the selected
+    /// message when none is tagged.
+    pub fn move_messages(&mut self) {
+        self.pick_folder("Move to", PromptKind::Move);
+    }
+
+    /// Tags or untags the selected message and moves to the next one.
+    pub fn toggle_tag(&mut self) {
+        let Some(idx) = self.selected_email() else {
+            return;
+        };
+        let uid = self.emails[idx]._uid;
+        if !self.tagged.remove(&uid) {
+            self.tagged.insert(uid);
+        }
+        let row = self.list_state.selected().unwrap_or(0);
+        if row + 1 < self.rows.len() {
+            self.select_row(row + 1);
+        }
+    }
+
+    /// Opens the `:` prompt for a command.
+    pub fn start_command(&mut self) {
+        self.history_position = None;
+        self.prompt = Some(Prompt {
+            label: ":".to_string(),
+            input: String::new(),
+            kind: PromptKind::Command,
+        });
+    }
+
+    /// Shows the command entered before the one in the `:` prompt.
+    pub fn prompt_history_previous(&mut self) {
+        if self.command_history.is_empty() {
+            return;
+        }
+        let position = match self.history_position {
+            Some(position) => position.saturating_sub(1),
+            None => self.command_history.len() - 1,
+        };
+        self.show_history(Some(position));
+    }
+
+    /// Shows the command entered after the one in the `:` prompt, or an
+    /// empty line past the last.
+    pub fn prompt_history_next(&mut self) {
+        let position = self
+            .history_position
+            .map(|position| position + 1)
+            .filter(|&position| position < self.command_history.len());
+        self.show_history(position);
+    }
+
+    fn show_history(&mut self, position: Option<usize>) {
+        let Some(prompt) = self
+            .prompt
+            .as_mut()
+            .filter(|prompt| matches!(prompt.kind, PromptKind::Command))
+        else {
+            return;
+        };
+        self.history_position = position;
+        prompt.input = position
+            .map(|position| self.command_history[position].clone())
+            .unwrap_or_default();
+    }
+
+    /// Completes the command in the `:` prompt as far as it is unambiguous,
+    /// listing the candidates when several remain.
+    pub fn prompt_complete(&mut self) {
+        let Some(input) = self
+            .prompt
+            .as_ref()
+            .filter(|prompt| matches!(prompt.kind, PromptKind::Command))
+            .map(|prompt| prompt.input.clone())
+        else {
+            return;
+        };
+        let folders = if input.contains(' ') {
+            self.folder_names()
+        } else {
+            Vec::new()
+        };
+        let completions = command::complete(&input, &folders);
+        let completed = command::common_prefix(&completions);
+        if completions.len() > 1 {
+            // Only the word being completed: the name, or the argument.
+            let names = completions
+                .iter()
+                .map(|c| c.trim_start_matches(':').trim_end())
+                .map(|c| c.split_once(' ').map_or(c, |(_, arg)| arg))
+                .collect::<Vec<_>>();
+            self.notify(names.join("  "));
+        }
+        if completed.len() > input.len()
+            && let Some(prompt) = self.prompt.as_mut()
+        {
+            prompt.input = completed;
+        }
+    }
+
+    /// Returns the names of the selectable folders, for completion.
+    fn folder_names(&mut self) -> Vec<String> {
+        if let Some(folders) = &self.known_folders {
+            return folders.iter().map(|f| f.path.clone()).collect();
+        }
+        self.client()
+            .and_then(|client| client.list_mailboxes())
+            .map(|mailboxes| selectable_folders(&build_folder_tree(&mailboxes)))
+            .unwrap_or_default()
+    }
+
+    /// Runs a command entered at the `:` prompt and remembers it.
+    fn submit_command(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+        if self.command_history.last().map(String::as_str) != Some(line) {
+            self.command_history.push(line.to_string());
+        }
+        if let Err(e) = self.run_command(line) {
+            self.notify(format!("Error: {e:#}"));
+        }
+    }
+
+    /// Opens the prompt for a pattern to tag the matching messages with,
+    /// like mutt's `T`.
+    pub fn start_tag_pattern(&mut self) {
+        self.prompt = Some(Prompt {
+            label: "Tag pattern (empty: untag all): ".to_string(),
+            input: String::new(),
+            kind: PromptKind::TagPattern,
+        });
+    }
+
+    /// Tags the loaded messages matching a pattern, checked against their
+    /// headers, or untags every message when it is empty.
+    fn tag_pattern(&mut self, source: &str) -> Result<()> {
+        if source.trim().is_empty() {
+            self.tagged.clear();
+            self.notify("Untagged all messages".to_string());
+            return Ok(());
+        }
+        let pattern = Pattern::parse(source, Local::now())?;
+        let matching = self
+            .emails
+            .iter()
+            .filter(|email| pattern.matches(email))
+            .map(|email| email._uid)
+            .collect::<Vec<_>>();
+        self.notify(match matching.len() {
+            0 => "No messages match the pattern".to_string(),
+            1 => "Tagged 1 message".to_string(),
+            n => format!("Tagged {} messages", n),
+        });
+        self.tagged.extend(matching);
+        Ok(())
+    }
+
+    /// Returns whether a message is tagged.
+    pub fn is_tagged(&self, email: &Email) -> bool {
+        self.tagged.contains(&email._uid)
+    }
+
+    /// Returns the indices of the loaded messages that are tagged.
+    fn tagged_emails(&self) -> Vec<usize> {
+        (0..self.emails.len())
+            .filter(|&idx| self.is_tagged(&self.emails[idx]))
+            .collect()
+    }
+
+    /// Returns how many loaded messages are tagged.
+    pub fn tagged_count(&self) -> usize {
+        self.tagged_emails().len()
+    }
+
+    /// Opens the label picker to add a label to the tagged messages, or the
+    /// selected message when none is tagged.
+    pub fn label(&mut self) {
+        self.pick_folder("Label", PromptKind::Label);
+    }
+
+    /// Opens the label picker to remove a label from the tagged messages, or
+    /// the selected message when none is tagged.
+    pub fn unlabel(&mut self) {
+        self.pick_folder("Remove label", PromptKind::Unlabel);
+    }
+
+    /// Prompts for a label or folder, offering the folders matching the
+    /// input.
+    fn pick_folder(&mut self, action: &str, kind: PromptKind) {
+        let tagged = self.tagged_count();
+        if tagged == 0 && self.selected_email().is_none() {
+            return;
+        }
+        // Names can still be typed when the folders cannot be listed.
+        self.folder_choices = self.rank_folder_choices().unwrap_or_default();
+        let label = match tagged {
+            0 => format!("{}: ", action),
+            1 => format!("{} (1 tagged): ", action),
+            n => format!("{} ({} tagged): ", action, n),
+        };
+        self.prompt = Some(Prompt {
+            label,
+            input: String::new(),
+            kind,
+        });
+    }
+
+    /// Prompts for a Gmail query to search the current folder with.
+    pub fn start_search(&mut self) {
+        self.prompt = Some(Prompt {
+            label: "Search: ".to_string(),
+            input: self.search.clone().unwrap_or_default(),
+            kind: PromptKind::Search,
+        });
+    }
+
+    /// Searches the current folder on the server and replaces the list with
+    /// the most recent matching messages.
+    ///
+    /// An empty query shows the folder again. With `cache.full_text` on,
+    /// plain words are looked up in the cached messages instead; queries with
+    /// search operators still go to the server.
+    fn search(&mut self, query: &str) -> Result<()> {
+        let full_text = self.config.cache.full_text;
+        let limit = self.config.imap.fetch_limit as usize;
+        if query.is_empty() {
+            if self.search.is_some() {
+                self.leave_search()?;
+            }
+            return Ok(());
+        }
+
+        // The cache answers a plain query without the server, even offline.
+        let cached = match (&self.offline, &self.client) {
+            _ if !full_text || query.contains(':') => None,
+            (Some(offline), _) => Some(offline.search_text(query)?),
+            (None, Some(client)) => client.search_cached(query)?,
+            (None, None) => None,
+        };
+        if let Some(mut emails) = cached {
+            if emails.is_empty() {
+                self.notify(format!("No matches for {} in cached messages", query));
+                return Ok(());
+            }
+            emails.sort_by_key(|e| std::cmp::Reverse(e.date));
+            self.notify(match emails.len() {
+                1 => "1 match in cached messages".to_string(),
+                n => format!("{} matches in cached messages", n),
+            });
+            self.show_results(query, emails);
+            return Ok(());
+        }
+
+        let client = self.client()?;
+        let mailbox = client.mailbox().to_string();
+        let uids = client.search(&mailbox, query)?;
+        if uids.is_empty() {
+            self.notify(format!("No matches for {}", query));
+            return Ok(());
+        }
+        let recent = &uids[uids.len().saturating_sub(limit)..];
+        let emails = client.fetch_envelopes(recent)?;
+
+        self.notify(match uids.len() {
+            1 => "1 match".to_string(),
+            n if n > recent.len() => {
+                format!("{} matches; showing the {} most recent", n, recent.len())
+            }
+            n => format!("{} matches", n),
+        });
+        self.show_results(query, emails);
+        Ok(())
+    }
+
+    /// Lists the folder again in place of search results, from the cache
+    /// with the queued flag changes made while offline.
+    fn leave_search(&mut self) -> Result<()> {
+        let Some(offline) = &self.offline else {
+            let mailbox = self.client()?.mailbox().to_string();
+            return self.open_folder(&mailbox);
+        };
+        let mut emails = offline.emails()?;
+        for entry in &self.queued {
+            for email in emails.iter_mut().filter(|e| entry.uids.contains(&e._uid)) {
+                match entry.op {
+                    JournalOp::MarkRead => email.is_read = true,
+                    JournalOp::MarkUnread => email.is_read = false,
+                    JournalOp::Flag => email.is_flagged = true,
+                    JournalOp::Unflag => email.is_flagged = false,
+                    _ => {}
+                }
+            }
+        }
+        self.emails = emails;
+        self.search = None;
+        self.list_state.select(None);
+        self.scroll_offset = 0;
+        self.rebuild_rows();
+        self.place_initial_cursor();
+        Ok(())
+    }
+
+    /// Replaces the list with search results, with the cursor on the first.
+    fn show_results(&mut self, query: &str, emails: Vec<Email>) {
+        self.search = Some(query.to_string());
+        self.failed_uids.clear();
+        self.limit = None;
+        self.new_mail.clear();
+        self.list_state.select(None);
+        self.emails = emails;
+        self.scroll_offset = 0;
+        self.rebuild_rows();
+    }
+
+    /// Starts searching every folder for `query` in the background, or All
+    /// Mail on Gmail. An empty query lists the last results again.
+    fn search_all(&mut self, query: &str) -> Result<()> {
+        if query.is_empty() {
+            if self.folder_results.is_none() {
+                bail!("No search across folders yet");
+            }
+            self.show_folder_results();
+            return Ok(());
+        }
+        if self.folder_search.is_some() {
+            bail!("A search across folders is already running");
+        }
+        let limit = self.config.imap.fetch_limit as usize;
+        let client = self.client()?;
+        let folders = if client.is_gmail() {
+            vec![client.special_folder(SpecialUse::All)]
+        } else {
+            client.subscribed_mailboxes()?
+        };
+        let connect = client.connector();
+        self.notify(match folders.len() {
+            1 => format!("Searching {} for {}", folders[0], query),
+            n => format!("Searching {} folders for {}", n, query),
+        });
+        self.folder_search = Some(FolderSearch::new(connect, folders, query, limit));
+        Ok(())
+    }
+
+    /// Returns how many folders the running all-folder search went through
+    /// and in all.
+    pub(crate) fn folder_search_progress(&self) -> Option<(usize, usize)> {
+        self.folder_search.as_ref().map(FolderSearch::progress)
+    }
+
+    /// Takes in the progress of an all-folder search, listing its matches
+    /// once it finished. They are kept for later when another view is open.
+    pub fn poll_folder_search(&mut self) {
+        let Some(result) = self.folder_search.as_mut().and_then(FolderSearch::poll) else {
+            return;
+        };
+        let Some(search) = self.folder_search.take() else {
+            return;
+        };
+        let query = search.query().to_string();
+        let results = match result {
+            Ok(results) => results,
+            Err(e) => {
+                self.notify(format!("Error: {e:#}"));
+                return;
+            }
+        };
+        if !results.failed.is_empty() {
+            self.notify(format!("Could not search {}", results.failed.join(", ")));
+        }
+        if results.hits.is_empty() {
+            self.notify(format!("No matches for {} in any folder", query));
+            return;
+        }
+        let folders = results
+            .hits
+            .iter()
+            .map(|hit| hit.folder.as_str())
+            .collect::<HashSet<_>>()
+            .len();
+        let found = match (results.hits.len(), folders) {
+            (1, _) => "1 match".to_string(),
+            (n, 1) => format!("{} matches", n),
+            (n, folders) => format!("{} matches in {} folders", n, folders),
+        };
+        self.folder_results = Some((query, results.hits));
+        if matches!(self.mode, ViewMode::List) {
+            self.notify(found);
+            self.show_folder_results();
+        } else {
+            self.notify(format!("{}; :search-all lists them", found));
+        }
+    }
+
+    /// Switches to the results of the last all-folder search.
+    fn show_folder_results(&mut self) {
+        self.mode = ViewMode::Results;
+        self.result_selected = 0;
+    }
+
+    /// Returns the query and matches of the last all-folder search, and the
+    /// selected row.
+    pub(crate) fn folder_results(&self) -> Option<(&str, &[FolderHit], usize)> {
+        self.folder_results
+            .as_ref()
+            .map(|(query, hits)| (query.as_str(), hits.as_slice(), self.result_selected))
+    }
+
+    /// Moves to the next match in the all-folder results.
+    pub fn result_next(&mut self) {
+        let len = self
+            .folder_results
+            .as_ref()
+            .map_or(0, |(_, hits)| hits.len());
+        if self.result_selected + 1 < len {
+            self.result_selected += 1;
+        }
+    }
+
+    /// Moves to the previous match in the all-folder results.
+    pub fn result_previous(&mut self) {
+        self.result_selected = self.result_selected.saturating_sub(1);
+    }
+
+    /// Opens the folder of the selected match and lists the folder's
+    /// matches there, with the cursor on it.
+    pub fn open_result(&mut self) {
+        let Some((query, hit)) = self.folder_results.as_ref().and_then(|(query, hits)| {
+            let hit = hits.get(self.result_selected)?;
+            Some((query.clone(), hit.clone()))
+        }) else {
+            return;
+        };
+        self.mode = ViewMode::List;
+        if let Err(e) = self
+            .open_folder(&hit.folder)
+            .and_then(|()| self.search(&query))
+        {
+            self.notify(format!("Error: {e:#}"));
+            return;
+        }
+        if let Some(idx) = self.emails.iter().position(|e| e._uid == hit.email._uid) {
+            self.select_email(idx);
+        }
+    }
+
+    /// Returns the query whose results are listed, if any.
+    pub fn search_query(&self) -> Option<&str> {
+        self.search.as_deref()
+    }
+
+    /// Moves the cursor to the next search result, or the previous one when
+    /// not `forward`, wrapping around at the ends of the list.
+    pub fn next_match(&mut self, forward: bool) {
+        if self.search.is_none() {
+            self.notify("No search; press / to search".to_string());
+            return;
+        }
+        let len = self.rows.len();
+        if len == 0 {
+            return;
+        }
+        let current = self.list_state.selected().unwrap_or(0);
+        let row = if forward {
+            if current + 1 == len {
+                self.notify("Search wrapped to top".to_string());
+            }
+            (current + 1) % len
+        } else {
+            if current == 0 {
+                self.notify("Search wrapped to bottom".to_string());
+            }
+            (current + len - 1) % len
+        };
+        self.select_row(row);
+    }
+
+    /// Returns the indexes of the emails in the selected row's thread, or of
+    /// the selected email alone outside threaded view.
+    ///
+    /// The thread is the one built from the loaded headers: Gmail's
+    /// `X-GM-THRID` fetch attribute is not understood by the IMAP library.
+    fn selected_thread(&self) -> Vec<usize> {
+        let Some(row) = self
+            .list_state
+            .selected()
+            .and_then(|row| self.rows.get(row))
+        else {
+            return Vec::new();
+        };
+        match row.thread {
+            Some(thread) => self.threads[thread]
+                .messages
+                .iter()
+                .map(|&(idx, _)| idx)
+                .collect(),
+            None => vec![row.email],
+        }
+    }
+
+    /// Applies an operation to the tagged messages, in one command per
+    /// operation. With none tagged, applies it to the selected message, first
+    /// asking whether to apply it to the rest of its thread when there is one.
+    ///
+    /// Trashing more than one tagged message is confirmed first, as the tags
+    /// may be forgotten by then.
+    fn request_op(&mut self, op: MessageOp) {
+        let tagged = self.tagged_emails();
+        if tagged.len() > 1 && op == MessageOp::Delete {
+            self.prompt = Some(Prompt {
+                label: format!(
+                    "Move {} tagged messages to the trash? (y/N): ",
+                    tagged.len()
+                ),
+                input: String::new(),
+                kind: PromptKind::ConfirmDeleteTagged,
+            });
+            return;
+        }
+        if !tagged.is_empty() {
+            self.run_op(op, &tagged);
+            return;
+        }
+        let thread = self.selected_thread();
+        if thread.len() > 1 {
+            self.prompt = Some(Prompt {
+                label: format!("Apply to {} messages in thread? (y/N): ", thread.len()),
+                input: String::new(),
+                kind: PromptKind::ConfirmThread(op),
+            });
+        } else {
+            self.run_op(op, &thread);
+        }
+    }
+
+    /// Groups emails by the folder the archive policy files them in, or
+    /// returns nothing when they are archived to All Mail.
+    fn archive_folders(&self, targets: &[usize]) -> BTreeMap<String, Vec<u32>> {
+        let mut folders = BTreeMap::<_, Vec<_>>::new();
+        if let Some(template) = &self.config.archive.folder {
+            for &idx in targets {
+                let email = &self.emails[idx];
+                folders
+                    .entry(archive_folder(template, email.date.year()))
+                    .or_default()
+                    .push(email._uid);
+            }
+        }
+        folders
+    }
+
+    /// Records an operation on messages of the current mailbox in the
+    /// journal. It is still returned when the journal cannot be written, so
+    /// the operation goes ahead.
+    fn record(&mut self, uids: Vec<u32>, op: JournalOp) -> JournalEntry {
+        let (mailbox, uid_validity) = match (&self.client, &self.offline) {
+            (Some(client), _) => (client.mailbox().to_string(), client.uid_validity()),
+            (None, Some(offline)) => (offline.mailbox().to_string(), Some(offline.uid_validity())),
+            (None, None) => ("INBOX".to_string(), None),
+        };
+        let recorded = self
+            .journal
+            .as_mut()
+            .map(|journal| journal.record(&mailbox, uid_validity, &uids, op.clone()));
+        match recorded {
+            Some(Ok(entry)) => entry,
+            other => {
+                if let Some(Err(e)) = other {
+                    self.notify(format!("Error: {e:#}"));
+                }
+                JournalEntry {
+                    id: 0,
+                    account: self.config.account(),
+                    mailbox,
+                    uid_validity,
+                    uids,
+                    op,
+                }
+            }
+        }
+    }
+
+    /// Marks a journaled operation done.
+    fn complete(&mut self, entry: &JournalEntry) {
+        let Some(journal) = &self.journal else {
+            return;
+        };
+        if let Err(e) = journal.complete(entry.id) {
+            self.notify(format!("Error: {e:#}"));
+        }
+    }
+
+    /// Performs recorded operations on the server, marking each done once
+    /// the server answered, even with an error. After an error, the rest are
+    /// left pending in the journal, offered for replay at the next start.
+    fn send_entries(&mut self, entries: &[JournalEntry]) -> Result<()> {
+        for entry in entries {
+            let result = self.client().and_then(|c| entry.op.apply(c, &entry.uids));
+            self.complete(entry);
+            result?;
+        }
+        Ok(())
+    }
+
+    /// Performs an operation on the server and updates the list to match.
+    fn run_op(&mut self, op: MessageOp, targets: &[usize]) {
+        if targets.is_empty() {
+            return;
+        }
+        let uids = targets
+            .iter()
+            .map(|&idx| self.emails[idx]._uid)
+            .collect::<Vec<_>>();
+        let archive_folders = self.archive_folders(targets);
+        if self.offline.is_some()
+            && !matches!(
+                op,
+                MessageOp::MarkRead | MessageOp::MarkUnread | MessageOp::Flag | MessageOp::Unflag
+            )
+        {
+            self.notify("Offline; only read and flag changes can be made".to_string());
+            return;
+        }
+        let entries = match &op {
+            MessageOp::Archive if !archive_folders.is_empty() => archive_folders
+                .iter()
+                .map(|(folder, uids)| {
+                    let folder = folder.clone();
+                    self.record(uids.clone(), JournalOp::MoveTo { folder })
+                })
+                .collect::<Vec<_>>(),
+            op => vec![self.record(uids, op.journal_op())],
+        };
+        // Offline, the change is queued and only applied locally.
+        if self.offline.is_some() {
+            self.queued.extend(entries);
+        } else if let Err(e) = self.send_entries(&entries) {
+            self.notify(format!("Error: {e:#}"));
+            return;
+        }
+
+        let count = match targets.len() {
+            1 => "1 message".to_string(),
+            n => format!("{} messages", n),
+        };
+        self.notify(match &op {
+            MessageOp::MarkRead => format!("Marked {} read", count),
+            MessageOp::MarkUnread => format!("Marked {} unread", count),
+            MessageOp::Archive => match archive_folders.keys().collect::<Vec<_>>()[..] {
+                [folder] => format!("Archived {} to {}", count, folder),
+                _ => format!("Archived {}", count),
+            },
+            MessageOp::Label(label) => format!("Labeled {} {}", count, label),
+            MessageOp::Unlabel(label) => format!("Removed {} from {}", label, count),
+            MessageOp::Delete => format!("Moved {} to the trash", count),
+            MessageOp::Move(folder) => format!("Moved {} to {}", count, folder),
+            MessageOp::Flag => format!("Flagged {}", count),
+            MessageOp::Unflag => format!("Unflagged {}", count),
+        });
+
+        match op {
+            MessageOp::MarkRead | MessageOp::MarkUnread => {
+                for &idx in targets {
+                    self.emails[idx].is_read = op == MessageOp::MarkRead;
+                }
+                self.rebuild_rows();
+            }
+            MessageOp::Flag | MessageOp::Unflag => {
+                for &idx in targets {
+                    self.emails[idx].is_flagged = op == MessageOp::Flag;
+                }
+                self.rebuild_rows();
+                self.focus_advance();
+            }
+            MessageOp::Archive | MessageOp::Delete | MessageOp::Move(_) => {
+                // Keep the cursor on the same row, now showing the next email.
+                let row = self.list_state.selected().unwrap_or(0);
+                let mut idx = 0;
+                self.emails.retain(|_| {
+                    idx += 1;
+                    !targets.contains(&(idx - 1))
+                });
+                self.list_state.select(None);
+                self.rebuild_rows();
+                if !self.rows.is_empty() {
+                    self.select_row(row.min(self.rows.len() - 1));
+                }
+            }
+            MessageOp::Label(label) => {
+                for &idx in targets {
+                    let labels = &mut self.emails[idx].labels;
+                    if !labels.contains(&label) {
+                        labels.push(label.clone());
+                    }
+                }
+                self.focus_advance();
+            }
+            MessageOp::Unlabel(label) => {
+                for &idx in targets {
+                    self.emails[idx].labels.retain(|l| *l != label);
+                }
+            }
+        }
+    }
+
+    /// Moves cursor to the first email.
+    pub fn goto_first(&mut self) {
+        if self.rows.is_empty() {
+            return;
+        }
+
+        if self.list_state.selected() == Some(0) && self.older_at_top() {
+            self.request_older();
+        }
+        self.list_state.select(Some(0));
+        self.scroll_offset = 0;
+    }
+
+    /// Moves the cursor to a message by its number in the list, counting
+    /// from 1 as the index column does.
+    pub fn goto_number(&mut self, number: usize) {
+        if number == 0 || number > self.rows.len() {
+            self.notify(format!(
+                "No message {}; the list has {}",
+                number,
+                self.rows.len()
+            ));
+            return;
+        }
+        self.select_row(number - 1);
+    }
+
+    /// Moves cursor to the last email.
+    pub fn goto_last(&mut self) {
+        if self.rows.is_empty() {
+            return;
+        }
+
+        let last = self.rows.len() - 1;
+        if self.list_state.selected() == Some(last) && !self.older_at_top() {
+            self.request_older();
+        }
+        self.list_state.select(Some(last));
+        self.scroll_offset = self.rows.len().saturating_sub(self.visible_items);
+    }
+
+    /// Returns whether older messages are listed at the top, so moving past
+    /// the top rather than the end of the list loads more.
+    fn older_at_top(&self) -> bool {
+        self.sort == SortMode::DateAsc
+    }
+
+    /// Asks for the next batch of older messages to be loaded, when the
+    /// folder has more than the list shows.
+    fn request_older(&mut self) {
+        if !matches!(self.mode, ViewMode::List)
+            || self.client.is_none()
+            || self.search.is_some()
+            || self.all_loaded
+        {
+            return;
+        }
+        self.older_requested = true;
+        self.notify("Loading older messages...".to_string());
+    }
+
+    /// Appends the next batch of older messages to the list.
+    pub fn load_older(&mut self) {
+        self.older_requested = false;
+        let Some(oldest) = self
+            .emails
+            .iter()
+            .map(|email| email._uid)
+            .filter(|uid| !self.fetched_parents.contains(uid))
+            .min()
+        else {
+            return;
+        };
+        let limit = self.config.imap.fetch_limit;
+        let (mut older, more) = match self.client().and_then(|c| c.fetch_older(oldest, limit)) {
+            Ok(page) => page,
+            Err(e) => {
+                self.notify(format!("Error: {e:#}"));
+                return;
+            }
+        };
+
+        self.all_loaded = !more;
+        // Parents fetched earlier may be among the page.
+        older.retain(|email| !self.fetched_parents.remove(&email._uid));
+        if older.is_empty() {
+            self.notify("No older messages".to_string());
+            return;
+        }
+        let loaded = older.len();
+        self.fetch_limit += loaded as u32;
+        self.emails.extend(older);
+        // The limit's matches were looked up before these were listed.
+        if let Some(query) = self.limit_query().map(str::to_string)
+            && let Err(e) = self.set_limit(&query)
+        {
+            self.notify(format!("Error: {e:#}"));
+            return;
+        }
+        self.rebuild_rows();
+        self.notify(match (loaded, more) {
+            (1, _) => "Loaded 1 older message".to_string(),
+            (n, true) => format!("Loaded {} older messages", n),
+            (n, false) => format!("Loaded {} older messages; that is all", n),
+        });
+    }
+
+    /// Moves cursor to the top of the visible window.
+    pub fn goto_page_top(&mut self) {
+        if self.rows.is_empty() {
+            return;
+        }
+
+        // Move to the first visible item in the current window.
+        self.list_state.select(Some(self.scroll_offset));
+    }
+
+    /// Moves cursor to the middle of the visible window.
+    pub fn goto_page_middle(&mut self) {
+        if self.rows.is_empty() {
+            return;
+        }
+
+        // Calculate the middle of the visible window
+        let window_end = (self.scroll_offset + self.visible_items).min(self.rows.len());
+        let window_size = window_end - self.scroll_offset;
+        let middle_offset = window_size / 2;
+        let middle_index = self.scroll_offset + middle_offset;
+
+        // Ensure we don't go past the last email
+        let target_index = middle_index.min(self.rows.len() - 1);
+        self.list_state.select(Some(target_index));
+    }
+
+    /// Moves cursor to the bottom of the visible window.
+    pub fn goto_page_bottom(&mut self) {
+        if self.rows.is_empty() {
+            return;
+        }
+
+        // Move to the last visible item in the current window
+        let last_visible = (self.scroll_offset + self.visible_items - 1).min(self.rows.len() - 1);
+        self.list_state.select(Some(last_visible));
+    }
+
+    /// Moves forward one page.
+    pub fn page_forward(&mut self) {
+        if self.rows.is_empty() {
+            return;
+        }
+
+        // Calculate new scroll offset (one page forward)
+        let new_offset = (self.scroll_offset + self.visible_items)
+            .min(self.rows.len().saturating_sub(self.visible_items));
+
+        // If we can scroll forward
+        if new_offset != self.scroll_offset {
+            self.scroll_offset = new_offset;
+            // Move cursor to the top of the new page
+            self.list_state.select(Some(self.scroll_offset));
+        } else {
+            // Already at the bottom, move cursor to last email
+            self.list_state.select(Some(self.rows.len() - 1));
+        }
+    }
+
+    /// Moves backward one page.
+    pub fn page_backward(&mut self) {
+        if self.rows.is_empty() {
+            return;
+        }
+
+        // Calculate new scroll offset (one page backward)
+        let new_offset = self.scroll_offset.saturating_sub(self.visible_items);
+
+        // Update scroll offset and move cursor to top of new page
+        self.scroll_offset = new_offset;
+        self.list_state.select(Some(self.scroll_offset));
+    }
+
+    /// Scrolls the window down by half a page.
+    pub fn half_page_forward(&mut self) {
+        if self.rows.is_empty() {
+            return;
+        }
+
+        let half_page = (self.visible_items / 2).max(1);
+        let current_selected = self.list_state.selected().unwrap_or(0);
+        let current_position_in_window = current_selected.saturating_sub(self.scroll_offset);
+
+        // Scroll the window down by half a page
+        let new_scroll_offset = (self.scroll_offset + half_page)
+            .min(self.rows.len().saturating_sub(self.visible_items));
+
+        // Try to keep cursor at the same relative position in the window
+        let new_selected =
+            (new_scroll_offset + current_position_in_window).min(self.rows.len() - 1);
+
+        self.scroll_offset = new_scroll_offset;
+        self.list_state.select(Some(new_selected));
+    }
+
+    /// Scrolls the window up by half a page.
+    pub fn half_page_backward(&mut self) {
+        if self.rows.is_empty() {
+            return;
+        }
+
+        let half_page = (self.visible_items / 2).max(1);
+        let current_selected = self.list_state.selected().unwrap_or(0);
+        let current_position_in_window = current_selected.saturating_sub(self.scroll_offset);
+
+        // Scroll the window up by half a page
+        let new_scroll_offset = self.scroll_offset.saturating_sub(half_page);
+
+        // Try to keep cursor at the same relative position in the window
+        let new_selected = new_scroll_offset + current_position_in_window;
+
+        self.scroll_offset = new_scroll_offset;
+        self.list_state.select(Some(new_selected));
+    }
+
+    /// Scrolls the window down by one line.
+    pub fn line_forward(&mut self) {
+        if self.rows.is_empty() {
+            return;
+        }
+
+        let current_selected = self.list_state.selected().unwrap_or(0);
+
+        // Check if cursor is at the top of the visible window
+        let cursor_at_top = current_selected == self.scroll_offset;
+
+        // Scroll the window down by one line
+        let new_scroll_offset =
+            (self.scroll_offset + 1).min(self.rows.len().saturating_sub(self.visible_items));
+
+        self.scroll_offset = new_scroll_offset;
+
+        // If cursor was at top and window actually scrolled, move cursor down to stay visible
+        if cursor_at_top
+            && new_scroll_offset > current_selected
+            && current_selected < self.rows.len() - 1
+        {
+            self.list_state.select(Some(current_selected + 1));
+        }
+    }
+
+    /// Scrolls the window up by one line.
+    pub fn line_backward(&mut self) {
+        if self.rows.is_empty() {
+            return;
+        }
+
+        let current_selected = self.list_state.selected().unwrap_or(0);
+
+        // Check if cursor is at the bottom of the visible window
+        let cursor_at_bottom = current_selected
+            == (self.scroll_offset + self.visible_items - 1).min(self.rows.len() - 1);
+
+        // Scroll the window up by one line
+        let new_scroll_offset = self.scroll_offset.saturating_sub(1);
+
+        self.scroll_offset = new_scroll_offset;
+
+        // If cursor was at bottom and window actually scrolled, move cursor up to stay visible
+        if cursor_at_bottom && new_scroll_offset < current_selected && current_selected > 0 {
+            self.list_state.select(Some(current_selected - 1));
+        }
+    }
+
+    /// Starts saving the attachments of the email shown in detail view.
+    ///
+    /// Each attachment is offered in turn through a prompt pre-filled with the
+    /// path chosen by the attachment rules, which the user can edit.
+    pub fn save_attachments(&mut self) {
+        let ViewMode::Detail(idx) = self.mode else {
+            return;
+        };
+        if self.confirm_over_budget(Download::Attachments(idx)) {
+            return;
+        }
+        self.download_attachments(idx, false);
+    }
+
+    /// Offers the attachments of the email shown in detail view for opening,
+    /// one at a time, once the scan command passed them.
+    pub fn open_attachments(&mut self) {
+        let ViewMode::Detail(idx) = self.mode else {
+            return;
+        };
+        if self.confirm_over_budget(Download::OpenAttachments(idx)) {
+            return;
+        }
+        self.download_attachments(idx, true);
+    }
+
+    /// Switches the detail view between the message and its raw source, with
+    /// every header and MIME part as received.
+    pub fn toggle_source(&mut self) {
+        let ViewMode::Detail(idx) = self.mode else {
+            return;
+        };
+        self.detail_scroll_offset = 0;
+        if self.shown_source().is_some() {
+            self.source = None;
+            return;
+        }
+        if self.confirm_over_budget(Download::Source(idx)) {
+            return;
+        }
+        self.show_source(idx);
+    }
+
+    /// Fetches the raw source of the email at index and shows it.
+    fn show_source(&mut self, idx: usize) {
+        let Some(uid) = self.emails.get(idx).map(|email| email._uid) else {
+            return;
+        };
+        match self.client().and_then(|c| c.fetch_raw_message(uid)) {
+            Ok(raw) => {
+                let source = String::from_utf8_lossy(&raw).replace("\r\n", "\n");
+                self.source = Some((uid, source));
+            }
+            Err(e) => self.notify(format!("Error: {e:#}")),
+        }
+    }
+
+    /// Returns the raw source of the email in detail view, when it is shown
+    /// instead of the message.
+    pub(crate) fn shown_source(&self) -> Option<&str> {
+        let (ViewMode::Detail(idx) | ViewMode::Participants(idx) | ViewMode::Urls(idx)) = self.mode
+        else {
+            return None;
+        };
+        let uid = self.emails.get(idx)?._uid;
+        match &self.source {
+            Some((shown, source)) if *shown == uid => Some(source),
+            _ => None,
+        }
+    }
+
+    /// Fetches the email at index and prompts for each of its attachments.
+    fn download_attachments(&mut self, idx: usize, open: bool) {
+        let Some(uid) = self.emails.get(idx).map(|email| email._uid) else {
+            return;
+        };
+
+        let attachments = self
+            .client()
+            .and_then(|c| c.fetch_raw_message(uid))
+            .and_then(|raw| extract_attachments(&raw));
+
+        match attachments {
+            Ok(attachments) if attachments.is_empty() => {
+                self.notify("No attachments".to_string());
+            }
+            Ok(attachments) => {
+                self.pending_attachments = attachments.into();
+                self.opening_attachments = open;
+                self.prompt_next_attachment();
+            }
+            Err(e) => self.notify(format!("Error: {e}")),
+        }
+    }
+
+    /// Opens the save or open prompt for the next pending attachment, if any.
+    ///
+    /// With a scan command configured, the attachment is scanned first and a
+    /// rejected one is reported instead of offered.
+    fn prompt_next_attachment(&mut self) {
+        let Some(attachment) = self.pending_attachments.pop_front() else {
+            return;
+        };
+        if let Some(command) = &self.config.attachments.scan_command
+            && let Err(e) = scan_attachment(command, &attachment)
+        {
+            self.prompt = Some(Prompt {
+                label: format!("Blocked {}: {} (Enter to continue)", attachment.filename, e),
+                input: String::new(),
+                kind: PromptKind::ScanBlocked,
+            });
+            return;
+        }
+        if self.opening_attachments {
+            self.prompt = Some(Prompt {
+                label: format!(
+                    "Open {} ({})? (y/N): ",
+                    attachment.filename, attachment.mime_type
+                ),
+                input: String::new(),
+                kind: PromptKind::OpenAttachment(attachment),
+            });
+            return;
+        }
+
+        let sender = match self.mode {
+            ViewMode::Detail(idx) => self
+                .emails
+                .get(idx)
+                .and_then(|email| email.from.email.as_deref()),
+            _ => None,
+        };
+        let dir = resolve_save_dir(&self.config.attachments, sender, &attachment.mime_type);
+
+        self.prompt = Some(Prompt {
+            label: format!("Save {} to: ", attachment.filename),
+            input: dir
+                .join(safe_filename(&attachment.filename))
+                .display()
+                .to_string(),
+            kind: PromptKind::SaveAttachment(attachment),
+        });
+    }
+
+    /// Appends a character to the prompt input.
+    pub fn prompt_input(&mut self, c: char) {
+        if let Some(prompt) = self.prompt.as_mut() {
+            prompt.input.push(c);
+        }
+    }
+
+    /// Deletes the last character of the prompt input.
+    pub fn prompt_backspace(&mut self) {
+        if let Some(prompt) = self.prompt.as_mut() {
+            prompt.input.pop();
+        }
+    }
+
+    /// Closes the prompt without acting on it.
+    pub fn prompt_cancel(&mut self) {
+        match self.prompt.take().map(|prompt| prompt.kind) {
+            Some(
+                PromptKind::SaveAttachment(attachment) | PromptKind::OpenAttachment(attachment),
+            ) => {
+                self.notify(format!("Skipped {}", attachment.filename));
+                self.prompt_next_attachment();
+            }
+            Some(PromptKind::ScanBlocked) => self.prompt_next_attachment(),
+            Some(PromptKind::ConfirmReplay(_)) => self.discard_replay(),
+            Some(PromptKind::ConfirmCertificate(_)) => self.distrust_certificate(),
+            Some(
+                PromptKind::ConfirmDownload(_)
+                | PromptKind::ConfirmSaveAttachments(_)
+                | PromptKind::ConfirmSaveMaildir(_),
+            ) => {
+                self.notify("Download cancelled".to_string());
+            }
+            Some(
+                PromptKind::SaveMessage(_)
+                | PromptKind::Label
+                | PromptKind::Unlabel
+                | PromptKind::Move
+                | PromptKind::TagPattern
+                | PromptKind::Search
+                | PromptKind::Command
+                | PromptKind::Pattern
+                | PromptKind::ChangeFolder
+                | PromptKind::ConfirmThread(_)
+                | PromptKind::ConfirmDeleteSender(_)
+                | PromptKind::ConfirmDeleteTagged,
+            ) => {
+                self.notify("Cancelled".to_string());
+            }
+            None => {}
+        }
+    }
+
+    /// Submits the prompt input and performs the prompt's action.
+    pub fn prompt_submit(&mut self) {
+        let Some(prompt) = self.prompt.take() else {
+            return;
+        };
+
+        match prompt.kind {
+            PromptKind::SaveAttachment(attachment) => {
+                // A name already taken is numbered, as when saving many.
+                let path = expand_tilde(prompt.input.trim());
+                let path = match path.file_name() {
+                    Some(name) => unique_path(
+                        path.parent().unwrap_or(Path::new("")),
+                        &name.to_string_lossy(),
+                    ),
+                    None => path,
+                };
+                self.notify(match save_attachment(&attachment, &path) {
+                    Ok(()) => format!("Saved {}", path.display()),
+                    Err(e) => format!("Error: {e}"),
+                });
+                self.prompt_next_attachment();
+            }
+            PromptKind::OpenAttachment(attachment) => {
+                if prompt.input.trim().eq_ignore_ascii_case("y") {
+                    self.notify(match open_attachment(&attachment) {
+                        Ok(path) => format!("Opened {}", path.display()),
+                        Err(e) => format!("Error: {e:#}"),
+                    });
+                } else {
+                    self.notify(format!("Skipped {}", attachment.filename));
+                }
+                self.prompt_next_attachment();
+            }
+            PromptKind::ScanBlocked => self.prompt_next_attachment(),
+            PromptKind::ConfirmReplay(entries) => {
+                if prompt.input.trim().eq_ignore_ascii_case("y") {
+                    self.replay(entries);
+                } else {
+                    self.discard_replay();
+                }
+            }
+            PromptKind::ConfirmCertificate(changed) => {
+                if prompt.input.trim().eq_ignore_ascii_case("y") {
+                    let trusted = KnownHosts::open_default()
+                        .and_then(|mut hosts| hosts.trust(&changed.host, changed.presented));
+                    self.notify(match trusted {
+                        Ok(()) => "Trusted the new certificate; reconnecting".to_string(),
+                        Err(e) => format!("Error: {e:#}"),
+                    });
+                } else {
+                    self.distrust_certificate();
+                }
+            }
+            PromptKind::ConfirmDownload(download) => {
+                if prompt.input.trim().eq_ignore_ascii_case("y") {
+                    self.run_download(download);
+                } else {
+                    self.notify("Download cancelled".to_string());
+                }
+            }
+            PromptKind::ConfirmSaveAttachments(dir) => {
+                if prompt.input.trim().eq_ignore_ascii_case("y") {
+                    self.run_save_attachments(&dir);
+                } else {
+                    self.notify("Download cancelled".to_string());
+                }
+            }
+            PromptKind::SaveMessage(idx) => {
+                let path = expand_tilde(prompt.input.trim());
+                let saved = self.write_message(idx, &path);
+                self.notify(match saved {
+                    Ok(()) => format!("Saved {}", path.display()),
+                    Err(e) => format!("Error: {e:#}"),
+                });
+            }
+            PromptKind::ConfirmSaveMaildir(dir) => {
+                if prompt.input.trim().eq_ignore_ascii_case("y") {
+                    self.run_save_maildir(&dir);
+                } else {
+                    self.notify("Download cancelled".to_string());
+                }
+            }
+            PromptKind::Label | PromptKind::Unlabel => {
+                let choices = std::mem::take(&mut self.folder_choices);
+                if let Some(label) = picked_label(&choices, &prompt.input) {
+                    self.request_op(match prompt.kind {
+                        PromptKind::Label => MessageOp::Label(label),
+                        _ => MessageOp::Unlabel(label),
+                    });
+                }
+            }
+            PromptKind::Move => {
+                let choices = std::mem::take(&mut self.folder_choices);
+                if let Some(folder) = picked_label(&choices, &prompt.input) {
+                    self.request_op(MessageOp::Move(folder));
+                }
+            }
+            PromptKind::TagPattern => {
+                if let Err(e) = self.tag_pattern(&prompt.input) {
+                    self.notify(format!("Error: {e:#}"));
+                }
+            }
+            PromptKind::Search => {
+                if let Err(e) = self.search(prompt.input.trim()) {
+                    self.notify(format!("Error: {e:#}"));
+                }
+            }
+            PromptKind::Command => self.submit_command(&prompt.input),
+            PromptKind::Pattern => {
+                if let Err(e) = self.set_pattern(&prompt.input) {
+                    self.notify(format!("Error: {e:#}"));
+                }
+            }
+            PromptKind::ChangeFolder => {
+                let input = prompt.input.trim();
+                let choices = std::mem::take(&mut self.folder_choices);
+                if input.is_empty() {
+                    self.browse_folders();
+                } else if let Some(choice) = matching_folders(&choices, input).first() {
+                    if let Err(e) = self.open_folder(&choice.path) {
+                        self.notify(format!("Error: {e:#}"));
+                    }
+                } else {
+                    self.notify(format!("No folder matches {}", input));
+                }
+            }
+            PromptKind::ConfirmDeleteSender(address) => {
+                if prompt.input.trim().eq_ignore_ascii_case("y") {
+                    let targets = self.sender_emails(&address);
+                    self.run_op(MessageOp::Delete, &targets);
+                    self.refresh_senders();
+                } else {
+                    self.notify("Cancelled".to_string());
+                }
+            }
+            PromptKind::ConfirmDeleteTagged => {
+                if prompt.input.trim().eq_ignore_ascii_case("y") {
+                    let targets = self.tagged_emails();
+                    self.run_op(MessageOp::Delete, &targets);
+                } else {
+                    self.notify("Cancelled".to_string());
+                }
+            }
+            PromptKind::ConfirmThread(op) => {
+                let targets = if prompt.input.trim().eq_ignore_ascii_case("y") {
+                    self.selected_thread()
+                } else {
+                    self.selected_email().into_iter().collect()
+                };
+                self.run_op(op, &targets);
+            }
+        }
+    }
+
+    /// Starts a reply to the email shown in detail view, quoting its body.
+    ///
+    /// With `all`, the other recipients of the email are copied too.
+    pub fn reply(&mut self, all: bool) {
+        let ViewMode::Detail(idx) = self.mode else {
+            return;
+        };
+        let Some(email) = self.emails.get(idx) else {
+            return;
+        };
+        if self.loading.contains(&email._uid) {
+            self.notify("The message is still loading".to_string());
+            return;
+        }
+        self.draft = Some(Draft::reply(
+            email,
+            &self.config.gmail.username,
+            all,
+            &self.config.compose.reply_templates,
+        ));
+        self.mode = ViewMode::Compose;
+        self.editor_requested = true;
+    }
+
+    /// Opens the message returned in the bounce shown in detail view as a new
+    /// draft, to be edited and sent again.
+    pub fn resend_bounced(&mut self) {
+        let ViewMode::Detail(idx) = self.mode else {
+            return;
+        };
+        let original = self
+            .emails
+            .get(idx)
+            .and_then(|email| self.reports.get(&email._uid))
+            .and_then(|report| report.original.as_deref());
+        let Some(original) = original else {
+            self.notify("No returned message to resend".to_string());
+            return;
+        };
+
+        match Draft::from_message(original) {
+            Ok(draft) => {
+                self.draft = Some(draft);
+                self.mode = ViewMode::Compose;
+                self.editor_requested = true;
+            }
+            Err(e) => self.notify(format!("Error: {e:#}")),
+        }
+    }
+
+    /// Toggles requesting delivery status notifications for the draft.
+    pub fn toggle_dsn(&mut self) {
+        if let Some(draft) = self.draft.as_mut() {
+            draft.request_dsn = !draft.request_dsn;
+        }
+    }
+
+    /// Toggles requesting a read receipt for the draft.
+    pub fn toggle_read_receipt(&mut self) {
+        if let Some(draft) = self.draft.as_mut() {
+            draft.request_read_receipt = !draft.request_read_receipt;
+        }
+    }
+
+    /// Requests that the draft be opened in the external editor.
+    pub fn edit_draft(&mut self) {
+        if self.draft.is_some() {
+            self.editor_requested = true;
+        }
+    }
+
+    /// Sends the current draft, queueing it in the outbox when offline.
+    pub fn send_draft(&mut self) {
+        let Some(draft) = self.draft.as_ref() else {
+            return;
+        };
+
+        match send_or_queue(
+            &self.config,
+            &Outbox::open_default(&self.config.account()),
+            draft,
+        ) {
+            Ok(SendOutcome::Sent(_)) => {
+                self.notify("Message sent".to_string());
+                self.harvest_contacts();
+                self.close_compose();
+            }
+            Ok(SendOutcome::Queued) => {
+                self.notify("Offline: message queued in outbox".to_string());
+                self.harvest_contacts();
+                self.close_compose();
+            }
+            Err(e) => self.notify(format!("Error: {e:#}")),
+        }
+    }
+
+    /// Adds the recipients of the draft being sent to the address book.
+    fn harvest_contacts(&mut self) {
+        let Some(draft) = &self.draft else {
+            return;
+        };
+        if let Err(e) = AddressBook::open_default().and_then(|mut book| book.harvest(draft)) {
+            self.notify(format!("Recipients not added to contacts: {e:#}"));
+        }
+    }
+
+    /// Discards the current draft.
+    pub fn abort_draft(&mut self) {
+        self.notify("Message aborted".to_string());
+        self.close_compose();
+    }
+
+    /// Leaves compose mode, exiting if the app was launched just to compose.
+    fn close_compose(&mut self) {
+        self.draft = None;
+        self.mode = ViewMode::List;
+        if self.quit_after_compose {
+            self.should_quit = true;
+        }
+    }
+
+    /// Records how many lines the detail view's text takes once wrapped and
+    /// how many fit, keeping the scroll offset within the text.
+    ///
+    /// This is called on every draw, so the bound follows resizes and a body
+    /// arriving after the view opened.
+    pub fn set_detail_area(&mut self, lines: usize, height: u16) {
+        let max_scroll = lines.saturating_sub(height as usize);
+        self.detail_max_scroll = u16::try_from(max_scroll).unwrap_or(u16::MAX);
+        self.detail_page = height;
+        self.detail_scroll_offset = self.detail_scroll_offset.min(self.detail_max_scroll);
+    }
+
+    /// Scrolls the detail view by lines, down when positive, stopping at the
+    /// top and at the last line of the text.
+    fn scroll_detail(&mut self, lines: i32) {
+        let offset = (i32::from(self.detail_scroll_offset) + lines)
+            .clamp(0, i32::from(self.detail_max_scroll));
+        self.detail_scroll_offset = offset as u16;
+    }
+
+    /// Scrolls detail view down by one line (j key).
+    pub fn detail_scroll_down(&mut self) {
+        self.scroll_detail(1);
+    }
+
+    /// Scrolls detail view up by one line (k key).
+    pub fn detail_scroll_up(&mut self) {
+        self.scroll_detail(-1);
+    }
+
+    /// Scrolls detail view down by one line (ctrl-e).
+    pub fn detail_line_forward(&mut self) {
+        self.scroll_detail(1);
+    }
+
+    /// Scrolls detail view up by one line (ctrl-y).
+    pub fn detail_line_backward(&mut self) {
+        self.scroll_detail(-1);
+    }
+
+    /// Scrolls detail view down by half a page (ctrl-d).
+    pub fn detail_half_page_down(&mut self) {
+        self.scroll_detail(i32::from(self.detail_page / 2).max(1));
+    }
+
+    /// Scrolls detail view up by half a page (ctrl-u).
+    pub fn detail_half_page_up(&mut self) {
+        self.scroll_detail(-i32::from(self.detail_page / 2).max(1));
+    }
+
+    /// Scrolls detail view to the top of the message (gg or ^).
+    pub fn detail_top(&mut self) {
+        self.detail_scroll_offset = 0;
+    }
+
+    /// Scrolls detail view to the end of the message (G).
+    pub fn detail_bottom(&mut self) {
+        self.detail_scroll_offset = self.detail_max_scroll;
+    }
+
+    /// Returns how far down the detail view is scrolled, in percent, or
+    /// nothing when the whole text fits.
+    pub(crate) fn detail_scroll_percent(&self) -> Option<u16> {
+        (self.detail_max_scroll > 0).then(|| {
+            (u32::from(self.detail_scroll_offset) * 100 / u32::from(self.detail_max_scroll)) as u16
+        })
+    }
+
+    /// Opens the help overlay listing every keybinding.
+    pub fn show_help(&mut self) {
+        self.help_scroll = Some(0);
+    }
+
+    /// Closes the help overlay.
+    pub fn close_help(&mut self) {
+        self.help_scroll = None;
+    }
+
+    /// Returns the scroll offset of the help overlay, if it is open.
+    pub(crate) fn help_scroll(&self) -> Option<usize> {
+        self.help_scroll
+    }
+
+    /// Scrolls the help overlay by lines, or by pages of the list's height,
+    /// keeping its last line on screen.
+    pub fn scroll_help(&mut self, lines: isize, pages: isize) {
+        let Some(offset) = self.help_scroll else {
+            return;
+        };
+        let delta = lines + pages * self.visible_items.max(1) as isize;
+        let last = help_lines().len().saturating_sub(1);
+        self.help_scroll = Some(offset.saturating_add_signed(delta).min(last));
+    }
+
+    /// Returns the bindings in effect: the help overlay's while it is open,
+    /// else the current view's.
+    pub(crate) fn bindings(&self) -> &'static [Binding] {
+        if self.help_scroll.is_some() {
+            HELP
+        } else {
+            bindings(&self.mode)
+        }
+    }
+}
+
+/// Returns the label or folder picked in the label or move prompt: the
+/// folder named exactly as the input, else the first folder matching it,
+/// else the input itself as a new one.
+fn picked_label(choices: &[FolderChoice], input: &str) -> Option<String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return None;
+    }
+    let candidates = matching_folders(choices, input);
+    let picked = candidates
+        .iter()
+        .find(|choice| choice.path.eq_ignore_ascii_case(input))
+        .or(candidates.first())
+        .map_or(input, |choice| &choice.path);
+    Some(picked.to_string())
+}
+
+/// Formats a message as plain text: its main headers, a blank line, and
+/// its decoded text.
+fn message_as_text(email: &Email, raw: &[u8]) -> Result<String> {
+    let addresses = |addrs: &[NameAddr]| {
+        addrs
+            .iter()
+            .map(|addr| addr.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut text = format!(
+        "Date: {}\nFrom: {}\n",
+        email.date.format("%a, %d %b %Y %H:%M:%S %z"),
+        email.from
+    );
+    if !email.to.is_empty() {
+        text.push_str(&format!("To: {}\n", addresses(&email.to)));
+    }
+    if !email.cc.is_empty() {
+        text.push_str(&format!("Cc: {}\n", addresses(&email.cc)));
+    }
+    text.push_str(&format!("Subject: {}\n\n", email.subject));
+    for line in message_text(raw)?.text.lines() {
+        text.push_str(line);
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+/// Returns whether an address is among the muted senders, ignoring case.
+fn is_muted_address(muted: &[String], address: &str) -> bool {
+    muted
+        .iter()
+        .any(|muted| muted.eq_ignore_ascii_case(address))
+}
+
+/// Returns whether the sender of an email is muted.
+fn is_muted(muted: &[String], email: &Email) -> bool {
+    sender_address(email).is_some_and(|address| is_muted_address(muted, &address))
+}
+
+/// Orders two emails for the list. Ties within a sender, subject, or read
+/// status go most recent first.
+fn compare_emails(sort: SortMode, a: &Email, b: &Email) -> Ordering {
+    let newest_first = b.date.cmp(&a.date);
+    match sort {
+        SortMode::DateDesc => newest_first,
+        SortMode::DateAsc => a.date.cmp(&b.date),
+        SortMode::Sender => sender_key(a).cmp(&sender_key(b)).then(newest_first),
+        SortMode::Subject => subject_key(a).cmp(&subject_key(b)).then(newest_first),
+        SortMode::UnreadFirst => a.is_read.cmp(&b.is_read).then(newest_first),
+        SortMode::FlaggedFirst => b.is_flagged.cmp(&a.is_flagged).then(newest_first),
+    }
+}
+
+/// Orders two threads for the list by their most recent message, or by
+/// whether they have unread mail.
+fn compare_threads(sort: SortMode, emails: &[Email], a: &Thread, b: &Thread) -> Ordering {
+    let newest = |thread: &Thread| {
+        thread
+            .messages
+            .iter()
+            .map(|&(idx, _)| &emails[idx])
+            .max_by_key(|email| email.date)
+            .expect("threads are not empty")
+    };
+    match sort {
+        SortMode::UnreadFirst => a
+            .all_read(emails)
+            .cmp(&b.all_read(emails))
+            .then(newest(b).date.cmp(&newest(a).date)),
+        SortMode::FlaggedFirst => {
+            let flagged = |thread: &Thread| {
+                thread
+                    .messages
+                    .iter()
+                    .any(|&(idx, _)| emails[idx].is_flagged)
+            };
+            flagged(b)
+                .cmp(&flagged(a))
+                .then(newest(b).date.cmp(&newest(a).date))
+        }
+        _ => compare_emails(sort, newest(a), newest(b)),
+    }
+}
+
+/// Returns the sender an email is sorted by, ignoring case.
+fn sender_key(email: &Email) -> String {
+    email.from.name_or_addr().unwrap_or_default().to_lowercase()
+}
+
+/// Returns the subject an email is sorted by, ignoring case and reply or
+/// forward prefixes.
+fn subject_key(email: &Email) -> String {
+    let mut subject = email.subject.trim();
+    while let Some((prefix, rest)) = subject.split_once(':') {
+        if !["re", "fw", "fwd"].contains(&prefix.trim().to_lowercase().as_str()) {
+            break;
+        }
+        subject = rest.trim_start();
+    }
+    subject.to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imap_client::{NameAddr, test_email};
+    use chrono::Local;
+
+    #[test]
+    fn test_app_navigation() {
+        let emails = vec![
+            Email {
+                _uid: 1,
+                subject: "Email 1".to_string(),
+                from: NameAddr {
+                    name: None,
+                    email: Some("test1@test.com".to_string()),
+                },
+                date: Local::now(),
+                ..Default::default()
+            },
+            Email {
+                _uid: 2,
+                subject: "Email 2".to_string(),
+                from: NameAddr {
+                    name: None,
+                    email: Some("test2@test.com".to_string()),
+                },
+                date: Local::now(),
+                is_read: true,
+                ..Default::default()
+            },
+        ];
+
+        let client = ImapClient::connect("dummy", "dummy");
+        if client.is_err() {
+            return;
+        }
+
+        let mut app = App::new(client.unwrap(), emails);
+
+        assert_eq!(app.list_state.selected(), Some(0));
+        app.next();
+        assert_eq!(app.list_state.selected(), Some(1));
+        app.next();
+        assert_eq!(app.list_state.selected(), Some(1)); // Stays at bottom
+        app.previous();
+        assert_eq!(app.list_state.selected(), Some(0));
+        app.previous();
+        assert_eq!(app.list_state.selected(), Some(0)); // Stays at top
+    }
+
+    #[test]
+    fn test_app_initialization() {
+        let emails = vec![];
+        let client = ImapClient::connect("dummy", "dummy");
+        if client.is_err() {
+            return;
+        }
+
+        let app = App::new(client.unwrap(), emails.clone());
+        assert_eq!(app.emails.len(), 0);
+        assert_eq!(app.list_state.selected(), None);
+    }
+
+    #[test]
+    fn test_vim_like_scrolling() {
+        let emails: Vec<Email> = (0..20)
+            .map(|i| Email {
+                _uid: i + 1,
+                subject: format!("Email {}", i + 1),
+                from: NameAddr {
+                    name: None,
+                    email: Some(format!("test{}@test.com", i + 1)),
+                },
+                date: Local::now(),
+                ..Default::default()
+            })
+            .collect();
+
+        let client = ImapClient::connect("dummy", "dummy");
+        if client.is_err() {
+            return;
+        }
+
+        let mut app = App::new(client.unwrap(), emails);
+        app.set_visible_items(5); // Simulate a small window with 5 visible items
+
+        // Test moving down: cursor should move without scrolling initially
+        assert_eq!(app.list_state.selected(), Some(0));
+        assert_eq!(app.scroll_offset, 0);
+
+        // Move down to position 4 (last visible item in window)
+        for _ in 0..4 {
+            app.next();
+        }
+        assert_eq!(app.list_state.selected(), Some(4));
+        assert_eq!(app.scroll_offset, 0); // Should not scroll yet
+
+        // Move down one more - this should trigger scrolling
+        app.next();
+        assert_eq!(app.list_state.selected(), Some(5));
+        assert_eq!(app.scroll_offset, 1); // Window should scroll down by 1
+
+        // Test moving up: scroll should happen when cursor reaches top
+        app.previous();
+        assert_eq!(app.list_state.selected(), Some(4));
+        assert_eq!(app.scroll_offset, 0); // Should scroll back up
+    }
+
+    #[test]
+    fn test_view_mode_transitions() {
+        let emails = vec![Email {
+            _uid: 1,
+            subject: "Test".to_string(),
+            from: NameAddr {
+                name: None,
+                email: Some("test@test.com".to_string()),
+            },
+            date: Local::now(),
+            ..Default::default()
+        }];
+
+        let client = ImapClient::connect("dummy", "dummy");
+        if client.is_err() {
+            return;
+        }
+
+        let mut app = App::new(client.unwrap(), emails);
+
+        // Start in list mode
+        assert!(matches!(app.mode, ViewMode::List));
+
+        // View email
+        app.view_email();
+        assert!(matches!(app.mode, ViewMode::Detail(0)));
+
+        // Go back to list
+        app.back_to_list();
+        assert!(matches!(app.mode, ViewMode::List));
+    }
+
+    #[test]
+    fn test_vim_navigation() {
+        let emails: Vec<Email> = (0..20)
+            .map(|i| Email {
+                _uid: i + 1,
+                subject: format!("Email {}", i + 1),
+                from: NameAddr {
+                    name: None,
+                    email: Some(format!("test{}@test.com", i + 1)),
+                },
+                date: Local::now(),
+                ..Default::default()
+            })
+            .collect();
+
+        let client = ImapClient::connect("dummy", "dummy");
+        if client.is_err() {
+            return;
+        }
+
+        let mut app = App::new(client.unwrap(), emails);
+        app.set_visible_items(5); // Window shows 5 items
+
+        // Start at position 0 with scroll_offset 0
+        assert_eq!(app.list_state.selected(), Some(0));
+        assert_eq!(app.scroll_offset, 0);
+
+        // Move down to trigger scrolling
+        for _ in 0..7 {
+            app.next();
+        }
+        assert_eq!(app.list_state.selected(), Some(7));
+        assert_eq!(app.scroll_offset, 3); // Window scrolled to show items 3-7
+
+        // Test goto_page_top (H key) - should go to top of visible window (item 3)
+        app.goto_page_top();
+        assert_eq!(app.list_state.selected(), Some(3)); // First visible item
+        assert_eq!(app.scroll_offset, 3); // Scroll doesn't change
+
+        // Test goto_page_bottom (L key) - should go to bottom of visible window (item 7)
+        app.goto_page_bottom();
+        assert_eq!(app.list_state.selected(), Some(7)); // Last visible item (3+5-1)
+        assert_eq!(app.scroll_offset, 3); // Scroll doesn't change
+
+        // Test goto_page_middle (M key) - should go to middle of visible window
+        app.goto_page_middle();
+        assert_eq!(app.list_state.selected(), Some(5)); // Middle of window (3 + 2)
+        assert_eq!(app.scroll_offset, 3); // Scroll doesn't change
+
+        // Test at end of list where window is partial
+        for _ in 0..14 {
+            app.next();
+        }
+        assert_eq!(app.list_state.selected(), Some(19)); // Last email
+        assert_eq!(app.scroll_offset, 15); // Window shows items 15-19
+
+        // Window shows items 15-19 (only 5 items visible)
+        app.goto_page_top();
+        assert_eq!(app.list_state.selected(), Some(15)); // Top of window
+
+        app.goto_page_bottom();
+        assert_eq!(app.list_state.selected(), Some(19)); // Bottom of window
+
+        app.goto_page_middle();
+        assert_eq!(app.list_state.selected(), Some(17)); // Middle of window (15 + 2)
+    }
+
+    #[test]
+    fn test_vim_navigation_empty_list() {
+        let emails = vec![];
+        let client = ImapClient::connect("dummy", "dummy");
+        if client.is_err() {
+            return;
+        }
+
+        let mut app = App::new(client.unwrap(), emails);
+
+        // These should not panic on empty list
+        app.goto_page_top();
+        app.goto_page_middle();
+        app.goto_page_bottom();
+        assert_eq!(app.list_state.selected(), None);
+    }
+
+    #[test]
+    fn test_page_navigation() {
+        let emails: Vec<Email> = (0..30)
+            .map(|i| Email {
+                _uid: i + 1,
+                subject: format!("Email {}", i + 1),
+                from: NameAddr {
+                    name: None,
+                    email: Some(format!("test{}@test.com", i + 1)),
+                },
+                date: Local::now(),
+                ..Default::default()
+            })
+            .collect();
+
+        let client = ImapClient::connect("dummy", "dummy");
+        if client.is_err() {
+            return;
+        }
+
+        let mut app = App::new(client.unwrap(), emails);
+        app.set_visible_items(10); // Window shows 10 items
+
+        // Start at position 0 with scroll_offset 0
+        assert_eq!(app.list_state.selected(), Some(0));
+        assert_eq!(app.scroll_offset, 0);
+
+        // Test page forward (Ctrl+F) - should move forward one page
+        app.page_forward();
+        assert_eq!(app.list_state.selected(), Some(10)); // Cursor at top of new page
+        assert_eq!(app.scroll_offset, 10); // Window shows items 10-19
+
+        // Another page forward
+        app.page_forward();
+        assert_eq!(app.list_state.selected(), Some(20)); // Cursor at top of new page
+        assert_eq!(app.scroll_offset, 20); // Window shows items 20-29
+
+        // Try to page forward at the end - should just move cursor to last item
+        app.page_forward();
+        assert_eq!(app.list_state.selected(), Some(29)); // Last email
+        assert_eq!(app.scroll_offset, 20); // Window doesn't change
+
+        // Test page backward (Ctrl+B) - should move backward one page
+        app.page_backward();
+        assert_eq!(app.list_state.selected(), Some(10)); // Cursor at top of new page
+        assert_eq!(app.scroll_offset, 10); // Window shows items 10-19
+
+        // Another page backward
+        app.page_backward();
+        assert_eq!(app.list_state.selected(), Some(0)); // Back to start
+        assert_eq!(app.scroll_offset, 0); // Window shows items 0-9
+
+        // Try to page backward at the beginning - should stay at start
+        app.page_backward();
+        assert_eq!(app.list_state.selected(), Some(0)); // Stay at start
+        assert_eq!(app.scroll_offset, 0); // Window doesn't change
+    }
+
+    #[test]
+    fn test_page_navigation_small_list() {
+        // Test with a list smaller than one page
+        let emails: Vec<Email> = (0..5)
+            .map(|i| Email {
+                _uid: i + 1,
+                subject: format!("Email {}", i + 1),
+                from: NameAddr {
+                    name: None,
+                    email: Some(format!("test{}@test.com", i + 1)),
+                },
+                date: Local::now(),
+                ..Default::default()
+            })
+            .collect();
+
+        let client = ImapClient::connect("dummy", "dummy");
+        if client.is_err() {
+            return;
+        }
+
+        let mut app = App::new(client.unwrap(), emails);
+        app.set_visible_items(10); // Window can show 10 items but we only have 5
+
+        // Page forward should move to last item since list is smaller than page
+        app.page_forward();
+        assert_eq!(app.list_state.selected(), Some(4)); // Last email
+        assert_eq!(app.scroll_offset, 0); // No scrolling needed
+
+        // Page backward should go to first item
+        app.page_backward();
+        assert_eq!(app.list_state.selected(), Some(0)); // First email
+        assert_eq!(app.scroll_offset, 0); // No scrolling needed
+    }
+
+    #[test]
+    fn test_half_page_navigation() {
+        let emails: Vec<Email> = (0..30)
+            .map(|i| Email {
+                _uid: i + 1,
+                subject: format!("Email {}", i + 1),
+                from: NameAddr {
+                    name: None,
+                    email: Some(format!("test{}@test.com", i + 1)),
+                },
+                date: Local::now(),
+                ..Default::default()
+            })
+            .collect();
+
+        let client = ImapClient::connect("dummy", "dummy");
+        if client.is_err() {
+            return;
+        }
+
+        let mut app = App::new(client.unwrap(), emails);
+        app.set_visible_items(10); // Window shows 10 items, so half-page is 5
+
+        // Start at position 0 (cursor at top of window)
+        assert_eq!(app.list_state.selected(), Some(0));
+        assert_eq!(app.scroll_offset, 0);
+
+        // Move cursor to middle of window first
+        app.next();
+        app.next();
+        app.next(); // Now at position 3
+        assert_eq!(app.list_state.selected(), Some(3));
+        assert_eq!(app.scroll_offset, 0);
+
+        // Test half page forward (Ctrl-D) - should scroll window down by 5
+        app.half_page_forward();
+        assert_eq!(app.scroll_offset, 5); // Window scrolled down by 5
+        assert_eq!(app.list_state.selected(), Some(8)); // Cursor maintains relative position (3rd item in window)
+
+        // Test half page backward (Ctrl-U) - should scroll window up by 5
+        app.half_page_backward();
+        assert_eq!(app.scroll_offset, 0); // Window scrolled back up
+        assert_eq!(app.list_state.selected(), Some(3)); // Cursor back to original position
+
+        // Test at end of list - scroll near the end first
+        app.scroll_offset = 15; // Window shows items 15-24
+        app.list_state.select(Some(18)); // Cursor at 3rd position in window
+
+        // Half page forward at end - should scroll as much as possible
+        app.half_page_forward();
+        assert_eq!(app.scroll_offset, 20); // Max scroll for 30 items with 10 visible (30-10=20)
+        assert_eq!(app.list_state.selected(), Some(23)); // Cursor maintains relative position
+    }
+
+    #[test]
+    fn test_half_page_navigation_small_window() {
+        let emails: Vec<Email> = (0..10)
+            .map(|i| Email {
+                _uid: i + 1,
+                subject: format!("Email {}", i + 1),
+                from: NameAddr {
+                    name: None,
+                    email: Some(format!("test{}@test.com", i + 1)),
+                },
+                date: Local::now(),
+                ..Default::default()
+            })
+            .collect();
+
+        let client = ImapClient::connect("dummy", "dummy");
+        if client.is_err() {
+            return;
+        }
+
+        let mut app = App::new(client.unwrap(), emails);
+        app.set_visible_items(3); // Very small window, half-page = 1 (minimum)
+
+        // Start at position 0 (cursor at top of window)
+        assert_eq!(app.list_state.selected(), Some(0));
+        assert_eq!(app.scroll_offset, 0);
+
+        // Move cursor to middle of small window
+        app.next(); // Now at position 1
+        assert_eq!(app.list_state.selected(), Some(1));
+        assert_eq!(app.scroll_offset, 0);
+
+        // Half page forward should scroll window by 1
+        app.half_page_forward();
+        assert_eq!(app.scroll_offset, 1); // Window scrolled down by 1
+        assert_eq!(app.list_state.selected(), Some(2)); // Cursor maintains relative position (1st item in window)
 
         // Half page backward should scroll window back
         app.half_page_backward();
@@ -771,114 +5753,1781 @@ mod tests {
     }
 
     #[test]
-    fn test_line_scrolling() {
-        let emails: Vec<Email> = (0..20)
-            .map(|i| Email {
-                _uid: i + 1,
-                subject: format!("Email {}", i + 1),
-                from: NameAddr {
-                    name: None,
-                    email: Some(format!("test{}@test.com", i + 1)),
-                },
-                to: vec![],
-                cc: vec![],
-                bcc: vec![],
-                date: Local::now(),
-                is_read: false,
-                body: None,
-            })
+    fn test_line_scrolling() {
+        let emails: Vec<Email> = (0..20)
+            .map(|i| Email {
+                _uid: i + 1,
+                subject: format!("Email {}", i + 1),
+                from: NameAddr {
+                    name: None,
+                    email: Some(format!("test{}@test.com", i + 1)),
+                },
+                date: Local::now(),
+                ..Default::default()
+            })
+            .collect();
+
+        let client = ImapClient::connect("dummy", "dummy");
+        if client.is_err() {
+            return;
+        }
+
+        let mut app = App::new(client.unwrap(), emails);
+        app.set_visible_items(5); // Window shows 5 items
+
+        // Test with cursor in middle - should stay fixed
+        app.list_state.select(Some(2));
+        assert_eq!(app.list_state.selected(), Some(2));
+        assert_eq!(app.scroll_offset, 0);
+
+        // Line forward with cursor in middle - cursor stays fixed
+        app.line_forward();
+        assert_eq!(app.list_state.selected(), Some(2)); // Cursor stays at same position
+        assert_eq!(app.scroll_offset, 1); // Window scrolled down by 1
+
+        // Test with cursor at top - should move down when scrolling
+        app.scroll_offset = 0;
+        app.list_state.select(Some(0)); // Cursor at top of window
+
+        app.line_forward();
+        assert_eq!(app.list_state.selected(), Some(1)); // Cursor moved down to stay visible
+        assert_eq!(app.scroll_offset, 1); // Window scrolled down by 1
+
+        // Test with cursor at bottom - should move up when scrolling backward
+        app.scroll_offset = 5;
+        app.list_state.select(Some(9)); // Cursor at bottom of window (scroll_offset 5 + visible_items 5 - 1 = 9)
+
+        app.line_backward();
+        assert_eq!(app.list_state.selected(), Some(8)); // Cursor moved up to stay visible
+        assert_eq!(app.scroll_offset, 4); // Window scrolled up by 1
+    }
+
+    #[test]
+    fn test_line_scrolling_edge_cases() {
+        let emails: Vec<Email> = (0..10)
+            .map(|i| Email {
+                _uid: i + 1,
+                subject: format!("Email {}", i + 1),
+                from: NameAddr {
+                    name: None,
+                    email: Some(format!("test{}@test.com", i + 1)),
+                },
+                date: Local::now(),
+                ..Default::default()
+            })
+            .collect();
+
+        let client = ImapClient::connect("dummy", "dummy");
+        if client.is_err() {
+            return;
+        }
+
+        let mut app = App::new(client.unwrap(), emails);
+        app.set_visible_items(5); // Window shows 5 items, list has 10 items
+
+        // Test at the beginning - line backward should do nothing when scroll is at 0
+        app.list_state.select(Some(2)); // Set cursor to position 2
+        assert_eq!(app.scroll_offset, 0);
+        app.line_backward();
+        assert_eq!(app.list_state.selected(), Some(2)); // Cursor stays at position 2
+        assert_eq!(app.scroll_offset, 0); // Scroll should stay at 0 (can't go negative)
+
+        // Test at the end - line forward should do nothing when at max scroll
+        app.scroll_offset = 5; // Max scroll for 10 items with 5 visible (10-5=5)
+        app.line_forward();
+        assert_eq!(app.list_state.selected(), Some(2)); // Cursor stays at position 2
+        assert_eq!(app.scroll_offset, 5); // Scroll should stay at max
+    }
+
+    #[test]
+    fn test_line_scrolling_empty_list() {
+        let emails = vec![];
+        let client = ImapClient::connect("dummy", "dummy");
+        if client.is_err() {
+            return;
+        }
+
+        let mut app = App::new(client.unwrap(), emails);
+
+        // These should not panic on empty list
+        app.line_forward();
+        app.line_backward();
+        assert_eq!(app.list_state.selected(), None);
+        assert_eq!(app.scroll_offset, 0);
+    }
+
+    fn unread_test_email(uid: u32, is_read: bool, days_ago: i64) -> Email {
+        Email {
+            date: Local::now() - chrono::Duration::days(days_ago),
+            is_read,
+            ..test_email(uid, "test@test.com")
+        }
+    }
+
+    #[test]
+    fn test_initial_cursor_and_next_unread() {
+        // Newest first, as fetched.
+        let emails = vec![
+            unread_test_email(5, true, 0),
+            unread_test_email(4, false, 1),
+            unread_test_email(3, true, 2),
+            unread_test_email(2, false, 3),
+            unread_test_email(1, true, 4),
+        ];
+
+        let mut config = Config::default();
+        config.ui.initial_cursor = InitialCursor::LatestUnread;
+        let app = App::with_client(None, emails.clone()).with_config(config.clone());
+        assert_eq!(app.list_state.selected(), Some(1));
+
+        config.ui.initial_cursor = InitialCursor::FirstUnread;
+        let mut app = App::with_client(None, emails).with_config(config);
+        assert_eq!(app.list_state.selected(), Some(3));
+
+        // Tab wraps around to the next unread message.
+        app.next_unread();
+        assert_eq!(app.list_state.selected(), Some(1));
+        app.next_unread();
+        assert_eq!(app.list_state.selected(), Some(3));
+
+        // The window follows the cursor on the next render.
+        app.set_visible_items(2);
+        assert_eq!(app.scroll_offset, 2);
+    }
+
+    #[test]
+    fn test_next_unread_none() {
+        let mut app = App::with_client(None, vec![unread_test_email(1, true, 0)]);
+        app.next_unread();
+        assert_eq!(app.list_state.selected(), Some(0));
+        assert_eq!(app.status.current(), Some("No unread messages"));
+    }
+
+    #[test]
+    fn test_threads_fold_by_read_state() {
+        let reply = |uid, is_read, days_ago, parent: &str| Email {
+            message_id: Some(format!("<{}>", uid)),
+            in_reply_to: Some(parent.to_string()),
+            ..unread_test_email(uid, is_read, days_ago)
+        };
+        let root = |uid, is_read, days_ago| Email {
+            message_id: Some(format!("<{}>", uid)),
+            ..unread_test_email(uid, is_read, days_ago)
+        };
+        // Thread <1> is all read, thread <3> has an unread reply.
+        let emails = vec![
+            reply(4, false, 0, "<3>"),
+            reply(2, true, 1, "<1>"),
+            root(3, true, 2),
+            root(1, true, 3),
+        ];
+
+        let mut config = Config::default();
+        config.ui.threaded = true;
+        let mut app = App::with_client(None, emails).with_config(config);
+        let rows = app
+            .rows
+            .iter()
+            .map(|r| (r.email, r.hidden))
+            .collect::<Vec<_>>();
+        assert_eq!(rows, [(2, 0), (0, 0), (3, 1)]);
+        assert_eq!(app.rows[1].depth, 1);
+        // The folded reply is still listed for exports.
+        assert_eq!(app.listed_emails(), [2, 0, 3, 1]);
+
+        // Expanding the read thread keeps the cursor on its root.
+        app.select_row(2);
+        app.toggle_thread();
+        assert_eq!(app.rows.len(), 4);
+        assert_eq!(app.selected_email(), Some(3));
+
+        // Collapse all, then expand all.
+        app.toggle_all_threads();
+        assert_eq!(app.rows.len(), 2);
+        app.toggle_all_threads();
+        assert_eq!(app.rows.len(), 4);
+
+        app.toggle_threaded();
+        assert_eq!(app.rows.len(), 4);
+        assert_eq!(app.selected_email(), Some(3));
+    }
+
+    #[test]
+    fn test_step_through_thread_in_detail() {
+        let emails = vec![
+            Email {
+                message_id: Some("<2>".to_string()),
+                in_reply_to: Some("<1>".to_string()),
+                ..unread_test_email(2, true, 0)
+            },
+            Email {
+                message_id: Some("<1>".to_string()),
+                ..unread_test_email(1, true, 1)
+            },
+            unread_test_email(3, true, 2),
+        ];
+        let mut app = App::with_client(None, emails);
+
+        app.mode = ViewMode::Detail(1);
+        assert_eq!(app.thread_position(), Some((1, 2)));
+        app.thread_next();
+        assert!(matches!(app.mode, ViewMode::Detail(0)));
+        assert_eq!(app.selected_email(), Some(0));
+        assert_eq!(app.thread_position(), Some((2, 2)));
+
+        app.status.clear();
+        app.thread_next();
+        assert!(matches!(app.mode, ViewMode::Detail(0)));
+        assert_eq!(app.status.current(), Some("Last message in thread"));
+        app.thread_previous();
+        assert!(matches!(app.mode, ViewMode::Detail(1)));
+
+        app.mode = ViewMode::Detail(2);
+        assert_eq!(app.thread_position(), None);
+    }
+
+    #[test]
+    fn test_toggle_source() {
+        let emails = vec![unread_test_email(1, true, 0), unread_test_email(2, true, 1)];
+        let mut app = App::with_client(None, emails);
+        app.mode = ViewMode::Detail(0);
+
+        app.toggle_source();
+        assert_eq!(app.shown_source(), None);
+        assert!(app.status.current().unwrap().starts_with("Error: "));
+
+        app.source = Some((1, "Received: from mx.example.com\n".to_string()));
+        assert_eq!(app.shown_source(), Some("Received: from mx.example.com\n"));
+        app.mode = ViewMode::Detail(1);
+        assert_eq!(app.shown_source(), None);
+
+        app.mode = ViewMode::Detail(0);
+        app.toggle_source();
+        assert_eq!(app.shown_source(), None);
+    }
+
+    #[test]
+    fn test_detail_scroll_is_bounded() {
+        let mut app = App::with_client(None, vec![unread_test_email(1, true, 0)]);
+        app.mode = ViewMode::Detail(0);
+        app.set_detail_area(30, 10);
+        assert_eq!(app.detail_scroll_percent(), Some(0));
+
+        app.detail_bottom();
+        assert_eq!(app.detail_scroll_offset, 20);
+        app.detail_scroll_down();
+        app.detail_half_page_down();
+        assert_eq!(app.detail_scroll_offset, 20);
+        assert_eq!(app.detail_scroll_percent(), Some(100));
+
+        app.detail_half_page_up();
+        assert_eq!(app.detail_scroll_offset, 15);
+        assert_eq!(app.detail_scroll_percent(), Some(75));
+        app.detail_top();
+        app.detail_scroll_up();
+        assert_eq!(app.detail_scroll_offset, 0);
+
+        // A shorter text, or a taller window, pulls the offset back.
+        app.detail_bottom();
+        app.set_detail_area(14, 10);
+        assert_eq!(app.detail_scroll_offset, 4);
+        app.set_detail_area(8, 10);
+        assert_eq!(app.detail_scroll_offset, 0);
+        assert_eq!(app.detail_scroll_percent(), None);
+    }
+
+    #[test]
+    fn test_copy_summary() {
+        let mut app = App::with_client(None, vec![unread_test_email(1, true, 0)]);
+        app.mode = ViewMode::Detail(0);
+        app.emails[0].body = Some("Just saying hi.".to_string());
+        app.copy_summary();
+        assert_eq!(app.status.current(), Some("Nothing to copy in the message"));
+        assert_eq!(app.take_clipboard(), None);
+        app.status.clear();
+
+        app.emails[0].subject = "Your verification code".to_string();
+        app.emails[0].body = Some("Enter this code to sign in:\n\n  482913\n".to_string());
+        app.copy_summary();
+        assert_eq!(app.status.current(), Some("Copied One-time code: 482913"));
+        assert_eq!(app.take_clipboard().as_deref(), Some("482913"));
+        assert_eq!(app.take_clipboard(), None);
+    }
+
+    #[test]
+    fn test_url_picker() {
+        let mut app = App::with_client(None, vec![unread_test_email(1, true, 0)]);
+        app.mode = ViewMode::Detail(0);
+        app.emails[0].body = Some("No links here.".to_string());
+        app.show_urls();
+        assert!(matches!(app.mode, ViewMode::Detail(0)));
+        app.status.clear();
+
+        app.emails[0].body = Some("See https://a.example/x and https://b.example/.".to_string());
+        app.show_urls();
+        assert!(matches!(app.mode, ViewMode::Urls(0)));
+        app.url_next();
+        app.url_next();
+        let shown = |app: &App| {
+            let (links, selected) = app.urls();
+            let links = links
+                .into_iter()
+                .map(|(number, link)| (number, link.url.clone()))
+                .collect::<Vec<_>>();
+            (links, selected)
+        };
+        assert_eq!(
+            shown(&app),
+            (
+                vec![
+                    (1, "https://a.example/x".to_string()),
+                    (2, "https://b.example/".to_string())
+                ],
+                1
+            )
+        );
+
+        // Typing narrows the links, keeping their numbers.
+        app.start_picker_filter();
+        app.picker_input('b');
+        app.picker_input('e');
+        assert_eq!(
+            shown(&app),
+            (vec![(2, "https://b.example/".to_string())], 0)
+        );
+        app.picker_input('z');
+        assert!(app.urls().0.is_empty());
+        app.picker_cancel();
+        assert!(!app.picker_typing());
+        assert_eq!(app.urls().0.len(), 2);
+
+        app.open_url_number(3);
+        assert_eq!(app.status.current(), Some("No link 3; the message has 2"));
+        assert!(matches!(app.mode, ViewMode::Urls(0)));
+        app.close_urls();
+        assert!(matches!(app.mode, ViewMode::Detail(0)));
+    }
+
+    #[test]
+    fn test_save_message() {
+        let mut app = App::with_client(None, vec![unread_test_email(1, true, 0)]);
+        app.emails[0].subject = "Q3 plan/draft".to_string();
+        app.save_message();
+        let prompt = app.prompt.as_ref().unwrap();
+        assert!(matches!(prompt.kind, PromptKind::SaveMessage(0)));
+        assert_eq!(prompt.input, "Q3 plan_draft.eml");
+
+        let raw = b"Content-Type: text/plain\r\n\r\nSee you at 10.\r\n";
+        let text = message_as_text(&app.emails[0], raw).unwrap();
+        assert!(text.starts_with("Date: "));
+        assert!(text.ends_with("Subject: Q3 plan/draft\n\nSee you at 10.\n"));
+    }
+
+    #[test]
+    fn test_toggle_headers() {
+        let mut app = App::with_client(None, vec![unread_test_email(1, true, 0)]);
+        app.mode = ViewMode::Detail(0);
+        app.toggle_headers();
+        assert!(app.full_headers());
+
+        // Returning to the list weeds the headers again.
+        app.back_to_list();
+        assert!(!app.full_headers());
+    }
+
+    #[test]
+    fn test_sidebar_cursor() {
+        let mut app = App::with_client(None, vec![unread_test_email(1, false, 0)]);
+        app.sidebar_next();
+        assert_eq!(
+            app.status.current(),
+            Some("The sidebar is hidden; b shows it")
+        );
+
+        app.sidebar = Some(
+            ["INBOX", "Receipts", "[Gmail]/Sent Mail"]
+                .map(|path| FolderChoice {
+                    path: path.to_string(),
+                    unread: 0,
+                })
+                .to_vec(),
+        );
+        app.sidebar_next();
+        app.sidebar_next();
+        app.sidebar_next();
+        assert_eq!(app.sidebar().map(|(_, selected)| selected), Some(2));
+        app.sidebar_previous();
+        assert_eq!(app.sidebar().map(|(_, selected)| selected), Some(1));
+
+        app.toggle_sidebar();
+        assert!(app.sidebar().is_none());
+    }
+
+    #[test]
+    fn test_goto_parent_and_back() {
+        let emails = vec![
+            Email {
+                message_id: Some("<2>".to_string()),
+                references: vec!["<0>".to_string(), "<1>".to_string()],
+                body: Some("Reply".to_string()),
+                ..unread_test_email(2, true, 0)
+            },
+            Email {
+                message_id: Some("<1>".to_string()),
+                subject: "Lunch?".to_string(),
+                in_reply_to: Some("<0>".to_string()),
+                body: Some("Original".to_string()),
+                ..unread_test_email(1, true, 1)
+            },
+        ];
+        let mut app = App::with_client(None, emails);
+        assert_eq!(app.in_reply_to(0), Some("Lunch?"));
+        assert_eq!(app.in_reply_to(1), Some("<0>"));
+
+        app.mode = ViewMode::Detail(0);
+        app.back_to_reply();
+        assert_eq!(app.status.current(), Some("No reply to go back to"));
+
+        app.goto_parent();
+        assert!(matches!(app.mode, ViewMode::Detail(1)));
+        assert_eq!(app.selected_email(), Some(1));
+        app.back_to_reply();
+        assert!(matches!(app.mode, ViewMode::Detail(0)));
+    }
+
+    #[test]
+    fn test_narrow_thread_to_participant() {
+        let from = |address: &str| crate::imap_client::NameAddr {
+            name: None,
+            email: Some(address.to_string()),
+        };
+        let emails = vec![
+            Email {
+                message_id: Some("<3>".to_string()),
+                in_reply_to: Some("<2>".to_string()),
+                from: from("alice@example.com"),
+                ..unread_test_email(3, true, 0)
+            },
+            Email {
+                message_id: Some("<2>".to_string()),
+                in_reply_to: Some("<1>".to_string()),
+                from: from("bob@example.com"),
+                to: vec![from("carol@example.com")],
+                ..unread_test_email(2, true, 1)
+            },
+            Email {
+                message_id: Some("<1>".to_string()),
+                from: from("alice@example.com"),
+                ..unread_test_email(1, true, 2)
+            },
+        ];
+        let mut app = App::with_client(None, emails);
+
+        app.mode = ViewMode::Detail(1);
+        app.show_participants();
+        let (participants, selected, narrowed) = app.participants();
+        let summary = participants
+            .iter()
+            .map(|p| (p.address.as_str(), p.sent))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                ("alice@example.com", 2),
+                ("bob@example.com", 1),
+                ("carol@example.com", 0)
+            ]
+        );
+        assert_eq!((selected, narrowed), (0, None));
+
+        // Narrowing shows the participant's first message.
+        app.narrow_to_participant();
+        assert!(matches!(app.mode, ViewMode::Detail(2)));
+        assert_eq!(app.thread_position(), Some((1, 2)));
+        app.thread_next();
+        assert!(matches!(app.mode, ViewMode::Detail(0)));
+
+        app.show_participants();
+        assert_eq!(app.participants().1, 0);
+        app.participant_next();
+        app.participant_next();
+        app.status.clear();
+        app.narrow_to_participant();
+        assert!(matches!(app.mode, ViewMode::Participants(0)));
+        assert_eq!(
+            app.status.current(),
+            Some("carol@example.com has not written in this thread")
+        );
+
+        app.show_all_participants();
+        assert!(matches!(app.mode, ViewMode::Detail(0)));
+        assert_eq!(app.thread_position(), Some((3, 3)));
+    }
+
+    #[test]
+    fn test_thread_operation_asks_for_scope() {
+        let emails = vec![
+            Email {
+                message_id: Some("<2>".to_string()),
+                in_reply_to: Some("<1>".to_string()),
+                ..unread_test_email(2, false, 0)
+            },
+            Email {
+                message_id: Some("<1>".to_string()),
+                ..unread_test_email(1, true, 1)
+            },
+        ];
+        let mut config = Config::default();
+        config.ui.threaded = true;
+        let mut app = App::with_client(None, emails.clone()).with_config(config);
+
+        app.toggle_read();
+        let prompt = app.prompt.as_ref().unwrap();
+        assert_eq!(prompt.label, "Apply to 2 messages in thread? (y/N): ");
+        assert!(matches!(
+            prompt.kind,
+            PromptKind::ConfirmThread(MessageOp::MarkRead)
+        ));
+        assert_eq!(app.selected_thread(), [1, 0]);
+
+        // Without threading the operation applies directly.
+        let mut app = App::with_client(None, emails);
+        app.archive();
+        assert!(app.prompt.is_none());
+        assert_eq!(
+            app.status.current(),
+            Some("Error: Not connected to IMAP server")
+        );
+    }
+
+    #[test]
+    fn test_resend_bounced() {
+        let mut app = App::with_client(None, vec![unread_test_email(7, true, 0)]);
+        app.mode = ViewMode::Detail(0);
+        app.resend_bounced();
+        assert_eq!(app.status.current(), Some("No returned message to resend"));
+
+        app.reports.insert(
+            7,
+            DeliveryReport {
+                reporting_mta: None,
+                recipients: vec![],
+                explanation: String::new(),
+                original: Some(b"To: bob@example.com\r\nSubject: Hi\r\n\r\nHello\r\n".to_vec()),
+            },
+        );
+        app.resend_bounced();
+        assert!(matches!(app.mode, ViewMode::Compose));
+        assert!(app.editor_requested);
+        let draft = app.draft.as_ref().unwrap();
+        assert_eq!(draft.to, "bob@example.com");
+        assert_eq!(draft.subject, "Hi");
+    }
+
+    #[test]
+    fn test_reply() {
+        let mut app = App::with_client(None, vec![unread_test_email(7, true, 0)]);
+        app.reply(false);
+        assert!(app.draft.is_none());
+
+        app.mode = ViewMode::Detail(0);
+        app.reply(true);
+        assert!(matches!(app.mode, ViewMode::Compose));
+        assert!(app.editor_requested);
+        let draft = app.draft.as_ref().unwrap();
+        assert!(draft.subject.starts_with("Re: "));
+    }
+
+    #[test]
+    fn test_new_mail_is_added() {
+        let mut app = App::with_client(
+            None,
+            vec![unread_test_email(2, true, 1), unread_test_email(1, true, 2)],
+        );
+        app.emails[1].body = Some("loaded".to_string());
+        app.select_row(1);
+
+        app.set_emails(vec![
+            unread_test_email(3, false, 0),
+            unread_test_email(2, true, 1),
+            unread_test_email(1, true, 2),
+        ]);
+        assert_eq!(app.new_mail_count(), 1);
+        assert_eq!(app.status.current(), Some("1 new message"));
+        assert_eq!(app.selected_email(), Some(2));
+        assert_eq!(app.emails[2].body.as_deref(), Some("loaded"));
+
+        app.mode = ViewMode::Detail(0);
+        app.open_email(0);
+        assert_eq!(app.new_mail_count(), 0);
+
+        app.config.gmail.tag = Some("W".to_string());
+        app.status.clear();
+        app.set_emails(vec![
+            unread_test_email(5, false, 0),
+            unread_test_email(4, false, 0),
+            unread_test_email(3, false, 0),
+        ]);
+        assert_eq!(app.status.current(), Some("[W] 2 new messages"));
+    }
+
+    #[test]
+    fn test_window_title_and_new_mail_alert() {
+        let mut app = App::with_client(None, vec![unread_test_email(1, true, 1)]);
+        assert_eq!(app.window_title(), "rutt: INBOX");
+
+        app.set_emails(vec![
+            unread_test_email(2, false, 0),
+            unread_test_email(1, true, 1),
+        ]);
+        assert_eq!(app.window_title(), "rutt: INBOX (1 unread)");
+        // No alert unless configured, and none left over once it is.
+        assert_eq!(app.take_new_mail_alert(), None);
+        app.config.ui.new_mail_alert = NewMailAlert::Bell;
+        assert_eq!(app.take_new_mail_alert(), None);
+
+        app.set_emails(vec![
+            unread_test_email(3, false, 0),
+            unread_test_email(2, false, 0),
+            unread_test_email(1, true, 1),
+        ]);
+        assert_eq!(app.take_new_mail_alert(), Some(NewMailAlert::Bell));
+        assert_eq!(app.take_new_mail_alert(), None);
+    }
+
+    #[test]
+    fn test_code_from_new_mail() {
+        let mut app = App::with_client(None, vec![unread_test_email(1, true, 1)]);
+        app.copy_code();
+        assert_eq!(app.status.current(), Some("No one-time code in new mail"));
+        app.status.clear();
+
+        let mut email = unread_test_email(2, false, 0);
+        email.subject = "731502 is your verification code".to_string();
+        app.set_emails(vec![email, unread_test_email(1, true, 1)]);
+        app.status.clear();
+        assert_eq!(
+            app.code_offer.as_ref().map(|(code, _)| code.as_str()),
+            Some("731502")
+        );
+
+        // A code in the body of a new message arrives from a background
+        // fetch that leaves the message unread.
+        app.peeking.insert(3);
+        let mut email = unread_test_email(3, false, 0);
+        email.subject = "Sign in to Example".to_string();
+        app.emails.insert(0, email);
+        app.receive_body(FetchedBody {
+            mailbox: "INBOX".to_string(),
+            uid: 3,
+            peek: true,
+            filter_errors: Vec::new(),
+            body: Ok(MessageBody {
+                text: "Your code is 482913.".to_string(),
+                report: None,
+                alternatives: None,
+                html: None,
+                headers: Vec::new(),
+            }),
+        });
+        assert!(!app.peeking());
+        assert_eq!(app.emails[0].body, None);
+        assert!(
+            app.status
+                .current()
+                .unwrap()
+                .starts_with("Code 482913 from ")
+        );
+
+        app.copy_code();
+        assert_eq!(app.take_clipboard().as_deref(), Some("482913"));
+        app.code_offer = Some(("482913".to_string(), Instant::now() - CODE_OFFER_TTL));
+        app.copy_code();
+        assert_eq!(app.take_clipboard(), None);
+    }
+
+    #[test]
+    fn test_step_through_messages_in_detail() {
+        let emails = (1..=3)
+            .map(|uid| unread_test_email(uid, false, uid as i64))
             .collect();
+        let mut app = App::with_client(None, emails);
+        app.list_state.select(Some(0));
+        app.mode = ViewMode::Detail(app.rows[0].email);
+        app.detail_previous();
+        assert_eq!(app.status.current(), Some("First message"));
+        app.status.clear();
+
+        // A body fetched ahead is kept for the message until it is opened.
+        let ahead = app.emails[app.rows[1].email]._uid;
+        app.prefetching.insert(ahead);
+        assert!(app.peeking());
+        app.receive_body(FetchedBody {
+            mailbox: "INBOX".to_string(),
+            uid: ahead,
+            peek: true,
+            filter_errors: Vec::new(),
+            body: Ok(MessageBody {
+                text: "Fetched ahead".to_string(),
+                report: None,
+                alternatives: None,
+                html: None,
+                headers: Vec::new(),
+            }),
+        });
+        assert!(!app.peeking());
+        assert_eq!(app.emails[app.rows[1].email].body, None);
+
+        app.detail_next();
+        assert_eq!(app.list_state.selected(), Some(1));
+        let idx = app.rows[1].email;
+        assert!(matches!(app.mode, ViewMode::Detail(i) if i == idx));
+        assert_eq!(app.emails[idx].body.as_deref(), Some("Fetched ahead"));
+        assert_eq!(app.queued_count(), 1);
+
+        let last = app.rows[2].email;
+        app.emails[last].body = Some("Body".to_string());
+        app.detail_next();
+        app.detail_next();
+        assert_eq!(app.list_state.selected(), Some(2));
+        assert_eq!(app.status.current(), Some("Last message"));
+    }
 
-        let client = GmailClient::connect("dummy", "dummy");
-        if client.is_err() {
-            return;
+    #[test]
+    fn test_archive_folders_by_year() {
+        let emails = vec![
+            unread_test_email(1, true, 0),
+            unread_test_email(2, true, 400),
+            unread_test_email(3, true, 800),
+        ];
+        let mut app = App::with_client(None, emails);
+        assert!(app.archive_folders(&[0, 1, 2]).is_empty());
+
+        app.config.archive.folder = Some("Archive/{year}".to_string());
+        let folders = app.archive_folders(&[0, 1, 2]);
+        let year = |idx: usize| format!("Archive/{}", app.emails[idx].date.year());
+        assert_eq!(folders.len(), 3);
+        assert_eq!(folders[&year(0)], [1]);
+        assert_eq!(folders[&year(2)], [3]);
+    }
+
+    #[test]
+    fn test_translate() {
+        let mut email = unread_test_email(1, true, 0);
+        email.body = Some("hola".to_string());
+        let mut app = App::with_client(None, vec![email]);
+        let err = app.run_command(":translate").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`:translate` works on the message being read"
+        );
+
+        app.mode = ViewMode::Detail(0);
+        let err = app.run_command(":translate").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Set command under [translate] to translate messages"
+        );
+
+        app.config.translate.command = Some("printf '%s:' {to}; tr a-z A-Z".to_string());
+        app.config.translate.source = Some("es".to_string());
+        app.run_command(":translate de").unwrap();
+        assert!(app.translating());
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while app.translating() {
+            assert!(Instant::now() < deadline, "translation not finished");
+            std::thread::sleep(Duration::from_millis(10));
+            app.poll_translation();
         }
+        let translation = app.translation(0).unwrap();
+        assert_eq!(translation.text, "de:HOLA");
+        assert_eq!(translation.from, "es");
 
-        let mut app = App::new(client.unwrap(), emails);
-        app.set_visible_items(5); // Window shows 5 items
+        app.run_command(":translate").unwrap();
+        assert!(app.translation(0).is_none());
+    }
 
-        // Test with cursor in middle - should stay fixed
-        app.list_state.select(Some(2));
-        assert_eq!(app.list_state.selected(), Some(2));
-        assert_eq!(app.scroll_offset, 0);
+    #[test]
+    fn test_receive_body() {
+        let fetched = |mailbox: &str, uid: u32| FetchedBody {
+            mailbox: mailbox.to_string(),
+            uid,
+            peek: false,
+            filter_errors: Vec::new(),
+            body: Ok(MessageBody {
+                text: format!("Body {}", uid),
+                report: None,
+                alternatives: None,
+                html: None,
+                headers: vec![("X-Mailer".to_string(), "rutt".to_string())],
+            }),
+        };
+        let emails = vec![
+            unread_test_email(1, false, 0),
+            unread_test_email(2, false, 1),
+        ];
+        let mut app = App::with_client(None, emails);
+        app.mode = ViewMode::Detail(0);
+        app.loading.extend([1, 2]);
+        assert!(app.body_placeholder(0).ends_with(" Loading..."));
+
+        let mut body = fetched("INBOX", 1);
+        body.filter_errors
+            .push(anyhow::anyhow!("Display filter failed"));
+        app.receive_body(body);
+        assert_eq!(app.emails[0].body.as_deref(), Some("Body 1"));
+        // What a filter's failure left is shown, with the failure.
+        assert_eq!(app.status.current(), Some("Error: Display filter failed"));
+        assert_eq!(
+            app.message_headers(0),
+            Some(&[("X-Mailer".to_string(), "rutt".to_string())][..])
+        );
+        assert!(app.loading());
+
+        // A body from a mailbox no longer shown is dropped.
+        app.receive_body(fetched("[Gmail]/Sent Mail", 2));
+        assert_eq!(app.emails[1].body, None);
+        assert!(!app.loading());
+        assert_eq!(app.body_placeholder(1), "(Body not loaded)");
+    }
 
-        // Line forward with cursor in middle - cursor stays fixed
-        app.line_forward();
-        assert_eq!(app.list_state.selected(), Some(2)); // Cursor stays at same position
-        assert_eq!(app.scroll_offset, 1); // Window scrolled down by 1
+    #[test]
+    fn test_filters_stack_and_keep_selection() {
+        let mut emails: Vec<_> = (1..=4)
+            .map(|uid| unread_test_email(uid, uid % 2 == 0, uid as i64))
+            .collect();
+        emails[0].is_flagged = true;
+        emails[1].is_flagged = true;
+        emails[2].cc = vec![NameAddr {
+            name: None,
+            email: Some("Me@Example.com".to_string()),
+        }];
+        let mut app = App::with_client(None, emails);
+        app.config.gmail.username = "me@example.com".to_string();
+        app.select_row(1);
+
+        // Unread: 1 and 3. The selected email 2 is hidden, so the cursor
+        // moves to the next one shown.
+        app.toggle_filter(Filter::Unread);
+        assert_eq!(app.rows.len(), 2);
+        assert_eq!(app.selected_email(), Some(2));
+
+        app.toggle_filter(Filter::ToMe);
+        assert_eq!(app.filters(), [Filter::Unread, Filter::ToMe]);
+        assert_eq!(app.rows.len(), 1);
+        assert_eq!(app.selected_email(), Some(2));
+
+        app.toggle_filter(Filter::ToMe);
+        app.toggle_filter(Filter::Flagged);
+        assert_eq!(app.rows.len(), 1);
+        assert_eq!(app.selected_email(), Some(0));
+
+        app.toggle_filter(Filter::Unread);
+        assert_eq!(app.rows.len(), 2);
+        assert_eq!(app.selected_email(), Some(0));
+
+        app.clear_filters();
+        assert!(app.filters().is_empty());
+        assert_eq!(app.rows.len(), 4);
+        assert_eq!(app.selected_email(), Some(0));
+        assert!(!app.should_quit);
+        app.clear_filters();
+        assert!(app.should_quit);
+    }
 
-        // Test with cursor at top - should move down when scrolling
-        app.scroll_offset = 0;
-        app.list_state.select(Some(0)); // Cursor at top of window
+    #[test]
+    fn test_focus_hides_read_messages() {
+        let emails = vec![
+            unread_test_email(1, false, 0),
+            unread_test_email(2, true, 1),
+            unread_test_email(3, false, 2),
+            unread_test_email(4, false, 3),
+        ];
+        let mut app = App::with_client(None, emails);
+        app.toggle_focus();
+        assert!(app.focus());
+        assert_eq!(app.rows.len(), 3);
+        assert_eq!(app.selected_email(), Some(0));
+
+        // A message read in the detail view leaves the list on return.
+        app.mode = ViewMode::Detail(0);
+        app.emails[0].body = Some("Body".to_string());
+        app.back_to_list();
+        assert_eq!(app.rows.len(), 2);
+        assert_eq!(app.selected_email(), Some(2));
 
-        app.line_forward();
-        assert_eq!(app.list_state.selected(), Some(1)); // Cursor moved down to stay visible
-        assert_eq!(app.scroll_offset, 1); // Window scrolled down by 1
+        // A message still listed after an action is skipped.
+        app.focus_advance();
+        assert_eq!(app.selected_email(), Some(3));
 
-        // Test with cursor at bottom - should move up when scrolling backward
-        app.scroll_offset = 5;
-        app.list_state.select(Some(9)); // Cursor at bottom of window (scroll_offset 5 + visible_items 5 - 1 = 9)
+        app.clear_filters();
+        assert!(!app.focus());
+        assert_eq!(app.rows.len(), 4);
+        assert!(!app.should_quit);
+    }
 
-        app.line_backward();
-        assert_eq!(app.list_state.selected(), Some(8)); // Cursor moved up to stay visible
-        assert_eq!(app.scroll_offset, 4); // Window scrolled up by 1
+    #[test]
+    fn test_scan_blocked_attachment_is_skipped() {
+        let attachment = |name: &str, data: &[u8]| Attachment {
+            filename: name.to_string(),
+            mime_type: "application/octet-stream".to_string(),
+            data: data.to_vec(),
+        };
+        let mut app = App::with_client(None, vec![unread_test_email(1, true, 0)]);
+        app.config.attachments.scan_command =
+            Some("! grep -q VIRUS || { echo 'stdin: FOUND'; exit 1; }".to_string());
+        app.pending_attachments = vec![
+            attachment("bad.exe", b"VIRUS"),
+            attachment("good.pdf", b"ok"),
+        ]
+        .into();
+
+        app.prompt_next_attachment();
+        let prompt = app.prompt.as_ref().unwrap();
+        assert!(matches!(prompt.kind, PromptKind::ScanBlocked));
+        assert!(prompt.label.contains("bad.exe"));
+        assert!(prompt.label.contains("stdin: FOUND"));
+
+        app.prompt_submit();
+        let prompt = app.prompt.as_ref().unwrap();
+        assert!(matches!(prompt.kind, PromptKind::SaveAttachment(_)));
+        assert_eq!(prompt.label, "Save good.pdf to: ");
+
+        // Opening is gated on the scan the same way.
+        app.prompt = None;
+        app.opening_attachments = true;
+        app.pending_attachments = vec![
+            attachment("bad.exe", b"VIRUS"),
+            attachment("good.pdf", b"ok"),
+        ]
+        .into();
+        app.prompt_next_attachment();
+        assert!(matches!(
+            app.prompt.as_ref().unwrap().kind,
+            PromptKind::ScanBlocked
+        ));
+        app.prompt_submit();
+        let prompt = app.prompt.as_ref().unwrap();
+        assert!(matches!(prompt.kind, PromptKind::OpenAttachment(_)));
+        assert_eq!(
+            prompt.label,
+            "Open good.pdf (application/octet-stream)? (y/N): "
+        );
+        app.status.clear();
+        app.prompt_submit();
+        assert!(app.prompt.is_none());
+        assert_eq!(app.status.current(), Some("Skipped good.pdf"));
     }
 
     #[test]
-    fn test_line_scrolling_edge_cases() {
-        let emails: Vec<Email> = (0..10)
-            .map(|i| Email {
-                _uid: i + 1,
-                subject: format!("Email {}", i + 1),
-                from: NameAddr {
-                    name: None,
-                    email: Some(format!("test{}@test.com", i + 1)),
-                },
-                to: vec![],
-                cc: vec![],
-                bcc: vec![],
-                date: Local::now(),
-                is_read: false,
-                body: None,
-            })
+    fn test_two_line_rows_halve_visible_items() {
+        let emails = (1..=10)
+            .map(|uid| unread_test_email(uid, true, 0))
             .collect();
+        let mut app = App::with_client(None, emails);
+        app.config.ui.two_line_below = Some(80);
 
-        let client = GmailClient::connect("dummy", "dummy");
-        if client.is_err() {
-            return;
+        app.set_list_area(120, 10);
+        assert!(!app.two_line_rows);
+        assert_eq!(app.visible_items, 10);
+
+        app.set_list_area(60, 11);
+        assert!(app.two_line_rows);
+        assert_eq!(app.visible_items, 5);
+        for _ in 0..5 {
+            app.next();
         }
+        assert_eq!(app.list_state.selected(), Some(5));
+        assert_eq!(app.scroll_offset, 1);
+    }
 
-        let mut app = App::new(client.unwrap(), emails);
-        app.set_visible_items(5); // Window shows 5 items, list has 10 items
+    #[test]
+    fn test_limit_to_pattern() {
+        let mut emails = vec![
+            unread_test_email(1, false, 0),
+            unread_test_email(2, true, 1),
+            unread_test_email(3, false, 40),
+        ];
+        emails[0].from.email = Some("alice@example.com".to_string());
+        let mut app = App::with_client(None, emails);
 
-        // Test at the beginning - line backward should do nothing when scroll is at 0
-        app.list_state.select(Some(2)); // Set cursor to position 2
-        assert_eq!(app.scroll_offset, 0);
-        app.line_backward();
-        assert_eq!(app.list_state.selected(), Some(2)); // Cursor stays at position 2
-        assert_eq!(app.scroll_offset, 0); // Scroll should stay at 0 (can't go negative)
+        app.start_pattern();
+        for c in "~N ~d <1m".chars() {
+            app.prompt_input(c);
+        }
+        app.prompt_submit();
+        assert_eq!(app.rows.len(), 1);
+        assert_eq!(app.pattern().map(Pattern::as_str), Some("~N ~d <1m"));
+
+        // The pattern combines with the quick filters, and the prompt starts
+        // from the active pattern.
+        app.toggle_filter(Filter::Unread);
+        app.start_pattern();
+        assert_eq!(app.prompt.as_ref().unwrap().input, "~N ~d <1m");
+        app.prompt_cancel();
+
+        app.set_pattern("!~f alice").unwrap();
+        assert_eq!(app.rows.len(), 1);
+        assert_eq!(app.emails[app.rows[0].email]._uid, 3);
+
+        assert!(app.set_pattern("~q").is_err());
+        assert_eq!(app.rows.len(), 1);
+
+        app.clear_filters();
+        assert!(app.pattern().is_none());
+        assert_eq!(app.rows.len(), 3);
+        assert!(!app.should_quit);
+    }
 
-        // Test at the end - line forward should do nothing when at max scroll
-        app.scroll_offset = 5; // Max scroll for 10 items with 5 visible (10-5=5)
-        app.line_forward();
-        assert_eq!(app.list_state.selected(), Some(2)); // Cursor stays at position 2
-        assert_eq!(app.scroll_offset, 5); // Scroll should stay at max
+    #[test]
+    fn test_cleanup_senders() {
+        let mut emails = vec![
+            unread_test_email(1, false, 0),
+            unread_test_email(2, true, 1),
+            unread_test_email(3, false, 2),
+        ];
+        emails[1].from.email = Some("alice@example.com".to_string());
+        let mut app = App::with_client(None, emails);
+
+        app.show_cleanup();
+        assert!(matches!(app.mode, ViewMode::Cleanup));
+        let (senders, selected) = app.senders();
+        assert_eq!(senders[0].address, "test@test.com");
+        assert_eq!(senders[0].count, 2);
+        assert_eq!(selected, 0);
+
+        // Deleting asks first.
+        app.delete_sender();
+        app.prompt_input('n');
+        app.prompt_submit();
+        assert_eq!(app.status.current(), Some("Cancelled"));
+        assert_eq!(app.emails.len(), 3);
+
+        // Muting hides the sender's messages from the list, even without a
+        // config file to save the rule to.
+        app.status.clear();
+        app.mute_sender();
+        assert_eq!(app.config.ui.muted_senders, ["test@test.com"]);
+        assert!(
+            app.status
+                .current()
+                .is_some_and(|s| s.starts_with("Muted test@test.com (not saved"))
+        );
+        assert_eq!(app.rows.len(), 1);
+        assert_eq!(app.emails[app.rows[0].email]._uid, 2);
+
+        app.sender_next();
+        app.toggle_sender_sort();
+        assert!(app.senders_by_size());
+        assert_eq!(app.senders().1, 0);
     }
 
     #[test]
-    fn test_line_scrolling_empty_list() {
-        let emails = vec![];
-        let client = GmailClient::connect("dummy", "dummy");
-        if client.is_err() {
-            return;
+    fn test_goto_number() {
+        let emails = (1..=5).map(|uid| unread_test_email(uid, true, 0)).collect();
+        let mut app = App::with_client(None, emails);
+
+        app.goto_number(4);
+        assert_eq!(app.list_state.selected(), Some(3));
+        app.run_command(":2").unwrap();
+        assert_eq!(app.list_state.selected(), Some(1));
+
+        app.status.clear();
+        app.goto_number(6);
+        assert_eq!(app.status.current(), Some("No message 6; the list has 5"));
+        assert_eq!(app.list_state.selected(), Some(1));
+    }
+
+    #[test]
+    fn test_after_read() {
+        let emails = vec![
+            unread_test_email(4, false, 0),
+            unread_test_email(3, true, 1),
+            unread_test_email(2, true, 2),
+            unread_test_email(1, false, 3),
+        ];
+        let mut app = App::with_client(None, emails);
+
+        app.view_email();
+        app.back_to_list();
+        assert_eq!(app.list_state.selected(), Some(0));
+
+        app.config.ui.after_read = AfterRead::Next;
+        app.view_email();
+        app.back_to_list();
+        assert_eq!(app.list_state.selected(), Some(1));
+
+        // Past the read messages, then around to the top.
+        app.config.ui.after_read = AfterRead::NextUnread;
+        app.view_email();
+        app.back_to_list();
+        assert_eq!(app.list_state.selected(), Some(3));
+        app.view_email();
+        app.back_to_list();
+        assert_eq!(app.list_state.selected(), Some(0));
+    }
+
+    #[test]
+    fn test_failed_envelopes() {
+        let mut app = App::with_client(None, vec![unread_test_email(1, true, 0)]);
+        app.retry_failed();
+        assert_eq!(app.status.current(), Some("No messages failed to load"));
+
+        app.status.clear();
+        app.set_failed(vec![5, 6]);
+        assert_eq!(
+            app.status.current(),
+            Some("1/3 loaded, 2 failed (press r to retry)")
+        );
+        // A refresh failing on the same messages says nothing new.
+        app.status.clear();
+        app.set_failed(vec![5, 6]);
+        assert!(app.status.is_empty());
+
+        app.retry_failed();
+        assert!(app.status.current().unwrap().starts_with("Error: "));
+        assert_eq!(app.failed_uids, [5, 6]);
+    }
+
+    #[test]
+    fn test_show_version() {
+        let mut app = App::with_client(None, vec![unread_test_email(1, true, 0)]);
+        app.run_command(":version").unwrap();
+        assert!(matches!(app.mode, ViewMode::Version));
+        // Nothing is looked up unless the check is enabled.
+        assert!(app.update_status().is_none());
+        app.back_to_list();
+        assert!(matches!(app.mode, ViewMode::List));
+    }
+
+    #[test]
+    fn test_folder_results() {
+        let mut app = App::with_client(None, vec![unread_test_email(1, true, 0)]);
+        let err = app.run_command(":search-all").unwrap_err();
+        assert_eq!(err.to_string(), "No search across folders yet");
+        assert!(app.run_command(":search-all invoice").is_err());
+
+        let hit = |folder: &str, uid| FolderHit {
+            folder: folder.to_string(),
+            email: unread_test_email(uid, false, 0),
+        };
+        app.folder_results = Some((
+            "invoice".to_string(),
+            vec![hit("Receipts", 7), hit("INBOX", 7)],
+        ));
+        app.run_command(":search-all").unwrap();
+        assert!(matches!(app.mode, ViewMode::Results));
+        assert_eq!(app.view_description(), "Matches in all folders: 2");
+        app.result_next();
+        app.result_next();
+        assert_eq!(app.folder_results().unwrap().2, 1);
+        app.result_previous();
+        assert_eq!(app.folder_results().unwrap().2, 0);
+
+        // Opening a match needs the server.
+        app.status.clear();
+        app.open_result();
+        assert!(matches!(app.mode, ViewMode::List));
+        assert!(app.status.current().unwrap().starts_with("Error: "));
+    }
+
+    #[test]
+    fn test_command_prompt() {
+        let emails = (1..=5).map(|uid| unread_test_email(uid, true, 0)).collect();
+        let mut app = App::with_client(None, emails);
+        let type_command = |app: &mut App, text: &str| {
+            app.start_command();
+            text.chars().for_each(|c| app.prompt_input(c));
+        };
+
+        // Tab completes the name, then the argument.
+        type_command(&mut app, "so");
+        app.prompt_complete();
+        "send".chars().for_each(|c| app.prompt_input(c));
+        app.prompt_complete();
+        assert_eq!(app.prompt.as_ref().unwrap().input, "sort sender");
+        app.prompt_submit();
+        assert_eq!(app.sort(), SortMode::Sender);
+
+        type_command(&mut app, "goto 3");
+        app.prompt_submit();
+        assert_eq!(app.list_state.selected(), Some(2));
+        app.status.clear();
+        type_command(&mut app, "sort size");
+        app.prompt_submit();
+        assert!(
+            app.status
+                .current()
+                .unwrap()
+                .starts_with("Error: Unknown order")
+        );
+
+        // Up goes back through the commands entered, Down forward again.
+        app.start_command();
+        app.prompt_history_previous();
+        app.prompt_history_previous();
+        assert_eq!(app.prompt.as_ref().unwrap().input, "goto 3");
+        app.prompt_history_previous();
+        app.prompt_history_previous();
+        assert_eq!(app.prompt.as_ref().unwrap().input, "sort sender");
+        app.prompt_history_next();
+        assert_eq!(app.prompt.as_ref().unwrap().input, "goto 3");
+        app.prompt_history_next();
+        app.prompt_history_next();
+        assert_eq!(app.prompt.as_ref().unwrap().input, "");
+    }
+
+    #[test]
+    fn test_cycle_sort() {
+        let mut emails = vec![
+            unread_test_email(1, true, 0),
+            unread_test_email(2, false, 1),
+            unread_test_email(3, true, 2),
+        ];
+        emails[0].subject = "Re: lunch".to_string();
+        emails[1].subject = "Agenda".to_string();
+        emails[2].subject = "Fwd: RE: Budget".to_string();
+        emails[2].from.email = Some("alice@example.com".to_string());
+        emails[2].is_flagged = true;
+        let mut app = App::with_client(None, emails);
+        let uids = |app: &App| {
+            app.rows
+                .iter()
+                .map(|row| app.emails[row.email]._uid)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(uids(&app), [1, 2, 3]);
+
+        app.cycle_sort();
+        assert_eq!(app.status.current(), Some("Sort: oldest first"));
+        assert_eq!(uids(&app), [3, 2, 1]);
+
+        app.cycle_sort();
+        assert_eq!(uids(&app), [3, 1, 2]);
+
+        app.cycle_sort();
+        assert_eq!(uids(&app), [2, 3, 1]);
+
+        app.cycle_sort();
+        assert_eq!(uids(&app), [2, 1, 3]);
+
+        app.cycle_sort();
+        assert_eq!(app.sort(), SortMode::FlaggedFirst);
+        assert_eq!(uids(&app), [3, 1, 2]);
+
+        // The selection follows the message, not the row.
+        app.select_email(2);
+        app.cycle_sort();
+        assert_eq!(app.sort(), SortMode::DateDesc);
+        assert_eq!(app.selected_email(), Some(2));
+    }
+
+    #[test]
+    fn test_export_index_writes_listed_messages() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let emails = vec![
+            unread_test_email(1, false, 0),
+            unread_test_email(2, true, 1),
+            unread_test_email(3, false, 2),
+        ];
+        let mut app = App::with_client(None, emails);
+        app.toggle_filter(Filter::Unread);
+
+        let path = dir.path().join("index.csv");
+        app.run_command(&format!(":export-index {}", path.display()))
+            .unwrap();
+        let csv = std::fs::read_to_string(&path).unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "date,from,subject,flags,uid,size");
+        assert!(lines[1..].iter().all(|line| line.contains(",Email ")));
+        assert_eq!(
+            app.status.current().unwrap(),
+            format!("Exported 2 messages to {}", path.display())
+        );
+    }
+
+    #[test]
+    fn test_save_attachment_keeps_existing_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let taken = dir.path().join("invoice.pdf");
+        std::fs::write(&taken, "old").unwrap();
+        let mut app = App::with_client(None, vec![]);
+        app.prompt = Some(Prompt {
+            label: "Save attachment as: ".to_string(),
+            input: taken.display().to_string(),
+            kind: PromptKind::SaveAttachment(Attachment {
+                filename: "invoice.pdf".to_string(),
+                mime_type: "application/pdf".to_string(),
+                data: b"new".to_vec(),
+            }),
+        });
+
+        app.prompt_submit();
+        assert_eq!(std::fs::read(&taken).unwrap(), b"old");
+        let numbered = dir.path().join("invoice (2).pdf");
+        assert_eq!(std::fs::read(&numbered).unwrap(), b"new");
+        assert_eq!(
+            app.status.current(),
+            Some(format!("Saved {}", numbered.display()).as_str())
+        );
+    }
+
+    #[test]
+    fn test_save_attachments_asks_over_budget() {
+        let mut emails = vec![
+            unread_test_email(1, false, 0),
+            unread_test_email(2, false, 1),
+        ];
+        for email in &mut emails {
+            email.size = 800 * 1024;
         }
+        let mut app = App::with_client(None, emails);
+        app.config.network.transfer_budget_kb = Some(1024);
+
+        app.run_command(":save-attachments ~/invoices").unwrap();
+        let prompt = app.prompt.as_ref().unwrap();
+        assert_eq!(
+            prompt.label,
+            "2 messages are 1.6 MiB, over the 1.0 MiB budget. Download? (y/N): "
+        );
+        assert!(matches!(
+            &prompt.kind,
+            PromptKind::ConfirmSaveAttachments(dir) if dir.ends_with("invoices")
+        ));
+
+        app.prompt_input('n');
+        app.prompt_submit();
+        assert!(app.prompt.is_none());
+        assert_eq!(app.status.current().unwrap(), "Download cancelled");
+
+        // Only the tagged message is downloaded, within the budget.
+        app.status.clear();
+        app.tagged.insert(1);
+        app.run_command(":save-attachments ~/invoices").unwrap();
+        assert!(app.prompt.is_none());
+        assert_eq!(
+            app.status.current().unwrap(),
+            "Error: Not connected to IMAP server"
+        );
+    }
 
-        let mut app = App::new(client.unwrap(), emails);
+    #[test]
+    fn test_screen_reader_announces_view_changes() {
+        let emails = vec![
+            unread_test_email(1, false, 0),
+            unread_test_email(2, true, 1),
+        ];
+        let mut app = App::with_client(None, emails);
+        app.announce_view();
+        assert!(app.status.is_empty());
 
-        // These should not panic on empty list
-        app.line_forward();
-        app.line_backward();
-        assert_eq!(app.list_state.selected(), None);
-        assert_eq!(app.scroll_offset, 0);
+        app.config.ui.screen_reader = true;
+        app.config.ui.two_line_below = Some(80);
+        app.set_list_area(60, 10);
+        assert!(!app.two_line_rows);
+
+        app.announce_view();
+        assert_eq!(app.status.current(), Some("INBOX: 2 messages, 1 unread"));
+
+        // Changes within a view are reported by the actions themselves.
+        app.status.clear();
+        app.toggle_read();
+        app.status.clear();
+        app.announce_view();
+        assert!(app.status.is_empty());
+
+        app.view_email();
+        app.status.clear();
+        app.announce_view();
+        assert!(
+            app.status
+                .current()
+                .unwrap()
+                .starts_with("Message from test@test.com: Email ")
+        );
+    }
+
+    #[test]
+    fn test_startup_commands_stop_at_failure() {
+        let mut app = App::with_client(None, vec![unread_test_email(1, false, 0)]);
+        app.config.startup_commands = vec![
+            ":limit".to_string(),
+            ":bogus".to_string(),
+            ":folder INBOX".to_string(),
+        ];
+        app.run_startup_commands();
+        assert_eq!(
+            app.status.current(),
+            Some("Startup command `:bogus` failed: Unknown command `:bogus`")
+        );
+        assert_eq!(app.limit_query(), None);
+
+        app.limit = Some(Limit {
+            query: "is:unread".to_string(),
+            uids: HashSet::from([2]),
+        });
+        app.rebuild_rows();
+        assert!(app.rows.is_empty());
+        app.run_command(":limit").unwrap();
+        assert_eq!(app.rows.len(), 1);
+    }
+
+    #[test]
+    fn test_step_through_search_results() {
+        let mut app = App::with_client(None, vec![unread_test_email(9, true, 0)]);
+        app.next_match(true);
+        assert_eq!(app.status.current(), Some("No search; press / to search"));
+
+        app.show_results(
+            "from:foo",
+            vec![
+                unread_test_email(3, true, 0),
+                unread_test_email(2, true, 1),
+                unread_test_email(1, true, 2),
+            ],
+        );
+        assert_eq!(app.search_query(), Some("from:foo"));
+        assert_eq!(app.selected_email(), Some(0));
+
+        app.status.clear();
+        app.next_match(false);
+        assert_eq!(app.selected_email(), Some(2));
+        assert_eq!(app.status.current(), Some("Search wrapped to bottom"));
+        app.status.clear();
+        app.next_match(true);
+        assert_eq!(app.selected_email(), Some(0));
+        assert_eq!(app.status.current(), Some("Search wrapped to top"));
+        app.next_match(true);
+        assert_eq!(app.selected_email(), Some(1));
+    }
+
+    #[test]
+    fn test_change_folder_prompt() {
+        let mut app = App::with_client(None, vec![]);
+        app.folder_choices = ["[Gmail]/Sent Mail", "[Gmail]/Spam"]
+            .map(|path| FolderChoice {
+                path: path.to_string(),
+                unread: 0,
+            })
+            .to_vec();
+        app.prompt = Some(Prompt {
+            label: "Open folder: ".to_string(),
+            input: "=se".to_string(),
+            kind: PromptKind::ChangeFolder,
+        });
+        let candidates = app.folder_candidates();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].path, "[Gmail]/Sent Mail");
+
+        app.prompt_input('x');
+        app.prompt_submit();
+        assert_eq!(app.status.current(), Some("No folder matches =sex"));
+        assert!(app.folder_candidates().is_empty());
+    }
+
+    #[test]
+    fn test_offline_queues_flag_changes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let cache = MessageCache::new(dir.path());
+        let emails = vec![
+            unread_test_email(2, false, 0),
+            unread_test_email(1, true, 1),
+        ];
+        cache.store_emails("INBOX", 5, &emails).unwrap();
+        cache
+            .store_body("INBOX", 5, 2, b"Subject: Hi\r\n\r\nCached body\r\n")
+            .unwrap();
+
+        let (mailbox, emails) = OfflineMailbox::open(cache, "INBOX").unwrap().unwrap();
+        let journal = Journal::new(dir.path().join("journal.jsonl"), "me@imap.gmail.com");
+        let mut app = App::offline(mailbox, emails).with_journal(journal);
+        assert!(app.is_offline());
+
+        // Cached bodies can be read, which marks them read once online.
+        app.view_email();
+        assert_eq!(app.emails[0].body.as_deref(), Some("Cached body\r\n"));
+        app.back_to_list();
+        app.toggle_flag();
+        assert!(app.emails[0].is_flagged);
+        let queued = app
+            .queued
+            .iter()
+            .map(|entry| (entry.uids.clone(), entry.op.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            queued,
+            [(vec![2], JournalOp::MarkRead), (vec![2], JournalOp::Flag)]
+        );
+        // Journaled too, in case the application stops before reconnecting.
+        let journal = app.journal.as_ref().unwrap();
+        assert_eq!(journal.pending().unwrap(), app.queued);
+        assert_eq!(app.queued[0].uid_validity, Some(5));
+
+        app.status.clear();
+        app.archive();
+        assert_eq!(
+            app.status.current(),
+            Some("Offline; only read and flag changes can be made")
+        );
+        assert_eq!(app.emails.len(), 2);
+
+        app.goto_last();
+        app.status.clear();
+        app.view_email();
+        assert_eq!(
+            app.status.current(),
+            Some("Offline; the message was not cached")
+        );
+    }
+
+    #[test]
+    fn test_send_entries_keeps_the_rest_pending() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let journal = Journal::new(dir.path().join("journal.jsonl"), "me@imap.gmail.com");
+        let mut app = App::with_client(None, vec![]).with_journal(journal);
+        let folder = |year: &str| JournalOp::MoveTo {
+            folder: format!("Archive/{}", year),
+        };
+        let entries = vec![
+            app.record(vec![1], folder("2024")),
+            app.record(vec![2], folder("2025")),
+        ];
+
+        // The first move fails, so the second is never sent.
+        assert!(app.send_entries(&entries).is_err());
+        let journal = app.journal.as_ref().unwrap();
+        assert_eq!(journal.pending().unwrap(), entries[1..]);
+    }
+
+    #[test]
+    fn test_offline_search() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let cache = MessageCache::new(dir.path());
+        let emails = vec![
+            unread_test_email(2, false, 0),
+            unread_test_email(1, true, 1),
+        ];
+        cache.store_emails("INBOX", 5, &emails).unwrap();
+        cache
+            .store_body("INBOX", 5, 1, b"Subject: x\r\n\r\nThe invoice is attached.")
+            .unwrap();
+        let (mailbox, emails) = OfflineMailbox::open(cache, "INBOX").unwrap().unwrap();
+        let mut config = Config::default();
+        config.cache.full_text = true;
+        let mut app = App::offline(mailbox, emails).with_config(config);
+
+        app.search("invoice").unwrap();
+        assert_eq!(app.emails.len(), 1);
+        assert_eq!(app.emails[0]._uid, 1);
+        assert_eq!(app.search.as_deref(), Some("invoice"));
+        // Only the cache can answer a query with fields.
+        assert!(app.search("from:alice").is_err());
+
+        // Leaving the results keeps the changes queued meanwhile.
+        app.toggle_read();
+        app.search("").unwrap();
+        assert_eq!(app.search, None);
+        assert_eq!(app.emails.len(), 2);
+        assert!(app.emails.iter().all(|e| !e.is_read));
+    }
+
+    #[test]
+    fn test_folder_switch_forgets_loading() {
+        let mut app = App::with_client(None, vec![unread_test_email(1, false, 0)]);
+        app.loading.insert(1);
+        app.fetched_parents.insert(1);
+        app.translation_job = Some(
+            TranslationJob::start(1, "cat", String::new(), "es", "en", Duration::ZERO).unwrap(),
+        );
+        assert!(app.loading());
+
+        // UID 1 of the next folder is another message.
+        app.clear_folder_state();
+        assert!(!app.loading());
+        assert!(!app.translating());
+        assert!(app.fetched_parents.is_empty());
+        assert_eq!(app.body_placeholder(0), "(Body not loaded)");
+    }
+
+    #[test]
+    fn test_distrust_changed_certificate() {
+        let mut app = App::with_client(None, vec![unread_test_email(1, false, 0)]);
+        app.reconnector = Some(Reconnector::new(|| bail!("offline")));
+        app.prompt = Some(Prompt {
+            label: String::new(),
+            input: "n".to_string(),
+            kind: PromptKind::ConfirmCertificate(CertificateChanged {
+                host: "imap.example.com:993".to_string(),
+                known: "SHA256:AA".to_string(),
+                presented: "SHA256:BB".to_string(),
+            }),
+        });
+        app.prompt_submit();
+        assert!(app.reconnector.is_none());
+        assert_eq!(
+            app.status.current(),
+            Some("Kept the known certificate; staying offline until restarted")
+        );
+    }
+
+    #[test]
+    fn test_tag_and_pick_label() {
+        let mut app = App::with_client(
+            None,
+            vec![
+                unread_test_email(3, true, 0),
+                unread_test_email(2, true, 1),
+                unread_test_email(1, true, 2),
+            ],
+        );
+        app.toggle_tag();
+        app.toggle_tag();
+        assert_eq!(app.tagged_count(), 2);
+        assert_eq!(app.selected_email(), Some(2));
+        assert!(app.is_tagged(&app.emails[0]) && !app.is_tagged(&app.emails[2]));
+        app.goto_first();
+        app.toggle_tag();
+        assert_eq!(app.tagged_emails(), [1]);
+
+        // The picker opens for the tagged messages; folders cannot be listed
+        // without a connection, but a label can still be typed.
+        app.label();
+        let prompt = app.prompt.as_ref().unwrap();
+        assert_eq!(prompt.label, "Label (1 tagged): ");
+        assert!(matches!(prompt.kind, PromptKind::Label));
+
+        let choices = ["Work", "Work/Old", "Receipts"]
+            .map(|path| FolderChoice {
+                path: path.to_string(),
+                unread: 0,
+            })
+            .to_vec();
+        assert_eq!(
+            picked_label(&choices, " rece "),
+            Some("Receipts".to_string())
+        );
+        assert_eq!(picked_label(&choices, "work"), Some("Work".to_string()));
+        assert_eq!(picked_label(&choices, "Travel"), Some("Travel".to_string()));
+        assert_eq!(picked_label(&choices, "  "), None);
+    }
+
+    #[test]
+    fn test_tag_pattern_bulk_ops() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let cache = MessageCache::new(dir.path());
+        let emails = vec![
+            unread_test_email(3, false, 0),
+            unread_test_email(2, true, 1),
+            unread_test_email(1, false, 2),
+        ];
+        cache.store_emails("INBOX", 5, &emails).unwrap();
+        let (mailbox, emails) = OfflineMailbox::open(cache, "INBOX").unwrap().unwrap();
+        let mut app = App::offline(mailbox, emails);
+
+        app.tag_pattern("~N").unwrap();
+        assert_eq!(app.tagged_emails(), [0, 2]);
+        assert_eq!(app.status.current(), Some("Tagged 2 messages"));
+
+        // Operations apply to all tagged messages at once, not the selected
+        // one.
+        app.toggle_read();
+        app.toggle_flag();
+        assert!(app.emails.iter().all(|e| e.is_read));
+        assert!(app.emails[0].is_flagged && app.emails[2].is_flagged);
+        assert!(!app.emails[1].is_flagged);
+        let queued = app
+            .queued
+            .iter()
+            .map(|entry| (entry.uids.clone(), entry.op.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            queued,
+            [
+                (vec![3, 1], JournalOp::MarkRead),
+                (vec![3, 1], JournalOp::Flag)
+            ]
+        );
+
+        // Trashing them is confirmed first.
+        app.status.clear();
+        app.delete();
+        assert_eq!(
+            app.prompt.as_ref().unwrap().label,
+            "Move 2 tagged messages to the trash? (y/N): "
+        );
+        app.prompt_submit();
+        assert_eq!(app.status.current(), Some("Cancelled"));
+        assert_eq!(app.emails.len(), 3);
+
+        app.tag_pattern(" ").unwrap();
+        assert_eq!(app.tagged_count(), 0);
+    }
+
+    #[test]
+    fn test_toggle_text_part() {
+        let mut app = App::with_client(
+            None,
+            vec![unread_test_email(2, true, 0), unread_test_email(1, true, 1)],
+        );
+        app.emails[0].body = Some("plain".to_string());
+        app.alternatives.insert(
+            2,
+            (
+                Alternatives {
+                    plain: "plain".to_string(),
+                    html: "html".to_string(),
+                },
+                TextPart::Plain,
+            ),
+        );
+
+        app.mode = ViewMode::Detail(0);
+        assert_eq!(app.shown_part(), Some(TextPart::Plain));
+        app.toggle_text_part();
+        assert_eq!(app.shown_part(), Some(TextPart::Html));
+        assert_eq!(app.emails[0].body.as_deref(), Some("html"));
+        app.toggle_text_part();
+        assert_eq!(app.emails[0].body.as_deref(), Some("plain"));
+
+        app.mode = ViewMode::Detail(1);
+        assert_eq!(app.shown_part(), None);
+        app.toggle_text_part();
+        assert_eq!(
+            app.status.current(),
+            Some("No other version of this message")
+        );
+    }
+
+    #[test]
+    fn test_apply_config() {
+        let mut app = App::with_client(None, vec![]);
+        let mut config = Config::default();
+        config.ui.index_avatars = true;
+        app.apply_config(config.clone());
+        assert!(app.config.ui.index_avatars);
+        assert_eq!(app.status.current(), Some("Config reloaded"));
+
+        // Credentials only change on restart.
+        config.gmail.username = "other@gmail.com".to_string();
+        app.status.clear();
+        app.apply_config(config.clone());
+        assert_eq!(app.config.gmail.username, "");
+        assert_eq!(
+            app.status.current(),
+            Some("Config reloaded; restart to apply [gmail]")
+        );
+
+        // `--fetch-limit` outlasts a reload.
+        app.imap_overrides.fetch_limit = Some(25);
+        config.imap.fetch_limit = 100;
+        app.apply_config(config.clone());
+        assert_eq!(app.config.imap.fetch_limit, 25);
+
+        // A changed sort reorders the list.
+        config.ui.sort = SortMode::DateAsc;
+        app.apply_config(config);
+        assert_eq!(app.sort(), SortMode::DateAsc);
+        assert!(app.older_at_top());
     }
 }
@@ -0,0 +1,135 @@
+//! Colored initials blocks identifying senders.
+//!
+//! Each sender gets a short block of initials whose background color is
+//! derived from a hash of the address, so the same sender always looks the
+//! same across sessions.
+
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::Span,
+};
+
+use crate::imap_client::NameAddr;
+
+/// Background colors avatars are drawn with, all readable with black text.
+const PALETTE: [Color; 10] = [
+    Color::Rgb(0xe5, 0x73, 0x73),
+    Color::Rgb(0xf0, 0x62, 0x92),
+    Color::Rgb(0xba, 0x68, 0xc8),
+    Color::Rgb(0x95, 0x75, 0xcd),
+    Color::Rgb(0x79, 0x86, 0xcb),
+    Color::Rgb(0x64, 0xb5, 0xf6),
+    Color::Rgb(0x4d, 0xb6, 0xac),
+    Color::Rgb(0x81, 0xc7, 0x84),
+    Color::Rgb(0xdc, 0xe7, 0x75),
+    Color::Rgb(0xff, 0xb7, 0x4d),
+];
+
+/// Returns the `[tag]` block identifying the account, drawn in a color
+/// derived from its address.
+pub(crate) fn account_tag_span(username: &str, tag: &str) -> Span<'static> {
+    let account = NameAddr {
+        name: None,
+        email: Some(username.to_string()),
+    };
+    Span::styled(
+        format!("[{}]", tag),
+        Style::default()
+            .fg(Color::Black)
+            .bg(avatar_color(&account))
+            .add_modifier(Modifier::BOLD),
+    )
+}
+
+/// Returns up to two uppercase initials for a sender.
+///
+/// Uses the first and last words of the display name, falling back to the
+/// parts of the address's local part (split on `.`, `_`, `-`, `+`).
+pub(crate) fn initials(addr: &NameAddr) -> String {
+    let words = match (&addr.name, &addr.email) {
+        (Some(name), _) if name.chars().any(char::is_alphanumeric) => name
+            .split_whitespace()
+            .filter(|w| w.chars().any(char::is_alphanumeric))
+            .collect::<Vec<_>>(),
+        (_, Some(email)) => email
+            .split('@')
+            .next()
+            .unwrap_or("")
+            .split(['.', '_', '-', '+'])
+            .filter(|w| !w.is_empty())
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    let first_letter = |word: &str| word.chars().find(|c| c.is_alphanumeric());
+    let letters = match words.as_slice() {
+        [] => return "?".to_string(),
+        [only] => vec![first_letter(only)],
+        [first, .., last] => vec![first_letter(first), first_letter(last)],
+    };
+    letters
+        .into_iter()
+        .flatten()
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+/// Picks a palette color for a sender from its address (or name when the
+/// address is unknown).
+pub(crate) fn avatar_color(addr: &NameAddr) -> Color {
+    let key = addr
+        .email
+        .as_deref()
+        .or(addr.name.as_deref())
+        .unwrap_or("")
+        .to_lowercase();
+
+    // FNV-1a, stable across runs and Rust versions.
+    let hash = key.bytes().fold(0xcbf29ce484222325u64, |hash, b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+    });
+    PALETTE[(hash % PALETTE.len() as u64) as usize]
+}
+
+/// Returns the avatar block for a sender, padded to a fixed width.
+pub(crate) fn avatar_span(addr: &NameAddr) -> Span<'static> {
+    Span::styled(
+        format!(" {:<2} ", initials(addr)),
+        Style::default()
+            .fg(Color::Black)
+            .bg(avatar_color(addr))
+            .add_modifier(Modifier::BOLD),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(name: Option<&str>, email: Option<&str>) -> NameAddr {
+        NameAddr {
+            name: name.map(str::to_string),
+            email: email.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_initials() {
+        assert_eq!(
+            initials(&addr(Some("Ada King Lovelace"), Some("ada@example.com"))),
+            "AL"
+        );
+        assert_eq!(initials(&addr(Some("ümit"), None)), "Ü");
+        assert_eq!(initials(&addr(None, Some("john.smith@example.com"))), "JS");
+        assert_eq!(initials(&addr(Some("\"\""), Some("noreply@x.com"))), "N");
+        assert_eq!(initials(&addr(None, None)), "?");
+    }
+
+    #[test]
+    fn test_avatar_color_is_stable() {
+        let a = addr(Some("Alice"), Some("alice@example.com"));
+        let b = addr(Some("Alice Smith"), Some("ALICE@example.com"));
+        assert_eq!(avatar_color(&a), avatar_color(&b));
+        assert!(PALETTE.contains(&avatar_color(&a)));
+    }
+}
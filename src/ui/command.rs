@@ -0,0 +1,287 @@
+//! Ex-style commands such as `:folder INBOX`.
+//!
+//! Commands are typed at the `:` prompt, with history and tab completion,
+//! or run from the `startup_commands` config list after launch, so the
+//! initial view can be scripted.
+
+use anyhow::{Result, bail};
+
+use crate::config::SortMode;
+use crate::folders::InboxTab;
+
+/// Command names offered by tab completion.
+const NAMES: [&str; 15] = [
+    "cache-compact",
+    "cache-stats",
+    "export-index",
+    "folder",
+    "goto",
+    "limit",
+    "mailbox",
+    "save-attachments",
+    "save-maildir",
+    "search",
+    "search-all",
+    "sort",
+    "tab",
+    "translate",
+    "version",
+];
+
+/// Orders accepted by `:sort`, by name.
+const SORT_ORDERS: [(&str, SortMode); 6] = [
+    ("date", SortMode::DateDesc),
+    ("date-asc", SortMode::DateAsc),
+    ("sender", SortMode::Sender),
+    ("subject", SortMode::Subject),
+    ("unread", SortMode::UnreadFirst),
+    ("flagged", SortMode::FlaggedFirst),
+];
+
+/// A parsed command.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Command {
+    /// Open a folder, e.g. `:folder [Gmail]/Sent Mail`.
+    Folder(String),
+    /// Show only the messages matching a Gmail search, e.g.
+    /// `:limit is:unread`. An empty query shows all messages again.
+    Limit(String),
+    /// Show one of Gmail's inbox tabs, e.g. `:tab promotions`.
+    Tab(InboxTab),
+    /// Report how much space the local cache takes, `:cache-stats`.
+    CacheStats,
+    /// Remove unneeded files from the local cache, `:cache-compact`.
+    CacheCompact,
+    /// Write the listed messages to a CSV or JSON file, e.g.
+    /// `:export-index ~/mail.csv`.
+    ExportIndex(String),
+    /// Move to a message by its number in the list, e.g. `:42` or
+    /// `:goto 42`.
+    Goto(usize),
+    /// Search the current folder on the server, e.g. `:search from:foo`.
+    /// An empty query shows the folder again.
+    Search(String),
+    /// Search every folder, e.g. `:search-all invoice`. An empty query
+    /// shows the last results again.
+    SearchAll(String),
+    /// Order the list, e.g. `:sort sender`.
+    Sort(SortMode),
+    /// Save the attachments of the tagged messages, or every listed one,
+    /// into one directory, e.g. `:save-attachments ~/invoices`.
+    SaveAttachments(String),
+    /// Save every listed message into a Maildir, e.g.
+    /// `:save-maildir ~/mail/project`.
+    SaveMaildir(String),
+    /// Show the build information and whether a newer release exists,
+    /// `:version`.
+    Version,
+    /// Translate the message being read, e.g. `:translate` or
+    /// `:translate de` for another language than the configured one.
+    Translate(String),
+}
+
+impl Command {
+    /// Parses a command line. The leading `:` is optional.
+    pub(crate) fn parse(line: &str) -> Result<Self> {
+        let line = line.trim();
+        let line = line.strip_prefix(':').unwrap_or(line);
+        let (name, arg) = match line.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (line, ""),
+        };
+
+        match name {
+            "folder" | "mailbox" if arg.is_empty() => bail!("`:{}` needs a folder name", name),
+            "folder" | "mailbox" => Ok(Command::Folder(arg.to_string())),
+            "limit" => Ok(Command::Limit(arg.to_string())),
+            "search" => Ok(Command::Search(arg.to_string())),
+            "search-all" => Ok(Command::SearchAll(arg.to_string())),
+            "sort" => match SORT_ORDERS.iter().find(|(order, _)| *order == arg) {
+                Some((_, sort)) => Ok(Command::Sort(*sort)),
+                None => bail!(
+                    "Unknown order `{}`; expected one of {}",
+                    arg,
+                    SORT_ORDERS.map(|(order, _)| order).join(", ")
+                ),
+            },
+            "goto" => match arg.parse() {
+                Ok(number) => Ok(Command::Goto(number)),
+                Err(_) => bail!("`:goto` needs a message number"),
+            },
+            "tab" => match InboxTab::from_name(arg) {
+                Some(tab) => Ok(Command::Tab(tab)),
+                None => bail!(
+                    "Unknown tab `{}`; expected one of {}",
+                    arg,
+                    InboxTab::ALL.map(InboxTab::name).join(", ")
+                ),
+            },
+            "cache-stats" => Ok(Command::CacheStats),
+            "cache-compact" => Ok(Command::CacheCompact),
+            "version" => Ok(Command::Version),
+            "translate" => Ok(Command::Translate(arg.to_string())),
+            "export-index" if arg.is_empty() => bail!("`:export-index` needs a file name"),
+            "export-index" => Ok(Command::ExportIndex(arg.to_string())),
+            "save-attachments" if arg.is_empty() => bail!("`:save-attachments` needs a directory"),
+            "save-attachments" => Ok(Command::SaveAttachments(arg.to_string())),
+            "save-maildir" if arg.is_empty() => bail!("`:save-maildir` needs a directory"),
+            "save-maildir" => Ok(Command::SaveMaildir(arg.to_string())),
+            _ if name.bytes().all(|b| b.is_ascii_digit()) && !name.is_empty() => {
+                Ok(Command::Goto(name.parse()?))
+            }
+            "" => bail!("Empty command"),
+            _ => bail!("Unknown command `:{}`", name),
+        }
+    }
+}
+
+/// Returns the completions of a partly typed command line: command names
+/// for the first word, then folder names, tabs or sort orders for the
+/// argument of the commands taking them.
+pub(crate) fn complete(line: &str, folders: &[String]) -> Vec<String> {
+    let (colon, line) = match line.strip_prefix(':') {
+        Some(rest) => (":", rest),
+        None => ("", line),
+    };
+    let Some((name, arg)) = line.split_once(' ') else {
+        return NAMES
+            .iter()
+            .filter(|n| n.starts_with(line))
+            .map(|n| format!("{}{} ", colon, n))
+            .collect();
+    };
+    let arg = arg.trim_start();
+    let candidates: Vec<&str> = match name {
+        "folder" | "mailbox" => folders.iter().map(String::as_str).collect(),
+        "tab" => InboxTab::ALL.map(InboxTab::name).to_vec(),
+        "sort" => SORT_ORDERS.map(|(order, _)| order).to_vec(),
+        _ => Vec::new(),
+    };
+    candidates
+        .into_iter()
+        .filter(|c| c.to_lowercase().starts_with(&arg.to_lowercase()))
+        .map(|c| format!("{}{} {}", colon, name, c))
+        .collect()
+}
+
+/// Returns the longest prefix shared by all completions.
+pub(crate) fn common_prefix(completions: &[String]) -> String {
+    let Some((first, rest)) = completions.split_first() else {
+        return String::new();
+    };
+    let mut prefix = first.as_str();
+    for completion in rest {
+        let len = prefix
+            .char_indices()
+            .zip(completion.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(prefix.len().min(completion.len()), |((i, _), _)| i);
+        prefix = &prefix[..len];
+    }
+    prefix.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Command::parse(":folder [Gmail]/Sent Mail").unwrap(),
+            Command::Folder("[Gmail]/Sent Mail".to_string())
+        );
+        assert_eq!(
+            Command::parse("mailbox INBOX").unwrap(),
+            Command::Folder("INBOX".to_string())
+        );
+        assert_eq!(
+            Command::parse(":limit  is:unread from:foo ").unwrap(),
+            Command::Limit("is:unread from:foo".to_string())
+        );
+        assert_eq!(
+            Command::parse(":limit").unwrap(),
+            Command::Limit(String::new())
+        );
+
+        assert_eq!(
+            Command::parse(":tab updates").unwrap(),
+            Command::Tab(InboxTab::Updates)
+        );
+
+        assert_eq!(Command::parse(":cache-stats").unwrap(), Command::CacheStats);
+        assert_eq!(Command::parse(":version").unwrap(), Command::Version);
+        assert_eq!(
+            Command::parse(":translate de").unwrap(),
+            Command::Translate("de".to_string())
+        );
+        assert_eq!(
+            Command::parse("cache-compact").unwrap(),
+            Command::CacheCompact
+        );
+
+        assert_eq!(
+            Command::parse(":export-index ~/mail volume.json").unwrap(),
+            Command::ExportIndex("~/mail volume.json".to_string())
+        );
+
+        assert_eq!(Command::parse(":42").unwrap(), Command::Goto(42));
+        assert_eq!(Command::parse(":goto 120").unwrap(), Command::Goto(120));
+        assert!(Command::parse(":goto").is_err());
+
+        assert_eq!(
+            Command::parse(":search from:foo").unwrap(),
+            Command::Search("from:foo".to_string())
+        );
+        assert_eq!(
+            Command::parse(":sort date").unwrap(),
+            Command::Sort(SortMode::DateDesc)
+        );
+        assert!(Command::parse(":sort size").is_err());
+        assert_eq!(
+            Command::parse(":search-all invoice 2024").unwrap(),
+            Command::SearchAll("invoice 2024".to_string())
+        );
+
+        assert_eq!(
+            Command::parse(":save-attachments ~/invoices").unwrap(),
+            Command::SaveAttachments("~/invoices".to_string())
+        );
+
+        assert!(Command::parse(":folder").is_err());
+        assert!(Command::parse(":export-index").is_err());
+        assert!(Command::parse(":save-attachments").is_err());
+        assert_eq!(
+            Command::parse(":save-maildir ~/mail/project").unwrap(),
+            Command::SaveMaildir("~/mail/project".to_string())
+        );
+        assert!(Command::parse(":save-maildir").is_err());
+        assert!(Command::parse(":tab forums").is_err());
+        assert!(Command::parse(":").is_err());
+        let err = Command::parse(":frobnicate x").unwrap_err();
+        assert_eq!(err.to_string(), "Unknown command `:frobnicate`");
+    }
+
+    #[test]
+    fn test_complete() {
+        let folders = ["INBOX".to_string(), "Sent".to_string(), "Spam".to_string()];
+        assert_eq!(complete("sea", &folders), ["search ", "search-all "]);
+        assert_eq!(
+            complete(":sa", &folders),
+            [":save-attachments ", ":save-maildir "]
+        );
+        assert_eq!(
+            complete("mailbox s", &folders),
+            ["mailbox Sent", "mailbox Spam"]
+        );
+        assert_eq!(
+            complete("sort da", &folders),
+            ["sort date", "sort date-asc"]
+        );
+        assert!(complete("search fr", &folders).is_empty());
+
+        let completions = complete("sa", &folders);
+        assert_eq!(common_prefix(&completions), "save-");
+        assert_eq!(common_prefix(&complete("sort da", &folders)), "sort date");
+    }
+}
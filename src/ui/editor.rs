@@ -0,0 +1,62 @@
+//! External editor integration for composing messages.
+//!
+//! Suspends the terminal UI, opens the draft in `$VISUAL`/`$EDITOR`, and
+//! restores the UI once the editor exits.
+
+use anyhow::{Context, Result, bail};
+use crossterm::{
+    execute,
+    terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
+};
+use ratatui::{Terminal, backend::Backend};
+use std::{fs, io, process::Command};
+
+use crate::compose::Draft;
+
+/// Returns the user's preferred editor command.
+fn editor_command() -> String {
+    std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+/// Opens the draft in the external editor and updates it with the result.
+///
+/// A reply's language is passed in `RUTT_LANGUAGE` as an ISO 639-3 code,
+/// for the editor to pick a spelling dictionary.
+pub(crate) fn edit_draft<B: Backend>(terminal: &mut Terminal<B>, draft: &mut Draft) -> Result<()> {
+    let path = std::env::temp_dir().join(format!("rutt-draft-{}.eml", std::process::id()));
+    fs::write(&path, draft.to_editor_text())
+        .with_context(|| format!("Failed to write draft to {:?}", path))?;
+
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen)?;
+
+    let editor = editor_command();
+    let mut parts = editor.split_whitespace();
+    let mut command = Command::new(parts.next().unwrap_or("vi"));
+    command.args(parts).arg(&path);
+    if let Some(language) = &draft.language {
+        command.env("RUTT_LANGUAGE", language);
+    }
+    let status = command.status();
+
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    enable_raw_mode()?;
+    terminal.clear()?;
+
+    let status = status.with_context(|| format!("Failed to run editor {:?}", editor))?;
+    if !status.success() {
+        let _ = fs::remove_file(&path);
+        bail!("Editor exited with {}", status);
+    }
+
+    let text =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read draft {:?}", path))?;
+    let _ = fs::remove_file(&path);
+    draft.update_from_editor_text(&text);
+
+    Ok(())
+}
@@ -4,75 +4,394 @@
 //! and application control.
 
 use anyhow::Result;
-use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::{execute, terminal::SetTitle};
 use ratatui::{Terminal, backend::Backend};
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use crate::clipboard;
+use crate::config::NewMailAlert;
 use crate::ui::app::{App, ViewMode};
+use crate::ui::editor::edit_draft;
+use crate::ui::keymap::{Action, ChordMatch, chords, lookup, lookup_chord, match_chord};
 use crate::ui::render::ui;
+use crate::updates::UpdateStatus;
+
+/// How often the config file and new mail notifications are checked while
+/// waiting for input.
+const BACKGROUND_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How often the screen is redrawn while a body is being fetched, to animate
+/// the spinner and show the body as soon as it arrives.
+const LOADING_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often the screen is redrawn while the status line shows a notice, so
+/// it goes away on time.
+const STATUS_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long the screen stays inverted when flashing for new mail.
+const FLASH_DURATION: Duration = Duration::from_millis(100);
 
 /// Main application event loop handling keyboard input and UI updates.
 ///
 /// Continuously renders the UI and processes keyboard events until the user
 /// quits. Supports navigation in list view and switching between views.
-pub fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> Result<()> {
+pub fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> Result<()> {
+    let mut title = None;
     loop {
-        terminal.draw(|f| ui(f, &mut app))?;
-
-        if let Event::Key(key) = event::read()? {
-            if key.kind == KeyEventKind::Press {
-                match app.mode {
-                    ViewMode::List => match key.code {
-                        KeyCode::Char('j') | KeyCode::Down => app.next(),
-                        KeyCode::Char('n') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                            app.next()
-                        }
-                        KeyCode::Char('k') | KeyCode::Up => app.previous(),
-                        KeyCode::Char('p') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                            app.previous()
-                        }
-                        KeyCode::Char('f') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                            app.page_forward()
-                        }
-                        KeyCode::Char('b') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                            app.page_backward()
-                        }
-                        KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                            app.half_page_forward()
-                        }
-                        KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                            app.half_page_backward()
-                        }
-                        KeyCode::Char('e') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                            app.line_forward()
-                        }
-                        KeyCode::Char('y') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                            app.line_backward()
-                        }
-                        KeyCode::Char('H') => app.goto_page_top(),
-                        KeyCode::Char('M') => app.goto_page_middle(),
-                        KeyCode::Char('L') => app.goto_page_bottom(),
-                        KeyCode::Enter => app.view_email(),
-                        KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
-                        _ => {}
-                    },
-                    ViewMode::Detail(_) => match key.code {
-                        KeyCode::Char('j') | KeyCode::Down => app.detail_scroll_down(),
-                        KeyCode::Char('k') | KeyCode::Up => app.detail_scroll_up(),
-                        KeyCode::Char('n') | KeyCode::Char('e')
-                            if key.modifiers.contains(KeyModifiers::CONTROL) =>
-                        {
-                            app.detail_line_forward()
-                        }
-                        KeyCode::Char('p') | KeyCode::Char('y')
-                            if key.modifiers.contains(KeyModifiers::CONTROL) =>
-                        {
-                            app.detail_line_backward()
-                        }
-                        KeyCode::Char('q') | KeyCode::Esc => app.back_to_list(),
-                        _ => {}
-                    },
+        if app.should_quit {
+            return Ok(());
+        }
+
+        if app.editor_requested {
+            app.editor_requested = false;
+            if let Some(draft) = app.draft.as_mut()
+                && let Err(e) = edit_draft(terminal, draft)
+            {
+                app.notify(format!("Error: {e:#}"));
+            }
+        }
+
+        app.poll_loader();
+        app.poll_folder_search();
+        app.poll_attachment_save();
+        app.poll_update_check();
+        app.poll_translation();
+        app.receive_bodies();
+        app.announce_view();
+        app.status.tick(Instant::now());
+        terminal.draw(|f| ui(f, app))?;
+        if app.config.ui.window_title {
+            let current = app.window_title();
+            if title.as_ref() != Some(&current) {
+                execute!(io::stdout(), SetTitle(&current))?;
+                title = Some(current);
+            }
+        }
+        if let Some(alert) = app.take_new_mail_alert() {
+            alert_new_mail(alert)?;
+        }
+        if let Some(text) = app.take_clipboard()
+            && let Err(e) = clipboard::copy(&text, app.config.ui.clipboard_command.as_deref())
+        {
+            app.notify(format!("Failed to copy: {:#}", e));
+        }
+
+        // Load older mail only now, so the "Loading" status shows meanwhile.
+        if app.older_requested {
+            app.load_older();
+            continue;
+        }
+
+        // While waiting for a key, drop an incomplete key sequence once it
+        // times out, pick up changes to the config file and new mail, and
+        // redraw for fetched bodies.
+        let background_poll = (app.watching_config() || app.watching_mail() || app.is_offline())
+            .then_some(BACKGROUND_POLL_INTERVAL);
+        let loading_poll = (app.loading()
+            || app.peeking()
+            || app.load_stage().is_some()
+            || app.folder_search_progress().is_some()
+            || app.attachment_save_progress().is_some()
+            || app.translating()
+            || app.update_status() == Some(&UpdateStatus::Checking))
+        .then_some(LOADING_POLL_INTERVAL);
+        let status_poll = (!app.status.is_empty()).then_some(STATUS_POLL_INTERVAL);
+        let wait = [
+            app.pending_timeout(),
+            app.warm_up_timeout(),
+            background_poll,
+            loading_poll,
+            status_poll,
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+        if let Some(wait) = wait
+            && !event::poll(wait)?
+        {
+            if app.pending_timeout().is_some_and(|t| t.is_zero()) {
+                let lone = lone_key_action(app);
+                app.clear_pending_keys();
+                if let Some(action) = lone {
+                    perform(app, action);
+                }
+            }
+            app.reload_config_if_changed();
+            app.reconnect_if_online();
+            app.refresh_if_mail_changed();
+            app.warm_up_if_idle();
+            continue;
+        }
+
+        if let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            app.note_activity();
+            if app.prompt.is_some() {
+                match key.code {
+                    KeyCode::Enter => app.prompt_submit(),
+                    KeyCode::Esc => app.prompt_cancel(),
+                    KeyCode::Backspace => app.prompt_backspace(),
+                    KeyCode::Up => app.prompt_history_previous(),
+                    KeyCode::Down => app.prompt_history_next(),
+                    KeyCode::Tab => app.prompt_complete(),
+                    KeyCode::Char(c) => app.prompt_input(c),
+                    _ => {}
+                }
+                continue;
+            }
+            if app.picker_typing() {
+                match key.code {
+                    KeyCode::Enter => app.picker_accept(),
+                    KeyCode::Esc => app.picker_cancel(),
+                    KeyCode::Backspace => app.picker_backspace(),
+                    KeyCode::Down => app.picker_next(),
+                    KeyCode::Up => app.picker_previous(),
+                    KeyCode::Char(c) => app.picker_input(c),
+                    _ => {}
+                }
+                continue;
+            }
+
+            let bindings = app.bindings();
+            match feed_chord(app, &chords(bindings), &key) {
+                ChordStep::Complete(chord) => {
+                    if let Some(action) = lookup_chord(bindings, chord) {
+                        perform(app, action);
+                    }
+                }
+                ChordStep::Consumed => {}
+                ChordStep::Unhandled => {
+                    if let Some(action) = lookup(bindings, &key) {
+                        perform(app, action);
+                    }
                 }
             }
         }
     }
 }
+
+/// Rings the terminal bell or flashes the screen for new mail.
+fn alert_new_mail(alert: NewMailAlert) -> Result<()> {
+    let mut stdout = io::stdout();
+    match alert {
+        NewMailAlert::Off => {}
+        NewMailAlert::Bell => write!(stdout, "\x07")?,
+        NewMailAlert::Flash => {
+            // Reverse video (DECSCNM) on, then off again.
+            write!(stdout, "\x1b[?5h")?;
+            stdout.flush()?;
+            thread::sleep(FLASH_DURATION);
+            write!(stdout, "\x1b[?5l")?;
+        }
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Performs a keymap action.
+fn perform(app: &mut App, action: Action) {
+    // A number typed before `G` or `gg` jumps to that message, as in mutt.
+    if let Some(number) = app.count.take()
+        && matches!(action, Action::First | Action::Last)
+    {
+        app.goto_number(number);
+        return;
+    }
+    match action {
+        Action::Next => app.next(),
+        Action::Previous => app.previous(),
+        Action::First => app.goto_first(),
+        Action::Last => app.goto_last(),
+        Action::PageForward => app.page_forward(),
+        Action::PageBackward => app.page_backward(),
+        Action::HalfPageForward => app.half_page_forward(),
+        Action::HalfPageBackward => app.half_page_backward(),
+        Action::LineForward => app.line_forward(),
+        Action::LineBackward => app.line_backward(),
+        Action::PageTop => app.goto_page_top(),
+        Action::PageMiddle => app.goto_page_middle(),
+        Action::PageBottom => app.goto_page_bottom(),
+        Action::NextUnread => app.next_unread(),
+        Action::NextUnreadFolder => app.next_unread_folder(),
+        Action::ToggleThreaded => app.toggle_threaded(),
+        Action::ToggleThread => app.toggle_thread(),
+        Action::ToggleAllThreads => app.toggle_all_threads(),
+        Action::ToggleThreadOrder => app.toggle_thread_order(),
+        Action::CycleSort => app.cycle_sort(),
+        Action::ToggleRead => app.toggle_read(),
+        Action::ToggleFlag => app.toggle_flag(),
+        Action::Archive => app.archive(),
+        Action::Label => app.label(),
+        Action::Unlabel => app.unlabel(),
+        Action::ToggleTag => app.toggle_tag(),
+        Action::TagPattern => app.start_tag_pattern(),
+        Action::Delete => app.delete(),
+        Action::Move => app.move_messages(),
+        Action::Search => app.start_search(),
+        Action::Command => app.start_command(),
+        Action::ShowHelp => app.show_help(),
+        Action::HelpScrollDown => app.scroll_help(1, 0),
+        Action::HelpScrollUp => app.scroll_help(-1, 0),
+        Action::HelpPageDown => app.scroll_help(0, 1),
+        Action::HelpPageUp => app.scroll_help(0, -1),
+        Action::CloseHelp => app.close_help(),
+        Action::NextMatch => app.next_match(true),
+        Action::PreviousMatch => app.next_match(false),
+        Action::ViewEmail => app.view_email(),
+        Action::ChangeFolder => app.change_folder(),
+        Action::OpenTab(tab) => app.open_tab(tab),
+        Action::ToggleFilter(filter) => app.toggle_filter(filter),
+        Action::ToggleFocus => app.toggle_focus(),
+        Action::ClearFilters => app.clear_filters(),
+        Action::LimitPattern => app.start_pattern(),
+        Action::ShowStats => app.show_stats(),
+        Action::ShowCleanup => app.show_cleanup(),
+        Action::ResultNext => app.result_next(),
+        Action::ResultPrevious => app.result_previous(),
+        Action::OpenResult => app.open_result(),
+        Action::Quit => app.should_quit = true,
+        Action::DetailScrollDown => app.detail_scroll_down(),
+        Action::DetailScrollUp => app.detail_scroll_up(),
+        Action::DetailHalfPageDown => app.detail_half_page_down(),
+        Action::DetailHalfPageUp => app.detail_half_page_up(),
+        Action::DetailTop => app.detail_top(),
+        Action::DetailBottom => app.detail_bottom(),
+        Action::DetailLineForward => app.detail_line_forward(),
+        Action::DetailLineBackward => app.detail_line_backward(),
+        Action::SaveAttachments => app.save_attachments(),
+        Action::OpenAttachments => app.open_attachments(),
+        Action::ThreadNext => app.thread_next(),
+        Action::MessageNext => app.detail_next(),
+        Action::MessagePrevious => app.detail_previous(),
+        Action::ThreadPrevious => app.thread_previous(),
+        Action::Reply => app.reply(false),
+        Action::ReplyAll => app.reply(true),
+        Action::ToggleTextPart => app.toggle_text_part(),
+        Action::ResendBounced => app.resend_bounced(),
+        Action::BackToList => app.back_to_list(),
+        Action::EditDraft => app.edit_draft(),
+        Action::SendDraft => app.send_draft(),
+        Action::AbortDraft => app.abort_draft(),
+        Action::ToggleDsn => app.toggle_dsn(),
+        Action::ToggleReadReceipt => app.toggle_read_receipt(),
+        Action::FolderNext => app.folder_next(),
+        Action::FolderPrevious => app.folder_previous(),
+        Action::FolderExpand => app.folder_expand(),
+        Action::FolderCollapse => app.folder_collapse(),
+        Action::FolderOpen => app.folder_activate(),
+        Action::SenderNext => app.sender_next(),
+        Action::SenderPrevious => app.sender_previous(),
+        Action::ArchiveSender => app.archive_sender(),
+        Action::DeleteSender => app.delete_sender(),
+        Action::MuteSender => app.mute_sender(),
+        Action::ToggleSenderSort => app.toggle_sender_sort(),
+        Action::ShowParticipants => app.show_participants(),
+        Action::ParticipantNext => app.participant_next(),
+        Action::ParticipantPrevious => app.participant_previous(),
+        Action::NarrowToParticipant => app.narrow_to_participant(),
+        Action::ShowAllParticipants => app.show_all_participants(),
+        Action::CloseParticipants => app.close_participants(),
+        Action::ToggleSidebar => app.toggle_sidebar(),
+        Action::SidebarNext => app.sidebar_next(),
+        Action::SidebarPrevious => app.sidebar_previous(),
+        Action::SidebarOpen => app.sidebar_open(),
+        Action::ToggleSource => app.toggle_source(),
+        Action::ToggleHeaders => app.toggle_headers(),
+        Action::CopySummary => app.copy_summary(),
+        Action::CopyCode => app.copy_code(),
+        Action::ShowUrls => app.show_urls(),
+        Action::UrlNext => app.url_next(),
+        Action::UrlPrevious => app.url_previous(),
+        Action::OpenUrl => app.open_selected_url(),
+        Action::OpenUrlNumber(number) => app.open_url_number(number),
+        Action::CloseUrls => app.close_urls(),
+        Action::PickerFilter => app.start_picker_filter(),
+        Action::GotoParent => app.goto_parent(),
+        Action::BackToReply => app.back_to_reply(),
+        Action::SaveMessage => app.save_message(),
+        Action::RetryFailed => app.retry_failed(),
+    }
+}
+
+/// Returns the action of a single pending key that is also bound on its
+/// own, such as `g` (reply all) beside `gg` in the detail view: like vim,
+/// it acts alone once no second key came in time.
+fn lone_key_action(app: &App) -> Option<Action> {
+    let mut keys = app.pending_keys.chars();
+    let (Some(c), None) = (keys.next(), keys.next()) else {
+        return None;
+    };
+    // A digit is a count waiting for its key.
+    if c.is_ascii_digit() {
+        return None;
+    }
+    lookup(
+        app.bindings(),
+        &KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE),
+    )
+}
+
+/// Outcome of feeding a key into the pending key sequence.
+enum ChordStep {
+    /// The key completed this chord.
+    Complete(&'static str),
+    /// The key extended or cancelled the pending sequence.
+    Consumed,
+    /// The key is not part of a sequence and should be handled normally.
+    Unhandled,
+}
+
+/// Feeds a key press into the pending multi-key sequence.
+///
+/// Like vim, Esc or a key that does not continue any chord discards the
+/// pending keys without running anything.
+fn feed_chord(app: &mut App, chords: &[&'static str], key: &KeyEvent) -> ChordStep {
+    let pending = !app.pending_keys.is_empty();
+
+    let c = match key.code {
+        KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => c,
+        _ if pending => {
+            app.clear_pending_keys();
+            return ChordStep::Consumed;
+        }
+        _ => return ChordStep::Unhandled,
+    };
+
+    let mut keys = app.pending_keys.clone();
+    keys.push(c);
+    // In the list, digits typed first are a count for the key that follows.
+    let digits = match app.mode {
+        ViewMode::List => keys.chars().take_while(char::is_ascii_digit).count(),
+        _ => 0,
+    };
+    if digits == keys.len() {
+        app.push_pending_key(c);
+        return ChordStep::Consumed;
+    }
+    let count = keys[..digits].parse().ok();
+    let keys = &keys[digits..];
+    match match_chord(chords, keys) {
+        ChordMatch::Complete(chord) => {
+            app.clear_pending_keys();
+            app.count = count;
+            ChordStep::Complete(chord)
+        }
+        ChordMatch::Prefix => {
+            app.push_pending_key(c);
+            ChordStep::Consumed
+        }
+        ChordMatch::None if keys.len() > 1 => {
+            app.clear_pending_keys();
+            ChordStep::Consumed
+        }
+        ChordMatch::None => {
+            app.clear_pending_keys();
+            app.count = count;
+            ChordStep::Unhandled
+        }
+    }
+}
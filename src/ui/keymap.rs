@@ -0,0 +1,952 @@
+//! Keybindings for each view.
+//!
+//! The keymap is the single source of truth for key handling: the event loop
+//! dispatches through it and the footer hints are generated from it, so the
+//! two cannot drift apart. Bindings are listed most relevant first, which is
+//! the order they appear in the footer.
+//!
+//! Multi-key sequences (chords) such as `gg` are collected as pending keys
+//! until they form a complete chord, stop matching any chord, or time out.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::folders::InboxTab;
+use crate::ui::app::{Filter, ViewMode};
+
+/// Something the user can do with a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Action {
+    Next,
+    Previous,
+    First,
+    Last,
+    PageForward,
+    PageBackward,
+    HalfPageForward,
+    HalfPageBackward,
+    LineForward,
+    LineBackward,
+    PageTop,
+    PageMiddle,
+    PageBottom,
+    NextUnread,
+    NextUnreadFolder,
+    ToggleThreaded,
+    ToggleThread,
+    ToggleAllThreads,
+    ToggleThreadOrder,
+    CycleSort,
+    ToggleRead,
+    ToggleFlag,
+    Archive,
+    Label,
+    Unlabel,
+    ToggleTag,
+    TagPattern,
+    Delete,
+    Move,
+    Search,
+    Command,
+    NextMatch,
+    PreviousMatch,
+    ViewEmail,
+    ChangeFolder,
+    ShowStats,
+    ShowCleanup,
+    Quit,
+    DetailScrollDown,
+    DetailScrollUp,
+    DetailLineForward,
+    DetailLineBackward,
+    DetailHalfPageDown,
+    DetailHalfPageUp,
+    DetailTop,
+    DetailBottom,
+    SaveAttachments,
+    OpenAttachments,
+    ThreadNext,
+    MessageNext,
+    MessagePrevious,
+    ThreadPrevious,
+    Reply,
+    ReplyAll,
+    ToggleTextPart,
+    ResendBounced,
+    BackToList,
+    EditDraft,
+    SendDraft,
+    AbortDraft,
+    ToggleDsn,
+    ToggleReadReceipt,
+    FolderNext,
+    FolderPrevious,
+    FolderExpand,
+    FolderCollapse,
+    FolderOpen,
+    OpenTab(InboxTab),
+    ToggleFilter(Filter),
+    ToggleFocus,
+    ClearFilters,
+    LimitPattern,
+    SenderNext,
+    SenderPrevious,
+    ArchiveSender,
+    DeleteSender,
+    MuteSender,
+    ToggleSenderSort,
+    ResultNext,
+    ResultPrevious,
+    OpenResult,
+    ShowParticipants,
+    ParticipantNext,
+    ParticipantPrevious,
+    NarrowToParticipant,
+    ShowAllParticipants,
+    CloseParticipants,
+    ToggleSidebar,
+    SidebarNext,
+    SidebarPrevious,
+    SidebarOpen,
+    ToggleSource,
+    ToggleHeaders,
+    CopySummary,
+    CopyCode,
+    ShowUrls,
+    UrlNext,
+    UrlPrevious,
+    OpenUrl,
+    OpenUrlNumber(usize),
+    CloseUrls,
+    PickerFilter,
+    GotoParent,
+    BackToReply,
+    SaveMessage,
+    RetryFailed,
+    ShowHelp,
+    HelpScrollDown,
+    HelpScrollUp,
+    HelpPageDown,
+    HelpPageUp,
+    CloseHelp,
+}
+
+/// A key or key sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Key {
+    /// A key pressed without Ctrl.
+    Code(KeyCode),
+    /// A character pressed with Ctrl.
+    Ctrl(char),
+    /// A sequence of characters typed one after another.
+    Chord(&'static str),
+}
+
+/// Keys bound to an action in a view.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Binding {
+    /// Keys triggering the action.
+    pub keys: &'static [Key],
+    /// The action performed.
+    pub action: Action,
+    /// Short description shown in the footer.
+    pub description: &'static str,
+}
+
+const fn bind(keys: &'static [Key], action: Action, description: &'static str) -> Binding {
+    Binding {
+        keys,
+        action,
+        description,
+    }
+}
+
+use Key::{Chord, Code, Ctrl};
+
+const LOADING: &[Binding] = &[bind(
+    &[Code(KeyCode::Char('q')), Code(KeyCode::Esc)],
+    Action::Quit,
+    "cancel",
+)];
+
+const LIST: &[Binding] = &[
+    bind(
+        &[Code(KeyCode::Char('j')), Code(KeyCode::Down), Ctrl('n')],
+        Action::Next,
+        "down",
+    ),
+    bind(
+        &[Code(KeyCode::Char('k')), Code(KeyCode::Up), Ctrl('p')],
+        Action::Previous,
+        "up",
+    ),
+    bind(&[Code(KeyCode::Enter)], Action::ViewEmail, "view"),
+    bind(&[Code(KeyCode::Char('q'))], Action::Quit, "quit"),
+    bind(&[Code(KeyCode::Char('?'))], Action::ShowHelp, "help"),
+    bind(&[Code(KeyCode::Tab)], Action::NextUnread, "next unread"),
+    bind(&[Code(KeyCode::Char('a'))], Action::Archive, "archive"),
+    bind(
+        &[Code(KeyCode::Char('N'))],
+        Action::ToggleRead,
+        "read/unread",
+    ),
+    bind(&[Code(KeyCode::Char('F'))], Action::ToggleFlag, "flag"),
+    bind(&[Code(KeyCode::Char('t'))], Action::ToggleTag, "tag"),
+    bind(&[Ctrl('t')], Action::TagPattern, "tag pattern"),
+    bind(&[Code(KeyCode::Char('d'))], Action::Delete, "delete"),
+    bind(&[Code(KeyCode::Char('m'))], Action::Move, "move"),
+    bind(&[Code(KeyCode::Char('+'))], Action::Label, "label"),
+    bind(&[Code(KeyCode::Char('-'))], Action::Unlabel, "remove label"),
+    bind(&[Code(KeyCode::Char('w'))], Action::SaveMessage, "save"),
+    bind(
+        &[Code(KeyCode::Char('r'))],
+        Action::RetryFailed,
+        "retry failed",
+    ),
+    bind(&[Code(KeyCode::Char('/'))], Action::Search, "search"),
+    bind(&[Code(KeyCode::Char(':'))], Action::Command, "command"),
+    bind(&[Code(KeyCode::Char('n'))], Action::NextMatch, "next match"),
+    bind(
+        &[Code(KeyCode::Char('p'))],
+        Action::PreviousMatch,
+        "previous match",
+    ),
+    bind(
+        &[Code(KeyCode::Char('u'))],
+        Action::ToggleFilter(Filter::Unread),
+        "unread only",
+    ),
+    bind(
+        &[Code(KeyCode::Char('!'))],
+        Action::ToggleFilter(Filter::Flagged),
+        "flagged only",
+    ),
+    bind(
+        &[Code(KeyCode::Char('@'))],
+        Action::ToggleFilter(Filter::ToMe),
+        "to me only",
+    ),
+    bind(&[Code(KeyCode::Char('f'))], Action::ToggleFocus, "focus"),
+    bind(&[Code(KeyCode::Char('l'))], Action::LimitPattern, "limit"),
+    bind(&[Code(KeyCode::Esc)], Action::ClearFilters, "clear filters"),
+    bind(
+        &[Code(KeyCode::Char(']'))],
+        Action::NextUnreadFolder,
+        "next unread folder",
+    ),
+    bind(
+        &[Code(KeyCode::Char('c'))],
+        Action::ChangeFolder,
+        "change folder",
+    ),
+    bind(&[Code(KeyCode::Char('S'))], Action::ShowStats, "stats"),
+    bind(&[Code(KeyCode::Char('C'))], Action::ShowCleanup, "cleanup"),
+    bind(
+        &[Code(KeyCode::Char('b'))],
+        Action::ToggleSidebar,
+        "sidebar",
+    ),
+    bind(
+        &[Code(KeyCode::Char('T'))],
+        Action::ToggleThreaded,
+        "threads",
+    ),
+    bind(
+        &[Code(KeyCode::Char('z'))],
+        Action::ToggleThread,
+        "fold thread",
+    ),
+    bind(
+        &[Code(KeyCode::Char('Z'))],
+        Action::ToggleAllThreads,
+        "fold all threads",
+    ),
+    bind(
+        &[Code(KeyCode::Char('O'))],
+        Action::ToggleThreadOrder,
+        "thread order",
+    ),
+    bind(&[Code(KeyCode::Char('o'))], Action::CycleSort, "sort"),
+    bind(
+        &[Code(KeyCode::Char('y'))],
+        Action::CopyCode,
+        "copy code from new mail",
+    ),
+    bind(&[Chord("gg")], Action::First, "first"),
+    bind(&[Code(KeyCode::Char('G'))], Action::Last, "last"),
+    bind(&[Ctrl('f')], Action::PageForward, "page down"),
+    bind(&[Ctrl('b')], Action::PageBackward, "page up"),
+    bind(&[Ctrl('d')], Action::HalfPageForward, "half page down"),
+    bind(&[Ctrl('u')], Action::HalfPageBackward, "half page up"),
+    bind(&[Ctrl('e')], Action::LineForward, "scroll down"),
+    bind(&[Ctrl('y')], Action::LineBackward, "scroll up"),
+    bind(&[Code(KeyCode::Char('H'))], Action::PageTop, "window top"),
+    bind(
+        &[Code(KeyCode::Char('M'))],
+        Action::PageMiddle,
+        "window middle",
+    ),
+    bind(
+        &[Code(KeyCode::Char('L'))],
+        Action::PageBottom,
+        "window bottom",
+    ),
+    bind(
+        &[Chord("g1")],
+        Action::OpenTab(InboxTab::Primary),
+        "primary",
+    ),
+    bind(
+        &[Chord("g2")],
+        Action::OpenTab(InboxTab::Promotions),
+        "promotions",
+    ),
+    bind(&[Chord("g3")], Action::OpenTab(InboxTab::Social), "social"),
+    bind(
+        &[Chord("g4")],
+        Action::OpenTab(InboxTab::Updates),
+        "updates",
+    ),
+    bind(
+        &[Chord("g5")],
+        Action::OpenTab(InboxTab::Important),
+        "important",
+    ),
+    bind(
+        &[Code(KeyCode::Char('J'))],
+        Action::SidebarNext,
+        "sidebar down",
+    ),
+    bind(
+        &[Code(KeyCode::Char('K'))],
+        Action::SidebarPrevious,
+        "sidebar up",
+    ),
+    bind(&[Ctrl('o')], Action::SidebarOpen, "sidebar open"),
+];
+
+const DETAIL: &[Binding] = &[
+    bind(
+        &[Code(KeyCode::Char('j')), Code(KeyCode::Down)],
+        Action::DetailScrollDown,
+        "down",
+    ),
+    bind(
+        &[Code(KeyCode::Char('k')), Code(KeyCode::Up)],
+        Action::DetailScrollUp,
+        "up",
+    ),
+    bind(&[Ctrl('d')], Action::DetailHalfPageDown, "half page down"),
+    bind(&[Ctrl('u')], Action::DetailHalfPageUp, "half page up"),
+    // `g` alone replies to all once `gg` times out; `^` goes up at once.
+    bind(
+        &[Chord("gg"), Code(KeyCode::Char('^')), Code(KeyCode::Home)],
+        Action::DetailTop,
+        "top",
+    ),
+    bind(
+        &[Code(KeyCode::Char('G')), Code(KeyCode::End)],
+        Action::DetailBottom,
+        "bottom",
+    ),
+    bind(
+        &[Code(KeyCode::Char('s'))],
+        Action::SaveAttachments,
+        "save attachments",
+    ),
+    bind(
+        &[Code(KeyCode::Char('o'))],
+        Action::OpenAttachments,
+        "open attachments",
+    ),
+    bind(
+        &[Code(KeyCode::Char('n'))],
+        Action::ThreadNext,
+        "next in thread",
+    ),
+    bind(
+        &[Code(KeyCode::Char('p'))],
+        Action::ThreadPrevious,
+        "previous in thread",
+    ),
+    bind(
+        &[Code(KeyCode::Char('J'))],
+        Action::MessageNext,
+        "next message",
+    ),
+    bind(
+        &[Code(KeyCode::Char('K'))],
+        Action::MessagePrevious,
+        "previous message",
+    ),
+    bind(&[Code(KeyCode::Char('r'))], Action::Reply, "reply"),
+    bind(&[Code(KeyCode::Char('g'))], Action::ReplyAll, "reply all"),
+    bind(
+        &[Code(KeyCode::Char('v'))],
+        Action::ToggleTextPart,
+        "plain/html",
+    ),
+    bind(&[Code(KeyCode::Char('h'))], Action::ToggleSource, "source"),
+    bind(
+        &[Code(KeyCode::Char('H'))],
+        Action::ToggleHeaders,
+        "all headers",
+    ),
+    bind(
+        &[Code(KeyCode::Char('q')), Code(KeyCode::Esc)],
+        Action::BackToList,
+        "back",
+    ),
+    bind(
+        &[Ctrl('n'), Ctrl('e')],
+        Action::DetailLineForward,
+        "scroll down",
+    ),
+    bind(
+        &[Ctrl('p'), Ctrl('y')],
+        Action::DetailLineBackward,
+        "scroll up",
+    ),
+    bind(
+        &[Code(KeyCode::Char('E'))],
+        Action::ResendBounced,
+        "edit and resend",
+    ),
+    bind(
+        &[Code(KeyCode::Char('P'))],
+        Action::ShowParticipants,
+        "participants",
+    ),
+    bind(&[Code(KeyCode::Char('u'))], Action::ShowUrls, "links"),
+    bind(
+        &[Code(KeyCode::Char('y'))],
+        Action::CopySummary,
+        "copy code/tracking no.",
+    ),
+    bind(&[Code(KeyCode::Char('['))], Action::GotoParent, "parent"),
+    bind(
+        &[Code(KeyCode::Char(']'))],
+        Action::BackToReply,
+        "back to reply",
+    ),
+    bind(
+        &[Code(KeyCode::Char('w'))],
+        Action::SaveMessage,
+        "save message",
+    ),
+    bind(&[Code(KeyCode::Char('?'))], Action::ShowHelp, "help"),
+];
+
+const COMPOSE: &[Binding] = &[
+    bind(&[Code(KeyCode::Char('e'))], Action::EditDraft, "edit"),
+    bind(&[Code(KeyCode::Char('y'))], Action::SendDraft, "send"),
+    bind(
+        &[Code(KeyCode::Char('q')), Code(KeyCode::Esc)],
+        Action::AbortDraft,
+        "abort",
+    ),
+    bind(
+        &[Code(KeyCode::Char('d'))],
+        Action::ToggleDsn,
+        "delivery report",
+    ),
+    bind(
+        &[Code(KeyCode::Char('R'))],
+        Action::ToggleReadReceipt,
+        "read receipt",
+    ),
+    bind(&[Code(KeyCode::Char('?'))], Action::ShowHelp, "help"),
+];
+
+const FOLDERS: &[Binding] = &[
+    bind(
+        &[Code(KeyCode::Char('j')), Code(KeyCode::Down)],
+        Action::FolderNext,
+        "down",
+    ),
+    bind(
+        &[Code(KeyCode::Char('k')), Code(KeyCode::Up)],
+        Action::FolderPrevious,
+        "up",
+    ),
+    bind(&[Code(KeyCode::Enter)], Action::FolderOpen, "open"),
+    bind(&[Code(KeyCode::Char('/'))], Action::PickerFilter, "find"),
+    bind(
+        &[Code(KeyCode::Char('q')), Code(KeyCode::Esc)],
+        Action::BackToList,
+        "back",
+    ),
+    bind(
+        &[Code(KeyCode::Char('l')), Code(KeyCode::Right)],
+        Action::FolderExpand,
+        "expand",
+    ),
+    bind(
+        &[Code(KeyCode::Char('h')), Code(KeyCode::Left)],
+        Action::FolderCollapse,
+        "collapse",
+    ),
+    bind(&[Code(KeyCode::Char('?'))], Action::ShowHelp, "help"),
+];
+
+const STATS: &[Binding] = &[
+    bind(
+        &[Code(KeyCode::Char('q')), Code(KeyCode::Esc)],
+        Action::BackToList,
+        "back",
+    ),
+    bind(&[Code(KeyCode::Char('?'))], Action::ShowHelp, "help"),
+];
+
+const VERSION: &[Binding] = &[
+    bind(
+        &[Code(KeyCode::Char('q')), Code(KeyCode::Esc)],
+        Action::BackToList,
+        "back",
+    ),
+    bind(&[Code(KeyCode::Char('?'))], Action::ShowHelp, "help"),
+];
+
+const CLEANUP: &[Binding] = &[
+    bind(
+        &[Code(KeyCode::Char('j')), Code(KeyCode::Down)],
+        Action::SenderNext,
+        "down",
+    ),
+    bind(
+        &[Code(KeyCode::Char('k')), Code(KeyCode::Up)],
+        Action::SenderPrevious,
+        "up",
+    ),
+    bind(
+        &[Code(KeyCode::Char('a'))],
+        Action::ArchiveSender,
+        "archive all",
+    ),
+    bind(
+        &[Code(KeyCode::Char('d'))],
+        Action::DeleteSender,
+        "delete all",
+    ),
+    bind(&[Code(KeyCode::Char('m'))], Action::MuteSender, "mute"),
+    bind(
+        &[Code(KeyCode::Char('s'))],
+        Action::ToggleSenderSort,
+        "count/size",
+    ),
+    bind(
+        &[Code(KeyCode::Char('q')), Code(KeyCode::Esc)],
+        Action::BackToList,
+        "back",
+    ),
+    bind(&[Code(KeyCode::Char('?'))], Action::ShowHelp, "help"),
+];
+
+const RESULTS: &[Binding] = &[
+    bind(
+        &[Code(KeyCode::Char('j')), Code(KeyCode::Down)],
+        Action::ResultNext,
+        "down",
+    ),
+    bind(
+        &[Code(KeyCode::Char('k')), Code(KeyCode::Up)],
+        Action::ResultPrevious,
+        "up",
+    ),
+    bind(&[Code(KeyCode::Enter)], Action::OpenResult, "open folder"),
+    bind(
+        &[Code(KeyCode::Char('q')), Code(KeyCode::Esc)],
+        Action::BackToList,
+        "back",
+    ),
+    bind(&[Code(KeyCode::Char('?'))], Action::ShowHelp, "help"),
+];
+
+const PARTICIPANTS: &[Binding] = &[
+    bind(
+        &[Code(KeyCode::Char('j')), Code(KeyCode::Down)],
+        Action::ParticipantNext,
+        "down",
+    ),
+    bind(
+        &[Code(KeyCode::Char('k')), Code(KeyCode::Up)],
+        Action::ParticipantPrevious,
+        "up",
+    ),
+    bind(
+        &[Code(KeyCode::Enter)],
+        Action::NarrowToParticipant,
+        "only theirs",
+    ),
+    bind(
+        &[Code(KeyCode::Char('a'))],
+        Action::ShowAllParticipants,
+        "everyone",
+    ),
+    bind(
+        &[Code(KeyCode::Char('q')), Code(KeyCode::Esc)],
+        Action::CloseParticipants,
+        "back",
+    ),
+    bind(&[Code(KeyCode::Char('?'))], Action::ShowHelp, "help"),
+];
+
+const URLS: &[Binding] = &[
+    bind(
+        &[Code(KeyCode::Char('j')), Code(KeyCode::Down)],
+        Action::UrlNext,
+        "down",
+    ),
+    bind(
+        &[Code(KeyCode::Char('k')), Code(KeyCode::Up)],
+        Action::UrlPrevious,
+        "up",
+    ),
+    bind(&[Code(KeyCode::Enter)], Action::OpenUrl, "open"),
+    bind(&[Code(KeyCode::Char('/'))], Action::PickerFilter, "filter"),
+    bind(
+        &[Code(KeyCode::Char('q')), Code(KeyCode::Esc)],
+        Action::CloseUrls,
+        "back",
+    ),
+    bind(
+        &[Code(KeyCode::Char('1'))],
+        Action::OpenUrlNumber(1),
+        "open 1",
+    ),
+    bind(
+        &[Code(KeyCode::Char('2'))],
+        Action::OpenUrlNumber(2),
+        "open 2",
+    ),
+    bind(
+        &[Code(KeyCode::Char('3'))],
+        Action::OpenUrlNumber(3),
+        "open 3",
+    ),
+    bind(
+        &[Code(KeyCode::Char('4'))],
+        Action::OpenUrlNumber(4),
+        "open 4",
+    ),
+    bind(
+        &[Code(KeyCode::Char('5'))],
+        Action::OpenUrlNumber(5),
+        "open 5",
+    ),
+    bind(
+        &[Code(KeyCode::Char('6'))],
+        Action::OpenUrlNumber(6),
+        "open 6",
+    ),
+    bind(
+        &[Code(KeyCode::Char('7'))],
+        Action::OpenUrlNumber(7),
+        "open 7",
+    ),
+    bind(
+        &[Code(KeyCode::Char('8'))],
+        Action::OpenUrlNumber(8),
+        "open 8",
+    ),
+    bind(
+        &[Code(KeyCode::Char('9'))],
+        Action::OpenUrlNumber(9),
+        "open 9",
+    ),
+    bind(&[Code(KeyCode::Char('?'))], Action::ShowHelp, "help"),
+];
+
+/// Bindings of the help overlay, which takes the keys while it is open.
+pub(crate) const HELP: &[Binding] = &[
+    bind(
+        &[Code(KeyCode::Char('j')), Code(KeyCode::Down)],
+        Action::HelpScrollDown,
+        "down",
+    ),
+    bind(
+        &[Code(KeyCode::Char('k')), Code(KeyCode::Up)],
+        Action::HelpScrollUp,
+        "up",
+    ),
+    bind(
+        &[Code(KeyCode::Char(' ')), Ctrl('f')],
+        Action::HelpPageDown,
+        "page down",
+    ),
+    bind(&[Ctrl('b')], Action::HelpPageUp, "page up"),
+    bind(
+        &[
+            Code(KeyCode::Char('?')),
+            Code(KeyCode::Char('q')),
+            Code(KeyCode::Esc),
+        ],
+        Action::CloseHelp,
+        "close",
+    ),
+];
+
+/// Views described by the help overlay, in its order, with their headings.
+const HELP_SECTIONS: [(&str, &[Binding]); 10] = [
+    ("Email list", LIST),
+    ("Message", DETAIL),
+    ("Participants", PARTICIPANTS),
+    ("Links", URLS),
+    ("Compose", COMPOSE),
+    ("Folders", FOLDERS),
+    ("Cleanup", CLEANUP),
+    ("Search results", RESULTS),
+    ("Statistics", STATS),
+    ("Version", VERSION),
+];
+
+/// A line of the help overlay.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum HelpLine {
+    /// The name of a view.
+    Heading(&'static str),
+    /// A binding's keys and description.
+    Binding(String, &'static str),
+    /// Space between views.
+    Blank,
+}
+
+/// Returns the help overlay: every view's bindings under its name, in
+/// keymap order.
+pub(crate) fn help_lines() -> Vec<HelpLine> {
+    let mut lines = Vec::new();
+    for (heading, bindings) in HELP_SECTIONS {
+        if !lines.is_empty() {
+            lines.push(HelpLine::Blank);
+        }
+        lines.push(HelpLine::Heading(heading));
+        lines.extend(
+            bindings
+                .iter()
+                .map(|binding| HelpLine::Binding(key_label(binding.keys), binding.description)),
+        );
+    }
+    lines
+}
+
+/// Returns the bindings of a view, most relevant first.
+pub(crate) fn bindings(mode: &ViewMode) -> &'static [Binding] {
+    match mode {
+        ViewMode::Loading => LOADING,
+        ViewMode::List => LIST,
+        ViewMode::Detail(_) => DETAIL,
+        ViewMode::Compose => COMPOSE,
+        ViewMode::Folders => FOLDERS,
+        ViewMode::Stats => STATS,
+        ViewMode::Version => VERSION,
+        ViewMode::Cleanup => CLEANUP,
+        ViewMode::Results => RESULTS,
+        ViewMode::Participants(_) => PARTICIPANTS,
+        ViewMode::Urls(_) => URLS,
+    }
+}
+
+/// Returns the action bound to a single key press, ignoring chords.
+pub(crate) fn lookup(bindings: &[Binding], key: &KeyEvent) -> Option<Action> {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    bindings
+        .iter()
+        .find(|binding| {
+            binding.keys.iter().any(|k| match *k {
+                Code(code) => !ctrl && key.code == code,
+                Ctrl(c) => ctrl && key.code == KeyCode::Char(c),
+                Chord(_) => false,
+            })
+        })
+        .map(|binding| binding.action)
+}
+
+/// Returns the action bound to a chord.
+pub(crate) fn lookup_chord(bindings: &[Binding], chord: &str) -> Option<Action> {
+    bindings
+        .iter()
+        .find(|binding| {
+            binding
+                .keys
+                .iter()
+                .any(|k| matches!(k, Chord(c) if *c == chord))
+        })
+        .map(|binding| binding.action)
+}
+
+/// Returns all chords in the bindings.
+pub(crate) fn chords(bindings: &[Binding]) -> Vec<&'static str> {
+    bindings
+        .iter()
+        .flat_map(|binding| binding.keys)
+        .filter_map(|k| match k {
+            Chord(chord) => Some(*chord),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Formats a binding's keys for display, e.g. "j/↓/^n".
+pub(crate) fn key_label(keys: &[Key]) -> String {
+    keys.iter()
+        .map(|k| match *k {
+            Code(KeyCode::Char(' ')) => "Space".to_string(),
+            Code(KeyCode::Char(c)) => c.to_string(),
+            Code(KeyCode::Down) => "↓".to_string(),
+            Code(KeyCode::Up) => "↑".to_string(),
+            Code(KeyCode::Left) => "←".to_string(),
+            Code(KeyCode::Right) => "→".to_string(),
+            Code(KeyCode::Enter) => "Enter".to_string(),
+            Code(KeyCode::Esc) => "Esc".to_string(),
+            Code(KeyCode::Tab) => "Tab".to_string(),
+            Code(code) => code.to_string(),
+            Ctrl(c) => format!("^{}", c),
+            Chord(chord) => chord.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Selects the footer hints that fit in `width` columns.
+///
+/// Returns `(keys, description)` pairs in keymap order and whether some
+/// bindings were left out.
+pub(crate) fn footer_hints(
+    bindings: &[Binding],
+    width: usize,
+) -> (Vec<(String, &'static str)>, bool) {
+    // Room kept for the overflow marker.
+    const MORE: usize = 2;
+
+    let mut hints = Vec::new();
+    let mut used = 0;
+    for binding in bindings {
+        let label = key_label(binding.keys);
+        let hint_width = label.chars().count() + 1 + binding.description.chars().count();
+        let sep = if hints.is_empty() { 0 } else { 1 };
+        if used + sep + hint_width + MORE > width {
+            return (hints, true);
+        }
+        used += sep + hint_width;
+        hints.push((label, binding.description));
+    }
+    (hints, false)
+}
+
+/// Result of matching pending keys against a chord table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ChordMatch {
+    /// The keys form this complete chord.
+    Complete(&'static str),
+    /// The keys are the start of at least one chord.
+    Prefix,
+    /// No chord starts with the keys.
+    None,
+}
+
+/// Matches pending keys against a chord table.
+pub(crate) fn match_chord(chords: &[&'static str], keys: &str) -> ChordMatch {
+    if keys.is_empty() {
+        return ChordMatch::None;
+    }
+    if let Some(chord) = chords.iter().find(|chord| **chord == keys) {
+        return ChordMatch::Complete(chord);
+    }
+    if chords.iter().any(|chord| chord.starts_with(keys)) {
+        ChordMatch::Prefix
+    } else {
+        ChordMatch::None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_chord() {
+        let chords = &["gg", "g1", "zz"];
+        assert_eq!(match_chord(chords, "g"), ChordMatch::Prefix);
+        assert_eq!(match_chord(chords, "gg"), ChordMatch::Complete("gg"));
+        assert_eq!(match_chord(chords, "g1"), ChordMatch::Complete("g1"));
+        assert_eq!(match_chord(chords, "gx"), ChordMatch::None);
+        assert_eq!(match_chord(chords, "j"), ChordMatch::None);
+        assert_eq!(match_chord(chords, ""), ChordMatch::None);
+    }
+
+    #[test]
+    fn test_lookup() {
+        let key = |code, modifiers| KeyEvent::new(code, modifiers);
+        assert_eq!(
+            lookup(LIST, &key(KeyCode::Char('j'), KeyModifiers::NONE)),
+            Some(Action::Next)
+        );
+        assert_eq!(
+            lookup(LIST, &key(KeyCode::Char('n'), KeyModifiers::CONTROL)),
+            Some(Action::Next)
+        );
+        assert_eq!(
+            lookup(LIST, &key(KeyCode::Char('G'), KeyModifiers::SHIFT)),
+            Some(Action::Last)
+        );
+        assert_eq!(
+            lookup(LIST, &key(KeyCode::Char('j'), KeyModifiers::CONTROL)),
+            None
+        );
+        assert_eq!(lookup_chord(LIST, "gg"), Some(Action::First));
+        assert_eq!(
+            lookup_chord(LIST, "g3"),
+            Some(Action::OpenTab(InboxTab::Social))
+        );
+        assert_eq!(chords(LIST), ["gg", "g1", "g2", "g3", "g4", "g5"]);
+
+        // In a message, `g` alone still replies to all once `gg` times out.
+        assert_eq!(lookup_chord(DETAIL, "gg"), Some(Action::DetailTop));
+        assert_eq!(
+            lookup(DETAIL, &key(KeyCode::Char('g'), KeyModifiers::NONE)),
+            Some(Action::ReplyAll)
+        );
+        assert_eq!(
+            lookup(DETAIL, &key(KeyCode::Char('^'), KeyModifiers::SHIFT)),
+            Some(Action::DetailTop)
+        );
+    }
+
+    #[test]
+    fn test_footer_hints() {
+        let (hints, overflow) = footer_hints(COMPOSE, 80);
+        assert_eq!(
+            hints,
+            [
+                ("e".to_string(), "edit"),
+                ("y".to_string(), "send"),
+                ("q/Esc".to_string(), "abort"),
+                ("d".to_string(), "delivery report"),
+                ("R".to_string(), "read receipt"),
+                ("?".to_string(), "help"),
+            ]
+        );
+        assert!(!overflow);
+
+        // "j/↓/^n:down" is 11 columns; the next hint does not fit.
+        let (hints, overflow) = footer_hints(LIST, 16);
+        assert_eq!(hints, [("j/↓/^n".to_string(), "down")]);
+        assert!(overflow);
+    }
+
+    #[test]
+    fn test_help_lines() {
+        // Every view opens the help, and the help lists every view.
+        for (_, bindings) in HELP_SECTIONS {
+            let key = KeyEvent::new(KeyCode::Char('?'), KeyModifiers::SHIFT);
+            assert_eq!(lookup(bindings, &key), Some(Action::ShowHelp));
+        }
+        let lines = help_lines();
+        let headings = lines
+            .iter()
+            .filter(|line| matches!(line, HelpLine::Heading(_)))
+            .count();
+        assert_eq!(headings, 10);
+        assert_eq!(lines[0], HelpLine::Heading("Email list"));
+        assert_eq!(lines[1], HelpLine::Binding("j/↓/^n".to_string(), "down"));
+    }
+}
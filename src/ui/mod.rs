@@ -4,8 +4,14 @@
 //! email list and detail views.
 
 mod app;
+mod avatar;
+mod command;
+mod editor;
 mod events;
+mod keymap;
+mod picker;
 mod render;
+mod status;
 
 pub use app::App;
 pub use events::run_app;
@@ -0,0 +1,203 @@
+//! Type-to-filter shared by the pickers: the folder browser, the
+//! change-folder and label prompts, and the URL picker.
+//!
+//! A query matches fuzzily, like in fzf: its characters must appear in the
+//! candidate in order, but not next to each other, so `smail` finds
+//! "[Gmail]/Sent Mail". Matches at the start of words and runs of
+//! consecutive characters rank first.
+
+use crate::folders::FolderChoice;
+
+/// Score of each matched character.
+const MATCH_SCORE: i32 = 1;
+
+/// Bonus for a character right after the previous matched one.
+const CONSECUTIVE_BONUS: i32 = 4;
+
+/// Bonus for a character starting a word.
+const WORD_START_BONUS: i32 = 6;
+
+/// Penalty for each character skipped between matched ones.
+const GAP_PENALTY: i32 = 1;
+
+/// Scores how well a candidate matches a query, ignoring case, or returns
+/// `None` when it does not match. Higher scores are better matches.
+pub(crate) fn fuzzy_score(candidate: &str, query: &str) -> Option<i32> {
+    let candidate = candidate.chars().collect::<Vec<_>>();
+    let query = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect::<Vec<_>>();
+    let Some(&first) = query.first() else {
+        return Some(0);
+    };
+
+    // Matching greedily can miss a better match further on, so try each
+    // place the query could start and keep the best.
+    (0..candidate.len())
+        .filter(|&start| lower_eq(candidate[start], first))
+        .filter_map(|start| score_from(&candidate, &query, start))
+        .max()
+}
+
+/// Scores the greedy match of a query starting at a position.
+fn score_from(candidate: &[char], query: &[char], start: usize) -> Option<i32> {
+    let mut score = 0;
+    let mut previous: Option<usize> = None;
+    let mut pos = start;
+    for &wanted in query {
+        let found = (pos..candidate.len()).find(|&i| lower_eq(candidate[i], wanted))?;
+        score += MATCH_SCORE;
+        if found == 0 || !candidate[found - 1].is_alphanumeric() {
+            score += WORD_START_BONUS;
+        }
+        match previous {
+            Some(previous) if previous + 1 == found => score += CONSECUTIVE_BONUS,
+            Some(previous) => score -= GAP_PENALTY * (found - previous - 1) as i32,
+            None => {}
+        }
+        previous = Some(found);
+        pos = found + 1;
+    }
+    Some(score)
+}
+
+/// Returns whether a character is the given lowercase character, ignoring
+/// case.
+fn lower_eq(c: char, lower: char) -> bool {
+    c.to_lowercase().eq(std::iter::once(lower))
+}
+
+/// Returns the indices of the items matching a query, best match first;
+/// equally good matches keep their order. An empty query matches all.
+pub(crate) fn fuzzy_matches<T>(items: &[T], query: &str, key: impl Fn(&T) -> &str) -> Vec<usize> {
+    let mut scored = items
+        .iter()
+        .enumerate()
+        .filter_map(|(i, item)| fuzzy_score(key(item), query).map(|score| (i, score)))
+        .collect::<Vec<_>>();
+    // Sorting is stable, so ties keep the original order.
+    scored.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
+    scored.into_iter().map(|(i, _)| i).collect()
+}
+
+/// Returns the choices matching the input of the change-folder or label
+/// prompt, best match first.
+///
+/// `=name` matches folders whose last path component starts with `name`, so
+/// `=Sent` finds "[Gmail]/Sent Mail"; other input matches the path fuzzily.
+/// Case is ignored.
+pub(crate) fn matching_folders<'a>(
+    choices: &'a [FolderChoice],
+    input: &str,
+) -> Vec<&'a FolderChoice> {
+    let input = input.trim();
+    if let Some(prefix) = input.strip_prefix('=') {
+        let prefix = prefix.to_lowercase();
+        return choices
+            .iter()
+            .filter(|choice| {
+                let path = choice.path.to_lowercase();
+                path.rsplit('/')
+                    .next()
+                    .unwrap_or(&path)
+                    .starts_with(&prefix)
+            })
+            .collect();
+    }
+    fuzzy_matches(choices, input, |choice| &choice.path)
+        .into_iter()
+        .map(|i| &choices[i])
+        .collect()
+}
+
+/// A filter being typed into a picker.
+#[derive(Debug, Default)]
+pub(crate) struct PickerFilter {
+    query: String,
+    typing: bool,
+}
+
+impl PickerFilter {
+    /// Starts typing the filter, keeping what was typed before.
+    pub fn start(&mut self) {
+        self.typing = true;
+    }
+
+    /// Returns whether keys go to the filter.
+    pub fn is_typing(&self) -> bool {
+        self.typing
+    }
+
+    /// Returns the filter typed so far.
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// Adds a character to the filter.
+    pub fn push(&mut self, c: char) {
+        self.query.push(c);
+    }
+
+    /// Removes the last character of the filter.
+    pub fn backspace(&mut self) {
+        self.query.pop();
+    }
+
+    /// Stops typing, keeping the filter.
+    pub fn finish(&mut self) {
+        self.typing = false;
+    }
+
+    /// Stops typing and clears the filter.
+    pub fn clear(&mut self) {
+        *self = PickerFilter::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert!(fuzzy_score("[Gmail]/Sent Mail", "smail").is_some());
+        assert!(fuzzy_score("[Gmail]/Sent Mail", "SENT").is_some());
+        assert_eq!(fuzzy_score("Receipts", "rx"), None);
+        assert_eq!(fuzzy_score("anything", ""), Some(0));
+
+        // Word starts and runs rank above scattered letters.
+        let paths = ["Personal/Letters", "[Gmail]/Sent Mail", "Sellers"];
+        assert_eq!(fuzzy_matches(&paths, "sent", |p| p), [1]);
+        assert_eq!(fuzzy_matches(&paths, "se", |p| p), [1, 2, 0]);
+        assert_eq!(fuzzy_matches(&paths, "", |p| p), [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_matching_folders() {
+        let choices = [
+            "[Gmail]/Spam",
+            "INBOX",
+            "Work/Projects",
+            "[Gmail]/Sent Mail",
+        ]
+        .map(|path| FolderChoice {
+            path: path.to_string(),
+            unread: 0,
+        });
+        let paths = |input| {
+            matching_folders(&choices, input)
+                .iter()
+                .map(|c| c.path.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(paths("=sent"), ["[Gmail]/Sent Mail"]);
+        assert_eq!(paths("=S"), ["[Gmail]/Spam", "[Gmail]/Sent Mail"]);
+        assert_eq!(paths("=Projects"), ["Work/Projects"]);
+        assert_eq!(paths("=Work"), Vec::<String>::new());
+        assert_eq!(paths("gmail"), ["[Gmail]/Spam", "[Gmail]/Sent Mail"]);
+        assert_eq!(paths("wproj"), ["Work/Projects"]);
+        assert_eq!(paths("").len(), 4);
+    }
+}
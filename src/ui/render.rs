@@ -5,25 +5,223 @@
 
 use ratatui::{
     Frame,
-    layout::{Alignment, Constraint, Direction, Layout},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span, Text},
-    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+    widgets::{Block, Borders, Clear, Gauge, List, ListItem, ListState, Paragraph, Wrap},
 };
 
-use crate::ui::app::{App, ViewMode};
-use crate::utils::format_date;
+use crate::body::DECEPTIVE_LINK_MARK;
+use crate::config::{SortMode, TextPart, TranslateLayout, Truncation};
+use crate::language::{display_lines, is_rtl};
+use crate::loader::LoadStage;
+use crate::ui::app::{App, ListRow, PromptKind, ViewMode};
+use crate::ui::avatar::{account_tag_span, avatar_span};
+use crate::ui::keymap::{HelpLine, footer_hints, help_lines};
+use crate::updates::{RELEASES_URL, UpdateStatus, build_info};
+use crate::utils::{
+    format_bytes, format_date, reading_time, truncate_end, truncate_middle, zone_label, zoned_date,
+};
+
+/// Width of the From column in the email list.
+const FROM_WIDTH: usize = 25;
+
+/// Width of the folder sidebar, including its border.
+const SIDEBAR_WIDTH: u16 = 28;
+
+/// Width of the folder column of the all-folder search results.
+const RESULT_FOLDER_WIDTH: usize = 20;
+
+/// Number of folders listed by the change-folder prompt.
+const FOLDER_CANDIDATES: usize = 10;
+
+/// Headers always shown above the body, which the weeded list leaves out.
+const STANDARD_HEADERS: &[&str] = &["date", "from", "to", "cc", "bcc", "subject"];
 
 /// Main UI rendering function that dispatches to appropriate view.
 pub(crate) fn ui(f: &mut Frame, app: &mut App) {
+    let [view, status] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(f.area());
     match app.mode {
-        ViewMode::List => render_list(f, app),
-        ViewMode::Detail(idx) => render_detail(f, app, idx),
+        ViewMode::Loading => render_loading(f, app, view),
+        ViewMode::List => render_list(f, app, view),
+        ViewMode::Detail(idx) => render_detail(f, app, idx, view),
+        ViewMode::Compose => render_compose(f, app, view),
+        ViewMode::Stats => render_stats(f, app, view),
+        ViewMode::Version => render_version(f, app, view),
+        ViewMode::Folders => render_folders(f, app, view),
+        ViewMode::Cleanup => render_cleanup(f, app, view),
+        ViewMode::Results => render_results(f, app, view),
+        ViewMode::Participants(idx) => {
+            render_detail(f, app, idx, view);
+            render_participants(f, app, view);
+        }
+        ViewMode::Urls(idx) => {
+            render_detail(f, app, idx, view);
+            render_urls(f, app, view);
+        }
+    }
+    if let Some(scroll) = app.help_scroll() {
+        render_help(f, app, scroll, view);
+    }
+    render_status(f, app, status);
+}
+
+/// Renders the help overlay over the view, leaving its footer visible for
+/// the overlay's own keys.
+fn render_help(f: &mut Frame, app: &App, scroll: usize, area: Rect) {
+    let lines = help_lines()
+        .into_iter()
+        .map(|line| match line {
+            HelpLine::Heading(heading) => Line::from(Span::styled(
+                heading,
+                Style::default()
+                    .fg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD),
+            )),
+            HelpLine::Binding(keys, description) => Line::from(vec![
+                Span::raw(format!("  {:<14} ", keys)),
+                Span::raw(description),
+            ]),
+            HelpLine::Blank => Line::default(),
+        })
+        .collect::<Vec<_>>();
+
+    let width = 60.min(area.width);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y,
+        width,
+        height: area.height.saturating_sub(1),
+    };
+    let help = Paragraph::new(lines)
+        .block(bordered_block(app, Borders::ALL).title(" Help "))
+        .scroll((scroll as u16, 0));
+    f.render_widget(Clear, popup);
+    f.render_widget(help, popup);
+}
+
+/// Renders the progress of connecting and fetching the inbox at startup: a
+/// spinner and the stage, with a bar once the number of envelopes is known.
+fn render_loading(f: &mut Frame, app: &App, area: Rect) {
+    let [body, footer] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(area);
+    let Some(stage) = app.load_stage() else {
+        render_footer(f, app, footer);
+        return;
+    };
+    // An animated spinner would be read out on every frame.
+    let text = if app.config.ui.screen_reader {
+        stage.to_string()
+    } else {
+        format!("{} {}", app.spinner(), stage)
+    };
+
+    let width = 50.min(body.width);
+    let [_, middle, _] = Layout::vertical([
+        Constraint::Fill(1),
+        Constraint::Length(2),
+        Constraint::Fill(1),
+    ])
+    .areas(body);
+    let middle = Rect {
+        x: body.x + (body.width - width) / 2,
+        width,
+        ..middle
+    };
+    let [label, bar] =
+        Layout::vertical([Constraint::Length(1), Constraint::Length(1)]).areas(middle);
+    f.render_widget(Paragraph::new(text), label);
+    if let LoadStage::Fetching { done, total } = stage
+        && total > 0
+        && !app.config.ui.screen_reader
+    {
+        let gauge = Gauge::default()
+            .gauge_style(Style::default().fg(Color::Cyan))
+            .ratio((done as f64 / total as f64).min(1.0))
+            .label(format!("{}%", done * 100 / total));
+        f.render_widget(gauge, bar);
+    }
+    render_footer(f, app, footer);
+}
+
+/// Returns the block under a view's header: a rule, left out in screen
+/// reader mode, where it would be read as a row of symbols.
+fn header_block(app: &App) -> Block<'static> {
+    bordered_block(app, Borders::BOTTOM)
+}
+
+/// Returns a block with `borders`, which are left out in screen reader mode
+/// like the header's rule.
+fn bordered_block(app: &App, borders: Borders) -> Block<'static> {
+    if app.config.ui.screen_reader {
+        Block::default()
+    } else {
+        Block::default().borders(borders)
     }
 }
 
+/// Builds a list row as one line of words for screen readers, e.g.
+/// "3, unread, important, Alice Smith, Lunch on Friday, 10/03".
+fn spoken_row(app: &App, number: usize, row: &ListRow) -> Line<'static> {
+    let email = &app.emails[row.email];
+    let mut parts = Vec::new();
+    if app.config.ui.index_numbers {
+        parts.push(number.to_string());
+    }
+    if app.is_tagged(email) {
+        parts.push("tagged".to_string());
+    }
+    parts.push(if row.unread { "unread" } else { "read" }.to_string());
+    if app.is_important(row.email) {
+        parts.push("important".to_string());
+    }
+    if email.is_flagged {
+        parts.push("flagged".to_string());
+    }
+    if row.depth > 0 {
+        parts.push("reply".to_string());
+    }
+    if email.malformed {
+        parts.push("malformed".to_string());
+    }
+    parts.push(
+        email
+            .from
+            .name_or_addr()
+            .unwrap_or("unknown sender")
+            .to_string(),
+    );
+    parts.push(email.subject.clone());
+    if !email.labels.is_empty() {
+        parts.push(format!("labels {}", email.labels.join(", ")));
+    }
+    if row.hidden > 0 {
+        parts.push(format!("{} more in thread", row.hidden));
+    }
+    parts.push(format_date(&zoned_date(email, app.config.ui.timezone)));
+    if app.config.ui.size_column {
+        parts.push(format_bytes(email.size.into()));
+    }
+
+    let style = if row.unread {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default()
+    };
+    Line::from(Span::styled(parts.join(", "), style))
+}
+
 /// Renders the email list view with header and footer.
-fn render_list(f: &mut Frame, app: &mut App) {
+fn render_list(f: &mut Frame, app: &mut App, area: Rect) {
+    let area = if app.sidebar().is_some() && area.width > SIDEBAR_WIDTH * 2 {
+        let [sidebar, list] =
+            Layout::horizontal([Constraint::Length(SIDEBAR_WIDTH), Constraint::Min(0)]).areas(area);
+        render_sidebar(f, app, sidebar);
+        list
+    } else {
+        area
+    };
+
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
@@ -31,10 +229,10 @@ fn render_list(f: &mut Frame, app: &mut App) {
             Constraint::Min(5),
             Constraint::Length(1),
         ])
-        .split(f.area());
+        .split(area);
 
     // Header
-    let header = Paragraph::new(Text::from(vec![Line::from(vec![
+    let mut title = vec![
         Span::styled(
             "Gmail IMAP Client",
             Style::default()
@@ -42,28 +240,136 @@ fn render_list(f: &mut Frame, app: &mut App) {
                 .add_modifier(Modifier::BOLD),
         ),
         Span::raw(" - "),
+        Span::styled(
+            app.client.as_ref().map_or("INBOX", |c| c.mailbox()),
+            Style::default().fg(Color::Yellow),
+        ),
+        Span::raw(" - "),
         Span::styled(
             format!("{} emails", app.emails.len()),
             Style::default().fg(Color::Gray),
         ),
-    ])]))
-    .block(Block::default().borders(Borders::BOTTOM))
-    .alignment(Alignment::Center);
+    ];
+    if let Some(tag) = &app.config.gmail.tag {
+        title.splice(
+            0..0,
+            [
+                account_tag_span(&app.config.gmail.username, tag),
+                Span::raw(" "),
+            ],
+        );
+    }
+    if let Some(query) = app.search_query() {
+        title.push(Span::raw(" - "));
+        title.push(Span::styled(
+            format!("search: {}", query),
+            Style::default().fg(Color::Magenta),
+        ));
+    }
+    if let Some(query) = app.limit_query() {
+        title.push(Span::raw(" - "));
+        title.push(Span::styled(
+            format!("limit: {}", query),
+            Style::default().fg(Color::Magenta),
+        ));
+    }
+    if app.is_offline() {
+        title.push(Span::raw(" - "));
+        title.push(Span::styled(
+            match app.queued_count() {
+                0 => "OFFLINE".to_string(),
+                n => format!("OFFLINE, {} changes queued", n),
+            },
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        ));
+    }
+    if let Some((done, total)) = app.folder_search_progress() {
+        title.push(Span::raw(" - "));
+        title.push(Span::styled(
+            format!("searching folders {}/{} {}", done, total, app.spinner()),
+            Style::default().fg(Color::Magenta),
+        ));
+    }
+    if let Some((done, total)) = app.attachment_save_progress() {
+        title.push(Span::raw(" - "));
+        title.push(Span::styled(
+            format!("saving attachments {}/{} {}", done, total, app.spinner()),
+            Style::default().fg(Color::Magenta),
+        ));
+    }
+    if app.focus() {
+        title.push(Span::raw(" - "));
+        title.push(Span::styled("focus", Style::default().fg(Color::Magenta)));
+    }
+    let tagged = app.tagged_count();
+    if tagged > 0 {
+        title.push(Span::raw(" - "));
+        title.push(Span::styled(
+            format!("{} tagged", tagged),
+            Style::default().fg(Color::Magenta),
+        ));
+    }
+    if !app.filters().is_empty() || app.pattern().is_some() {
+        let mut names: Vec<_> = app.filters().iter().map(|f| f.name()).collect();
+        names.extend(app.pattern().map(|pattern| pattern.as_str()));
+        title.push(Span::raw(" - "));
+        title.push(Span::styled(
+            format!("filter: {}", names.join(" ")),
+            Style::default().fg(Color::Magenta),
+        ));
+    }
+    if app.sort() != SortMode::DateDesc {
+        title.push(Span::raw(" - "));
+        title.push(Span::styled(
+            format!("sort: {}", app.sort().name()),
+            Style::default().fg(Color::Magenta),
+        ));
+    }
+    let new_mail = app.new_mail_count();
+    if new_mail > 0 {
+        title.push(Span::raw(" - "));
+        title.push(Span::styled(
+            format!("{} new mail", new_mail),
+            Style::default()
+                .fg(Color::Green)
+                .add_modifier(Modifier::BOLD),
+        ));
+    }
+    let header = Paragraph::new(Text::from(vec![Line::from(title)]))
+        .block(header_block(app))
+        .alignment(Alignment::Center);
     f.render_widget(header, chunks[0]);
 
-    // Update visible items count based on list area height.
-    app.set_visible_items(chunks[1].height as usize);
+    // Update visible items count based on list area size.
+    app.set_list_area(chunks[1].width, chunks[1].height);
 
     // Email list - only show items in the visible window.
-    let visible_emails = app
-        .emails
+    let visible_rows = app
+        .rows
         .iter()
         .skip(app.scroll_offset)
         .take(app.visible_items);
 
-    let items: Vec<ListItem> = visible_emails
-        .map(|email| {
-            let status = if email.is_read {
+    // Row numbers are as wide as the largest one.
+    let number_width = app.rows.len().to_string().len();
+
+    let items: Vec<ListItem> = visible_rows
+        .enumerate()
+        .map(|(i, row)| {
+            let number = app.scroll_offset + i + 1;
+            if app.config.ui.screen_reader {
+                return ListItem::new(spoken_row(app, number, row));
+            }
+            let email = &app.emails[row.email];
+            // A tag shows over the read status, as in mutt.
+            let status = if app.is_tagged(email) {
+                Span::styled(
+                    "*",
+                    Style::default()
+                        .fg(Color::Magenta)
+                        .add_modifier(Modifier::BOLD),
+                )
+            } else if !row.unread {
                 Span::styled("R", Style::default().fg(Color::Gray))
             } else {
                 Span::styled(
@@ -74,40 +380,113 @@ fn render_list(f: &mut Frame, app: &mut App) {
                 )
             };
 
-            let date_str = format_date(&email.date);
-
-            let from = &email.from.name_or_addr().unwrap_or("(unknown)");
-            let from = if from.len() > 25 {
-                format!("{}...", &from[..22])
+            // Gmail's importance marker takes the space after the status.
+            let important = if app.is_important(row.email) {
+                Span::styled(
+                    "!",
+                    Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                )
             } else {
-                format!("{:<25}", from)
+                Span::raw(" ")
             };
-
-            let subject = if email.subject.len() > 100 {
-                format!("{}...", &email.subject[..97])
+            let star = if email.is_flagged {
+                Span::styled("★", Style::default().fg(Color::Yellow))
             } else {
-                email.subject.clone()
+                Span::raw(" ")
             };
 
-            let subject_span = if email.is_read {
+            let date_str = format_date(&zoned_date(email, app.config.ui.timezone));
+
+            let from = email.from.name_or_addr().unwrap_or("(unknown)");
+            let from = match app.config.ui.truncation {
+                Truncation::End => truncate_end(from, FROM_WIDTH),
+                Truncation::Middle => truncate_middle(from, FROM_WIDTH),
+            };
+            let from = format!("{:<width$}", from, width = FROM_WIDTH);
+
+            let mut subject = truncate_end(&email.subject, 100);
+            if email.malformed {
+                subject = format!("(malformed) {}", subject);
+            }
+            if row.depth > 0 {
+                subject = format!("{}└ {}", "  ".repeat(row.depth - 1), subject);
+            }
+            if row.hidden > 0 {
+                subject = format!("{} (+{})", subject, row.hidden);
+            }
+
+            let subject_span = if !row.unread {
                 Span::raw(subject)
             } else {
                 Span::styled(subject, Style::default().fg(Color::Yellow))
             };
+            let chips = email.labels.iter().flat_map(|label| {
+                [
+                    Span::raw(" "),
+                    Span::styled(
+                        format!(" {} ", label),
+                        Style::default().fg(Color::Black).bg(Color::Cyan),
+                    ),
+                ]
+            });
 
-            let content = vec![Line::from(vec![
+            let mut spans = Vec::new();
+            if app.config.ui.index_numbers {
+                spans.push(Span::styled(
+                    format!("{:>number_width$} ", number),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            spans.extend([
                 Span::raw("["),
                 status,
-                Span::raw("] "),
+                Span::raw("]"),
+                important,
+                star,
                 Span::styled(
                     format!("{:>10}", date_str),
                     Style::default().fg(Color::Blue),
                 ),
                 Span::raw(" │ "),
-                Span::styled(from, Style::default().fg(Color::Green)),
-                Span::raw(" │ "),
-                subject_span,
-            ])];
+            ]);
+            if app.config.ui.size_column {
+                spans.extend([
+                    Span::styled(
+                        format!("{:>10}", format_bytes(email.size.into())),
+                        Style::default().fg(Color::Gray),
+                    ),
+                    Span::raw(" │ "),
+                ]);
+            }
+            if app.config.ui.index_avatars {
+                spans.push(avatar_span(&email.from));
+                spans.push(Span::raw(" "));
+            }
+            let content = if app.two_line_rows {
+                spans.push(Span::styled(
+                    from.trim_end().to_string(),
+                    Style::default().fg(Color::Green),
+                ));
+                // Indent the subject to line up with the status column.
+                let indent = if app.config.ui.index_numbers {
+                    number_width + 6
+                } else {
+                    5
+                };
+                let subject_line = [Span::raw(" ".repeat(indent)), subject_span]
+                    .into_iter()
+                    .chain(chips)
+                    .collect::<Vec<_>>();
+                vec![Line::from(spans), Line::from(subject_line)]
+            } else {
+                spans.extend([
+                    Span::styled(from, Style::default().fg(Color::Green)),
+                    Span::raw(" │ "),
+                    subject_span,
+                ]);
+                spans.extend(chips);
+                vec![Line::from(spans)]
+            };
 
             ListItem::new(content)
         })
@@ -124,35 +503,35 @@ fn render_list(f: &mut Frame, app: &mut App) {
 
     // Create a temporary list state for rendering with relative positioning.
     let mut render_state = ListState::default();
-    if let Some(selected) = app.list_state.selected() {
-        if selected >= app.scroll_offset && selected < app.scroll_offset + app.visible_items {
-            render_state.select(Some(selected - app.scroll_offset));
-        }
+    if let Some(selected) = app.list_state.selected()
+        && selected >= app.scroll_offset
+        && selected < app.scroll_offset + app.visible_items
+    {
+        render_state.select(Some(selected - app.scroll_offset));
     }
 
     f.render_stateful_widget(emails, chunks[1], &mut render_state);
+    render_folder_candidates(f, app, chunks[1]);
 
     // Footer
-    let footer = Paragraph::new(Line::from(vec![
-        Span::raw("j/↓"),
-        Span::styled(":down", Style::default().fg(Color::DarkGray)),
-        Span::raw(" "),
-        Span::raw("k/↑"),
-        Span::styled(":up", Style::default().fg(Color::DarkGray)),
-        Span::raw(" "),
-        Span::raw("Enter"),
-        Span::styled(":view", Style::default().fg(Color::DarkGray)),
-        Span::raw(" "),
-        Span::raw("q/Esc"),
-        Span::styled(":quit", Style::default().fg(Color::DarkGray)),
-    ]))
-    .style(Style::default().fg(Color::White))
-    .alignment(Alignment::Center);
-    f.render_widget(footer, chunks[2]);
+    render_footer(f, app, chunks[2]);
+}
+
+/// Formats a raw header as shown in the detail view.
+fn header_line<'a>(name: &'a str, value: &'a str) -> Line<'a> {
+    Line::from(vec![
+        Span::styled(
+            format!("{name}: "),
+            Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+        ),
+        Span::raw(value),
+    ])
 }
 
 /// Renders the email detail view for a specific email.
-fn render_detail(f: &mut Frame, app: &App, idx: usize) {
+fn render_detail(f: &mut Frame, app: &mut App, idx: usize, area: Rect) {
     if idx >= app.emails.len() {
         return;
     }
@@ -166,19 +545,62 @@ fn render_detail(f: &mut Frame, app: &App, idx: usize) {
             Constraint::Min(5),
             Constraint::Length(1),
         ])
-        .split(f.area());
+        .split(area);
 
     // Header
+    let (_, _, narrowed) = app.participants();
+    let mut title = match (app.thread_position(), narrowed) {
+        (Some((position, len)), Some(address)) => format!(
+            "Email Details ({} of {} in thread from {})",
+            position, len, address
+        ),
+        (Some((position, len)), None) => {
+            format!("Email Details ({} of {} in thread)", position, len)
+        }
+        (None, _) => "Email Details".to_string(),
+    };
+    if app.shown_source().is_some() {
+        title.push_str(" [source; h: message]");
+    } else if app.full_headers() {
+        title.push_str(" [all headers; H: weed]");
+    }
+    match app.shown_part() {
+        Some(TextPart::Plain) => title.push_str(" [plain; v: html]"),
+        Some(TextPart::Html) => title.push_str(" [html; v: plain]"),
+        None => {}
+    }
+    let translation = app.translation(idx);
+    if let Some(translation) = translation {
+        title.push_str(&format!(
+            " [translated {} → {}]",
+            translation.from, translation.to
+        ));
+    }
+    if let Some(body) = &email.body {
+        title.push_str(&format!(" - {}", reading_time(body)));
+    }
     let header = Paragraph::new(Text::from(vec![Line::from(vec![Span::styled(
-        "Email Details",
+        title,
         Style::default()
             .fg(Color::Cyan)
             .add_modifier(Modifier::BOLD),
     )])]))
-    .block(Block::default().borders(Borders::BOTTOM))
+    .block(header_block(app))
     .alignment(Alignment::Center);
     f.render_widget(header, chunks[0]);
 
+    // The source is shown as received, without wrapped lines trimmed.
+    if let Some(source) = app.shown_source() {
+        let lines = source.lines().map(Line::raw).collect::<Vec<_>>();
+        let widget = Paragraph::new(lines).wrap(Wrap { trim: false });
+        let line_count = widget.line_count(chunks[1].width);
+        let offset = bounded_scroll(app, line_count, chunks[1].height);
+        f.render_widget(widget.scroll((offset, 0)), chunks[1]);
+        app.set_detail_area(line_count, chunks[1].height);
+        render_footer(f, app, chunks[2]);
+        return;
+    }
+
     let mut content = vec![
         Line::from(vec![
             Span::styled(
@@ -188,7 +610,11 @@ fn render_detail(f: &mut Frame, app: &App, idx: usize) {
                     .add_modifier(Modifier::BOLD),
             ),
             Span::styled(
-                email.date.format("%Y/%m/%d %H:%M").to_string(),
+                format!(
+                    "{} ({})",
+                    zoned_date(email, app.config.ui.timezone).format("%Y/%m/%d %H:%M %:z"),
+                    zone_label(email, app.config.ui.timezone)
+                ),
                 Style::default().fg(Color::Yellow),
             ),
         ]),
@@ -202,6 +628,11 @@ fn render_detail(f: &mut Frame, app: &App, idx: usize) {
             Span::styled(email.from.to_string(), Style::default().fg(Color::Yellow)),
         ]),
     ];
+    if !app.config.ui.screen_reader {
+        content[1]
+            .spans
+            .splice(1..1, [avatar_span(&email.from), Span::raw(" ")]);
+    }
 
     // Add To field if present
     if !email.to.is_empty() {
@@ -269,39 +700,959 @@ fn render_detail(f: &mut Frame, app: &App, idx: usize) {
         ),
         Span::styled(&email.subject, Style::default().fg(Color::Yellow)),
     ]));
+    if !email.labels.is_empty() {
+        let mut spans = vec![Span::styled(
+            "Labels:",
+            Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+        )];
+        for label in &email.labels {
+            spans.push(Span::raw(" "));
+            spans.push(Span::styled(
+                format!(" {} ", label),
+                Style::default().fg(Color::Black).bg(Color::Cyan),
+            ));
+        }
+        content.push(Line::from(spans));
+    }
+    if let Some(parent) = app.in_reply_to(idx) {
+        content.push(Line::from(vec![
+            Span::styled(
+                "In reply to: ",
+                Style::default()
+                    .fg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::styled(parent, Style::default().fg(Color::Gray)),
+        ]));
+    }
+
+    // Other headers, either all of them in order or those not weeded out.
+    if let Some(headers) = app.message_headers(idx) {
+        if app.full_headers() {
+            content.clear();
+        }
+        for (name, value) in headers {
+            let standard = STANDARD_HEADERS.contains(&name.to_lowercase().as_str());
+            let shown = if app.full_headers() {
+                true
+            } else {
+                !standard && app.config.headers.shows(name)
+            };
+            if shown {
+                content.push(header_line(name, value));
+            }
+        }
+    }
 
     // Add empty line separator between headers and body
     content.push(Line::from(""));
 
+    // Bounces: show each failed recipient prominently above the report.
+    if let Some(report) = app.reports.get(&email._uid) {
+        let failure_style = Style::default()
+            .fg(Color::White)
+            .bg(Color::Red)
+            .add_modifier(Modifier::BOLD);
+        let mut failed = false;
+        for recipient in report.failures() {
+            content.push(Line::from(Span::styled(
+                recipient.failure_line(),
+                failure_style,
+            )));
+            failed = true;
+        }
+        if failed && report.original.is_some() {
+            content.push(Line::from(Span::styled(
+                "Press E to edit the original message and send it again.",
+                Style::default().fg(Color::Gray),
+            )));
+        }
+        if failed {
+            content.push(Line::from(""));
+        }
+    }
+
+    // Key values of structured mail, such as a one-time code, ahead of the
+    // body that would otherwise have to be searched for them.
+    let summaries = app.summaries(idx);
+    for (i, summary) in summaries.iter().enumerate() {
+        let mut spans = vec![
+            Span::styled(
+                format!("{}: ", summary.kind.label()),
+                Style::default()
+                    .fg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::styled(
+                summary.value.clone(),
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+            ),
+        ];
+        if let Some(detail) = &summary.detail {
+            spans.push(Span::styled(
+                format!(" ({})", detail),
+                Style::default().fg(Color::Gray),
+            ));
+        }
+        if i == 0 {
+            spans.push(Span::styled("  y: copy", Style::default().fg(Color::Gray)));
+        }
+        content.push(Line::from(spans));
+    }
+    if !summaries.is_empty() {
+        content.push(Line::from(""));
+    }
+
+    // A translation replaces the body, or is shown in a column beside it
+    // starting level with the body.
+    let side_by_side =
+        translation.filter(|_| app.config.translate.layout == TranslateLayout::SideBySide);
+    let (body_area, translated) = match side_by_side {
+        Some(translation) => {
+            let [original, translated] =
+                Layout::horizontal([Constraint::Percentage(50); 2]).areas(chunks[1]);
+            let mut lines = vec![Line::from(""); content.len().saturating_sub(1)];
+            lines.push(Line::from(Span::styled(
+                format!("Translation ({} → {})", translation.from, translation.to),
+                Style::default()
+                    .fg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD),
+            )));
+            lines.extend(translation.text.lines().map(body_line));
+            let widget = Paragraph::new(lines).wrap(Wrap { trim: true });
+            (original, Some((widget, translated)))
+        }
+        None => (chunks[1], None),
+    };
+
     // Add email body
-    let body_text = email
-        .body
-        .as_ref()
-        .unwrap_or(&"Loading...".to_string())
-        .clone();
-    let body_lines: Vec<Line> = body_text
-        .lines()
-        .map(|line| Line::from(line.to_string()))
-        .collect();
-    content.extend(body_lines);
+    let body_text = match translation {
+        Some(translation) if side_by_side.is_none() => translation.text.clone(),
+        _ => email
+            .body
+            .clone()
+            .unwrap_or_else(|| app.body_placeholder(idx)),
+    };
+    // Right-to-left lines are wrapped here, as they must be reordered one
+    // screen line at a time. Screen readers get them in logical order.
+    let bidi = app.config.ui.bidi && !app.config.ui.screen_reader;
+    for line in body_text.lines() {
+        if bidi && is_rtl(line) {
+            content.extend(
+                display_lines(line, body_area.width as usize)
+                    .iter()
+                    .map(|shown| body_line(shown).alignment(Alignment::Right)),
+            );
+        } else {
+            content.push(body_line(line));
+        }
+    }
+
+    // Both columns scroll together, as far as the longer one goes.
+    let widget = Paragraph::new(content).wrap(Wrap { trim: true });
+    let mut line_count = widget.line_count(body_area.width);
+    if let Some((translated, area)) = &translated {
+        line_count = line_count.max(translated.line_count(area.width));
+    }
+    let offset = bounded_scroll(app, line_count, body_area.height);
+    f.render_widget(widget.scroll((offset, 0)), body_area);
+    if let Some((translated, area)) = translated {
+        f.render_widget(translated.scroll((offset, 0)), area);
+    }
+
+    app.set_detail_area(line_count, body_area.height);
+
+    // Footer
+    render_footer(f, app, chunks[2]);
+}
+
+/// Returns the detail view's scroll offset kept within text of the given
+/// number of lines, so that the last line stays at the bottom of the area.
+fn bounded_scroll(app: &App, lines: usize, height: u16) -> u16 {
+    let max_scroll = u16::try_from(lines.saturating_sub(height as usize)).unwrap_or(u16::MAX);
+    app.detail_scroll_offset.min(max_scroll)
+}
+
+/// Builds a line of a message body, highlighting links marked as deceptive
+/// through to their real target.
+fn body_line(line: &str) -> Line<'static> {
+    let warning = Style::default()
+        .fg(Color::White)
+        .bg(Color::Red)
+        .add_modifier(Modifier::BOLD);
+    let mut spans = Vec::new();
+    let mut rest = line;
+    while let Some(start) = rest.find(DECEPTIVE_LINK_MARK) {
+        spans.push(Span::raw(rest[..start].to_string()));
+        let end = rest[start..]
+            .find('>')
+            .map_or(rest.len(), |i| start + i + 1);
+        spans.push(Span::styled(rest[start..end].to_string(), warning));
+        rest = &rest[end..];
+    }
+    spans.push(Span::raw(rest.to_string()));
+    Line::from(spans)
+}
+
+/// Renders the compose view showing the current draft.
+fn render_compose(f: &mut Frame, app: &App, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(2),
+            Constraint::Min(5),
+            Constraint::Length(1),
+        ])
+        .split(area);
+
+    // Header
+    let header = Paragraph::new(Text::from(vec![Line::from(vec![Span::styled(
+        "Compose",
+        Style::default()
+            .fg(Color::Cyan)
+            .add_modifier(Modifier::BOLD),
+    )])]))
+    .block(header_block(app))
+    .alignment(Alignment::Center);
+    f.render_widget(header, chunks[0]);
+
+    if let Some(draft) = &app.draft {
+        let label_style = Style::default()
+            .fg(Color::Cyan)
+            .add_modifier(Modifier::BOLD);
+        let mut content = vec![
+            Line::from(vec![
+                Span::styled("To: ", label_style),
+                Span::styled(draft.to.as_str(), Style::default().fg(Color::Green)),
+            ]),
+            Line::from(vec![
+                Span::styled("Cc: ", label_style),
+                Span::styled(draft.cc.as_str(), Style::default().fg(Color::Green)),
+            ]),
+            Line::from(vec![
+                Span::styled("Bcc: ", label_style),
+                Span::styled(draft.bcc.as_str(), Style::default().fg(Color::Green)),
+            ]),
+            Line::from(vec![
+                Span::styled("Subject: ", label_style),
+                Span::styled(draft.subject.as_str(), Style::default().fg(Color::Yellow)),
+            ]),
+        ];
+        let notify = [
+            (draft.request_dsn, "delivery report"),
+            (draft.request_read_receipt, "read receipt"),
+        ]
+        .into_iter()
+        .filter_map(|(on, name)| on.then_some(name))
+        .collect::<Vec<_>>();
+        if !notify.is_empty() {
+            content.push(Line::from(vec![
+                Span::styled("Request: ", label_style),
+                Span::raw(notify.join(", ")),
+            ]));
+        }
+        content.push(Line::from(""));
+        content.extend(draft.body.lines().map(Line::from));
+
+        let widget = Paragraph::new(content).wrap(Wrap { trim: false });
+        f.render_widget(widget, chunks[1]);
+    }
+
+    // Footer
+    render_footer(f, app, chunks[2]);
+}
+
+/// Renders the folder browser as an indented tree.
+///
+/// Folders that cannot be opened are dimmed and marked with an expand arrow
+/// when they have children.
+fn render_folders(f: &mut Frame, app: &App, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(2),
+            Constraint::Min(5),
+            Constraint::Length(1),
+        ])
+        .split(area);
+
+    // Header
+    let mut title = vec![Span::styled(
+        "Folders",
+        Style::default()
+            .fg(Color::Cyan)
+            .add_modifier(Modifier::BOLD),
+    )];
+    if let Some(query) = app.picker_filter() {
+        title.push(Span::raw(" - "));
+        title.push(Span::styled(
+            format!("find: {}", query),
+            Style::default().fg(Color::Magenta),
+        ));
+    }
+    let header = Paragraph::new(Text::from(vec![Line::from(title)]))
+        .block(header_block(app))
+        .alignment(Alignment::Center);
+    f.render_widget(header, chunks[0]);
+
+    if let Some(tree) = &app.folder_tree {
+        let current = app.client.as_ref().map_or("INBOX", |c| c.mailbox());
+        let items = tree
+            .rows()
+            .into_iter()
+            .map(|row| {
+                let marker = match (row.node.children.is_empty(), row.expanded) {
+                    _ if app.config.ui.screen_reader => "",
+                    (true, _) => "  ",
+                    (false, true) => "▾ ",
+                    (false, false) => "▸ ",
+                };
+                let state = match (row.node.children.is_empty(), row.expanded) {
+                    (false, true) if app.config.ui.screen_reader => ", expanded",
+                    (false, false) if app.config.ui.screen_reader => ", collapsed",
+                    _ => "",
+                };
+                let style = if row.node.path == current {
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD)
+                } else if row.node.selectable {
+                    Style::default().fg(Color::White)
+                } else {
+                    Style::default().fg(Color::DarkGray)
+                };
+                ListItem::new(Line::from(vec![
+                    Span::raw("  ".repeat(row.depth)),
+                    Span::raw(marker),
+                    Span::styled(row.node.label.as_str(), style),
+                    Span::raw(state),
+                ]))
+            })
+            .collect::<Vec<_>>();
+
+        let list = List::new(items)
+            .highlight_style(
+                Style::default()
+                    .bg(Color::DarkGray)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .highlight_symbol("> ");
+        let mut state = ListState::default();
+        state.select(Some(tree.selected()));
+        f.render_stateful_widget(list, chunks[1], &mut state);
+    }
+
+    // Footer
+    render_footer(f, app, chunks[2]);
+}
+
+/// Renders the folders offered by the change-folder or label prompt at the
+/// bottom of the list area, the one picked on Enter highlighted.
+fn render_folder_candidates(f: &mut Frame, app: &App, area: Rect) {
+    let Some(kind) = app.prompt.as_ref().map(|p| &p.kind) else {
+        return;
+    };
+    if !matches!(
+        kind,
+        PromptKind::ChangeFolder | PromptKind::Label | PromptKind::Unlabel | PromptKind::Move
+    ) {
+        return;
+    }
+    let candidates = app.folder_candidates();
+    let width = candidates
+        .iter()
+        .map(|c| c.path.chars().count())
+        .max()
+        .unwrap_or(0);
+    let items = candidates
+        .iter()
+        .take(FOLDER_CANDIDATES)
+        .map(|choice| {
+            let mut spans = vec![Span::raw(format!("{:<width$}", choice.path))];
+            if choice.unread > 0 {
+                spans.push(Span::styled(
+                    format!("  {} unread", choice.unread),
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD),
+                ));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect::<Vec<_>>();
+
+    let height = (items.len() as u16 + 2).min(area.height);
+    let popup = Rect {
+        y: area.bottom() - height,
+        height,
+        ..area
+    };
+    let title = match (candidates.is_empty(), kind) {
+        (true, PromptKind::ChangeFolder) => " No matching folder ",
+        (false, PromptKind::ChangeFolder) => {
+            " Enter: open first · =name: match name start · empty: browse "
+        }
+        (true, PromptKind::Move) => " Enter: new folder ",
+        (false, PromptKind::Move) => " Enter: pick first · exact name: that folder ",
+        (true, _) => " Enter: new label ",
+        (false, _) => " Enter: pick first · exact name: that label ",
+    };
+    let list = List::new(items)
+        .block(bordered_block(app, Borders::ALL).title(title))
+        .highlight_style(
+            Style::default()
+                .bg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("> ");
+    let mut state = ListState::default();
+    state.select((!candidates.is_empty()).then_some(0));
+    f.render_widget(Clear, popup);
+    f.render_stateful_widget(list, popup, &mut state);
+}
+
+/// Renders the folder sidebar: each folder with its unread count, the open
+/// one highlighted.
+fn render_sidebar(f: &mut Frame, app: &App, area: Rect) {
+    let Some((folders, selected)) = app.sidebar() else {
+        return;
+    };
+    let current = app.client.as_ref().map_or("INBOX", |c| c.mailbox());
+    // Room left by the border and the cursor symbol.
+    let width = (area.width as usize).saturating_sub(3);
+    let items = folders
+        .iter()
+        .map(|folder| {
+            let count = if folder.unread > 0 {
+                folder.unread.to_string()
+            } else {
+                String::new()
+            };
+            let name_width = width.saturating_sub(count.len() + 1);
+            let name = format!("{:<name_width$}", truncate_end(&folder.path, name_width));
+            let style = if folder.path == current {
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD)
+            } else if folder.unread > 0 {
+                Style::default().add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(Color::Gray)
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(name, style),
+                Span::raw(" "),
+                Span::styled(count, Style::default().fg(Color::Yellow)),
+            ]))
+        })
+        .collect::<Vec<_>>();
+
+    let list = List::new(items)
+        .block(bordered_block(app, Borders::RIGHT))
+        .highlight_style(Style::default().bg(Color::DarkGray))
+        .highlight_symbol("> ");
+    let mut state = ListState::default();
+    state.select((!folders.is_empty()).then_some(selected));
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+/// Renders the participants panel on the right of the detail view, with the
+/// number of messages each participant sent.
+fn render_participants(f: &mut Frame, app: &App, area: Rect) {
+    let (participants, selected, narrowed) = app.participants();
+    let items = participants
+        .iter()
+        .map(|participant| {
+            let who = match &participant.name {
+                Some(name) => format!("{} <{}>", name, participant.address),
+                None => participant.address.clone(),
+            };
+            let style = if narrowed == Some(participant.address.as_str()) {
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            ListItem::new(Line::from(vec![
+                Span::raw(format!("{:>3}  ", participant.sent)),
+                Span::styled(who, style),
+            ]))
+        })
+        .collect::<Vec<_>>();
+
+    let width = participants
+        .iter()
+        .map(|p| p.address.chars().count() + p.name.as_ref().map_or(0, |n| n.chars().count() + 3))
+        .max()
+        .unwrap_or(0) as u16
+        + 9;
+    let width = width.max(32).min(area.width);
+    // Below the detail view's header, so the thread position stays visible.
+    let top = area.height.min(2);
+    let height = (items.len() as u16 + 2).min(area.height - top);
+    let popup = Rect {
+        x: area.right() - width,
+        y: area.y + top,
+        width,
+        height,
+    };
+    let list = List::new(items)
+        .block(bordered_block(app, Borders::ALL).title(" Participants (messages sent) "))
+        .highlight_style(
+            Style::default()
+                .bg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("> ");
+    let mut state = ListState::default();
+    state.select((!participants.is_empty()).then_some(selected));
+    f.render_widget(Clear, popup);
+    f.render_stateful_widget(list, popup, &mut state);
+}
 
-    let widget = Paragraph::new(content)
-        .wrap(Wrap { trim: true })
-        .scroll((app.detail_scroll_offset, 0));
-    f.render_widget(widget, chunks[1]);
+/// Renders the session statistics view.
+fn render_stats(f: &mut Frame, app: &App, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(2),
+            Constraint::Min(5),
+            Constraint::Length(1),
+        ])
+        .split(area);
+
+    // Header
+    let header = Paragraph::new(Text::from(vec![Line::from(vec![Span::styled(
+        "Session Statistics",
+        Style::default()
+            .fg(Color::Cyan)
+            .add_modifier(Modifier::BOLD),
+    )])]))
+    .block(header_block(app))
+    .alignment(Alignment::Center);
+    f.render_widget(header, chunks[0]);
+
+    let label_style = Style::default()
+        .fg(Color::Cyan)
+        .add_modifier(Modifier::BOLD);
+    let row = |label: &'static str, value: String| {
+        Line::from(vec![Span::styled(label, label_style), Span::raw(value)])
+    };
+
+    let elapsed = app.started_at.elapsed().as_secs();
+    let unread = app.emails.iter().filter(|email| !email.is_read).count();
+    let mut content = vec![
+        row(
+            "Session time:   ",
+            format!(
+                "{}:{:02}:{:02}",
+                elapsed / 3600,
+                elapsed / 60 % 60,
+                elapsed % 60
+            ),
+        ),
+        row(
+            "Emails loaded:  ",
+            format!("{} ({} unread)", app.emails.len(), unread),
+        ),
+    ];
+    match &app.client {
+        Some(client) => {
+            let stats = client.transfer_stats();
+            content.extend([
+                row("IMAP commands:  ", stats.commands.to_string()),
+                row("Received:       ", format_bytes(stats.bytes_received)),
+                row("Sent:           ", format_bytes(stats.bytes_sent)),
+            ]);
+        }
+        None => content.push(row("IMAP:           ", "not connected".to_string())),
+    }
+    content.push(row(
+        "Transfer budget:",
+        match app.config.network.transfer_budget_kb {
+            Some(kb) => format!(" {} per action", format_bytes(kb.saturating_mul(1024))),
+            None => " unlimited".to_string(),
+        },
+    ));
+
+    f.render_widget(Paragraph::new(content), chunks[1]);
 
     // Footer
-    let footer = Paragraph::new(Line::from(vec![
-        Span::raw("j/↓"),
-        Span::styled(":down", Style::default().fg(Color::DarkGray)),
-        Span::raw(" "),
-        Span::raw("k/↑"),
-        Span::styled(":up", Style::default().fg(Color::DarkGray)),
-        Span::raw(" "),
-        Span::raw("q/Esc"),
-        Span::styled(":back", Style::default().fg(Color::DarkGray)),
-    ]))
-    .style(Style::default().fg(Color::White))
+    render_footer(f, app, chunks[2]);
+}
+
+/// Renders the version view: how rutt was built, the optional features
+/// enabled in the config, and the outcome of the update check.
+fn render_version(f: &mut Frame, app: &App, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(2),
+            Constraint::Min(5),
+            Constraint::Length(1),
+        ])
+        .split(area);
+
+    let header = Paragraph::new(Text::from(vec![Line::from(vec![Span::styled(
+        build_info(),
+        Style::default()
+            .fg(Color::Cyan)
+            .add_modifier(Modifier::BOLD),
+    )])]))
+    .block(header_block(app))
     .alignment(Alignment::Center);
-    f.render_widget(footer, chunks[2]);
+    f.render_widget(header, chunks[0]);
+
+    let label_style = Style::default()
+        .fg(Color::Cyan)
+        .add_modifier(Modifier::BOLD);
+    let row = |label: &'static str, value: String| {
+        Line::from(vec![Span::styled(label, label_style), Span::raw(value)])
+    };
+    let on_off = |enabled: bool| if enabled { "on" } else { "off" }.to_string();
+
+    let config = &app.config;
+    let mut content = vec![
+        row(
+            "Sign-in:        ",
+            match config.gmail.auth_command {
+                Some(_) => "auth command (OAuth)".to_string(),
+                None => "app password".to_string(),
+            },
+        ),
+        row("New mail IDLE:  ", on_off(config.network.idle)),
+        row("Local cache:    ", on_off(config.cache.enabled)),
+        row(
+            "Full-text index:",
+            format!(" {}", on_off(config.cache.full_text)),
+        ),
+        row("Update check:   ", on_off(config.network.check_updates)),
+        Line::from(""),
+    ];
+    content.push(match app.update_status() {
+        None => row(
+            "Latest release: ",
+            "not checked; set check_updates under [network]".to_string(),
+        ),
+        Some(UpdateStatus::Checking) => {
+            row("Latest release: ", format!("checking {}", app.spinner()))
+        }
+        Some(UpdateStatus::UpToDate) => row("Latest release: ", "up to date".to_string()),
+        Some(UpdateStatus::Available(version)) => Line::from(vec![
+            Span::styled("Latest release: ", label_style),
+            Span::styled(
+                format!("rutt {} is available at {}", version, RELEASES_URL),
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+            ),
+        ]),
+        Some(UpdateStatus::Failed(error)) => {
+            row("Latest release: ", format!("check failed: {}", error))
+        }
+    });
+
+    f.render_widget(
+        Paragraph::new(content).wrap(Wrap { trim: false }),
+        chunks[1],
+    );
+
+    render_footer(f, app, chunks[2]);
+}
+
+/// Renders the inbox cleanup view: senders of the loaded messages, most
+/// messages or largest total size first.
+fn render_cleanup(f: &mut Frame, app: &App, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(2),
+            Constraint::Min(5),
+            Constraint::Length(1),
+        ])
+        .split(area);
+
+    let (senders, selected) = app.senders();
+    let ranking = if app.senders_by_size() {
+        "by size"
+    } else {
+        "by count"
+    };
+    let header = Paragraph::new(Text::from(vec![Line::from(vec![Span::styled(
+        format!("Inbox Cleanup: {} senders, {}", senders.len(), ranking),
+        Style::default()
+            .fg(Color::Cyan)
+            .add_modifier(Modifier::BOLD),
+    )])]))
+    .block(header_block(app))
+    .alignment(Alignment::Center);
+    f.render_widget(header, chunks[0]);
+
+    let items = senders
+        .iter()
+        .map(|sender| {
+            let who = match &sender.name {
+                Some(name) => format!("{} <{}>", name, sender.address),
+                None => sender.address.clone(),
+            };
+            let mut spans = vec![
+                Span::raw(format!("{:>5}  ", sender.count)),
+                Span::styled(
+                    format!("{:>9}  ", format_bytes(sender.bytes)),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::raw(who),
+            ];
+            if sender.unread > 0 {
+                spans.push(Span::styled(
+                    format!("  {} unread", sender.unread),
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD),
+                ));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect::<Vec<_>>();
+    let list = List::new(items)
+        .highlight_style(
+            Style::default()
+                .bg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("> ");
+    let mut state = ListState::default();
+    state.select((!senders.is_empty()).then_some(selected));
+    f.render_stateful_widget(list, chunks[1], &mut state);
+
+    // Footer
+    render_footer(f, app, chunks[2]);
+}
+
+/// Renders the matches of a search across all folders, most recent first,
+/// each with the folder it is in.
+fn render_results(f: &mut Frame, app: &App, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(2),
+            Constraint::Min(5),
+            Constraint::Length(1),
+        ])
+        .split(area);
+
+    let (query, hits, selected) = app.folder_results().unwrap_or_default();
+    let header = Paragraph::new(Text::from(vec![Line::from(vec![Span::styled(
+        format!("All folders: {} matches for {}", hits.len(), query),
+        Style::default()
+            .fg(Color::Cyan)
+            .add_modifier(Modifier::BOLD),
+    )])]))
+    .block(header_block(app))
+    .alignment(Alignment::Center);
+    f.render_widget(header, chunks[0]);
+
+    let items = hits
+        .iter()
+        .map(|hit| {
+            let email = &hit.email;
+            let from = email.from.name_or_addr().unwrap_or("(unknown)");
+            let mut spans = vec![
+                Span::styled(
+                    format!(
+                        "{:<width$}  ",
+                        truncate_middle(&hit.folder, RESULT_FOLDER_WIDTH),
+                        width = RESULT_FOLDER_WIDTH
+                    ),
+                    Style::default().fg(Color::Yellow),
+                ),
+                Span::styled(
+                    format!(
+                        "{}  ",
+                        format_date(&zoned_date(email, app.config.ui.timezone))
+                    ),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::raw(format!(
+                    "{:<width$}  ",
+                    truncate_end(from, FROM_WIDTH),
+                    width = FROM_WIDTH
+                )),
+                Span::raw(truncate_end(&email.subject, 100)),
+            ];
+            for label in &email.labels {
+                spans.push(Span::raw(" "));
+                spans.push(Span::styled(
+                    format!(" {} ", label),
+                    Style::default().fg(Color::Black).bg(Color::Cyan),
+                ));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect::<Vec<_>>();
+    let list = List::new(items)
+        .highlight_style(
+            Style::default()
+                .bg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("> ");
+    let mut state = ListState::default();
+    state.select((!hits.is_empty()).then_some(selected));
+    f.render_stateful_widget(list, chunks[1], &mut state);
+
+    // Footer
+    render_footer(f, app, chunks[2]);
+}
+
+/// Builds the keybinding hints that fit in the footer, ending with "…" when
+/// some bindings had to be left out.
+fn hint_line(app: &App, width: usize) -> Line<'static> {
+    let (hints, overflow) = footer_hints(app.bindings(), width);
+
+    let mut spans = Vec::new();
+    for (keys, description) in hints {
+        if !spans.is_empty() {
+            spans.push(Span::raw(" "));
+        }
+        spans.push(Span::raw(keys));
+        spans.push(Span::styled(
+            format!(":{}", description),
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    if overflow {
+        spans.push(Span::styled(" …", Style::default().fg(Color::DarkGray)));
+    }
+    Line::from(spans)
+}
+
+/// Renders the status line with the current notice, if any.
+fn render_status(f: &mut Frame, app: &App, area: Rect) {
+    let notice = app.status.current().unwrap_or_default();
+    let style = if notice.starts_with("Error") {
+        Style::default().fg(Color::Red)
+    } else {
+        Style::default().fg(Color::Yellow)
+    };
+    f.render_widget(Paragraph::new(Span::styled(notice, style)), area);
+}
+
+/// Renders the footer line.
+///
+/// An open prompt takes the place of the keybinding hints, which are
+/// generated from the current view's keymap.
+fn render_footer(f: &mut Frame, app: &App, area: Rect) {
+    if let Some(prompt) = &app.prompt {
+        let line = Line::from(vec![
+            Span::styled(prompt.label.as_str(), Style::default().fg(Color::Cyan)),
+            Span::raw(prompt.input.as_str()),
+        ]);
+        let cursor_x = area.x + line.width() as u16;
+        f.render_widget(Paragraph::new(line), area);
+        f.set_cursor_position((cursor_x.min(area.right().saturating_sub(1)), area.y));
+        return;
+    }
+
+    let footer = Paragraph::new(hint_line(app, area.width as usize))
+        .style(Style::default().fg(Color::White))
+        .alignment(Alignment::Center);
+    f.render_widget(footer, area);
+
+    // Show the keys of an incomplete sequence at the right, like vim's
+    // showcmd, or else how far a message is scrolled.
+    if app.pending_keys.is_empty() {
+        if let ViewMode::Detail(_) = app.mode
+            && let Some(percent) = app.detail_scroll_percent()
+        {
+            let position = Paragraph::new(Span::styled(
+                format!("{}%", percent),
+                Style::default().fg(Color::Gray),
+            ))
+            .alignment(Alignment::Right);
+            f.render_widget(position, area);
+        }
+    } else {
+        let pending = Paragraph::new(Span::styled(
+            app.pending_keys.as_str(),
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        ))
+        .alignment(Alignment::Right);
+        f.render_widget(pending, area);
+    }
+}
+
+/// Renders the URL picker over the detail view, numbering each link.
+fn render_urls(f: &mut Frame, app: &App, area: Rect) {
+    let (urls, selected) = app.urls();
+    let items = urls
+        .iter()
+        .map(|&(number, link)| {
+            let mut spans = vec![Span::styled(
+                format!("{:>2}  ", number),
+                Style::default().fg(Color::Cyan),
+            )];
+            // Marked as in the body, so a lookalike link is not opened blindly.
+            if link.deceptive {
+                spans.push(Span::styled(
+                    DECEPTIVE_LINK_MARK,
+                    Style::default()
+                        .fg(Color::White)
+                        .bg(Color::Red)
+                        .add_modifier(Modifier::BOLD),
+                ));
+            }
+            spans.push(Span::raw(link.url.as_str()));
+            ListItem::new(Line::from(spans))
+        })
+        .collect::<Vec<_>>();
+
+    let width = urls
+        .iter()
+        .map(|(_, link)| {
+            let mark = if link.deceptive {
+                DECEPTIVE_LINK_MARK
+            } else {
+                ""
+            };
+            mark.chars().count() + link.url.chars().count()
+        })
+        .max()
+        .unwrap_or(0) as u16
+        + 8;
+    let width = width.max(32).min(area.width);
+    // Below the detail view's header, so the thread position stays visible.
+    let top = area.height.min(2);
+    let height = (items.len() as u16 + 2).min(area.height - top);
+    let popup = Rect {
+        x: area.right() - width,
+        y: area.y + top,
+        width,
+        height,
+    };
+    let list = List::new(items)
+        .block(
+            bordered_block(app, Borders::ALL).title(match app.picker_filter() {
+                Some(query) if urls.is_empty() => format!(" No matching link: /{} ", query),
+                Some(query) => format!(" Links: /{} ", query),
+                None => " Links ".to_string(),
+            }),
+        )
+        .highlight_style(
+            Style::default()
+                .bg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("> ");
+    let mut state = ListState::default();
+    state.select(Some(selected));
+    f.render_widget(Clear, popup);
+    f.render_stateful_widget(list, popup, &mut state);
 }
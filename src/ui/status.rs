@@ -0,0 +1,122 @@
+//! Transient notifications for the status line.
+//!
+//! Actions report their outcome ("Marked 3 read", errors, new mail) as
+//! notices. They are shown one at a time below the footer, each for a few
+//! seconds, or only briefly when more are waiting, so no report is lost when
+//! several arrive together.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How long a notice is shown when no other is waiting.
+const SHOW_FOR: Duration = Duration::from_secs(4);
+
+/// How long a notice is shown when others are waiting behind it.
+const SHOW_QUEUED_FOR: Duration = Duration::from_secs(1);
+
+/// Most notices kept waiting; older ones are dropped first.
+const MAX_QUEUED: usize = 5;
+
+/// A notice and when it was first shown.
+#[derive(Debug)]
+struct Notice {
+    text: String,
+    shown_at: Option<Instant>,
+}
+
+/// Queue of notices for the status line.
+#[derive(Debug, Default)]
+pub(crate) struct StatusLine {
+    notices: VecDeque<Notice>,
+}
+
+impl StatusLine {
+    /// Queues a notice behind the ones already waiting.
+    pub fn push(&mut self, text: String) {
+        // Repeating the last notice, e.g. "Last message in thread" when
+        // pressing a key again, restarts its timer instead of queueing it.
+        if let Some(last) = self.notices.back_mut()
+            && last.text == text
+        {
+            last.shown_at = None;
+            return;
+        }
+        self.notices.push_back(Notice {
+            text,
+            shown_at: None,
+        });
+        if self.notices.len() > MAX_QUEUED {
+            self.notices.pop_front();
+        }
+    }
+
+    /// Drops the notice on screen once it has been shown long enough, and
+    /// starts the timer of the next one.
+    pub fn tick(&mut self, now: Instant) {
+        while let Some(notice) = self.notices.front_mut() {
+            let shown_at = *notice.shown_at.get_or_insert(now);
+            let show_for = if self.notices.len() > 1 {
+                SHOW_QUEUED_FOR
+            } else {
+                SHOW_FOR
+            };
+            if now.duration_since(shown_at) < show_for {
+                return;
+            }
+            self.notices.pop_front();
+        }
+    }
+
+    /// Returns the notice to show, if any.
+    pub fn current(&self) -> Option<&str> {
+        self.notices.front().map(|notice| notice.text.as_str())
+    }
+
+    /// Returns whether any notice is shown or waiting.
+    pub fn is_empty(&self) -> bool {
+        self.notices.is_empty()
+    }
+
+    /// Drops all notices.
+    pub fn clear(&mut self) {
+        self.notices.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notices_expire_in_order() {
+        let start = Instant::now();
+        let mut status = StatusLine::default();
+        status.push("Marked 1 message read".to_string());
+        status.tick(start);
+        assert_eq!(status.current(), Some("Marked 1 message read"));
+
+        // Alone, a notice stays for the full time.
+        status.tick(start + Duration::from_secs(2));
+        assert_eq!(status.current(), Some("Marked 1 message read"));
+
+        // With another waiting, it gives way after the short time.
+        status.push("Archived 1 message".to_string());
+        status.push("Archived 1 message".to_string());
+        status.tick(start + Duration::from_secs(2));
+        assert_eq!(status.current(), Some("Archived 1 message"));
+
+        status.tick(start + Duration::from_secs(5));
+        assert_eq!(status.current(), Some("Archived 1 message"));
+        status.tick(start + Duration::from_secs(7));
+        assert!(status.is_empty());
+    }
+
+    #[test]
+    fn test_queue_is_bounded() {
+        let mut status = StatusLine::default();
+        for n in 0..10 {
+            status.push(format!("Notice {}", n));
+        }
+        assert_eq!(status.current(), Some("Notice 5"));
+    }
+}
@@ -0,0 +1,189 @@
+//! Build information and the check for a newer release.
+//!
+//! `:version` shows how rutt was built, and with `check_updates` under
+//! `[network]` asks GitHub for the latest release in the background, so the
+//! screen opens at once and a slow or unreachable API only delays the answer.
+
+use anyhow::{Context, Result, bail};
+use native_tls::TlsConnector;
+use std::cmp::Ordering;
+use std::io::{Read, Write};
+use std::sync::mpsc::{Receiver, channel};
+use std::thread;
+use std::time::Duration;
+
+use crate::imap_client::connect_tcp;
+
+/// Version of this build.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Host of the API listing the releases.
+const RELEASES_HOST: &str = "api.github.com";
+
+/// Path of the latest release on [`RELEASES_HOST`].
+const LATEST_RELEASE_PATH: &str = "/repos/ohno418/rutt/releases/latest";
+
+/// Where the releases are published, for the user to download from.
+pub const RELEASES_URL: &str = "https://github.com/ohno418/rutt/releases";
+
+/// How long the release API may take to connect and answer.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Returns how this binary was built: version, profile, and target.
+pub fn build_info() -> String {
+    let profile = if cfg!(debug_assertions) {
+        "debug"
+    } else {
+        "release"
+    };
+    format!(
+        "rutt {} ({} build, {}-{})",
+        VERSION,
+        profile,
+        std::env::consts::ARCH,
+        std::env::consts::OS
+    )
+}
+
+/// The outcome of an update check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateStatus {
+    /// Still waiting for the release API.
+    Checking,
+    /// This build is the latest release or newer.
+    UpToDate,
+    /// A newer release was published, with its version.
+    Available(String),
+    /// The latest release could not be looked up.
+    Failed(String),
+}
+
+/// An update check running in a background thread.
+#[derive(Debug)]
+pub struct UpdateCheck {
+    result: Receiver<Result<String>>,
+    status: UpdateStatus,
+}
+
+impl UpdateCheck {
+    /// Starts looking up the latest release.
+    pub fn start() -> Self {
+        let (tx, result) = channel();
+        thread::spawn(move || {
+            let _ = tx.send(latest_release());
+        });
+        UpdateCheck {
+            result,
+            status: UpdateStatus::Checking,
+        }
+    }
+
+    /// Takes in the answer once it arrived, without blocking, and returns
+    /// the status so far.
+    pub fn poll(&mut self) -> &UpdateStatus {
+        if let Ok(result) = self.result.try_recv() {
+            self.status = match result {
+                Ok(latest) if is_newer(&latest, VERSION) => UpdateStatus::Available(latest),
+                Ok(_) => UpdateStatus::UpToDate,
+                Err(e) => UpdateStatus::Failed(format!("{e:#}")),
+            };
+        }
+        &self.status
+    }
+
+    /// Returns the status as of the last poll.
+    pub fn status(&self) -> &UpdateStatus {
+        &self.status
+    }
+}
+
+/// Looks up the version of the latest release.
+fn latest_release() -> Result<String> {
+    let tcp = connect_tcp((RELEASES_HOST, 443), CHECK_TIMEOUT)
+        .with_context(|| format!("Failed to connect to {}", RELEASES_HOST))?;
+    tcp.set_read_timeout(Some(CHECK_TIMEOUT))?;
+    let mut tls = TlsConnector::new()?
+        .connect(RELEASES_HOST, tcp)
+        .with_context(|| format!("TLS handshake with {} failed", RELEASES_HOST))?;
+
+    // HTTP/1.0 keeps the body unchunked and closes the connection after it.
+    write!(
+        tls,
+        "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: rutt/{}\r\nAccept: application/vnd.github+json\r\n\r\n",
+        LATEST_RELEASE_PATH, RELEASES_HOST, VERSION
+    )?;
+    let mut response = String::new();
+    tls.read_to_string(&mut response)
+        .context("Failed to read the latest release")?;
+    parse_release(&response)
+}
+
+/// Takes the release version out of an HTTP response of the release API.
+fn parse_release(response: &str) -> Result<String> {
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .context("Malformed response from the release API")?;
+    let status = head.lines().next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("200") {
+        bail!("Release API answered {}", status);
+    }
+    let release: serde_json::Value =
+        serde_json::from_str(body).context("Malformed release from the release API")?;
+    let Some(tag) = release["tag_name"].as_str() else {
+        bail!("The latest release has no version");
+    };
+    Ok(tag.trim_start_matches('v').to_string())
+}
+
+/// Returns whether `latest` is a later version than `current`, comparing
+/// their numeric parts. A pre-release suffix is ignored.
+fn is_newer(latest: &str, current: &str) -> bool {
+    let parts = |version: &str| {
+        version
+            .split(['-', '+'])
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map(|part| part.parse::<u64>().unwrap_or(0))
+            .collect::<Vec<_>>()
+    };
+    let (latest, current) = (parts(latest), parts(current));
+    for i in 0..latest.len().max(current.len()) {
+        let a = latest.get(i).copied().unwrap_or(0);
+        let b = current.get(i).copied().unwrap_or(0);
+        match a.cmp(&b) {
+            Ordering::Equal => continue,
+            order => return order == Ordering::Greater,
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer("0.2.0", "0.1.0"));
+        assert!(is_newer("0.10.0", "0.9.3"));
+        assert!(is_newer("1.0", "0.9.9"));
+        assert!(!is_newer("0.1.0", "0.1.0"));
+        assert!(!is_newer("0.1", "0.1.0"));
+        assert!(!is_newer("0.1.0-rc.1", "0.1.0"));
+        assert!(!is_newer("0.0.9", "0.1.0"));
+    }
+
+    #[test]
+    fn test_parse_release() {
+        let ok = "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{\"tag_name\": \"v0.3.1\", \"name\": \"0.3.1\"}";
+        assert_eq!(parse_release(ok).unwrap(), "0.3.1");
+
+        let missing = "HTTP/1.0 404 Not Found\r\n\r\n{\"message\": \"Not Found\"}";
+        assert_eq!(
+            parse_release(missing).unwrap_err().to_string(),
+            "Release API answered HTTP/1.0 404 Not Found"
+        );
+        assert!(parse_release("garbage").is_err());
+    }
+}
@@ -0,0 +1,222 @@
+//! Links found in message bodies, for the URL picker of the detail view.
+//!
+//! Like urlview, the body is scanned for `http`, `https` and `www.` links,
+//! and the one picked is opened in `$BROWSER`, or with the desktop's opener
+//! when it is not set. Links the body marks as deceptive keep the mark in the
+//! picker.
+
+use anyhow::{Context, Result};
+use std::env;
+use std::ops::Range;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use crate::body::DECEPTIVE_LINK_MARK;
+
+/// Characters that end a link wherever they appear.
+const DELIMITERS: &[char] = &['<', '>', '"', '`', '{', '}', '|', '\\', '^'];
+
+/// Characters left out at the end of a link, as they usually belong to the
+/// sentence around it.
+const TRAILING: &[char] = &['.', ',', ';', ':', '!', '?', '\'', '*'];
+
+/// A link found in a text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    pub url: String,
+    /// Whether the link is marked with [`DECEPTIVE_LINK_MARK`], its text
+    /// naming another site than it leads to.
+    pub deceptive: bool,
+}
+
+/// Returns the links in a text in order of appearance, each listed once.
+pub fn find_urls(text: &str) -> Vec<String> {
+    find_links(text).into_iter().map(|link| link.url).collect()
+}
+
+/// Returns the links in a text in order of appearance, each listed once and
+/// marked deceptive when it appears anywhere within a deceptive link.
+pub fn find_links(text: &str) -> Vec<Link> {
+    let marked = deceptive_ranges(text);
+    let mut links = Vec::<Link>::new();
+    let mut offset = 0;
+    while let Some(start) = next_start(&text[offset..]) {
+        let start = offset + start;
+        let candidate = &text[start..];
+        let end = candidate
+            .find(|c: char| c.is_whitespace() || c.is_control() || DELIMITERS.contains(&c))
+            .unwrap_or(candidate.len());
+        let url = trim_url(&candidate[..end]);
+        let scheme_only = url.ends_with("://") || url == "www.";
+        let deceptive = marked.iter().any(|range| range.contains(&start));
+        if !scheme_only {
+            match links.iter_mut().find(|link| link.url == url) {
+                Some(link) => link.deceptive |= deceptive,
+                None => links.push(Link {
+                    url: url.to_string(),
+                    deceptive,
+                }),
+            }
+        }
+        offset = start + end.max(1);
+    }
+    links
+}
+
+/// Returns where deceptive links are in a text: from their mark through the
+/// `<target>` following their text, as the detail view highlights them.
+fn deceptive_ranges(text: &str) -> Vec<Range<usize>> {
+    text.match_indices(DECEPTIVE_LINK_MARK)
+        .map(|(start, _)| {
+            let end = text[start..]
+                .find(['>', '\n'])
+                .map_or(text.len(), |i| start + i);
+            start..end
+        })
+        .collect()
+}
+
+/// Returns the byte offset of the next link in a text.
+///
+/// A `www.` link counts only at the start of a word, so the host of an
+/// `https://www.` link is not found again.
+fn next_start(text: &str) -> Option<usize> {
+    let scheme = ["https://", "http://"]
+        .iter()
+        .filter_map(|prefix| text.find(prefix))
+        .min();
+    let www = text.match_indices("www.").map(|(i, _)| i).find(|&i| {
+        text[..i]
+            .chars()
+            .next_back()
+            .is_none_or(|c| !c.is_alphanumeric() && c != '/' && c != '.')
+    });
+    scheme.into_iter().chain(www).min()
+}
+
+/// Strips punctuation ending the sentence around a link, and a closing
+/// parenthesis without an opening one in the link.
+fn trim_url(mut url: &str) -> &str {
+    loop {
+        if let Some(stripped) = url.strip_suffix(TRAILING) {
+            url = stripped;
+        } else if url.ends_with(')') && url.matches('(').count() < url.matches(')').count() {
+            url = &url[..url.len() - 1];
+        } else {
+            return url;
+        }
+    }
+}
+
+/// The desktop's opener, used for links when `$BROWSER` is not set.
+const OPENER: &str = if cfg!(target_os = "macos") {
+    "open"
+} else {
+    "xdg-open"
+};
+
+/// Opens a link in `$BROWSER`, or with `xdg-open` (`open` on macOS).
+///
+/// The browser is started in the background with its output discarded, so
+/// it cannot draw over the interface.
+pub fn open_url(url: &str) -> Result<()> {
+    let url = if url.starts_with("www.") {
+        format!("http://{}", url)
+    } else {
+        url.to_string()
+    };
+    let browser = env::var("BROWSER")
+        .ok()
+        .filter(|browser| !browser.trim().is_empty());
+    let command = match &browser {
+        // `$BROWSER` may hold a command with arguments, with `%s` standing
+        // for the link; the link is passed as an argument, never quoted into
+        // the script.
+        Some(browser) => {
+            let script = if browser.contains("%s") {
+                browser.replace("%s", "\"$1\"")
+            } else {
+                format!("{} \"$1\"", browser)
+            };
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(script).arg("sh").arg(&url);
+            command
+        }
+        None => {
+            let mut command = Command::new(OPENER);
+            command.arg(&url);
+            command
+        }
+    };
+    spawn_detached(command, browser.as_deref().unwrap_or(OPENER))
+}
+
+/// Opens a file with `xdg-open` (`open` on macOS), in the background like
+/// [`open_url`].
+pub fn open_path(path: &Path) -> Result<()> {
+    let mut command = Command::new(OPENER);
+    command.arg(path);
+    spawn_detached(command, OPENER)
+}
+
+/// Starts `command` with its output discarded, without waiting for it.
+fn spawn_detached(mut command: Command, program: &str) -> Result<()> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run {}", program))?;
+    // Reap the program whenever it exits, without waiting for it here.
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_urls() {
+        let text = "See https://example.com/a?x=1&y=2. Also (http://example.org/wiki/Rust_(language))\n\
+            and <https://example.com/b>, www.rust-lang.org/learn! Repeated: https://example.com/b\n\
+            Not a link: https:// or foo.www.example.com";
+        assert_eq!(
+            find_urls(text),
+            [
+                "https://example.com/a?x=1&y=2",
+                "http://example.org/wiki/Rust_(language)",
+                "https://example.com/b",
+                "www.rust-lang.org/learn",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_links_marks_deceptive() {
+        let text = "Log in at ⚠ https://bank.example <https://evil.example/login>\n\
+            or see https://help.example and https://evil.example/login again";
+        let links = find_links(text);
+        let marked = links
+            .iter()
+            .map(|link| (link.url.as_str(), link.deceptive))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            marked,
+            [
+                ("https://bank.example", true),
+                ("https://evil.example/login", true),
+                ("https://help.example", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_urls_skips_www_in_scheme_links() {
+        assert_eq!(
+            find_urls("Go to https://www.example.com/x."),
+            ["https://www.example.com/x"]
+        );
+    }
+}
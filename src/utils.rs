@@ -3,16 +3,312 @@
 //! Provides common utility functions used throughout the application for data
 //! formatting and manipulation.
 
-use chrono::Local;
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, FixedOffset, Offset, TimeZone, Utc};
+use std::fmt::Display;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{Receiver, channel};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::DateZone;
+use crate::imap_client::Email;
 
 /// Formats a date/time for display in the email list.
-pub fn format_date(date: &chrono::DateTime<Local>) -> String {
+pub fn format_date<Tz: TimeZone>(date: &DateTime<Tz>) -> String
+where
+    Tz::Offset: Display,
+{
     date.format("%Y/%m/%d %H:%M").to_string()
 }
 
+/// Returns the date of an email in the given timezone. Without a known
+/// sender's timezone, the local one is used.
+pub fn zoned_date(email: &Email, zone: DateZone) -> DateTime<FixedOffset> {
+    let offset = match (zone, email.utc_offset.and_then(FixedOffset::east_opt)) {
+        (DateZone::Utc, _) => Utc.fix(),
+        (DateZone::Sender, Some(offset)) => offset,
+        (DateZone::Local | DateZone::Sender, _) => *email.date.offset(),
+    };
+    email.date.with_timezone(&offset)
+}
+
+/// Names the timezone a date is shown in, for the detail view.
+pub fn zone_label(email: &Email, zone: DateZone) -> &'static str {
+    match (zone, email.utc_offset) {
+        (DateZone::Utc, _) => "UTC",
+        (DateZone::Sender, Some(_)) => "sender's time",
+        (DateZone::Sender, None) => "local time; sender's unknown",
+        (DateZone::Local, _) => "local time",
+    }
+}
+
+/// Formats a byte count with a binary unit suffix (e.g. "1.5 MiB").
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Reading speed assumed for read-time estimates, in words per minute.
+const WORDS_PER_MINUTE: usize = 200;
+
+/// Describes the length of a text as its word count and estimated reading
+/// time, e.g. "420 words, ~3 min read".
+pub fn reading_time(text: &str) -> String {
+    let words = text.split_whitespace().count();
+    let minutes = words.div_ceil(WORDS_PER_MINUTE).max(1);
+    let unit = if words == 1 { "word" } else { "words" };
+    format!("{} {}, ~{} min read", words, unit, minutes)
+}
+
+/// Shortens text to at most `width` characters, ending with "...".
+pub fn truncate_end(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let kept = text
+        .chars()
+        .take(width.saturating_sub(3))
+        .collect::<String>();
+    format!("{}...", kept)
+}
+
+/// Shortens text to at most `width` characters by replacing its middle with
+/// "…".
+///
+/// An email address keeps its domain whole when it fits, so
+/// `jsmith.longname@longcompany.com` becomes `jsmith…@longcompany.com`.
+pub fn truncate_middle(text: &str, width: usize) -> String {
+    let len = text.chars().count();
+    if len <= width {
+        return text.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+
+    if let Some(at) = text.rfind('@') {
+        let domain = &text[at..];
+        let domain_len = domain.chars().count();
+        // Keep at least one character of the local part before the "…".
+        if domain_len + 2 <= width {
+            let local = text[..at].chars().take(width - domain_len - 1);
+            return format!("{}…{}", local.collect::<String>(), domain);
+        }
+    }
+
+    let tail = (width - 1) / 2;
+    let head = width - 1 - tail;
+    let chars = text.chars().collect::<Vec<_>>();
+    format!(
+        "{}…{}",
+        chars[..head].iter().collect::<String>(),
+        chars[len - tail..].iter().collect::<String>()
+    )
+}
+
+/// Returns the directory for persistent application data.
+///
+/// Uses `$XDG_DATA_HOME/rutt`, falling back to `~/.local/share/rutt`.
+pub fn data_dir() -> PathBuf {
+    match std::env::var_os("XDG_DATA_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("rutt"),
+        None => expand_tilde("~/.local/share/rutt"),
+    }
+}
+
+/// Returns the directory for cached data that can be fetched again.
+///
+/// Uses `$XDG_CACHE_HOME/rutt`, falling back to `~/.cache/rutt`.
+pub fn cache_dir() -> PathBuf {
+    match std::env::var_os("XDG_CACHE_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("rutt"),
+        None => expand_tilde("~/.cache/rutt"),
+    }
+}
+
+/// How often a command run by [`pipe_through`] is checked for having exited.
+const COMMAND_POLL: Duration = Duration::from_millis(10);
+
+/// Runs a command through `sh -c` with `input` on stdin and returns what it
+/// printed, failing if it exits with a non-zero status or runs past
+/// `timeout`, when it is killed. A process it leaves in the background
+/// holding its output open counts against the same deadline.
+pub fn pipe_through(command: &str, input: &str, timeout: Duration) -> Result<String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {}", command))?;
+
+    // Feed stdin and drain the output from other threads so a command
+    // writing output before it has read everything cannot deadlock against
+    // us.
+    let mut stdin = child.stdin.take().context("Command has no stdin")?;
+    let data = input.as_bytes().to_vec();
+    // Not waited for: a process left in the background may hold stdin open
+    // without reading it.
+    thread::spawn(move || {
+        let _ = stdin.write_all(&data);
+    });
+    let stdout = drain(child.stdout.take().context("Command has no stdout")?);
+    let stderr = drain(child.stderr.take().context("Command has no stderr")?);
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("Command timed out after {}s", timeout.as_secs());
+        }
+        thread::sleep(COMMAND_POLL);
+    };
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    let Ok(out) = stdout.recv_timeout(remaining) else {
+        bail!(
+            "Command left its output open for more than {}s",
+            timeout.as_secs()
+        );
+    };
+
+    if !status.success() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let err = stderr.recv_timeout(remaining).unwrap_or_default();
+        bail!(
+            "Command failed ({}): {}",
+            status,
+            String::from_utf8_lossy(&err).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&out).into_owned())
+}
+
+/// Reads a pipe to its end on another thread, sending what it read once the
+/// pipe closes.
+fn drain(mut pipe: impl Read + Send + 'static) -> Receiver<Vec<u8>> {
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        let mut out = Vec::new();
+        let _ = pipe.read_to_end(&mut out);
+        let _ = tx.send(out);
+    });
+    rx
+}
+
+/// Expands a leading `~` in a path to the user's home directory.
+pub fn expand_tilde(path: &str) -> PathBuf {
+    if let Some(rest) = path.strip_prefix('~')
+        && (rest.is_empty() || rest.starts_with('/'))
+        && let Some(home) = std::env::var_os("HOME")
+    {
+        let mut expanded = PathBuf::from(home);
+        expanded.push(rest.trim_start_matches('/'));
+        return expanded;
+    }
+
+    PathBuf::from(path)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::imap_client::NameAddr;
+    use chrono::Local;
+
+    #[test]
+    fn test_zoned_date() {
+        let sent = DateTime::parse_from_rfc2822("Mon, 1 Jan 2024 09:30:00 +0900").unwrap();
+        let mut email = Email {
+            _uid: 1,
+            subject: String::new(),
+            from: NameAddr {
+                name: None,
+                email: None,
+            },
+            date: sent.with_timezone(&Local),
+            is_read: true,
+            utc_offset: Some(9 * 3600),
+            ..Default::default()
+        };
+        let shown = |email: &Email, zone| format_date(&zoned_date(email, zone));
+
+        assert_eq!(shown(&email, DateZone::Utc), "2024/01/01 00:30");
+        assert_eq!(shown(&email, DateZone::Sender), "2024/01/01 09:30");
+        assert_eq!(zone_label(&email, DateZone::Sender), "sender's time");
+        assert_eq!(
+            shown(&email, DateZone::Local),
+            format_date(&sent.with_timezone(&Local))
+        );
+
+        // Without the sender's timezone, the local one stands in.
+        email.utc_offset = None;
+        assert_eq!(
+            shown(&email, DateZone::Sender),
+            shown(&email, DateZone::Local)
+        );
+    }
+
+    #[test]
+    fn test_reading_time() {
+        assert_eq!(reading_time(""), "0 words, ~1 min read");
+        assert_eq!(reading_time("Hi"), "1 word, ~1 min read");
+        assert_eq!(reading_time(&"word ".repeat(200)), "200 words, ~1 min read");
+        assert_eq!(
+            reading_time(&"word\n".repeat(420)),
+            "420 words, ~3 min read"
+        );
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(5 * 1024 * 1024), "5.0 MiB");
+    }
+
+    #[test]
+    fn test_truncate_end() {
+        assert_eq!(truncate_end("short", 10), "short");
+        assert_eq!(truncate_end("a long sender name", 10), "a long ...");
+    }
+
+    #[test]
+    fn test_truncate_middle() {
+        assert_eq!(truncate_middle("short", 10), "short");
+        assert_eq!(
+            truncate_middle("jsmith.longname@longcompany.com", 23),
+            "jsmith…@longcompany.com"
+        );
+        // A domain too long to keep whole falls back to a plain middle cut.
+        assert_eq!(
+            truncate_middle("a@averyveryverylongdomain.example", 11),
+            "a@ave…ample"
+        );
+        assert_eq!(
+            truncate_middle("Johnathan Q. Smithsonian", 11),
+            "Johna…onian"
+        );
+    }
 
     #[test]
     fn test_format_date_today() {
@@ -32,6 +328,26 @@ mod tests {
         assert_eq!(formatted.len(), 16); // YYYY/MM/DD HH:MM
     }
 
+    #[test]
+    fn test_pipe_through_background_process() {
+        let started = Instant::now();
+        let err = pipe_through("echo hi; sleep 5 &", "", Duration::from_millis(200)).unwrap_err();
+        assert!(err.to_string().contains("output open"), "{err}");
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_expand_tilde() {
+        let home = std::env::var("HOME").unwrap();
+        assert_eq!(
+            expand_tilde("~/Documents"),
+            PathBuf::from(&home).join("Documents")
+        );
+        assert_eq!(expand_tilde("~"), PathBuf::from(&home));
+        assert_eq!(expand_tilde("/tmp/mail"), PathBuf::from("/tmp/mail"));
+        assert_eq!(expand_tilde("~user/x"), PathBuf::from("~user/x"));
+    }
+
     #[test]
     fn test_format_date_older() {
         let date = Local::now() - chrono::Duration::days(30);
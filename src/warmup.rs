@@ -0,0 +1,136 @@
+//! Filling the cache of other folders while the user is idle.
+//!
+//! Opening a folder first syncs its headers with the server, which takes a
+//! while for one not opened lately. After a spell without input, a worker
+//! with its own session syncs the folders likely to be opened next, such as
+//! Sent and Starred, into the cache, so switching to them later only has to
+//! fetch what changed since. On metered connections it stops once it used up
+//! the transfer budget of a single action.
+
+use anyhow::{Context, Result};
+use std::sync::mpsc::{Receiver, TryRecvError, channel};
+use std::thread;
+
+use crate::imap_client::ImapClient;
+
+/// Most folders synced in one warm-up.
+pub const MAX_WARM_FOLDERS: usize = 5;
+
+/// Rough size of one envelope on the wire, for fitting a warm-up into the
+/// transfer budget.
+const ENVELOPE_BYTES: u64 = 2048;
+
+/// A warm-up running in a background thread.
+#[derive(Debug)]
+pub struct WarmUp {
+    /// Disconnects once the worker is done.
+    done: Receiver<()>,
+}
+
+impl WarmUp {
+    /// Starts syncing the headers of the `limit` most recent messages of
+    /// each folder into the cache, with a session opened by `connect`, within
+    /// a budget in KiB.
+    pub fn start(
+        connect: impl Fn() -> Result<ImapClient> + Send + 'static,
+        folders: Vec<String>,
+        limit: u32,
+        budget_kb: Option<u64>,
+    ) -> Self {
+        let (tx, done) = channel();
+        thread::spawn(move || {
+            // Warming up is a convenience, so failures go unreported.
+            let _ = warm_up(&connect, &folders, limit, budget_kb);
+            drop(tx);
+        });
+        WarmUp { done }
+    }
+
+    /// Returns whether the worker is still syncing.
+    pub fn is_running(&self) -> bool {
+        matches!(self.done.try_recv(), Err(TryRecvError::Empty))
+    }
+}
+
+fn warm_up(
+    connect: &impl Fn() -> Result<ImapClient>,
+    folders: &[String],
+    limit: u32,
+    budget_kb: Option<u64>,
+) -> Result<()> {
+    let mut session = connect().context("Failed to connect for warming up the cache")?;
+    let start = session.transfer_stats().bytes_received;
+    for folder in folders {
+        let spent = session.transfer_stats().bytes_received - start;
+        let limit = folder_limit(limit, spent, budget_kb);
+        if limit == 0 {
+            break;
+        }
+        session.set_mailbox(folder);
+        // A folder that cannot be opened should not keep the others cold.
+        let _ = session.fetch_emails(limit);
+    }
+    Ok(())
+}
+
+/// Returns how many envelopes of the next folder fit in what is left of the
+/// budget after `spent` bytes, up to `limit`.
+fn folder_limit(limit: u32, spent: u64, budget_kb: Option<u64>) -> u32 {
+    let Some(kb) = budget_kb else {
+        return limit;
+    };
+    let left = kb.saturating_mul(1024).saturating_sub(spent);
+    (left / ENVELOPE_BYTES).min(limit.into()) as u32
+}
+
+/// Picks the folders to warm up from candidates in order of preference,
+/// leaving out the one shown and repeats.
+pub fn folders_to_warm(current: &str, candidates: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut folders: Vec<String> = Vec::new();
+    for folder in candidates {
+        if folder != current && !folders.contains(&folder) {
+            folders.push(folder);
+        }
+    }
+    folders.truncate(MAX_WARM_FOLDERS);
+    folders
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_folder_limit() {
+        assert_eq!(folder_limit(200, 1 << 30, None), 200);
+        assert_eq!(folder_limit(200, 0, Some(2048)), 200);
+        assert_eq!(folder_limit(200, 0, Some(100)), 50);
+        assert_eq!(folder_limit(200, 90 * 1024, Some(100)), 5);
+        assert_eq!(folder_limit(200, 200 * 1024, Some(100)), 0);
+    }
+
+    #[test]
+    fn test_folders_to_warm() {
+        let candidates = [
+            "[Gmail]/Sent Mail",
+            "[Gmail]/Starred",
+            "INBOX",
+            "Work",
+            "[Gmail]/Sent Mail",
+            "Lists/rust",
+            "Lists/linux",
+            "Receipts",
+        ]
+        .map(str::to_string);
+        assert_eq!(
+            folders_to_warm("INBOX", candidates),
+            [
+                "[Gmail]/Sent Mail",
+                "[Gmail]/Starred",
+                "Work",
+                "Lists/rust",
+                "Lists/linux",
+            ]
+        );
+    }
+}
@@ -0,0 +1,128 @@
+//! Detection of a stalled IMAP connection.
+//!
+//! The socket is given read and write timeouts, so a server that stops
+//! responding, e.g. in the middle of a FETCH literal, makes the blocked call
+//! fail instead of freezing the app until it is killed. The stream wrapper
+//! records the stall so the client can drop the connection, whose protocol
+//! state is then unknown, and reconnect before the next command.
+
+use imap::extensions::idle::SetReadTimeout;
+use native_tls::TlsStream;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// How long the server may stay silent during a command before the
+/// connection is considered stalled, unless `imap.timeout_secs` says
+/// otherwise.
+pub const IMAP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Wraps a stream whose socket has timeouts, flagging when one expires.
+#[derive(Debug)]
+pub struct WatchdogStream<S> {
+    inner: S,
+    stalled: Arc<AtomicBool>,
+    timeout: Duration,
+}
+
+impl<S> WatchdogStream<S> {
+    /// Wraps a stream whose socket has `timeout` set, setting `stalled` when
+    /// an operation times out.
+    pub fn new(inner: S, stalled: Arc<AtomicBool>, timeout: Duration) -> Self {
+        WatchdogStream {
+            inner,
+            stalled,
+            timeout,
+        }
+    }
+
+    /// Turns a timeout into a descriptive error and records the stall.
+    fn check<T>(&self, result: io::Result<T>) -> io::Result<T> {
+        result.map_err(|e| {
+            if is_timeout(&e) {
+                self.stalled.store(true, Ordering::Relaxed);
+                io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!(
+                        "IMAP server stopped responding for {}s",
+                        self.timeout.as_secs()
+                    ),
+                )
+            } else {
+                e
+            }
+        })
+    }
+}
+
+/// Returns true if an I/O error is a socket timeout. Depending on the
+/// platform an expired timeout is reported as either kind.
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+impl<S: Read> Read for WatchdogStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = self.inner.read(buf);
+        self.check(result)
+    }
+}
+
+impl<S: Write> Write for WatchdogStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = self.inner.write(buf);
+        self.check(result)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let result = self.inner.flush();
+        self.check(result)
+    }
+}
+
+impl SetReadTimeout for WatchdogStream<TlsStream<TcpStream>> {
+    /// Sets the socket's read timeout for IMAP IDLE. Removing it restores
+    /// the stream's timeout, so the connection stays watched afterwards.
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> imap::Result<()> {
+        self.inner
+            .get_ref()
+            .set_read_timeout(timeout.or(Some(self.timeout)))
+            .map_err(imap::Error::Io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// A stream whose reads always time out.
+    struct Silent;
+
+    impl Read for Silent {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::WouldBlock.into())
+        }
+    }
+
+    #[test]
+    fn test_timeout_flags_stall() {
+        let stalled = Arc::new(AtomicBool::new(false));
+        let mut stream =
+            WatchdogStream::new(Cursor::new(b"* OK".to_vec()), stalled.clone(), IMAP_TIMEOUT);
+        let mut buf = [0u8; 4];
+        stream.read_exact(&mut buf).unwrap();
+        assert!(!stalled.load(Ordering::Relaxed));
+
+        let mut stream = WatchdogStream::new(Silent, stalled.clone(), IMAP_TIMEOUT);
+        let err = stream.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(err.to_string().contains("stopped responding"));
+        assert!(stalled.load(Ordering::Relaxed));
+    }
+}
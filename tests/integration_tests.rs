@@ -4,7 +4,7 @@ use rutt::{Email, NameAddr};
 #[test]
 fn test_email_sorting() {
     let now = Local::now();
-    let mut emails = vec![
+    let mut emails = [
         Email {
             _uid: 1,
             subject: "First".to_string(),
@@ -17,7 +17,15 @@ fn test_email_sorting() {
             bcc: vec![],
             date: now - chrono::Duration::days(2),
             is_read: false,
+            is_flagged: false,
+            size: 0,
             body: None,
+            message_id: None,
+            in_reply_to: None,
+            references: vec![],
+            utc_offset: None,
+            malformed: false,
+            labels: vec![],
         },
         Email {
             _uid: 2,
@@ -31,7 +39,15 @@ fn test_email_sorting() {
             bcc: vec![],
             date: now - chrono::Duration::days(1),
             is_read: true,
+            is_flagged: false,
+            size: 0,
             body: None,
+            message_id: None,
+            in_reply_to: None,
+            references: vec![],
+            utc_offset: None,
+            malformed: false,
+            labels: vec![],
         },
         Email {
             _uid: 3,
@@ -45,11 +61,19 @@ fn test_email_sorting() {
             bcc: vec![],
             date: now,
             is_read: false,
+            is_flagged: false,
+            size: 0,
             body: None,
+            message_id: None,
+            in_reply_to: None,
+            references: vec![],
+            utc_offset: None,
+            malformed: false,
+            labels: vec![],
         },
     ];
 
-    emails.sort_by(|a, b| b.date.cmp(&a.date));
+    emails.sort_by_key(|e| std::cmp::Reverse(e.date));
 
     assert_eq!(emails[0].subject, "Third");
     assert_eq!(emails[1].subject, "Second");
@@ -58,7 +82,7 @@ fn test_email_sorting() {
 
 #[test]
 fn test_email_list_creation() {
-    let emails = vec![
+    let emails = [
         Email {
             _uid: 100,
             subject: "Test Email 1".to_string(),
@@ -71,7 +95,15 @@ fn test_email_list_creation() {
             bcc: vec![],
             date: Local::now(),
             is_read: false,
+            is_flagged: false,
+            size: 0,
             body: None,
+            message_id: None,
+            in_reply_to: None,
+            references: vec![],
+            utc_offset: None,
+            malformed: false,
+            labels: vec![],
         },
         Email {
             _uid: 101,
@@ -85,7 +117,15 @@ fn test_email_list_creation() {
             bcc: vec![],
             date: Local::now() - chrono::Duration::hours(1),
             is_read: true,
+            is_flagged: false,
+            size: 0,
             body: None,
+            message_id: None,
+            in_reply_to: None,
+            references: vec![],
+            utc_offset: None,
+            malformed: false,
+            labels: vec![],
         },
     ];
 